@@ -0,0 +1,324 @@
+// The renderer's hot paths on the first adapter that can run compute shaders, software
+// ones included, so only compare numbers taken on the same machine. Without any
+// adapter the benches are skipped. For whole frames, run the app with
+// LEARN_WGPU_STRESS=<instances> instead.
+
+use bytemuck::Zeroable;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::{DynamicImage, RgbaImage};
+use learn_wgpu::{renderer_backend::{assets::Mesh, draw_queue::{DrawQueue, InstancedDraw}, gpu_allocator::{GpuAllocator, DEFAULT_BLOCK_SIZE}, instance_buffer::{InstanceBatch, InstanceBuffer, InstanceStorage}, pipeline_builder::PipelineBuilder, shader_registry::ShaderHandle, shader_test::ShaderTest, texture::{Texture, TextureKind}, vertex::Vertex}, InstanceRaw, RendererError, State};
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Buffer, BufferDescriptor, BufferUsages, Color, CommandEncoderDescriptor, Device, Extent3d, LoadOp, Maintain, Operations, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, SamplerDescriptor, StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor};
+
+const TARGET_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+const TARGET_SIZE: u32 = 256;
+// Enough for the camera and vertex animation uniforms.
+const UNIFORM_SIZE: u64 = 256;
+const INSTANCE_COUNTS: [usize; 3] = [1_000, 10_000, 100_000];
+const TEXTURE_SIZES: [u32; 3] = [256, 1024, 2048];
+const DRAW_COUNTS: [usize; 3] = [100, 1_000, 10_000];
+const MESH_COUNT: usize = 4;
+
+#[rustfmt::skip]
+const QUAD_VERTICES: [Vertex; 4] = [
+    Vertex { position: [-0.5, -0.5, 0.0], tex_coords: [0.0, 1.0] },
+    Vertex { position: [0.5, -0.5, 0.0], tex_coords: [1.0, 1.0] },
+    Vertex { position: [0.5, 0.5, 0.0], tex_coords: [1.0, 0.0] },
+    Vertex { position: [-0.5, 0.5, 0.0], tex_coords: [0.0, 0.0] }
+];
+const QUAD_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
+
+// The bind group layouts of the instance pipeline, in group order.
+struct InstanceLayouts {
+    texture: BindGroupLayout,
+    camera: BindGroupLayout,
+    vertex_animation: BindGroupLayout,
+    instances: BindGroupLayout
+}
+
+impl InstanceLayouts {
+    fn new(device: &Device, storage: InstanceStorage) -> Self
+    {
+        Self {
+            texture: Texture::get_texture_array_bind_group_layout(device),
+            camera: State::get_camera_bind_group_layout(device),
+            vertex_animation: State::get_vertex_animation_bind_group_layout(device),
+            instances: InstanceBuffer::get_bind_group_layout(device, "Bench Instance", storage)
+        }
+    }
+
+    fn all(&self) -> [&BindGroupLayout; 4]
+    {
+        [&self.texture, &self.camera, &self.vertex_animation, &self.instances]
+    }
+}
+
+fn shader_test() -> Option<ShaderTest>
+{
+    match pollster::block_on(ShaderTest::new()) {
+        Ok(shader_test) => Some(shader_test),
+        Err(RendererError::NoAdapter) => {
+            eprintln!("No adapter that can run compute shaders, skipping the benches");
+            None
+        },
+        Err(e) => panic!("Couldn't create the bench device: {e}")
+    }
+}
+
+fn instance_pipeline_builder(storage: InstanceStorage) -> PipelineBuilder
+{
+    let mut builder = PipelineBuilder::builder();
+    builder
+        .set_label("Bench Instance")
+        .set_shader_module(ShaderHandle::Vertex, "vs_main", "fs_main")
+        .set_pixel_format(TARGET_FORMAT);
+    storage.configure(&mut builder);
+
+    builder
+}
+
+fn create_uniform_bind_group(device: &Device, layout: &BindGroupLayout) -> (Buffer, BindGroup)
+{
+    let buffer = device.create_buffer(
+        &BufferDescriptor {
+            label: Some("Bench Uniform Buffer"),
+            size: UNIFORM_SIZE,
+            usage: BufferUsages::UNIFORM,
+            mapped_at_creation: false
+        }
+    );
+    let bind_group = device.create_bind_group(
+        &BindGroupDescriptor {
+            label: Some("Bench Uniform Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding()
+                }
+            ]
+        }
+    );
+
+    (buffer, bind_group)
+}
+
+fn create_material_bind_group(shader_test: &ShaderTest, layout: &BindGroupLayout, color: [u8; 4]) -> BindGroup
+{
+    let device = shader_test.device();
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, image::Rgba(color)));
+    let texture = Texture::from_images(device, shader_test.queue(), &[image], TextureKind::Color,
+        Some("Bench Material Texture")).expect("Couldn't create the material texture");
+    let sampler = device.create_sampler(&SamplerDescriptor::default());
+
+    device.create_bind_group(
+        &BindGroupDescriptor {
+            label: Some("Bench Material Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&texture.view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler)
+                }
+            ]
+        }
+    )
+}
+
+fn create_target_view(device: &Device, format: TextureFormat) -> TextureView
+{
+    device.create_texture(
+        &TextureDescriptor {
+            label: Some("Bench Target"),
+            size: Extent3d {
+                width: TARGET_SIZE,
+                height: TARGET_SIZE,
+                depth_or_array_layers: 1
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[]
+        }
+    ).create_view(&TextureViewDescriptor::default())
+}
+
+// Lays out the instances and uploads them, as every frame does for every instance group.
+fn instance_buffer_write(c: &mut Criterion, shader_test: &ShaderTest)
+{
+    let (device, queue) = (shader_test.device(), shader_test.queue());
+    let storage = InstanceStorage::for_device(device);
+    let layout = InstanceBuffer::get_bind_group_layout(device, "Bench Instance", storage);
+    let mut instance_buffer = InstanceBuffer::new(device, "Bench Instance", &layout, storage);
+
+    let mut group = c.benchmark_group("instance_buffer_write");
+    for count in INSTANCE_COUNTS {
+        let groups = [vec![InstanceRaw::zeroed(); count]];
+        group.throughput(Throughput::Bytes((count * std::mem::size_of::<InstanceRaw>()) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &groups, |b, groups| {
+            b.iter(|| {
+                let batches = instance_buffer.write(device, queue, &layout, groups);
+                queue.submit(None);
+                device.poll(Maintain::Wait);
+                batches
+            });
+        });
+    }
+    group.finish();
+}
+
+// Preprocessing, validation and the backend's compile of the main instance pipeline,
+// what a pipeline cache miss or a shader hot reload costs.
+fn pipeline_build(c: &mut Criterion, shader_test: &ShaderTest)
+{
+    let device = shader_test.device();
+    let storage = InstanceStorage::for_device(device);
+    let layouts = InstanceLayouts::new(device, storage);
+    let mut builder = instance_pipeline_builder(storage);
+
+    c.bench_function("pipeline_build", |b| {
+        b.iter(|| builder.build(device, shader_test.shaders(), &layouts.all())
+            .expect("Couldn't build the pipeline"));
+    });
+}
+
+// Mip generation included, as textures are loaded.
+fn texture_upload(c: &mut Criterion, shader_test: &ShaderTest)
+{
+    let (device, queue) = (shader_test.device(), shader_test.queue());
+
+    let mut group = c.benchmark_group("texture_upload");
+    group.sample_size(10);
+    for size in TEXTURE_SIZES {
+        let images = [DynamicImage::ImageRgba8(RgbaImage::from_fn(size, size, |x, y| {
+            image::Rgba([x as u8, y as u8, (x ^ y) as u8, 255])
+        }))];
+        group.throughput(Throughput::Bytes(size as u64 * size as u64 * 4));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &images, |b, images| {
+            b.iter(|| {
+                let texture = Texture::from_images(device, queue, images, TextureKind::Color,
+                    Some("Bench Texture")).expect("Couldn't upload the texture");
+                queue.submit(None);
+                device.poll(Maintain::Wait);
+                texture
+            });
+        });
+    }
+    group.finish();
+}
+
+// Recording a pass of single instance draws through the DrawQueue, every draw on a
+// different mesh than the last and the material changing halfway. Nothing is submitted,
+// so this is the CPU side only.
+fn draw_recording(c: &mut Criterion, shader_test: &ShaderTest)
+{
+    let (device, queue) = (shader_test.device(), shader_test.queue());
+    let storage = InstanceStorage::for_device(device);
+    let layouts = InstanceLayouts::new(device, storage);
+    let pipeline = instance_pipeline_builder(storage)
+        .build(device, shader_test.shaders(), &layouts.all())
+        .expect("Couldn't build the pipeline");
+
+    let materials = [[255, 255, 255, 255], [255, 128, 0, 255]]
+        .map(|color| create_material_bind_group(shader_test, &layouts.texture, color));
+    let (_camera_buffer, camera_bind_group) = create_uniform_bind_group(device, &layouts.camera);
+    let (_animation_buffer, animation_bind_group) = create_uniform_bind_group(device,
+        &layouts.vertex_animation);
+
+    let mut allocator = GpuAllocator::new("Bench", DEFAULT_BLOCK_SIZE);
+    let meshes: Vec<Mesh> = (0..MESH_COUNT)
+        .map(|_| Mesh::new(device, queue, &mut allocator, &QUAD_VERTICES, &QUAD_INDICES))
+        .collect();
+
+    let max_draws = DRAW_COUNTS.iter().copied().max().unwrap_or_default();
+    let mut instance_buffer = InstanceBuffer::new(device, "Bench Instance", &layouts.instances, storage);
+    let batches = instance_buffer.write(device, queue, &layouts.instances,
+        &[vec![InstanceRaw::zeroed(); max_draws]]);
+    // One instance per draw, whichever storage the instances ended up in.
+    let instance_batches: Vec<InstanceBatch> = batches[0].iter()
+        .flat_map(|batch| batch.instances.clone()
+            .map(|instance| InstanceBatch { instances: instance..instance + 1, offset: batch.offset }))
+        .collect();
+
+    let color_view = create_target_view(device, TARGET_FORMAT);
+    let depth_view = create_target_view(device, Texture::DEPTH_FORMAT);
+
+    let mut group = c.benchmark_group("draw_recording");
+    for count in DRAW_COUNTS {
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter(|| {
+                let mut draw_queue = DrawQueue::default();
+                for (i, batch) in instance_batches[..count].iter().enumerate() {
+                    let mesh = &meshes[i % MESH_COUNT];
+                    draw_queue.push(InstancedDraw {
+                        pipeline: &pipeline,
+                        material: &materials[i * materials.len() / count],
+                        mesh,
+                        indices: 0..mesh.num_indices(),
+                        instances: instance_buffer.bind_group(),
+                        batch: batch.clone(),
+                        indirect: None
+                    });
+                }
+
+                let mut encoder = device.create_command_encoder(
+                    &CommandEncoderDescriptor {
+                        label: Some("Bench Encoder")
+                    }
+                );
+                let stats = {
+                    let mut render_pass = encoder.begin_render_pass(
+                        &RenderPassDescriptor {
+                            label: Some("Bench Render Pass"),
+                            color_attachments: &[Some(RenderPassColorAttachment {
+                                view: &color_view,
+                                resolve_target: None,
+                                ops: Operations {
+                                    load: LoadOp::Clear(Color::BLACK),
+                                    store: StoreOp::Store
+                                }
+                            })],
+                            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                                view: &depth_view,
+                                depth_ops: Some(Operations {
+                                    load: LoadOp::Clear(1.0),
+                                    store: StoreOp::Store
+                                }),
+                                stencil_ops: None
+                            }),
+                            timestamp_writes: None,
+                            occlusion_query_set: None
+                        }
+                    );
+                    render_pass.set_bind_group(1, &camera_bind_group, &[]);
+                    render_pass.set_bind_group(2, &animation_bind_group, &[]);
+                    draw_queue.record(&mut render_pass, &allocator)
+                };
+
+                (stats, encoder.finish())
+            });
+        });
+    }
+    group.finish();
+}
+
+fn renderer(c: &mut Criterion)
+{
+    let Some(shader_test) = shader_test() else {
+        return;
+    };
+
+    instance_buffer_write(c, &shader_test);
+    pipeline_build(c, &shader_test);
+    texture_upload(c, &shader_test);
+    draw_recording(c, &shader_test);
+}
+
+criterion_group!(benches, renderer);
+criterion_main!(benches);
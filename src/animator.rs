@@ -0,0 +1,166 @@
+use cgmath::Matrix4;
+use web_time::Duration;
+
+use super::skeleton::{AnimationClip, Skeleton, Transform};
+
+#[derive(Debug, Clone, Copy)]
+struct Playback {
+    clip: usize,
+    time: f32,
+    speed: f32,
+    looping: bool,
+    finished: bool
+}
+
+impl Playback {
+    // Returns true on the frame a one-shot clip reaches its end.
+    fn advance(&mut self, delta: f32, clip: &AnimationClip) -> bool
+    {
+        if self.finished {
+            return false;
+        }
+
+        self.time += delta * self.speed;
+        if self.looping && clip.duration > 0.0 {
+            self.time = self.time.rem_euclid(clip.duration);
+            return false;
+        }
+
+        let reached_end = if self.speed < 0.0 { self.time <= 0.0 } else { self.time >= clip.duration };
+        self.time = self.time.clamp(0.0, clip.duration);
+        self.finished = reached_end;
+
+        reached_end
+    }
+}
+
+// Plays one clip at a time and cross-fades into the next one: while fading, both
+// poses are sampled and blended, the outgoing clip keeps advancing so the fade
+// doesn't freeze it. One-shot clips hold their last frame once finished.
+pub struct Animator {
+    current: Option<Playback>,
+    previous: Option<Playback>,
+    fade_duration: f32,
+    fade_elapsed: f32
+}
+
+impl Animator {
+    pub fn new() -> Self
+    {
+        Self {
+            current: None,
+            previous: None,
+            fade_duration: 0.0,
+            fade_elapsed: 0.0
+        }
+    }
+
+    pub fn play(&mut self, clip: usize, looping: bool, fade: Duration)
+    {
+        let speed = self.current.map_or(1.0, |current| current.speed);
+        self.previous = self.current.take().filter(|_| !fade.is_zero());
+        self.current = Some(Playback {
+            clip,
+            time: 0.0,
+            speed,
+            looping,
+            finished: false
+        });
+        self.fade_duration = fade.as_secs_f32();
+        self.fade_elapsed = 0.0;
+    }
+
+    pub fn stop(&mut self)
+    {
+        self.current = None;
+        self.previous = None;
+    }
+
+    // Negative speeds play backwards. Applies to the current clip and any clip played after it.
+    pub fn set_speed(&mut self, speed: f32)
+    {
+        if let Some(current) = &mut self.current {
+            current.speed = speed;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool
+    {
+        self.current.is_none_or(|current| current.finished)
+    }
+
+    // Returns the clips that finished during this step.
+    pub fn advance(&mut self, delta: Duration, clips: &[AnimationClip]) -> Vec<usize>
+    {
+        let delta = delta.as_secs_f32();
+        let mut finished = Vec::new();
+
+        if let Some(previous) = &mut self.previous {
+            self.fade_elapsed += delta;
+            if self.fade_elapsed >= self.fade_duration {
+                self.previous = None;
+            } else if let Some(clip) = clips.get(previous.clip) {
+                previous.advance(delta, clip);
+            }
+        }
+
+        if let Some(current) = &mut self.current {
+            if let Some(clip) = clips.get(current.clip) {
+                if current.advance(delta, clip) {
+                    finished.push(current.clip);
+                }
+            }
+        }
+
+        finished
+    }
+
+    // The rest pose when nothing is playing.
+    pub fn joint_matrices(&self, skeleton: &Skeleton, clips: &[AnimationClip]) -> Vec<Matrix4<f32>>
+    {
+        let sample = |playback: Option<Playback>| playback
+            .and_then(|playback| clips.get(playback.clip).map(|clip| clip.sample(skeleton, playback.time)));
+
+        let pose = match (sample(self.previous), sample(self.current)) {
+            (Some(from), Some(to)) => {
+                let amount = self.fade_amount();
+                from.iter()
+                    .zip(&to)
+                    .map(|(from, to)| from.blend(to, amount))
+                    .collect::<Vec<Transform>>()
+            },
+            (_, Some(pose)) | (Some(pose), None) => pose,
+            (None, None) => skeleton.rest_pose()
+        };
+
+        skeleton.joint_matrices(&pose)
+    }
+
+    pub fn morph_weights(&self, defaults: &[f32], clips: &[AnimationClip]) -> Vec<f32>
+    {
+        let sample = |playback: Option<Playback>| playback
+            .and_then(|playback| clips.get(playback.clip)
+                .map(|clip| clip.sample_morph_weights(defaults, playback.time)));
+
+        match (sample(self.previous), sample(self.current)) {
+            (Some(from), Some(to)) => {
+                let amount = self.fade_amount();
+                from.iter()
+                    .zip(&to)
+                    .map(|(from, to)| from + (to - from) * amount)
+                    .collect()
+            },
+            (_, Some(weights)) | (Some(weights), None) => weights,
+            (None, None) => defaults.to_vec()
+        }
+    }
+
+    fn fade_amount(&self) -> f32
+    {
+        if self.fade_duration > 0.0 {
+            (self.fade_elapsed / self.fade_duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        }
+    }
+}
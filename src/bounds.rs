@@ -0,0 +1,124 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Transform};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>
+}
+
+impl Aabb {
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self
+    {
+        let mut min = Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+
+        for point in points {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(point[axis]);
+                max[axis] = max[axis].max(point[axis]);
+            }
+        }
+
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Point3<f32>
+    {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8]
+    {
+        let (min, max) = (self.min, self.max);
+
+        [
+            Point3::new(min.x, min.y, min.z),
+            Point3::new(max.x, min.y, min.z),
+            Point3::new(max.x, max.y, min.z),
+            Point3::new(min.x, max.y, min.z),
+            Point3::new(min.x, min.y, max.z),
+            Point3::new(max.x, min.y, max.z),
+            Point3::new(max.x, max.y, max.z),
+            Point3::new(min.x, max.y, max.z)
+        ]
+    }
+
+    // The box around the transformed corners, so it stays axis aligned but can grow.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self
+    {
+        Self::from_points(self.corners().map(|corner| matrix.transform_point(corner)))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32
+}
+
+impl BoundingSphere {
+    // Centered on the points' box, which is close to minimal for most meshes and
+    // cheap next to an exact fit.
+    pub fn from_points(points: &[Point3<f32>]) -> Self
+    {
+        let center = Aabb::from_points(points.iter().copied()).center();
+        let radius = points.iter()
+            .map(|point| point.distance(center))
+            .fold(0.0, f32::max);
+
+        Self { center, radius }
+    }
+
+    // Scaled by the largest axis scale of `matrix`, so it still encloses the mesh
+    // under non-uniform scaling.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self
+    {
+        let scale = [matrix.x, matrix.y, matrix.z]
+            .iter()
+            .map(|axis| axis.truncate().magnitude())
+            .fold(0.0, f32::max);
+
+        Self {
+            center: matrix.transform_point(self.center),
+            radius: self.radius * scale
+        }
+    }
+}
+
+// Both volumes of a mesh, computed once from its vertices. The sphere is the cheap
+// first test, the box the tighter one.
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere
+}
+
+impl Bounds {
+    pub fn from_points(points: &[Point3<f32>]) -> Self
+    {
+        Self {
+            aabb: Aabb::from_points(points.iter().copied()),
+            sphere: BoundingSphere::from_points(points)
+        }
+    }
+
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self
+    {
+        Self {
+            aabb: self.aabb.transform(matrix),
+            sphere: self.sphere.transform(matrix)
+        }
+    }
+}
+
+impl Default for Bounds {
+    fn default() -> Self
+    {
+        let origin = Point3::origin();
+
+        Self {
+            aabb: Aabb { min: origin, max: origin },
+            sphere: BoundingSphere { center: origin, radius: 0.0 }
+        }
+    }
+}
@@ -1,6 +1,10 @@
+use std::time::Duration;
+
 use bytemuck::{Pod, Zeroable};
-use cgmath::{perspective, Deg, Matrix4, Point3, Vector3};
-use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+use cgmath::{perspective, Angle, Deg, EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, Quaternion, Rad, Rotation, Rotation3, SquareMatrix, Transform, Vector2, Vector3, Vector4};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::state::{bounds::Aabb, input_map::Action, picking::Ray};
 
 const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -9,6 +13,23 @@ const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     0.0, 0.0, 0.0, 1.0,
 );
 
+// Depth to 1 - depth, from a projection for standard depth to one for reverse Z.
+const REVERSE_Z_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
+);
+
+// How far analog look input turns the view per update at full tilt, in radians.
+const LOOK_SPEED: f32 = 0.04;
+const MAX_LOOK_PITCH: f32 = 1.5;
+// How close zooming in gets to the target.
+const MIN_ZOOM_DISTANCE: f32 = 0.1;
+// Room left around a framed box, as a factor of its size.
+const FRAMING_MARGIN: f32 = 1.1;
+
+#[derive(Clone)]
 pub struct Camera {
     pub eye: Point3<f32>,
     pub target: Point3<f32>,
@@ -16,47 +37,393 @@ pub struct Camera {
     pub aspect: f32,
     pub fovy: f32,
     pub znear: f32,
-    pub zfar: f32
+    // Ignored with reverse Z, which puts the far plane infinitely far away.
+    pub zfar: f32,
+    // Depth goes from 1 at the near plane to 0 at infinity, see StateOptions::reverse_z.
+    pub reverse_z: bool
 }
 
 impl Camera {
     pub fn build_view_projection_matrix(&self) -> Matrix4<f32>
     {
         let view = Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = perspective(Deg(self.fovy), self.aspect, self.znear, self.zfar);
 
-        OPENGL_TO_WGPU_MATRIX * proj * view
+        self.build_projection_matrix() * view
+    }
+
+    pub fn build_projection_matrix(&self) -> Matrix4<f32>
+    {
+        if self.reverse_z {
+            return self.build_reverse_z_projection_matrix();
+        }
+
+        OPENGL_TO_WGPU_MATRIX * perspective(Deg(self.fovy), self.aspect, self.znear, self.zfar)
+    }
+
+    // Depth near / distance: 1 at the near plane, 0 infinitely far away. Floats are
+    // densest towards 0, which makes up for the perspective bunching depth up near 1.
+    fn build_reverse_z_projection_matrix(&self) -> Matrix4<f32>
+    {
+        let f = 1.0 / (Rad::from(Deg(self.fovy)) / 2.0).tan();
+
+        Matrix4::new(
+            f / self.aspect, 0.0, 0.0, 0.0,
+            0.0, f, 0.0, 0.0,
+            0.0, 0.0, 0.0, -1.0,
+            0.0, 0.0, self.znear, 0.0
+        )
+    }
+
+    // The directions the screen's x and y axes point in, in world space.
+    pub fn right_and_up(&self) -> (Vector3<f32>, Vector3<f32>)
+    {
+        let forward = (self.target - self.eye).normalize();
+        let right = forward.cross(self.up).normalize();
+
+        (right, right.cross(forward))
+    }
+
+    // Bottom left, bottom right, top right and top left on the near plane, then the same
+    // on the far plane, in the order of Aabb::corners.
+    pub fn frustum_corners(&self) -> [Point3<f32>; 8]
+    {
+        let forward = (self.target - self.eye).normalize();
+        let (right, up) = self.right_and_up();
+        let tan_y = (Deg(self.fovy) / 2.0).tan();
+        let corner = |distance: f32, x: f32, y: f32| {
+            self.eye + forward * distance + right * (x * distance * tan_y * self.aspect)
+                + up * (y * distance * tan_y)
+        };
+
+        [
+            corner(self.znear, -1.0, -1.0),
+            corner(self.znear, 1.0, -1.0),
+            corner(self.znear, 1.0, 1.0),
+            corner(self.znear, -1.0, 1.0),
+            corner(self.zfar, -1.0, -1.0),
+            corner(self.zfar, 1.0, -1.0),
+            corner(self.zfar, 1.0, 1.0),
+            corner(self.zfar, -1.0, 1.0)
+        ]
+    }
+
+    // Looks at the center of `aabb` from the current direction, from just far enough
+    // that the sphere around it fits the narrower field of view. The clip planes move
+    // out of the way when it's too small or large for them.
+    pub fn frame_bounds(&mut self, aabb: &Aabb)
+    {
+        let center = aabb.center();
+        let radius = ((aabb.max - aabb.min).magnitude() / 2.0).max(f32::EPSILON);
+        let half_fovy = Rad::from(Deg(self.fovy)).0 / 2.0;
+        let half_fovx = (half_fovy.tan() * self.aspect).atan();
+        let distance = radius * FRAMING_MARGIN / half_fovy.min(half_fovx).sin();
+
+        let forward = self.target - self.eye;
+        let forward = if forward.magnitude2() > 0.0 { forward.normalize() } else { -Vector3::unit_z() };
+        self.eye = center - forward * distance;
+        self.target = center;
+        self.znear = self.znear.min((distance - radius) / 2.0);
+        self.zfar = self.zfar.max((distance + radius) * 2.0);
+    }
+
+    // Mirrored below a horizontal plane at `height`, for planar reflections. What it
+    // sees comes out upside down compared to this camera.
+    pub fn reflected(&self, height: f32) -> Camera
+    {
+        let mirror = |point: Point3<f32>| Point3::new(point.x, 2.0 * height - point.y, point.z);
+
+        Camera {
+            eye: mirror(self.eye),
+            target: mirror(self.target),
+            up: self.up,
+            aspect: self.aspect,
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar,
+            reverse_z: self.reverse_z
+        }
+    }
+
+    // Moved so `origin` is at 0, the subtraction done in f64 so it's exact even far
+    // from the world's origin. See StateOptions::camera_relative.
+    pub fn relative_to(&self, origin: Point3<f64>) -> Camera
+    {
+        let shift = |point: Point3<f32>| Point3::from_vec((point.cast::<f64>().unwrap() - origin).cast().unwrap());
+
+        Camera {
+            eye: shift(self.eye),
+            target: shift(self.target),
+            ..self.clone()
+        }
+    }
+
+    // Swaps the near plane for `plane` (world space, ax + by + cz + d, kept where it's
+    // positive) so everything behind it is clipped without touching the shaders.
+    // This is Lengyel's oblique frustum; it only works with the eye behind the plane.
+    // With reverse Z it's built for a finite far plane and then has its depth flipped.
+    pub fn build_clipped_view_projection_matrix(&self, plane: Vector4<f32>) -> Matrix4<f32>
+    {
+        let view = Matrix4::look_at_rh(self.eye, self.target, self.up);
+        let mut proj = OPENGL_TO_WGPU_MATRIX * perspective(Deg(self.fovy), self.aspect, self.znear,
+            self.zfar);
+        let flip = if self.reverse_z { REVERSE_Z_MATRIX } else { Matrix4::identity() };
+        let (Some(inverse_view), Some(inverse_proj)) = (view.invert(), proj.invert()) else {
+            return flip * proj * view;
+        };
+
+        let plane = inverse_view.transpose() * plane;
+        if plane.w >= 0.0 {
+            return flip * proj * view;
+        }
+
+        let corner = inverse_proj * Vector4::new(plane.x.signum(), plane.y.signum(), 1.0, 1.0);
+        let near = plane / plane.dot(corner);
+        proj.x.z = near.x;
+        proj.y.z = near.y;
+        proj.z.z = near.z;
+        proj.w.z = near.w;
+
+        flip * proj * view
+    }
+
+    pub fn screen_to_ray(&self, cursor: PhysicalPosition<f64>, size: PhysicalSize<u32>) -> Ray
+    {
+        let ndc_x = (2.0 * cursor.x / size.width.max(1) as f64 - 1.0) as f32;
+        let ndc_y = (1.0 - 2.0 * cursor.y / size.height.max(1) as f64) as f32;
+
+        let inverse_view_proj = self.build_view_projection_matrix()
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        // Reverse Z has its far plane at infinity, halfway there is as good for the direction.
+        let (near_depth, far_depth) = if self.reverse_z { (1.0, 0.5) } else { (0.0, 1.0) };
+        let near = inverse_view_proj.transform_point(Point3::new(ndc_x, ndc_y, near_depth));
+        let far = inverse_view_proj.transform_point(Point3::new(ndc_x, ndc_y, far_depth));
+
+        Ray::new(near, far - near)
+    }
+}
+
+// Eases a camera from one view to another, e.g. onto something being framed. Only the
+// eye, target and clip planes move.
+pub struct CameraTransition {
+    from: Camera,
+    to: Camera,
+    duration: f32,
+    elapsed: f32
+}
+
+impl CameraTransition {
+    pub fn new(from: &Camera, to: &Camera, duration: Duration) -> Self
+    {
+        Self {
+            from: from.clone(),
+            to: to.clone(),
+            duration: duration.as_secs_f32(),
+            elapsed: 0.0
+        }
+    }
+
+    // Moves `camera` `delta` seconds further along, returning whether it has arrived.
+    pub fn step(&mut self, camera: &mut Camera, delta: f32) -> bool
+    {
+        self.elapsed = (self.elapsed + delta).min(self.duration);
+        let t = if self.duration > 0.0 { self.elapsed / self.duration } else { 1.0 };
+        // Smoothstep, so it starts and stops gently.
+        let t = t * t * (3.0 - 2.0 * t);
+
+        camera.eye = self.from.eye + (self.to.eye - self.from.eye) * t;
+        camera.target = self.from.target + (self.to.target - self.from.target) * t;
+        camera.znear = self.from.znear + (self.to.znear - self.from.znear) * t;
+        camera.zfar = self.from.zfar + (self.to.zfar - self.from.zfar) * t;
+
+        self.elapsed >= self.duration
     }
 }
 
+// Element `index` of the Halton sequence in `base`, in [0, 1).
+pub fn halton(mut index: u32, base: u32) -> f32
+{
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
+
+// A high and a low part that add up to `value` closer than an f32 alone gets, so an
+// f64 position can be handed to shaders.
+pub fn split_f64(value: Vector3<f64>) -> (Vector3<f32>, Vector3<f32>)
+{
+    let high = value.cast::<f32>().unwrap();
+    let low = (value - high.cast::<f64>().unwrap()).cast::<f32>().unwrap();
+
+    (high, low)
+}
+
+// view_proj is what geometry is drawn with, moved by the jitter when there is one.
+// The unjittered matrices of this and the last update are kept for motion vectors,
+// so it has to be updated exactly once per frame.
+// The relative matrices take positions relative to the origin instead of world ones,
+// which stay precise when the camera is far from the world's origin.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 pub struct CameraUniform {
-    view_proj: [[f32; 4]; 4]
+    view_proj: [[f32; 4]; 4],
+    unjittered_view_proj: [[f32; 4]; 4],
+    prev_view_proj: [[f32; 4]; 4],
+    // xy this frame's jitter in NDC, zw the last frame's
+    jitter: [f32; 4],
+    // The camera's right and up directions in world space, for billboards.
+    right: [f32; 4],
+    up: [f32; 4],
+    relative_view_proj: [[f32; 4]; 4],
+    relative_unjittered_view_proj: [[f32; 4]; 4],
+    relative_prev_view_proj: [[f32; 4]; 4],
+    // The origin split by split_f64, and the one of the last update.
+    origin_high: [f32; 4],
+    origin_low: [f32; 4],
+    prev_origin_high: [f32; 4],
+    prev_origin_low: [f32; 4]
 }
 
 impl CameraUniform {
     pub fn new() -> Self
     {
-        use cgmath::SquareMatrix;
-
         Self {
-            view_proj: Matrix4::identity().into()
+            view_proj: Matrix4::identity().into(),
+            unjittered_view_proj: Matrix4::identity().into(),
+            prev_view_proj: Matrix4::identity().into(),
+            jitter: [0.0; 4],
+            right: [1.0, 0.0, 0.0, 0.0],
+            up: [0.0, 1.0, 0.0, 0.0],
+            relative_view_proj: Matrix4::identity().into(),
+            relative_unjittered_view_proj: Matrix4::identity().into(),
+            relative_prev_view_proj: Matrix4::identity().into(),
+            origin_high: [0.0; 4],
+            origin_low: [0.0; 4],
+            prev_origin_high: [0.0; 4],
+            prev_origin_low: [0.0; 4]
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera)
     {
-        self.view_proj = camera.build_view_projection_matrix().into();
+        let relative = camera.relative_to(self.origin()).build_view_projection_matrix();
+        self.update(camera, camera.build_view_projection_matrix(), relative);
+    }
+
+    pub fn update_clipped_view_proj(&mut self, camera: &Camera, plane: Vector4<f32>)
+    {
+        // The same plane through positions relative to the origin.
+        let origin = self.origin();
+        let offset = plane.truncate().cast::<f64>().unwrap().dot(origin.to_vec()) as f32;
+        let relative_plane = Vector4::new(plane.x, plane.y, plane.z, plane.w + offset);
+        let relative = camera.relative_to(origin).build_clipped_view_projection_matrix(relative_plane);
+        self.update(camera, camera.build_clipped_view_projection_matrix(plane), relative);
+    }
+
+    // What the relative matrices are relative to, usually the eye. Applies from the
+    // next update on.
+    pub fn set_origin(&mut self, origin: Point3<f64>)
+    {
+        let (high, low) = split_f64(origin.to_vec());
+        self.origin_high = high.extend(0.0).into();
+        self.origin_low = low.extend(0.0).into();
+    }
+
+    pub fn origin(&self) -> Point3<f64>
+    {
+        join_f64(self.origin_high, self.origin_low)
+    }
+
+    // In NDC, so a pixel is 2 / width across. Applies from the next update on.
+    pub fn set_jitter(&mut self, jitter: Vector2<f32>)
+    {
+        self.jitter[0] = jitter.x;
+        self.jitter[1] = jitter.y;
+    }
+
+    pub fn jitter(&self) -> Vector2<f32>
+    {
+        Vector2::new(self.jitter[0], self.jitter[1])
+    }
+
+    // From this update's clip space to the last one's, both without the jitter.
+    pub fn reprojection(&self) -> Matrix4<f32>
+    {
+        let inverse = Matrix4::from(self.unjittered_view_proj)
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+
+        Matrix4::from(self.prev_view_proj) * inverse
+    }
+
+    // Of view_proj, jitter included, so depth drawn with it unprojects to where it was.
+    pub fn inverse_view_proj(&self) -> Matrix4<f32>
+    {
+        Matrix4::from(self.view_proj)
+            .invert()
+            .unwrap_or_else(Matrix4::identity)
+    }
+
+    fn update(&mut self, camera: &Camera, view_proj: Matrix4<f32>, relative_view_proj: Matrix4<f32>)
+    {
+        let (right, up) = camera.right_and_up();
+        self.right = right.extend(0.0).into();
+        self.up = up.extend(0.0).into();
+
+        self.prev_view_proj = self.unjittered_view_proj;
+        self.unjittered_view_proj = view_proj.into();
+        self.jitter[2] = self.jitter[0];
+        self.jitter[3] = self.jitter[1];
+
+        // Offsets clip space xy by jitter * w, i.e. the NDC position by the jitter.
+        let jitter = Matrix4::from_translation(Vector3::new(self.jitter[0], self.jitter[1], 0.0));
+        self.view_proj = (jitter * view_proj).into();
+
+        // The last update's matrix was relative to its own origin, it's moved by how far
+        // the origin has since.
+        let origin = self.origin();
+        let moved = (origin - join_f64(self.prev_origin_high, self.prev_origin_low)).cast().unwrap();
+        self.relative_prev_view_proj = (Matrix4::from(self.relative_unjittered_view_proj)
+            * Matrix4::from_translation(moved)).into();
+        self.relative_unjittered_view_proj = relative_view_proj.into();
+        self.relative_view_proj = (jitter * relative_view_proj).into();
+        self.prev_origin_high = self.origin_high;
+        self.prev_origin_low = self.origin_low;
     }
 }
 
+fn join_f64(high: [f32; 4], low: [f32; 4]) -> Point3<f64>
+{
+    Point3::new(high[0] as f64 + low[0] as f64, high[1] as f64 + low[1] as f64, high[2] as f64 + low[2] as f64)
+}
+
 pub struct CameraController {
     speed: f32,
     is_forward_pressed: bool,
     is_backward_pressed: bool,
     is_left_pressed: bool,
     is_right_pressed: bool,
+    // Analog input on top of the pressed actions, e.g. from a gamepad's sticks. Both go
+    // from -1 to 1, x to the right and y forward or up.
+    move_axis: Vector2<f32>,
+    look_axis: Vector2<f32>,
+    speed_scale: f32,
+    // One-off movement, e.g. from touch gestures, applied on the next update. The look
+    // is in radians, the pan in view heights and the zoom a factor of the distance to
+    // the target.
+    pending_look: Vector2<f32>,
+    pending_pan: Vector2<f32>,
+    pending_zoom: f32,
+    // Downwards acceleration in units per second squared, None leaves the height alone.
+    gravity: Option<f32>,
+    eye_height: f32,
+    fall_speed: f32
 }
 
 impl CameraController {
@@ -67,67 +434,170 @@ impl CameraController {
             is_backward_pressed: false,
             is_left_pressed: false,
             is_right_pressed: false,
+            move_axis: Vector2::new(0.0, 0.0),
+            look_axis: Vector2::new(0.0, 0.0),
+            speed_scale: 1.0,
+            pending_look: Vector2::new(0.0, 0.0),
+            pending_pan: Vector2::new(0.0, 0.0),
+            pending_zoom: 1.0,
+            gravity: None,
+            eye_height: 1.0,
+            fall_speed: 0.0
+        }
+    }
+
+    pub fn speed(&self) -> f32
+    {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32)
+    {
+        self.speed = speed;
+    }
+
+    pub fn set_gravity(&mut self, gravity: Option<f32>, eye_height: f32)
+    {
+        self.gravity = gravity;
+        self.eye_height = eye_height;
+        self.fall_speed = 0.0;
+    }
+
+    // Movement is held down, so it follows both presses and releases of its actions.
+    pub fn process_action(&mut self, action: Action, pressed: bool) -> bool {
+        match action {
+            Action::MoveForward => self.is_forward_pressed = pressed,
+            Action::MoveLeft => self.is_left_pressed = pressed,
+            Action::MoveBackward => self.is_backward_pressed = pressed,
+            Action::MoveRight => self.is_right_pressed = pressed,
+            _ => return false
         }
+
+        true
+    }
+
+    // Kept until the next call, so it's meant to be set every frame. `speed_scale`
+    // multiplies the speed of all movement.
+    pub fn set_analog_input(&mut self, move_axis: Vector2<f32>, look_axis: Vector2<f32>, speed_scale: f32)
+    {
+        self.move_axis = move_axis;
+        self.look_axis = look_axis;
+        self.speed_scale = speed_scale.max(0.0);
+    }
+
+    // Positive yaw turns right and positive pitch up.
+    pub fn look_by(&mut self, yaw_pitch: Vector2<f32>)
+    {
+        self.pending_look += yaw_pitch;
+    }
+
+    // Moves the eye and target together across the view, by fractions of its height
+    // at the target so what's there follows along. Positive x goes right, positive y up.
+    pub fn pan_by(&mut self, view_heights: Vector2<f32>)
+    {
+        self.pending_pan += view_heights;
     }
 
-    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput {
-                event: KeyEvent {
-                    state,
-                    physical_key: PhysicalKey::Code(keycode),
-                    ..
-                },
-                ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    KeyCode::KeyW => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyA => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyS => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyD => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            _ => false,
-        }
-    }
-
-    pub fn update_camera(&self, camera: &mut Camera) {
-        use cgmath::InnerSpace;
+    // Above 1 moves the eye closer to the target, below 1 further away.
+    pub fn zoom_by(&mut self, factor: f32)
+    {
+        if factor > 0.0 {
+            self.pending_zoom *= factor;
+        }
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera) {
+        let axis = |positive: bool, negative: bool, analog: f32| {
+            (positive as i32 - negative as i32) as f32 + analog
+        };
+        let forward_amount = axis(self.is_forward_pressed, self.is_backward_pressed, self.move_axis.y)
+            .clamp(-1.0, 1.0);
+        let right_amount = axis(self.is_right_pressed, self.is_left_pressed, self.move_axis.x)
+            .clamp(-1.0, 1.0);
+        let speed = self.speed * self.speed_scale;
+
         let forward = camera.target - camera.eye;
         let forward_norm = forward.normalize();
         let forward_mag = forward.magnitude();
 
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+        if (forward_amount > 0.0 && forward_mag > speed) || forward_amount < 0.0 {
+            camera.eye += forward_norm * speed * forward_amount;
         }
 
         let right = forward_norm.cross(camera.up);
         let forward = camera.target - camera.eye;
         let forward_mag = forward.magnitude();
 
-        if self.is_right_pressed {
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
+        if right_amount != 0.0 {
+            camera.eye = camera.target - (forward + right * speed * right_amount).normalize() * forward_mag;
+        }
+
+        let pending_look = std::mem::replace(&mut self.pending_look, Vector2::new(0.0, 0.0));
+        let look = self.look_axis * LOOK_SPEED + pending_look;
+        if look != Vector2::new(0.0, 0.0) {
+            Self::look(camera, look);
+        }
+        let pan = std::mem::replace(&mut self.pending_pan, Vector2::new(0.0, 0.0));
+        if pan != Vector2::new(0.0, 0.0) {
+            Self::pan(camera, pan);
+        }
+        let zoom = std::mem::replace(&mut self.pending_zoom, 1.0);
+        if zoom != 1.0 {
+            let forward = camera.target - camera.eye;
+            let distance = (forward.magnitude() / zoom).max(MIN_ZOOM_DISTANCE);
+            camera.eye = camera.target - forward.normalize() * distance;
+        }
+    }
+
+    fn pan(camera: &mut Camera, view_heights: Vector2<f32>)
+    {
+        let forward = camera.target - camera.eye;
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward).normalize();
+        let view_height = 2.0 * forward.magnitude() * (Deg(camera.fovy) / 2.0).tan();
+
+        let offset = (right * view_heights.x + up * view_heights.y) * view_height;
+        camera.eye += offset;
+        camera.target += offset;
+    }
+
+    // Turns the view around the eye by `yaw_pitch` radians, keeping the pitch short of
+    // straight up or down so the up vector still works.
+    fn look(camera: &mut Camera, yaw_pitch: Vector2<f32>)
+    {
+        let forward = camera.target - camera.eye;
+        let up = camera.up.normalize();
+        let right = forward.cross(up).normalize();
+
+        let pitch = forward.normalize().dot(up).clamp(-1.0, 1.0).asin();
+        let new_pitch = (pitch + yaw_pitch.y).clamp(-MAX_LOOK_PITCH, MAX_LOOK_PITCH);
+        let rotation = Quaternion::from_axis_angle(up, Rad(-yaw_pitch.x))
+            * Quaternion::from_axis_angle(right, Rad(new_pitch - pitch));
+
+        camera.target = camera.eye + rotation.rotate_vector(forward);
+    }
+
+    // Keeps the eye at least eye_height above `ground_height`, and with gravity pulls
+    // it back down onto it. The target moves along so the view direction is kept.
+    pub fn follow_ground(&mut self, camera: &mut Camera, ground_height: Option<f32>, delta: f32)
+    {
+        let Some(floor) = ground_height.map(|height| height + self.eye_height) else {
+            self.fall_speed = 0.0;
+            return;
+        };
+
+        let mut eye_y = camera.eye.y;
+        if let Some(gravity) = self.gravity {
+            self.fall_speed += gravity * delta;
+            eye_y -= self.fall_speed * delta;
         }
-        if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+        if eye_y <= floor {
+            eye_y = floor;
+            self.fall_speed = 0.0;
         }
+
+        let offset = eye_y - camera.eye.y;
+        camera.eye.y += offset;
+        camera.target.y += offset;
     }
 }
-
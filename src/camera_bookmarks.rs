@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+
+use cgmath::{Point3, Vector3};
+use winit::keyboard::KeyCode;
+
+use super::camera::Camera;
+
+pub const BOOKMARK_SLOTS: usize = 9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraBookmark {
+    pub name: String,
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32
+}
+
+impl CameraBookmark {
+    pub fn from_camera(name: &str, camera: &Camera) -> Self
+    {
+        Self {
+            name: String::from(name),
+            eye: camera.eye,
+            target: camera.target,
+            up: camera.up,
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar
+        }
+    }
+
+    // The aspect ratio belongs to the window, not the view, so it's left alone.
+    pub fn apply(&self, camera: &mut Camera)
+    {
+        camera.eye = self.eye;
+        camera.target = self.target;
+        camera.up = self.up;
+        camera.fovy = self.fovy;
+        camera.znear = self.znear;
+        camera.zfar = self.zfar;
+    }
+
+    fn to_line(&self, slot: usize) -> String
+    {
+        let values = [
+            self.eye.x, self.eye.y, self.eye.z,
+            self.target.x, self.target.y, self.target.z,
+            self.up.x, self.up.y, self.up.z,
+            self.fovy, self.znear, self.zfar
+        ];
+
+        format!("{}\t{}\t{}", slot + 1, self.name.replace(['\t', '\n'], " "),
+            values.map(|value| value.to_string()).join(" "))
+    }
+
+    fn from_line(line: &str) -> Option<(usize, Self)>
+    {
+        let mut fields = line.splitn(3, '\t');
+        let slot = fields.next()?.parse::<usize>().ok()?.checked_sub(1)?;
+        let name = fields.next()?;
+        let values = fields.next()?
+            .split_whitespace()
+            .map(|value| value.parse::<f32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let [ex, ey, ez, tx, ty, tz, ux, uy, uz, fovy, znear, zfar] = values[..] else {
+            return None;
+        };
+
+        Some((slot, Self {
+            name: String::from(name),
+            eye: Point3::new(ex, ey, ez),
+            target: Point3::new(tx, ty, tz),
+            up: Vector3::new(ux, uy, uz),
+            fovy,
+            znear,
+            zfar
+        }))
+    }
+}
+
+// Slots 1-9 map to the number keys. With a path, every change is written back to a
+// small tab separated file so bookmarks survive restarts and can be referenced by
+// name from tests and benchmarks.
+pub struct CameraBookmarks {
+    slots: [Option<CameraBookmark>; BOOKMARK_SLOTS],
+    path: Option<PathBuf>
+}
+
+impl CameraBookmarks {
+    pub fn new(path: Option<&Path>) -> Self
+    {
+        let mut bookmarks = Self {
+            slots: Default::default(),
+            path: path.map(Path::to_path_buf)
+        };
+        bookmarks.load();
+
+        bookmarks
+    }
+
+    pub fn slot_for_key(code: KeyCode) -> Option<usize>
+    {
+        let slot = match code {
+            KeyCode::Digit1 => 0,
+            KeyCode::Digit2 => 1,
+            KeyCode::Digit3 => 2,
+            KeyCode::Digit4 => 3,
+            KeyCode::Digit5 => 4,
+            KeyCode::Digit6 => 5,
+            KeyCode::Digit7 => 6,
+            KeyCode::Digit8 => 7,
+            KeyCode::Digit9 => 8,
+            _ => return None
+        };
+
+        Some(slot)
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&CameraBookmark>
+    {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&CameraBookmark>
+    {
+        self.slots.iter()
+            .flatten()
+            .find(|bookmark| bookmark.name == name)
+    }
+
+    pub fn set(&mut self, slot: usize, bookmark: CameraBookmark) -> bool
+    {
+        let Some(entry) = self.slots.get_mut(slot) else {
+            return false;
+        };
+        *entry = Some(bookmark);
+        self.persist();
+
+        true
+    }
+
+    fn load(&mut self)
+    {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return;
+        };
+
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match CameraBookmark::from_line(line) {
+                Some((slot, bookmark)) if slot < BOOKMARK_SLOTS => self.slots[slot] = Some(bookmark),
+                _ => log::warn!("Ignoring malformed camera bookmark in {}: {line}", path.display())
+            }
+        }
+    }
+
+    fn persist(&self)
+    {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let contents = self.slots.iter()
+            .enumerate()
+            .filter_map(|(slot, bookmark)| bookmark.as_ref().map(|bookmark| bookmark.to_line(slot)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Err(e) = std::fs::write(path, contents + "\n") {
+            log::warn!("Couldn't save camera bookmarks to {}: {e}", path.display());
+        }
+    }
+}
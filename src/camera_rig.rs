@@ -0,0 +1,187 @@
+use std::ops::{Add, Mul, Sub};
+
+use cgmath::{EuclideanSpace, Point3, Vector3};
+
+use super::camera::Camera;
+
+// Where the camera is at `time` seconds into a path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    // In degrees, like Camera's.
+    pub fovy: f32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RigMotion {
+    // Keeps the eye at `offset` from `target`, looking at it, as it moves.
+    Follow {
+        target: Point3<f32>,
+        offset: Vector3<f32>
+    },
+    // Flies through the keyframes along a Catmull-Rom spline, from the first one's time
+    // to the last one's, starting over when looping.
+    Path {
+        keyframes: Vec<CameraKeyframe>,
+        looping: bool
+    }
+}
+
+// Moves the camera by itself instead of the interactive controllers, e.g. for
+// fly-through demos. The eye of a follow and the point looked at are damped, so they
+// lag smoothly behind where they're going.
+#[derive(Debug, Clone)]
+pub struct CameraRig {
+    motion: RigMotion,
+    // Seconds to close about two thirds of the way to where the eye or the point looked
+    // at should be, 0 goes there right away.
+    follow_damping: f32,
+    look_damping: f32,
+    time: f32,
+    look_at: Option<Point3<f32>>
+}
+
+impl CameraRig {
+    pub fn new(motion: RigMotion) -> Self
+    {
+        Self {
+            motion,
+            follow_damping: 0.3,
+            look_damping: 0.15,
+            time: 0.0,
+            look_at: None
+        }
+    }
+
+    pub fn follow(target: Point3<f32>, offset: Vector3<f32>) -> Self
+    {
+        Self::new(RigMotion::Follow { target, offset })
+    }
+
+    // Sorts the keyframes by time.
+    pub fn path(mut keyframes: Vec<CameraKeyframe>, looping: bool) -> Self
+    {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Self::new(RigMotion::Path { keyframes, looping })
+    }
+
+    pub fn set_damping(&mut self, follow: f32, look: f32) -> &mut Self
+    {
+        self.follow_damping = follow.max(0.0);
+        self.look_damping = look.max(0.0);
+        self
+    }
+
+    pub fn motion(&self) -> &RigMotion
+    {
+        &self.motion
+    }
+
+    // Moves what a follow follows, meant to be called as it moves.
+    pub fn set_follow_target(&mut self, point: Point3<f32>)
+    {
+        if let RigMotion::Follow { target, .. } = &mut self.motion {
+            *target = point;
+        }
+    }
+
+    // Seconds since the rig started.
+    pub fn time(&self) -> f32
+    {
+        self.time
+    }
+
+    // Past the last keyframe of a path that doesn't loop. Follows never finish.
+    pub fn is_finished(&self) -> bool
+    {
+        match &self.motion {
+            RigMotion::Path { keyframes, looping: false } => keyframes.last()
+                .is_none_or(|last| self.time >= last.time),
+            _ => false
+        }
+    }
+
+    // Moves `camera` along by `delta` seconds.
+    pub fn update(&mut self, camera: &mut Camera, delta: f32)
+    {
+        self.time += delta;
+
+        let look_at = match &self.motion {
+            RigMotion::Follow { target, offset } => {
+                let eye = target + offset;
+                camera.eye += (eye - camera.eye) * damping_factor(self.follow_damping, delta);
+                *target
+            },
+            RigMotion::Path { keyframes, looping } => {
+                let Some(keyframe) = sample_path(keyframes, self.time, *looping) else {
+                    return;
+                };
+                camera.eye = keyframe.eye;
+                camera.fovy = keyframe.fovy;
+                keyframe.target
+            }
+        };
+
+        let smoothed = match self.look_at {
+            Some(previous) => previous + (look_at - previous) * damping_factor(self.look_damping, delta),
+            None => look_at
+        };
+        self.look_at = Some(smoothed);
+        camera.target = smoothed;
+    }
+}
+
+// How much of the remaining way to go in `delta` seconds, framerate independent.
+fn damping_factor(damping: f32, delta: f32) -> f32
+{
+    if damping > 0.0 {
+        1.0 - (-delta / damping).exp()
+    } else {
+        1.0
+    }
+}
+
+// The keyframes interpolated at `time`, None when there are none.
+fn sample_path(keyframes: &[CameraKeyframe], time: f32, looping: bool) -> Option<CameraKeyframe>
+{
+    let (first, last) = (keyframes.first()?, keyframes.last()?);
+    let duration = last.time - first.time;
+    let time = if looping && duration > 0.0 {
+        first.time + (time - first.time).rem_euclid(duration)
+    } else {
+        time.clamp(first.time, last.time)
+    };
+
+    let segment = keyframes.iter()
+        .rposition(|keyframe| keyframe.time <= time)
+        .unwrap_or(0)
+        .min(keyframes.len().saturating_sub(2));
+    let at = |offset: isize| keyframes[(segment as isize + offset).clamp(0, keyframes.len() as isize - 1) as usize];
+    let (k0, k1, k2, k3) = (at(-1), at(0), at(1), at(2));
+    let span = k2.time - k1.time;
+    let u = if span > 0.0 { ((time - k1.time) / span).clamp(0.0, 1.0) } else { 0.0 };
+
+    Some(CameraKeyframe {
+        time,
+        eye: Point3::from_vec(catmull_rom(k0.eye.to_vec(), k1.eye.to_vec(), k2.eye.to_vec(), k3.eye.to_vec(), u)),
+        target: Point3::from_vec(catmull_rom(k0.target.to_vec(), k1.target.to_vec(), k2.target.to_vec(),
+            k3.target.to_vec(), u)),
+        fovy: catmull_rom(k0.fovy, k1.fovy, k2.fovy, k3.fovy, u)
+    })
+}
+
+// Uniform Catmull-Rom between `p1` and `p2`, passing through both.
+fn catmull_rom<V>(p0: V, p1: V, p2: V, p3: V, u: f32) -> V
+where
+    V: Copy + Add<Output = V> + Sub<Output = V> + Mul<f32, Output = V>
+{
+    let (u2, u3) = (u * u, u * u * u);
+
+    (p1 * 2.0
+        + (p2 - p0) * u
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * u2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * u3) * 0.5
+}
@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use learn_wgpu::{RecordingOptions, RecordingOutput, State, StateOptions};
+use pollster::block_on;
+use wgpu::{util::parse_backends_from_comma_list, Backends};
+use winit::dpi::{LogicalSize, PhysicalSize};
+
+// Used when only one of --width and --height is given, or for a headless render
+// without either.
+const DEFAULT_SIZE: (u32, u32) = (1280, 720);
+
+// Everything here wins over the environment and the settings file.
+#[derive(Debug, Parser)]
+#[command(version, about = "Views a glTF or OBJ model, in a window or rendered headless into an image")]
+pub struct Cli {
+    #[arg(long, value_name = "PATH", help = "glTF or OBJ model to load, animated when it's skinned")]
+    model: Option<PathBuf>,
+    #[arg(long, value_name = "NAMES", value_parser = parse_backends,
+        help = "Comma separated backends to pick the adapter from, e.g. vulkan or dx12,gl")]
+    backend: Option<Backends>,
+    #[arg(long, help = "Renders with a software adapter, for machines without a GPU, e.g. with --backend gl")]
+    fallback_adapter: bool,
+    #[arg(long, help = "Width in logical pixels, physical ones when headless")]
+    width: Option<u32>,
+    #[arg(long, help = "Height in logical pixels, physical ones when headless")]
+    height: Option<u32>,
+    #[arg(long, help = "Renders without a window and saves the last frame to --output")]
+    pub headless: bool,
+    #[arg(long, default_value_t = 1, requires = "headless", help = "Frames to render before saving")]
+    frames: u32,
+    #[arg(long, value_name = "PATH", default_value = "out.png", requires = "headless",
+        help = "Image the headless render is saved to")]
+    output: PathBuf,
+    #[arg(long, value_name = "PATH", requires = "headless",
+        help = "Records every headless frame, as PNGs into a directory or with ffmpeg into a video by its extension")]
+    record: Option<PathBuf>,
+    #[arg(long, default_value_t = 30, requires = "record", help = "Frame rate of a recorded video")]
+    fps: u32
+}
+
+fn parse_backends(names: &str) -> Result<Backends, String>
+{
+    let backends = parse_backends_from_comma_list(names);
+    if backends.is_empty() {
+        return Err(String::from("expected vulkan, metal, dx12, gl or webgpu"));
+    }
+
+    Ok(backends)
+}
+
+impl Cli {
+    fn size(&self) -> Option<(u32, u32)>
+    {
+        match (self.width, self.height) {
+            (None, None) => None,
+            (width, height) => Some((width.unwrap_or(DEFAULT_SIZE.0), height.unwrap_or(DEFAULT_SIZE.1)))
+        }
+    }
+
+    pub fn apply(&self, options: &mut StateOptions)
+    {
+        if let Some(model) = &self.model {
+            options.skinned_model = Some(model.clone());
+        }
+        if let Some(backends) = self.backend {
+            options.backends = backends;
+        }
+        if self.fallback_adapter {
+            options.fallback_adapter = true;
+        }
+        if let Some((width, height)) = self.size() {
+            options.window.size = Some(LogicalSize::new(width, height));
+        }
+    }
+
+    pub fn render_headless(&self, options: StateOptions) -> anyhow::Result<()>
+    {
+        let (width, height) = self.size().unwrap_or(DEFAULT_SIZE);
+        let mut state = block_on(State::new_headless(PhysicalSize::new(width, height), options))?;
+        if let Some(path) = &self.record {
+            state.start_recording(RecordingOptions {
+                output: RecordingOutput::from_path(path, self.fps),
+                frames: None
+            })?;
+        }
+        for _ in 0..self.frames.max(1) {
+            state.update();
+            state.render()?;
+        }
+        state.stop_recording();
+
+        block_on(state.read_frame())?.save(&self.output)?;
+        println!("Saved {}", self.output.display());
+
+        Ok(())
+    }
+}
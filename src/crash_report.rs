@@ -0,0 +1,144 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}
+};
+use wgpu::{Device, DeviceLostReason};
+
+const MAX_RECENT_COMMANDS: usize = 256;
+const MAX_REPORTS: u32 = 8;
+
+#[derive(Default)]
+struct CrashLog {
+    frame: u64,
+    frame_description: Vec<String>,
+    pipelines: BTreeMap<String, String>,
+    recent_commands: VecDeque<String>,
+    reports_written: u32
+}
+
+impl CrashLog {
+    fn format_report(&self, reason: &str) -> String
+    {
+        let mut report = String::new();
+
+        writeln!(report, "reason: {reason}").ok();
+        writeln!(report, "frame: {}", self.frame).ok();
+        writeln!(report, "\n[pipelines]").ok();
+        for (label, shader) in &self.pipelines {
+            writeln!(report, "{label}: {shader}").ok();
+        }
+        writeln!(report, "\n[last frame]").ok();
+        for command in &self.frame_description {
+            writeln!(report, "{command}").ok();
+        }
+        writeln!(report, "\n[recent commands]").ok();
+        for command in &self.recent_commands {
+            writeln!(report, "{command}").ok();
+        }
+
+        report
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CrashReporter {
+    log: Arc<Mutex<CrashLog>>,
+    device_lost: Arc<AtomicBool>
+}
+
+impl CrashReporter {
+    pub fn install(&self, device: &Device)
+    {
+        self.device_lost.store(false, Ordering::SeqCst);
+
+        let reporter = self.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            log::error!("wgpu error: {error}");
+            reporter.write_report(&format!("uncaptured error: {error}"));
+        }));
+
+        let reporter = self.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            // wgpu 0.19 reports the device simply being dropped as Unknown too.
+            let dropped = message == "Device dropped.";
+            if matches!(reason, DeviceLostReason::Unknown | DeviceLostReason::Destroyed) && !dropped {
+                reporter.device_lost.store(true, Ordering::SeqCst);
+                reporter.write_report(&format!("device lost ({reason:?}): {message}"));
+            }
+        });
+    }
+
+    pub fn is_device_lost(&self) -> bool
+    {
+        self.device_lost.load(Ordering::SeqCst)
+    }
+
+    pub fn register_pipeline(&self, label: &str, shader: &str)
+    {
+        if let Ok(mut log) = self.log.lock() {
+            log.pipelines.insert(String::from(label), String::from(shader));
+        }
+    }
+
+    pub fn unregister_pipeline(&self, label: &str)
+    {
+        if let Ok(mut log) = self.log.lock() {
+            log.pipelines.remove(label);
+        }
+    }
+
+    pub fn begin_frame(&self)
+    {
+        if let Ok(mut log) = self.log.lock() {
+            log.frame += 1;
+            log.frame_description.clear();
+        }
+    }
+
+    pub fn record(&self, command: impl Into<String>)
+    {
+        if let Ok(mut log) = self.log.lock() {
+            let command = format!("[{}] {}", log.frame, command.into());
+
+            if log.recent_commands.len() == MAX_RECENT_COMMANDS {
+                log.recent_commands.pop_front();
+            }
+            log.recent_commands.push_back(command.clone());
+            log.frame_description.push(command);
+        }
+    }
+
+    pub fn write_report(&self, reason: &str)
+    {
+        let Ok(mut log) = self.log.lock() else { return };
+        if log.reports_written >= MAX_REPORTS {
+            return;
+        }
+        log.reports_written += 1;
+
+        let report = log.format_report(reason);
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                log::error!("GPU crash report:\n{report}");
+            } else {
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or_default();
+                let report_dir = std::env::current_dir()
+                    .unwrap_or_default()
+                    .join("crash_reports")
+                    .join(format!("{timestamp}-frame{}-{}", log.frame, log.reports_written));
+
+                let written = std::fs::create_dir_all(&report_dir)
+                    .and_then(|_| std::fs::write(report_dir.join("report.txt"), report));
+                match written {
+                    Ok(()) => log::error!("GPU crash report written to {}", report_dir.display()),
+                    Err(e) => log::error!("Couldn't write GPU crash report: {e}")
+                }
+            }
+        }
+    }
+}
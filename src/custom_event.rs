@@ -1,4 +1,9 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum CustomEvent {
-    Timer
+    Timer,
+    // Name of a one-shot animation clip that reached its end.
+    AnimationFinished(String),
+    // Sent from the page's JavaScript, see web_api.rs.
+    #[cfg(target_arch = "wasm32")]
+    Web(crate::web_api::WebCommand)
 }
@@ -0,0 +1,89 @@
+use std::{io, path::PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RendererError {
+    #[error("couldn't create the window surface: {0}")]
+    CreateSurface(#[from] wgpu::CreateSurfaceError),
+    #[error("no compatible GPU adapter was found")]
+    NoAdapter,
+    #[error("couldn't create the GPU device: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+    #[error("couldn't read shader {path}: {source}")]
+    ShaderRead {
+        path: PathBuf,
+        source: io::Error
+    },
+    #[error("no shader was set for {pipeline}")]
+    MissingShader {
+        pipeline: String
+    },
+    #[error("{shader}:{line}: {message}")]
+    ShaderPreprocess {
+        shader: String,
+        line: usize,
+        message: String
+    },
+    #[error("{file}:{line}:{column}: {message}\n{excerpt}")]
+    ShaderValidation {
+        file: String,
+        line: usize,
+        column: usize,
+        message: String,
+        excerpt: String
+    },
+    #[error("couldn't load model {path}: {source}")]
+    Model {
+        path: PathBuf,
+        source: anyhow::Error
+    },
+    #[error("can't open {path}, only glTF and OBJ models and PNG and JPEG images are supported")]
+    UnsupportedFile {
+        path: PathBuf
+    },
+    #[error("couldn't read LUT {path}: {source}")]
+    LutRead {
+        path: PathBuf,
+        source: io::Error
+    },
+    #[error("{file}:{line}: {message}")]
+    CubeLut {
+        file: String,
+        line: usize,
+        message: String
+    },
+    #[error("{label}: {message}")]
+    GpuValidation {
+        label: String,
+        message: String
+    },
+    #[error("{label}: out of GPU memory")]
+    GpuOutOfMemory {
+        label: String
+    },
+    #[error("couldn't read back from the GPU: {0}")]
+    Readback(#[from] wgpu::BufferAsyncError),
+    #[error("can't read back {0:?} textures")]
+    ReadbackFormat(wgpu::TextureFormat),
+    #[error("{0} needs compute shaders, which this device doesn't have")]
+    NoComputeShaders(String),
+    #[error("couldn't record the frames: {0}")]
+    Recording(io::Error),
+    #[error("couldn't fetch {url}: {message}")]
+    Fetch {
+        url: String,
+        message: String
+    },
+    #[error("couldn't start the render worker: {0}")]
+    Worker(String),
+    #[error("only a headless renderer can read its frames back")]
+    NotHeadless,
+    #[error("couldn't decode image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error(transparent)]
+    Texture(#[from] anyhow::Error),
+    #[error("couldn't create the event loop: {0}")]
+    EventLoop(#[from] winit::error::EventLoopError),
+    #[error("couldn't create the window: {0}")]
+    Window(#[from] winit::error::OsError)
+}
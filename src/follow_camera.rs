@@ -0,0 +1,112 @@
+use cgmath::{InnerSpace, Point3, Vector2, Vector3};
+
+use super::{bounds::Aabb, camera::Camera, picking::Ray};
+
+// Keeps the pitch short of straight up or down, like the free camera's look.
+const MAX_PITCH: f32 = 1.4;
+// How close the camera gets to the pivot when something is right behind the target.
+const MIN_DISTANCE: f32 = 0.2;
+
+// A third person camera behind an instance, orbiting it as it's turned. When something
+// is between the target and where the camera would be, the camera zooms in in front of
+// it, and eases back out once it's clear.
+#[derive(Debug, Clone)]
+pub struct FollowCamera {
+    // The index of the instance followed.
+    pub target: usize,
+    // From the pivot to the eye.
+    pub distance: f32,
+    // Of the pivot the camera looks at above the target's origin.
+    pub height: f32,
+    // Moves the pivot to the right of the view, negative to the left, so the target
+    // isn't in the way of what's ahead.
+    pub shoulder_offset: f32,
+    // Of the sphere swept from the pivot to the eye, which keeps the near plane clear
+    // of what it hits.
+    pub collision_radius: f32,
+    // Seconds to ease about two thirds of the way back out after a collision.
+    pub zoom_out_damping: f32,
+    // Around the world up axis and above the horizon, in radians. At 0 the camera is
+    // on the +z side of the target.
+    yaw: f32,
+    pitch: f32,
+    current_distance: Option<f32>
+}
+
+impl FollowCamera {
+    pub fn new(target: usize) -> Self
+    {
+        Self {
+            target,
+            distance: 4.0,
+            height: 1.5,
+            shoulder_offset: 0.5,
+            collision_radius: 0.2,
+            zoom_out_damping: 0.3,
+            yaw: 0.0,
+            pitch: 0.3,
+            current_distance: None
+        }
+    }
+
+    // Positive yaw swings the view right and positive pitch looks further down.
+    pub fn orbit_by(&mut self, yaw_pitch: Vector2<f32>)
+    {
+        self.yaw -= yaw_pitch.x;
+        self.pitch = (self.pitch + yaw_pitch.y).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // Above 1 moves closer, below 1 further away.
+    pub fn zoom_by(&mut self, factor: f32)
+    {
+        if factor > 0.0 {
+            self.distance = (self.distance / factor).max(MIN_DISTANCE);
+        }
+    }
+
+    // Places `camera` behind `target_position`. `obstacles` are what it can't go
+    // through, and `cast` how far a ray gets through anything else, e.g. terrain,
+    // within a distance.
+    pub fn update(
+        &mut self,
+        camera: &mut Camera,
+        target_position: Point3<f32>,
+        obstacles: &[Aabb],
+        cast: impl Fn(&Ray, f32) -> Option<f32>,
+        delta: f32
+    )
+    {
+        let up = Vector3::unit_y();
+        let back = Vector3::new(self.pitch.cos() * self.yaw.sin(), self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos());
+        let right = up.cross(back).normalize();
+        let pivot = target_position + up * self.height + right * self.shoulder_offset;
+
+        let ray = Ray::new(pivot, back);
+        let grow = Vector3::new(1.0, 1.0, 1.0) * self.collision_radius;
+        let blocked = obstacles.iter()
+            .map(|aabb| Aabb {
+                min: aabb.min - grow,
+                max: aabb.max + grow
+            })
+            // Boxes around the pivot would pull the camera all the way in.
+            .filter(|aabb| (0..3).any(|axis| pivot[axis] < aabb.min[axis] || pivot[axis] > aabb.max[axis]))
+            .filter_map(|aabb| ray.intersect_aabb(&aabb))
+            .chain(cast(&ray, self.distance).map(|distance| distance - self.collision_radius))
+            .fold(self.distance, f32::min)
+            .max(MIN_DISTANCE);
+
+        // Zooming in is immediate so nothing gets between the camera and the target.
+        let distance = match self.current_distance {
+            Some(current) if current < blocked => {
+                current + (blocked - current) * (1.0 - (-delta / self.zoom_out_damping.max(f32::EPSILON)).exp())
+            },
+            _ => blocked
+        };
+        self.current_distance = Some(distance);
+
+        camera.eye = pivot + back * distance;
+        camera.target = pivot;
+        camera.up = up;
+    }
+}
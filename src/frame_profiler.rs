@@ -0,0 +1,108 @@
+use std::{cell::RefCell, rc::Rc};
+
+use tracing::span::EnteredSpan;
+use web_time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct ScopeStats {
+    pub name: &'static str,
+    pub last: Duration,
+    pub min: Duration,
+    pub average: Duration,
+    pub max: Duration,
+    pub frames: u64
+}
+
+struct ScopeEntry {
+    name: &'static str,
+    frame_total: Duration,
+    total: Duration,
+    stats: ScopeStats
+}
+
+type Scopes = Rc<RefCell<Vec<ScopeEntry>>>;
+
+// Records elapsed time into its scope when dropped. Also a span, so traces break the
+// frame down the same way.
+pub struct ScopeTimer {
+    name: &'static str,
+    start: Instant,
+    scopes: Scopes,
+    _span: EnteredSpan
+}
+
+impl Drop for ScopeTimer {
+    fn drop(&mut self)
+    {
+        let elapsed = self.start.elapsed();
+        let mut scopes = self.scopes.borrow_mut();
+
+        match scopes.iter_mut().find(|entry| entry.name == self.name) {
+            Some(entry) => entry.frame_total += elapsed,
+            None => scopes.push(ScopeEntry {
+                name: self.name,
+                frame_total: elapsed,
+                total: Duration::ZERO,
+                stats: ScopeStats {
+                    name: self.name,
+                    last: Duration::ZERO,
+                    min: Duration::MAX,
+                    average: Duration::ZERO,
+                    max: Duration::ZERO,
+                    frames: 0
+                }
+            })
+        }
+    }
+}
+
+// Scopes entered several times in one frame are summed before being folded into
+// the min/avg/max stats, so the stats are always per frame.
+#[derive(Default)]
+pub struct FrameProfiler {
+    scopes: Scopes
+}
+
+impl FrameProfiler {
+    pub fn begin_frame(&mut self)
+    {
+        for entry in self.scopes.borrow_mut().iter_mut() {
+            let elapsed = std::mem::take(&mut entry.frame_total);
+            if elapsed.is_zero() {
+                continue;
+            }
+
+            let stats = &mut entry.stats;
+            entry.total += elapsed;
+            stats.frames += 1;
+            stats.last = elapsed;
+            stats.min = stats.min.min(elapsed);
+            stats.max = stats.max.max(elapsed);
+            stats.average = entry.total / stats.frames as u32;
+        }
+    }
+
+    pub fn scope(&self, name: &'static str) -> ScopeTimer
+    {
+        ScopeTimer {
+            name,
+            start: Instant::now(),
+            scopes: self.scopes.clone(),
+            _span: tracing::info_span!("frame_scope", name).entered()
+        }
+    }
+
+    pub fn stats(&self) -> Vec<ScopeStats>
+    {
+        self.scopes.borrow()
+            .iter()
+            .filter(|entry| entry.stats.frames > 0)
+            .map(|entry| entry.stats.clone())
+            .collect()
+    }
+
+    pub fn reset(&mut self)
+    {
+        self.scopes.borrow_mut().clear();
+    }
+}
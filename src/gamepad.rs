@@ -0,0 +1,131 @@
+use cgmath::Vector2;
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+
+use super::input_map::{ActionEvent, Binding, InputMap};
+
+// Stick values closer to rest than this read as zero, worn sticks rarely center.
+const STICK_DEAD_ZONE: f32 = 0.15;
+
+// What the sticks and triggers of the active gamepad read this frame. Sticks go from
+// -1 to 1 with up and right positive, triggers from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GamepadAxes {
+    pub left_stick: Vector2<f32>,
+    pub right_stick: Vector2<f32>,
+    pub left_trigger: f32,
+    pub right_trigger: f32
+}
+
+impl Default for GamepadAxes {
+    fn default() -> Self
+    {
+        Self {
+            left_stick: Vector2::new(0.0, 0.0),
+            right_stick: Vector2::new(0.0, 0.0),
+            left_trigger: 0.0,
+            right_trigger: 0.0
+        }
+    }
+}
+
+impl GamepadAxes {
+    // The right trigger speeds movement up to three times, the left one slows it down
+    // to a quarter.
+    pub fn speed_scale(&self) -> f32
+    {
+        1.0 + 2.0 * self.right_trigger - 0.75 * self.left_trigger
+    }
+}
+
+// Polls the connected gamepads. Buttons go through the InputMap like keys do, the
+// sticks and triggers are analog so they're read as axes instead. The last gamepad
+// anything was pressed on is the active one.
+pub struct Gamepads {
+    gilrs: Gilrs,
+    active: Option<GamepadId>
+}
+
+impl Gamepads {
+    // None where the platform has no gamepad support, which is logged.
+    pub fn new() -> Option<Self>
+    {
+        match Gilrs::new() {
+            Ok(gilrs) => {
+                for (_, gamepad) in gilrs.gamepads() {
+                    log::info!("Gamepad connected: {}", gamepad.name());
+                }
+                Some(Self {
+                    gilrs,
+                    active: None
+                })
+            },
+            Err(e) => {
+                log::warn!("Gamepads aren't available: {e}");
+                None
+            }
+        }
+    }
+
+    // Every button event since the last poll that's bound to an action.
+    pub fn poll(&mut self, input_map: &InputMap) -> Vec<ActionEvent>
+    {
+        let mut actions = Vec::new();
+        while let Some(event) = self.gilrs.next_event() {
+            let (button, pressed) = match event.event {
+                EventType::ButtonPressed(button, _) => (button, true),
+                EventType::ButtonReleased(button, _) => (button, false),
+                EventType::Connected => {
+                    log::info!("Gamepad connected: {}", self.gilrs.gamepad(event.id).name());
+                    continue;
+                },
+                EventType::Disconnected => {
+                    log::info!("Gamepad disconnected: {}", self.gilrs.gamepad(event.id).name());
+                    if self.active == Some(event.id) {
+                        self.active = None;
+                    }
+                    continue;
+                },
+                EventType::AxisChanged(..) | EventType::ButtonChanged(..) => {
+                    self.active = Some(event.id);
+                    continue;
+                },
+                _ => continue
+            };
+            self.active = Some(event.id);
+
+            if let Some(action) = input_map.action(Binding::Gamepad(button)) {
+                actions.push(ActionEvent {
+                    action,
+                    pressed,
+                    repeat: false
+                });
+            }
+        }
+
+        actions
+    }
+
+    pub fn axes(&self) -> GamepadAxes
+    {
+        let Some(gamepad) = self.active
+            .and_then(|id| self.gilrs.connected_gamepad(id)) else {
+            return GamepadAxes::default();
+        };
+        let stick = |x, y| {
+            let value = Vector2::new(gamepad.value(x), gamepad.value(y));
+            if value.x.hypot(value.y) < STICK_DEAD_ZONE {
+                Vector2::new(0.0, 0.0)
+            } else {
+                value
+            }
+        };
+        let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+
+        GamepadAxes {
+            left_stick: stick(Axis::LeftStickX, Axis::LeftStickY),
+            right_stick: stick(Axis::RightStickX, Axis::RightStickY),
+            left_trigger: trigger(Button::LeftTrigger2),
+            right_trigger: trigger(Button::RightTrigger2)
+        }
+    }
+}
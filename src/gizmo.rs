@@ -0,0 +1,278 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Quaternion, Rad, Rotation, Rotation3, Vector3};
+
+use super::{bounds::Aabb, instance::Instance, picking::Ray, renderer_backend::debug_lines::DebugLines};
+
+// How long the handles are as a fraction of the gizmo's distance from the eye, which
+// keeps it the same size on screen.
+const SCREEN_SIZE: f32 = 0.15;
+// How close the cursor ray has to pass a handle, as a fraction of the handle length.
+const PICK_TOLERANCE: f32 = 0.08;
+// Scaling an axis stops here rather than flipping the instance inside out.
+const MIN_SCALE: f32 = 0.01;
+const AXIS_COLORS: [[f32; 3]; 3] = [[1.0, 0.2, 0.2], [0.2, 0.9, 0.2], [0.3, 0.4, 1.0]];
+const ACTIVE_COLOR: [f32; 3] = [1.0, 0.9, 0.1];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale
+}
+
+impl GizmoMode {
+    pub fn next(&self) -> Self
+    {
+        match self {
+            GizmoMode::Translate => GizmoMode::Rotate,
+            GizmoMode::Rotate => GizmoMode::Scale,
+            GizmoMode::Scale => GizmoMode::Translate
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstanceTransform {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>
+}
+
+impl InstanceTransform {
+    pub fn of(instance: &Instance) -> Self
+    {
+        Self {
+            position: instance.position,
+            rotation: instance.rotation,
+            scale: instance.scale
+        }
+    }
+
+    pub fn apply(&self, instance: &mut Instance)
+    {
+        instance.position = self.position;
+        instance.rotation = self.rotation;
+        instance.scale = self.scale;
+    }
+}
+
+// One finished drag of the gizmo, e.g. for an undo history or saving the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformEdit {
+    pub instance: usize,
+    pub before: InstanceTransform,
+    pub after: InstanceTransform
+}
+
+struct Drag {
+    instance: usize,
+    axis: usize,
+    start: InstanceTransform,
+    // Where the cursor ray first hit the drag plane.
+    start_point: Point3<f32>,
+    plane_normal: Vector3<f32>,
+    size: f32
+}
+
+// Translates, rotates or scales one instance along one axis at a time, picked and
+// dragged with the cursor ray. Moving and rotating go along the world axes, scaling
+// along the instance's own ones.
+#[derive(Default)]
+pub struct Gizmo {
+    mode: GizmoMode,
+    hovered: Option<usize>,
+    drag: Option<Drag>
+}
+
+impl Gizmo {
+    pub fn mode(&self) -> GizmoMode
+    {
+        self.mode
+    }
+
+    // A drag in progress has to be ended first, it's along the old mode's axes.
+    pub fn set_mode(&mut self, mode: GizmoMode)
+    {
+        self.mode = mode;
+        self.hovered = None;
+    }
+
+    pub fn is_dragging(&self) -> bool
+    {
+        self.drag.is_some()
+    }
+
+    fn size(center: Point3<f32>, eye: Point3<f32>) -> f32
+    {
+        (center - eye).magnitude() * SCREEN_SIZE
+    }
+
+    fn axes(&self, transform: &InstanceTransform) -> [Vector3<f32>; 3]
+    {
+        let world = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+        match self.mode {
+            GizmoMode::Scale => world.map(|axis| transform.rotation.rotate_vector(axis)),
+            GizmoMode::Translate | GizmoMode::Rotate => world
+        }
+    }
+
+    // The handle `ray` passes closest to, within the tolerance.
+    fn pick_axis(&self, ray: &Ray, transform: &InstanceTransform, eye: Point3<f32>) -> Option<usize>
+    {
+        let center = Point3::from_vec(transform.position);
+        let size = Self::size(center, eye);
+
+        self.axes(transform).into_iter()
+            .enumerate()
+            .filter_map(|(index, axis)| {
+                let distance = match self.mode {
+                    GizmoMode::Translate | GizmoMode::Scale => ray_segment_distance(ray, center, center + axis * size),
+                    GizmoMode::Rotate => {
+                        let t = ray.intersect_plane(center, axis)?;
+                        ((ray.at(t) - center).magnitude() - size).abs()
+                    }
+                };
+                (distance < size * PICK_TOLERANCE).then_some((index, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+
+    // Highlights the handle under the cursor, returning whether there is one.
+    pub fn hover(&mut self, ray: &Ray, transform: &InstanceTransform, eye: Point3<f32>) -> bool
+    {
+        if self.drag.is_none() {
+            self.hovered = self.pick_axis(ray, transform, eye);
+        }
+
+        self.hovered.is_some()
+    }
+
+    // Starts dragging the handle under `ray`, false when it misses all of them.
+    pub fn begin_drag(&mut self, ray: &Ray, instance: usize, transform: &InstanceTransform, eye: Point3<f32>) -> bool
+    {
+        let Some(axis_index) = self.pick_axis(ray, transform, eye) else {
+            return false;
+        };
+        let center = Point3::from_vec(transform.position);
+        let axis = self.axes(transform)[axis_index];
+        let plane_normal = match self.mode {
+            GizmoMode::Rotate => axis,
+            // The plane through the axis that faces the eye the most, looking straight
+            // down the axis falls back to facing the eye.
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let view = center - eye;
+                let normal = axis.cross(view).cross(axis);
+                if normal.magnitude2() > f32::EPSILON { normal.normalize() } else { view.normalize() }
+            }
+        };
+        let Some(t) = ray.intersect_plane(center, plane_normal) else {
+            return false;
+        };
+
+        self.hovered = Some(axis_index);
+        self.drag = Some(Drag {
+            instance,
+            axis: axis_index,
+            start: *transform,
+            start_point: ray.at(t),
+            plane_normal,
+            size: Self::size(center, eye)
+        });
+
+        true
+    }
+
+    // Where the dragged instance goes for the cursor ray, None when not dragging or the
+    // ray runs parallel to the drag plane.
+    pub fn drag(&self, ray: &Ray) -> Option<(usize, InstanceTransform)>
+    {
+        let drag = self.drag.as_ref()?;
+        let center = Point3::from_vec(drag.start.position);
+        let point = ray.at(ray.intersect_plane(center, drag.plane_normal)?);
+        let axis = self.axes(&drag.start)[drag.axis];
+
+        let mut transform = drag.start;
+        match self.mode {
+            GizmoMode::Translate => {
+                transform.position += axis * (point - drag.start_point).dot(axis);
+            },
+            GizmoMode::Scale => {
+                let factor = 1.0 + (point - drag.start_point).dot(axis) / drag.size;
+                transform.scale[drag.axis] = (drag.start.scale[drag.axis] * factor).max(MIN_SCALE);
+            },
+            GizmoMode::Rotate => {
+                let (from, to) = (drag.start_point - center, point - center);
+                let angle = axis.dot(from.cross(to)).atan2(from.dot(to));
+                transform.rotation = Quaternion::from_axis_angle(axis, Rad(angle)) * drag.start.rotation;
+            }
+        }
+
+        Some((drag.instance, transform))
+    }
+
+    // Finishes a drag with the instance's transform at its end, None when the instance
+    // is gone.
+    pub fn end_drag(&mut self, after: Option<InstanceTransform>) -> Option<TransformEdit>
+    {
+        let drag = self.drag.take()?;
+
+        after.filter(|after| *after != drag.start).map(|after| TransformEdit {
+            instance: drag.instance,
+            before: drag.start,
+            after
+        })
+    }
+
+    pub fn push_lines(&self, lines: &mut DebugLines, transform: &InstanceTransform, eye: Point3<f32>)
+    {
+        let center = Point3::from_vec(transform.position);
+        let size = Self::size(center, eye);
+        let axes = self.axes(transform);
+
+        for (index, axis) in axes.into_iter().enumerate() {
+            let color = if self.hovered == Some(index) { ACTIVE_COLOR } else { AXIS_COLORS[index] };
+            let tip = center + axis * size;
+            match self.mode {
+                GizmoMode::Translate => {
+                    lines.push_line(center, tip, color);
+                    let side = axes[(index + 1) % 3] * size * 0.06;
+                    lines.push_line(tip, tip - axis * size * 0.15 + side, color);
+                    lines.push_line(tip, tip - axis * size * 0.15 - side, color);
+                },
+                GizmoMode::Scale => {
+                    lines.push_line(center, tip, color);
+                    let half = Vector3::new(1.0, 1.0, 1.0) * size * 0.04;
+                    lines.push_aabb(&Aabb {
+                        min: tip - half,
+                        max: tip + half
+                    }, color);
+                },
+                GizmoMode::Rotate => {
+                    lines.push_circle(center, axes[(index + 1) % 3], axes[(index + 2) % 3], size, color);
+                }
+            }
+        }
+    }
+}
+
+// The closest the ray gets to the segment from `from` to `to`.
+fn ray_segment_distance(ray: &Ray, from: Point3<f32>, to: Point3<f32>) -> f32
+{
+    let segment = to - from;
+    let offset = ray.origin - from;
+    let (b, c, e, f) = (ray.direction.dot(segment), ray.direction.dot(offset), segment.magnitude2(), segment.dot(offset));
+    let denom = e - b * b;
+
+    let mut s = if denom.abs() > f32::EPSILON { ((b * f - c * e) / denom).max(0.0) } else { 0.0 };
+    let mut t = (b * s + f) / e;
+    if t < 0.0 {
+        t = 0.0;
+        s = (-c).max(0.0);
+    } else if t > 1.0 {
+        t = 1.0;
+        s = (b - c).max(0.0);
+    }
+
+    (ray.at(s) - (from + segment * t)).magnitude()
+}
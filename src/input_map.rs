@@ -0,0 +1,330 @@
+use std::{collections::{BTreeMap, HashMap}, path::Path};
+
+use gilrs::Button;
+use winit::{event::{ElementState, KeyEvent, MouseButton, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+
+// What the demo does in response to input, independent of the key or button that
+// triggered it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    // Picks the instance under the cursor, with shift held places one there instead.
+    Select,
+    ReloadShaders,
+    ToggleFullscreen,
+    Screenshot,
+    // Records frames with StateOptions::recording, see State::start_recording.
+    ToggleRecording,
+    ToggleWireframe,
+    CycleDebugView,
+    ToggleBounds,
+    // Between moving, rotating and scaling the selected instance.
+    CycleGizmoMode,
+    // Moves the camera to frame the selected instance, or the skinned model.
+    FrameSelected,
+    // Between the camera rig, when there is one, and the interactive controls.
+    ToggleCameraRig,
+    // Through the cameras added to the scene, see State::add_camera.
+    CycleCamera,
+    CycleAntiAliasing,
+    ToggleSsao,
+    CycleFog,
+    ToggleMotionBlur,
+    ToggleColorGrading,
+    ToggleGlow,
+    ToggleDepthOfField,
+    ToggleEdgeDetection,
+    FocusNearer,
+    FocusFarther
+}
+
+impl Action {
+    pub const ALL: [Action; 26] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Select,
+        Action::ReloadShaders,
+        Action::ToggleFullscreen,
+        Action::Screenshot,
+        Action::ToggleRecording,
+        Action::ToggleWireframe,
+        Action::CycleDebugView,
+        Action::ToggleBounds,
+        Action::CycleGizmoMode,
+        Action::FrameSelected,
+        Action::ToggleCameraRig,
+        Action::CycleCamera,
+        Action::CycleAntiAliasing,
+        Action::ToggleSsao,
+        Action::CycleFog,
+        Action::ToggleMotionBlur,
+        Action::ToggleColorGrading,
+        Action::ToggleGlow,
+        Action::ToggleDepthOfField,
+        Action::ToggleEdgeDetection,
+        Action::FocusNearer,
+        Action::FocusFarther
+    ];
+
+    // Held down rather than triggered, so they also see releases and key repeats.
+    pub fn is_continuous(&self) -> bool
+    {
+        matches!(self, Action::MoveForward | Action::MoveBackward | Action::MoveLeft | Action::MoveRight
+            | Action::FocusNearer | Action::FocusFarther)
+    }
+
+    fn default_bindings(&self) -> &'static [Binding]
+    {
+        match self {
+            Action::MoveForward => &[Binding::Key(KeyCode::KeyW), Binding::Gamepad(Button::DPadUp)],
+            Action::MoveBackward => &[Binding::Key(KeyCode::KeyS), Binding::Gamepad(Button::DPadDown)],
+            Action::MoveLeft => &[Binding::Key(KeyCode::KeyA), Binding::Gamepad(Button::DPadLeft)],
+            Action::MoveRight => &[Binding::Key(KeyCode::KeyD), Binding::Gamepad(Button::DPadRight)],
+            Action::Select => &[Binding::Mouse(MouseButton::Left)],
+            Action::ReloadShaders => &[Binding::Key(KeyCode::F5)],
+            Action::ToggleFullscreen => &[Binding::Key(KeyCode::F11), Binding::Gamepad(Button::Start)],
+            Action::Screenshot => &[Binding::Key(KeyCode::F12), Binding::Gamepad(Button::Select)],
+            Action::ToggleRecording => &[Binding::Key(KeyCode::F9)],
+            Action::ToggleWireframe => &[Binding::Key(KeyCode::KeyL)],
+            Action::CycleDebugView => &[Binding::Key(KeyCode::KeyV), Binding::Gamepad(Button::North)],
+            Action::ToggleBounds => &[Binding::Key(KeyCode::KeyB), Binding::Gamepad(Button::West)],
+            Action::CycleGizmoMode => &[Binding::Key(KeyCode::KeyE)],
+            Action::FrameSelected => &[Binding::Key(KeyCode::Period), Binding::Key(KeyCode::NumpadDecimal),
+                Binding::Gamepad(Button::RightThumb)],
+            Action::ToggleCameraRig => &[Binding::Key(KeyCode::KeyP)],
+            Action::CycleCamera => &[Binding::Key(KeyCode::KeyN)],
+            Action::CycleAntiAliasing => &[Binding::Key(KeyCode::KeyT)],
+            Action::ToggleSsao => &[Binding::Key(KeyCode::KeyO)],
+            Action::CycleFog => &[Binding::Key(KeyCode::KeyG)],
+            Action::ToggleMotionBlur => &[Binding::Key(KeyCode::KeyM)],
+            Action::ToggleColorGrading => &[Binding::Key(KeyCode::KeyC)],
+            Action::ToggleGlow => &[Binding::Key(KeyCode::KeyH)],
+            Action::ToggleDepthOfField => &[Binding::Key(KeyCode::KeyF)],
+            Action::ToggleEdgeDetection => &[Binding::Key(KeyCode::KeyJ)],
+            Action::FocusNearer => &[Binding::Key(KeyCode::BracketLeft)],
+            Action::FocusFarther => &[Binding::Key(KeyCode::BracketRight)]
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self>
+    {
+        Self::ALL.into_iter().find(|action| format!("{action:?}") == name)
+    }
+}
+
+// Keys are physical, so the bindings stay in the same place on every keyboard layout.
+// Gamepad buttons are named by where they sit, South being A on an Xbox controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(Button)
+}
+
+// The keys a bindings file can name, by their KeyCode names.
+const NAMED_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF,
+    KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL,
+    KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR,
+    KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX,
+    KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Escape, KeyCode::Tab, KeyCode::Backspace,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight,
+    KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight,
+    KeyCode::Backslash, KeyCode::Semicolon, KeyCode::Quote, KeyCode::Backquote,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    KeyCode::NumpadAdd, KeyCode::NumpadSubtract, KeyCode::NumpadDecimal
+];
+
+// The gamepad buttons a bindings file can name, prefixed with Gamepad.
+const NAMED_BUTTONS: &[Button] = &[
+    Button::South, Button::East, Button::North, Button::West,
+    Button::LeftTrigger, Button::LeftTrigger2, Button::RightTrigger, Button::RightTrigger2,
+    Button::Select, Button::Start, Button::Mode, Button::LeftThumb, Button::RightThumb,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight
+];
+
+impl Binding {
+    // A KeyCode name like KeyW or F5, MouseLeft, MouseRight or MouseMiddle, or a
+    // gamepad button like GamepadSouth or GamepadDPadUp.
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name {
+            "MouseLeft" => Some(Binding::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(Binding::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(Binding::Mouse(MouseButton::Middle)),
+            _ => match name.strip_prefix("Gamepad") {
+                Some(button) => NAMED_BUTTONS.iter()
+                    .find(|named| format!("{named:?}") == button)
+                    .map(|named| Binding::Gamepad(*named)),
+                None => NAMED_KEYS.iter()
+                    .find(|code| format!("{code:?}") == name)
+                    .map(|code| Binding::Key(*code))
+            }
+        }
+    }
+
+    // What from_name takes back.
+    pub fn name(&self) -> String
+    {
+        match self {
+            Binding::Key(code) => format!("{code:?}"),
+            Binding::Mouse(button) => format!("Mouse{button:?}"),
+            Binding::Gamepad(button) => format!("Gamepad{button:?}")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActionEvent {
+    pub action: Action,
+    pub pressed: bool,
+    // A key held down long enough to repeat.
+    pub repeat: bool
+}
+
+// Translates key, mouse button and gamepad button events into actions. A bindings
+// file rebinds them, one action per line followed by the comma separated keys or
+// buttons it's bound to:
+//
+//   MoveForward = KeyW, ArrowUp, GamepadDPadUp
+//   Screenshot = F12
+//   ToggleWireframe =
+//
+// An action in the file loses its default bindings, so an empty list unbinds it.
+// Lines starting with # are comments.
+#[derive(Debug, Clone)]
+pub struct InputMap {
+    bindings: HashMap<Binding, Action>
+}
+
+impl Default for InputMap {
+    fn default() -> Self
+    {
+        let mut input_map = Self {
+            bindings: HashMap::new()
+        };
+        for action in Action::ALL {
+            input_map.bind(action, action.default_bindings());
+        }
+
+        input_map
+    }
+}
+
+impl InputMap {
+    // The default bindings, rebound by the file at `path` if there is one.
+    pub fn new(path: Option<&Path>) -> Self
+    {
+        let mut input_map = Self::default();
+        if let Some(path) = path {
+            input_map.load(path);
+        }
+
+        input_map
+    }
+
+    fn load(&mut self, path: &Path)
+    {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return;
+        };
+
+        for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let Some((action, bindings)) = line.split_once('=') else {
+                log::warn!("Ignoring malformed input binding in {}: {line}", path.display());
+                continue;
+            };
+            let names = bindings.split(',').map(str::trim).filter(|name| !name.is_empty());
+            self.bind_names(action.trim(), names, &path.display().to_string());
+        }
+    }
+
+    // Rebinds every action in `bindings` by name, e.g. from the [bindings] of Settings,
+    // like a bindings file would.
+    pub fn bind_all(&mut self, bindings: &BTreeMap<String, Vec<String>>, source: &str)
+    {
+        for (action, names) in bindings {
+            self.bind_names(action, names.iter().map(String::as_str), source);
+        }
+    }
+
+    fn bind_names<'n>(&mut self, action: &str, names: impl Iterator<Item = &'n str>, source: &str)
+    {
+        let Some(action) = Action::from_name(action) else {
+            log::warn!("Ignoring unknown action {action} in {source}");
+            return;
+        };
+
+        let bindings = names
+            .filter_map(|name| {
+                let binding = Binding::from_name(name);
+                if binding.is_none() {
+                    log::warn!("Ignoring unknown key {name} for {action:?} in {source}");
+                }
+                binding
+            })
+            .collect::<Vec<_>>();
+        self.bind(action, &bindings);
+    }
+
+    // Replaces what `action` is bound to. A binding can only trigger one action, so
+    // any other action it was bound to loses it.
+    pub fn bind(&mut self, action: Action, bindings: &[Binding])
+    {
+        self.bindings.retain(|_, bound| *bound != action);
+        for binding in bindings {
+            self.bindings.insert(*binding, action);
+        }
+    }
+
+    pub fn bindings(&self, action: Action) -> impl Iterator<Item = Binding> + '_
+    {
+        self.bindings.iter()
+            .filter(move |(_, bound)| **bound == action)
+            .map(|(binding, _)| *binding)
+    }
+
+    pub fn action(&self, binding: Binding) -> Option<Action>
+    {
+        self.bindings.get(&binding).copied()
+    }
+
+    // The action a key or mouse button event is bound to, None for every other event.
+    pub fn translate(&self, event: &WindowEvent) -> Option<ActionEvent>
+    {
+        let (binding, state, repeat) = match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(code),
+                    state,
+                    repeat,
+                    ..
+                },
+                ..
+            } => (Binding::Key(*code), *state, *repeat),
+            WindowEvent::MouseInput { state, button, .. } => (Binding::Mouse(*button), *state, false),
+            _ => return None
+        };
+
+        Some(ActionEvent {
+            action: self.action(binding)?,
+            pressed: state == ElementState::Pressed,
+            repeat
+        })
+    }
+}
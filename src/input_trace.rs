@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+use web_time::{Duration, Instant};
+
+const MAX_RECORDS: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct InputRecord {
+    pub timestamp: Duration,
+    pub event: String,
+    pub handler: Option<&'static str>
+}
+
+pub struct InputTracer {
+    enabled: bool,
+    start: Instant,
+    records: VecDeque<InputRecord>
+}
+
+impl InputTracer {
+    pub fn new(enabled: bool) -> Self
+    {
+        Self {
+            enabled,
+            start: Instant::now(),
+            records: VecDeque::new()
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool)
+    {
+        self.enabled = enabled;
+    }
+
+    pub fn record(&mut self, event: &impl std::fmt::Debug, handler: Option<&'static str>)
+    {
+        if !self.enabled {
+            return;
+        }
+
+        let record = InputRecord {
+            timestamp: self.start.elapsed(),
+            event: format!("{event:?}"),
+            handler
+        };
+        log::info!(target: "input_trace", "[{:>10.3}s] {:<18} {}", record.timestamp.as_secs_f64(),
+            record.handler.unwrap_or("unhandled"), record.event);
+
+        if self.records.len() == MAX_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &InputRecord>
+    {
+        self.records.iter()
+    }
+}
@@ -1,56 +1,136 @@
 use bytemuck::{Pod, Zeroable};
-use cgmath::{Matrix4, Quaternion, Vector3};
-use wgpu::VertexBufferLayout;
+use cgmath::{EuclideanSpace, Matrix4, Point3, Quaternion, Vector3};
+use wgpu::{vertex_attr_array, VertexAttribute, VertexStepMode};
+
+use super::{bounds::Bounds, renderer_backend::{assets::MaterialHandle, vertex_layout::VertexLayout}, vertex_animation::AnimationParams};
+
+pub const WHITE: [f32; 4] = [1.0; 4];
 
 pub struct Instance {
     pub position: Vector3<f32>,
-    pub rotation: Quaternion<f32>
+    pub rotation: Quaternion<f32>,
+    // Along the instance's own axes, before it's rotated.
+    pub scale: Vector3<f32>,
+    pub animation: AnimationParams,
+    // Multiplied with the diffuse texture, alpha included.
+    pub color: [f32; 4],
+    // Which of the bound material textures the instance samples.
+    pub texture_index: u32,
+    // Drawn with the material's textures and shader permutation instead of the
+    // material texture array.
+    pub material: Option<MaterialHandle>,
+    // Replaces the emissive intensity of the material, None keeps the material's.
+    pub emissive_intensity: Option<f32>
 }
 
 impl Instance {
+    pub fn model_matrix(&self) -> Matrix4<f32>
+    {
+        Matrix4::from_translation(self.position) * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    // With the translation relative to `origin`, subtracted in f64 so instances far from
+    // the world's origin keep their place to the camera exactly.
+    pub fn model_matrix_relative(&self, origin: Point3<f64>) -> Matrix4<f32>
+    {
+        let translation = (self.position.cast::<f64>().unwrap() - origin.to_vec()).cast().unwrap();
+
+        Matrix4::from_translation(translation) * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    pub fn world_bounds(&self, mesh_bounds: &Bounds) -> Bounds
+    {
+        mesh_bounds.transform(&self.model_matrix())
+    }
+
     pub fn to_raw(&self) -> InstanceRaw
+    {
+        self.to_raw_relative(Point3::origin())
+    }
+
+    // For shaders drawing with CameraUniform's relative matrices.
+    pub fn to_raw_relative(&self, origin: Point3<f64>) -> InstanceRaw
     {
         InstanceRaw {
-            model: (Matrix4::from_translation(self.position) * Matrix4::from(self.rotation)).into()
+            model: self.model_matrix_relative(origin).into(),
+            animation: self.animation.to_raw(),
+            color: self.color,
+            material: [self.texture_index, 0, 0, 0],
+            emissive: [0.0; 4]
         }
     }
 }
 
+// A soft tint per index, the hues spread by the golden angle so neighbours differ.
+pub fn palette_color(index: u32) -> [f32; 4]
+{
+    let hue = (index as f32 * 0.618_034).fract() * 6.0;
+    let channel = |offset: f32| {
+        let distance = ((hue + offset) % 6.0 - 3.0).abs();
+        1.0 - 0.4 * (2.0 - distance).clamp(0.0, 1.0)
+    };
+
+    [channel(0.0), channel(4.0), channel(2.0), 1.0]
+}
+
+// Matches InstanceData in instancing.wgsl, which reads it from a storage or uniform
+// buffer by instance index.
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct InstanceRaw {
-    model: [[f32; 4]; 4]
+    model: [[f32; 4]; 4],
+    animation: [f32; 4],
+    color: [f32; 4],
+    // x is the texture index, the rest is padding.
+    material: [u32; 4],
+    // rgb is what the material emits, its intensity included. Only instances drawn
+    // with a material emit anything. w is the LOD fade, see with_lod_fade.
+    emissive: [f32; 4]
 }
 
 impl InstanceRaw {
-    pub fn get_vertex_buffer_layout() -> VertexBufferLayout<'static>
-    {
-        use std::mem;
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 5,
-                    format: wgpu::VertexFormat::Float32x4
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float32x4
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float32x4
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4
-                }
-            ]
+    pub fn tinted(mut self, color: [f32; 4]) -> Self
+    {
+        for (channel, tint) in self.color.iter_mut().zip(color) {
+            *channel *= tint;
         }
+
+        self
     }
+
+    pub fn with_emissive(mut self, emissive: [f32; 3]) -> Self
+    {
+        self.emissive = [emissive[0], emissive[1], emissive[2], self.emissive[3]];
+
+        self
+    }
+
+    // While two LOD levels cross-fade, the outgoing one drops `fade` of its pixels in a
+    // dither pattern and the incoming one, given -`fade`, draws exactly those. 0 draws
+    // every pixel.
+    pub fn with_lod_fade(mut self, fade: f32) -> Self
+    {
+        self.emissive[3] = fade.clamp(-1.0, 1.0);
+
+        self
+    }
+}
+
+// The skinned mesh still takes its instance as a vertex buffer. Locations 5 to 8 are
+// the model matrix columns, 9 the animation parameters, 10 the color, 11 the material
+// and 12 the emission.
+impl VertexLayout for InstanceRaw {
+    const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x4,
+        9 => Float32x4,
+        10 => Float32x4,
+        11 => Uint32x4,
+        12 => Float32x4
+    ];
+    const STEP_MODE: VertexStepMode = VertexStepMode::Instance;
 }
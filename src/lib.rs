@@ -2,10 +2,9 @@ use std::{
     thread::{sleep, spawn},
     time::Duration
 };
-use state::State;
 use wgpu::SurfaceError;
 use winit::{
-    event::{Event, WindowEvent}, event_loop::EventLoopBuilder, window::WindowBuilder
+    event::{Event, WindowEvent}, event_loop::EventLoopBuilder
 };
 
 #[cfg(target_arch = "wasm32")]
@@ -13,85 +12,195 @@ use wasm_bindgen::prelude::*;
 
 use custom_event::CustomEvent;
 
+pub use error::RendererError;
+pub use logging::LogConfig;
+pub use settings::{CameraSettings, RenderSettings, Settings, WindowSettings};
+pub use window_config::WindowConfig;
+pub use state::{options::{StateOptions, SurfaceOptions}, renderer_backend, Aabb, Action, AntiAliasing, AssetStats, Billboard, BillboardMode, Binding, BoundingSphere, Bounds, CameraBookmark, CameraKeyframe, CameraKind, CameraRig, ColorGradingOptions, DebugView, Decal, DepthOfFieldOptions, DrawQueueStats, EdgeDetectionOptions, FogOptions, FollowCamera, GizmoMode, GlowOptions, GpuAllocatorStats, GpuCapabilities, GpuTiming, ImportSettings, InputMap, InputRecord, InstanceRaw, InstanceTransform, MotionBlurOptions, OutlineOptions, PipelineCacheStats, PlacementOptions, PostEffect, ProceduralPattern, RecordingOptions, RecordingOutput, RenderPassConfig, ResidencyStats, RigMotion, ScopeStats, SkyOptions, SsaoOptions, State, StreamingStats, SubmitStats, SystemTiming, TerrainOptions, Tick, TransformEdit, TransientPoolStats, Upscaling, VegetationOptions, ViewportRect, WaterOptions};
+
 mod custom_event;
+mod error;
+mod logging;
+mod settings;
+mod window_config;
 mod state;
+#[cfg(target_arch = "wasm32")]
+mod web_api;
+#[cfg(target_arch = "wasm32")]
+mod web_worker;
 
-#[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
-pub async fn run()
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(start)]
+pub async fn start() -> Result<(), JsValue>
 {
-    cfg_if::cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-            console_log::init_with_level(log::Level::Warn).expect("Couldn't initialize logger");
-        } else {
-            env_logger::init();
-        }
+    // A render worker loads the module too, and starts with start_worker instead.
+    if web_sys::window().is_none() {
+        return Ok(());
     }
+    run().await.map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+pub async fn run() -> Result<(), RendererError>
+{
+    let mut options = StateOptions::from_env();
+    let _log_guard = options.log.init();
+    options.apply_settings();
+
+    run_with_options(options).await
+}
+
+// Logging has to be set up by the caller first, with options.log.init(), and stays set
+// up for as long as its guard lives.
+pub async fn run_with_options(options: StateOptions) -> Result<(), RendererError>
+{
+    #[cfg(target_arch = "wasm32")]
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
 
     let event_loop = EventLoopBuilder::<CustomEvent>::with_user_event()
-        .build()
-        .unwrap();
+        .build()?;
+    let event_loop_proxy = event_loop.create_proxy();
 
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
             use winit::platform::web::WindowBuilderExtWebSys;
             use winit::platform::web::WindowExtWebSys;
 
-            let window = WindowBuilder::new()
-                .with_canvas(None)
-                .build(&event_loop)
-                .unwrap();
-
-            web_sys::window()
+            let document = web_sys::window()
                 .and_then(|win| win.document())
-                .and_then(|doc| {
-                    let body = doc.body()?;
-                    let canvas = web_sys::Element::from(window.canvas().unwrap());
-                    body.append_child(&canvas).ok()?;
-                    Some(())
-                }).expect("Couldn't append canvas to document body.");
+                .expect("Couldn't get the document.");
+            let page_canvas = options.window.canvas_id.as_deref()
+                .and_then(|id| document.get_element_by_id(id))
+                .and_then(|element| element.dyn_into::<web_sys::HtmlCanvasElement>().ok());
+            let appended = page_canvas.is_none();
+
+            match (&options.window.worker, &page_canvas) {
+                (Some(worker), Some(canvas)) => return web_worker::spawn(worker, canvas),
+                (Some(_), None) => log::warn!("Rendering on the page, a render worker needs the page's canvas"),
+                _ => {}
+            }
+
+            // winit follows the canvas' size and devicePixelRatio with a ResizeObserver,
+            // which ends up in State::resize like any other window resize.
+            let window = options.window.builder()
+                .with_canvas(page_canvas)
+                .build(&event_loop)?;
+
+            web_api::set_event_loop_proxy(event_loop.create_proxy());
+
+            let canvas = window.canvas().expect("The window has a canvas.");
+            // Touches go to State::input instead of scrolling or zooming the page.
+            let mut style = vec![("touch-action", "none")];
+            if appended {
+                style.extend([("display", "block"), ("width", "100%"), ("height", "100%")]);
+            }
+            for (property, value) in style {
+                canvas.style().set_property(property, value).expect("Couldn't style the canvas.");
+            }
+            if appended {
+                document.body()
+                    .and_then(|body| body.append_child(&canvas).ok())
+                    .expect("Couldn't append canvas to document body.");
+            }
         } else {
-            let window = WindowBuilder::new()
-                .build(&event_loop)
-                .unwrap();
+            let window = options.window.builder()
+                .build(&event_loop)?;
     
-            let event_loop_proxy = event_loop.create_proxy();
+            let timer_proxy = event_loop.create_proxy();
     
             spawn(move || loop {
                 sleep(Duration::from_millis(18));
-                event_loop_proxy.send_event(CustomEvent::Timer).ok();
+                timer_proxy.send_event(CustomEvent::Timer).ok();
             });
         }
     }
 
-    let mut state = State::new(&window).await;
+    let window = &window;
+    let mut state = State::new(window, options).await?;
 
     event_loop.run(move |event, elwt| match event {
-        Event::UserEvent(..) => {
-            state.window.request_redraw();
+        Event::UserEvent(CustomEvent::AnimationFinished(clip)) => {
+            log::info!("Animation {clip} finished");
+        },
+        #[cfg(target_arch = "wasm32")]
+        Event::UserEvent(CustomEvent::Web(command)) => {
+            command.apply(&mut state);
+            if !state.is_paused() {
+                window.request_redraw();
+            }
+        },
+        Event::UserEvent(..) if !state.is_paused() => {
+            window.request_redraw();
         },
+        Event::DeviceEvent { ref event, .. } => state.trace_device_event(event),
+        Event::LoopExiting => state.shutdown(),
         Event::WindowEvent {
             window_id, ref event
-        } if window_id == state.window.id() => {
-            if !state.input(event) {
-                match event {
-                    WindowEvent::CloseRequested => {
+        } if window_id == window.id() && !state.input(event) => {
+            match event {
+                WindowEvent::CloseRequested => {
+                    state.shutdown();
+                    elwt.exit();
+                },
+                // Nothing was redrawn while paused, so the restored window gets a frame right away.
+                WindowEvent::Resized(physical_size) => {
+                    state.resize(*physical_size);
+                    if !state.is_paused() {
+                        window.request_redraw();
+                    }
+                },
+                WindowEvent::ScaleFactorChanged { scale_factor, .. } => state.set_scale_factor(*scale_factor),
+                WindowEvent::Occluded(occluded) => {
+                    state.set_occluded(*occluded);
+                    if !state.is_paused() {
+                        window.request_redraw();
+                    }
+                },
+                WindowEvent::RedrawRequested => {
+                    if state.is_device_lost() && !recover_device(&mut state) {
                         elwt.exit();
-                    },
-                    WindowEvent::Resized(physical_size) => state.resize(*physical_size),
-                    WindowEvent::RedrawRequested => {
-                        state.update();
-                        match state.render() {
-                            Ok(_) => {},
-                            Err(SurfaceError::Lost) => state.resize(state.size),
-                            Err(SurfaceError::OutOfMemory) => elwt.exit(),
-                            Err(e) => eprintln!("{e:?}")
-                        }
-                    },
-                    _ => {}
-                }
+                        return;
+                    }
+
+                    state.update();
+                    for custom_event in state.take_custom_events() {
+                        event_loop_proxy.send_event(custom_event).ok();
+                    }
+                    match state.render() {
+                        Ok(_) => {},
+                        Err(SurfaceError::Lost | SurfaceError::Outdated) => state.resize(state.size),
+                        Err(SurfaceError::OutOfMemory) => {
+                            state.write_crash_report("surface out of memory");
+                            if !recover_device(&mut state) {
+                                elwt.exit();
+                            }
+                        },
+                        Err(e) => eprintln!("{e:?}")
+                    }
+                },
+                _ => {}
             }
         },
         _ => {}
-    }).expect("Error!");
+    })?;
+
+    Ok(())
+}
+
+fn recover_device(state: &mut State) -> bool
+{
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            let _ = state;
+            log::error!("GPU device lost, recovery isn't supported on the web");
+            false
+        } else {
+            match pollster::block_on(state.recover_device()) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::error!("Couldn't recover the GPU device: {e:?}");
+                    false
+                }
+            }
+        }
+    }
 }
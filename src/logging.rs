@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+
+use tracing::{level_filters::LevelFilter, Metadata};
+use tracing_subscriber::{filter::{filter_fn, Directive}, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+// What gets logged, and where the renderer's spans (init, resize, render) are traced to.
+// `log` records from dependencies are forwarded and filtered the same way.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    // For every module without a level of its own.
+    pub level: LevelFilter,
+    // Per module levels, e.g. ("wgpu_core", LevelFilter::ERROR). The longest matching
+    // module path wins.
+    pub modules: Vec<(String, LevelFilter)>,
+    // Writes every span to this file as a trace chrome://tracing and Perfetto open. The
+    // web has nowhere to write it, so there any path measures the spans into the
+    // browser profiler's timings instead.
+    pub chrome_trace: Option<PathBuf>
+}
+
+impl Default for LogConfig {
+    fn default() -> Self
+    {
+        Self {
+            level: LevelFilter::WARN,
+            modules: Vec::new(),
+            chrome_trace: None
+        }
+    }
+}
+
+// Finishes writing the trace when dropped, so it has to outlive the event loop.
+pub struct LogGuard {
+    #[cfg(not(target_arch = "wasm32"))]
+    _chrome_trace: Option<tracing_chrome::FlushGuard>
+}
+
+impl LogConfig {
+    // RUST_LOG takes a comma separated list of levels like env_logger did, either bare
+    // for the default or as module=level, e.g. RUST_LOG=info,wgpu_core=warn. Directives
+    // without a valid level are skipped.
+    // LEARN_WGPU_CHROME_TRACE names the file to write a trace to.
+    pub fn from_env() -> Self
+    {
+        let mut config = Self {
+            chrome_trace: std::env::var_os("LEARN_WGPU_CHROME_TRACE").map(PathBuf::from),
+            ..Self::default()
+        };
+        if let Ok(directives) = std::env::var("RUST_LOG") {
+            config.parse_directives(&directives);
+        }
+
+        config
+    }
+
+    fn parse_directives(&mut self, directives: &str)
+    {
+        for directive in directives.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            let parsed = match directive.split_once('=') {
+                Some((module, level)) => level.parse()
+                    .map(|level| self.modules.push((String::from(module), level))),
+                None => directive.parse().map(|level| self.level = level)
+            };
+            if parsed.is_err() {
+                eprintln!("Ignoring the log directive {directive}, it has no valid level");
+            }
+        }
+    }
+
+    // Only the first call in a process installs anything, later ones keep what's there.
+    pub fn init(&self) -> LogGuard
+    {
+        let traced = self.chrome_trace.is_some();
+        let spans = filter_fn(move |metadata: &Metadata| traced && metadata.is_span());
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                use tracing_subscriber::filter::FilterExt;
+
+                let console = tracing_wasm::WASMLayer::new(tracing_wasm::WASMLayerConfigBuilder::new()
+                    .set_report_logs_in_timings(false)
+                    .build());
+                let installed = tracing_subscriber::registry()
+                    .with(console.with_filter(self.env_filter().or(spans)))
+                    .try_init();
+                let guard = LogGuard {};
+            } else {
+                let chrome_trace = self.chrome_trace.as_ref().and_then(|path| {
+                    std::fs::File::create(path)
+                        .map_err(|e| eprintln!("Couldn't create the trace {}: {e}", path.display()))
+                        .ok()
+                });
+                let (chrome_layer, chrome_guard) = match chrome_trace {
+                    Some(file) => {
+                        let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+                            .writer(file)
+                            .include_args(true)
+                            .build();
+                        (Some(layer.with_filter(spans)), Some(guard))
+                    },
+                    None => (None, None)
+                };
+                let installed = tracing_subscriber::registry()
+                    .with(tracing_subscriber::fmt::layer().with_filter(self.env_filter()))
+                    .with(chrome_layer)
+                    .try_init();
+                let guard = LogGuard {
+                    _chrome_trace: chrome_guard
+                };
+            }
+        }
+
+        if let Err(e) = installed {
+            log::warn!("Logging was already set up: {e}");
+        }
+
+        guard
+    }
+
+    fn env_filter(&self) -> EnvFilter
+    {
+        let mut filter = EnvFilter::default().add_directive(self.level.into());
+        for (module, level) in &self.modules {
+            match format!("{module}={level}").parse::<Directive>() {
+                Ok(directive) => filter = filter.add_directive(directive),
+                Err(e) => log::warn!("Couldn't filter the logs of {module}: {e}")
+            }
+        }
+
+        filter
+    }
+}
@@ -1,7 +1,32 @@
-use learn_wgpu::run;
-use pollster::block_on;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
 
+#[cfg(not(target_arch = "wasm32"))]
+fn main()
+{
+    use clap::Parser;
+    use learn_wgpu::{run_with_options, StateOptions};
+    use pollster::block_on;
+
+    let cli = cli::Cli::parse();
+    let mut options = StateOptions::from_env();
+    let _log_guard = options.log.init();
+    options.apply_settings();
+    cli.apply(&mut options);
+
+    let result = if cli.headless {
+        cli.render_headless(options)
+    } else {
+        block_on(run_with_options(options)).map_err(anyhow::Error::from)
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+// The web starts from learn_wgpu::start instead.
+#[cfg(target_arch = "wasm32")]
 fn main()
 {
-    block_on(run());
 }
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use bytemuck::Pod;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportSettings {
+    // Merges vertices whose attributes (and morph target offsets) are bit-identical,
+    // which glTF exporters often duplicate per primitive or face.
+    pub weld_vertices: bool,
+    // Reorders triangles so recently transformed vertices get reused (native only).
+    pub optimize_vertex_cache: bool,
+    // Reorders vertices into the order the triangles first use them, dropping unused ones.
+    pub optimize_vertex_fetch: bool,
+    // Packs the materials' occlusion, roughness and metallic maps into one texture each,
+    // written to a `packed` directory next to the model. Only for models loaded from a
+    // file, see ChannelPacker.
+    pub pack_channels: bool
+}
+
+impl Default for ImportSettings {
+    fn default() -> Self
+    {
+        Self {
+            weld_vertices: true,
+            optimize_vertex_cache: true,
+            optimize_vertex_fetch: true,
+            pack_channels: true
+        }
+    }
+}
+
+impl ImportSettings {
+    // `morph_targets` hold one offset per vertex each and are kept in step with `vertices`.
+    pub fn optimize<V: Pod>(
+        &self,
+        vertices: &mut Vec<V>,
+        indices: &mut [u32],
+        morph_targets: &mut [Vec<[f32; 3]>]
+    )
+    {
+        if self.weld_vertices {
+            let remap = weld_remap(vertices, morph_targets);
+            apply_remap(&remap, vertices, indices, morph_targets);
+        }
+
+        if self.optimize_vertex_cache {
+            optimize_vertex_cache(indices, vertices.len());
+        }
+
+        // Runs last so the vertex order follows the final triangle order.
+        if self.optimize_vertex_fetch {
+            let remap = first_use_remap(indices, vertices.len());
+            apply_remap(&remap, vertices, indices, morph_targets);
+        }
+    }
+}
+
+// New index of every vertex, u32::MAX for the ones that are dropped.
+fn weld_remap<V: Pod>(vertices: &[V], morph_targets: &[Vec<[f32; 3]>]) -> Vec<u32>
+{
+    let mut unique = HashMap::new();
+
+    vertices.iter()
+        .enumerate()
+        .map(|(index, vertex)| {
+            let mut key = bytemuck::bytes_of(vertex).to_vec();
+            for target in morph_targets {
+                key.extend_from_slice(bytemuck::bytes_of(&target[index]));
+            }
+
+            let next = unique.len() as u32;
+            *unique.entry(key).or_insert(next)
+        })
+        .collect()
+}
+
+fn first_use_remap(indices: &[u32], vertex_count: usize) -> Vec<u32>
+{
+    let mut remap = vec![u32::MAX; vertex_count];
+    let mut next = 0;
+
+    for &index in indices {
+        if remap[index as usize] == u32::MAX {
+            remap[index as usize] = next;
+            next += 1;
+        }
+    }
+
+    remap
+}
+
+fn apply_remap<V: Pod>(
+    remap: &[u32],
+    vertices: &mut Vec<V>,
+    indices: &mut [u32],
+    morph_targets: &mut [Vec<[f32; 3]>]
+)
+{
+    let vertex_count = remap.iter()
+        .filter(|&&index| index != u32::MAX)
+        .map(|&index| index as usize + 1)
+        .max()
+        .unwrap_or(0);
+
+    *vertices = remap_values(remap, vertices, vertex_count);
+    for target in morph_targets.iter_mut() {
+        *target = remap_values(remap, target, vertex_count);
+    }
+    for index in indices.iter_mut() {
+        *index = remap[*index as usize];
+    }
+}
+
+fn remap_values<T: Pod>(remap: &[u32], values: &[T], count: usize) -> Vec<T>
+{
+    let mut remapped = vec![T::zeroed(); count];
+    for (value, &index) in values.iter().zip(remap) {
+        if index != u32::MAX {
+            remapped[index as usize] = *value;
+        }
+    }
+
+    remapped
+}
+
+fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize)
+{
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            meshopt::optimize_vertex_cache_in_place(indices, vertex_count);
+        } else {
+            let _ = (indices, vertex_count);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::ops::Range;
+
+// A range of MeshLods::indices and how far, in object space units, its surface is
+// from the full detail mesh at most.
+#[derive(Debug, Clone)]
+pub struct LodLevel {
+    pub indices: Range<u32>,
+    pub error: f32
+}
+
+// Every level of a mesh in one index list, all referencing the original vertices, so
+// a single vertex and index buffer serve every level.
+#[derive(Debug, Clone)]
+pub struct MeshLods {
+    pub indices: Vec<u16>,
+    pub levels: Vec<LodLevel>
+}
+
+impl MeshLods {
+    // Level 0 is the mesh as given. Every next level aims for half the triangles of
+    // the one before, until simplifying stops removing any.
+    pub fn generate(positions: &[[f32; 3]], indices: &[u16], max_levels: usize) -> Self
+    {
+        let mut lods = Self {
+            indices: indices.to_vec(),
+            levels: vec![LodLevel {
+                indices: 0..indices.len() as u32,
+                error: 0.0
+            }]
+        };
+
+        // Each level is simplified from the full mesh so its error is measured against it.
+        let full_detail = indices.iter().map(|&index| index as u32).collect::<Vec<_>>();
+        let mut previous = full_detail.clone();
+        while lods.levels.len() < max_levels {
+            let target_count = (previous.len() / 6 * 3).max(3);
+            let Some((simplified, error)) = simplify(positions, &full_detail, target_count) else {
+                break;
+            };
+            if simplified.is_empty() || simplified.len() >= previous.len() {
+                break;
+            }
+
+            let start = lods.indices.len() as u32;
+            lods.indices.extend(simplified.iter().map(|&index| index as u16));
+            lods.levels.push(LodLevel {
+                indices: start..lods.indices.len() as u32,
+                error
+            });
+            previous = simplified;
+        }
+
+        lods
+    }
+
+    pub fn level(&self, level: usize) -> &LodLevel
+    {
+        &self.levels[level.min(self.levels.len() - 1)]
+    }
+
+    // The coarsest level whose error, projected at `distance`, stays under
+    // `max_pixels`. `pixels_per_unit` is how many pixels one unit covers at distance 1.
+    pub fn select(&self, distance: f32, pixels_per_unit: f32, max_pixels: f32) -> usize
+    {
+        let pixels_per_unit = pixels_per_unit / distance.max(f32::EPSILON);
+
+        self.levels.iter()
+            .rposition(|level| level.error * pixels_per_unit <= max_pixels)
+            .unwrap_or(0)
+    }
+
+    // Like select, but over the last `fade_band` (a fraction of the distance) before the
+    // next coarser level takes over, also that level and how far the fade to it has
+    // come, from 0 to 1.
+    pub fn select_blend(
+        &self,
+        distance: f32,
+        pixels_per_unit: f32,
+        max_pixels: f32,
+        fade_band: f32
+    ) -> (usize, Option<(usize, f32)>)
+    {
+        let level = self.select(distance, pixels_per_unit, max_pixels);
+        let next = level + 1;
+        if fade_band <= 0.0 || max_pixels <= 0.0 || next >= self.levels.len() {
+            return (level, None);
+        }
+
+        // Where the next level's projected error drops under `max_pixels`.
+        let switch_distance = self.levels[next].error * pixels_per_unit / max_pixels;
+        let fade_start = switch_distance * (1.0 - fade_band.min(1.0));
+        if switch_distance <= 0.0 || distance <= fade_start || distance >= switch_distance {
+            return (level, None);
+        }
+
+        (level, Some((next, (distance - fade_start) / (switch_distance - fade_start))))
+    }
+}
+
+// meshopt is native C++ and isn't built for the web, where meshes only get their
+// full detail level.
+fn simplify(positions: &[[f32; 3]], indices: &[u32], target_count: usize) -> Option<(Vec<u32>, f32)>
+{
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            let mut error = 0.0;
+            let simplified = meshopt::simplify_decoder(indices, positions, target_count, f32::MAX,
+                meshopt::SimplifyOptions::ErrorAbsolute, Some(&mut error));
+
+            Some((simplified, error))
+        } else {
+            let _ = (positions, indices, target_count);
+            None
+        }
+    }
+}
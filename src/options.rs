@@ -0,0 +1,302 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use wgpu::{util::{backend_bits_from_env, power_preference_from_env}, Backends, PowerPreference, PresentMode, TextureFormat};
+
+use crate::{logging::LogConfig, settings::Settings, window_config::WindowConfig};
+
+use super::{mesh_import::ImportSettings, renderer_backend::{anti_aliasing::AntiAliasing, color_grading::ColorGradingOptions, depth_of_field::DepthOfFieldOptions, edge_detection::EdgeDetectionOptions, outline::OutlineOptions, fog::FogOptions, glow::GlowOptions, motion_blur::MotionBlurOptions, render_scale::Upscaling, ssao::SsaoOptions}, recorder::{RecordingOptions, RecordingOutput}};
+
+#[derive(Debug, Clone)]
+pub struct StateOptions {
+    pub backends: Backends,
+    pub adapter_name: Option<String>,
+    pub adapter_index: Option<usize>,
+    pub power_preference: PowerPreference,
+    // Only takes a software adapter (e.g. llvmpipe or WARP), for machines without a GPU
+    // or its drivers. One is tried anyway when no other adapter is found.
+    pub fallback_adapter: bool,
+    pub surface: SurfaceOptions,
+    pub trace_input: bool,
+    // Set up with LogConfig::init before anything else, by run or main.
+    pub log: LogConfig,
+    // Only used by run_with_options, which opens the window.
+    pub window: WindowConfig,
+    // A settings file apply_settings applies to these options, and State applies again
+    // whenever it changes. On the web it names a localStorage entry instead.
+    pub settings: Option<PathBuf>,
+    // Units the camera moves per update while a movement key is held.
+    pub camera_speed: f32,
+    pub debug_markers: bool,
+    // Where camera bookmarks are persisted, None keeps them in memory only.
+    pub camera_bookmarks: Option<PathBuf>,
+    // Rebinds keys and mouse buttons to actions, see InputMap. A missing file keeps the
+    // default bindings.
+    pub input_bindings: Option<PathBuf>,
+    // Rebinds actions by name on top of input_bindings, see Settings.
+    pub bindings: BTreeMap<String, Vec<String>>,
+    // Polls gamepads for input when running in a window.
+    pub gamepad: bool,
+    // Shaders found here replace the embedded ones, so edits show up on reload (F5).
+    pub shader_dir: Option<PathBuf>,
+    // A glTF or OBJ model to load at startup, animated when it has a skin or morph
+    // targets.
+    pub skinned_model: Option<PathBuf>,
+    // Applied to every model as it's loaded.
+    pub import_settings: ImportSettings,
+    // Generates a noise terrain with the default TerrainOptions at startup.
+    pub terrain: bool,
+    // Scatters grass with the default VegetationOptions over the terrain at startup.
+    pub vegetation: bool,
+    // Adds a reflective water plane with the default WaterOptions at startup.
+    pub water: bool,
+    // Depth from 1 at the near plane to 0 infinitely far away, for far better precision
+    // in large scenes. The camera's far plane is ignored then.
+    pub reverse_z: bool,
+    // Draws the instances relative to the eye, subtracted in f64 on the CPU, so they
+    // don't jitter far from the world's origin. The terrain, water, billboards and
+    // skinned mesh are still drawn in world space.
+    pub camera_relative: bool,
+    // Frustum culls the instances in a compute pass where the device can, see
+    // State::set_gpu_culling.
+    pub gpu_culling: bool,
+    // Replaces the instance grid with this many instances and logs the frame timings
+    // every few seconds, for measuring how the renderer scales.
+    pub stress_instances: Option<u32>,
+    // FXAA on the web, none elsewhere.
+    pub anti_aliasing: AntiAliasing,
+    // The scene renders at this times the surface's size, see State::set_render_scale.
+    pub render_scale: f32,
+    // How a scene at another render scale is brought to the surface's size.
+    pub upscaling: Upscaling,
+    // Screen-space ambient occlusion with these options, off when None.
+    pub ssao: Option<SsaoOptions>,
+    // Fog, and with its sky options a daylight sky, off when None.
+    pub fog: Option<FogOptions>,
+    // Depth of field with these options, off when None.
+    pub depth_of_field: Option<DepthOfFieldOptions>,
+    // Motion blur with these options, off when None.
+    pub motion_blur: Option<MotionBlurOptions>,
+    // Glow around bright and emissive surfaces with these options, off when None.
+    pub glow: Option<GlowOptions>,
+    // Color grading with these options, off when None.
+    pub color_grading: Option<ColorGradingOptions>,
+    // A .cube LUT for color grading to load at startup, turning it on if it's off.
+    pub color_grading_lut: Option<PathBuf>,
+    // Sobel edge detection on the frame with these options, off when None.
+    pub edge_detection: Option<EdgeDetectionOptions>,
+    // Outlines the instance picked with the mouse with these options, off when None.
+    pub selection_outline: Option<OutlineOptions>,
+    // Where and how many frames the recording hotkey records, see State::start_recording.
+    pub recording: RecordingOptions
+}
+
+impl Default for StateOptions {
+    fn default() -> Self
+    {
+        Self {
+            backends: Backends::all(),
+            adapter_name: None,
+            adapter_index: None,
+            power_preference: PowerPreference::HighPerformance,
+            fallback_adapter: false,
+            surface: SurfaceOptions::default(),
+            trace_input: false,
+            log: LogConfig::default(),
+            window: WindowConfig::default(),
+            settings: Some(PathBuf::from(if cfg!(target_arch = "wasm32") { "learn_wgpu_settings" } else { "settings.toml" })),
+            camera_speed: 0.2,
+            debug_markers: cfg!(debug_assertions),
+            camera_bookmarks: (!cfg!(target_arch = "wasm32"))
+                .then(|| PathBuf::from("camera_bookmarks.txt")),
+            input_bindings: (!cfg!(target_arch = "wasm32"))
+                .then(|| PathBuf::from("input_bindings.txt")),
+            bindings: BTreeMap::new(),
+            gamepad: true,
+            shader_dir: (cfg!(debug_assertions) && !cfg!(target_arch = "wasm32"))
+                .then(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders"))),
+            skinned_model: None,
+            import_settings: ImportSettings::default(),
+            terrain: false,
+            vegetation: false,
+            water: false,
+            reverse_z: false,
+            camera_relative: false,
+            gpu_culling: true,
+            stress_instances: None,
+            anti_aliasing: AntiAliasing::default(),
+            render_scale: 1.0,
+            upscaling: Upscaling::default(),
+            ssao: None,
+            fog: None,
+            depth_of_field: None,
+            motion_blur: None,
+            glow: None,
+            color_grading: None,
+            color_grading_lut: None,
+            edge_detection: None,
+            selection_outline: Some(OutlineOptions::default()),
+            recording: RecordingOptions::default()
+        }
+    }
+}
+
+impl StateOptions {
+    // WGPU_BACKEND, WGPU_POWER_PREF and WGPU_ADAPTER_NAME follow wgpu's own conventions.
+    // LEARN_WGPU_FALLBACK_ADAPTER=1 renders with a software adapter, e.g. on CI.
+    // RUST_LOG and LEARN_WGPU_CHROME_TRACE configure logging, see LogConfig::from_env.
+    // LEARN_WGPU_FULLSCREEN=1 opens the window in fullscreen.
+    // LEARN_WGPU_SETTINGS points at the settings file.
+    // LEARN_WGPU_TRACE_INPUT=1 logs every input event under the `input_trace` target.
+    // LEARN_WGPU_INPUT_BINDINGS points at a file rebinding the keys.
+    // LEARN_WGPU_GAMEPAD=0 ignores gamepads.
+    // LEARN_WGPU_SHADER_DIR overrides where shaders are hot reloaded from.
+    // LEARN_WGPU_SKINNED_MODEL points at a glTF or OBJ file to load at startup.
+    // LEARN_WGPU_TERRAIN=1 generates a terrain at startup, LEARN_WGPU_VEGETATION=1 grows
+    // grass on it and LEARN_WGPU_WATER=1 adds water.
+    // LEARN_WGPU_REVERSE_Z=1 uses reverse Z with an infinite far plane.
+    // LEARN_WGPU_CAMERA_RELATIVE=1 draws the instances relative to the camera.
+    // LEARN_WGPU_GPU_CULLING=0 draws every instance without culling them first.
+    // LEARN_WGPU_STRESS=N runs the stress mode with N instances, its reports are logged at
+    // info level.
+    // LEARN_WGPU_EDGE_DETECTION=1 outlines the frame with Sobel edge detection.
+    // LEARN_WGPU_RENDER_SCALE=0.5 renders the scene at half the surface's size.
+    // LEARN_WGPU_SELECTION_OUTLINE=0 leaves the selected instance without an outline.
+    // LEARN_WGPU_RECORDING=PATH records to PATH, a directory of PNGs or, with an
+    // extension, a video encoded by ffmpeg at 30 fps.
+    pub fn from_env() -> Self
+    {
+        let defaults = Self::default();
+
+        Self {
+            backends: backend_bits_from_env().unwrap_or(defaults.backends),
+            adapter_name: std::env::var("WGPU_ADAPTER_NAME").ok(),
+            adapter_index: std::env::var("WGPU_ADAPTER_INDEX").ok()
+                .and_then(|index| index.parse().ok()),
+            power_preference: power_preference_from_env().unwrap_or(defaults.power_preference),
+            fallback_adapter: std::env::var("LEARN_WGPU_FALLBACK_ADAPTER").is_ok_and(|value| value == "1"),
+            trace_input: std::env::var("LEARN_WGPU_TRACE_INPUT").is_ok_and(|value| value == "1"),
+            log: LogConfig::from_env(),
+            input_bindings: std::env::var_os("LEARN_WGPU_INPUT_BINDINGS").map(PathBuf::from)
+                .or(defaults.input_bindings.clone()),
+            gamepad: std::env::var("LEARN_WGPU_GAMEPAD").map_or(defaults.gamepad, |value| value != "0"),
+            window: WindowConfig::from_env(),
+            settings: std::env::var_os("LEARN_WGPU_SETTINGS").map(PathBuf::from)
+                .or(defaults.settings.clone()),
+            shader_dir: std::env::var_os("LEARN_WGPU_SHADER_DIR").map(PathBuf::from)
+                .or(defaults.shader_dir.clone()),
+            skinned_model: std::env::var_os("LEARN_WGPU_SKINNED_MODEL").map(PathBuf::from),
+            terrain: std::env::var("LEARN_WGPU_TERRAIN").is_ok_and(|value| value == "1"),
+            vegetation: std::env::var("LEARN_WGPU_VEGETATION").is_ok_and(|value| value == "1"),
+            water: std::env::var("LEARN_WGPU_WATER").is_ok_and(|value| value == "1"),
+            reverse_z: std::env::var("LEARN_WGPU_REVERSE_Z").is_ok_and(|value| value == "1"),
+            camera_relative: std::env::var("LEARN_WGPU_CAMERA_RELATIVE").is_ok_and(|value| value == "1"),
+            gpu_culling: std::env::var("LEARN_WGPU_GPU_CULLING").map_or(defaults.gpu_culling,
+                |value| value != "0"),
+            stress_instances: std::env::var("LEARN_WGPU_STRESS").ok()
+                .and_then(|count| count.parse().ok()),
+            edge_detection: std::env::var("LEARN_WGPU_EDGE_DETECTION").is_ok_and(|value| value == "1")
+                .then(EdgeDetectionOptions::default),
+            render_scale: std::env::var("LEARN_WGPU_RENDER_SCALE").ok()
+                .and_then(|scale| scale.parse().ok())
+                .unwrap_or(defaults.render_scale),
+            selection_outline: std::env::var("LEARN_WGPU_SELECTION_OUTLINE")
+                .map_or(defaults.selection_outline, |value| (value != "0").then(OutlineOptions::default)),
+            recording: std::env::var_os("LEARN_WGPU_RECORDING")
+                .map_or(defaults.recording.clone(), |path| RecordingOptions {
+                    output: RecordingOutput::from_path(Path::new(&path), 30),
+                    ..defaults.recording.clone()
+                }),
+            ..defaults
+        }
+    }
+
+    // Overrides these options with the settings file, when there is one. Anything
+    // set after this wins over the file, e.g. command line arguments.
+    pub fn apply_settings(&mut self)
+    {
+        if let Some(settings) = self.settings.as_deref().and_then(Settings::load) {
+            settings.apply_to_options(self);
+        }
+    }
+
+    pub fn requests_specific_adapter(&self) -> bool
+    {
+        self.adapter_name.is_some() || self.adapter_index.is_some()
+    }
+
+    pub fn matches_adapter(&self, index: usize, name: &str) -> bool
+    {
+        let matches_name = self.adapter_name.as_ref()
+            .is_some_and(|requested| name.to_lowercase().contains(&requested.to_lowercase()));
+
+        matches_name || self.adapter_index == Some(index)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceOptions {
+    pub format: Option<TextureFormat>,
+    pub prefer_hdr: bool,
+    // Off presents frames as soon as they're done, tearing where the platform can't
+    // do that without.
+    pub vsync: bool
+}
+
+impl Default for SurfaceOptions {
+    fn default() -> Self
+    {
+        Self {
+            format: None,
+            prefer_hdr: false,
+            vsync: true
+        }
+    }
+}
+
+impl SurfaceOptions {
+    // Falls back to vsync where it can't be turned off.
+    pub fn present_mode(&self) -> PresentMode
+    {
+        if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        }
+    }
+
+    pub fn select_format(&self, supported: &[TextureFormat]) -> TextureFormat
+    {
+        if let Some(format) = self.format {
+            if supported.contains(&format) {
+                return format;
+            }
+            log::warn!("Requested surface format {format:?} isn't supported, falling back");
+        }
+
+        if self.prefer_hdr && supported.contains(&TextureFormat::Rgba16Float) {
+            return TextureFormat::Rgba16Float;
+        }
+
+        supported.iter()
+            .copied()
+            .find(|f| f.is_srgb())
+            .unwrap_or(supported[0])
+    }
+
+    // The sRGB/linear counterpart of the surface format, so UI passes can
+    // render through a view without the automatic sRGB conversion.
+    pub fn view_formats(format: TextureFormat) -> Vec<TextureFormat>
+    {
+        let counterpart = if format.is_srgb() {
+            format.remove_srgb_suffix()
+        } else {
+            format.add_srgb_suffix()
+        };
+
+        if counterpart == format {
+            vec![]
+        } else {
+            vec![counterpart]
+        }
+    }
+}
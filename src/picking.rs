@@ -0,0 +1,198 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, Transform, Vector3};
+
+use super::bounds::{Aabb, BoundingSphere, Bounds};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self
+    {
+        Self {
+            origin,
+            direction: direction.normalize()
+        }
+    }
+
+    pub fn at(&self, t: f32) -> Point3<f32>
+    {
+        self.origin + self.direction * t
+    }
+
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self
+    {
+        let origin = matrix.transform_point(self.origin);
+        let direction = matrix.transform_vector(self.direction);
+
+        Self { origin, direction }
+    }
+
+    pub fn intersect_plane(&self, point: Point3<f32>, normal: Vector3<f32>) -> Option<f32>
+    {
+        let denom = normal.dot(self.direction);
+        if denom.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let t = (point - self.origin).dot(normal) / denom;
+        (t > f32::EPSILON).then_some(t)
+    }
+
+    // Slab test, returns the distance to the entry point (or 0 when starting inside).
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32>
+    {
+        let mut t_min = 0.0_f32;
+        let mut t_max = f32::INFINITY;
+
+        for axis in 0..3 {
+            let origin = self.origin[axis];
+            let direction = self.direction[axis];
+
+            if direction.abs() < f32::EPSILON {
+                if origin < aabb.min[axis] || origin > aabb.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv = 1.0 / direction;
+            let mut t0 = (aabb.min[axis] - origin) * inv;
+            let mut t1 = (aabb.max[axis] - origin) * inv;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+
+    // Distance to the first point on the sphere (or 0 when starting inside).
+    pub fn intersect_sphere(&self, sphere: &BoundingSphere) -> Option<f32>
+    {
+        let to_center = sphere.center - self.origin;
+        let along = to_center.dot(self.direction);
+        let distance2 = to_center.magnitude2() - along * along;
+        let radius2 = sphere.radius * sphere.radius;
+        if distance2 > radius2 {
+            return None;
+        }
+
+        let half_chord = (radius2 - distance2).sqrt();
+        let (t0, t1) = (along - half_chord, along + half_chord);
+        (t1 >= 0.0).then_some(t0.max(0.0))
+    }
+
+    // Möller–Trumbore, double sided so picking doesn't depend on the cull mode.
+    pub fn intersect_triangle(
+        &self,
+        a: Point3<f32>,
+        b: Point3<f32>,
+        c: Point3<f32>
+    ) -> Option<f32>
+    {
+        let edge_ab = b - a;
+        let edge_ac = c - a;
+        let p = self.direction.cross(edge_ac);
+        let det = edge_ab.dot(p);
+
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let s = self.origin - a;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge_ab);
+        let v = self.direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge_ac.dot(q) * inv_det;
+        (t > f32::EPSILON).then_some(t)
+    }
+}
+
+// `normal` is in world space and always faces back towards the ray origin.
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub distance: f32,
+    pub point: Point3<f32>,
+    pub normal: Vector3<f32>
+}
+
+pub struct PickMesh {
+    positions: Vec<Point3<f32>>,
+    indices: Vec<u16>,
+    bounds: Bounds
+}
+
+impl PickMesh {
+    pub fn new(positions: Vec<Point3<f32>>, indices: &[u16]) -> Self
+    {
+        let bounds = Bounds::from_points(&positions);
+
+        Self {
+            positions,
+            indices: indices.to_vec(),
+            bounds
+        }
+    }
+
+    pub fn bounds(&self) -> &Bounds
+    {
+        &self.bounds
+    }
+
+    // `model_inverse` takes the world space ray into the mesh's local space, the
+    // returned distance is measured along the world space ray.
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        model: &Matrix4<f32>,
+        model_inverse: &Matrix4<f32>
+    ) -> Option<RayHit>
+    {
+        // The world space sphere rejects most misses before the ray is transformed.
+        ray.intersect_sphere(&self.bounds.sphere.transform(model))?;
+        let local_ray = ray.transform(model_inverse);
+        local_ray.intersect_aabb(&self.bounds.aabb)?;
+
+        let (t, tri) = self.indices.chunks_exact(3)
+            .filter_map(|tri| local_ray.intersect_triangle(
+                self.positions[tri[0] as usize],
+                self.positions[tri[1] as usize],
+                self.positions[tri[2] as usize]
+            ).map(|t| (t, tri)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))?;
+
+        let a = self.positions[tri[0] as usize];
+        let local_normal = (self.positions[tri[1] as usize] - a)
+            .cross(self.positions[tri[2] as usize] - a);
+        let mut normal = model_inverse.transpose().transform_vector(local_normal).normalize();
+        if normal.dot(ray.direction) > 0.0 {
+            normal = -normal;
+        }
+
+        let point = model.transform_point(local_ray.at(t));
+
+        Some(RayHit {
+            distance: (point - ray.origin).magnitude(),
+            point,
+            normal
+        })
+    }
+}
@@ -0,0 +1,52 @@
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation3, Vector3, Zero};
+
+use super::{instance::{Instance, WHITE}, picking::RayHit, vertex_animation::AnimationParams};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlacementOptions {
+    // Snaps the hit point to a grid of this size on the horizontal plane.
+    pub grid_snap: Option<f32>,
+    // Rotates placed instances so their front face (+Z) points along the surface normal.
+    pub align_to_surface: bool,
+    // Used when the cursor ray doesn't hit any instance, a horizontal plane at this height.
+    pub ground_height: f32
+}
+
+impl Default for PlacementOptions {
+    fn default() -> Self
+    {
+        Self {
+            grid_snap: None,
+            align_to_surface: true,
+            ground_height: 0.0
+        }
+    }
+}
+
+impl PlacementOptions {
+    pub fn place(&self, hit: &RayHit) -> Instance
+    {
+        let mut position = Vector3::new(hit.point.x, hit.point.y, hit.point.z);
+        if let Some(grid) = self.grid_snap.filter(|grid| *grid > 0.0) {
+            position.x = (position.x / grid).round() * grid;
+            position.z = (position.z / grid).round() * grid;
+        }
+
+        let rotation = if self.align_to_surface && !hit.normal.is_zero() {
+            Quaternion::from_arc(Vector3::unit_z(), hit.normal.normalize(), None)
+        } else {
+            Quaternion::from_axis_angle(Vector3::unit_z(), Deg(0.0))
+        };
+
+        Instance {
+            position,
+            rotation,
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            animation: AnimationParams::default(),
+            color: WHITE,
+            texture_index: 0,
+            material: None,
+            emissive_intensity: None
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    io,
+    path::{Path, PathBuf}
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io::Write,
+    process::{Child, ChildStdin, Command, Stdio}
+};
+
+use image::RgbaImage;
+
+// Where recorded frames go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordingOutput {
+    // frame_00000.png, frame_00001.png and so on in this directory.
+    Images(PathBuf),
+    // The raw frames are piped to ffmpeg, which encodes them into this file by its
+    // extension, e.g. a .gif or an .mp4. The video plays at `fps`, however fast the
+    // frames were rendered.
+    Ffmpeg {
+        path: PathBuf,
+        fps: u32
+    }
+}
+
+impl RecordingOutput {
+    // A path with an extension is a video for ffmpeg, one without a directory.
+    pub fn from_path(path: &Path, fps: u32) -> Self
+    {
+        match path.extension() {
+            Some(_) => Self::Ffmpeg { path: path.to_path_buf(), fps },
+            None => Self::Images(path.to_path_buf())
+        }
+    }
+
+    pub fn path(&self) -> &Path
+    {
+        match self {
+            Self::Images(path) | Self::Ffmpeg { path, .. } => path
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingOptions {
+    pub output: RecordingOutput,
+    // How many frames to record before stopping by itself, None until it's stopped.
+    pub frames: Option<u32>
+}
+
+impl Default for RecordingOptions {
+    fn default() -> Self
+    {
+        Self {
+            output: RecordingOutput::Images(PathBuf::from("recordings")),
+            frames: Some(120)
+        }
+    }
+}
+
+enum Sink {
+    Images(PathBuf),
+    // There are no processes to pipe to on the web.
+    #[cfg(not(target_arch = "wasm32"))]
+    Ffmpeg(Child, ChildStdin)
+}
+
+// Writes every frame it's handed until it has as many as it was asked for. The frames
+// keep the size they started at, as neither an image sequence nor a video can change
+// it halfway.
+pub struct Recorder {
+    options: RecordingOptions,
+    size: (u32, u32),
+    sink: Sink,
+    frames_written: u32
+}
+
+impl Recorder {
+    // Creates the directory, or starts ffmpeg, which has to be on the PATH.
+    pub fn start(options: RecordingOptions, (width, height): (u32, u32)) -> io::Result<Self>
+    {
+        let sink = match &options.output {
+            RecordingOutput::Images(directory) => {
+                std::fs::create_dir_all(directory)?;
+                Sink::Images(directory.clone())
+            },
+            RecordingOutput::Ffmpeg { path, fps } => start_ffmpeg(path, *fps, (width, height))?
+        };
+
+        Ok(Self {
+            options,
+            size: (width, height),
+            sink,
+            frames_written: 0
+        })
+    }
+
+    pub fn options(&self) -> &RecordingOptions
+    {
+        &self.options
+    }
+
+    pub fn frames_written(&self) -> u32
+    {
+        self.frames_written
+    }
+
+    pub fn is_done(&self) -> bool
+    {
+        self.options.frames.is_some_and(|frames| self.frames_written >= frames)
+    }
+
+    pub fn write_frame(&mut self, frame: &RgbaImage) -> io::Result<()>
+    {
+        if frame.dimensions() != self.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "the frame changed size from {}x{} to {}x{}", self.size.0, self.size.1,
+                frame.width(), frame.height())));
+        }
+
+        match &mut self.sink {
+            Sink::Images(directory) => {
+                let path = directory.join(format!("frame_{:05}.png", self.frames_written));
+                frame.save(&path).map_err(io::Error::other)?;
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            Sink::Ffmpeg(_, stdin) => stdin.write_all(frame.as_raw())?
+        }
+        self.frames_written += 1;
+
+        Ok(())
+    }
+
+    // Waits for ffmpeg to encode what it was sent.
+    pub fn finish(self) -> io::Result<()>
+    {
+        match self.sink {
+            Sink::Images(_) => Ok(()),
+            #[cfg(not(target_arch = "wasm32"))]
+            Sink::Ffmpeg(mut child, stdin) => {
+                drop(stdin);
+                let status = child.wait()?;
+                if !status.success() {
+                    return Err(io::Error::other(format!("ffmpeg exited with {status}")));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn start_ffmpeg(path: &Path, fps: u32, (width, height): (u32, u32)) -> io::Result<Sink>
+{
+    let mut child = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pixel_format", "rgba"])
+        .args(["-video_size", &format!("{width}x{height}")])
+        .args(["-framerate", &fps.max(1).to_string(), "-i", "-"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("couldn't run ffmpeg: {e}")))?;
+    let stdin = child.stdin.take()
+        .ok_or_else(|| io::Error::other("ffmpeg has no stdin"))?;
+
+    Ok(Sink::Ffmpeg(child, stdin))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn start_ffmpeg(_path: &Path, _fps: u32, _size: (u32, u32)) -> io::Result<Sink>
+{
+    Err(io::Error::new(io::ErrorKind::Unsupported, "ffmpeg can't run on the web"))
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use wgpu::Backend;
+
+use super::{gpu_capabilities::GpuCapabilities, msaa::MSAA_SAMPLE_COUNT, post_effect::PostEffect, taa::MOTION_VECTOR_FORMAT};
+
+// Named in lowercase in the settings file, e.g. anti_aliasing = "taa".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AntiAliasing {
+    None,
+    // A post effect over the finished frame.
+    Fxaa,
+    // The scene is drawn with MSAA_SAMPLE_COUNT samples per pixel and resolved.
+    Msaa,
+    // The main camera is jittered and every frame resolved against the ones before it.
+    Taa
+}
+
+// FXAA on the web, where WebGL2 can't be counted on for TAA, nothing elsewhere.
+impl Default for AntiAliasing {
+    fn default() -> Self
+    {
+        if cfg!(target_arch = "wasm32") {
+            AntiAliasing::Fxaa
+        } else {
+            AntiAliasing::None
+        }
+    }
+}
+
+impl AntiAliasing {
+    const ALL: [AntiAliasing; 4] = [
+        AntiAliasing::None,
+        AntiAliasing::Fxaa,
+        AntiAliasing::Msaa,
+        AntiAliasing::Taa
+    ];
+
+    // TAA renders motion vectors into a float target, which WebGL2 only has with an
+    // extension. MSAA's depth resolve loads from a multisampled depth texture, which
+    // GLSL can't, so it's off on the GL backend and with it WebGL2.
+    pub fn is_supported(&self, capabilities: &GpuCapabilities) -> bool
+    {
+        match self {
+            AntiAliasing::Msaa => capabilities.supports_sample_count(MSAA_SAMPLE_COUNT)
+                && capabilities.backend() != Backend::Gl,
+            AntiAliasing::Taa => capabilities.can_render_to(MOTION_VECTOR_FORMAT),
+            _ => true
+        }
+    }
+
+    pub fn next(&self, capabilities: &GpuCapabilities) -> Self
+    {
+        let index = Self::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+
+        Self::ALL.iter()
+            .cycle()
+            .skip(index + 1)
+            .find(|mode| mode.is_supported(capabilities))
+            .copied()
+            .unwrap_or(AntiAliasing::None)
+    }
+
+    pub fn label(&self) -> &'static str
+    {
+        match self {
+            AntiAliasing::None => "No Anti-Aliasing",
+            AntiAliasing::Fxaa => "FXAA",
+            AntiAliasing::Msaa => "MSAA",
+            AntiAliasing::Taa => "TAA"
+        }
+    }
+
+    pub fn is_temporal(&self) -> bool
+    {
+        *self == AntiAliasing::Taa
+    }
+
+    // Of the passes drawing the scene, and the pipelines they draw with.
+    pub fn sample_count(&self) -> u32
+    {
+        match self {
+            AntiAliasing::Msaa => MSAA_SAMPLE_COUNT,
+            _ => 1
+        }
+    }
+
+    pub fn post_effect(&self) -> Option<PostEffect>
+    {
+        match self {
+            AntiAliasing::Fxaa => Some(PostEffect::Fxaa),
+            _ => None
+        }
+    }
+}
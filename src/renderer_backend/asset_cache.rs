@@ -0,0 +1,174 @@
+use std::{collections::HashMap, fmt, hash::{Hash, Hasher}, marker::PhantomData, rc::{Rc, Weak}};
+
+// A counted reference to an asset in an AssetCache. Clones share the asset, which
+// stays loaded until the last of them is dropped and the cache collects it.
+pub struct Handle<T> {
+    id: Rc<u64>,
+    marker: PhantomData<fn() -> T>
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> u64
+    {
+        *self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self
+    {
+        Self {
+            id: self.id.clone(),
+            marker: PhantomData
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool
+    {
+        Rc::ptr_eq(&self.id, &other.id)
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        self.id().hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "Handle({})", self.id())
+    }
+}
+
+struct Entry<T> {
+    key: Option<String>,
+    asset: T,
+    handle: Weak<u64>
+}
+
+// Assets by id, with an optional key (usually the path it was loaded from) so loading
+// the same thing twice hands back the existing asset. Nothing is destroyed here:
+// collect_unused returns the assets nobody holds a handle to any more, as releasing
+// them may need the device or an allocator.
+pub struct AssetCache<T> {
+    entries: HashMap<u64, Entry<T>>,
+    keys: HashMap<String, u64>,
+    next_id: u64
+}
+
+impl<T> Default for AssetCache<T> {
+    fn default() -> Self
+    {
+        Self {
+            entries: HashMap::new(),
+            keys: HashMap::new(),
+            next_id: 0
+        }
+    }
+}
+
+impl<T> AssetCache<T> {
+    // A key already in use is taken over by the new asset.
+    pub fn insert(&mut self, key: Option<&str>, asset: T) -> Handle<T>
+    {
+        let id = Rc::new(self.next_id);
+        self.next_id += 1;
+
+        if let Some(key) = key {
+            self.keys.insert(String::from(key), *id);
+        }
+        self.entries.insert(*id, Entry {
+            key: key.map(String::from),
+            asset,
+            handle: Rc::downgrade(&id)
+        });
+
+        Handle {
+            id,
+            marker: PhantomData
+        }
+    }
+
+    // Only finds assets that are still held, an unused one waiting to be collected
+    // is as good as gone.
+    pub fn find(&self, key: &str) -> Option<Handle<T>>
+    {
+        let entry = self.entries.get(self.keys.get(key)?)?;
+
+        entry.handle.upgrade().map(|id| Handle {
+            id,
+            marker: PhantomData
+        })
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T>
+    {
+        self.entries.get(&handle.id()).map(|entry| &entry.asset)
+    }
+
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T>
+    {
+        self.entries.get_mut(&handle.id()).map(|entry| &mut entry.asset)
+    }
+
+    // By the id of its handles, for what tracks assets without holding on to them.
+    pub fn get_mut_by_id(&mut self, id: u64) -> Option<&mut T>
+    {
+        self.entries.get_mut(&id).map(|entry| &mut entry.asset)
+    }
+
+    pub fn key(&self, handle: &Handle<T>) -> Option<&str>
+    {
+        self.entries.get(&handle.id())?.key.as_deref()
+    }
+
+    // With the id every handle to the asset has.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &T)>
+    {
+        self.entries.iter().map(|(id, entry)| (*id, &entry.asset))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T>
+    {
+        self.entries.values_mut().map(|entry| &mut entry.asset)
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    pub fn collect_unused(&mut self) -> Vec<T>
+    {
+        let unused = self.entries.iter()
+            .filter(|(_, entry)| entry.handle.strong_count() == 0)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        unused.into_iter()
+            .filter_map(|id| {
+                let entry = self.entries.remove(&id)?;
+                // The key may have been taken over since.
+                if let Some(key) = &entry.key {
+                    if self.keys.get(key) == Some(&id) {
+                        self.keys.remove(key);
+                    }
+                }
+
+                Some(entry.asset)
+            })
+            .collect()
+    }
+}
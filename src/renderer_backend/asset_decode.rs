@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use image::{DynamicImage, ImageResult};
+
+// Decoding is CPU bound and independent per asset, so natively it runs on the rayon
+// pool and only the uploads stay on the calling thread. The web has no threads to
+// spare, there everything runs in order.
+pub fn par_map<'a, T, R, F>(items: &'a [T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&'a T) -> R + Sync + Send
+{
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            use rayon::prelude::*;
+
+            items.par_iter().map(f).collect()
+        } else {
+            items.iter().map(f).collect()
+        }
+    }
+}
+
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send
+{
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            rayon::join(a, b)
+        } else {
+            (a(), b())
+        }
+    }
+}
+
+// Converted to RGBA8 up front, which is what Texture uploads, so the conversion
+// happens on the decoding thread as well.
+pub fn decode_image(bytes: &[u8]) -> ImageResult<DynamicImage>
+{
+    Ok(DynamicImage::ImageRgba8(image::load_from_memory(bytes)?.into_rgba8()))
+}
+
+pub fn open_image(path: &Path) -> ImageResult<DynamicImage>
+{
+    Ok(DynamicImage::ImageRgba8(image::open(path)?.into_rgba8()))
+}
+
+pub fn open_images<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<ImageResult<DynamicImage>>
+{
+    par_map(paths, |path| open_image(path.as_ref()))
+}
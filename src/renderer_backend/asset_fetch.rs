@@ -0,0 +1,79 @@
+use js_sys::{Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{ReadableStreamDefaultReader, Response, Window, WorkerGlobalScope};
+
+use crate::error::RendererError;
+
+use super::shader_registry::ShaderHandle;
+
+// Fetches `url`, relative to the page or, in a render worker, to its script, reading the
+// body as it arrives so `progress` is called with the bytes read so far and the
+// Content-Length, when the server sent one.
+pub async fn fetch_bytes(url: &str, mut progress: impl FnMut(u64, Option<u64>)) -> Result<Vec<u8>, RendererError>
+{
+    let error = |message: String| RendererError::Fetch { url: String::from(url), message };
+    let js_error = |value: JsValue| error(value.as_string().unwrap_or_else(|| format!("{value:?}")));
+
+    let global = js_sys::global();
+    let request = if let Some(scope) = global.dyn_ref::<WorkerGlobalScope>() {
+        scope.fetch_with_str(url)
+    } else if let Some(window) = global.dyn_ref::<Window>() {
+        window.fetch_with_str(url)
+    } else {
+        return Err(error(String::from("there's neither a window nor a worker to fetch from")));
+    };
+    let response: Response = JsFuture::from(request).await
+        .and_then(JsCast::dyn_into)
+        .map_err(js_error)?;
+    if !response.ok() {
+        return Err(error(format!("{} {}", response.status(), response.status_text())));
+    }
+    let total = response.headers().get("Content-Length").ok().flatten()
+        .and_then(|length| length.parse().ok());
+
+    let Some(body) = response.body() else {
+        progress(0, total);
+        return Ok(Vec::new());
+    };
+    let reader: ReadableStreamDefaultReader = body.get_reader().unchecked_into();
+    let mut bytes = Vec::with_capacity(total.unwrap_or(0) as usize);
+    loop {
+        let chunk = JsFuture::from(reader.read()).await.map_err(js_error)?;
+        if Reflect::get(&chunk, &JsValue::from_str("done")).map_err(js_error)?.is_truthy() {
+            break;
+        }
+        let value = Reflect::get(&chunk, &JsValue::from_str("value")).map_err(js_error)?;
+        bytes.extend(Uint8Array::new(&value).to_vec());
+        progress(bytes.len() as u64, total);
+    }
+
+    Ok(bytes)
+}
+
+// Every shader found under `base_url` by its file name, e.g. shaders/vertex.wgsl. The
+// ones the server doesn't have are left out, so they keep their embedded source.
+// `progress` is called with how many of ShaderHandle::ALL were tried so far.
+pub async fn fetch_shaders(
+    base_url: &str,
+    mut progress: impl FnMut(u64, Option<u64>)
+) -> Result<Vec<(ShaderHandle, String)>, RendererError>
+{
+    let base_url = base_url.trim_end_matches('/');
+    let total = ShaderHandle::ALL.len() as u64;
+    let mut sources = Vec::new();
+    for (index, shader) in ShaderHandle::ALL.into_iter().enumerate() {
+        let url = format!("{base_url}/{}", shader.filename());
+        match fetch_bytes(&url, |_, _| {}).await {
+            Ok(bytes) => {
+                let source = String::from_utf8(bytes)
+                    .map_err(|e| RendererError::Fetch { url: url.clone(), message: e.to_string() })?;
+                sources.push((shader, source));
+            },
+            Err(e) => log::warn!("Keeping the embedded {}: {e}", shader.filename())
+        }
+        progress(index as u64 + 1, Some(total));
+    }
+
+    Ok(sources)
+}
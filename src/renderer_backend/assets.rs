@@ -0,0 +1,292 @@
+use std::path::Path;
+
+use bytemuck::Pod;
+use wgpu::{BindGroupLayout, Device, IndexFormat, Queue, RenderPass, Sampler};
+
+use crate::error::RendererError;
+
+use super::{asset_cache::{AssetCache, Handle}, asset_decode, gpu_allocator::{AllocationKind, GpuAllocation, GpuAllocator}, material::Material, procedural_texture::ProceduralTexture, render_target::RenderTarget, residency::ResidentTexture, texture::TextureKind};
+
+pub type TextureHandle = Handle<ResidentTexture>;
+pub type MeshHandle = Handle<Mesh>;
+pub type MaterialHandle = Handle<Material>;
+pub type RenderTargetHandle = Handle<RenderTarget>;
+pub type ProceduralTextureHandle = Handle<ProceduralTexture>;
+
+pub trait MeshIndex: Pod {
+    const FORMAT: IndexFormat;
+}
+
+impl MeshIndex for u16 {
+    const FORMAT: IndexFormat = IndexFormat::Uint16;
+}
+
+impl MeshIndex for u32 {
+    const FORMAT: IndexFormat = IndexFormat::Uint32;
+}
+
+// Vertices and indices sub-allocated from the GpuAllocator. The bytes stay on the CPU
+// so the mesh can be uploaded again after a device loss or an eviction.
+pub struct Mesh {
+    vertex_data: Vec<u8>,
+    index_data: Vec<u8>,
+    index_format: IndexFormat,
+    num_indices: u32,
+    allocations: Option<(GpuAllocation, GpuAllocation)>
+}
+
+impl Mesh {
+    pub fn new<V: Pod, I: MeshIndex>(
+        device: &Device,
+        queue: &Queue,
+        allocator: &mut GpuAllocator,
+        vertices: &[V],
+        indices: &[I]
+    ) -> Self
+    {
+        let vertex_data = bytemuck::cast_slice(vertices).to_vec();
+        let index_data = bytemuck::cast_slice(indices).to_vec();
+        let allocations = Self::upload(device, queue, allocator, &vertex_data, &index_data);
+
+        Self {
+            vertex_data,
+            index_data,
+            index_format: I::FORMAT,
+            num_indices: indices.len() as u32,
+            allocations: Some(allocations)
+        }
+    }
+
+    pub fn num_indices(&self) -> u32
+    {
+        self.num_indices
+    }
+
+    pub fn size_in_bytes(&self) -> u64
+    {
+        (self.vertex_data.len() + self.index_data.len()) as u64
+    }
+
+    pub fn is_resident(&self) -> bool
+    {
+        self.allocations.is_some()
+    }
+
+    pub fn make_resident(&mut self, device: &Device, queue: &Queue, allocator: &mut GpuAllocator)
+    {
+        if self.allocations.is_none() {
+            self.allocations = Some(Self::upload(device, queue, allocator, &self.vertex_data,
+                &self.index_data));
+        }
+    }
+
+    // Frees the GPU ranges, the mesh can't be bound until make_resident.
+    pub fn evict(&mut self, allocator: &mut GpuAllocator)
+    {
+        if let Some((vertex_allocation, index_allocation)) = self.allocations.take() {
+            allocator.free(vertex_allocation);
+            allocator.free(index_allocation);
+        }
+    }
+
+    // Sets vertex buffer 0 and the index buffer, the draws are up to the caller. Binds
+    // nothing while the mesh is evicted.
+    pub fn bind<'a>(&'a self, render_pass: &mut RenderPass<'a>, allocator: &'a GpuAllocator)
+    {
+        let Some((vertex_allocation, index_allocation)) = &self.allocations else {
+            return;
+        };
+        render_pass.set_vertex_buffer(0, allocator.slice(vertex_allocation));
+        render_pass.set_index_buffer(allocator.slice(index_allocation), self.index_format);
+    }
+
+    pub fn free(mut self, allocator: &mut GpuAllocator)
+    {
+        self.evict(allocator);
+    }
+
+    fn upload(
+        device: &Device,
+        queue: &Queue,
+        allocator: &mut GpuAllocator,
+        vertex_data: &[u8],
+        index_data: &[u8]
+    ) -> (GpuAllocation, GpuAllocation)
+    {
+        (
+            allocator.allocate_init(device, queue, AllocationKind::Vertex, vertex_data),
+            allocator.allocate_init(device, queue, AllocationKind::Index, index_data)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AssetStats {
+    pub textures: usize,
+    pub meshes: usize,
+    pub materials: usize,
+    pub render_targets: usize,
+    pub procedural_textures: usize,
+    pub unloaded: u64
+}
+
+// The shared textures, meshes and materials. Textures are keyed by the path they were
+// loaded from, exactly as given, and their kind, so the same file loaded twice is one
+// GPU texture unless it is read as something else.
+#[derive(Default)]
+pub struct Assets {
+    pub textures: AssetCache<ResidentTexture>,
+    pub meshes: AssetCache<Mesh>,
+    pub materials: AssetCache<Material>,
+    pub render_targets: AssetCache<RenderTarget>,
+    pub procedural_textures: AssetCache<ProceduralTexture>,
+    unloaded: u64
+}
+
+impl Assets {
+    pub fn load_texture(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        path: &Path,
+        kind: TextureKind
+    ) -> Result<TextureHandle, RendererError>
+    {
+        self.load_textures(device, queue, layout, sampler, &[path], kind)
+            .map(|mut handles| handles.remove(0))
+    }
+
+    // The files not loaded yet are decoded in parallel, then uploaded one by one.
+    pub fn load_textures<P: AsRef<Path> + Sync>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        paths: &[P],
+        kind: TextureKind
+    ) -> Result<Vec<TextureHandle>, RendererError>
+    {
+        let keys = paths.iter()
+            .map(|path| Self::texture_key(path.as_ref(), kind))
+            .collect::<Vec<_>>();
+        let mut missing: Vec<&Path> = Vec::new();
+        for (path, key) in paths.iter().zip(&keys) {
+            let path = path.as_ref();
+            if self.textures.find(key).is_none() && !missing.contains(&path) {
+                missing.push(path);
+            }
+        }
+        let decoded = asset_decode::open_images(&missing);
+
+        // Held until the handles are handed out, as nothing can find an asset nobody
+        // holds. On an error they go again with the next collection.
+        let mut loaded = Vec::with_capacity(decoded.len());
+        for (path, image) in missing.iter().zip(decoded) {
+            let key = Self::texture_key(path, kind);
+            let mut texture = ResidentTexture::new(&key, image?, kind);
+            texture.make_resident(device, queue, layout, sampler)?;
+            loaded.push(self.textures.insert(Some(&key), texture));
+        }
+
+        Ok(keys.iter()
+            .map(|key| self.textures.find(key).expect("every texture was just loaded"))
+            .collect())
+    }
+
+    // Keyed by `name` like a path, so loading it again by that name shares the texture.
+    #[allow(clippy::too_many_arguments)]
+    pub fn load_texture_from_bytes(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        name: &str,
+        bytes: &[u8],
+        kind: TextureKind
+    ) -> Result<TextureHandle, RendererError>
+    {
+        let key = Self::texture_key(Path::new(name), kind);
+        if let Some(handle) = self.textures.find(&key) {
+            return Ok(handle);
+        }
+
+        let mut texture = ResidentTexture::new(&key, asset_decode::decode_image(bytes)?, kind);
+        texture.make_resident(device, queue, layout, sampler)?;
+
+        Ok(self.textures.insert(Some(&key), texture))
+    }
+
+    fn texture_key(path: &Path, kind: TextureKind) -> String
+    {
+        format!("{} ({kind:?})", path.to_string_lossy())
+    }
+
+    // Materials go first, as they hold on to textures, render targets and procedural
+    // textures. Returns how many assets went.
+    pub fn collect_unused(&mut self, allocator: &mut GpuAllocator) -> usize
+    {
+        let materials = self.materials.collect_unused().len();
+        let render_targets = self.render_targets.collect_unused().len();
+        let procedural_textures = self.procedural_textures.collect_unused().len();
+        let mut textures = self.textures.collect_unused();
+        for texture in &mut textures {
+            texture.evict();
+        }
+        let meshes = self.meshes.collect_unused();
+        let unloaded = materials + render_targets + procedural_textures + textures.len() + meshes.len();
+        for mesh in meshes {
+            mesh.free(allocator);
+        }
+        self.unloaded += unloaded as u64;
+
+        unloaded
+    }
+
+    // Expects the allocator to be cleared already, everything is uploaded anew.
+    pub fn recover(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        allocator: &mut GpuAllocator,
+        layout: &BindGroupLayout,
+        sampler: &Sampler
+    ) -> Result<(), RendererError>
+    {
+        for texture in self.textures.iter_mut() {
+            texture.evict();
+            texture.make_resident(device, queue, layout, sampler)?;
+        }
+        for mesh in self.meshes.iter_mut() {
+            mesh.allocations = Some(Mesh::upload(device, queue, allocator, &mesh.vertex_data,
+                &mesh.index_data));
+        }
+        for material in self.materials.iter_mut() {
+            material.release_bind_group();
+        }
+
+        Ok(())
+    }
+
+    pub fn evict_textures(&mut self)
+    {
+        for texture in self.textures.iter_mut() {
+            texture.evict();
+        }
+    }
+
+    pub fn stats(&self) -> AssetStats
+    {
+        AssetStats {
+            textures: self.textures.len(),
+            meshes: self.meshes.len(),
+            materials: self.materials.len(),
+            render_targets: self.render_targets.len(),
+            procedural_textures: self.procedural_textures.len(),
+            unloaded: self.unloaded
+        }
+    }
+}
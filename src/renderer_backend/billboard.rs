@@ -0,0 +1,286 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Vector2, Vector3};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, Device, Extent3d, Queue, RenderPass, ShaderStages, TextureDescriptor, TextureDimension, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexAttribute, VertexStepMode};
+
+use crate::state::camera::Camera;
+
+use super::{debug_labels::DebugLabels, texture::Texture, vertex_layout::VertexLayout};
+
+// How far in front of what's behind them, in world units, billboards start fading out.
+pub const DEFAULT_SOFT_DISTANCE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BillboardMode {
+    // Faces the camera head on, e.g. particles and labels. Centered on its position.
+    #[default]
+    Spherical,
+    // Only turns around the world's y axis and stands on its position, e.g. impostors
+    // of distant trees.
+    Cylindrical
+}
+
+impl BillboardMode {
+    fn index(self) -> u32
+    {
+        match self {
+            BillboardMode::Spherical => 0,
+            BillboardMode::Cylindrical => 1
+        }
+    }
+}
+
+// A quad turned towards the camera in the vertex shader, textured with a layer of the
+// material texture array.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Billboard {
+    pub position: Vector3<f32>,
+    // Width and height in world units.
+    pub size: Vector2<f32>,
+    pub color: [f32; 4],
+    pub texture_index: u32,
+    pub mode: BillboardMode
+}
+
+impl Billboard {
+    pub fn new(position: Vector3<f32>, size: Vector2<f32>) -> Self
+    {
+        Self {
+            position,
+            size,
+            color: [1.0; 4],
+            texture_index: 0,
+            mode: BillboardMode::default()
+        }
+    }
+
+    pub fn to_raw(&self) -> BillboardRaw
+    {
+        BillboardRaw {
+            position: [self.position.x, self.position.y, self.position.z, 0.0],
+            size: [self.size.x, self.size.y, 0.0, 0.0],
+            color: self.color,
+            params: [self.texture_index, self.mode.index(), 0, 0]
+        }
+    }
+}
+
+// Matches BillboardInput in billboard.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct BillboardRaw {
+    position: [f32; 4],
+    size: [f32; 4],
+    color: [f32; 4],
+    // texture index, mode
+    params: [u32; 4]
+}
+
+// The only vertex buffer, the quad's corners come from the vertex index.
+impl VertexLayout for BillboardRaw {
+    const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+        2 => Float32x4,
+        3 => Uint32x4
+    ];
+    const STEP_MODE: VertexStepMode = VertexStepMode::Instance;
+}
+
+// Matches BillboardUniform in billboard.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct BillboardUniform {
+    // near plane, far plane, soft distance
+    params: [f32; 4]
+}
+
+// The billboards of a frame, uploaded furthest from the camera first so they blend
+// in order. The instance buffer only ever grows. Where they come close to the scene
+// behind them they fade out, so they don't cut hard lines into it. That reads the
+// depth the main camera left, viewports seeing through other cameras draw them with
+// the hard bind group instead, as does GL, which can't copy depth.
+pub struct BillboardBuffer {
+    labels: DebugLabels,
+    instance_buffer: Option<Buffer>,
+    num_billboards: u32,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    // A zeroed uniform, a soft distance of 0 doesn't fade, and a placeholder depth.
+    hard_bind_group: BindGroup
+}
+
+impl BillboardBuffer {
+    pub fn new(device: &Device, label: &str) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let create_uniform_buffer = |suffix: &str| device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix(suffix)),
+                contents: bytemuck::cast_slice(&[BillboardUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        let hard_uniform_buffer = create_uniform_buffer("Hard Uniform Buffer");
+        let placeholder_depth = device.create_texture(
+            &TextureDescriptor {
+                label: Some(&labels.with_suffix("Placeholder Depth Texture")),
+                size: Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: Texture::DEPTH_FORMAT,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[]
+            }
+        );
+        let bind_group_layout = Self::get_bind_group_layout(device, &labels);
+        let hard_bind_group = Self::get_bind_group(device, &labels, &bind_group_layout,
+            &hard_uniform_buffer, &placeholder_depth.create_view(&TextureViewDescriptor::default()));
+
+        Self {
+            uniform_buffer: create_uniform_buffer("Uniform Buffer"),
+            bind_group_layout,
+            hard_bind_group,
+            labels,
+            instance_buffer: None,
+            num_billboards: 0
+        }
+    }
+
+    // The depth is bound as unfilterable floats rather than as depth, which GLSL can't
+    // load from, so billboards still draw on WebGL2.
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Float { filterable: false }
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    // Depth is linearized with the planes of `camera`, the one the main pass used.
+    // Billboards fade out over `soft_distance`, 0 draws them hard.
+    pub fn write_uniforms(&self, queue: &Queue, camera: &Camera, soft_distance: f32)
+    {
+        let uniform = BillboardUniform {
+            params: [camera.znear, camera.zfar, soft_distance.max(0.0), 0.0]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // `depth` is a copy of what the main pass drew, or its MSAA resolve, which can be
+    // read while the depth texture is attached. The depth texture is recreated with
+    // the surface, so the bind group is made per frame.
+    pub fn create_bind_group(&self, device: &Device, depth: &TextureView) -> BindGroup
+    {
+        Self::get_bind_group(device, &self.labels, &self.bind_group_layout, &self.uniform_buffer,
+            depth)
+    }
+
+    pub fn hard_bind_group(&self) -> &BindGroup
+    {
+        &self.hard_bind_group
+    }
+
+    fn get_bind_group(
+        device: &Device,
+        labels: &DebugLabels,
+        layout: &BindGroupLayout,
+        uniform_buffer: &Buffer,
+        depth: &TextureView
+    ) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.bind_group()),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(depth)
+                    }
+                ]
+            }
+        )
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn num_billboards(&self) -> u32
+    {
+        self.num_billboards
+    }
+
+    pub fn write(&mut self, device: &Device, queue: &Queue, billboards: &[BillboardRaw])
+    {
+        self.num_billboards = billboards.len() as u32;
+        if billboards.is_empty() {
+            return;
+        }
+
+        let size = std::mem::size_of_val(billboards) as u64;
+        if self.instance_buffer.as_ref().is_none_or(|buffer| buffer.size() < size) {
+            self.instance_buffer = Some(device.create_buffer(
+                &BufferDescriptor {
+                    label: Some(&self.labels.buffer()),
+                    size: size.next_power_of_two(),
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    mapped_at_creation: false
+                }
+            ));
+        }
+        if let Some(buffer) = &self.instance_buffer {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(billboards));
+        }
+    }
+
+    // Six vertices per billboard, with whatever pipeline and groups are bound.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>)
+    {
+        let Some(buffer) = self.instance_buffer.as_ref().filter(|_| self.num_billboards > 0) else {
+            return;
+        };
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        render_pass.draw(0..6, 0..self.num_billboards);
+    }
+}
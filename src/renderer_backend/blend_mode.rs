@@ -0,0 +1,37 @@
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+const ADDITIVE: BlendComponent = BlendComponent {
+    src_factor: BlendFactor::One,
+    dst_factor: BlendFactor::One,
+    operation: BlendOperation::Add
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendMode {
+    #[default]
+    Opaque,
+    AlphaBlending,
+    Additive,
+    // For colors already multiplied by their alpha, e.g. textures exported that way
+    // or anything that mixes additive and alpha blended output in one shader.
+    Premultiplied
+}
+
+impl BlendMode {
+    pub fn blend_state(&self) -> BlendState
+    {
+        match self {
+            BlendMode::Opaque => BlendState::REPLACE,
+            BlendMode::AlphaBlending => BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => BlendState { color: ADDITIVE, alpha: ADDITIVE },
+            BlendMode::Premultiplied => BlendState::PREMULTIPLIED_ALPHA_BLENDING
+        }
+    }
+
+    // Anything but opaque reads what's behind it, so it has to be drawn after the
+    // opaque geometry, back to front and without writing depth.
+    pub fn is_transparent(&self) -> bool
+    {
+        *self != BlendMode::Opaque
+    }
+}
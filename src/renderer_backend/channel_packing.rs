@@ -0,0 +1,194 @@
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use anyhow::*;
+use image::{imageops::FilterType, DynamicImage, GrayImage, RgbaImage};
+
+// One of a material's maps, a grayscale image or a channel of one holding several,
+// like glTF's metallic-roughness texture.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MapSource {
+    pub path: PathBuf,
+    // Read as luma without one.
+    pub channel: Option<usize>
+}
+
+impl MapSource {
+    pub fn grayscale(path: PathBuf) -> Self
+    {
+        Self {
+            path,
+            channel: None
+        }
+    }
+
+    pub fn channel(path: PathBuf, channel: usize) -> Self
+    {
+        Self {
+            path,
+            channel: Some(channel)
+        }
+    }
+}
+
+// The maps a material references, as an import reads them. After packing, `packed`
+// points at the shared texture and the separate references are cleared.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialMaps {
+    pub occlusion: Option<MapSource>,
+    pub roughness: Option<MapSource>,
+    pub metallic: Option<MapSource>,
+    pub packed: Option<PathBuf>
+}
+
+impl MaterialMaps {
+    fn sources(&self) -> [Option<MapSource>; 3]
+    {
+        [self.occlusion.clone(), self.roughness.clone(), self.metallic.clone()]
+    }
+
+    // All three already come from the channels they'd be packed into, of one image.
+    fn already_packed(sources: &[Option<MapSource>; 3]) -> Option<PathBuf>
+    {
+        let first = sources[0].as_ref()?;
+        sources.iter()
+            .enumerate()
+            .all(|(index, source)| source.as_ref()
+                .is_some_and(|source| source.path == first.path && source.channel == Some(index)))
+            .then(|| first.path.clone())
+    }
+}
+
+// Packs into R = occlusion, G = roughness, B = metallic, glTF's convention, so the
+// result can be bound as both the occlusion and the metallic-roughness map. Missing
+// maps are filled with white, which is what glTF assumes for an absent texture.
+// Each map is an image and the channel to read, see MapSource.
+pub fn pack_channels(
+    occlusion: Option<(&DynamicImage, Option<usize>)>,
+    roughness: Option<(&DynamicImage, Option<usize>)>,
+    metallic: Option<(&DynamicImage, Option<usize>)>
+) -> Result<RgbaImage>
+{
+    let maps = [occlusion, roughness, metallic];
+    let (width, height) = maps.iter()
+        .flatten()
+        .map(|(map, _)| (map.width(), map.height()))
+        .max_by_key(|(width, height)| *width as u64 * *height as u64)
+        .ok_or_else(|| anyhow!("no maps to pack"))?;
+
+    let channels = maps.map(|map| map.map(|(map, channel)| {
+        let gray = extract_channel(map, channel);
+        if gray.dimensions() == (width, height) {
+            gray
+        } else {
+            image::imageops::resize(&gray, width, height, FilterType::Triangle)
+        }
+    }));
+
+    let mut packed = RgbaImage::from_pixel(width, height, image::Rgba([255; 4]));
+    for (index, channel) in channels.iter().enumerate() {
+        let Some(channel) = channel else {
+            continue;
+        };
+
+        for (pixel, value) in packed.pixels_mut().zip(channel.pixels()) {
+            pixel[index] = value[0];
+        }
+    }
+
+    Ok(packed)
+}
+
+fn extract_channel(map: &DynamicImage, channel: Option<usize>) -> GrayImage
+{
+    let Some(channel) = channel.filter(|&channel| channel < 4) else {
+        return map.to_luma8();
+    };
+
+    let rgba = map.to_rgba8();
+    GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| image::Luma([rgba.get_pixel(x, y)[channel]]))
+}
+
+// Packs the maps of every material at import time. Materials referencing the same
+// set of maps share one packed texture.
+pub struct ChannelPacker {
+    output_dir: PathBuf,
+    packed: HashMap<[Option<MapSource>; 3], PathBuf>
+}
+
+impl ChannelPacker {
+    pub fn new(output_dir: &Path) -> Self
+    {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            packed: HashMap::new()
+        }
+    }
+
+    // Materials whose maps already are one image in the packed layout point at it
+    // without writing another.
+    pub fn pack(&mut self, maps: &mut MaterialMaps) -> Result<()>
+    {
+        let sources = maps.sources();
+        if sources.iter().all(Option::is_none) {
+            return Ok(());
+        }
+
+        let packed_path = if let Some(packed_path) = MaterialMaps::already_packed(&sources) {
+            packed_path
+        } else if let Some(packed_path) = self.packed.get(&sources) {
+            packed_path.clone()
+        } else {
+            let packed_path = self.write_packed(&sources)?;
+            self.packed.insert(sources, packed_path.clone());
+            packed_path
+        };
+
+        maps.occlusion = None;
+        maps.roughness = None;
+        maps.metallic = None;
+        maps.packed = Some(packed_path);
+
+        Ok(())
+    }
+
+    fn write_packed(&self, sources: &[Option<MapSource>; 3]) -> Result<PathBuf>
+    {
+        let mut images = HashMap::new();
+        for source in sources.iter().flatten() {
+            if !images.contains_key(&source.path) {
+                let image = image::open(&source.path)
+                    .with_context(|| format!("couldn't open {}", source.path.display()))?;
+                images.insert(source.path.clone(), image);
+            }
+        }
+        let [occlusion, roughness, metallic] = sources.each_ref()
+            .map(|source| source.as_ref().map(|source| (&images[&source.path], source.channel)));
+        let packed = pack_channels(occlusion, roughness, metallic)?;
+
+        let packed_path = self.output_path(sources);
+        std::fs::create_dir_all(&self.output_dir)?;
+        packed.save(&packed_path)
+            .with_context(|| format!("couldn't write {}", packed_path.display()))?;
+        log::info!("Packed {} maps into {}", sources.iter().flatten().count(), packed_path.display());
+
+        Ok(packed_path)
+    }
+
+    fn output_path(&self, sources: &[Option<MapSource>; 3]) -> PathBuf
+    {
+        let stem = sources.iter()
+            .flatten()
+            .find_map(|source| source.path.file_stem())
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("material");
+
+        let mut packed_path = self.output_dir.join(format!("{stem}_orm.png"));
+        let mut suffix = 1;
+        while self.packed.values().any(|path| *path == packed_path) {
+            packed_path = self.output_dir.join(format!("{stem}_orm_{suffix}.png"));
+            suffix += 1;
+        }
+
+        packed_path
+    }
+}
@@ -0,0 +1,386 @@
+use std::rc::Rc;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, Sampler, SamplerBindingType, ShaderStages, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension};
+
+use crate::error::RendererError;
+
+use super::{debug_labels::DebugLabels, sampler_cache::{SamplerCache, SamplerSpec}, texture::Texture};
+
+pub const LUT_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+// .cube files go up to 256, but past 65 nobody can tell.
+pub const MAX_LUT_SIZE: u32 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGradingOptions {
+    // In stops, every one doubles the brightness.
+    pub exposure: f32,
+    // Around middle grey, 1 leaves it as it is.
+    pub contrast: f32,
+    // 0 is greyscale, 1 leaves it as it is.
+    pub saturation: f32,
+    // White balance, positive is warmer and negative cooler, about -1 to 1.
+    pub temperature: f32,
+    // White balance, positive towards magenta and negative towards green.
+    pub tint: f32,
+    // Applied to the linear color after everything above, 1 leaves it as it is.
+    pub gamma: f32,
+    // How much of the LUT's result is used, 0 skips it.
+    pub lut_strength: f32
+}
+
+impl Default for ColorGradingOptions {
+    fn default() -> Self
+    {
+        Self {
+            exposure: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            temperature: 0.0,
+            tint: 0.0,
+            gamma: 1.0,
+            lut_strength: 1.0
+        }
+    }
+}
+
+// A 3D lookup table as it's read from an Adobe/Resolve .cube file, red changing
+// fastest, then green, then blue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<[f32; 3]>
+}
+
+impl CubeLut {
+    // Maps every color to itself.
+    pub fn identity(size: u32) -> Self
+    {
+        let size = size.max(2);
+        let scale = 1.0 / (size - 1) as f32;
+        let data = (0..size * size * size)
+            .map(|index| [
+                (index % size) as f32 * scale,
+                (index / size % size) as f32 * scale,
+                (index / (size * size)) as f32 * scale
+            ])
+            .collect();
+
+        Self {
+            title: None,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data
+        }
+    }
+
+    // `file` only goes into error messages. 1D LUTs aren't supported.
+    pub fn parse(file: &str, source: &str) -> Result<Self, RendererError>
+    {
+        let error = |line: usize, message: &str| RendererError::CubeLut {
+            file: String::from(file),
+            line,
+            message: String::from(message)
+        };
+        let parse_triple = |line: usize, values: &[&str]| -> Result<[f32; 3], RendererError> {
+            let [r, g, b] = values else {
+                return Err(error(line, "expected three numbers"));
+            };
+            let parse = |value: &str| value.parse::<f32>()
+                .map_err(|_| error(line, &format!("{value} isn't a number")));
+
+            Ok([parse(r)?, parse(g)?, parse(b)?])
+        };
+
+        let mut lut = Self {
+            title: None,
+            size: 0,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data: Vec::new()
+        };
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let values = words.collect::<Vec<_>>();
+            match keyword {
+                "TITLE" => lut.title = Some(String::from(line["TITLE".len()..].trim().trim_matches('"'))),
+                "LUT_3D_SIZE" => {
+                    let size = values.first()
+                        .and_then(|value| value.parse::<u32>().ok())
+                        .ok_or_else(|| error(number, "expected the LUT size"))?;
+                    if !(2..=MAX_LUT_SIZE).contains(&size) {
+                        return Err(error(number, &format!("sizes go from 2 to {MAX_LUT_SIZE}")));
+                    }
+                    lut.size = size;
+                },
+                "LUT_1D_SIZE" => return Err(error(number, "1D LUTs aren't supported")),
+                "DOMAIN_MIN" => lut.domain_min = parse_triple(number, &values)?,
+                "DOMAIN_MAX" => lut.domain_max = parse_triple(number, &values)?,
+                _ if keyword.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') => {
+                    if lut.size == 0 {
+                        return Err(error(number, "LUT_3D_SIZE has to come before the data"));
+                    }
+                    let mut triple = vec![keyword];
+                    triple.extend(values);
+                    lut.data.push(parse_triple(number, &triple)?);
+                },
+                // Other keywords are for other tools.
+                _ => ()
+            }
+        }
+
+        let expected = (lut.size * lut.size * lut.size) as usize;
+        if lut.size == 0 || lut.data.len() != expected {
+            return Err(error(source.lines().count(),
+                &format!("expected {expected} entries, found {}", lut.data.len())));
+        }
+
+        Ok(lut)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ColorGradingUniform {
+    // exposure multiplier, contrast, saturation, gamma
+    params: [f32; 4],
+    // white balance multiplier, LUT strength
+    balance: [f32; 4],
+    // LUT input range
+    domain_min: [f32; 4],
+    domain_max: [f32; 4]
+}
+
+// What the color grading post effect binds on top of the frame: its options and the
+// LUT, an identity one until another is set. The parsed LUT is kept so it can be
+// uploaded again after the device is lost.
+pub struct ColorGrading {
+    labels: DebugLabels,
+    options: ColorGradingOptions,
+    lut: Option<CubeLut>,
+    lut_texture: Texture,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    sampler: Rc<Sampler>
+}
+
+impl ColorGrading {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        label: &str,
+        options: ColorGradingOptions,
+        samplers: &mut SamplerCache
+    ) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[ColorGradingUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let lut_texture = Self::create_lut_texture(device, queue, &labels, &CubeLut::identity(2));
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            sampler: samplers.get(device, SamplerSpec::default().with_anisotropy(1)),
+            labels,
+            options: Self::clamp_options(options),
+            lut: None,
+            lut_texture,
+            uniform_buffer
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D3,
+                            sample_type: TextureSampleType::Float { filterable: true }
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    fn create_lut_texture(device: &Device, queue: &Queue, labels: &DebugLabels, lut: &CubeLut) -> Texture
+    {
+        let size = Extent3d {
+            width: lut.size,
+            height: lut.size,
+            depth_or_array_layers: lut.size
+        };
+        let texture = device.create_texture(
+            &TextureDescriptor {
+                label: Some(&labels.with_suffix("LUT")),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D3,
+                format: LUT_FORMAT,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[]
+            }
+        );
+        let texels = lut.data.iter()
+            .flat_map(|color| {
+                let [r, g, b] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+                [r, g, b, 255]
+            })
+            .collect::<Vec<_>>();
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All
+            },
+            &texels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * lut.size),
+                rows_per_image: Some(lut.size)
+            },
+            size
+        );
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        Texture { texture, view }
+    }
+
+    fn clamp_options(options: ColorGradingOptions) -> ColorGradingOptions
+    {
+        ColorGradingOptions {
+            exposure: options.exposure,
+            contrast: options.contrast.max(0.0),
+            saturation: options.saturation.max(0.0),
+            temperature: options.temperature.clamp(-1.0, 1.0),
+            tint: options.tint.clamp(-1.0, 1.0),
+            gamma: options.gamma.max(0.01),
+            lut_strength: options.lut_strength.clamp(0.0, 1.0)
+        }
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn options(&self) -> ColorGradingOptions
+    {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: ColorGradingOptions)
+    {
+        self.options = Self::clamp_options(options);
+    }
+
+    pub fn lut(&self) -> Option<&CubeLut>
+    {
+        self.lut.as_ref()
+    }
+
+    // None goes back to the identity LUT.
+    pub fn set_lut(&mut self, device: &Device, queue: &Queue, lut: Option<CubeLut>)
+    {
+        let texture = match &lut {
+            Some(lut) => Self::create_lut_texture(device, queue, &self.labels, lut),
+            None => Self::create_lut_texture(device, queue, &self.labels, &CubeLut::identity(2))
+        };
+        self.lut_texture.texture.destroy();
+        self.lut_texture = texture;
+        self.lut = lut;
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    // The white balance is a multiplier that leaves the luminance of white alone.
+    pub fn write_uniforms(&self, queue: &Queue)
+    {
+        let options = &self.options;
+        let balance = [
+            1.0 + 0.2 * options.temperature,
+            1.0 - 0.2 * options.tint,
+            1.0 - 0.2 * options.temperature
+        ];
+        let luminance = 0.2126 * balance[0] + 0.7152 * balance[1] + 0.0722 * balance[2];
+        let (domain_min, domain_max) = self.lut.as_ref()
+            .map_or(([0.0; 3], [1.0; 3]), |lut| (lut.domain_min, lut.domain_max));
+        let uniform = ColorGradingUniform {
+            params: [options.exposure.exp2(), options.contrast, options.saturation, options.gamma],
+            balance: [balance[0] / luminance, balance[1] / luminance, balance[2] / luminance,
+                options.lut_strength],
+            domain_min: [domain_min[0], domain_min[1], domain_min[2], 0.0],
+            domain_max: [domain_max[0], domain_max[1], domain_max[2], 0.0]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn create_bind_group(&self, device: &Device) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&self.lut_texture.view)
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(&self.sampler)
+                    }
+                ]
+            }
+        )
+    }
+}
@@ -0,0 +1,195 @@
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, CommandEncoder, ComputePassDescriptor, ComputePipeline, Device, ShaderStages, StorageTextureAccess, TextureFormat, TextureSampleType, TextureViewDimension};
+
+use crate::error::RendererError;
+
+use super::{compute_pipeline_builder::ComputePipelineBuilder, debug_labels::DebugLabels, shader_registry::{ShaderHandle, ShaderRegistry}};
+
+// A compute pipeline with the workgroup size its shader declares, so dispatches can be
+// sized by the work to cover instead of by workgroups.
+pub struct ComputePass {
+    labels: DebugLabels,
+    pipeline: ComputePipeline,
+    workgroup_size: [u32; 3]
+}
+
+impl ComputePass {
+    // `workgroup_size` has to match the entry point's @workgroup_size.
+    pub fn new(
+        device: &Device,
+        shaders: &ShaderRegistry,
+        label: &str,
+        shader: ShaderHandle,
+        entry_point: &str,
+        workgroup_size: [u32; 3],
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Self, RendererError>
+    {
+        let pipeline = ComputePipelineBuilder::builder()
+            .set_label(label)
+            .set_shader_module(shader, entry_point)
+            .build(device, shaders, bind_group_layouts)?;
+
+        Ok(Self {
+            labels: DebugLabels::new(label),
+            pipeline,
+            workgroup_size: workgroup_size.map(|size| size.max(1))
+        })
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    // Enough workgroups that every one of `size` invocations runs, the shader skips
+    // those past the edge.
+    pub fn workgroup_count(&self, size: [u32; 3]) -> [u32; 3]
+    {
+        [0, 1, 2].map(|axis| size[axis].div_ceil(self.workgroup_size[axis]))
+    }
+
+    // Into a compute pass that's already begun, e.g. one shared with other dispatches.
+    // The bind groups are set in order from group 0.
+    pub fn dispatch<'a>(
+        &'a self,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+        bind_groups: &[&'a BindGroup],
+        size: [u32; 3]
+    )
+    {
+        let [x, y, z] = self.workgroup_count(size);
+        if x == 0 || y == 0 || z == 0 {
+            return;
+        }
+
+        compute_pass.set_pipeline(&self.pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+
+    // In a compute pass of its own, named after the pipeline.
+    pub fn encode(
+        &self,
+        command_encoder: &mut CommandEncoder,
+        bind_groups: &[&BindGroup],
+        size: [u32; 3])
+    {
+        let label = self.labels.with_suffix("Pass");
+        let mut compute_pass = command_encoder.begin_compute_pass(
+            &ComputePassDescriptor {
+                label: Some(&label),
+                timestamp_writes: None
+            }
+        );
+        self.dispatch(&mut compute_pass, bind_groups, size);
+    }
+}
+
+// The entries of a compute bind group layout, numbered in the order they're added.
+#[derive(Debug, Clone, Default)]
+pub struct ComputeBindingsBuilder {
+    entries: Vec<BindGroupLayoutEntry>
+}
+
+impl ComputeBindingsBuilder {
+    pub fn builder() -> Self
+    {
+        Self::default()
+    }
+
+    // Read with textureLoad, so it doesn't need a sampler.
+    pub fn texture(&mut self) -> &mut Self
+    {
+        self.push(BindingType::Texture {
+            multisampled: false,
+            view_dimension: TextureViewDimension::D2,
+            sample_type: TextureSampleType::Float { filterable: false }
+        })
+    }
+
+    pub fn storage_texture(&mut self, format: TextureFormat) -> &mut Self
+    {
+        self.push(BindingType::StorageTexture {
+            access: StorageTextureAccess::WriteOnly,
+            format,
+            view_dimension: TextureViewDimension::D2
+        })
+    }
+
+    // Written a layer per invocation along z.
+    pub fn storage_texture_array(&mut self, format: TextureFormat) -> &mut Self
+    {
+        self.push(BindingType::StorageTexture {
+            access: StorageTextureAccess::WriteOnly,
+            format,
+            view_dimension: TextureViewDimension::D2Array
+        })
+    }
+
+    pub fn uniform_buffer(&mut self) -> &mut Self
+    {
+        self.push(BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None
+        })
+    }
+
+    pub fn storage_buffer(&mut self, read_only: bool) -> &mut Self
+    {
+        self.push(BindingType::Buffer {
+            ty: BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None
+        })
+    }
+
+    pub fn build(&self, device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &self.entries
+            }
+        )
+    }
+
+    fn push(&mut self, ty: BindingType) -> &mut Self
+    {
+        self.entries.push(BindGroupLayoutEntry {
+            binding: self.entries.len() as u32,
+            visibility: ShaderStages::COMPUTE,
+            ty,
+            count: None
+        });
+
+        self
+    }
+}
+
+// A bind group for a ComputeBindingsBuilder layout, the resources in binding order.
+pub fn create_compute_bind_group(
+    device: &Device,
+    labels: &DebugLabels,
+    layout: &BindGroupLayout,
+    resources: Vec<BindingResource>
+) -> BindGroup
+{
+    let entries = resources.into_iter()
+        .enumerate()
+        .map(|(binding, resource)| BindGroupEntry {
+            binding: binding as u32,
+            resource
+        })
+        .collect::<Vec<_>>();
+
+    device.create_bind_group(
+        &BindGroupDescriptor {
+            label: Some(&labels.bind_group()),
+            layout,
+            entries: &entries
+        }
+    )
+}
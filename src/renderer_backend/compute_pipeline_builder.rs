@@ -0,0 +1,87 @@
+use wgpu::{BindGroupLayout, ComputePipeline, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor};
+
+use crate::error::RendererError;
+
+use super::{debug_labels::DebugLabels, error_scope::ErrorScope, pipeline_builder::{create_shader_module, ShaderStage}, shader_preprocessor::ShaderPreprocessor, shader_registry::{ShaderHandle, ShaderRegistry}};
+
+pub struct ComputePipelineBuilder {
+    labels: DebugLabels,
+    stage: Option<ShaderStage>,
+    preprocessor: ShaderPreprocessor
+}
+
+impl ComputePipelineBuilder {
+    pub fn builder() -> Self
+    {
+        Self {
+            labels: DebugLabels::new("Compute"),
+            stage: None,
+            preprocessor: ShaderPreprocessor::default()
+        }
+    }
+
+    pub fn set_label(&mut self, label: &str) -> &mut Self
+    {
+        self.labels = DebugLabels::new(label);
+
+        self
+    }
+
+    pub fn set_shader_module(&mut self, shader: ShaderHandle, entry_point: &str) -> &mut Self
+    {
+        self.stage = Some(ShaderStage::new(shader, entry_point));
+
+        self
+    }
+
+    pub fn set_define(&mut self, name: &str, value: &str) -> &mut Self
+    {
+        self.preprocessor.set_define(name, value);
+
+        self
+    }
+
+    pub fn build(
+        &mut self,
+        device: &Device,
+        shaders: &ShaderRegistry,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<ComputePipeline, RendererError>
+    {
+        let scope = ErrorScope::push(device, &self.labels.pipeline());
+        let pipeline = self.create_pipeline(device, shaders, bind_group_layouts);
+        scope.pop_now(device)?;
+
+        pipeline
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &Device,
+        shaders: &ShaderRegistry,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<ComputePipeline, RendererError>
+    {
+        // Compute shaders have nothing in common, so there's no sensible default to fall back to.
+        let stage = self.stage.as_ref()
+            .ok_or_else(|| RendererError::MissingShader { pipeline: self.labels.pipeline() })?;
+        let shader_module = create_shader_module(device, shaders, &self.preprocessor,
+            stage.shader, &self.labels.shader())?;
+        let compute_pipeline_layout = device.create_pipeline_layout(
+            &PipelineLayoutDescriptor {
+                label: Some(&self.labels.pipeline_layout()),
+                bind_group_layouts,
+                push_constant_ranges: &[]
+            }
+        );
+
+        Ok(device.create_compute_pipeline(
+            &ComputePipelineDescriptor {
+                label: Some(&self.labels.pipeline()),
+                layout: Some(&compute_pipeline_layout),
+                module: &shader_module,
+                entry_point: &stage.entry_point
+            }
+        ))
+    }
+}
@@ -0,0 +1,66 @@
+// Derives consistent labels for every GPU object that belongs to one logical
+// resource, so captures in RenderDoc/Xcode group "Cry Cat", "Cry Cat View",
+// "Cry Cat Sampler" and "Cry Cat Bind Group" together.
+#[derive(Debug, Clone)]
+pub struct DebugLabels {
+    name: String
+}
+
+impl DebugLabels {
+    pub fn new(name: &str) -> Self
+    {
+        Self {
+            name: String::from(name)
+        }
+    }
+
+    pub fn name(&self) -> &str
+    {
+        &self.name
+    }
+
+    pub fn with_suffix(&self, suffix: &str) -> String
+    {
+        format!("{} {suffix}", self.name)
+    }
+
+    pub fn shader(&self) -> String
+    {
+        self.with_suffix("Shader")
+    }
+
+    pub fn pipeline_layout(&self) -> String
+    {
+        self.with_suffix("Pipeline Layout")
+    }
+
+    pub fn pipeline(&self) -> String
+    {
+        self.with_suffix("Pipeline")
+    }
+
+    pub fn view(&self) -> String
+    {
+        self.with_suffix("View")
+    }
+
+    pub fn sampler(&self) -> String
+    {
+        self.with_suffix("Sampler")
+    }
+
+    pub fn bind_group(&self) -> String
+    {
+        self.with_suffix("Bind Group")
+    }
+
+    pub fn bind_group_layout(&self) -> String
+    {
+        self.with_suffix("Bind Group Layout")
+    }
+
+    pub fn buffer(&self) -> String
+    {
+        self.with_suffix("Buffer")
+    }
+}
@@ -0,0 +1,155 @@
+use std::f32::consts::TAU;
+
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Point3, Vector3};
+use wgpu::{vertex_attr_array, Buffer, BufferDescriptor, BufferUsages, Device, Queue, RenderPass, VertexAttribute};
+
+use crate::state::bounds::{Aabb, BoundingSphere};
+
+use super::{debug_labels::DebugLabels, vertex_layout::VertexLayout};
+
+const CIRCLE_SEGMENTS: usize = 24;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3]
+}
+
+impl VertexLayout for LineVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3
+    ];
+}
+
+// Immediate mode lines: shapes are pushed on the CPU every frame, uploaded in one
+// write and drawn as a single line list. The vertex buffer only ever grows.
+pub struct DebugLines {
+    labels: DebugLabels,
+    vertices: Vec<LineVertex>,
+    vertex_buffer: Option<Buffer>,
+    num_vertices: u32
+}
+
+impl DebugLines {
+    pub fn new(label: &str) -> Self
+    {
+        Self {
+            labels: DebugLabels::new(label),
+            vertices: Vec::new(),
+            vertex_buffer: None,
+            num_vertices: 0
+        }
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn num_vertices(&self) -> u32
+    {
+        self.num_vertices
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.vertices.clear();
+    }
+
+    pub fn push_line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 3])
+    {
+        self.vertices.push(LineVertex { position: from.into(), color });
+        self.vertices.push(LineVertex { position: to.into(), color });
+    }
+
+    pub fn push_aabb(&mut self, aabb: &Aabb, color: [f32; 3])
+    {
+        self.push_box(&aabb.corners(), color);
+    }
+
+    // Any hexahedron, e.g. a camera's frustum, with its corners in the order of
+    // Aabb::corners.
+    pub fn push_box(&mut self, corners: &[Point3<f32>; 8], color: [f32; 3])
+    {
+        const EDGES: [(usize, usize); 12] = [
+            (0, 1), (1, 2), (2, 3), (3, 0),
+            (4, 5), (5, 6), (6, 7), (7, 4),
+            (0, 4), (1, 5), (2, 6), (3, 7)
+        ];
+
+        for (from, to) in EDGES {
+            self.push_line(corners[from], corners[to], color);
+        }
+    }
+
+    // One circle around each axis.
+    pub fn push_sphere(&mut self, sphere: &BoundingSphere, color: [f32; 3])
+    {
+        let axes = [
+            (Vector3::unit_x(), Vector3::unit_y()),
+            (Vector3::unit_y(), Vector3::unit_z()),
+            (Vector3::unit_z(), Vector3::unit_x())
+        ];
+
+        for (u, v) in axes {
+            self.push_circle(sphere.center, u, v, sphere.radius, color);
+        }
+    }
+
+    // In the plane spanned by `u` and `v`, which have to be perpendicular unit vectors.
+    pub fn push_circle(&mut self, center: Point3<f32>, u: Vector3<f32>, v: Vector3<f32>, radius: f32, color: [f32; 3])
+    {
+        let point = |segment: usize| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for segment in 0..CIRCLE_SEGMENTS {
+            self.push_line(point(segment), point(segment + 1), color);
+        }
+    }
+
+    pub fn upload(&mut self, device: &Device, queue: &Queue)
+    {
+        self.num_vertices = self.vertices.len() as u32;
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        let size = std::mem::size_of_val(self.vertices.as_slice()) as u64;
+        if self.vertex_buffer.as_ref().is_none_or(|buffer| buffer.size() < size) {
+            self.vertex_buffer = Some(device.create_buffer(
+                &BufferDescriptor {
+                    label: Some(&self.labels.buffer()),
+                    size: size.next_power_of_two(),
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    mapped_at_creation: false
+                }
+            ));
+        }
+
+        if let Some(vertex_buffer) = &self.vertex_buffer {
+            queue.write_buffer(vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+    }
+
+    // Buffers belong to the device, so they are dropped along with it.
+    pub fn release(&mut self)
+    {
+        self.vertex_buffer = None;
+        self.num_vertices = 0;
+    }
+
+    // Expects the pipeline and the camera bind group to be set already.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>)
+    {
+        let Some(vertex_buffer) = self.vertex_buffer.as_ref().filter(|_| self.num_vertices > 0) else {
+            return;
+        };
+
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
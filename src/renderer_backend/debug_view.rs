@@ -0,0 +1,96 @@
+use wgpu::{CompareFunction, Features, PolygonMode};
+
+use super::{blend_mode::BlendMode, pipeline_builder::PipelineBuilder, shader_registry::ShaderHandle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    Wireframe,
+    Normals,
+    Uvs,
+    Depth,
+    Overdraw
+}
+
+impl DebugView {
+    const ALL: [DebugView; 6] = [
+        DebugView::Shaded,
+        DebugView::Wireframe,
+        DebugView::Normals,
+        DebugView::Uvs,
+        DebugView::Depth,
+        DebugView::Overdraw
+    ];
+
+    // Requested when available, the wireframe view is skipped without it.
+    pub fn optional_features() -> Features
+    {
+        Features::POLYGON_MODE_LINE
+    }
+
+    pub fn is_supported(&self, features: Features) -> bool
+    {
+        match self {
+            DebugView::Wireframe => features.contains(Features::POLYGON_MODE_LINE),
+            _ => true
+        }
+    }
+
+    pub fn next(&self, features: Features) -> Self
+    {
+        let index = Self::ALL.iter().position(|view| view == self).unwrap_or(0);
+
+        Self::ALL.iter()
+            .cycle()
+            .skip(index + 1)
+            .find(|view| view.is_supported(features))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn label(&self) -> &'static str
+    {
+        match self {
+            DebugView::Shaded => "Shaded",
+            DebugView::Wireframe => "Debug Wireframe",
+            DebugView::Normals => "Debug Normals",
+            DebugView::Uvs => "Debug UVs",
+            DebugView::Depth => "Debug Depth",
+            DebugView::Overdraw => "Debug Overdraw"
+        }
+    }
+
+    fn fragment_entry(&self) -> &'static str
+    {
+        match self {
+            DebugView::Shaded => "fs_main",
+            DebugView::Wireframe => "fs_wireframe",
+            DebugView::Normals => "fs_normals",
+            DebugView::Uvs => "fs_uvs",
+            DebugView::Depth => "fs_depth",
+            DebugView::Overdraw => "fs_overdraw"
+        }
+    }
+
+    // Sets up everything but the pixel format for this view's pipeline. Overdraw
+    // accumulates a constant per fragment, so it blends additively and doesn't depth test.
+    pub fn configure(&self, builder: &mut PipelineBuilder)
+    {
+        builder
+            .set_label(self.label())
+            .set_shader_module(ShaderHandle::DebugView, "vs_main", self.fragment_entry());
+
+        match self {
+            DebugView::Wireframe => {
+                builder.set_polygon_mode(PolygonMode::Line);
+            },
+            DebugView::Overdraw => {
+                builder
+                    .set_blend_mode(BlendMode::Additive)
+                    .set_depth_state(false, CompareFunction::Always);
+            },
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, Device, Queue, RenderPass, ShaderStages, TextureSampleType, TextureView, TextureViewDimension, VertexAttribute, VertexStepMode};
+
+use super::{assets::MaterialHandle, debug_labels::DebugLabels, vertex_layout::VertexLayout};
+
+// A box projecting its material onto whatever opaque geometry is inside it, e.g. a
+// bullet hole or a stain. The material's layer 0 covers the box's x and y and is
+// projected along -z, onto surfaces facing +z.
+#[derive(Debug, Clone)]
+pub struct Decal {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    // Of the box, in world units along its own axes.
+    pub size: Vector3<f32>,
+    pub material: MaterialHandle,
+    // Multiplied with the material's color, alpha fades the decal out.
+    pub color: [f32; 4]
+}
+
+impl Decal {
+    pub fn model_matrix(&self) -> Matrix4<f32>
+    {
+        Matrix4::from_translation(self.position) * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.size.x, self.size.y, self.size.z)
+    }
+
+    // `color` and `emissive` come from the material.
+    pub fn to_raw(&self, color: [f32; 4], emissive: [f32; 3]) -> DecalRaw
+    {
+        let model = self.model_matrix();
+        let mut tinted = self.color;
+        for (channel, tint) in tinted.iter_mut().zip(color) {
+            *channel *= tint;
+        }
+
+        DecalRaw {
+            model: model.into(),
+            inverse_model: model.invert().unwrap_or_else(Matrix4::identity).into(),
+            color: tinted,
+            emissive: [emissive[0], emissive[1], emissive[2], 0.0]
+        }
+    }
+}
+
+// Matches DecalInput in decal.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct DecalRaw {
+    model: [[f32; 4]; 4],
+    // From world space into the unit box.
+    inverse_model: [[f32; 4]; 4],
+    color: [f32; 4],
+    emissive: [f32; 4]
+}
+
+// The only vertex buffer, the box's corners come from the vertex index.
+impl VertexLayout for DecalRaw {
+    const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x4,
+        9 => Float32x4
+    ];
+    const STEP_MODE: VertexStepMode = VertexStepMode::Instance;
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DecalUniform {
+    inverse_view_proj: [[f32; 4]; 4],
+    // the main viewport in pixels
+    viewport: [f32; 4]
+}
+
+// The decals of a frame, uploaded in one write and drawn in a pass of their own after
+// the opaque geometry. They read the depth buffer to find the surfaces they land on,
+// so that pass has no depth attachment. The instance buffer only ever grows.
+pub struct DecalBuffer {
+    labels: DebugLabels,
+    uniform_buffer: Buffer,
+    instance_buffer: Option<Buffer>,
+    bind_group_layout: BindGroupLayout
+}
+
+impl DecalBuffer {
+    pub fn new(device: &Device, label: &str) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix("Uniform Buffer")),
+                contents: bytemuck::cast_slice(&[DecalUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            uniform_buffer,
+            instance_buffer: None
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Depth
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    pub fn write(&mut self, device: &Device, queue: &Queue, decals: &[DecalRaw])
+    {
+        if decals.is_empty() {
+            return;
+        }
+
+        let size = std::mem::size_of_val(decals) as u64;
+        if self.instance_buffer.as_ref().is_none_or(|buffer| buffer.size() < size) {
+            self.instance_buffer = Some(device.create_buffer(
+                &BufferDescriptor {
+                    label: Some(&self.labels.buffer()),
+                    size: size.next_power_of_two(),
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    mapped_at_creation: false
+                }
+            ));
+        }
+        if let Some(buffer) = &self.instance_buffer {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(decals));
+        }
+    }
+
+    // `inverse_view_proj` has to undo exactly what the main pass drew with, jitter
+    // included, and `viewport` is the main viewport in pixels.
+    pub fn write_uniforms(&self, queue: &Queue, inverse_view_proj: Matrix4<f32>, viewport: (u32, u32, u32, u32))
+    {
+        let (x, y, width, height) = viewport;
+        let uniform = DecalUniform {
+            inverse_view_proj: inverse_view_proj.into(),
+            viewport: [x as f32, y as f32, width as f32, height as f32]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // The depth texture is recreated with the surface, so the bind group is made per
+    // frame.
+    pub fn create_bind_group(&self, device: &Device, depth: &TextureView) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(depth)
+                    }
+                ]
+            }
+        )
+    }
+
+    // Binds the instances at slot 0. False before anything was written.
+    pub fn bind<'a>(&'a self, render_pass: &mut RenderPass<'a>) -> bool
+    {
+        let Some(buffer) = &self.instance_buffer else {
+            return false;
+        };
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+
+        true
+    }
+}
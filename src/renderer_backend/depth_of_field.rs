@@ -0,0 +1,160 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Queue, ShaderStages, TextureSampleType, TextureView, TextureViewDimension};
+
+use crate::state::camera::Camera;
+
+use super::debug_labels::DebugLabels;
+
+// The gather takes about radius squared samples, this keeps it to a few hundred.
+pub const MAX_BLUR_RADIUS: f32 = 16.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthOfFieldOptions {
+    // Distance from the camera that's perfectly sharp, in world units.
+    pub focus_distance: f32,
+    // How quickly things blur away from the focus distance, 0 keeps everything sharp.
+    pub aperture: f32,
+    // In pixels, up to MAX_BLUR_RADIUS.
+    pub max_blur_radius: f32
+}
+
+impl Default for DepthOfFieldOptions {
+    fn default() -> Self
+    {
+        Self {
+            focus_distance: 10.0,
+            aperture: 1.0,
+            max_blur_radius: 8.0
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DepthOfFieldUniform {
+    // focus distance, aperture, largest blur radius
+    params: [f32; 4],
+    // camera near and far plane
+    planes: [f32; 4]
+}
+
+// What the depth of field post effect binds on top of the frame: its options and the
+// depth buffer the main pass left behind.
+pub struct DepthOfField {
+    labels: DebugLabels,
+    options: DepthOfFieldOptions,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout
+}
+
+impl DepthOfField {
+    pub fn new(device: &Device, label: &str, options: DepthOfFieldOptions) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[DepthOfFieldUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            options: Self::clamp_options(options),
+            uniform_buffer
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Depth
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    fn clamp_options(options: DepthOfFieldOptions) -> DepthOfFieldOptions
+    {
+        DepthOfFieldOptions {
+            focus_distance: options.focus_distance.max(0.001),
+            aperture: options.aperture.max(0.0),
+            max_blur_radius: options.max_blur_radius.clamp(1.0, MAX_BLUR_RADIUS)
+        }
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn options(&self) -> DepthOfFieldOptions
+    {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: DepthOfFieldOptions)
+    {
+        self.options = Self::clamp_options(options);
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    // Depth is linearized with the planes of `camera`, the one the main pass used.
+    pub fn write_uniforms(&self, queue: &Queue, camera: &Camera)
+    {
+        let uniform = DepthOfFieldUniform {
+            params: [self.options.focus_distance, self.options.aperture, self.options.max_blur_radius, 0.0],
+            planes: [camera.znear, camera.zfar, 0.0, 0.0]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn create_bind_group(&self, device: &Device, depth: &TextureView) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(depth)
+                    }
+                ]
+            }
+        )
+    }
+}
@@ -0,0 +1,130 @@
+use std::ops::Range;
+
+use wgpu::{BindGroup, Buffer, RenderPass, RenderPipeline};
+
+use super::{assets::Mesh, gpu_allocator::GpuAllocator, instance_buffer::InstanceBatch};
+
+// One instanced draw of a mesh. Group 0 is the material, group 3 the instance data at
+// the batch's dynamic offset; groups 1 and 2 are left to the caller. Indirect draws
+// take their instance count from the buffer at the given offset instead of the batch.
+#[derive(Clone)]
+pub struct InstancedDraw<'a> {
+    pub pipeline: &'a RenderPipeline,
+    pub material: &'a BindGroup,
+    pub mesh: &'a Mesh,
+    pub indices: Range<u32>,
+    pub instances: &'a BindGroup,
+    pub batch: InstanceBatch,
+    pub indirect: Option<(&'a Buffer, u64)>
+}
+
+impl InstancedDraw<'_> {
+    // Only the grouping matters, so resources are ordered by address.
+    fn sort_key(&self) -> (usize, usize, usize, usize, u32, u32, u32)
+    {
+        (
+            self.pipeline as *const _ as usize,
+            self.material as *const _ as usize,
+            self.mesh as *const _ as usize,
+            self.instances as *const _ as usize,
+            self.batch.offset,
+            self.indices.start,
+            self.batch.instances.start
+        )
+    }
+
+    // Same state and index range, and the instances pick up where this draw's end.
+    // How many instances an indirect draw has isn't known until it runs.
+    fn can_merge(&self, next: &Self) -> bool
+    {
+        self.indirect.is_none() && next.indirect.is_none()
+            && std::ptr::eq(self.pipeline, next.pipeline)
+            && std::ptr::eq(self.material, next.material)
+            && std::ptr::eq(self.mesh, next.mesh)
+            && std::ptr::eq(self.instances, next.instances)
+            && self.batch.offset == next.batch.offset
+            && self.indices == next.indices
+            && self.batch.instances.end == next.batch.instances.start
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrawQueueStats {
+    pub submitted: usize,
+    pub recorded: usize,
+    pub pipeline_changes: usize,
+    pub material_changes: usize,
+    pub mesh_changes: usize
+}
+
+// Collects a pass's opaque instanced draws, then records them sorted by pipeline,
+// material and mesh so each is only bound when it changes. Blended draws depend on
+// their order and don't belong in here.
+#[derive(Default)]
+pub struct DrawQueue<'a> {
+    draws: Vec<InstancedDraw<'a>>,
+    submitted: usize
+}
+
+impl<'a> DrawQueue<'a> {
+    pub fn push(&mut self, draw: InstancedDraw<'a>)
+    {
+        self.draws.push(draw);
+        self.submitted += 1;
+    }
+
+    pub fn draws(&self) -> &[InstancedDraw<'a>]
+    {
+        &self.draws
+    }
+
+    pub fn sort_and_merge(&mut self)
+    {
+        self.draws.sort_by_key(InstancedDraw::sort_key);
+
+        let mut merged: Vec<InstancedDraw<'a>> = Vec::with_capacity(self.draws.len());
+        for draw in self.draws.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.can_merge(&draw) => last.batch.instances.end = draw.batch.instances.end,
+                _ => merged.push(draw)
+            }
+        }
+        self.draws = merged;
+    }
+
+    pub fn record(&self, render_pass: &mut RenderPass<'a>, allocator: &'a GpuAllocator) -> DrawQueueStats
+    {
+        let mut stats = DrawQueueStats {
+            submitted: self.submitted,
+            recorded: self.draws.len(),
+            ..Default::default()
+        };
+        let mut bound: Option<&InstancedDraw> = None;
+
+        for draw in &self.draws {
+            if bound.is_none_or(|bound| !std::ptr::eq(bound.pipeline, draw.pipeline)) {
+                render_pass.set_pipeline(draw.pipeline);
+                stats.pipeline_changes += 1;
+            }
+            if bound.is_none_or(|bound| !std::ptr::eq(bound.material, draw.material)) {
+                render_pass.set_bind_group(0, draw.material, &[]);
+                stats.material_changes += 1;
+            }
+            if bound.is_none_or(|bound| !std::ptr::eq(bound.mesh, draw.mesh)) {
+                draw.mesh.bind(render_pass, allocator);
+                stats.mesh_changes += 1;
+            }
+            if bound.is_none_or(|bound| !std::ptr::eq(bound.instances, draw.instances)
+                || bound.batch.offset != draw.batch.offset) {
+                render_pass.set_bind_group(3, draw.instances, &[draw.batch.offset]);
+            }
+            match draw.indirect {
+                Some((buffer, offset)) => render_pass.draw_indexed_indirect(buffer, offset),
+                None => render_pass.draw_indexed(draw.indices.clone(), 0, draw.batch.instances.clone())
+            }
+            bound = Some(draw);
+        }
+
+        stats
+    }
+}
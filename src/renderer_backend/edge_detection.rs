@@ -0,0 +1,176 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroupLayout, BindingResource, Buffer, BufferUsages, CommandEncoder, Device, Queue, SurfaceConfiguration, TextureFormat, TextureUsages, TextureView};
+
+use crate::error::RendererError;
+
+use super::{compute::{create_compute_bind_group, ComputeBindingsBuilder, ComputePass}, debug_labels::DebugLabels, gpu_capabilities::GpuCapabilities, shader_registry::{ShaderHandle, ShaderRegistry}, transient::TransientTextureDesc};
+
+// Matches WORKGROUP_SIZE in edge_detection.wgsl.
+pub const EDGE_WORKGROUP_SIZE: u32 = 8;
+// Matches the storage texture in edge_detection.wgsl. The surface's formats can't be
+// written from a compute shader, so the result is read by the passes after it.
+pub const EDGE_OUTPUT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeDetectionOptions {
+    // What the edges are drawn in, linear.
+    pub color: [f32; 3],
+    // 1 draws the edges in their color, 0 leaves the frame as it was.
+    pub strength: f32,
+    // How much the luma has to change across a pixel before it counts as an edge.
+    pub threshold: f32
+}
+
+impl Default for EdgeDetectionOptions {
+    fn default() -> Self
+    {
+        Self {
+            color: [0.0, 0.0, 0.0],
+            strength: 1.0,
+            threshold: 0.1
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct EdgeDetectionUniform {
+    color: [f32; 4],
+    // strength, threshold
+    params: [f32; 4]
+}
+
+// Sobel edge detection on the rendered frame, in a compute pass that reads the frame
+// and writes the result into a storage texture. It runs before the post effects.
+pub struct EdgeDetection {
+    labels: DebugLabels,
+    options: EdgeDetectionOptions,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    compute_pass: ComputePass
+}
+
+impl EdgeDetection {
+    pub fn is_supported(capabilities: &GpuCapabilities) -> bool
+    {
+        capabilities.supports_compute()
+    }
+
+    pub fn new(
+        device: &Device,
+        shaders: &ShaderRegistry,
+        label: &str,
+        options: EdgeDetectionOptions
+    ) -> Result<Self, RendererError>
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[EdgeDetectionUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let bind_group_layout = ComputeBindingsBuilder::builder()
+            .texture()
+            .storage_texture(EDGE_OUTPUT_FORMAT)
+            .uniform_buffer()
+            .build(device, &labels);
+        let compute_pass = Self::create_compute_pass(device, shaders, &labels, &bind_group_layout)?;
+
+        Ok(Self {
+            labels,
+            options: Self::clamp_options(options),
+            uniform_buffer,
+            bind_group_layout,
+            compute_pass
+        })
+    }
+
+    fn create_compute_pass(
+        device: &Device,
+        shaders: &ShaderRegistry,
+        labels: &DebugLabels,
+        bind_group_layout: &BindGroupLayout
+    ) -> Result<ComputePass, RendererError>
+    {
+        ComputePass::new(device, shaders, labels.name(), ShaderHandle::EdgeDetection,
+            "cs_edge_detection", [EDGE_WORKGROUP_SIZE, EDGE_WORKGROUP_SIZE, 1], &[bind_group_layout])
+    }
+
+    fn clamp_options(options: EdgeDetectionOptions) -> EdgeDetectionOptions
+    {
+        EdgeDetectionOptions {
+            color: options.color.map(|channel| channel.max(0.0)),
+            strength: options.strength.clamp(0.0, 1.0),
+            threshold: options.threshold.max(0.0)
+        }
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn options(&self) -> EdgeDetectionOptions
+    {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: EdgeDetectionOptions)
+    {
+        self.options = Self::clamp_options(options);
+    }
+
+    // After the shader changed, the last good pipeline is kept when it doesn't build.
+    pub fn rebuild(&mut self, device: &Device, shaders: &ShaderRegistry) -> Result<(), RendererError>
+    {
+        self.compute_pass = Self::create_compute_pass(device, shaders, &self.labels,
+            &self.bind_group_layout)?;
+
+        Ok(())
+    }
+
+    pub fn output_target_desc(config: &SurfaceConfiguration) -> TransientTextureDesc
+    {
+        TransientTextureDesc {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            format: EDGE_OUTPUT_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            sample_count: 1
+        }
+    }
+
+    pub fn write_uniforms(&self, queue: &Queue)
+    {
+        let [r, g, b] = self.options.color;
+        let uniform = EdgeDetectionUniform {
+            color: [r, g, b, 1.0],
+            params: [self.options.strength, self.options.threshold, 0.0, 0.0]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // `source` and `output` are both `size` pixels large, `output` one of
+    // output_target_desc.
+    pub fn encode(
+        &self,
+        device: &Device,
+        command_encoder: &mut CommandEncoder,
+        source: &TextureView,
+        output: &TextureView,
+        size: (u32, u32)
+    )
+    {
+        let bind_group = create_compute_bind_group(device, &self.labels, &self.bind_group_layout,
+            vec![
+                BindingResource::TextureView(source),
+                BindingResource::TextureView(output),
+                self.uniform_buffer.as_entire_binding()
+            ]);
+
+        self.compute_pass.encode(command_encoder, &[&bind_group], [size.0, size.1, 1]);
+    }
+}
@@ -0,0 +1,68 @@
+use wgpu::{Device, Error, ErrorFilter};
+
+use crate::error::RendererError;
+
+// Catches the validation and out of memory errors of everything created or submitted
+// on the device between push and pop, which would otherwise only reach the uncaptured
+// error handler, and returns them as a RendererError naming what was being done.
+// Scopes nest, the innermost one catches the error, so they have to be popped on the
+// device they were pushed on in reverse order.
+#[must_use = "an error scope catches nothing until it's popped"]
+pub struct ErrorScope {
+    label: String
+}
+
+impl ErrorScope {
+    pub fn push(device: &Device, label: &str) -> Self
+    {
+        device.push_error_scope(ErrorFilter::OutOfMemory);
+        device.push_error_scope(ErrorFilter::Validation);
+
+        Self {
+            label: String::from(label)
+        }
+    }
+
+    pub async fn pop(self, device: &Device) -> Result<(), RendererError>
+    {
+        let validation = device.pop_error_scope();
+        let out_of_memory = device.pop_error_scope();
+
+        Self::check(self.label, validation.await.or(out_of_memory.await))
+    }
+
+    // For synchronous callers. Native backends know their errors right away, so this
+    // returns them. The browser only answers later, so on the web they're logged once
+    // it does and this always succeeds.
+    pub fn pop_now(self, device: &Device) -> Result<(), RendererError>
+    {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                let validation = device.pop_error_scope();
+                let out_of_memory = device.pop_error_scope();
+                let label = self.label;
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Err(e) = Self::check(label, validation.await.or(out_of_memory.await)) {
+                        log::error!("{e}");
+                    }
+                });
+
+                Ok(())
+            } else {
+                pollster::block_on(self.pop(device))
+            }
+        }
+    }
+
+    fn check(label: String, error: Option<Error>) -> Result<(), RendererError>
+    {
+        match error {
+            None => Ok(()),
+            Some(Error::OutOfMemory { .. }) => Err(RendererError::GpuOutOfMemory { label }),
+            Some(Error::Validation { description, .. }) => Err(RendererError::GpuValidation {
+                label,
+                message: description
+            })
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Queue, ShaderStages, TextureSampleType, TextureView, TextureViewDimension};
+
+use crate::state::camera::Camera;
+
+use super::debug_labels::DebugLabels;
+
+// Preetham's model holds up for a clear sky at 2 to a hazy one at about 10.
+pub const MIN_TURBIDITY: f32 = 2.0;
+pub const MAX_TURBIDITY: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyOptions {
+    // Towards the sun, in world space.
+    pub sun_direction: Vector3<f32>,
+    // How hazy the air is, from MIN_TURBIDITY to MAX_TURBIDITY.
+    pub turbidity: f32
+}
+
+impl Default for SkyOptions {
+    fn default() -> Self
+    {
+        Self {
+            sun_direction: Vector3::new(0.4, 0.5, 0.3),
+            turbidity: 2.5
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogOptions {
+    // Linear RGB, not used for the fog with the sky model on.
+    pub color: [f32; 3],
+    // Exponential fog per world unit past the start distance, 0 leaves only the linear
+    // fog.
+    pub density: f32,
+    // Distance from the camera where fog begins.
+    pub start: f32,
+    // Distance where the linear fog is complete.
+    pub end: f32,
+    // Where the exponential fog has its density.
+    pub height: f32,
+    // How quickly it thins out above that height, 0 keeps it the same at every height.
+    pub height_falloff: f32,
+    // Paints a daylight sky where nothing was drawn, instead of the clear color, and
+    // fades into it instead of the fog color.
+    pub sky: Option<SkyOptions>
+}
+
+impl Default for FogOptions {
+    fn default() -> Self
+    {
+        Self {
+            color: [0.6, 0.65, 0.7],
+            density: 0.02,
+            start: 5.0,
+            end: 200.0,
+            height: 0.0,
+            height_falloff: 0.0,
+            sky: None
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct FogUniform {
+    inverse_view_proj: [[f32; 4]; 4],
+    // camera position, height fog base height
+    eye: [f32; 4],
+    // fog color, 1 in alpha with the sky model on
+    color: [f32; 4],
+    // density, start, end, height falloff
+    params: [f32; 4],
+    // direction towards the sun, turbidity
+    sun: [f32; 4],
+    // the main viewport in pixels
+    viewport: [f32; 4]
+}
+
+// What the fog post effect binds on top of the frame: its options and the depth
+// buffer, from which it works out where every pixel is in the world.
+pub struct Fog {
+    labels: DebugLabels,
+    options: FogOptions,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout
+}
+
+impl Fog {
+    pub fn new(device: &Device, label: &str, options: FogOptions) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[FogUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            options: Self::clamp_options(options),
+            uniform_buffer
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Depth
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    fn clamp_options(options: FogOptions) -> FogOptions
+    {
+        let start = options.start.max(0.0);
+
+        FogOptions {
+            color: options.color,
+            density: options.density.max(0.0),
+            start,
+            end: options.end.max(start),
+            height: options.height,
+            height_falloff: options.height_falloff.max(0.0),
+            sky: options.sky.map(|sky| SkyOptions {
+                sun_direction: if sky.sun_direction.magnitude2() > 0.0 {
+                    sky.sun_direction.normalize()
+                } else {
+                    Vector3::unit_y()
+                },
+                turbidity: sky.turbidity.clamp(MIN_TURBIDITY, MAX_TURBIDITY)
+            })
+        }
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn options(&self) -> FogOptions
+    {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: FogOptions)
+    {
+        self.options = Self::clamp_options(options);
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    // `viewport` is the main viewport in pixels, the camera the one it's drawn with.
+    pub fn write_uniforms(&self, queue: &Queue, camera: &Camera, viewport: (u32, u32, u32, u32))
+    {
+        let options = &self.options;
+        let sky = options.sky.unwrap_or_default();
+        let (x, y, width, height) = viewport;
+        let uniform = FogUniform {
+            inverse_view_proj: camera.build_view_projection_matrix()
+                .invert()
+                .unwrap_or_else(Matrix4::identity)
+                .into(),
+            eye: [camera.eye.x, camera.eye.y, camera.eye.z, options.height],
+            color: [options.color[0], options.color[1], options.color[2],
+                if options.sky.is_some() { 1.0 } else { 0.0 }],
+            params: [options.density, options.start, options.end, options.height_falloff],
+            sun: [sky.sun_direction.x, sky.sun_direction.y, sky.sun_direction.z, sky.turbidity],
+            viewport: [x as f32, y as f32, width as f32, height as f32]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn create_bind_group(&self, device: &Device, depth: &TextureView) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(depth)
+                    }
+                ]
+            }
+        )
+    }
+}
@@ -0,0 +1,144 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Queue, ShaderStages};
+
+use super::debug_labels::DebugLabels;
+
+// The gather takes the same number of samples at any radius, past this they spread
+// too thin.
+pub const MAX_RADIUS: f32 = 64.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlowOptions {
+    // Brightness, as the largest channel, above which things glow. Only an HDR surface
+    // (SurfaceOptions::prefer_hdr) keeps colors past 1, with one a threshold of 1 leaves
+    // everything but bright emissive surfaces out.
+    pub threshold: f32,
+    // How much of the glow is added to the frame.
+    pub intensity: f32,
+    // How far it spreads, in pixels up to MAX_RADIUS.
+    pub radius: f32
+}
+
+impl Default for GlowOptions {
+    fn default() -> Self
+    {
+        Self {
+            threshold: 0.8,
+            intensity: 1.0,
+            radius: 16.0
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct GlowUniform {
+    // threshold, soft knee, intensity, radius
+    params: [f32; 4]
+}
+
+// What the glow post effect binds on top of the frame, its options.
+pub struct Glow {
+    labels: DebugLabels,
+    options: GlowOptions,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout
+}
+
+impl Glow {
+    pub fn new(device: &Device, label: &str, options: GlowOptions) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[GlowUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            options: Self::clamp_options(options),
+            uniform_buffer
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    fn clamp_options(options: GlowOptions) -> GlowOptions
+    {
+        GlowOptions {
+            threshold: options.threshold.max(0.0),
+            intensity: options.intensity.max(0.0),
+            radius: options.radius.clamp(1.0, MAX_RADIUS)
+        }
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn options(&self) -> GlowOptions
+    {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: GlowOptions)
+    {
+        self.options = Self::clamp_options(options);
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    // The knee is half the threshold, enough that things don't pop as they cross it.
+    pub fn write_uniforms(&self, queue: &Queue)
+    {
+        let uniform = GlowUniform {
+            params: [self.options.threshold, self.options.threshold * 0.5, self.options.intensity,
+                self.options.radius]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn create_bind_group(&self, device: &Device) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    }
+                ]
+            }
+        )
+    }
+}
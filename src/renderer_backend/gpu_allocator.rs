@@ -0,0 +1,278 @@
+use std::{iter, ops::Range};
+
+use wgpu::{Buffer, BufferAddress, BufferBinding, BufferDescriptor, BufferSize, BufferSlice, BufferUsages, Device, Queue, COPY_BUFFER_ALIGNMENT};
+
+use super::debug_labels::DebugLabels;
+
+pub const DEFAULT_BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+
+// Each kind is carved out of its own blocks, as a buffer's usage is fixed at creation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AllocationKind {
+    Vertex,
+    Index,
+    Uniform
+}
+
+impl AllocationKind {
+    const ALL: [AllocationKind; 3] = [
+        AllocationKind::Vertex,
+        AllocationKind::Index,
+        AllocationKind::Uniform
+    ];
+
+    fn usage(&self) -> BufferUsages
+    {
+        match self {
+            AllocationKind::Vertex => BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            AllocationKind::Index => BufferUsages::INDEX | BufferUsages::COPY_DST,
+            AllocationKind::Uniform => BufferUsages::UNIFORM | BufferUsages::COPY_DST
+        }
+    }
+
+    fn label(&self) -> &'static str
+    {
+        match self {
+            AllocationKind::Vertex => "Vertex Block",
+            AllocationKind::Index => "Index Block",
+            AllocationKind::Uniform => "Uniform Block"
+        }
+    }
+}
+
+// A range of one of the allocator's buffers. Deliberately not Clone, so a range can
+// only be freed once.
+#[derive(Debug)]
+pub struct GpuAllocation {
+    kind: AllocationKind,
+    block: usize,
+    range: Range<BufferAddress>,
+    generation: u64
+}
+
+impl GpuAllocation {
+    pub fn size(&self) -> BufferAddress
+    {
+        self.range.end - self.range.start
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GpuAllocatorStats {
+    pub blocks: usize,
+    pub allocations: usize,
+    pub allocated_bytes: u64,
+    pub capacity_bytes: u64
+}
+
+struct Block {
+    buffer: Buffer,
+    // Sorted and never adjacent, neighbours are merged when a range is freed.
+    free: Vec<Range<BufferAddress>>
+}
+
+// Sub-allocates vertex, index and uniform ranges from a few large buffers instead of
+// creating a buffer per mesh. Ranges are placed first fit and merged with their free
+// neighbours on release; a block that ends up entirely free is destroyed.
+pub struct GpuAllocator {
+    labels: DebugLabels,
+    block_size: u64,
+    // Indexed by AllocationKind, a released block leaves a None so handles stay valid.
+    blocks: [Vec<Option<Block>>; 3],
+    allocations: usize,
+    // Bumped by clear(), so handles from before a device loss are ignored.
+    generation: u64
+}
+
+impl GpuAllocator {
+    pub fn new(label: &str, block_size: u64) -> Self
+    {
+        Self {
+            labels: DebugLabels::new(label),
+            block_size,
+            blocks: Default::default(),
+            allocations: 0,
+            generation: 0
+        }
+    }
+
+    pub fn allocate(&mut self, device: &Device, kind: AllocationKind, size: u64) -> GpuAllocation
+    {
+        let alignment = match kind {
+            AllocationKind::Uniform => device.limits().min_uniform_buffer_offset_alignment as u64,
+            _ => COPY_BUFFER_ALIGNMENT
+        };
+        let size = size.max(1).next_multiple_of(COPY_BUFFER_ALIGNMENT);
+        let blocks = &mut self.blocks[kind as usize];
+
+        let found = blocks.iter_mut()
+            .enumerate()
+            .filter_map(|(index, block)| block.as_mut().map(|block| (index, block)))
+            .find_map(|(index, block)| Self::take_range(block, size, alignment)
+                .map(|range| (index, range)));
+        let (block, range) = found.unwrap_or_else(|| {
+            let capacity = self.block_size.max(size);
+            let mut block = Block {
+                buffer: device.create_buffer(
+                    &BufferDescriptor {
+                        label: Some(&self.labels.with_suffix(kind.label())),
+                        size: capacity,
+                        usage: kind.usage(),
+                        mapped_at_creation: false
+                    }
+                ),
+                free: iter::once(0..capacity).collect()
+            };
+            let range = Self::take_range(&mut block, size, alignment)
+                .expect("a new block fits the allocation");
+
+            let index = blocks.iter().position(Option::is_none).unwrap_or(blocks.len());
+            if index == blocks.len() {
+                blocks.push(None);
+            }
+            blocks[index] = Some(block);
+
+            (index, range)
+        });
+
+        self.allocations += 1;
+
+        GpuAllocation {
+            kind,
+            block,
+            range,
+            generation: self.generation
+        }
+    }
+
+    // Allocates and uploads `contents`, padded to the copy alignment.
+    pub fn allocate_init(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        kind: AllocationKind,
+        contents: &[u8]
+    ) -> GpuAllocation
+    {
+        let allocation = self.allocate(device, kind, contents.len() as u64);
+        self.write(queue, &allocation, contents);
+
+        allocation
+    }
+
+    pub fn write(&self, queue: &Queue, allocation: &GpuAllocation, data: &[u8])
+    {
+        let Some(block) = self.block(allocation) else {
+            return;
+        };
+
+        if (data.len() as u64).is_multiple_of(COPY_BUFFER_ALIGNMENT) {
+            queue.write_buffer(&block.buffer, allocation.range.start, data);
+        } else {
+            let mut padded = data.to_vec();
+            padded.resize((data.len() as u64).next_multiple_of(COPY_BUFFER_ALIGNMENT) as usize, 0);
+            queue.write_buffer(&block.buffer, allocation.range.start, &padded);
+        }
+    }
+
+    pub fn free(&mut self, allocation: GpuAllocation)
+    {
+        if allocation.generation != self.generation {
+            return;
+        }
+        let slot = &mut self.blocks[allocation.kind as usize][allocation.block];
+        let Some(block) = slot else {
+            return;
+        };
+
+        let index = block.free.partition_point(|free| free.start < allocation.range.start);
+        block.free.insert(index, allocation.range);
+        if index + 1 < block.free.len() && block.free[index].end == block.free[index + 1].start {
+            block.free[index].end = block.free.remove(index + 1).end;
+        }
+        if index > 0 && block.free[index - 1].end == block.free[index].start {
+            block.free[index - 1].end = block.free.remove(index).end;
+        }
+        self.allocations -= 1;
+
+        if block.free.len() == 1 && block.free[0] == (0..block.buffer.size()) {
+            block.buffer.destroy();
+            *slot = None;
+        }
+    }
+
+    // The buffers belong to the device, after a device loss everything is allocated anew.
+    pub fn clear(&mut self)
+    {
+        self.blocks = Default::default();
+        self.allocations = 0;
+        self.generation += 1;
+    }
+
+    // Panics on a handle from before clear(), like using a destroyed buffer would.
+    pub fn slice(&self, allocation: &GpuAllocation) -> BufferSlice<'_>
+    {
+        let block = self.block(allocation).expect("the allocation was freed");
+
+        block.buffer.slice(allocation.range.clone())
+    }
+
+    pub fn binding(&self, allocation: &GpuAllocation) -> BufferBinding<'_>
+    {
+        let block = self.block(allocation).expect("the allocation was freed");
+
+        BufferBinding {
+            buffer: &block.buffer,
+            offset: allocation.range.start,
+            size: BufferSize::new(allocation.size())
+        }
+    }
+
+    pub fn stats(&self) -> GpuAllocatorStats
+    {
+        let blocks = || AllocationKind::ALL.iter()
+            .flat_map(|kind| self.blocks[*kind as usize].iter().flatten());
+        let capacity_bytes = blocks().map(|block| block.buffer.size()).sum::<u64>();
+        let free_bytes = blocks()
+            .flat_map(|block| block.free.iter())
+            .map(|free| free.end - free.start)
+            .sum::<u64>();
+
+        GpuAllocatorStats {
+            blocks: blocks().count(),
+            allocations: self.allocations,
+            allocated_bytes: capacity_bytes - free_bytes,
+            capacity_bytes
+        }
+    }
+
+    fn block(&self, allocation: &GpuAllocation) -> Option<&Block>
+    {
+        if allocation.generation != self.generation {
+            return None;
+        }
+
+        self.blocks[allocation.kind as usize].get(allocation.block)?.as_ref()
+    }
+
+    // First fit. The padding in front of an aligned range stays free.
+    fn take_range(block: &mut Block, size: u64, alignment: u64) -> Option<Range<BufferAddress>>
+    {
+        let (index, start) = block.free.iter()
+            .enumerate()
+            .map(|(index, free)| (index, free.start.next_multiple_of(alignment)))
+            .find(|(index, start)| start + size <= block.free[*index].end)?;
+
+        let free = block.free.remove(index);
+        let mut position = index;
+        if free.start < start {
+            block.free.insert(position, free.start..start);
+            position += 1;
+        }
+        if start + size < free.end {
+            block.free.insert(position, start + size..free.end);
+        }
+
+        Some(start..start + size)
+    }
+}
@@ -0,0 +1,121 @@
+use wgpu::{Adapter, AdapterInfo, Backend, Device, DownlevelFlags, Features, Limits, Surface, SurfaceConfiguration, TextureFormat, TextureUsages};
+
+use super::{debug_view::DebugView, gpu_profiler::GpuProfiler, ssao::OCCLUSION_FORMAT, taa::MOTION_VECTOR_FORMAT, texture::Texture};
+
+// The render targets the renderer makes besides the surface's, checked up front.
+const TARGET_FORMATS: [TextureFormat; 5] = [
+    Texture::DEPTH_FORMAT,
+    Texture::DEPTH_STENCIL_FORMAT,
+    MOTION_VECTOR_FORMAT,
+    OCCLUSION_FORMAT,
+    TextureFormat::Rgba16Float
+];
+
+// What the adapter and device turned out to support, gathered whenever the device is
+// created. Subsystems needing more than the baseline check it when they're turned on
+// and stay off with a warning, instead of failing when their pipelines are built.
+#[derive(Debug, Clone)]
+pub struct GpuCapabilities {
+    pub adapter: AdapterInfo,
+    // The ones enabled on the device, not everything the adapter has.
+    pub features: Features,
+    pub limits: Limits,
+    pub downlevel: DownlevelFlags,
+    // The surface's, in its order of preference. Only the configured format when headless.
+    pub surface_formats: Vec<TextureFormat>,
+    // MSAA sample counts the surface format and the depth format both take.
+    pub sample_counts: Vec<u32>,
+    // Those of TARGET_FORMATS that can be rendered to.
+    pub target_formats: Vec<TextureFormat>
+}
+
+impl GpuCapabilities {
+    // Requested when the adapter has them, whatever needs them is skipped without.
+    pub fn optional_features() -> Features
+    {
+        GpuProfiler::required_features() | DebugView::optional_features()
+    }
+
+    pub fn new(adapter: &Adapter, device: &Device, surface: Option<&Surface>, config: &SurfaceConfiguration) -> Self
+    {
+        let surface_formats = match surface {
+            Some(surface) => surface.get_capabilities(adapter).formats,
+            None => vec![config.format]
+        };
+        let color_flags = adapter.get_texture_format_features(config.format).flags;
+        let depth_flags = adapter.get_texture_format_features(Texture::DEPTH_FORMAT).flags;
+        let sample_counts = color_flags.supported_sample_counts()
+            .into_iter()
+            .filter(|&count| depth_flags.sample_count_supported(count))
+            .collect();
+        let target_formats = TARGET_FORMATS.into_iter()
+            .filter(|&format| adapter.get_texture_format_features(format).allowed_usages
+                .contains(TextureUsages::RENDER_ATTACHMENT))
+            .collect();
+
+        Self {
+            adapter: adapter.get_info(),
+            features: device.features(),
+            limits: device.limits(),
+            downlevel: adapter.get_downlevel_capabilities().flags,
+            surface_formats,
+            sample_counts,
+            target_formats
+        }
+    }
+
+    // On the web, BrowserWebGpu or Gl for WebGL2, whichever the browser could give.
+    pub fn backend(&self) -> Backend
+    {
+        self.adapter.backend
+    }
+
+    // GL copies textures through a framebuffer's color read buffer, which depth isn't.
+    pub fn can_copy_depth(&self) -> bool
+    {
+        self.backend() != Backend::Gl
+    }
+
+    pub fn preferred_format(&self) -> Option<TextureFormat>
+    {
+        self.surface_formats.first().copied()
+    }
+
+    pub fn supports_compute(&self) -> bool
+    {
+        self.downlevel.contains(DownlevelFlags::COMPUTE_SHADERS)
+    }
+
+    pub fn supports_indirect_draws(&self) -> bool
+    {
+        self.downlevel.contains(DownlevelFlags::INDIRECT_EXECUTION)
+    }
+
+    pub fn supports_sample_count(&self, count: u32) -> bool
+    {
+        self.sample_counts.contains(&count)
+    }
+
+    pub fn can_render_to(&self, format: TextureFormat) -> bool
+    {
+        self.target_formats.contains(&format)
+    }
+
+    pub fn log(&self)
+    {
+        log::info!("Adapter: {} ({:?}, {:?})", self.adapter.name, self.adapter.backend,
+            self.adapter.device_type);
+        log::info!("Max texture size {}, bind groups {}, storage buffers per stage {}, workgroup size {}",
+            self.limits.max_texture_dimension_2d, self.limits.max_bind_groups,
+            self.limits.max_storage_buffers_per_shader_stage, self.limits.max_compute_invocations_per_workgroup);
+        log::info!("Compute shaders: {}, indirect draws: {}, MSAA sample counts: {:?}",
+            self.supports_compute(), self.supports_indirect_draws(), self.sample_counts);
+        log::info!("Preferred format: {:?}, render targets: {:?}", self.preferred_format(),
+            self.target_formats);
+
+        let missing = Self::optional_features() - self.features;
+        if !missing.is_empty() {
+            log::info!("Missing optional features: {missing:?}");
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Angle, Deg, InnerSpace, Matrix, Matrix4};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, ComputePass, Device, Queue, ShaderStages};
+
+use crate::state::{bounds::Aabb, camera::Camera, instance::InstanceRaw};
+
+use super::{debug_labels::DebugLabels, gpu_capabilities::GpuCapabilities, instance_buffer::InstanceBatch};
+
+// Matches WORKGROUP_SIZE in cull.wgsl.
+pub const CULL_WORKGROUP_SIZE: u32 = 64;
+
+const INSTANCE_SIZE: u64 = std::mem::size_of::<InstanceRaw>() as u64;
+const DRAW_ARGS_SIZE: u64 = std::mem::size_of::<DrawArgs>() as u64;
+
+// Matches CullUniform in cull.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct CullUniform {
+    // left, right, bottom, top, near, far, normals pointing inside
+    planes: [[f32; 4]; 6],
+    // xyz the camera's position, w the cosine of the view cone's half angle
+    cone_apex: [f32; 4],
+    // xyz the view direction, w the sine of the view cone's half angle
+    cone_axis: [f32; 4],
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+    wind: [f32; 4],
+    // x the instance count
+    params: [u32; 4]
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct CullInstance {
+    draw: u32,
+    output_start: u32
+}
+
+// What draw_indexed_indirect reads, the compute pass counts instance_count up.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DrawArgs {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32
+}
+
+// One instanced draw to cull: where its instances are in the input buffer and the
+// indices of the LOD level it draws.
+#[derive(Debug, Clone)]
+pub struct CullDraw {
+    pub instances: Range<u32>,
+    pub indices: Range<u32>
+}
+
+// Where a culled draw finds its visible instances, and the offset of its arguments in
+// the indirect buffer. `batch.instances` is how many there are at most.
+#[derive(Debug, Clone)]
+pub struct CulledDraw {
+    pub batch: InstanceBatch,
+    pub args_offset: u64
+}
+
+// Frustum culling of the instances on the GPU. A compute pass tests every instance's
+// bounds against the camera's view cone and frustum and copies the visible ones into
+// a compacted buffer, counting them in the indirect arguments of the draw they belong
+// to. Every draw's visible instances start at a dynamic offset of their own, so they
+// bind the same way InstanceBuffer's batches do. The buffers only ever grow.
+pub struct GpuCulling {
+    labels: DebugLabels,
+    bind_group_layout: BindGroupLayout,
+    uniform_buffer: Buffer,
+    cull_instance_buffer: Buffer,
+    visible_buffer: Buffer,
+    args_buffer: Buffer,
+    // Covers the largest draw's instances, the dynamic offset picks the draw.
+    visible_bind_group: BindGroup,
+    visible_binding_instances: u64,
+    compute_bind_group: Option<BindGroup>,
+    draws: Vec<CulledDraw>,
+    num_instances: u32
+}
+
+impl GpuCulling {
+    // The vertex stage has to read the compacted instances from a storage buffer, and
+    // WebGL2 has neither compute shaders nor indirect draws.
+    pub fn is_supported(capabilities: &GpuCapabilities) -> bool
+    {
+        capabilities.supports_compute() && capabilities.supports_indirect_draws()
+            && capabilities.limits.max_storage_buffers_per_shader_stage >= 4
+    }
+
+    pub fn new(device: &Device, label: &str, instance_layout: &BindGroupLayout) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix("Uniform Buffer")),
+                contents: bytemuck::cast_slice(&[CullUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let cull_instance_buffer = Self::create_buffer(device, &labels.with_suffix("Input Buffer"),
+            std::mem::size_of::<CullInstance>() as u64, BufferUsages::STORAGE | BufferUsages::COPY_DST);
+        let visible_buffer = Self::create_buffer(device, &labels.buffer(), INSTANCE_SIZE,
+            BufferUsages::STORAGE);
+        let args_buffer = Self::create_buffer(device, &labels.with_suffix("Indirect Buffer"),
+            DRAW_ARGS_SIZE, BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST);
+        let visible_bind_group = Self::create_visible_bind_group(device, &labels, instance_layout,
+            &visible_buffer, 1);
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            uniform_buffer,
+            cull_instance_buffer,
+            visible_buffer,
+            args_buffer,
+            visible_bind_group,
+            visible_binding_instances: 1,
+            compute_bind_group: None,
+            draws: Vec::new(),
+            num_instances: 0
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None
+            },
+            count: None
+        };
+
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    storage(1, true),
+                    storage(2, true),
+                    storage(3, false),
+                    storage(4, false)
+                ]
+            }
+        )
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    pub fn num_instances(&self) -> u32
+    {
+        self.num_instances
+    }
+
+    // In the order they were written.
+    pub fn draws(&self) -> &[CulledDraw]
+    {
+        &self.draws
+    }
+
+    // Bound at group 3 in place of the instance buffer, at each draw's batch offset.
+    pub fn visible_bind_group(&self) -> &BindGroup
+    {
+        &self.visible_bind_group
+    }
+
+    pub fn args_buffer(&self) -> &Buffer
+    {
+        &self.args_buffer
+    }
+
+    // `input` holds every draw's instances, as InstanceBuffer wrote them. It may have
+    // been reallocated since the last frame, so the compute bind group is made anew.
+    pub fn write(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        instance_layout: &BindGroupLayout,
+        input: &Buffer,
+        draws: &[CullDraw]
+    )
+    {
+        // Each draw's output starts on an offset the instance bind group can be bound at.
+        let alignment = (device.limits().min_storage_buffer_offset_alignment as u64)
+            .div_ceil(INSTANCE_SIZE)
+            .max(1);
+        let mut cull_instances = Vec::new();
+        let mut args = Vec::with_capacity(draws.len());
+        let mut output_end = 0u64;
+        let mut largest = 1u64;
+        self.draws.clear();
+        for (index, draw) in draws.iter().enumerate() {
+            let output_start = output_end.div_ceil(alignment) * alignment;
+            let count = draw.instances.len() as u64;
+            cull_instances.resize(draw.instances.end as usize, CullInstance::zeroed());
+            for cull_instance in &mut cull_instances[draw.instances.start as usize..] {
+                *cull_instance = CullInstance { draw: index as u32, output_start: output_start as u32 };
+            }
+            args.push(DrawArgs {
+                index_count: draw.indices.len() as u32,
+                instance_count: 0,
+                first_index: draw.indices.start,
+                base_vertex: 0,
+                first_instance: 0
+            });
+            self.draws.push(CulledDraw {
+                batch: InstanceBatch {
+                    instances: 0..count as u32,
+                    offset: (output_start * INSTANCE_SIZE) as u32
+                },
+                args_offset: index as u64 * DRAW_ARGS_SIZE
+            });
+            output_end = output_start + count;
+            largest = largest.max(count);
+        }
+        self.num_instances = cull_instances.len() as u32;
+        if args.is_empty() {
+            self.compute_bind_group = None;
+            return;
+        }
+
+        let cull_instance_size = std::mem::size_of_val(cull_instances.as_slice()) as u64;
+        if cull_instance_size > self.cull_instance_buffer.size() {
+            self.cull_instance_buffer = Self::create_buffer(device, &self.labels.with_suffix("Input Buffer"),
+                cull_instance_size.next_power_of_two(), BufferUsages::STORAGE | BufferUsages::COPY_DST);
+        }
+        // The last draw's binding reaches past its own instances, up to the largest's size.
+        let binding_instances = largest.next_power_of_two();
+        let visible_size = (output_end.div_ceil(alignment) * alignment + binding_instances) * INSTANCE_SIZE;
+        let mut visible_changed = binding_instances != self.visible_binding_instances;
+        if visible_size > self.visible_buffer.size() {
+            self.visible_buffer = Self::create_buffer(device, &self.labels.buffer(),
+                visible_size.next_power_of_two(), BufferUsages::STORAGE);
+            visible_changed = true;
+        }
+        if visible_changed {
+            self.visible_bind_group = Self::create_visible_bind_group(device, &self.labels,
+                instance_layout, &self.visible_buffer, binding_instances);
+            self.visible_binding_instances = binding_instances;
+        }
+        let args_size = std::mem::size_of_val(args.as_slice()) as u64;
+        if args_size > self.args_buffer.size() {
+            self.args_buffer = Self::create_buffer(device, &self.labels.with_suffix("Indirect Buffer"),
+                args_size.next_power_of_two(),
+                BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST);
+        }
+
+        queue.write_buffer(&self.cull_instance_buffer, 0, bytemuck::cast_slice(&cull_instances));
+        queue.write_buffer(&self.args_buffer, 0, bytemuck::cast_slice(&args));
+        self.compute_bind_group = Some(self.create_compute_bind_group(device, input));
+    }
+
+    // Culls against `camera`'s unjittered view. `mesh_bounds` is the instance mesh's
+    // object space box and `wind` the vertex animation's, so swaying instances aren't
+    // cut off at the edges.
+    pub fn write_view(&self, queue: &Queue, camera: &Camera, mesh_bounds: &Aabb, wind: [f32; 4])
+    {
+        let axis = (camera.target - camera.eye).normalize();
+        // The cone goes through the frustum's corners.
+        let tan_y = Deg(camera.fovy / 2.0).tan();
+        let half_angle = (tan_y * (1.0 + camera.aspect * camera.aspect).sqrt()).atan();
+        let uniform = CullUniform {
+            planes: frustum_planes(camera.build_view_projection_matrix()),
+            cone_apex: [camera.eye.x, camera.eye.y, camera.eye.z, half_angle.cos()],
+            cone_axis: [axis.x, axis.y, axis.z, half_angle.sin()],
+            bounds_min: mesh_bounds.min.to_homogeneous().into(),
+            bounds_max: mesh_bounds.max.to_homogeneous().into(),
+            wind,
+            params: [self.num_instances, 0, 0, 0]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Expects the cull pipeline to be set already.
+    pub fn dispatch<'a>(&'a self, compute_pass: &mut ComputePass<'a>)
+    {
+        let Some(bind_group) = &self.compute_bind_group else {
+            return;
+        };
+
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.num_instances.div_ceil(CULL_WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn create_buffer(device: &Device, label: &str, size: u64, usage: BufferUsages) -> Buffer
+    {
+        device.create_buffer(
+            &BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false
+            }
+        )
+    }
+
+    fn create_visible_bind_group(
+        device: &Device,
+        labels: &DebugLabels,
+        instance_layout: &BindGroupLayout,
+        buffer: &Buffer,
+        instances: u64
+    ) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.bind_group()),
+                layout: instance_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer,
+                            offset: 0,
+                            size: BufferSize::new(instances * INSTANCE_SIZE)
+                        })
+                    }
+                ]
+            }
+        )
+    }
+
+    fn create_compute_bind_group(&self, device: &Device, input: &Buffer) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.with_suffix("Compute Bind Group")),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: input.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: self.cull_instance_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: self.visible_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: self.args_buffer.as_entire_binding()
+                    }
+                ]
+            }
+        )
+    }
+}
+
+// The planes bounding what `view_proj` sees, normalized with their normals pointing
+// inside. wgpu's depth goes from 0 to 1, so the near plane is the third row alone.
+pub fn frustum_planes(view_proj: Matrix4<f32>) -> [[f32; 4]; 6]
+{
+    let row = |index| view_proj.row(index);
+    let planes = [
+        row(3) + row(0),
+        row(3) - row(0),
+        row(3) + row(1),
+        row(3) - row(1),
+        row(2),
+        row(3) - row(2)
+    ];
+
+    planes.map(|plane| (plane / plane.truncate().magnitude().max(f32::EPSILON)).into())
+}
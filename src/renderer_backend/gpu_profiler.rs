@@ -0,0 +1,169 @@
+use std::sync::{Arc, Mutex};
+
+use wgpu::{Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder, Device, Features, MapMode, Maintain, QuerySet, QuerySetDescriptor, QueryType, Queue, RenderPassTimestampWrites};
+use web_time::Duration;
+
+const MAX_PASSES: u32 = 16;
+const QUERY_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+#[derive(Debug, Clone)]
+pub struct GpuTiming {
+    pub label: String,
+    pub duration: Duration
+}
+
+// Writes a begin/end timestamp pair per render pass and reads the results back
+// a few frames later without stalling. Frames recorded while the previous
+// readback is still mapped aren't profiled.
+pub struct GpuProfiler {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    timestamp_period: f32,
+    frame_passes: Vec<String>,
+    pending_passes: Vec<String>,
+    readback_busy: bool,
+    readback_result: Arc<Mutex<Option<Result<(), BufferAsyncError>>>>,
+    timings: Vec<GpuTiming>
+}
+
+impl GpuProfiler {
+    pub fn required_features() -> Features
+    {
+        Features::TIMESTAMP_QUERY
+    }
+
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self>
+    {
+        if !device.features().contains(Self::required_features()) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(
+            &QuerySetDescriptor {
+                label: Some("GPU Profiler Query Set"),
+                ty: QueryType::Timestamp,
+                count: MAX_PASSES * 2
+            }
+        );
+        let buffer_size = MAX_PASSES as u64 * 2 * QUERY_SIZE;
+        let resolve_buffer = device.create_buffer(
+            &BufferDescriptor {
+                label: Some("GPU Profiler Resolve Buffer"),
+                size: buffer_size,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false
+            }
+        );
+        let readback_buffer = device.create_buffer(
+            &BufferDescriptor {
+                label: Some("GPU Profiler Readback Buffer"),
+                size: buffer_size,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false
+            }
+        );
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            timestamp_period: queue.get_timestamp_period(),
+            frame_passes: Vec::new(),
+            pending_passes: Vec::new(),
+            readback_busy: false,
+            readback_result: Arc::new(Mutex::new(None)),
+            timings: Vec::new()
+        })
+    }
+
+    pub fn begin_frame(&mut self)
+    {
+        self.frame_passes.clear();
+    }
+
+    pub fn timestamp_writes(&mut self, label: &str) -> Option<RenderPassTimestampWrites<'_>>
+    {
+        if self.readback_busy || self.frame_passes.len() as u32 >= MAX_PASSES {
+            return None;
+        }
+
+        let index = self.frame_passes.len() as u32;
+        self.frame_passes.push(String::from(label));
+
+        Some(RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1)
+        })
+    }
+
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder)
+    {
+        if self.readback_busy || self.frame_passes.is_empty() {
+            return;
+        }
+
+        let query_count = self.frame_passes.len() as u32 * 2;
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0,
+            query_count as u64 * QUERY_SIZE);
+    }
+
+    // Call after the frame's command buffer has been submitted.
+    pub fn end_frame(&mut self, device: &Device)
+    {
+        if !self.readback_busy && !self.frame_passes.is_empty() {
+            self.readback_busy = true;
+            self.pending_passes = std::mem::take(&mut self.frame_passes);
+
+            let readback_result = self.readback_result.clone();
+            self.readback_buffer.slice(..).map_async(MapMode::Read, move |result| {
+                if let Ok(mut readback_result) = readback_result.lock() {
+                    *readback_result = Some(result);
+                }
+            });
+        }
+
+        device.poll(Maintain::Poll);
+
+        let result = self.readback_result.lock()
+            .ok()
+            .and_then(|mut readback_result| readback_result.take());
+        match result {
+            Some(Ok(())) => {
+                self.read_timings();
+                self.readback_buffer.unmap();
+                self.readback_busy = false;
+            },
+            Some(Err(e)) => {
+                log::warn!("Couldn't read back GPU timestamps: {e}");
+                self.readback_busy = false;
+            },
+            None => {}
+        }
+    }
+
+    pub fn timings(&self) -> &[GpuTiming]
+    {
+        &self.timings
+    }
+
+    fn read_timings(&mut self)
+    {
+        let data = self.readback_buffer.slice(..).get_mapped_range();
+        let timestamps: &[u64] = bytemuck::cast_slice(&data);
+
+        self.timings = self.pending_passes.iter()
+            .enumerate()
+            .map(|(index, label)| {
+                let ticks = timestamps[index * 2 + 1].saturating_sub(timestamps[index * 2]);
+
+                GpuTiming {
+                    label: label.clone(),
+                    duration: Duration::from_nanos((ticks as f64 * self.timestamp_period as f64) as u64)
+                }
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,188 @@
+use std::{future::Future, ops::Range, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll, Waker}};
+
+use image::RgbaImage;
+use wgpu::{Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, Extent3d, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, MapMode, Origin3d, Queue, Texture, TextureAspect, TextureFormat, COPY_BUFFER_ALIGNMENT, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+use crate::error::RendererError;
+
+// Copies buffers and textures into a mappable staging buffer and hands their bytes
+// back to the CPU, e.g. for screenshots, picking on the GPU or checking a shader's
+// output in a test. Every read submits its own copy and waits for it, so it's not
+// meant for every frame. What's read needs COPY_SRC among its usages.
+pub struct GpuReadback<'a> {
+    device: &'a Device,
+    queue: &'a Queue
+}
+
+impl<'a> GpuReadback<'a> {
+    pub fn new(device: &'a Device, queue: &'a Queue) -> Self
+    {
+        Self {
+            device,
+            queue
+        }
+    }
+
+    // The bytes of `range`, which is widened to COPY_BUFFER_ALIGNMENT for the copy.
+    pub async fn read_buffer(&self, buffer: &Buffer, range: Range<u64>) -> Result<Vec<u8>, RendererError>
+    {
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        let start = range.start / COPY_BUFFER_ALIGNMENT * COPY_BUFFER_ALIGNMENT;
+        let end = range.end.div_ceil(COPY_BUFFER_ALIGNMENT) * COPY_BUFFER_ALIGNMENT;
+
+        let staging = self.create_staging_buffer(end - start);
+        let mut encoder = self.device.create_command_encoder(
+            &CommandEncoderDescriptor {
+                label: Some("Readback Encoder")
+            }
+        );
+        encoder.copy_buffer_to_buffer(buffer, start, &staging, 0, end - start);
+        self.queue.submit(Some(encoder.finish()));
+
+        let bytes = self.map(&staging).await?;
+        let offset = (range.start - start) as usize;
+
+        Ok(bytes[offset..offset + (range.end - range.start) as usize].to_vec())
+    }
+
+    // Mip level `mip_level` of the first layer, its rows packed without any padding.
+    pub async fn read_texture(&self, texture: &Texture, mip_level: u32) -> Result<Vec<u8>, RendererError>
+    {
+        let format = texture.format();
+        let block_size = format.block_copy_size(None)
+            .ok_or(RendererError::ReadbackFormat(format))?;
+        let (block_width, block_height) = format.block_dimensions();
+        let size = texture.size().mip_level_size(mip_level, texture.dimension());
+        let row_bytes = size.width.div_ceil(block_width) * block_size;
+        let rows = size.height.div_ceil(block_height);
+        // Copies into a buffer have to start every row on a 256 byte boundary.
+        let padded_row_bytes = row_bytes.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let staging = self.create_staging_buffer(padded_row_bytes as u64 * rows as u64);
+        let mut encoder = self.device.create_command_encoder(
+            &CommandEncoderDescriptor {
+                label: Some("Readback Encoder")
+            }
+        );
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture,
+                mip_level,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All
+            },
+            ImageCopyBuffer {
+                buffer: &staging,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(rows)
+                }
+            },
+            Extent3d {
+                depth_or_array_layers: 1,
+                ..size
+            }
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let bytes = self.map(&staging).await?;
+
+        Ok(bytes.chunks(padded_row_bytes as usize)
+            .flat_map(|row| &row[..row_bytes as usize])
+            .copied()
+            .collect())
+    }
+
+    // The full size level of an 8 bit RGBA or BGRA texture, e.g. a render target.
+    pub async fn read_image(&self, texture: &Texture) -> Result<RgbaImage, RendererError>
+    {
+        let format = texture.format();
+        let swap_red_blue = match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            _ => return Err(RendererError::ReadbackFormat(format))
+        };
+
+        let mut bytes = self.read_texture(texture, 0).await?;
+        if swap_red_blue {
+            for pixel in bytes.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        let size = texture.size();
+
+        RgbaImage::from_raw(size.width, size.height, bytes)
+            .ok_or(RendererError::ReadbackFormat(format))
+    }
+
+    fn create_staging_buffer(&self, size: u64) -> Buffer
+    {
+        self.device.create_buffer(
+            &BufferDescriptor {
+                label: Some("Readback Staging Buffer"),
+                size,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false
+            }
+        )
+    }
+
+    // Native backends only call back from device.poll, so it waits for the copy right
+    // away. On the web the browser calls back by itself once the copy is done.
+    async fn map(&self, staging: &Buffer) -> Result<Vec<u8>, RendererError>
+    {
+        let mapped = MapFuture::default();
+        let state = mapped.state.clone();
+        staging.slice(..).map_async(MapMode::Read, move |result| {
+            if let Ok(mut state) = state.lock() {
+                state.result = Some(result);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        self.device.poll(wgpu::Maintain::Wait);
+
+        mapped.await?;
+        let bytes = staging.slice(..).get_mapped_range().to_vec();
+        staging.unmap();
+
+        Ok(bytes)
+    }
+}
+
+#[derive(Default)]
+struct MapState {
+    result: Option<Result<(), BufferAsyncError>>,
+    waker: Option<Waker>
+}
+
+// Resolves once map_async has called back.
+#[derive(Default)]
+struct MapFuture {
+    state: Arc<Mutex<MapState>>
+}
+
+impl Future for MapFuture {
+    type Output = Result<(), BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output>
+    {
+        let Ok(mut state) = self.state.lock() else {
+            return Poll::Ready(Err(BufferAsyncError));
+        };
+
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::ops::Range;
+
+use bytemuck::Zeroable;
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Device, Queue, ShaderStages};
+
+use crate::state::instance::InstanceRaw;
+
+use super::{debug_labels::DebugLabels, pipeline_builder::PipelineBuilder};
+
+// Instances per uniform block. 128 of them fill 14336 bytes, under the 16 KiB uniform
+// binding limit of WebGL2 and a multiple of the 256 byte offset alignment.
+pub const MAX_UNIFORM_INSTANCES: u32 = 128;
+
+const INSTANCE_SIZE: u64 = std::mem::size_of::<InstanceRaw>() as u64;
+
+// How the vertex shader reaches the instance data. Storage buffers are read whole by
+// instance index; where the vertex stage can't read them, a uniform array is bound
+// once per block of MAX_UNIFORM_INSTANCES with a dynamic offset instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceStorage {
+    Storage,
+    Uniform
+}
+
+impl InstanceStorage {
+    pub fn for_device(device: &Device) -> Self
+    {
+        if device.limits().max_storage_buffers_per_shader_stage > 0 {
+            InstanceStorage::Storage
+        } else {
+            InstanceStorage::Uniform
+        }
+    }
+
+    // Picks the address space and array type of `instances` in instancing.wgsl.
+    pub fn configure(&self, builder: &mut PipelineBuilder)
+    {
+        if *self == InstanceStorage::Uniform {
+            builder
+                .set_define("INSTANCE_ADDRESS_SPACE", "uniform")
+                .set_define("INSTANCE_ARRAY", &format!("array<InstanceData, {MAX_UNIFORM_INSTANCES}>"));
+        }
+    }
+
+    fn binding_type(&self) -> BufferBindingType
+    {
+        match self {
+            InstanceStorage::Storage => BufferBindingType::Storage { read_only: true },
+            InstanceStorage::Uniform => BufferBindingType::Uniform
+        }
+    }
+
+    fn usage(&self) -> BufferUsages
+    {
+        match self {
+            InstanceStorage::Storage => BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            InstanceStorage::Uniform => BufferUsages::UNIFORM | BufferUsages::COPY_DST
+        }
+    }
+
+    fn block_size(&self) -> Option<u64>
+    {
+        (*self == InstanceStorage::Uniform).then_some(MAX_UNIFORM_INSTANCES as u64 * INSTANCE_SIZE)
+    }
+
+    // The uniform array is sized, so its binding has to cover a whole block.
+    fn min_binding_size(&self) -> Option<BufferSize>
+    {
+        BufferSize::new(self.block_size().unwrap_or(INSTANCE_SIZE))
+    }
+}
+
+// One draw's worth of instances: the instance range to draw and the dynamic offset to
+// bind the instance buffer at while drawing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceBatch {
+    pub instances: Range<u32>,
+    pub offset: u32
+}
+
+// The per-instance data of one group of draws, bound at group 3. Every write lays out
+// a list of instance groups (e.g. one per LOD level) and returns the batches to draw
+// each of them with, so callers don't care which storage the device ended up with.
+pub struct InstanceBuffer {
+    labels: DebugLabels,
+    storage: InstanceStorage,
+    buffer: Buffer,
+    bind_group: BindGroup
+}
+
+impl InstanceBuffer {
+    pub fn get_bind_group_layout(device: &Device, label: &str, storage: InstanceStorage) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&DebugLabels::new(label).bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: storage.binding_type(),
+                            has_dynamic_offset: true,
+                            min_binding_size: storage.min_binding_size()
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    pub fn new(device: &Device, label: &str, layout: &BindGroupLayout, storage: InstanceStorage) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let buffer = Self::create_buffer(device, &labels, storage, 1);
+        let bind_group = Self::create_bind_group(device, &labels, layout, storage, &buffer);
+
+        Self {
+            labels,
+            storage,
+            buffer,
+            bind_group
+        }
+    }
+
+    pub fn bind_group(&self) -> &BindGroup
+    {
+        &self.bind_group
+    }
+
+    pub fn buffer(&self) -> &Buffer
+    {
+        &self.buffer
+    }
+
+    // Storage keeps the groups back to back. Uniform blocks start every group on a
+    // fresh block, so each batch draws from instance 0 of the block it binds, which
+    // also works where the first instance of a draw can't be set (WebGL2).
+    pub fn write(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        groups: &[Vec<InstanceRaw>]
+    ) -> Vec<Vec<InstanceBatch>>
+    {
+        let mut instance_data = Vec::new();
+        let batches = groups.iter()
+            .map(|group| {
+                let Some(block_size) = self.storage.block_size() else {
+                    let start = instance_data.len() as u32;
+                    instance_data.extend_from_slice(group);
+                    return vec![InstanceBatch { instances: start..instance_data.len() as u32, offset: 0 }];
+                };
+
+                group.chunks(MAX_UNIFORM_INSTANCES as usize)
+                    .map(|block| {
+                        let block_start = instance_data.len().div_ceil(MAX_UNIFORM_INSTANCES as usize)
+                            * MAX_UNIFORM_INSTANCES as usize;
+                        instance_data.resize(block_start, InstanceRaw::zeroed());
+                        instance_data.extend_from_slice(block);
+
+                        InstanceBatch {
+                            instances: 0..block.len() as u32,
+                            offset: (block_start as u64 / MAX_UNIFORM_INSTANCES as u64 * block_size) as u32
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // A uniform binding always covers a full block, even past the last instance.
+        let needed = match self.storage.block_size() {
+            Some(block_size) => (instance_data.len() as u64).div_ceil(MAX_UNIFORM_INSTANCES as u64)
+                * block_size,
+            None => instance_data.len() as u64 * INSTANCE_SIZE
+        };
+        if needed > self.buffer.size() {
+            let capacity = (needed / INSTANCE_SIZE).next_power_of_two();
+            self.buffer = Self::create_buffer(device, &self.labels, self.storage, capacity);
+            self.bind_group = Self::create_bind_group(device, &self.labels, layout, self.storage,
+                &self.buffer);
+        }
+        if !instance_data.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&instance_data));
+        }
+
+        batches
+    }
+
+    fn create_buffer(device: &Device, labels: &DebugLabels, storage: InstanceStorage, capacity: u64) -> Buffer
+    {
+        let size = match storage.block_size() {
+            Some(block_size) => capacity.div_ceil(MAX_UNIFORM_INSTANCES as u64).max(1) * block_size,
+            None => capacity.max(1) * INSTANCE_SIZE
+        };
+
+        device.create_buffer(
+            &BufferDescriptor {
+                label: Some(&labels.buffer()),
+                size,
+                usage: storage.usage(),
+                mapped_at_creation: false
+            }
+        )
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        labels: &DebugLabels,
+        layout: &BindGroupLayout,
+        storage: InstanceStorage,
+        buffer: &Buffer
+    ) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.bind_group()),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer,
+                            offset: 0,
+                            size: storage.block_size().and_then(BufferSize::new)
+                        })
+                    }
+                ]
+            }
+        )
+    }
+}
@@ -0,0 +1,317 @@
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Device, SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension};
+
+use super::{asset_cache::AssetCache, assets::{ProceduralTextureHandle, RenderTargetHandle, TextureHandle}, debug_labels::DebugLabels, pipeline_builder::PipelineBuilder, procedural_texture::ProceduralTexture, render_target::RenderTarget, residency::ResidentTexture, sampler_cache::{SamplerCache, SamplerSpec}};
+
+// What a material needs from the shader. Every combination is its own permutation of
+// the material shaders, compiled with the matching defines from material.wgsl, and
+// its own bind group layout holding only the textures it samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialFeatures {
+    pub normal_map: bool,
+    pub emissive: bool,
+    // Drawn by the skinned pipeline instead of the instanced one.
+    pub skinning: bool
+}
+
+impl MaterialFeatures {
+    pub fn configure(&self, builder: &mut PipelineBuilder)
+    {
+        if self.normal_map {
+            builder.set_define("HAS_NORMAL_MAP", "1");
+        }
+        if self.emissive {
+            builder.set_define("HAS_EMISSIVE", "1");
+        }
+    }
+
+    // Shows up in labels, e.g. "Material normal_map+emissive Pipeline".
+    pub fn name(&self) -> String
+    {
+        let names = [(self.normal_map, "normal_map"), (self.emissive, "emissive"),
+            (self.skinning, "skinning")];
+        let enabled = names.iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+
+        if enabled.is_empty() {
+            String::from("base")
+        } else {
+            enabled.join("+")
+        }
+    }
+
+    // Binding 0 and 1 are the diffuse array and the shared sampler, 2 the normal map
+    // and 3 the emissive map, each only present with its feature.
+    pub fn get_bind_group_layout(&self, device: &Device, label: &str) -> BindGroupLayout
+    {
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension: TextureViewDimension::D2Array,
+                sample_type: TextureSampleType::Float { filterable: true }
+            },
+            count: None
+        };
+        let mut entries = vec![
+            texture_entry(0),
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None
+            }
+        ];
+        if self.normal_map {
+            entries.push(texture_entry(2));
+        }
+        if self.emissive {
+            entries.push(texture_entry(3));
+        }
+
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&DebugLabels::new(&format!("{label} {}", self.name())).bind_group_layout()),
+                entries: &entries
+            }
+        )
+    }
+}
+
+enum Diffuse {
+    Texture(TextureHandle),
+    RenderTarget(RenderTargetHandle),
+    Procedural(ProceduralTextureHandle)
+}
+
+pub struct Material {
+    diffuse: Diffuse,
+    normal_map: Option<TextureHandle>,
+    emissive: Option<TextureHandle>,
+    emissive_factor: Option<[f32; 3]>,
+    emissive_intensity: f32,
+    color: [f32; 4],
+    sampler: SamplerSpec,
+    skinning: bool,
+    bind_group: Option<BindGroup>
+}
+
+impl Material {
+    pub fn new(diffuse: TextureHandle) -> Self
+    {
+        Self::with_diffuse(Diffuse::Texture(diffuse))
+    }
+
+    // Shows what the render target saw this frame. Draws with the material are left
+    // out of the target's own pass, which can't sample what it renders to.
+    pub fn from_render_target(render_target: RenderTargetHandle) -> Self
+    {
+        Self::with_diffuse(Diffuse::RenderTarget(render_target))
+    }
+
+    // Shows a texture generated on the GPU, see State::create_procedural_texture.
+    pub fn from_procedural(procedural_texture: ProceduralTextureHandle) -> Self
+    {
+        Self::with_diffuse(Diffuse::Procedural(procedural_texture))
+    }
+
+    fn with_diffuse(diffuse: Diffuse) -> Self
+    {
+        Self {
+            diffuse,
+            normal_map: None,
+            emissive: None,
+            emissive_factor: None,
+            emissive_intensity: 1.0,
+            color: [1.0; 4],
+            sampler: SamplerSpec::default(),
+            skinning: false,
+            bind_group: None
+        }
+    }
+
+    pub fn set_normal_map(&mut self, normal_map: TextureHandle) -> &mut Self
+    {
+        self.normal_map = Some(normal_map);
+        self.bind_group = None;
+
+        self
+    }
+
+    pub fn set_emissive(&mut self, emissive: TextureHandle) -> &mut Self
+    {
+        self.emissive = Some(emissive);
+        self.bind_group = None;
+
+        self
+    }
+
+    // Multiplied with the emissive map, or emitted as it is without one. Defaults to
+    // white with an emissive map and black without.
+    pub fn set_emissive_factor(&mut self, factor: [f32; 3]) -> &mut Self
+    {
+        self.emissive_factor = Some(factor);
+
+        self
+    }
+
+    // Scales the emission, past 1 it's bright enough for the glow post effect to
+    // pick up on an HDR surface. Instances can override it.
+    pub fn set_emissive_intensity(&mut self, intensity: f32) -> &mut Self
+    {
+        self.emissive_intensity = intensity.max(0.0);
+
+        self
+    }
+
+    // Multiplied into the color of every instance drawn with the material.
+    pub fn set_color(&mut self, color: [f32; 4]) -> &mut Self
+    {
+        self.color = color;
+
+        self
+    }
+
+    // Every texture of the material is sampled with it, shared through the SamplerCache.
+    pub fn set_sampler(&mut self, sampler: SamplerSpec) -> &mut Self
+    {
+        self.sampler = sampler;
+        self.bind_group = None;
+
+        self
+    }
+
+    pub fn set_skinning(&mut self, skinning: bool) -> &mut Self
+    {
+        self.skinning = skinning;
+
+        self
+    }
+
+    pub fn color(&self) -> [f32; 4]
+    {
+        self.color
+    }
+
+    // What an instance drawn with the material emits before the emissive map, with
+    // the instance's intensity in place of the material's if it has one.
+    pub fn emissive(&self, intensity: Option<f32>) -> [f32; 3]
+    {
+        let default_factor = if self.emissive.is_some() { [1.0; 3] } else { [0.0; 3] };
+        let intensity = intensity.map_or(self.emissive_intensity, |intensity| intensity.max(0.0));
+
+        self.emissive_factor.unwrap_or(default_factor).map(|channel| channel * intensity)
+    }
+
+    pub fn features(&self) -> MaterialFeatures
+    {
+        MaterialFeatures {
+            normal_map: self.normal_map.is_some(),
+            emissive: self.emissive.is_some(),
+            skinning: self.skinning
+        }
+    }
+
+    pub fn textures(&self) -> impl Iterator<Item = &TextureHandle>
+    {
+        let diffuse = match &self.diffuse {
+            Diffuse::Texture(handle) => Some(handle),
+            Diffuse::RenderTarget(_) | Diffuse::Procedural(_) => None
+        };
+
+        diffuse.into_iter()
+            .chain(self.normal_map.as_ref())
+            .chain(self.emissive.as_ref())
+    }
+
+    pub fn render_target(&self) -> Option<&RenderTargetHandle>
+    {
+        match &self.diffuse {
+            Diffuse::RenderTarget(handle) => Some(handle),
+            Diffuse::Texture(_) | Diffuse::Procedural(_) => None
+        }
+    }
+
+    pub fn procedural_texture(&self) -> Option<&ProceduralTextureHandle>
+    {
+        match &self.diffuse {
+            Diffuse::Procedural(handle) => Some(handle),
+            Diffuse::Texture(_) | Diffuse::RenderTarget(_) => None
+        }
+    }
+
+    pub fn bind_group(&self) -> Option<&BindGroup>
+    {
+        self.bind_group.as_ref()
+    }
+
+    // Creates the bind group if there is none yet. Returns false while one of the
+    // textures isn't resident, the material can't be drawn then.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        layout: &BindGroupLayout,
+        textures: &AssetCache<ResidentTexture>,
+        render_targets: &AssetCache<RenderTarget>,
+        procedural_textures: &AssetCache<ProceduralTexture>,
+        samplers: &mut SamplerCache
+    ) -> bool
+    {
+        if self.bind_group.is_some() {
+            return true;
+        }
+
+        let texture = |handle: &TextureHandle| textures.get(handle)
+            .and_then(ResidentTexture::texture)
+            .map(|texture| &texture.view);
+        let diffuse = match &self.diffuse {
+            Diffuse::Texture(handle) => texture(handle),
+            Diffuse::RenderTarget(handle) => render_targets.get(handle).map(RenderTarget::view),
+            Diffuse::Procedural(handle) => procedural_textures.get(handle).map(ProceduralTexture::view)
+        };
+        let Some(diffuse) = diffuse else {
+            return false;
+        };
+        let sampler = samplers.get(device, self.sampler);
+        let mut entries = vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(diffuse)
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(&sampler)
+            }
+        ];
+        for (binding, handle) in [(2, &self.normal_map), (3, &self.emissive)] {
+            let Some(handle) = handle else {
+                continue;
+            };
+            let Some(map) = texture(handle) else {
+                return false;
+            };
+            entries.push(BindGroupEntry {
+                binding,
+                resource: BindingResource::TextureView(map)
+            });
+        }
+
+        self.bind_group = Some(device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&DebugLabels::new("Material").bind_group()),
+                layout,
+                entries: &entries
+            }
+        ));
+
+        true
+    }
+
+    // After the textures or the device changed.
+    pub fn release_bind_group(&mut self)
+    {
+        self.bind_group = None;
+    }
+}
@@ -1,3 +1,57 @@
 pub mod pipeline_builder;
 pub mod vertex;
 pub mod texture;
+pub mod render_pass;
+pub mod residency;
+pub mod transient;
+pub mod debug_labels;
+pub mod gpu_profiler;
+pub mod debug_view;
+pub mod channel_packing;
+pub mod pipeline_cache;
+pub mod submit_batch;
+pub mod compute_pipeline_builder;
+pub mod shader_preprocessor;
+pub mod shader_validation;
+pub mod shader_registry;
+pub mod blend_mode;
+pub mod vertex_layout;
+pub mod skinned_mesh;
+pub mod terrain_mesh;
+pub mod vegetation_mesh;
+pub mod water;
+pub mod debug_lines;
+pub mod instance_buffer;
+pub mod gpu_allocator;
+pub mod asset_cache;
+pub mod assets;
+pub mod material;
+pub mod draw_queue;
+pub mod asset_decode;
+#[cfg(target_arch = "wasm32")]
+pub mod asset_fetch;
+pub mod texture_streaming;
+pub mod sampler_cache;
+pub mod render_target;
+pub mod anti_aliasing;
+pub mod taa;
+pub mod msaa;
+pub mod post_effect;
+pub mod ssao;
+pub mod depth_of_field;
+pub mod motion_blur;
+pub mod fog;
+pub mod color_grading;
+pub mod glow;
+pub mod decal;
+pub mod billboard;
+pub mod gpu_culling;
+pub mod compute;
+pub mod edge_detection;
+pub mod procedural_texture;
+pub mod outline;
+pub mod render_scale;
+pub mod gpu_capabilities;
+pub mod gpu_readback;
+pub mod error_scope;
+pub mod shader_test;
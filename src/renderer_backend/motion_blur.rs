@@ -0,0 +1,171 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::Matrix4;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Queue, ShaderStages, TextureSampleType, TextureView, TextureViewDimension};
+
+use super::debug_labels::DebugLabels;
+
+pub const MAX_SAMPLE_COUNT: u32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionBlurOptions {
+    // The share of a frame the shutter is open for, 0.5 is a 180 degree shutter.
+    pub shutter_scale: f32,
+    // Per pixel along its motion, up to MAX_SAMPLE_COUNT.
+    pub sample_count: u32
+}
+
+impl Default for MotionBlurOptions {
+    fn default() -> Self
+    {
+        Self {
+            shutter_scale: 0.5,
+            sample_count: 8
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct MotionBlurUniform {
+    // from this frame's clip space to the last one's
+    reprojection: [[f32; 4]; 4],
+    // shutter scale, sample count
+    params: [f32; 4]
+}
+
+// What the motion blur post effect binds on top of the frame: the motion vectors, and
+// the depth and camera reprojection for the camera's own motion where nothing was
+// drawn to leave a motion vector.
+pub struct MotionBlur {
+    labels: DebugLabels,
+    options: MotionBlurOptions,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout
+}
+
+impl MotionBlur {
+    pub fn new(device: &Device, label: &str, options: MotionBlurOptions) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[MotionBlurUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            options: Self::clamp_options(options),
+            uniform_buffer
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Float { filterable: true }
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Depth
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    fn clamp_options(options: MotionBlurOptions) -> MotionBlurOptions
+    {
+        MotionBlurOptions {
+            shutter_scale: options.shutter_scale.max(0.0),
+            sample_count: options.sample_count.clamp(1, MAX_SAMPLE_COUNT)
+        }
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn options(&self) -> MotionBlurOptions
+    {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: MotionBlurOptions)
+    {
+        self.options = Self::clamp_options(options);
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    // `reprojection` takes the main camera's clip space this frame to the last one's.
+    pub fn write_uniforms(&self, queue: &Queue, reprojection: Matrix4<f32>)
+    {
+        let uniform = MotionBlurUniform {
+            reprojection: reprojection.into(),
+            params: [self.options.shutter_scale, self.options.sample_count as f32, 0.0, 0.0]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // The motion vectors come from the transient pool, so the bind group is made per
+    // frame.
+    pub fn create_bind_group(&self, device: &Device, motion: &TextureView, depth: &TextureView) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(motion)
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(depth)
+                    }
+                ]
+            }
+        )
+    }
+}
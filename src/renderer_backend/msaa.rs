@@ -0,0 +1,134 @@
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Color, Device, Operations, RenderPassColorAttachment, ShaderStages, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDimension};
+
+use super::{debug_labels::DebugLabels, pipeline_builder::PipelineBuilder, shader_registry::ShaderHandle, transient::TransientTextureDesc};
+
+// Every pixel's samples with AntiAliasing::Msaa. Four is the most WebGPU guarantees.
+pub const MSAA_SAMPLE_COUNT: u32 = 4;
+
+// Multisample anti-aliasing. The passes drawing the scene draw into multisampled color
+// and depth targets from the transient pool, with the color resolved into the target
+// they'd draw to otherwise. A pass can't resolve depth, so a fullscreen one writes the
+// nearest sample of every pixel into the depth texture the decals and post effects
+// read. Viewports can't resolve over the frame either, the resolve would replace all
+// of it, so they're composited onto it where their samples left depth.
+pub struct Msaa {
+    labels: DebugLabels,
+    bind_group_layout: BindGroupLayout
+}
+
+impl Msaa {
+    pub fn new(device: &Device, label: &str) -> Self
+    {
+        let labels = DebugLabels::new(label);
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: true,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Depth
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    // Depth targets are read by the depth resolve and the viewport composite.
+    pub fn target_desc(width: u32, height: u32, format: TextureFormat) -> TransientTextureDesc
+    {
+        let usage = if format.is_depth_stencil_format() {
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING
+        } else {
+            TextureUsages::RENDER_ATTACHMENT
+        };
+
+        TransientTextureDesc {
+            width: width.max(1),
+            height: height.max(1),
+            format,
+            usage,
+            sample_count: MSAA_SAMPLE_COUNT
+        }
+    }
+
+    // Built with set_depth_output, for a pass with only the depth texture attached.
+    pub fn configure_depth_resolve(builder: &mut PipelineBuilder)
+    {
+        builder
+            .set_shader_module(ShaderHandle::Msaa, "vs_fullscreen", "fs_resolve_depth")
+            .set_define("SAMPLE_COUNT", &MSAA_SAMPLE_COUNT.to_string())
+            .set_fullscreen()
+            .set_depth_output();
+    }
+
+    // Binds this group first and the resolved viewport as a post effect's source second.
+    pub fn configure_composite(builder: &mut PipelineBuilder)
+    {
+        builder
+            .set_shader_module(ShaderHandle::Msaa, "vs_fullscreen", "fs_composite")
+            .set_define("SAMPLE_COUNT", &MSAA_SAMPLE_COUNT.to_string())
+            .set_fullscreen();
+    }
+
+    pub fn create_bind_group(&self, device: &Device, depth: &TextureView) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(depth)
+                    }
+                ]
+            }
+        )
+    }
+}
+
+// Draws into `msaa` and resolves into `view` when there is one, into `view` otherwise.
+pub fn color_attachment<'a>(
+    view: &'a TextureView,
+    msaa: Option<&'a TextureView>,
+    ops: Operations<Color>
+) -> RenderPassColorAttachment<'a>
+{
+    match msaa {
+        Some(msaa) => RenderPassColorAttachment {
+            view: msaa,
+            resolve_target: Some(view),
+            ops
+        },
+        None => RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops
+        }
+    }
+}
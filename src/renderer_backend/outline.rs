@@ -0,0 +1,221 @@
+use std::{ops::Range, rc::Rc};
+
+use wgpu::{BindGroupLayout, ColorWrites, CompareFunction, Device, LoadOp, Operations, Queue, RenderPass, RenderPassDepthStencilAttachment, RenderPipeline, StencilFaceState, StencilOperation, StoreOp, SurfaceConfiguration};
+
+use crate::{error::RendererError, state::instance::InstanceRaw};
+
+use super::{debug_labels::DebugLabels, instance_buffer::{InstanceBatch, InstanceBuffer, InstanceStorage}, pipeline_builder::PipelineBuilder, pipeline_cache::PipelineCache, shader_registry::{ShaderHandle, ShaderRegistry}, texture::Texture};
+
+// What the mask draw writes into the stencil buffer.
+const OUTLINE_STENCIL_REFERENCE: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutlineOptions {
+    // Linear, drawn over the finished frame.
+    pub color: [f32; 3],
+    // How much larger than the instance the outline is redrawn, 0.05 is 5%.
+    pub width: f32
+}
+
+impl Default for OutlineOptions {
+    fn default() -> Self
+    {
+        Self {
+            color: [1.0, 0.6, 0.1],
+            width: 0.05
+        }
+    }
+}
+
+// Outlines instances with the stencil buffer: the first draw marks their pixels, the
+// second redraws them a bit larger and only colors what lies outside the marks. It
+// has a depth-stencil target of its own and doesn't test depth, so outlined instances
+// show through whatever is in front of them.
+pub struct Outline {
+    labels: DebugLabels,
+    options: OutlineOptions,
+    mask_pipeline: Rc<RenderPipeline>,
+    outline_pipeline: Rc<RenderPipeline>,
+    instance_buffer: InstanceBuffer,
+    batches: Vec<InstanceBatch>,
+    depth_stencil: Texture
+}
+
+impl Outline {
+    // The layouts are the instance pipeline's, ending with the instance layout.
+    pub fn new(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shaders: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        label: &str,
+        options: OutlineOptions,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Self, RendererError>
+    {
+        let labels = DebugLabels::new(label);
+        let (mask_pipeline, outline_pipeline) = Self::create_pipelines(pipeline_cache, device,
+            shaders, config, &labels, &options, bind_group_layouts)?;
+        let instance_layout = bind_group_layouts.last().expect("the instance layout comes last");
+        let instance_buffer = InstanceBuffer::new(device, label, instance_layout,
+            InstanceStorage::for_device(device));
+
+        Ok(Self {
+            depth_stencil: Self::create_depth_stencil(device, config, &labels),
+            labels,
+            options,
+            mask_pipeline,
+            outline_pipeline,
+            instance_buffer,
+            batches: Vec::new()
+        })
+    }
+
+    pub fn options(&self) -> OutlineOptions
+    {
+        self.options
+    }
+
+    // The options are built into the pipelines, so they're rebuilt with them.
+    pub fn set_options(
+        &mut self,
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shaders: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        options: OutlineOptions,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<(), RendererError>
+    {
+        (self.mask_pipeline, self.outline_pipeline) = Self::create_pipelines(pipeline_cache, device,
+            shaders, config, &self.labels, &options, bind_group_layouts)?;
+        self.options = options;
+
+        Ok(())
+    }
+
+    // After the shaders changed, keeping the last good pipelines on errors.
+    pub fn rebuild(
+        &mut self,
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shaders: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<(), RendererError>
+    {
+        self.set_options(pipeline_cache, device, shaders, config, self.options, bind_group_layouts)
+    }
+
+    pub fn resize(&mut self, device: &Device, config: &SurfaceConfiguration)
+    {
+        self.depth_stencil = Self::create_depth_stencil(device, config, &self.labels);
+    }
+
+    pub fn write_instances(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        instances: Vec<InstanceRaw>
+    )
+    {
+        self.batches = self.instance_buffer.write(device, queue, layout, &[instances]).concat();
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.batches.iter().all(|batch| batch.instances.is_empty())
+    }
+
+    // Cleared every pass, nothing reads it after.
+    pub fn depth_stencil_attachment(&self) -> RenderPassDepthStencilAttachment<'_>
+    {
+        RenderPassDepthStencilAttachment {
+            view: &self.depth_stencil.view,
+            depth_ops: Some(Operations { load: LoadOp::Clear(1.0), store: StoreOp::Discard }),
+            stencil_ops: Some(Operations { load: LoadOp::Clear(0), store: StoreOp::Discard })
+        }
+    }
+
+    // In a pass with depth_stencil_attachment, with groups 0 to 2 of the instance
+    // pipeline and the instance mesh already bound.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, indices: Range<u32>)
+    {
+        render_pass.set_stencil_reference(OUTLINE_STENCIL_REFERENCE);
+        for pipeline in [&self.mask_pipeline, &self.outline_pipeline] {
+            render_pass.set_pipeline(pipeline);
+            for batch in &self.batches {
+                render_pass.set_bind_group(3, self.instance_buffer.bind_group(), &[batch.offset]);
+                render_pass.draw_indexed(indices.clone(), 0, batch.instances.clone());
+            }
+        }
+    }
+
+    fn create_pipelines(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shaders: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        labels: &DebugLabels,
+        options: &OutlineOptions,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<(Rc<RenderPipeline>, Rc<RenderPipeline>), RendererError>
+    {
+        let [r, g, b] = options.color;
+        let color = format!("vec4<f32>({r:?}, {g:?}, {b:?}, 1.0)");
+        let scale = format!("{:?}", 1.0 + options.width.max(0.0));
+        let storage = InstanceStorage::for_device(device);
+
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(&labels.with_suffix("Mask"))
+            .set_shader_module(ShaderHandle::Outline, "vs_mask", "fs_mask")
+            .set_pixel_format(config.format)
+            .set_color_writes(ColorWrites::empty())
+            .set_depth_state(false, CompareFunction::Always)
+            .set_stencil_state(
+                StencilFaceState {
+                    compare: CompareFunction::Always,
+                    fail_op: StencilOperation::Keep,
+                    depth_fail_op: StencilOperation::Keep,
+                    pass_op: StencilOperation::Replace
+                },
+                !0,
+                !0
+            );
+        storage.configure(&mut builder);
+        let mask_pipeline = pipeline_cache.get_or_build(&mut builder, device, shaders,
+            bind_group_layouts)?;
+
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(labels.name())
+            .set_shader_module(ShaderHandle::Outline, "vs_outline", "fs_outline")
+            .set_define("OUTLINE_COLOR", &color)
+            .set_define("OUTLINE_SCALE", &scale)
+            .set_pixel_format(config.format)
+            .set_depth_state(false, CompareFunction::Always)
+            .set_stencil_state(
+                StencilFaceState {
+                    compare: CompareFunction::NotEqual,
+                    fail_op: StencilOperation::Keep,
+                    depth_fail_op: StencilOperation::Keep,
+                    pass_op: StencilOperation::Keep
+                },
+                !0,
+                0
+            );
+        storage.configure(&mut builder);
+        let outline_pipeline = pipeline_cache.get_or_build(&mut builder, device, shaders,
+            bind_group_layouts)?;
+
+        Ok((mask_pipeline, outline_pipeline))
+    }
+
+    fn create_depth_stencil(device: &Device, config: &SurfaceConfiguration, labels: &DebugLabels) -> Texture
+    {
+        Texture::create_depth_texture(device, config, Texture::DEPTH_STENCIL_FORMAT, 1,
+            &labels.with_suffix("Depth Stencil Texture"))
+    }
+}
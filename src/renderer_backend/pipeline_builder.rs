@@ -1,37 +1,152 @@
-use std::{env::current_dir, fs};
+use wgpu::{BindGroupLayout, BlendState, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Device, Face, FragmentState, FrontFace, IndexFormat, MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, StencilFaceState, StencilState, TextureFormat, VertexBufferLayout, VertexState};
 
-use wgpu::{BindGroupLayout, BlendState, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Device, Face, FragmentState, FrontFace, MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, StencilState, TextureFormat, VertexState};
+use crate::{error::RendererError, state::renderer_backend::{blend_mode::BlendMode, debug_labels::DebugLabels, error_scope::ErrorScope, pipeline_cache::PipelineKey, shader_preprocessor::ShaderPreprocessor, shader_registry::{ShaderHandle, ShaderRegistry}, shader_validation, texture::Texture, vertex::Vertex, vertex_layout::VertexLayout}};
 
-use crate::state::{instance::InstanceRaw, renderer_backend::{texture::Texture, vertex::Vertex}};
+// Set on the ShaderRegistry for reverse Z, which flips every pipeline's depth compare.
+pub const REVERSE_Z_DEFINE: &str = "REVERSE_Z";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShaderStage {
+    pub shader: ShaderHandle,
+    pub entry_point: String
+}
+
+impl ShaderStage {
+    pub fn new(shader: ShaderHandle, entry_point: &str) -> Self
+    {
+        Self {
+            shader,
+            entry_point: String::from(entry_point)
+        }
+    }
+}
 
 pub struct PipelineBuilder {
-    shader_filename: String,
-    vertex_entry: String,
-    fragment_entry: String,
-    pixel_format: TextureFormat
+    labels: DebugLabels,
+    vertex_stage: ShaderStage,
+    fragment_stage: Option<ShaderStage>,
+    vertex_layouts: Vec<VertexBufferLayout<'static>>,
+    preprocessor: ShaderPreprocessor,
+    pixel_format: TextureFormat,
+    topology: PrimitiveTopology,
+    cull_mode: Option<Face>,
+    front_face: FrontFace,
+    polygon_mode: PolygonMode,
+    blend_state: BlendState,
+    color_writes: ColorWrites,
+    color_target: bool,
+    depth_enabled: bool,
+    depth_format: TextureFormat,
+    depth_write_enabled: bool,
+    depth_compare: CompareFunction,
+    stencil: StencilState,
+    sample_count: u32,
+    alpha_to_coverage: bool
 }
 
 impl PipelineBuilder {
     pub fn builder() -> Self
     {
         Self {
-            shader_filename: String::from("shader.wgsl"),
-            vertex_entry: String::from("vs_main"),
-            fragment_entry: String::from("fs_main"),
-            pixel_format: TextureFormat::Rgba8Unorm
+            labels: DebugLabels::new("Render"),
+            vertex_stage: ShaderStage::new(ShaderHandle::Vertex, "vs_main"),
+            fragment_stage: Some(ShaderStage::new(ShaderHandle::Vertex, "fs_main")),
+            vertex_layouts: vec![Vertex::vertex_buffer_layout()],
+            preprocessor: ShaderPreprocessor::default(),
+            pixel_format: TextureFormat::Rgba8Unorm,
+            topology: PrimitiveTopology::TriangleList,
+            cull_mode: Some(Face::Back),
+            front_face: FrontFace::Ccw,
+            polygon_mode: PolygonMode::Fill,
+            blend_state: BlendState::REPLACE,
+            color_writes: ColorWrites::ALL,
+            color_target: true,
+            depth_enabled: true,
+            depth_format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: CompareFunction::Less,
+            stencil: StencilState::default(),
+            sample_count: 1,
+            alpha_to_coverage: false
         }
     }
 
+    pub fn set_label(&mut self, label: &str) -> &mut Self
+    {
+        self.labels = DebugLabels::new(label);
+
+        self
+    }
+
     pub fn set_shader_module(
         &mut self,
-        shader_filename: &str,
+        shader: ShaderHandle,
         vertex_entry: &str,
         fragment_entry: &str
     ) -> &mut Self
     {
-        self.shader_filename = String::from(shader_filename);
-        self.vertex_entry = String::from(vertex_entry);
-        self.fragment_entry = String::from(fragment_entry);
+        self.vertex_stage = ShaderStage::new(shader, vertex_entry);
+        self.fragment_stage = Some(ShaderStage::new(shader, fragment_entry));
+
+        self
+    }
+
+    pub fn set_vertex_shader(&mut self, shader: ShaderHandle, entry_point: &str) -> &mut Self
+    {
+        self.vertex_stage = ShaderStage::new(shader, entry_point);
+
+        self
+    }
+
+    pub fn set_fragment_shader(&mut self, shader: ShaderHandle, entry_point: &str) -> &mut Self
+    {
+        self.fragment_stage = Some(ShaderStage::new(shader, entry_point));
+
+        self
+    }
+
+    // Builds without a fragment stage or color target, only writing depth (shadow maps, depth prepasses).
+    pub fn set_depth_only(&mut self) -> &mut Self
+    {
+        self.fragment_stage = None;
+
+        self
+    }
+
+    // Keeps the fragment stage but drops its color target, for shaders that only write
+    // frag_depth. Every fragment passes and writes, e.g. to copy one depth buffer into
+    // another.
+    pub fn set_depth_output(&mut self) -> &mut Self
+    {
+        self.color_target = false;
+        self.depth_enabled = true;
+        self.depth_write_enabled = true;
+        self.depth_compare = CompareFunction::Always;
+
+        self
+    }
+
+    // For passes without a depth attachment, e.g. ones reading the depth buffer as a
+    // texture.
+    pub fn set_depth_disabled(&mut self) -> &mut Self
+    {
+        self.depth_enabled = false;
+
+        self
+    }
+
+    // One layout per vertex buffer slot, in slot order. Defaults to the textured
+    // vertex in slot 0, instances are read from a buffer binding by instance index.
+    pub fn set_vertex_layouts(&mut self, vertex_layouts: &[VertexBufferLayout<'static>]) -> &mut Self
+    {
+        self.vertex_layouts = vertex_layouts.to_vec();
+
+        self
+    }
+
+    pub fn set_define(&mut self, name: &str, value: &str) -> &mut Self
+    {
+        self.preprocessor.set_define(name, value);
 
         self
     }
@@ -43,97 +158,293 @@ impl PipelineBuilder {
         self
     }
 
+    // A cull mode of None draws both sides, e.g. for foliage cards. PolygonMode::Line
+    // and PolygonMode::Point need their matching device features.
+    pub fn set_primitive(
+        &mut self,
+        topology: PrimitiveTopology,
+        cull_mode: Option<Face>,
+        front_face: FrontFace,
+        polygon_mode: PolygonMode
+    ) -> &mut Self
+    {
+        self.topology = topology;
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self.polygon_mode = polygon_mode;
+
+        self
+    }
+
+    pub fn set_polygon_mode(&mut self, polygon_mode: PolygonMode) -> &mut Self
+    {
+        self.polygon_mode = polygon_mode;
+
+        self
+    }
+
+    pub fn set_blend_state(&mut self, blend_state: BlendState) -> &mut Self
+    {
+        self.blend_state = blend_state;
+
+        self
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) -> &mut Self
+    {
+        self.blend_state = blend_mode.blend_state();
+
+        self
+    }
+
+    // Compares are written for standard depth, nearer is less. With the registry's
+    // REVERSE_Z_DEFINE they're flipped when building.
+    pub fn set_depth_state(
+        &mut self,
+        depth_write_enabled: bool,
+        depth_compare: CompareFunction
+    ) -> &mut Self
+    {
+        self.depth_write_enabled = depth_write_enabled;
+        self.depth_compare = depth_compare;
+
+        self
+    }
+
+    // Has to match the depth attachment of the passes drawing with the pipeline, e.g.
+    // Texture::DEPTH_STENCIL_FORMAT for stencil effects.
+    pub fn set_depth_format(&mut self, depth_format: TextureFormat) -> &mut Self
+    {
+        self.depth_format = depth_format;
+
+        self
+    }
+
+    // The same test and operations for both faces, against the reference the render pass
+    // sets. Switches to Texture::DEPTH_STENCIL_FORMAT unless the depth format already has
+    // a stencil aspect.
+    pub fn set_stencil_state(
+        &mut self,
+        face: StencilFaceState,
+        read_mask: u32,
+        write_mask: u32
+    ) -> &mut Self
+    {
+        if !self.depth_format.has_stencil_aspect() {
+            self.depth_format = Texture::DEPTH_STENCIL_FORMAT;
+        }
+        self.stencil = StencilState {
+            front: face,
+            back: face,
+            read_mask,
+            write_mask
+        };
+
+        self
+    }
+
+    // ColorWrites::empty() for passes that only write depth or stencil into a pass that
+    // still has a color attachment.
+    pub fn set_color_writes(&mut self, color_writes: ColorWrites) -> &mut Self
+    {
+        self.color_writes = color_writes;
+
+        self
+    }
+
+    // Has to match the sample count of the pass' attachments.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> &mut Self
+    {
+        self.sample_count = sample_count;
+
+        self
+    }
+
+    // Turns the fragment's alpha into sample coverage, for cutouts like leaves. It only
+    // smooths their edges with multisampling, with one sample it's an alpha test at
+    // best, so the shader should still discard what's clearly transparent.
+    pub fn set_alpha_to_coverage(&mut self, enabled: bool) -> &mut Self
+    {
+        self.alpha_to_coverage = enabled;
+
+        self
+    }
+
+    // No vertex buffers, culling or depth: one triangle made from the vertex index
+    // covers the target, as vs_fullscreen in fullscreen.wgsl does.
+    pub fn set_fullscreen(&mut self) -> &mut Self
+    {
+        self.vertex_layouts.clear();
+        self.cull_mode = None;
+        self.depth_enabled = false;
+
+        self
+    }
+
+    pub fn cache_key(&self, bind_group_layouts: &[&BindGroupLayout]) -> PipelineKey
+    {
+        PipelineKey {
+            vertex_stage: self.vertex_stage.clone(),
+            defines: self.preprocessor.defines().iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            fragment_stage: self.fragment_stage.clone(),
+            vertex_layouts: self.vertex_layouts.clone(),
+            pixel_format: self.pixel_format,
+            topology: self.topology,
+            cull_mode: self.cull_mode,
+            front_face: self.front_face,
+            polygon_mode: self.polygon_mode,
+            blend_state: self.blend_state,
+            color_writes: self.color_writes,
+            color_target: self.color_target,
+            depth_enabled: self.depth_enabled,
+            depth_format: self.depth_format,
+            depth_write_enabled: self.depth_write_enabled,
+            depth_compare: self.depth_compare,
+            stencil: self.stencil.clone(),
+            sample_count: self.sample_count,
+            alpha_to_coverage: self.alpha_to_coverage,
+            bind_group_layouts: bind_group_layouts.iter().map(|layout| layout.global_id()).collect()
+        }
+    }
+
+    // Errors the backend runs into compiling the shaders or creating the pipeline are
+    // returned as well, not only the ones of the preprocessor and naga.
     pub fn build(
         &mut self,
         device: &Device,
+        shaders: &ShaderRegistry,
         bind_group_layouts: &[&BindGroupLayout]
-    ) -> RenderPipeline
-    {
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {
-                let source_code = self.shader_filename.as_str();
-            } else {
-                let filepath = current_dir()
-                    .unwrap()
-                    .join("src")
-                    .join("shaders")
-                    .join(self.shader_filename.as_str())
-                    .into_os_string()
-                    .into_string()
-                    .unwrap();
-
-                let source_code = fs::read_to_string(filepath)
-                    .expect("Can't read the shader source file.");
-            }
-        }
+    ) -> Result<RenderPipeline, RendererError>
+    {
+        let scope = ErrorScope::push(device, &self.labels.pipeline());
+        let pipeline = self.create_pipeline(device, shaders, bind_group_layouts);
+        scope.pop_now(device)?;
+
+        pipeline
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &Device,
+        shaders: &ShaderRegistry,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<RenderPipeline, RendererError>
+    {
+        let shared_module = self.fragment_stage.as_ref()
+            .is_some_and(|fragment| fragment.shader == self.vertex_stage.shader);
+        let vertex_label = if shared_module {
+            self.labels.shader()
+        } else {
+            self.labels.with_suffix("Vertex Shader")
+        };
+        let vertex_module = create_shader_module(device, shaders, &self.preprocessor,
+            self.vertex_stage.shader, &vertex_label)?;
+        let fragment_module = match &self.fragment_stage {
+            Some(_) if shared_module => None,
+            Some(fragment) => Some(create_shader_module(device, shaders, &self.preprocessor,
+                fragment.shader, &self.labels.with_suffix("Fragment Shader"))?),
+            None => None
+        };
+        let render_targets = self.get_render_targets();
+        let depth_compare = if shaders.defines().contains_key(REVERSE_Z_DEFINE) {
+            reverse_depth_compare(self.depth_compare)
+        } else {
+            self.depth_compare
+        };
 
-        let shader_module = device.create_shader_module(
-            ShaderModuleDescriptor {
-                label: Some("Shader"),
-                source: ShaderSource::Wgsl(source_code.into())
-            }
-        );
         let render_pipeline_layout = device.create_pipeline_layout(
             &PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: bind_group_layouts,
+                label: Some(&self.labels.pipeline_layout()),
+                bind_group_layouts,
                 push_constant_ranges: &[]
             }
         );
 
-        device.create_render_pipeline(
+        Ok(device.create_render_pipeline(
             &RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
+                label: Some(&self.labels.pipeline()),
                 layout: Some(&render_pipeline_layout),
                 vertex: VertexState {
-                    module: &shader_module,
-                    entry_point: &self.vertex_entry,
-                    buffers: &[
-                        Vertex::get_vertex_buffer_layout(),
-                        InstanceRaw::get_vertex_buffer_layout()
-                    ]
+                    module: &vertex_module,
+                    entry_point: &self.vertex_stage.entry_point,
+                    buffers: &self.vertex_layouts
                 },
                 primitive: PrimitiveState {
-                    topology: PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: FrontFace::Ccw,
-                    cull_mode: Some(Face::Back),
-                    polygon_mode: PolygonMode::Fill,
+                    topology: self.topology,
+                    // Strips restart on the maximum index, and every index buffer here is u16.
+                    strip_index_format: self.topology.is_strip().then_some(IndexFormat::Uint16),
+                    front_face: self.front_face,
+                    cull_mode: self.cull_mode,
+                    polygon_mode: self.polygon_mode,
                     unclipped_depth: false,
                     conservative: false
                 },
-                fragment: Some(FragmentState {
-                    module: &shader_module,
-                    entry_point: &self.fragment_entry,
-                    targets: &self.get_render_targets()
+                fragment: self.fragment_stage.as_ref().map(|fragment| FragmentState {
+                    module: fragment_module.as_ref().unwrap_or(&vertex_module),
+                    entry_point: &fragment.entry_point,
+                    targets: &render_targets
+                }),
+                depth_stencil: self.depth_enabled.then(|| DepthStencilState {
+                    format: self.depth_format,
+                    depth_write_enabled: self.depth_write_enabled,
+                    depth_compare,
+                    stencil: self.stencil.clone(),
+                    bias: DepthBiasState::default()
                 }),
-                depth_stencil: Some(
-                    DepthStencilState {
-                        format: Texture::DEPTH_FORMAT,
-                        depth_write_enabled: true,
-                        depth_compare: CompareFunction::Less,
-                        stencil: StencilState::default(),
-                        bias: DepthBiasState::default()
-                    }
-                ),
                 multisample: MultisampleState {
-                    count: 1,
+                    count: self.sample_count,
                     mask: !0,
-                    alpha_to_coverage_enabled: false
+                    alpha_to_coverage_enabled: self.alpha_to_coverage
                 },
                 multiview: None
             }
-        )
+        ))
     }
 
-    fn get_render_targets(&self) -> [Option<ColorTargetState>; 1]
+    fn get_render_targets(&self) -> Vec<Option<ColorTargetState>>
     {
-        [
+        if !self.color_target {
+            return Vec::new();
+        }
+
+        vec![
             Some(ColorTargetState {
                 format: self.pixel_format,
-                blend: Some(BlendState::REPLACE),
-                write_mask: ColorWrites::ALL
+                blend: Some(self.blend_state),
+                write_mask: self.color_writes
             })
         ]
     }
 }
+
+fn reverse_depth_compare(compare: CompareFunction) -> CompareFunction
+{
+    match compare {
+        CompareFunction::Less => CompareFunction::Greater,
+        CompareFunction::LessEqual => CompareFunction::GreaterEqual,
+        CompareFunction::Greater => CompareFunction::Less,
+        CompareFunction::GreaterEqual => CompareFunction::LessEqual,
+        other => other
+    }
+}
+
+pub fn create_shader_module(
+    device: &Device,
+    shaders: &ShaderRegistry,
+    preprocessor: &ShaderPreprocessor,
+    shader: ShaderHandle,
+    label: &str
+) -> Result<ShaderModule, RendererError>
+{
+    let shader = preprocessor.process(shaders, shader)?;
+    shader_validation::validate(&shader)?;
+
+    Ok(device.create_shader_module(
+        ShaderModuleDescriptor {
+            label: Some(label),
+            source: ShaderSource::Wgsl(shader.source.into())
+        }
+    ))
+}
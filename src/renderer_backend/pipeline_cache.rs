@@ -0,0 +1,91 @@
+use std::{collections::HashMap, rc::Rc};
+
+use wgpu::{BindGroupLayout, BlendState, ColorWrites, CompareFunction, Device, Face, FrontFace, Id, PolygonMode, PrimitiveTopology, RenderPipeline, StencilState, TextureFormat, VertexBufferLayout};
+
+use crate::error::RendererError;
+
+use super::{pipeline_builder::{PipelineBuilder, ShaderStage}, shader_registry::{ShaderHandle, ShaderRegistry}};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub vertex_stage: ShaderStage,
+    pub defines: Vec<(String, String)>,
+    pub fragment_stage: Option<ShaderStage>,
+    pub vertex_layouts: Vec<VertexBufferLayout<'static>>,
+    pub pixel_format: TextureFormat,
+    pub topology: PrimitiveTopology,
+    pub cull_mode: Option<Face>,
+    pub front_face: FrontFace,
+    pub polygon_mode: PolygonMode,
+    pub blend_state: BlendState,
+    pub color_writes: ColorWrites,
+    pub color_target: bool,
+    pub depth_enabled: bool,
+    pub depth_format: TextureFormat,
+    pub depth_write_enabled: bool,
+    pub depth_compare: CompareFunction,
+    pub stencil: StencilState,
+    pub sample_count: u32,
+    pub alpha_to_coverage: bool,
+    pub bind_group_layouts: Vec<Id<BindGroupLayout>>
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PipelineCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub cached: usize
+}
+
+// Pipelines are looked up by everything that goes into building them, so asking for
+// the same variant twice hands back the already compiled pipeline.
+#[derive(Default)]
+pub struct PipelineCache {
+    pipelines: HashMap<PipelineKey, Rc<RenderPipeline>>,
+    hits: u64,
+    misses: u64
+}
+
+impl PipelineCache {
+    pub fn get_or_build(
+        &mut self,
+        builder: &mut PipelineBuilder,
+        device: &Device,
+        shaders: &ShaderRegistry,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let key = builder.cache_key(bind_group_layouts);
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            self.hits += 1;
+            return Ok(pipeline.clone());
+        }
+
+        self.misses += 1;
+        let pipeline = Rc::new(builder.build(device, shaders, bind_group_layouts)?);
+        self.pipelines.insert(key, pipeline.clone());
+
+        Ok(pipeline)
+    }
+
+    // Drops every pipeline built from `shader`, e.g. after its source changed on disk.
+    pub fn invalidate_shader(&mut self, shader: ShaderHandle)
+    {
+        self.pipelines.retain(|key, _| key.vertex_stage.shader != shader
+            && key.fragment_stage.as_ref().is_none_or(|stage| stage.shader != shader));
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.pipelines.clear();
+    }
+
+    pub fn stats(&self) -> PipelineCacheStats
+    {
+        PipelineCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            cached: self.pipelines.len()
+        }
+    }
+}
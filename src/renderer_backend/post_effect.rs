@@ -0,0 +1,174 @@
+use std::rc::Rc;
+
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Device, Sampler, SamplerBindingType, ShaderStages, SurfaceConfiguration, TextureSampleType, TextureUsages, TextureView, TextureViewDimension};
+
+use super::{debug_labels::DebugLabels, gpu_capabilities::GpuCapabilities, pipeline_builder::PipelineBuilder, sampler_cache::{SamplerCache, SamplerSpec}, shader_registry::ShaderHandle, taa::MOTION_VECTOR_FORMAT, transient::TransientTextureDesc};
+
+// Fullscreen passes over the finished frame, each reading what the one before it wrote
+// and the last one writing the surface. They run in the order they're declared in, so
+// anti-aliasing comes after everything that changes colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PostEffect {
+    // Multiplies in the ambient occlusion worked out by the SSAO passes before it.
+    Ssao,
+    // Distance and height fog by the depth the main pass left, and a daylight sky
+    // where nothing was drawn.
+    Fog,
+    // Blurs what's away from the focus distance by the depth the main pass left.
+    DepthOfField,
+    // Smears every pixel along its motion vector, camera and object motion both.
+    MotionBlur,
+    // Spreads light from what's brighter than a threshold, emissive surfaces mostly,
+    // over what's around it.
+    Glow,
+    // Exposure, white balance, contrast, saturation and a 3D LUT, the look of the
+    // frame once everything else that changes it is done.
+    ColorGrading,
+    // Smooths the edges it finds by luma contrast. Cheap, and needs nothing WebGL2
+    // doesn't have.
+    Fxaa
+}
+
+impl PostEffect {
+    // Motion blur needs motion vectors, which like TAA's are a float target WebGL2 only
+    // has with an extension.
+    pub fn is_supported(&self, capabilities: &GpuCapabilities) -> bool
+    {
+        match self {
+            PostEffect::MotionBlur => capabilities.can_render_to(MOTION_VECTOR_FORMAT),
+            _ => true
+        }
+    }
+
+    pub fn label(&self) -> &'static str
+    {
+        match self {
+            PostEffect::Ssao => "SSAO",
+            PostEffect::Fog => "Fog",
+            PostEffect::DepthOfField => "Depth of Field",
+            PostEffect::MotionBlur => "Motion Blur",
+            PostEffect::Glow => "Glow",
+            PostEffect::ColorGrading => "Color Grading",
+            PostEffect::Fxaa => "FXAA"
+        }
+    }
+
+    pub fn shader(&self) -> ShaderHandle
+    {
+        match self {
+            PostEffect::Ssao => ShaderHandle::Ssao,
+            PostEffect::Fog => ShaderHandle::Fog,
+            PostEffect::DepthOfField => ShaderHandle::DepthOfField,
+            PostEffect::MotionBlur => ShaderHandle::MotionBlur,
+            PostEffect::Glow => ShaderHandle::Glow,
+            PostEffect::ColorGrading => ShaderHandle::ColorGrading,
+            PostEffect::Fxaa => ShaderHandle::Fxaa
+        }
+    }
+
+    // Sets up everything but the pixel format for this effect's pipeline.
+    pub fn configure(&self, builder: &mut PipelineBuilder)
+    {
+        let fragment_entry = match self {
+            PostEffect::Ssao => "fs_composite",
+            PostEffect::Fog => "fs_fog",
+            PostEffect::DepthOfField => "fs_depth_of_field",
+            PostEffect::MotionBlur => "fs_motion_blur",
+            PostEffect::Glow => "fs_glow",
+            PostEffect::ColorGrading => "fs_color_grading",
+            PostEffect::Fxaa => "fs_fxaa"
+        };
+
+        builder
+            .set_label(self.label())
+            .set_shader_module(self.shader(), "vs_fullscreen", fragment_entry)
+            .set_fullscreen();
+    }
+}
+
+// What every post effect binds: the frame so far and a linear sampler for it. Blits
+// to the surface go through the same layout.
+pub struct PostProcess {
+    labels: DebugLabels,
+    bind_group_layout: BindGroupLayout,
+    sampler: Rc<Sampler>
+}
+
+impl PostProcess {
+    pub fn new(device: &Device, label: &str, samplers: &mut SamplerCache) -> Self
+    {
+        let labels = DebugLabels::new(label);
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            sampler: samplers.get(device, SamplerSpec::default().with_anisotropy(1))
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Float { filterable: true }
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    // The main pass's target when anything runs after it, and every target between
+    // two effects.
+    pub fn target_desc(&self, config: &SurfaceConfiguration) -> TransientTextureDesc
+    {
+        TransientTextureDesc {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            format: config.format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            sample_count: 1
+        }
+    }
+
+    // Sources change from frame to frame, so the bind group is rebuilt along with them.
+    pub fn create_bind_group(&self, device: &Device, source: &TextureView) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(source)
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&self.sampler)
+                    }
+                ]
+            }
+        )
+    }
+}
@@ -0,0 +1,248 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Deg, Rad};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroupLayout, BindingResource, Buffer, BufferUsages, CommandEncoderDescriptor, Device, Queue, TextureFormat, TextureView};
+
+use crate::error::RendererError;
+
+use super::{compute::{create_compute_bind_group, ComputeBindingsBuilder, ComputePass}, debug_labels::DebugLabels, gpu_capabilities::GpuCapabilities, shader_registry::{ShaderHandle, ShaderRegistry}, texture::Texture};
+
+// Matches WORKGROUP_SIZE in procedural.wgsl.
+pub const PROCEDURAL_WORKGROUP_SIZE: u32 = 8;
+// Matches the storage texture in procedural.wgsl. None of the sRGB formats can be
+// written from a compute shader, so the colors are stored linear.
+pub const PROCEDURAL_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+// GL takes a texture with a single layer for a plain 2D one, so procedural textures
+// have the same pattern in two, as Texture::from_images does with a single image.
+pub const PROCEDURAL_LAYERS: u32 = 2;
+
+// What a procedural texture shows. Colors are linear, the pattern goes from the first
+// to the second. Every pattern tiles, so the texture can repeat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProceduralPattern {
+    // Fractal value noise, `cells` across at the coarsest of its octaves, each octave
+    // twice as fine and half as strong as the one before.
+    Noise {
+        cells: u32,
+        octaves: u32,
+        seed: u32,
+        colors: [[f32; 4]; 2]
+    },
+    Checkerboard {
+        cells: u32,
+        colors: [[f32; 4]; 2]
+    },
+    // Across the texture, 0 degrees going from left to right and 90 from top to bottom.
+    Gradient {
+        angle: Deg<f32>,
+        colors: [[f32; 4]; 2]
+    }
+}
+
+impl ProceduralPattern {
+    pub fn label(&self) -> &'static str
+    {
+        match self {
+            Self::Noise { .. } => "Noise",
+            Self::Checkerboard { .. } => "Checkerboard",
+            Self::Gradient { .. } => "Gradient"
+        }
+    }
+
+    fn uniform(&self) -> ProceduralUniform
+    {
+        let (kind, cells, octaves, seed, angle, colors) = match *self {
+            Self::Noise { cells, octaves, seed, colors } => (0, cells, octaves, seed, 0.0, colors),
+            Self::Checkerboard { cells, colors } => (1, cells, 0, 0, 0.0, colors),
+            Self::Gradient { angle, colors } => (2, 0, 0, 0, Rad::from(angle).0, colors)
+        };
+
+        ProceduralUniform {
+            colors,
+            params: [kind, cells.max(1), octaves.clamp(1, 8), seed],
+            angle: [angle, 0.0, 0.0, 0.0]
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ProceduralUniform {
+    colors: [[f32; 4]; 2],
+    // pattern, cells, octaves, seed
+    params: [u32; 4],
+    // x the gradient's angle in radians
+    angle: [f32; 4]
+}
+
+// The compute pipeline every procedural texture is generated with, see
+// Texture::from_compute.
+pub struct TextureGenerator {
+    labels: DebugLabels,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    compute_pass: ComputePass
+}
+
+impl TextureGenerator {
+    pub fn is_supported(capabilities: &GpuCapabilities) -> bool
+    {
+        capabilities.supports_compute()
+    }
+
+    pub fn new(device: &Device, shaders: &ShaderRegistry, label: &str) -> Result<Self, RendererError>
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[ProceduralUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let bind_group_layout = ComputeBindingsBuilder::builder()
+            .storage_texture_array(PROCEDURAL_FORMAT)
+            .uniform_buffer()
+            .build(device, &labels);
+        let compute_pass = Self::create_compute_pass(device, shaders, &labels, &bind_group_layout)?;
+
+        Ok(Self {
+            labels,
+            uniform_buffer,
+            bind_group_layout,
+            compute_pass
+        })
+    }
+
+    fn create_compute_pass(
+        device: &Device,
+        shaders: &ShaderRegistry,
+        labels: &DebugLabels,
+        bind_group_layout: &BindGroupLayout
+    ) -> Result<ComputePass, RendererError>
+    {
+        ComputePass::new(device, shaders, labels.name(), ShaderHandle::Procedural, "cs_procedural",
+            [PROCEDURAL_WORKGROUP_SIZE, PROCEDURAL_WORKGROUP_SIZE, 1], &[bind_group_layout])
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    // After the shader changed, the last good pipeline is kept when it doesn't build.
+    pub fn rebuild(&mut self, device: &Device, shaders: &ShaderRegistry) -> Result<(), RendererError>
+    {
+        self.compute_pass = Self::create_compute_pass(device, shaders, &self.labels,
+            &self.bind_group_layout)?;
+
+        Ok(())
+    }
+
+    // Fills every layer of `target`, a view of a PROCEDURAL_FORMAT texture with
+    // STORAGE_BINDING and PROCEDURAL_LAYERS layers, in a submission of its own. The uniforms are shared, so generating another pattern
+    // has to wait for this one's submission, which the queue's order takes care of.
+    pub fn generate(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        pattern: &ProceduralPattern,
+        target: &TextureView,
+        size: (u32, u32)
+    )
+    {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[pattern.uniform()]));
+        let bind_group = create_compute_bind_group(device, &self.labels, &self.bind_group_layout,
+            vec![
+                BindingResource::TextureView(target),
+                self.uniform_buffer.as_entire_binding()
+            ]);
+
+        let mut command_encoder = device.create_command_encoder(
+            &CommandEncoderDescriptor {
+                label: Some(&self.labels.with_suffix("Encoder"))
+            }
+        );
+        self.compute_pass.encode(&mut command_encoder, &[&bind_group], [size.0, size.1, PROCEDURAL_LAYERS]);
+        queue.submit(std::iter::once(command_encoder.finish()));
+    }
+}
+
+// A texture generated on the GPU, which materials made with Material::from_procedural
+// show in place of an image. Only the pattern is kept, the texels are generated again
+// when it changes or after a device loss.
+pub struct ProceduralTexture {
+    labels: DebugLabels,
+    pattern: ProceduralPattern,
+    width: u32,
+    height: u32,
+    texture: Texture
+}
+
+impl ProceduralTexture {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        generator: &TextureGenerator,
+        label: &str,
+        pattern: ProceduralPattern,
+        (width, height): (u32, u32)
+    ) -> Result<Self, RendererError>
+    {
+        let (width, height) = (width.max(1), height.max(1));
+        let texture = Texture::from_compute(device, queue, generator, &pattern, (width, height),
+            Some(label))?;
+
+        Ok(Self {
+            labels: DebugLabels::new(label),
+            pattern,
+            width,
+            height,
+            texture
+        })
+    }
+
+    // After a device loss, with the new device's generator.
+    pub fn recreate(&mut self, device: &Device, queue: &Queue, generator: &TextureGenerator) -> Result<(), RendererError>
+    {
+        self.texture = Texture::from_compute(device, queue, generator, &self.pattern,
+            (self.width, self.height), Some(self.labels.name()))?;
+
+        Ok(())
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn size(&self) -> (u32, u32)
+    {
+        (self.width, self.height)
+    }
+
+    pub fn pattern(&self) -> &ProceduralPattern
+    {
+        &self.pattern
+    }
+
+    // Generates the texels again in the same texture, so the bind groups of the
+    // materials showing it stay valid.
+    pub fn set_pattern(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        generator: &TextureGenerator,
+        pattern: ProceduralPattern
+    )
+    {
+        self.pattern = pattern;
+        let target = self.texture.storage_view(Some(&self.labels.with_suffix("Storage View")));
+        generator.generate(device, queue, &self.pattern, &target, (self.width, self.height));
+    }
+
+    // A D2Array view, as materials bind their textures.
+    pub fn view(&self) -> &TextureView
+    {
+        &self.texture.view
+    }
+}
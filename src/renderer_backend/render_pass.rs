@@ -0,0 +1,83 @@
+use wgpu::{Color, LoadOp, Operations, StoreOp};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderPassConfig {
+    pub color_load_op: LoadOp<Color>,
+    pub clear_depth: bool,
+    pub depth_clear_value: f32,
+    // Tints the clear color by the cursor position, off so a configured color or Load stays.
+    pub cursor_tint: bool
+}
+
+impl Default for RenderPassConfig {
+    fn default() -> Self
+    {
+        Self {
+            color_load_op: LoadOp::Clear(
+                Color {
+                    r: 0.1,
+                    g: 0.2,
+                    b: 0.3,
+                    a: 1.0
+                }
+            ),
+            clear_depth: true,
+            depth_clear_value: 1.0,
+            cursor_tint: false
+        }
+    }
+}
+
+impl RenderPassConfig {
+    // Clears depth to the far plane, which is at 0 with reverse Z.
+    pub fn for_depth(reverse_z: bool) -> Self
+    {
+        Self {
+            depth_clear_value: if reverse_z { 0.0 } else { 1.0 },
+            ..Self::default()
+        }
+    }
+
+    pub fn color_operations(&self) -> Operations<Color>
+    {
+        Operations {
+            load: self.color_load_op,
+            store: StoreOp::Store
+        }
+    }
+
+    pub fn depth_operations(&self) -> Operations<f32>
+    {
+        Operations {
+            load: if self.clear_depth {
+                LoadOp::Clear(self.depth_clear_value)
+            } else {
+                LoadOp::Load
+            },
+            store: StoreOp::Store
+        }
+    }
+
+    // Offscreen targets are always cleared, pooled ones may still hold another pass's
+    // contents. A Load color op falls back to black.
+    pub fn offscreen_color_operations(&self) -> Operations<Color>
+    {
+        let clear_color = match self.color_load_op {
+            LoadOp::Clear(color) => color,
+            LoadOp::Load => Color::BLACK
+        };
+
+        Operations {
+            load: LoadOp::Clear(clear_color),
+            store: StoreOp::Store
+        }
+    }
+
+    pub fn offscreen_depth_operations(&self) -> Operations<f32>
+    {
+        Operations {
+            load: LoadOp::Clear(self.depth_clear_value),
+            store: StoreOp::Store
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use wgpu::SurfaceConfiguration;
+
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+
+// How a frame rendered at another scale is brought to the surface's size. Named in
+// lowercase in the settings file, e.g. upscaling = "sharpened".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Upscaling {
+    #[default]
+    Bilinear,
+    // Bilinear, then contrast adaptive sharpening like FSR1's, which brings back some of
+    // the detail a lower scale blurs away.
+    Sharpened
+}
+
+impl Upscaling {
+    pub fn label(&self) -> &'static str
+    {
+        match self {
+            Upscaling::Bilinear => "Bilinear",
+            Upscaling::Sharpened => "Sharpened"
+        }
+    }
+
+    // The entry point in blit.wgsl.
+    pub fn fragment_entry(&self) -> &'static str
+    {
+        match self {
+            Upscaling::Bilinear => "fs_blit",
+            Upscaling::Sharpened => "fs_sharpen"
+        }
+    }
+}
+
+pub fn clamp_render_scale(scale: f32) -> f32
+{
+    if scale.is_finite() {
+        scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE)
+    } else {
+        1.0
+    }
+}
+
+// The surface's configuration at `scale`, for everything rendered before the upscale.
+pub fn scaled_config(config: &SurfaceConfiguration, scale: f32) -> SurfaceConfiguration
+{
+    let scale_size = |size: u32| ((size as f32 * scale).round() as u32).max(1);
+
+    SurfaceConfiguration {
+        width: scale_size(config.width),
+        height: scale_size(config.height),
+        ..config.clone()
+    }
+}
@@ -0,0 +1,199 @@
+use cgmath::Point3;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, Buffer, BufferUsages, Device, Extent3d, Queue, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension};
+
+use crate::state::camera::{Camera, CameraUniform};
+
+use super::{debug_labels::DebugLabels, texture::Texture};
+
+// A color and depth pair the scene is rendered into from its own camera, before the
+// main pass of every frame, like the swapchain image is from the main one. Materials
+// made with Material::from_render_target then show what it saw, e.g. as a security
+// camera screen or a portal. The color is in the surface format, so every pipeline
+// that draws to the screen can draw into it too.
+pub struct RenderTarget {
+    labels: DebugLabels,
+    width: u32,
+    height: u32,
+    camera: Camera,
+    color: Texture,
+    depth: Texture,
+    camera_buffer: Buffer,
+    camera_bind_group: BindGroup
+}
+
+impl RenderTarget {
+    // The camera's aspect is set to the target's.
+    pub fn new(
+        device: &Device,
+        label: &str,
+        (width, height): (u32, u32),
+        format: TextureFormat,
+        mut camera: Camera,
+        camera_bind_group_layout: &BindGroupLayout
+    ) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let (width, height) = (width.max(1), height.max(1));
+        camera.aspect = width as f32 / height as f32;
+        let (color, depth) = Self::create_textures(device, &labels, width, height, format);
+        let (camera_buffer, camera_bind_group) = Self::create_camera_binding(device, &labels,
+            camera_bind_group_layout, &camera);
+
+        Self {
+            labels,
+            width,
+            height,
+            camera,
+            color,
+            depth,
+            camera_buffer,
+            camera_bind_group
+        }
+    }
+
+    // After a device loss, with the new device's surface format.
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        camera_bind_group_layout: &BindGroupLayout
+    )
+    {
+        (self.color, self.depth) = Self::create_textures(device, &self.labels, self.width,
+            self.height, format);
+        (self.camera_buffer, self.camera_bind_group) = Self::create_camera_binding(device,
+            &self.labels, camera_bind_group_layout, &self.camera);
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn size(&self) -> (u32, u32)
+    {
+        (self.width, self.height)
+    }
+
+    pub fn camera(&self) -> &Camera
+    {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera
+    {
+        &mut self.camera
+    }
+
+    // A D2Array of one layer, as materials sample it.
+    pub fn view(&self) -> &TextureView
+    {
+        &self.color.view
+    }
+
+    // A D2 view of the color, as a multisampled pass can only resolve into those.
+    pub fn resolve_view(&self) -> TextureView
+    {
+        self.color.texture.create_view(
+            &TextureViewDescriptor {
+                label: Some(&self.labels.with_suffix("Color Resolve View")),
+                dimension: Some(TextureViewDimension::D2),
+                ..Default::default()
+            }
+        )
+    }
+
+    pub fn depth_view(&self) -> &TextureView
+    {
+        &self.depth.view
+    }
+
+    pub fn camera_bind_group(&self) -> &BindGroup
+    {
+        &self.camera_bind_group
+    }
+
+    // `origin` is what the instances are uploaded relative to.
+    pub fn write_camera(&self, queue: &Queue, origin: Point3<f64>)
+    {
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.set_origin(origin);
+        camera_uniform.update_view_proj(&self.camera);
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+    }
+
+    fn create_textures(
+        device: &Device,
+        labels: &DebugLabels,
+        width: u32,
+        height: u32,
+        format: TextureFormat
+    ) -> (Texture, Texture)
+    {
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1
+        };
+        let create = |suffix: &str, format, view_dimension| {
+            let texture = device.create_texture(
+                &TextureDescriptor {
+                    label: Some(&labels.with_suffix(suffix)),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[]
+                }
+            );
+            let view = texture.create_view(
+                &TextureViewDescriptor {
+                    label: Some(&labels.with_suffix(&format!("{suffix} View"))),
+                    dimension: Some(view_dimension),
+                    ..Default::default()
+                }
+            );
+
+            Texture { texture, view }
+        };
+
+        (
+            create("Color", format, TextureViewDimension::D2Array),
+            create("Depth", Texture::DEPTH_FORMAT, TextureViewDimension::D2)
+        )
+    }
+
+    fn create_camera_binding(
+        device: &Device,
+        labels: &DebugLabels,
+        layout: &BindGroupLayout,
+        camera: &Camera
+    ) -> (Buffer, BindGroup)
+    {
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(camera);
+        let camera_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix("Camera Buffer")),
+                contents: bytemuck::cast_slice(&[camera_uniform]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let camera_bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.with_suffix("Camera Bind Group")),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: camera_buffer.as_entire_binding()
+                    }
+                ]
+            }
+        );
+
+        (camera_buffer, camera_bind_group)
+    }
+}
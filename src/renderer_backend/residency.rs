@@ -0,0 +1,335 @@
+use std::{collections::HashMap, hash::Hash};
+
+use anyhow::*;
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Device, Queue, Sampler};
+
+use super::{debug_labels::DebugLabels, texture::{Texture, TextureKind}};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResidencyStats {
+    pub budget_bytes: u64,
+    pub resident_bytes: u64,
+    pub resident_count: usize,
+    pub evictions: u64
+}
+
+// What the renderer keeps within its budget: the material texture array, and the
+// asset textures and meshes by the id their handles share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResidentAsset {
+    DiffuseArray,
+    Texture(u64),
+    Mesh(u64)
+}
+
+struct ResidencyEntry {
+    size_bytes: u64,
+    last_used_frame: u64
+}
+
+pub struct ResidencyManager<K> {
+    budget_bytes: u64,
+    frame: u64,
+    entries: HashMap<K, ResidencyEntry>,
+    evictions: u64
+}
+
+impl<K: Eq + Hash + Clone> ResidencyManager<K> {
+    pub fn new(budget_bytes: u64) -> Self
+    {
+        Self {
+            budget_bytes,
+            frame: 0,
+            entries: HashMap::new(),
+            evictions: 0
+        }
+    }
+
+    pub fn set_budget(&mut self, budget_bytes: u64)
+    {
+        self.budget_bytes = budget_bytes;
+    }
+
+    pub fn begin_frame(&mut self)
+    {
+        self.frame += 1;
+    }
+
+    pub fn make_resident(&mut self, key: K, size_bytes: u64)
+    {
+        self.entries.insert(key, ResidencyEntry {
+            size_bytes,
+            last_used_frame: self.frame
+        });
+    }
+
+    // Returns false when the asset isn't resident and has to be reloaded by the caller.
+    pub fn touch(&mut self, key: &K) -> bool
+    {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used_frame = self.frame;
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn is_resident(&self, key: &K) -> bool
+    {
+        self.entries.contains_key(key)
+    }
+
+    // After the asset's GPU copy changed size, e.g. streamed to another mip, without
+    // counting as a use.
+    pub fn resize(&mut self, key: &K, size_bytes: u64)
+    {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.size_bytes = size_bytes;
+        }
+    }
+
+    // Stops tracking the assets `keep` returns false for, e.g. ones that were unloaded.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool)
+    {
+        self.entries.retain(|key, _| keep(key));
+    }
+
+    pub fn resident_bytes(&self) -> u64
+    {
+        self.entries.values().map(|entry| entry.size_bytes).sum()
+    }
+
+    // Evicts least recently used assets until the budget is met. Assets used in the
+    // current frame are never evicted, so the budget may be exceeded temporarily.
+    pub fn evict_over_budget(&mut self) -> Vec<K>
+    {
+        let mut resident_bytes = self.resident_bytes();
+        if resident_bytes <= self.budget_bytes {
+            return Vec::new();
+        }
+
+        let mut candidates = self.entries.iter()
+            .filter(|(_, entry)| entry.last_used_frame < self.frame)
+            .map(|(key, entry)| (key.clone(), entry.last_used_frame, entry.size_bytes))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, last_used_frame, _)| *last_used_frame);
+
+        let mut evicted = Vec::new();
+        for (key, _, size_bytes) in candidates {
+            if resident_bytes <= self.budget_bytes {
+                break;
+            }
+
+            self.entries.remove(&key);
+            resident_bytes -= size_bytes;
+            evicted.push(key);
+        }
+        self.evictions += evicted.len() as u64;
+
+        evicted
+    }
+
+    pub fn stats(&self) -> ResidencyStats
+    {
+        ResidencyStats {
+            budget_bytes: self.budget_bytes,
+            resident_bytes: self.resident_bytes(),
+            resident_count: self.entries.len(),
+            evictions: self.evictions
+        }
+    }
+}
+
+// Mips up to this size go to the GPU right away, the finer ones are streamed in.
+pub const STREAMING_BASE_SIZE: u32 = 64;
+
+// Keeps the decoded images on the CPU so the GPU copy can be dropped and recreated on
+// demand. Every image is one layer of a texture array, bound with
+// Texture::get_texture_array_bind_group_layout.
+//
+// Only the mips from resident_mip down are on the GPU. make_resident starts at the
+// base mip, the TextureStreamer moves it to finer ones and back.
+pub struct ResidentTexture {
+    label: String,
+    images: Vec<DynamicImage>,
+    kind: TextureKind,
+    // `mips[level][layer]`, generated the first time the texture goes to the GPU.
+    mips: Vec<Vec<RgbaImage>>,
+    resident_mip: u32,
+    gpu: Option<(Texture, BindGroup)>
+}
+
+impl ResidentTexture {
+    pub fn new(label: &str, image: DynamicImage, kind: TextureKind) -> Self
+    {
+        let mut texture = Self {
+            label: String::from(label),
+            images: vec![image],
+            kind,
+            mips: Vec::new(),
+            resident_mip: 0,
+            gpu: None
+        };
+        texture.resident_mip = texture.base_mip();
+
+        texture
+    }
+
+    pub fn label(&self) -> &str
+    {
+        &self.label
+    }
+
+    pub fn kind(&self) -> TextureKind
+    {
+        self.kind
+    }
+
+    pub fn width(&self) -> u32
+    {
+        self.images[0].width()
+    }
+
+    // The full chain, down to 1x1.
+    pub fn num_mips(&self) -> u32
+    {
+        let (width, height) = self.images[0].dimensions();
+
+        u32::BITS - width.max(height).max(1).leading_zeros()
+    }
+
+    // The finest mip no larger than STREAMING_BASE_SIZE.
+    pub fn base_mip(&self) -> u32
+    {
+        let (width, height) = self.images[0].dimensions();
+
+        (0..self.num_mips())
+            .find(|mip| width.max(height) >> mip <= STREAMING_BASE_SIZE)
+            .unwrap_or(0)
+    }
+
+    pub fn resident_mip(&self) -> u32
+    {
+        self.resident_mip
+    }
+
+    // Every layer is stored at the size of the first, with the mips from resident_mip
+    // down.
+    pub fn size_in_bytes(&self) -> u64
+    {
+        self.size_from_mip(self.resident_mip)
+    }
+
+    pub fn size_from_mip(&self, first_mip: u32) -> u64
+    {
+        let (width, height) = self.images[0].dimensions();
+
+        (first_mip..self.num_mips())
+            .map(|mip| self.kind.bytes_per_pixel() as u64 * (width >> mip).max(1) as u64 * (height >> mip).max(1) as u64)
+            .sum::<u64>() * self.images.len() as u64
+    }
+
+    pub fn num_layers(&self) -> u32
+    {
+        self.images.len() as u32
+    }
+
+    // Returns the new layer's index. The GPU copy is dropped, as the array can't grow
+    // in place, and comes back with the next make_resident.
+    pub fn push_layer(&mut self, image: DynamicImage) -> u32
+    {
+        self.images.push(image);
+        self.mips.clear();
+        self.evict();
+
+        self.num_layers() - 1
+    }
+
+    pub fn is_resident(&self) -> bool
+    {
+        self.gpu.is_some()
+    }
+
+    pub fn make_resident(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        sampler: &Sampler
+    ) -> Result<()>
+    {
+        let first_mip = self.resident_mip;
+        self.upload(device, queue, layout, sampler, first_mip)
+    }
+
+    // Replaces the GPU copy with one holding the mips from first_mip down. The old one
+    // is only dropped, as bind groups made from it may still be in flight.
+    pub fn stream_to(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        first_mip: u32
+    ) -> Result<()>
+    {
+        self.upload(device, queue, layout, sampler, first_mip.min(self.num_mips() - 1))
+    }
+
+    pub fn evict(&mut self)
+    {
+        if let Some((texture, _)) = self.gpu.take() {
+            texture.texture.destroy();
+        }
+        self.resident_mip = self.base_mip();
+    }
+
+    pub fn texture(&self) -> Option<&Texture>
+    {
+        self.gpu.as_ref().map(|(texture, _)| texture)
+    }
+
+    pub fn bind_group(&self) -> Option<&BindGroup>
+    {
+        self.gpu.as_ref().map(|(_, bind_group)| bind_group)
+    }
+
+    fn upload(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        first_mip: u32
+    ) -> Result<()>
+    {
+        if self.mips.is_empty() {
+            self.mips = Texture::generate_mips(&self.images)?;
+        }
+        let texture = Texture::from_mips(device, queue, &self.mips[first_mip as usize..],
+            self.kind, Some(&self.label))?;
+        let bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&DebugLabels::new(&self.label).bind_group()),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&texture.view)
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler)
+                    }
+                ]
+            }
+        );
+
+        self.gpu = Some((texture, bind_group));
+        self.resident_mip = first_mip;
+
+        Ok(())
+    }
+}
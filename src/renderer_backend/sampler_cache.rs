@@ -0,0 +1,134 @@
+use std::{collections::HashMap, rc::Rc};
+
+use wgpu::{AddressMode, CompareFunction, Device, FilterMode, Sampler, SamplerDescriptor};
+
+use super::debug_labels::DebugLabels;
+
+pub const DEFAULT_ANISOTROPY: u16 = 16;
+
+// Everything that tells samplers apart. The default is trilinear and anisotropic,
+// clamped to the edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerSpec {
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    // 1 turns it off. Only applies with every filter linear.
+    pub anisotropy: u16,
+    pub compare: Option<CompareFunction>
+}
+
+impl Default for SamplerSpec {
+    fn default() -> Self
+    {
+        Self {
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            anisotropy: DEFAULT_ANISOTROPY,
+            compare: None
+        }
+    }
+}
+
+impl SamplerSpec {
+    pub fn nearest() -> Self
+    {
+        Self {
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            anisotropy: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_address_mode(mut self, address_mode: AddressMode) -> Self
+    {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: u16) -> Self
+    {
+        self.anisotropy = anisotropy;
+
+        self
+    }
+
+    fn is_linear(&self) -> bool
+    {
+        [self.mag_filter, self.min_filter, self.mipmap_filter].iter()
+            .all(|filter| *filter == FilterMode::Linear)
+    }
+}
+
+// Samplers shared by spec, so textures and materials sampled the same way bind the
+// same one. The anisotropy of a spec is clamped to what the adapter supports when the
+// sampler is created, specs keep what was asked for.
+pub struct SamplerCache {
+    samplers: HashMap<SamplerSpec, Rc<Sampler>>,
+    max_anisotropy: u16
+}
+
+impl SamplerCache {
+    // 1 when the adapter can't filter anisotropically.
+    pub fn new(max_anisotropy: u16) -> Self
+    {
+        Self {
+            samplers: HashMap::new(),
+            max_anisotropy: max_anisotropy.max(1)
+        }
+    }
+
+    pub fn get(&mut self, device: &Device, spec: SamplerSpec) -> Rc<Sampler>
+    {
+        let max_anisotropy = self.max_anisotropy;
+        self.samplers.entry(spec)
+            .or_insert_with(|| {
+                let anisotropy_clamp = if spec.is_linear() {
+                    spec.anisotropy.clamp(1, max_anisotropy)
+                } else {
+                    1
+                };
+
+                Rc::new(device.create_sampler(
+                    &SamplerDescriptor {
+                        label: Some(&DebugLabels::new("Shared").sampler()),
+                        address_mode_u: spec.address_mode_u,
+                        address_mode_v: spec.address_mode_v,
+                        address_mode_w: AddressMode::ClampToEdge,
+                        mag_filter: spec.mag_filter,
+                        min_filter: spec.min_filter,
+                        mipmap_filter: spec.mipmap_filter,
+                        compare: spec.compare,
+                        anisotropy_clamp,
+                        ..Default::default()
+                    }
+                ))
+            })
+            .clone()
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.samplers.is_empty()
+    }
+
+    // After a device loss, the samplers are made again on demand.
+    pub fn clear(&mut self)
+    {
+        self.samplers.clear();
+    }
+}
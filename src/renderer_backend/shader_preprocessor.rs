@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use crate::error::RendererError;
+
+use super::shader_registry::{ShaderHandle, ShaderRegistry};
+
+// Resolves `#include "file.wgsl"` (any registered shader, each file included once),
+// `#define NAME value` and `#ifdef NAME` / `#ifndef NAME` / `#else` / `#endif` blocks
+// before the source reaches wgpu, which knows none of them. Defines set on the
+// preprocessor apply to every file and win over in-source ones.
+// The expanded source plus where each of its lines came from, so diagnostics can
+// point at the original file instead of the flattened output.
+#[derive(Debug, Clone)]
+pub struct PreprocessedShader {
+    pub name: String,
+    pub source: String,
+    line_map: Vec<(String, usize)>
+}
+
+impl PreprocessedShader {
+    // `line` is 1-based, the returned line in the original file is as well.
+    pub fn original_location(&self, line: usize) -> (&str, usize)
+    {
+        line.checked_sub(1)
+            .and_then(|index| self.line_map.get(index))
+            .map_or((self.name.as_str(), line), |(file, line)| (file.as_str(), *line))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ShaderPreprocessor {
+    defines: BTreeMap<String, String>
+}
+
+impl ShaderPreprocessor {
+    pub fn set_define(&mut self, name: &str, value: &str) -> &mut Self
+    {
+        self.defines.insert(String::from(name), String::from(value));
+
+        self
+    }
+
+    pub fn defines(&self) -> &BTreeMap<String, String>
+    {
+        &self.defines
+    }
+
+    pub fn process(
+        &self,
+        shaders: &ShaderRegistry,
+        shader: ShaderHandle
+    ) -> Result<PreprocessedShader, RendererError>
+    {
+        let source = shaders.source(shader)?;
+
+        self.process_str(shaders, shader.filename(), &source)
+    }
+
+    // For source that isn't a registered shader, e.g. generated in a test. `name` is
+    // what diagnostics call it, it can still include registered shaders.
+    pub fn process_str(
+        &self,
+        shaders: &ShaderRegistry,
+        name: &str,
+        source: &str
+    ) -> Result<PreprocessedShader, RendererError>
+    {
+        let mut defines = shaders.defines().clone();
+        let mut included = Vec::new();
+        let mut output = PreprocessedShader {
+            name: String::from(name),
+            source: String::with_capacity(source.len()),
+            line_map: Vec::new()
+        };
+
+        self.process_source(shaders, name, source, &mut defines, &mut included, &mut Vec::new(),
+            &mut output)?;
+
+        Ok(output)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn process_source(
+        &self,
+        shaders: &ShaderRegistry,
+        shader: &str,
+        source: &str,
+        defines: &mut BTreeMap<String, String>,
+        included: &mut Vec<String>,
+        stack: &mut Vec<String>,
+        output: &mut PreprocessedShader
+    ) -> Result<(), RendererError>
+    {
+        stack.push(String::from(shader));
+        // Whether each open #ifdef block is taken, the innermost last.
+        let mut conditions: Vec<bool> = Vec::new();
+
+        for (line_number, line) in source.lines().enumerate() {
+            let trimmed = line.trim();
+            let error = |message: &str| RendererError::ShaderPreprocess {
+                shader: String::from(shader),
+                line: line_number + 1,
+                message: String::from(message)
+            };
+            let active = conditions.iter().all(|taken| *taken);
+
+            if let Some(name) = trimmed.strip_prefix("#ifdef").or_else(|| trimmed.strip_prefix("#ifndef")) {
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(error("expected a define name"));
+                }
+                let defined = self.defines.contains_key(name) || defines.contains_key(name);
+                conditions.push(defined == trimmed.starts_with("#ifdef"));
+            } else if trimmed.starts_with("#else") {
+                let taken = conditions.last_mut().ok_or_else(|| error("#else without #ifdef"))?;
+                *taken = !*taken;
+            } else if trimmed.starts_with("#endif") {
+                conditions.pop().ok_or_else(|| error("#endif without #ifdef"))?;
+            } else if !active {
+                // Skipped, but still counted so later lines map back correctly.
+            } else if let Some(include) = trimmed.strip_prefix("#include") {
+                let name = include.trim()
+                    .strip_prefix('"')
+                    .and_then(|name| name.strip_suffix('"'))
+                    .ok_or_else(|| error("expected #include \"file.wgsl\""))?;
+
+                if stack.iter().any(|parent| parent == name) {
+                    return Err(error(&format!("{name} includes itself")));
+                }
+                if included.iter().any(|done| done == name) {
+                    continue;
+                }
+                included.push(String::from(name));
+
+                let include = ShaderHandle::from_filename(name)
+                    .ok_or_else(|| error(&format!("unknown shader {name}")))?;
+                let include_source = shaders.source(include)?;
+                self.process_source(shaders, name, &include_source, defines, included, stack,
+                    output)?;
+            } else if let Some(define) = trimmed.strip_prefix("#define") {
+                let mut parts = define.trim().splitn(2, char::is_whitespace);
+                let name = parts.next()
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| error("expected #define NAME value"))?;
+                let value = parts.next().unwrap_or("").trim();
+
+                defines.entry(String::from(name)).or_insert_with(|| String::from(value));
+            } else {
+                self.substitute(line, defines, &mut output.source);
+                output.source.push('\n');
+                output.line_map.push((String::from(shader), line_number + 1));
+            }
+        }
+
+        if !conditions.is_empty() {
+            return Err(RendererError::ShaderPreprocess {
+                shader: String::from(shader),
+                line: source.lines().count(),
+                message: String::from("#ifdef without #endif")
+            });
+        }
+        stack.pop();
+
+        Ok(())
+    }
+
+    // Replaces whole identifiers only, so MAX_LIGHTS doesn't touch MAX_LIGHTS_PER_TILE.
+    fn substitute(&self, line: &str, defines: &BTreeMap<String, String>, output: &mut String)
+    {
+        let mut rest = line;
+
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let identifier = &rest[..end];
+            let value = self.defines.get(identifier).or_else(|| defines.get(identifier));
+            output.push_str(value.map_or(identifier, String::as_str));
+
+            rest = &rest[end..];
+        }
+
+        output.push_str(rest);
+    }
+}
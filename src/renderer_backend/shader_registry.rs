@@ -0,0 +1,215 @@
+use std::{borrow::Cow, collections::{BTreeMap, HashMap}, path::PathBuf};
+
+use crate::error::RendererError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderHandle {
+    Billboard,
+    Blit,
+    Color,
+    ColorGrading,
+    ColorfulTriangle,
+    Common,
+    Cull,
+    Culling,
+    DebugLines,
+    DebugView,
+    Decal,
+    DepthOfField,
+    EdgeDetection,
+    Fog,
+    Fullscreen,
+    Fxaa,
+    Glow,
+    Instancing,
+    Material,
+    MotionBlur,
+    Msaa,
+    Outline,
+    Procedural,
+    Skinned,
+    Ssao,
+    Taa,
+    Terrain,
+    Vegetation,
+    Vertex,
+    Water
+}
+
+impl ShaderHandle {
+    pub const ALL: [ShaderHandle; 30] = [
+        ShaderHandle::Billboard,
+        ShaderHandle::Blit,
+        ShaderHandle::Color,
+        ShaderHandle::ColorGrading,
+        ShaderHandle::ColorfulTriangle,
+        ShaderHandle::Common,
+        ShaderHandle::Cull,
+        ShaderHandle::Culling,
+        ShaderHandle::DebugLines,
+        ShaderHandle::DebugView,
+        ShaderHandle::Decal,
+        ShaderHandle::DepthOfField,
+        ShaderHandle::EdgeDetection,
+        ShaderHandle::Fog,
+        ShaderHandle::Fullscreen,
+        ShaderHandle::Fxaa,
+        ShaderHandle::Glow,
+        ShaderHandle::Instancing,
+        ShaderHandle::Material,
+        ShaderHandle::MotionBlur,
+        ShaderHandle::Msaa,
+        ShaderHandle::Outline,
+        ShaderHandle::Procedural,
+        ShaderHandle::Skinned,
+        ShaderHandle::Ssao,
+        ShaderHandle::Taa,
+        ShaderHandle::Terrain,
+        ShaderHandle::Vegetation,
+        ShaderHandle::Vertex,
+        ShaderHandle::Water
+    ];
+
+    pub fn from_filename(filename: &str) -> Option<Self>
+    {
+        Self::ALL.iter()
+            .find(|shader| shader.filename() == filename)
+            .copied()
+    }
+
+    pub fn filename(&self) -> &'static str
+    {
+        match self {
+            ShaderHandle::Billboard => "billboard.wgsl",
+            ShaderHandle::Blit => "blit.wgsl",
+            ShaderHandle::Color => "color.wgsl",
+            ShaderHandle::ColorGrading => "color_grading.wgsl",
+            ShaderHandle::ColorfulTriangle => "colorful_triangle.wgsl",
+            ShaderHandle::Common => "common.wgsl",
+            ShaderHandle::Cull => "cull.wgsl",
+            ShaderHandle::Culling => "culling.wgsl",
+            ShaderHandle::DebugLines => "debug_lines.wgsl",
+            ShaderHandle::DebugView => "debug_view.wgsl",
+            ShaderHandle::Decal => "decal.wgsl",
+            ShaderHandle::DepthOfField => "depth_of_field.wgsl",
+            ShaderHandle::EdgeDetection => "edge_detection.wgsl",
+            ShaderHandle::Fog => "fog.wgsl",
+            ShaderHandle::Fullscreen => "fullscreen.wgsl",
+            ShaderHandle::Fxaa => "fxaa.wgsl",
+            ShaderHandle::Glow => "glow.wgsl",
+            ShaderHandle::Instancing => "instancing.wgsl",
+            ShaderHandle::Material => "material.wgsl",
+            ShaderHandle::MotionBlur => "motion_blur.wgsl",
+            ShaderHandle::Msaa => "msaa.wgsl",
+            ShaderHandle::Outline => "outline.wgsl",
+            ShaderHandle::Procedural => "procedural.wgsl",
+            ShaderHandle::Skinned => "skinned.wgsl",
+            ShaderHandle::Ssao => "ssao.wgsl",
+            ShaderHandle::Taa => "taa.wgsl",
+            ShaderHandle::Terrain => "terrain.wgsl",
+            ShaderHandle::Vegetation => "vegetation.wgsl",
+            ShaderHandle::Vertex => "vertex.wgsl",
+            ShaderHandle::Water => "water.wgsl"
+        }
+    }
+
+    fn embedded_source(&self) -> &'static str
+    {
+        match self {
+            ShaderHandle::Billboard => include_str!("../shaders/billboard.wgsl"),
+            ShaderHandle::Blit => include_str!("../shaders/blit.wgsl"),
+            ShaderHandle::Color => include_str!("../shaders/color.wgsl"),
+            ShaderHandle::ColorGrading => include_str!("../shaders/color_grading.wgsl"),
+            ShaderHandle::ColorfulTriangle => include_str!("../shaders/colorful_triangle.wgsl"),
+            ShaderHandle::Common => include_str!("../shaders/common.wgsl"),
+            ShaderHandle::Cull => include_str!("../shaders/cull.wgsl"),
+            ShaderHandle::Culling => include_str!("../shaders/culling.wgsl"),
+            ShaderHandle::DebugLines => include_str!("../shaders/debug_lines.wgsl"),
+            ShaderHandle::DebugView => include_str!("../shaders/debug_view.wgsl"),
+            ShaderHandle::Decal => include_str!("../shaders/decal.wgsl"),
+            ShaderHandle::DepthOfField => include_str!("../shaders/depth_of_field.wgsl"),
+            ShaderHandle::EdgeDetection => include_str!("../shaders/edge_detection.wgsl"),
+            ShaderHandle::Fog => include_str!("../shaders/fog.wgsl"),
+            ShaderHandle::Fullscreen => include_str!("../shaders/fullscreen.wgsl"),
+            ShaderHandle::Fxaa => include_str!("../shaders/fxaa.wgsl"),
+            ShaderHandle::Glow => include_str!("../shaders/glow.wgsl"),
+            ShaderHandle::Instancing => include_str!("../shaders/instancing.wgsl"),
+            ShaderHandle::Material => include_str!("../shaders/material.wgsl"),
+            ShaderHandle::MotionBlur => include_str!("../shaders/motion_blur.wgsl"),
+            ShaderHandle::Msaa => include_str!("../shaders/msaa.wgsl"),
+            ShaderHandle::Outline => include_str!("../shaders/outline.wgsl"),
+            ShaderHandle::Procedural => include_str!("../shaders/procedural.wgsl"),
+            ShaderHandle::Skinned => include_str!("../shaders/skinned.wgsl"),
+            ShaderHandle::Ssao => include_str!("../shaders/ssao.wgsl"),
+            ShaderHandle::Taa => include_str!("../shaders/taa.wgsl"),
+            ShaderHandle::Terrain => include_str!("../shaders/terrain.wgsl"),
+            ShaderHandle::Vegetation => include_str!("../shaders/vegetation.wgsl"),
+            ShaderHandle::Vertex => include_str!("../shaders/vertex.wgsl"),
+            ShaderHandle::Water => include_str!("../shaders/water.wgsl")
+        }
+    }
+}
+
+// Every shader is compiled into the binary, so it runs the same from any working
+// directory and on the web. With an override directory (native only), files found
+// there win over the embedded copies, which lets a reload pick up edits without
+// rebuilding. Sources set at runtime, e.g. fetched on the web, win over both.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderRegistry {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    override_dir: Option<PathBuf>,
+    sources: HashMap<ShaderHandle, String>,
+    // Seen by every shader, e.g. what the backend can't do.
+    defines: BTreeMap<String, String>
+}
+
+impl ShaderRegistry {
+    pub fn new(override_dir: Option<PathBuf>) -> Self
+    {
+        Self {
+            override_dir,
+            sources: HashMap::new(),
+            defines: BTreeMap::new()
+        }
+    }
+
+    pub fn set_define(&mut self, name: &str, value: &str) -> &mut Self
+    {
+        self.defines.insert(String::from(name), String::from(value));
+        self
+    }
+
+    pub fn defines(&self) -> &BTreeMap<String, String>
+    {
+        &self.defines
+    }
+
+    // Used from the next reload on.
+    pub fn set_source(&mut self, shader: ShaderHandle, source: String) -> &mut Self
+    {
+        self.sources.insert(shader, source);
+        self
+    }
+
+    pub fn source(&self, shader: ShaderHandle) -> Result<Cow<'static, str>, RendererError>
+    {
+        if let Some(source) = self.sources.get(&shader) {
+            return Ok(Cow::Owned(source.clone()));
+        }
+        cfg_if::cfg_if! {
+            if #[cfg(not(target_arch = "wasm32"))] {
+                if let Some(override_dir) = &self.override_dir {
+                    let filepath = override_dir.join(shader.filename());
+
+                    match std::fs::read_to_string(&filepath) {
+                        Ok(source) => return Ok(Cow::Owned(source)),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+                        Err(source) => return Err(RendererError::ShaderRead { path: filepath, source })
+                    }
+                }
+            }
+        }
+
+        Ok(Cow::Borrowed(shader.embedded_source()))
+    }
+}
@@ -0,0 +1,174 @@
+use bytemuck::Pod;
+use wgpu::{util::{backend_bits_from_env, BufferInitDescriptor, DeviceExt}, Backend, Backends, BindGroupDescriptor, BindGroupEntry, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipelineDescriptor, Device, DeviceDescriptor, DownlevelFlags, Features, Instance, InstanceDescriptor, Limits, Queue, RequestAdapterOptions, ShaderModuleDescriptor, ShaderSource};
+
+use crate::error::RendererError;
+
+use super::{error_scope::ErrorScope, gpu_readback::GpuReadback, shader_preprocessor::ShaderPreprocessor, shader_registry::ShaderRegistry, shader_validation};
+
+const TEST_WORKGROUP_SIZE: u32 = 64;
+
+// Runs WGSL functions on the GPU over known inputs, without a window, so shader math
+// can be tested like any other function. The source given to run declares the types
+// `Input` and `Output` and `fn test(input: Input) -> Output`, and can #include any
+// registered shader whose functions it wants to call.
+pub struct ShaderTest {
+    device: Device,
+    queue: Queue,
+    shaders: ShaderRegistry
+}
+
+impl ShaderTest {
+    // On any adapter that can run compute shaders, software ones included. The
+    // backends can be narrowed with WGPU_BACKEND.
+    pub async fn new() -> Result<Self, RendererError>
+    {
+        let instance = Instance::new(
+            InstanceDescriptor {
+                backends: backend_bits_from_env().unwrap_or(Backends::all()),
+                ..Default::default()
+            }
+        );
+        let adapter = instance.request_adapter(&RequestAdapterOptions::default())
+            .await
+            .filter(|adapter| adapter.get_downlevel_capabilities().flags
+                .contains(DownlevelFlags::COMPUTE_SHADERS))
+            .ok_or(RendererError::NoAdapter)?;
+        let (device, queue) = adapter.request_device(
+            &DeviceDescriptor {
+                label: Some("Shader Test Device"),
+                required_features: Features::empty(),
+                required_limits: Limits::downlevel_defaults().using_resolution(adapter.limits())
+            },
+            None
+        ).await?;
+
+        let mut shaders = ShaderRegistry::default();
+        if adapter.get_info().backend == Backend::Gl {
+            shaders.set_define("BACKEND_GL", "1");
+        }
+
+        Ok(Self {
+            device,
+            queue,
+            shaders
+        })
+    }
+
+    pub fn device(&self) -> &Device
+    {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Queue
+    {
+        &self.queue
+    }
+
+    // With BACKEND_GL set when it applies, for building pipelines on the same device.
+    pub fn shaders(&self) -> &ShaderRegistry
+    {
+        &self.shaders
+    }
+
+    // `test` once per input, in parallel, the outputs in the same order. `I` and `O`
+    // have to be laid out like `Input` and `Output` in a storage array, so a vec3 is
+    // best passed as a vec4.
+    pub async fn run<I: Pod, O: Pod>(&self, source: &str, inputs: &[I]) -> Result<Vec<O>, RendererError>
+    {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let source = format!("{source}
+
+@group(0) @binding(0)
+var<storage, read> test_inputs: array<Input>;
+@group(0) @binding(1)
+var<storage, read_write> test_outputs: array<Output>;
+
+@compute @workgroup_size({TEST_WORKGROUP_SIZE})
+fn cs_test(@builtin(global_invocation_id) id: vec3<u32>)
+{{
+    if id.x < arrayLength(&test_outputs) {{
+        test_outputs[id.x] = test(test_inputs[id.x]);
+    }}
+}}
+");
+        let shader = ShaderPreprocessor::default().process_str(&self.shaders, "test.wgsl", &source)?;
+        shader_validation::validate(&shader)?;
+        // What naga accepts can still fail in the backend, or not fit the inputs.
+        let scope = ErrorScope::push(&self.device, "Shader Test");
+        let module = self.device.create_shader_module(
+            ShaderModuleDescriptor {
+                label: Some("Shader Test Shader"),
+                source: ShaderSource::Wgsl(shader.source.into())
+            }
+        );
+        let pipeline = self.device.create_compute_pipeline(
+            &ComputePipelineDescriptor {
+                label: Some("Shader Test Pipeline"),
+                layout: None,
+                module: &module,
+                entry_point: "cs_test"
+            }
+        );
+
+        let input_buffer = self.device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some("Shader Test Input Buffer"),
+                contents: bytemuck::cast_slice(inputs),
+                usage: BufferUsages::STORAGE
+            }
+        );
+        let output_size = (inputs.len() * std::mem::size_of::<O>()) as u64;
+        let output_buffer = self.device.create_buffer(
+            &BufferDescriptor {
+                label: Some("Shader Test Output Buffer"),
+                size: output_size,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false
+            }
+        );
+        let bind_group = self.device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some("Shader Test Bind Group"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: input_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: output_buffer.as_entire_binding()
+                    }
+                ]
+            }
+        );
+
+        let mut encoder = self.device.create_command_encoder(
+            &CommandEncoderDescriptor {
+                label: Some("Shader Test Encoder")
+            }
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(
+                &ComputePassDescriptor {
+                    label: Some("Shader Test Pass"),
+                    timestamp_writes: None
+                }
+            );
+            compute_pass.set_pipeline(&pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups((inputs.len() as u32).div_ceil(TEST_WORKGROUP_SIZE), 1, 1);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        scope.pop(&self.device).await?;
+
+        let bytes = GpuReadback::new(&self.device, &self.queue)
+            .read_buffer(&output_buffer, 0..output_size)
+            .await?;
+
+        Ok(bytemuck::pod_collect_to_vec(&bytes))
+    }
+}
@@ -0,0 +1,59 @@
+use std::error::Error;
+
+use naga::{front::wgsl, valid::{Capabilities, ValidationFlags, Validator}, SourceLocation};
+
+use crate::error::RendererError;
+
+use super::shader_preprocessor::PreprocessedShader;
+
+// Runs naga over the preprocessed source before wgpu sees it, so a broken shader
+// turns into an error pointing at the original file and line instead of a
+// validation panic inside create_shader_module.
+pub fn validate(shader: &PreprocessedShader) -> Result<(), RendererError>
+{
+    let module = wgsl::parse_str(&shader.source)
+        .map_err(|e| diagnostic(shader, e.location(&shader.source), e.message()))?;
+
+    Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| diagnostic(shader, e.location(&shader.source), &error_chain(&e)))?;
+
+    Ok(())
+}
+
+fn diagnostic(
+    shader: &PreprocessedShader,
+    location: Option<SourceLocation>,
+    message: &str
+) -> RendererError
+{
+    let (line, column) = location.map_or((0, 0),
+        |location| (location.line_number as usize, location.line_position as usize));
+    let (file, original_line) = shader.original_location(line);
+    let excerpt = shader.source.lines()
+        .nth(line.saturating_sub(1))
+        .filter(|_| line > 0)
+        .map(|text| format!("{original_line:>5} | {text}\n      | {:>column$}", "^"))
+        .unwrap_or_default();
+
+    RendererError::ShaderValidation {
+        file: String::from(file),
+        line: original_line,
+        column,
+        message: String::from(message),
+        excerpt
+    }
+}
+
+fn error_chain(error: &dyn Error) -> String
+{
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+
+    message
+}
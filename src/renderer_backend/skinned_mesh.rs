@@ -0,0 +1,241 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::Matrix4;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Device, IndexFormat, Queue, RenderPass, ShaderStages};
+
+use crate::state::{instance::{Instance, InstanceRaw}, skinned_model::SkinnedModel};
+
+use super::{debug_labels::DebugLabels, gpu_allocator::{AllocationKind, GpuAllocation, GpuAllocator}};
+
+pub const MAX_MORPH_TARGETS: usize = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct MorphUniform {
+    weights: [f32; MAX_MORPH_TARGETS],
+    target_count: u32,
+    vertex_count: u32,
+    _padding: [u32; 2]
+}
+
+impl MorphUniform {
+    fn new(weights: &[f32], vertex_count: usize) -> Self
+    {
+        let target_count = weights.len().min(MAX_MORPH_TARGETS);
+        let mut uniform = Self {
+            weights: [0.0; MAX_MORPH_TARGETS],
+            target_count: target_count as u32,
+            vertex_count: vertex_count as u32,
+            _padding: [0; 2]
+        };
+        uniform.weights[..target_count].copy_from_slice(&weights[..target_count]);
+
+        uniform
+    }
+}
+
+// GPU copy of a SkinnedModel. Joint matrices and morph target offsets live in
+// storage buffers bound at group 3, which WebGL2 doesn't support in vertex shaders,
+// so nothing here is created until a skinned model is actually loaded. Vertices,
+// indices, the instance and the morph weights are ranges of the shared GpuAllocator
+// and go back to it with free().
+pub struct SkinnedMesh {
+    labels: DebugLabels,
+    joint_bind_group_layout: BindGroupLayout,
+    vertex_allocation: GpuAllocation,
+    index_allocation: GpuAllocation,
+    num_indices: u32,
+    num_vertices: usize,
+    instance: InstanceRaw,
+    instance_allocation: GpuAllocation,
+    joint_buffer: Buffer,
+    morph_weights_allocation: GpuAllocation,
+    joint_bind_group: BindGroup
+}
+
+impl SkinnedMesh {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        allocator: &mut GpuAllocator,
+        label: &str,
+        model: &SkinnedModel,
+        instance: &Instance
+    ) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let joint_bind_group_layout = Self::get_joint_bind_group_layout(device, &labels);
+        let vertex_allocation = allocator.allocate_init(device, queue, AllocationKind::Vertex,
+            bytemuck::cast_slice(&model.vertices));
+        let index_allocation = allocator.allocate_init(device, queue, AllocationKind::Index,
+            bytemuck::cast_slice(&model.indices));
+        let instance = instance.to_raw();
+        let instance_allocation = allocator.allocate_init(device, queue, AllocationKind::Vertex,
+            bytemuck::cast_slice(&[instance]));
+
+        // Starts in the bind pose, where every joint matrix is the identity.
+        let joint_count = model.skeleton.joints.len().max(1);
+        let identity: [[f32; 4]; 4] = Matrix4::from_scale(1.0).into();
+        let joint_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix("Joint Buffer")),
+                contents: bytemuck::cast_slice(&vec![identity; joint_count]),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST
+            }
+        );
+
+        // Target-major, so the shader finds a vertex's offset for target t at
+        // t * vertex_count + vertex_index. Padded to vec4 for the storage layout.
+        if model.morph_targets.len() > MAX_MORPH_TARGETS {
+            log::warn!("{label} has {} morph targets, only the first {MAX_MORPH_TARGETS} are used",
+                model.morph_targets.len());
+        }
+        let mut morph_target_data = model.morph_targets.iter()
+            .take(MAX_MORPH_TARGETS)
+            .flatten()
+            .map(|&[x, y, z]| [x, y, z, 0.0])
+            .collect::<Vec<_>>();
+        if morph_target_data.is_empty() {
+            morph_target_data.push([0.0; 4]);
+        }
+        let morph_target_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix("Morph Target Buffer")),
+                contents: bytemuck::cast_slice(&morph_target_data),
+                usage: BufferUsages::STORAGE
+            }
+        );
+        let morph_weights_allocation = allocator.allocate_init(device, queue,
+            AllocationKind::Uniform, bytemuck::cast_slice(&[MorphUniform::new(
+                &model.default_weights, model.vertices.len())]));
+
+        let joint_bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.with_suffix("Joint Bind Group")),
+                layout: &joint_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: joint_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: morph_target_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Buffer(allocator.binding(&morph_weights_allocation))
+                    }
+                ]
+            }
+        );
+
+        Self {
+            labels,
+            joint_bind_group_layout,
+            vertex_allocation,
+            index_allocation,
+            num_indices: model.indices.len() as u32,
+            num_vertices: model.vertices.len(),
+            instance,
+            instance_allocation,
+            joint_buffer,
+            morph_weights_allocation,
+            joint_bind_group
+        }
+    }
+
+    fn get_joint_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.with_suffix("Joint Bind Group Layout")),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    pub fn joint_bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.joint_bind_group_layout
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn num_indices(&self) -> u32
+    {
+        self.num_indices
+    }
+
+    pub fn write_joint_matrices(&self, queue: &Queue, joint_matrices: &[Matrix4<f32>])
+    {
+        let joint_data = joint_matrices.iter()
+            .map(|&matrix| -> [[f32; 4]; 4] { matrix.into() })
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.joint_buffer, 0, bytemuck::cast_slice(&joint_data));
+    }
+
+    pub fn write_morph_weights(&self, queue: &Queue, allocator: &GpuAllocator, weights: &[f32])
+    {
+        allocator.write(queue, &self.morph_weights_allocation,
+            bytemuck::cast_slice(&[MorphUniform::new(weights, self.num_vertices)]));
+    }
+
+    // The emission comes from the material, which can change after the mesh is made.
+    pub fn write_emissive(&self, queue: &Queue, allocator: &GpuAllocator, emissive: [f32; 3])
+    {
+        allocator.write(queue, &self.instance_allocation,
+            bytemuck::cast_slice(&[self.instance.with_emissive(emissive)]));
+    }
+
+    pub fn free(self, allocator: &mut GpuAllocator)
+    {
+        allocator.free(self.vertex_allocation);
+        allocator.free(self.index_allocation);
+        allocator.free(self.instance_allocation);
+        allocator.free(self.morph_weights_allocation);
+    }
+
+    // Expects the pipeline and bind groups 0 to 2 to be set already.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, allocator: &'a GpuAllocator)
+    {
+        render_pass.set_bind_group(3, &self.joint_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, allocator.slice(&self.vertex_allocation));
+        render_pass.set_vertex_buffer(1, allocator.slice(&self.instance_allocation));
+        render_pass.set_index_buffer(allocator.slice(&self.index_allocation), IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
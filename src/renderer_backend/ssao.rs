@@ -0,0 +1,236 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Queue, ShaderStages, SurfaceConfiguration, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDimension};
+
+use crate::state::camera::{halton, Camera};
+
+use super::{debug_labels::DebugLabels, transient::TransientTextureDesc};
+
+pub const OCCLUSION_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+pub const MAX_SAMPLE_COUNT: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SsaoOptions {
+    // How far around a pixel, in world units, geometry occludes it.
+    pub radius: f32,
+    // How much closer a surface has to be to count, against surfaces shadowing
+    // themselves.
+    pub bias: f32,
+    // Per pixel, up to MAX_SAMPLE_COUNT.
+    pub sample_count: u32,
+    // 1 lets full occlusion go black, 0 leaves the frame as it was.
+    pub intensity: f32,
+    pub blur: bool
+}
+
+impl Default for SsaoOptions {
+    fn default() -> Self
+    {
+        Self {
+            radius: 0.5,
+            bias: 0.025,
+            sample_count: 16,
+            intensity: 1.0,
+            blur: true
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct SsaoUniform {
+    proj: [[f32; 4]; 4],
+    inverse_proj: [[f32; 4]; 4],
+    // the main viewport in pixels
+    viewport: [f32; 4],
+    // radius, bias, intensity, sample count
+    params: [f32; 4],
+    kernel: [[f32; 4]; MAX_SAMPLE_COUNT as usize]
+}
+
+// Screen-space ambient occlusion. One pass works out from the depth buffer how much of
+// the hemisphere over every pixel is hidden, another blurs that, and the SSAO post
+// effect multiplies the frame by it. All three bind the same group, the occlusion
+// texture in it being the one the pass reads.
+pub struct Ssao {
+    labels: DebugLabels,
+    options: SsaoOptions,
+    kernel: [[f32; 4]; MAX_SAMPLE_COUNT as usize],
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout
+}
+
+impl Ssao {
+    pub fn new(device: &Device, label: &str, options: SsaoOptions) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[SsaoUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let options = Self::clamp_options(options);
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            kernel: Self::create_kernel(options.sample_count),
+            options,
+            uniform_buffer
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Depth
+                        },
+                        count: None
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type: TextureSampleType::Float { filterable: true }
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    fn clamp_options(options: SsaoOptions) -> SsaoOptions
+    {
+        SsaoOptions {
+            radius: options.radius.max(0.001),
+            bias: options.bias.max(0.0),
+            sample_count: options.sample_count.clamp(1, MAX_SAMPLE_COUNT),
+            intensity: options.intensity.clamp(0.0, 1.0),
+            blur: options.blur
+        }
+    }
+
+    // Points in the hemisphere around +z from the Halton sequence, scaled so that
+    // more of them land close to the pixel, where occluders matter most.
+    fn create_kernel(sample_count: u32) -> [[f32; 4]; MAX_SAMPLE_COUNT as usize]
+    {
+        let mut kernel = [[0.0; 4]; MAX_SAMPLE_COUNT as usize];
+        for (index, sample) in kernel.iter_mut().take(sample_count as usize).enumerate() {
+            let halton_index = index as u32 + 1;
+            let direction = Vector3::new(
+                halton(halton_index, 2) * 2.0 - 1.0,
+                halton(halton_index, 3) * 2.0 - 1.0,
+                halton(halton_index, 5).max(0.05)
+            ).normalize();
+            let t = index as f32 / sample_count as f32;
+            let scale = 0.1 + 0.9 * t * t;
+            let point = direction * scale * halton(halton_index, 7).max(0.1);
+
+            *sample = [point.x, point.y, point.z, 0.0];
+        }
+
+        kernel
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn options(&self) -> SsaoOptions
+    {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: SsaoOptions)
+    {
+        let options = Self::clamp_options(options);
+        if options.sample_count != self.options.sample_count {
+            self.kernel = Self::create_kernel(options.sample_count);
+        }
+        self.options = options;
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    pub fn occlusion_target_desc(config: &SurfaceConfiguration) -> TransientTextureDesc
+    {
+        TransientTextureDesc {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            format: OCCLUSION_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            sample_count: 1
+        }
+    }
+
+    // `viewport` is the main viewport in pixels, the camera the one it's drawn with.
+    pub fn write_uniforms(&self, queue: &Queue, camera: &Camera, viewport: (u32, u32, u32, u32))
+    {
+        let proj = camera.build_projection_matrix();
+        let (x, y, width, height) = viewport;
+        let uniform = SsaoUniform {
+            proj: proj.into(),
+            inverse_proj: proj.invert().unwrap_or_else(Matrix4::identity).into(),
+            viewport: [x as f32, y as f32, width as f32, height as f32],
+            params: [self.options.radius, self.options.bias, self.options.intensity,
+                self.options.sample_count as f32],
+            kernel: self.kernel
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // The occlusion texture comes from the transient pool and is a different one in
+    // every pass, so bind groups are made per frame.
+    pub fn create_bind_group(&self, device: &Device, depth: &TextureView, occlusion: &TextureView) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(depth)
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(occlusion)
+                    }
+                ]
+            }
+        )
+    }
+}
@@ -0,0 +1,72 @@
+use wgpu::{CommandBuffer, Queue, SubmissionIndex};
+
+// Every queue submission has a fixed cost on the driver side, so a frame is expected
+// to get by with one submit for its passes plus at most one for late work (readbacks).
+pub const MAX_SUBMITS_PER_FRAME: u32 = 2;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubmitStats {
+    pub submits: u32,
+    pub command_buffers: u32,
+    pub max_submits: u32,
+    pub frames_over_limit: u64
+}
+
+// Passes that need their own encoder hand over the finished command buffers here
+// instead of submitting them, and the frame submits everything at once in `flush`.
+#[derive(Default)]
+pub struct SubmitBatch {
+    pending: Vec<CommandBuffer>,
+    submits: u32,
+    command_buffers: u32,
+    stats: SubmitStats
+}
+
+impl SubmitBatch {
+    pub fn begin_frame(&mut self)
+    {
+        if !self.pending.is_empty() {
+            log::warn!("Dropping {} command buffers that were never submitted", self.pending.len());
+            self.pending.clear();
+        }
+
+        self.submits = 0;
+        self.command_buffers = 0;
+    }
+
+    pub fn push(&mut self, command_buffer: CommandBuffer)
+    {
+        self.command_buffers += 1;
+        self.pending.push(command_buffer);
+    }
+
+    pub fn flush(&mut self, queue: &Queue) -> Option<SubmissionIndex>
+    {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        self.submits += 1;
+        Some(queue.submit(self.pending.drain(..)))
+    }
+
+    pub fn end_frame(&mut self)
+    {
+        self.stats.submits = self.submits;
+        self.stats.command_buffers = self.command_buffers;
+        self.stats.max_submits = self.stats.max_submits.max(self.submits);
+
+        if self.submits > MAX_SUBMITS_PER_FRAME {
+            self.stats.frames_over_limit += 1;
+            log::warn!("Frame used {} queue submits, expected at most {MAX_SUBMITS_PER_FRAME}",
+                self.submits);
+        }
+        debug_assert!(self.submits <= MAX_SUBMITS_PER_FRAME,
+            "too many queue submits in one frame, batch them through SubmitBatch");
+    }
+
+    pub fn stats(&self) -> SubmitStats
+    {
+        self.stats
+    }
+}
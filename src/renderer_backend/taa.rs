@@ -0,0 +1,232 @@
+use std::rc::Rc;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Extent3d, Queue, Sampler, SamplerBindingType, ShaderStages, SurfaceConfiguration, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension};
+
+use super::{debug_labels::DebugLabels, sampler_cache::{SamplerCache, SamplerSpec}, texture::Texture, transient::TransientTextureDesc};
+
+pub const MOTION_VECTOR_FORMAT: TextureFormat = TextureFormat::Rg16Float;
+// How much of the resolved color comes from the history, the rest is this frame's.
+pub const DEFAULT_HISTORY_WEIGHT: f32 = 0.9;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct TaaUniform {
+    // history weight, 1 when the history holds a resolved frame
+    params: [f32; 4]
+}
+
+// Temporal anti-aliasing. The main pass renders jittered into an offscreen color
+// target, a second pass writes motion vectors where it left depth, and the resolve
+// blends that color with the last resolved frame found along the motion vectors.
+// Resolved frames ping-pong between two history textures, the newest goes on to the
+// post effects or is blitted to the surface.
+pub struct Taa {
+    labels: DebugLabels,
+    history_weight: f32,
+    history: [Texture; 2],
+    // Index of the history the next resolve writes.
+    current: usize,
+    history_valid: bool,
+    uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    sampler: Rc<Sampler>
+}
+
+impl Taa {
+    pub fn new(
+        device: &Device,
+        label: &str,
+        config: &SurfaceConfiguration,
+        samplers: &mut SamplerCache
+    ) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[TaaUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        Self {
+            history: Self::create_history(device, &labels, config),
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            history_weight: DEFAULT_HISTORY_WEIGHT,
+            current: 0,
+            history_valid: false,
+            uniform_buffer,
+            sampler: samplers.get(device, SamplerSpec::default().with_anisotropy(1))
+        }
+    }
+
+    // After a resize, what the history holds no longer lines up with the screen.
+    pub fn resize(&mut self, device: &Device, config: &SurfaceConfiguration)
+    {
+        self.history = Self::create_history(device, &self.labels, config);
+        self.reset_history();
+    }
+
+    fn create_history(device: &Device, labels: &DebugLabels, config: &SurfaceConfiguration) -> [Texture; 2]
+    {
+        [0, 1].map(|index| {
+            let label = labels.with_suffix(&format!("History {index}"));
+            let texture = device.create_texture(
+                &TextureDescriptor {
+                    label: Some(&label),
+                    size: Extent3d {
+                        width: config.width.max(1),
+                        height: config.height.max(1),
+                        depth_or_array_layers: 1
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: config.format,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[]
+                }
+            );
+            let view = texture.create_view(&TextureViewDescriptor::default());
+
+            Texture { texture, view }
+        })
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        let texture_entry = |binding: u32| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension: TextureViewDimension::D2,
+                sample_type: TextureSampleType::Float { filterable: true }
+            },
+            count: None
+        };
+
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    texture_entry(1),
+                    texture_entry(2),
+                    texture_entry(3),
+                    BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    pub fn history_weight(&self) -> f32
+    {
+        self.history_weight
+    }
+
+    pub fn set_history_weight(&mut self, history_weight: f32)
+    {
+        self.history_weight = history_weight.clamp(0.0, 1.0);
+    }
+
+    // The next resolve uses this frame's color only, e.g. after a camera cut.
+    pub fn reset_history(&mut self)
+    {
+        self.history_valid = false;
+    }
+
+    pub fn motion_target_desc(config: &SurfaceConfiguration) -> TransientTextureDesc
+    {
+        TransientTextureDesc {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            format: MOTION_VECTOR_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            sample_count: 1
+        }
+    }
+
+    pub fn write_uniforms(&self, queue: &Queue)
+    {
+        let uniform = TaaUniform {
+            params: [self.history_weight, if self.history_valid { 1.0 } else { 0.0 }, 0.0, 0.0]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Where this frame's resolve writes to.
+    pub fn resolve_view(&self) -> &TextureView
+    {
+        &self.history[self.current].view
+    }
+
+    // The color and motion targets come from the transient pool and the history
+    // alternates, so the bind group is rebuilt every frame.
+    pub fn create_bind_group(&self, device: &Device, color: &TextureView, motion: &TextureView) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(color)
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(&self.history[1 - self.current].view)
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(motion)
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::Sampler(&self.sampler)
+                    }
+                ]
+            }
+        )
+    }
+
+    // Once everything reading this frame's resolve is encoded, it becomes the history
+    // of the next one.
+    pub fn end_frame(&mut self)
+    {
+        self.current = 1 - self.current;
+        self.history_valid = true;
+    }
+}
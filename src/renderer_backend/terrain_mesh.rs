@@ -0,0 +1,103 @@
+use std::ops::Range;
+
+use cgmath::{MetricSpace, Point3};
+use wgpu::{Device, IndexFormat, Queue, RenderPass};
+
+use crate::state::{bounds::Bounds, terrain::{self, Heightmap, CHUNK_CELLS, LOD_LEVELS}};
+
+use super::{debug_labels::DebugLabels, gpu_allocator::{AllocationKind, GpuAllocation, GpuAllocator}};
+
+// GPU copy of a Heightmap. Every chunk lives in one vertex buffer at its own base
+// vertex, and all of them share one index buffer holding a range per LOD level, so
+// the whole terrain is two allocations however many chunks it has.
+pub struct TerrainMesh {
+    labels: DebugLabels,
+    vertex_allocation: GpuAllocation,
+    index_allocation: GpuAllocation,
+    lod_ranges: Vec<Range<u32>>,
+    chunk_centers: Vec<Point3<f32>>,
+    chunk_bounds: Vec<Bounds>,
+    lod_distance: f32
+}
+
+impl TerrainMesh {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        allocator: &mut GpuAllocator,
+        label: &str,
+        heightmap: &Heightmap
+    ) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let vertex_allocation = allocator.allocate_init(device, queue, AllocationKind::Vertex,
+            bytemuck::cast_slice(&heightmap.chunk_vertices()));
+        let (indices, lod_ranges) = terrain::lod_indices();
+        let index_allocation = allocator.allocate_init(device, queue, AllocationKind::Index,
+            bytemuck::cast_slice(&indices));
+        let chunk_centers = (0..heightmap.chunks().pow(2))
+            .map(|chunk| heightmap.chunk_center(chunk))
+            .collect();
+        let chunk_bounds = (0..heightmap.chunks().pow(2))
+            .map(|chunk| heightmap.chunk_bounds(chunk))
+            .collect();
+
+        Self {
+            labels,
+            vertex_allocation,
+            index_allocation,
+            lod_ranges,
+            chunk_centers,
+            chunk_bounds,
+            lod_distance: heightmap.options().lod_distance
+        }
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn num_chunks(&self) -> usize
+    {
+        self.chunk_centers.len()
+    }
+
+    pub fn chunk_bounds(&self) -> &[Bounds]
+    {
+        &self.chunk_bounds
+    }
+
+    // Full detail up to lod_distance, one level coarser each time the distance doubles.
+    fn lod_level(&self, chunk_center: Point3<f32>, eye: Point3<f32>) -> usize
+    {
+        let distance = chunk_center.distance(eye) / self.lod_distance.max(f32::EPSILON);
+        let level = if distance > 1.0 { distance.log2().ceil() as u32 } else { 0 };
+
+        level.min(LOD_LEVELS - 1) as usize
+    }
+
+    pub fn free(self, allocator: &mut GpuAllocator)
+    {
+        allocator.free(self.vertex_allocation);
+        allocator.free(self.index_allocation);
+    }
+
+    // Expects the pipeline and the camera bind group to be set already.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        allocator: &'a GpuAllocator,
+        eye: Point3<f32>
+    )
+    {
+        let vertices_per_chunk = (CHUNK_CELLS + 1).pow(2) as i32;
+
+        render_pass.set_vertex_buffer(0, allocator.slice(&self.vertex_allocation));
+        render_pass.set_index_buffer(allocator.slice(&self.index_allocation), IndexFormat::Uint16);
+        for (chunk, &center) in self.chunk_centers.iter().enumerate() {
+            let lod_range = self.lod_ranges[self.lod_level(center, eye)].clone();
+            render_pass.draw_indexed(lod_range, chunk as i32 * vertices_per_chunk, 0..1);
+        }
+    }
+}
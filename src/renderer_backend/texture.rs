@@ -1,103 +1,405 @@
-use wgpu::{AddressMode, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, CompareFunction, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, SurfaceConfiguration, Texture as WgpuTexture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension};
-use image::{DynamicImage, GenericImageView};
+use std::borrow::{Borrow, Cow};
+
+use wgpu::{BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Device, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, SamplerBindingType, ShaderStages, SurfaceConfiguration, Texture as WgpuTexture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension};
+use image::{imageops::{self, FilterType}, DynamicImage, GenericImageView, RgbaImage};
 use anyhow::*;
 
+use super::{asset_decode, debug_labels::DebugLabels, error_scope::ErrorScope, procedural_texture::{ProceduralPattern, TextureGenerator, PROCEDURAL_FORMAT, PROCEDURAL_LAYERS}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear
+}
+
+// What the texels hold, which decides the format. Colors are decoded from sRGB when
+// sampled, everything else is read as stored: normal maps, packed ORM maps and other
+// data go in as Data, single channel masks and two channel data as R8 and Rg8 (taken
+// from the red and green channels of the image).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    #[default]
+    Color,
+    Data,
+    R8,
+    Rg8
+}
+
+impl TextureKind {
+    pub fn format(&self) -> TextureFormat
+    {
+        match self {
+            Self::Color => TextureFormat::Rgba8UnormSrgb,
+            Self::Data => TextureFormat::Rgba8Unorm,
+            Self::R8 => TextureFormat::R8Unorm,
+            Self::Rg8 => TextureFormat::Rg8Unorm
+        }
+    }
+
+    pub fn color_space(&self) -> ColorSpace
+    {
+        match self {
+            Self::Color => ColorSpace::Srgb,
+            _ => ColorSpace::Linear
+        }
+    }
+
+    // The same texels read in the other color space, only the RGBA kinds have one.
+    pub fn reinterpreted_format(&self) -> Option<TextureFormat>
+    {
+        match self {
+            Self::Color => Some(TextureFormat::Rgba8Unorm),
+            Self::Data => Some(TextureFormat::Rgba8UnormSrgb),
+            _ => None
+        }
+    }
+
+    pub fn bytes_per_pixel(&self) -> u32
+    {
+        match self {
+            Self::Color | Self::Data => 4,
+            Self::R8 => 1,
+            Self::Rg8 => 2
+        }
+    }
+
+    fn pixel_data<'a>(&self, rgba: &'a RgbaImage) -> Cow<'a, [u8]>
+    {
+        let channels = self.bytes_per_pixel() as usize;
+        match self {
+            Self::Color | Self::Data => Cow::Borrowed(rgba.as_raw()),
+            _ => Cow::Owned(rgba.pixels()
+                .flat_map(|pixel| pixel.0[..channels].iter().copied())
+                .collect())
+        }
+    }
+}
+
 pub struct Texture {
     pub texture: WgpuTexture,
-    pub view: TextureView,
-    pub sampler: Sampler
+    pub view: TextureView
 }
 
 impl Texture {
     pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+    // For passes that need a stencil buffer, every backend has it.
+    pub const DEPTH_STENCIL_FORMAT: TextureFormat = TextureFormat::Depth24PlusStencil8;
 
     pub fn from_bytes(
         device: &Device,
         queue: &Queue,
         bytes: &[u8],
+        kind: TextureKind,
         label: &str
     ) -> Result<Self>
     {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        let img = asset_decode::decode_image(bytes)?;
+        Self::from_image(device, queue, &img, kind, Some(label))
     }
 
     pub fn from_image(
         device: &Device,
         queue: &Queue,
         img: &DynamicImage,
+        kind: TextureKind,
+        label: Option<&str>
+    ) -> Result<Self>
+    {
+        Self::from_layers(device, queue, std::slice::from_ref(img), kind, &[],
+            TextureViewDimension::D2, label)
+    }
+
+    // Like from_image, but views in the other color space can be made with view_as.
+    // Needs DownlevelFlags::VIEW_FORMATS, which WebGL2 doesn't have.
+    pub fn from_image_reinterpretable(
+        device: &Device,
+        queue: &Queue,
+        img: &DynamicImage,
+        kind: TextureKind,
+        label: Option<&str>
+    ) -> Result<Self>
+    {
+        let view_formats = kind.reinterpreted_format()
+            .ok_or_else(|| anyhow!("{kind:?} textures have no other color space"))?;
+        Self::from_layers(device, queue, std::slice::from_ref(img), kind, &[view_formats],
+            TextureViewDimension::D2, label)
+    }
+
+    // One layer per image, viewed as a D2Array so a shader can pick the layer per draw
+    // or per instance. Layers must share a size, so every image is scaled to the first.
+    pub fn from_images(
+        device: &Device,
+        queue: &Queue,
+        images: &[DynamicImage],
+        kind: TextureKind,
+        label: Option<&str>
+    ) -> Result<Self>
+    {
+        Self::from_layers(device, queue, images, kind, &[], TextureViewDimension::D2Array, label)
+    }
+
+    // `levels[mip][layer]`, every level half the size of the one before, viewed as a
+    // D2Array. The first level doesn't have to be the full resolution one.
+    pub fn from_mips(
+        device: &Device,
+        queue: &Queue,
+        levels: &[Vec<RgbaImage>],
+        kind: TextureKind,
+        label: Option<&str>
+    ) -> Result<Self>
+    {
+        Self::from_levels(device, queue, levels, kind, &[], TextureViewDimension::D2Array, label)
+    }
+
+    // Generated by a compute shader instead of uploaded, in one level, viewed as a
+    // D2Array like the textures from images. Has STORAGE_BINDING, so it can be generated
+    // again in place through storage_view. Needs compute shaders, which WebGL2 doesn't
+    // have.
+    pub fn from_compute(
+        device: &Device,
+        queue: &Queue,
+        generator: &TextureGenerator,
+        pattern: &ProceduralPattern,
+        (width, height): (u32, u32),
+        label: Option<&str>
+    ) -> Result<Self>
+    {
+        let scope = ErrorScope::push(device, label.unwrap_or("Texture"));
+        let texture = device.create_texture(
+            &TextureDescriptor {
+                label,
+                size: Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: PROCEDURAL_LAYERS
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: PROCEDURAL_FORMAT,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING,
+                view_formats: &[]
+            }
+        );
+        let labels = label.map(DebugLabels::new);
+        let view = texture.create_view(
+            &TextureViewDescriptor {
+                label: labels.as_ref().map(DebugLabels::view).as_deref(),
+                dimension: Some(TextureViewDimension::D2Array),
+                ..Default::default()
+            }
+        );
+        let texture = Self {
+            texture,
+            view
+        };
+        let storage_view = texture.storage_view(labels.as_ref()
+            .map(|labels| labels.with_suffix("Storage View")).as_deref());
+        generator.generate(device, queue, pattern, &storage_view, (width.max(1), height.max(1)));
+        scope.pop_now(device).map_err(|e| anyhow!("{e}"))?;
+
+        Ok(texture)
+    }
+
+    // The first level, what a storage texture binding takes. Only for textures made
+    // with from_compute.
+    pub fn storage_view(&self, label: Option<&str>) -> TextureView
+    {
+        self.texture.create_view(
+            &TextureViewDescriptor {
+                label,
+                dimension: Some(TextureViewDimension::D2Array),
+                base_mip_level: 0,
+                mip_level_count: Some(1),
+                ..Default::default()
+            }
+        )
+    }
+
+    // Only for textures made with from_image_reinterpretable.
+    pub fn view_as(&self, color_space: ColorSpace, label: Option<&str>) -> TextureView
+    {
+        let format = match color_space {
+            ColorSpace::Srgb => self.texture.format().add_srgb_suffix(),
+            ColorSpace::Linear => self.texture.format().remove_srgb_suffix()
+        };
+
+        self.texture.create_view(
+            &TextureViewDescriptor {
+                label,
+                format: Some(format),
+                ..Default::default()
+            }
+        )
+    }
+
+    // Each level of the chain down to 1x1, with the layers scaled to the first image
+    // like from_images does.
+    pub fn generate_mips(images: &[DynamicImage]) -> Result<Vec<Vec<RgbaImage>>>
+    {
+        let layers = Self::prepare_layers(images)?.into_iter()
+            .map(Cow::into_owned)
+            .collect::<Vec<_>>();
+        let mut levels = vec![layers];
+        loop {
+            let last = &levels[levels.len() - 1];
+            let (width, height) = last[0].dimensions();
+            if width == 1 && height == 1 {
+                break;
+            }
+
+            let (width, height) = ((width / 2).max(1), (height / 2).max(1));
+            let next = asset_decode::par_map(last, |layer| imageops::resize(layer, width, height,
+                FilterType::Triangle));
+            levels.push(next);
+        }
+
+        Ok(levels)
+    }
+
+    fn from_layers(
+        device: &Device,
+        queue: &Queue,
+        images: &[DynamicImage],
+        kind: TextureKind,
+        view_formats: &[TextureFormat],
+        view_dimension: TextureViewDimension,
+        label: Option<&str>
+    ) -> Result<Self>
+    {
+        let layers = Self::prepare_layers(images)?;
+
+        Self::from_levels(device, queue, &[layers], kind, view_formats, view_dimension, label)
+    }
+
+    // Converting and scaling the layers is the slow part, the uploads are queued in order.
+    fn prepare_layers(images: &[DynamicImage]) -> Result<Vec<Cow<'_, RgbaImage>>>
+    {
+        let dimensions = images.first()
+            .map(|img| img.dimensions())
+            .ok_or_else(|| anyhow!("a texture needs at least one image"))?;
+
+        Ok(asset_decode::par_map(images, |img| match img {
+            DynamicImage::ImageRgba8(rgba) if img.dimensions() == dimensions => Cow::Borrowed(rgba),
+            _ if img.dimensions() == dimensions => Cow::Owned(img.to_rgba8()),
+            _ => Cow::<RgbaImage>::Owned(img.resize_exact(dimensions.0, dimensions.1,
+                FilterType::Triangle).to_rgba8())
+        }))
+    }
+
+    fn from_levels<L: Borrow<RgbaImage>>(
+        device: &Device,
+        queue: &Queue,
+        levels: &[Vec<L>],
+        kind: TextureKind,
+        view_formats: &[TextureFormat],
+        view_dimension: TextureViewDimension,
         label: Option<&str>
     ) -> Result<Self>
     {
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+        let layers = levels.first()
+            .filter(|layers| !layers.is_empty())
+            .ok_or_else(|| anyhow!("a texture needs at least one image"))?;
+        let dimensions = layers[0].borrow().dimensions();
+        // GL guesses how a texture is viewed from its layer count, and takes a single
+        // layer for a plain 2D texture, so arrays get the last layer repeated up to two.
+        let num_layers = match view_dimension {
+            TextureViewDimension::D2Array => layers.len().max(2),
+            _ => layers.len()
+        };
 
         let size = Extent3d {
             width: dimensions.0,
             height: dimensions.1,
-            depth_or_array_layers: 1
+            depth_or_array_layers: num_layers as u32
         };
+        // E.g. a size over the device's limits, or a format it can't sample.
+        let scope = ErrorScope::push(device, label.unwrap_or("Texture"));
         let texture = device.create_texture(
             &TextureDescriptor {
                 label,
                 size,
-                mip_level_count: 1,
+                mip_level_count: levels.len() as u32,
                 sample_count: 1,
                 dimension: TextureDimension::D2,
-                format: TextureFormat::Rgba8UnormSrgb,
+                format: kind.format(),
                 usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                view_formats: &[]
+                view_formats
             }
         );
 
-        queue.write_texture(
-            ImageCopyTexture {
-                aspect: TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO
-            },
-            &rgba,
-            ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1)
-            },
-            size
-        );
+        for (mip, layers) in levels.iter().enumerate() {
+            for layer in 0..num_layers {
+                let rgba: &RgbaImage = layers[layer.min(layers.len() - 1)].borrow();
+                let (width, height) = rgba.dimensions();
+                queue.write_texture(
+                    ImageCopyTexture {
+                        aspect: TextureAspect::All,
+                        texture: &texture,
+                        mip_level: mip as u32,
+                        origin: Origin3d { x: 0, y: 0, z: layer as u32 }
+                    },
+                    &kind.pixel_data(rgba),
+                    ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(kind.bytes_per_pixel() * width),
+                        rows_per_image: Some(height)
+                    },
+                    Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1
+                    }
+                );
+            }
+        }
 
-        let view = texture.create_view(&TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
-            &SamplerDescriptor {
-                address_mode_u: AddressMode::ClampToEdge,
-                address_mode_v: AddressMode::ClampToEdge,
-                address_mode_w: AddressMode::ClampToEdge,
-                mag_filter: FilterMode::Linear,
-                min_filter: FilterMode::Nearest,
-                mipmap_filter: FilterMode::Nearest,
+        let labels = label.map(DebugLabels::new);
+        let view = texture.create_view(
+            &TextureViewDescriptor {
+                label: labels.as_ref().map(DebugLabels::view).as_deref(),
+                dimension: Some(view_dimension),
                 ..Default::default()
             }
         );
+        // Formatted, as RendererError isn't Send on the web, which anyhow needs.
+        scope.pop_now(device).map_err(|e| anyhow!("{e}"))?;
 
         Ok(Self {
             texture,
-            view,
-            sampler
+            view
         })
     }
 
     pub fn get_texture_bind_group_layout(device: &Device) -> BindGroupLayout
+    {
+        Self::create_texture_bind_group_layout(device, "Texture Bind Group Layout",
+            TextureViewDimension::D2)
+    }
+
+    // For textures made with from_images.
+    pub fn get_texture_array_bind_group_layout(device: &Device) -> BindGroupLayout
+    {
+        Self::create_texture_bind_group_layout(device, "Texture Array Bind Group Layout",
+            TextureViewDimension::D2Array)
+    }
+
+    fn create_texture_bind_group_layout(
+        device: &Device,
+        label: &str,
+        view_dimension: TextureViewDimension
+    ) -> BindGroupLayout
     {
         device.create_bind_group_layout(
             &BindGroupLayoutDescriptor {
-                label: Some("Texture Bind Group Layout"),
+                label: Some(label),
                 entries: &[
                     BindGroupLayoutEntry {
                         binding: 0,
                         visibility: ShaderStages::FRAGMENT,
                         ty: BindingType::Texture {
                             multisampled: false,
-                            view_dimension: TextureViewDimension::D2,
+                            view_dimension,
                             sample_type: TextureSampleType::Float {
                                 filterable: true
                             }
@@ -115,9 +417,14 @@ impl Texture {
         )
     }
 
+    // Usually DEPTH_FORMAT, or DEPTH_STENCIL_FORMAT for stencil effects. Its view covers
+    // every aspect, for attaching it. A multisampled one matches multisampled color
+    // attachments. Billboards read a copy of it while it's attached.
     pub fn create_depth_texture(
         device: &Device,
         config: &SurfaceConfiguration,
+        format: TextureFormat,
+        sample_count: u32,
         label: &str
     ) -> Self
     {
@@ -131,30 +438,22 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
             view_formats: &[]
         };
         let texture = device.create_texture(&desc);
 
-        let view = texture.create_view(&TextureViewDescriptor::default());
-        let sampler = device.create_sampler(
-            &SamplerDescriptor {
-                address_mode_u: AddressMode::ClampToEdge,
-                address_mode_v: AddressMode::ClampToEdge,
-                address_mode_w: AddressMode::ClampToEdge,
-                mag_filter: FilterMode::Linear,
-                min_filter: FilterMode::Linear,
-                mipmap_filter: FilterMode::Nearest,
-                compare: Some(CompareFunction::LessEqual),
-                lod_min_clamp: 0.0,
-                lod_max_clamp: 100.0,
+        let labels = DebugLabels::new(label);
+        let view = texture.create_view(
+            &TextureViewDescriptor {
+                label: Some(&labels.view()),
                 ..Default::default()
             }
         );
-
-        Self { texture, view, sampler }
+        Self { texture, view }
     }
 }
@@ -0,0 +1,153 @@
+use std::cmp::Ordering;
+
+use anyhow::*;
+use wgpu::{BindGroupLayout, Device, Queue, Sampler};
+
+use super::residency::ResidentTexture;
+
+pub const DEFAULT_UPLOAD_BUDGET_BYTES: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamingStats {
+    pub upload_budget_bytes: u64,
+    // Last update only.
+    pub uploaded_bytes: u64,
+    // Textures still coarser than they want to be after the last update.
+    pub pending: usize,
+    pub streamed_mips: u64,
+    pub dropped_mips: u64
+}
+
+// A resident texture and how close the camera is to its nearest user, None when
+// nothing drew with it this frame.
+pub struct StreamRequest<'a> {
+    texture: &'a mut ResidentTexture,
+    distance: Option<f32>,
+    wanted_mip: u32
+}
+
+impl<'a> StreamRequest<'a> {
+    // Wants the mip whose texels roughly match the pixels a unit sized object covers
+    // at this distance, never coarser than the base mip.
+    pub fn new(texture: &'a mut ResidentTexture, distance: Option<f32>, pixels_per_unit: f32) -> Self
+    {
+        let wanted_mip = distance.map_or(texture.base_mip(), |distance| {
+            let pixels = (pixels_per_unit / distance.max(0.01)).max(1.0);
+            let mip = (texture.width() as f32 / pixels).log2().floor().max(0.0) as u32;
+
+            mip.min(texture.base_mip())
+        });
+
+        Self {
+            texture,
+            distance,
+            wanted_mip
+        }
+    }
+}
+
+// Moves resident textures one mip at a time between their base mip and the one their
+// distance to the camera asks for. The closest go first, until the frame's upload
+// budget is spent. Over the VRAM budget, the farthest drop their finest mips again
+// before anything new is streamed in.
+pub struct TextureStreamer {
+    upload_budget_bytes: u64,
+    stats: StreamingStats
+}
+
+impl TextureStreamer {
+    pub fn new(upload_budget_bytes: u64) -> Self
+    {
+        Self {
+            upload_budget_bytes,
+            stats: StreamingStats {
+                upload_budget_bytes,
+                ..Default::default()
+            }
+        }
+    }
+
+    pub fn set_upload_budget(&mut self, upload_budget_bytes: u64)
+    {
+        self.upload_budget_bytes = upload_budget_bytes;
+        self.stats.upload_budget_bytes = upload_budget_bytes;
+    }
+
+    // Returns whether any texture was recreated, bind groups made from them are stale.
+    pub fn update(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        requests: &mut [StreamRequest],
+        vram_budget_bytes: u64
+    ) -> Result<bool>
+    {
+        requests.sort_by(|a, b| match (a.distance, b.distance) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal
+        });
+        let mut resident_bytes = requests.iter()
+            .map(|request| request.texture.size_in_bytes())
+            .sum::<u64>();
+        let mut uploaded_bytes = 0;
+        let mut changed = false;
+
+        for request in requests.iter_mut().rev() {
+            if resident_bytes <= vram_budget_bytes {
+                break;
+            }
+
+            let texture = &mut request.texture;
+            while resident_bytes > vram_budget_bytes && texture.resident_mip() < texture.base_mip() {
+                let size_bytes = texture.size_in_bytes();
+                texture.stream_to(device, queue, layout, sampler, texture.resident_mip() + 1)?;
+                resident_bytes = resident_bytes - size_bytes + texture.size_in_bytes();
+                uploaded_bytes += texture.size_in_bytes();
+                self.stats.dropped_mips += 1;
+                changed = true;
+            }
+        }
+
+        let mut pending = 0;
+        for request in requests.iter_mut() {
+            let wanted_mip = request.wanted_mip;
+            let texture = &mut request.texture;
+            if texture.resident_mip() <= wanted_mip {
+                continue;
+            }
+
+            // The first upload always goes, so a budget below one mip can't stall streaming.
+            let next_mip = texture.resident_mip() - 1;
+            let size_bytes = texture.size_from_mip(next_mip);
+            let over_vram = resident_bytes - texture.size_in_bytes() + size_bytes > vram_budget_bytes;
+            let over_upload = uploaded_bytes > 0 && uploaded_bytes + size_bytes > self.upload_budget_bytes;
+            if over_vram || over_upload {
+                pending += 1;
+                continue;
+            }
+
+            resident_bytes = resident_bytes - texture.size_in_bytes() + size_bytes;
+            texture.stream_to(device, queue, layout, sampler, next_mip)?;
+            uploaded_bytes += size_bytes;
+            self.stats.streamed_mips += 1;
+            changed = true;
+            if next_mip > wanted_mip {
+                pending += 1;
+            }
+        }
+
+        self.stats.uploaded_bytes = uploaded_bytes;
+        self.stats.pending = pending;
+
+        Ok(changed)
+    }
+
+    pub fn stats(&self) -> StreamingStats
+    {
+        self.stats
+    }
+}
@@ -0,0 +1,119 @@
+use std::rc::Rc;
+
+use wgpu::{Device, Extent3d, Texture as WgpuTexture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor};
+
+use super::debug_labels::DebugLabels;
+
+const MAX_IDLE_FRAMES: u64 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientTextureDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    pub usage: TextureUsages,
+    pub sample_count: u32
+}
+
+impl TransientTextureDesc {
+    pub fn size_in_bytes(&self) -> u64
+    {
+        let block_size = self.format.block_copy_size(None).unwrap_or(4) as u64;
+
+        self.width as u64 * self.height as u64 * block_size * self.sample_count as u64
+    }
+}
+
+pub struct TransientTexture {
+    pub texture: WgpuTexture,
+    pub view: TextureView,
+    pub desc: TransientTextureDesc
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransientPoolStats {
+    pub allocated: u64,
+    pub reused: u64,
+    pub pooled_bytes: u64
+}
+
+// Passes acquire intermediate targets for the part of the frame they need them and
+// release them afterwards, so later passes with a matching description render into
+// the same texture instead of allocating their own.
+#[derive(Default)]
+pub struct TransientTexturePool {
+    free: Vec<(Rc<TransientTexture>, u64)>,
+    frame: u64,
+    stats: TransientPoolStats
+}
+
+impl TransientTexturePool {
+    pub fn begin_frame(&mut self)
+    {
+        self.frame += 1;
+
+        let frame = self.frame;
+        self.free.retain(|(_, last_used_frame)| frame - last_used_frame <= MAX_IDLE_FRAMES);
+        self.stats.pooled_bytes = self.free.iter()
+            .map(|(texture, _)| texture.desc.size_in_bytes())
+            .sum();
+    }
+
+    pub fn acquire(
+        &mut self,
+        device: &Device,
+        desc: &TransientTextureDesc,
+        label: &str
+    ) -> Rc<TransientTexture>
+    {
+        if let Some(index) = self.free.iter().position(|(texture, _)| texture.desc == *desc) {
+            self.stats.reused += 1;
+            return self.free.swap_remove(index).0;
+        }
+
+        self.stats.allocated += 1;
+        let texture = device.create_texture(
+            &TextureDescriptor {
+                label: Some(label),
+                size: Extent3d {
+                    width: desc.width,
+                    height: desc.height,
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: desc.sample_count,
+                dimension: TextureDimension::D2,
+                format: desc.format,
+                usage: desc.usage,
+                view_formats: &[]
+            }
+        );
+        let view = texture.create_view(
+            &TextureViewDescriptor {
+                label: Some(&DebugLabels::new(label).view()),
+                ..Default::default()
+            }
+        );
+
+        Rc::new(TransientTexture {
+            texture,
+            view,
+            desc: *desc
+        })
+    }
+
+    pub fn release(&mut self, texture: Rc<TransientTexture>)
+    {
+        self.free.push((texture, self.frame));
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.free.clear();
+    }
+
+    pub fn stats(&self) -> TransientPoolStats
+    {
+        self.stats
+    }
+}
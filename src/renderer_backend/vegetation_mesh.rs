@@ -0,0 +1,86 @@
+use std::ops::Range;
+
+use cgmath::{MetricSpace, Point3};
+use wgpu::{Device, Queue, RenderPass};
+
+use crate::state::{terrain::Heightmap, vegetation::{self, VegetationOptions}};
+
+use super::{debug_labels::DebugLabels, gpu_allocator::{AllocationKind, GpuAllocation, GpuAllocator}};
+
+// Three quads crossing at the plant's center, two triangles each.
+pub const VERTICES_PER_PLANT: u32 = 18;
+
+// The plants scattered over a terrain, one instance each in a single allocation,
+// sorted by terrain chunk so whole chunks past the draw distance can be skipped.
+pub struct VegetationMesh {
+    labels: DebugLabels,
+    instance_allocation: GpuAllocation,
+    chunk_ranges: Vec<Range<u32>>,
+    chunk_centers: Vec<Point3<f32>>,
+    draw_distance: f32,
+    num_plants: u32
+}
+
+impl VegetationMesh {
+    // None when nothing could grow anywhere on the terrain.
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        allocator: &mut GpuAllocator,
+        label: &str,
+        heightmap: &Heightmap,
+        options: &VegetationOptions
+    ) -> Option<Self>
+    {
+        let (plants, chunk_ranges) = vegetation::scatter(heightmap, options);
+        if plants.is_empty() {
+            return None;
+        }
+
+        let instance_allocation = allocator.allocate_init(device, queue, AllocationKind::Vertex,
+            bytemuck::cast_slice(&plants));
+        let chunk_centers = (0..heightmap.chunks().pow(2))
+            .map(|chunk| heightmap.chunk_center(chunk))
+            .collect();
+
+        Some(Self {
+            labels: DebugLabels::new(label),
+            instance_allocation,
+            chunk_ranges,
+            chunk_centers,
+            draw_distance: options.draw_distance,
+            num_plants: plants.len() as u32
+        })
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn num_plants(&self) -> u32
+    {
+        self.num_plants
+    }
+
+    pub fn free(self, allocator: &mut GpuAllocator)
+    {
+        allocator.free(self.instance_allocation);
+    }
+
+    // Expects the pipeline and its bind groups to be set already.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        allocator: &'a GpuAllocator,
+        eye: Point3<f32>
+    )
+    {
+        render_pass.set_vertex_buffer(0, allocator.slice(&self.instance_allocation));
+        for (range, &center) in self.chunk_ranges.iter().zip(&self.chunk_centers) {
+            if !range.is_empty() && center.distance(eye) <= self.draw_distance {
+                render_pass.draw(0..VERTICES_PER_PLANT, range.clone());
+            }
+        }
+    }
+}
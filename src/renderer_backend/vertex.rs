@@ -1,6 +1,7 @@
-use std::mem::size_of;
 use bytemuck::{Pod, Zeroable};
-use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode};
+use wgpu::{vertex_attr_array, VertexAttribute};
+
+use super::vertex_layout::VertexLayout;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
@@ -9,24 +10,9 @@ pub struct Vertex {
     pub tex_coords: [f32; 2]
 }
 
-impl Vertex {
-    pub fn get_vertex_buffer_layout() -> VertexBufferLayout<'static>
-    {
-        VertexBufferLayout {
-            array_stride: size_of::<Vertex>() as BufferAddress,
-            step_mode: VertexStepMode::Vertex,
-            attributes: &[
-                VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: VertexFormat::Float32x3
-                },
-                VertexAttribute {
-                    offset: size_of::<[f32; 3]>() as BufferAddress,
-                    shader_location: 1,
-                    format: VertexFormat::Float32x2
-                }
-            ]
-        }
-    }
+impl VertexLayout for Vertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x2
+    ];
 }
@@ -0,0 +1,26 @@
+use std::mem::size_of;
+
+use bytemuck::Pod;
+use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexStepMode};
+
+// Implementors only list their attributes, usually with wgpu::vertex_attr_array! so
+// offsets follow from the formats, and get a matching buffer layout for free:
+//
+//     impl VertexLayout for SkinnedVertex {
+//         const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+//             0 => Float32x3, 1 => Float32x2, 2 => Float32x3, 3 => Uint32x4, 4 => Float32x4
+//         ];
+//     }
+pub trait VertexLayout: Pod {
+    const ATTRIBUTES: &'static [VertexAttribute];
+    const STEP_MODE: VertexStepMode = VertexStepMode::Vertex;
+
+    fn vertex_buffer_layout() -> VertexBufferLayout<'static>
+    {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as BufferAddress,
+            step_mode: Self::STEP_MODE,
+            attributes: Self::ATTRIBUTES
+        }
+    }
+}
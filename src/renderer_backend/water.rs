@@ -0,0 +1,321 @@
+use std::{f32::consts::TAU, rc::Rc};
+
+use bytemuck::{Pod, Zeroable};
+use cgmath::Point3;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt, TextureDataOrder}, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Extent3d, Queue, Sampler, SamplerBindingType, ShaderStages, SurfaceConfiguration, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension};
+
+use crate::state::camera::CameraUniform;
+
+use super::{debug_labels::DebugLabels, sampler_cache::{SamplerCache, SamplerSpec}, texture::Texture, transient::TransientTextureDesc};
+
+const NORMAL_MAP_SIZE: u32 = 128;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WaterOptions {
+    // The water is a square of `extent` units around the origin at this height.
+    pub height: f32,
+    pub extent: f32,
+    pub color: [f32; 3],
+    // How much of the refracted scene is replaced by the water color, 0 is clear water.
+    pub murkiness: f32,
+    // Screen space offset of the reflection and refraction lookups along the waves.
+    pub wave_strength: f32,
+    pub wave_speed: f32,
+    // Size of the reflection and refraction targets relative to the surface.
+    pub resolution_scale: f32
+}
+
+impl Default for WaterOptions {
+    fn default() -> Self
+    {
+        Self {
+            height: -3.0,
+            extent: 128.0,
+            color: [0.05, 0.22, 0.3],
+            murkiness: 0.35,
+            wave_strength: 0.02,
+            wave_speed: 0.03,
+            resolution_scale: 0.5
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct WaterUniform {
+    // xyz is the camera position, w the time in seconds.
+    eye: [f32; 4],
+    // height, extent, wave strength, wave speed
+    params: [f32; 4],
+    // rgb is the water color, a the murkiness.
+    color: [f32; 4]
+}
+
+// Planar water. Every frame the scene is rendered twice more before the main pass:
+// mirrored below the water plane into the reflection target, and as seen from the
+// camera into the refraction target. The water surface then blends the two with a
+// Fresnel term, both lookups offset by two scrolling normal maps.
+pub struct Water {
+    labels: DebugLabels,
+    options: WaterOptions,
+    time: f32,
+    uniform_buffer: Buffer,
+    reflection_camera_buffer: Buffer,
+    reflection_camera_bind_group: BindGroup,
+    bind_group_layout: BindGroupLayout,
+    normal_map: TextureView,
+    screen_sampler: Rc<Sampler>,
+    normal_sampler: Rc<Sampler>
+}
+
+impl Water {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        label: &str,
+        options: &WaterOptions,
+        camera_bind_group_layout: &BindGroupLayout,
+        samplers: &mut SamplerCache
+    ) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let uniform_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[WaterUniform::zeroed()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let reflection_camera_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix("Reflection Camera Buffer")),
+                contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let reflection_camera_bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.with_suffix("Reflection Camera Bind Group")),
+                layout: camera_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: reflection_camera_buffer.as_entire_binding()
+                    }
+                ]
+            }
+        );
+
+        let normal_map = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some(&labels.with_suffix("Normal Map")),
+                size: Extent3d {
+                    width: NORMAL_MAP_SIZE,
+                    height: NORMAL_MAP_SIZE,
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[]
+            },
+            TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&Self::generate_normal_map(NORMAL_MAP_SIZE))
+        ).create_view(&TextureViewDescriptor::default());
+        let screen_sampler = samplers.get(device, SamplerSpec::default().with_anisotropy(1));
+        let normal_sampler = samplers.get(device, SamplerSpec::default()
+            .with_address_mode(AddressMode::Repeat));
+
+        Self {
+            bind_group_layout: Self::get_bind_group_layout(device, &labels),
+            labels,
+            options: *options,
+            time: 0.0,
+            uniform_buffer,
+            reflection_camera_buffer,
+            reflection_camera_bind_group,
+            normal_map,
+            screen_sampler,
+            normal_sampler
+        }
+    }
+
+    fn get_bind_group_layout(device: &Device, labels: &DebugLabels) -> BindGroupLayout
+    {
+        let texture_entry = |binding: u32| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension: TextureViewDimension::D2,
+                sample_type: TextureSampleType::Float { filterable: true }
+            },
+            count: None
+        };
+        let sampler_entry = |binding: u32| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None
+        };
+
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&labels.bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    },
+                    texture_entry(1),
+                    texture_entry(2),
+                    texture_entry(3),
+                    sampler_entry(4),
+                    sampler_entry(5)
+                ]
+            }
+        )
+    }
+
+    // A tileable height field made of sine waves with whole periods across the
+    // texture, stored as tangent space normals with z up.
+    fn generate_normal_map(size: u32) -> Vec<[u8; 4]>
+    {
+        const WAVES: [(f32, f32, f32, f32); 4] = [
+            (3.0, 1.0, 0.0, 1.0),
+            (-2.0, 5.0, 1.3, 0.6),
+            (7.0, -4.0, 2.1, 0.3),
+            (-9.0, -11.0, 4.7, 0.15)
+        ];
+
+        (0..size * size)
+            .map(|index| {
+                let u = (index % size) as f32 / size as f32;
+                let v = (index / size) as f32 / size as f32;
+                let (mut slope_u, mut slope_v) = (0.0, 0.0);
+                for (frequency_u, frequency_v, phase, amplitude) in WAVES {
+                    let wave = (TAU * (frequency_u * u + frequency_v * v) + phase).cos() * amplitude;
+                    slope_u += wave * frequency_u * 0.05;
+                    slope_v += wave * frequency_v * 0.05;
+                }
+
+                let length = (slope_u * slope_u + slope_v * slope_v + 1.0).sqrt();
+                let encode = |value: f32| ((value / length * 0.5 + 0.5) * 255.0).round() as u8;
+                [encode(-slope_u), encode(-slope_v), encode(1.0), 255]
+            })
+            .collect()
+    }
+
+    pub fn options(&self) -> &WaterOptions
+    {
+        &self.options
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout
+    {
+        &self.bind_group_layout
+    }
+
+    pub fn reflection_camera_bind_group(&self) -> &BindGroup
+    {
+        &self.reflection_camera_bind_group
+    }
+
+    pub fn color_target_desc(&self, config: &SurfaceConfiguration) -> TransientTextureDesc
+    {
+        TransientTextureDesc {
+            format: config.format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            ..self.depth_target_desc(config)
+        }
+    }
+
+    pub fn depth_target_desc(&self, config: &SurfaceConfiguration) -> TransientTextureDesc
+    {
+        let scale = |size: u32| ((size as f32 * self.options.resolution_scale) as u32).max(1);
+
+        TransientTextureDesc {
+            width: scale(config.width),
+            height: scale(config.height),
+            format: Texture::DEPTH_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            sample_count: 1
+        }
+    }
+
+    pub fn advance(&mut self, delta: f32)
+    {
+        self.time += delta;
+    }
+
+    pub fn write_uniforms(&self, queue: &Queue, eye: Point3<f32>, reflection_camera: &CameraUniform)
+    {
+        let options = &self.options;
+        let [r, g, b] = options.color;
+        let uniform = WaterUniform {
+            eye: [eye.x, eye.y, eye.z, self.time],
+            params: [options.height, options.extent, options.wave_strength, options.wave_speed],
+            color: [r, g, b, options.murkiness]
+        };
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        queue.write_buffer(&self.reflection_camera_buffer, 0, bytemuck::cast_slice(&[*reflection_camera]));
+    }
+
+    // The targets come from the transient pool and may change every frame, so the
+    // bind group is rebuilt along with them.
+    pub fn create_bind_group(
+        &self,
+        device: &Device,
+        reflection: &TextureView,
+        refraction: &TextureView
+    ) -> BindGroup
+    {
+        device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&self.labels.bind_group()),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(reflection)
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(refraction)
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(&self.normal_map)
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::Sampler(&self.screen_sampler)
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: BindingResource::Sampler(&self.normal_sampler)
+                    }
+                ]
+            }
+        )
+    }
+}
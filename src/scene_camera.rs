@@ -0,0 +1,143 @@
+use cgmath::{InnerSpace, Quaternion, Rad, Rotation, Rotation3, Vector3};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, Buffer, BufferUsages, Device};
+
+use super::{camera::{Camera, CameraUniform}, camera_rig::CameraRig, renderer_backend::debug_labels::DebugLabels};
+
+// What moves a scene camera while it's the active one.
+#[derive(Debug, Clone)]
+pub enum CameraKind {
+    // The camera controller, camera rig and follow camera, like the startup camera.
+    Free,
+    // Circles its target around the up axis by itself, `speed` radians per second.
+    Orbit {
+        speed: f32
+    },
+    // Flies a rig of its own, e.g. a keyframed path.
+    Cinematic(CameraRig),
+    // Looks at its target from `distance` towards the sun, to see the scene the way the
+    // light does.
+    LightDebug {
+        distance: f32
+    }
+}
+
+impl CameraKind {
+    // Moves `camera` along by `delta` seconds. Free cameras are left where they are.
+    pub fn update(&mut self, camera: &mut Camera, sun_direction: Vector3<f32>, delta: f32)
+    {
+        match self {
+            CameraKind::Free => {},
+            CameraKind::Orbit { speed } => {
+                let rotation = Quaternion::from_axis_angle(camera.up.normalize(), Rad(*speed * delta));
+                camera.eye = camera.target + rotation.rotate_vector(camera.eye - camera.target);
+            },
+            CameraKind::Cinematic(rig) => rig.update(camera, delta),
+            CameraKind::LightDebug { distance } => {
+                if sun_direction.magnitude2() > 0.0 {
+                    camera.eye = camera.target + sun_direction.normalize() * *distance;
+                }
+            }
+        }
+    }
+}
+
+// One of the cameras the main pass can look through, each with its own uniform buffer.
+// The active camera is State's, so while a camera is active its own fields here are
+// stale, see State::set_active_camera.
+pub struct SceneCamera {
+    labels: DebugLabels,
+    pub kind: CameraKind,
+    camera: Camera,
+    uniform: CameraUniform,
+    buffer: Buffer,
+    bind_group: BindGroup
+}
+
+impl SceneCamera {
+    pub fn new(
+        device: &Device,
+        label: &str,
+        kind: CameraKind,
+        camera: Camera,
+        camera_bind_group_layout: &BindGroupLayout
+    ) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let mut uniform = CameraUniform::new();
+        uniform.update_view_proj(&camera);
+        let (buffer, bind_group) = Self::create_camera_binding(device, &labels, camera_bind_group_layout,
+            &uniform);
+
+        Self {
+            labels,
+            kind,
+            camera,
+            uniform,
+            buffer,
+            bind_group
+        }
+    }
+
+    // After a device loss.
+    pub fn recreate(&mut self, device: &Device, camera_bind_group_layout: &BindGroupLayout)
+    {
+        (self.buffer, self.bind_group) = Self::create_camera_binding(device, &self.labels,
+            camera_bind_group_layout, &self.uniform);
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    // Stale while the camera is active.
+    pub fn camera(&self) -> &Camera
+    {
+        &self.camera
+    }
+
+    // Trades places with the active camera's state, which makes this one active.
+    pub fn swap(
+        &mut self,
+        camera: &mut Camera,
+        uniform: &mut CameraUniform,
+        buffer: &mut Buffer,
+        bind_group: &mut BindGroup
+    )
+    {
+        std::mem::swap(&mut self.camera, camera);
+        std::mem::swap(&mut self.uniform, uniform);
+        std::mem::swap(&mut self.buffer, buffer);
+        std::mem::swap(&mut self.bind_group, bind_group);
+    }
+
+    fn create_camera_binding(
+        device: &Device,
+        labels: &DebugLabels,
+        layout: &BindGroupLayout,
+        uniform: &CameraUniform
+    ) -> (Buffer, BindGroup)
+    {
+        let buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix("Camera Buffer")),
+                contents: bytemuck::cast_slice(&[*uniform]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.with_suffix("Camera Bind Group")),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding()
+                    }
+                ]
+            }
+        );
+
+        (buffer, bind_group)
+    }
+}
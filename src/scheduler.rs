@@ -0,0 +1,119 @@
+use web_time::{Duration, Instant};
+
+pub struct Tick {
+    pub delta: Duration,
+    pub deadline: Option<Instant>
+}
+
+impl Tick {
+    pub fn has_time_left(&self) -> bool
+    {
+        self.deadline.is_none_or(|deadline| Instant::now() < deadline)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SystemTiming {
+    pub name: &'static str,
+    pub last: Duration,
+    pub average: Duration,
+    pub max: Duration,
+    pub over_budget: u64
+}
+
+type TickFn<C> = Box<dyn FnMut(&mut C, &Tick)>;
+
+struct System<C> {
+    priority: i32,
+    budget: Option<Duration>,
+    tick: TickFn<C>,
+    timing: SystemTiming
+}
+
+// Runs registered systems in ascending priority order once per frame.
+pub struct Scheduler<C> {
+    systems: Vec<System<C>>,
+    last_tick: Option<Instant>
+}
+
+impl<C> Default for Scheduler<C> {
+    fn default() -> Self
+    {
+        Self {
+            systems: Vec::new(),
+            last_tick: None
+        }
+    }
+}
+
+impl<C> Scheduler<C> {
+    pub fn add_system(
+        &mut self,
+        name: &'static str,
+        priority: i32,
+        budget: Option<Duration>,
+        tick: impl FnMut(&mut C, &Tick) + 'static
+    )
+    {
+        let index = self.systems.partition_point(|system| system.priority <= priority);
+        self.systems.insert(index, System {
+            priority,
+            budget,
+            tick: Box::new(tick),
+            timing: SystemTiming {
+                name,
+                ..Default::default()
+            }
+        });
+    }
+
+    pub fn remove_system(&mut self, name: &str)
+    {
+        self.systems.retain(|system| system.timing.name != name);
+    }
+
+    // The next run gets a zero delta, e.g. after rendering was paused, so the time in
+    // between isn't simulated in one step.
+    pub fn reset_clock(&mut self)
+    {
+        self.last_tick = None;
+    }
+
+    pub fn run(&mut self, context: &mut C)
+    {
+        let now = Instant::now();
+        let delta = self.last_tick.map_or(Duration::ZERO, |last_tick| now - last_tick);
+        self.last_tick = Some(now);
+
+        for system in &mut self.systems {
+            let start = Instant::now();
+            let tick = Tick {
+                delta,
+                deadline: system.budget.map(|budget| start + budget)
+            };
+
+            (system.tick)(context, &tick);
+
+            let elapsed = start.elapsed();
+            let timing = &mut system.timing;
+            timing.last = elapsed;
+            timing.average = if timing.average.is_zero() {
+                elapsed
+            } else {
+                timing.average.mul_f32(0.9) + elapsed.mul_f32(0.1)
+            };
+            timing.max = timing.max.max(elapsed);
+
+            if system.budget.is_some_and(|budget| elapsed > budget) {
+                timing.over_budget += 1;
+                log::debug!("System {} took {elapsed:?}, over its {:?} budget", timing.name,
+                    system.budget);
+            }
+        }
+    }
+
+    pub fn timings(&self) -> impl Iterator<Item = &SystemTiming>
+    {
+        self.systems.iter().map(|system| &system.timing)
+    }
+}
@@ -0,0 +1,326 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}, time::Duration};
+
+use serde::{Deserialize, Serialize, Serializer};
+use web_time::Instant;
+use winit::dpi::LogicalSize;
+
+use crate::{state::{options::StateOptions, renderer_backend::{anti_aliasing::AntiAliasing, render_scale::Upscaling}}, window_config::WindowConfig};
+
+// How often a SettingsWatcher looks for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// What a user can change without rebuilding, read from a TOML file, or on the web from
+// localStorage. Everything is optional, values the file leaves out keep what the
+// renderer was started with, e.g. from the environment or the command line:
+//
+//   [window]
+//   width = 1920
+//   fullscreen = true
+//
+//   [render]
+//   vsync = false
+//   anti_aliasing = "taa"
+//   render_scale = 0.75
+//   upscaling = "sharpened"
+//
+//   [camera]
+//   speed = 0.4
+//
+//   [bindings]
+//   MoveForward = ["KeyW", "ArrowUp"]
+//
+// Bindings use the action and key names of InputMap's bindings file. What's changed
+// while running is written back on State::shutdown.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    #[serde(skip_serializing_if = "is_default")]
+    pub window: WindowSettings,
+    #[serde(skip_serializing_if = "is_default")]
+    pub render: RenderSettings,
+    #[serde(skip_serializing_if = "is_default")]
+    pub camera: CameraSettings,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub bindings: BTreeMap<String, Vec<String>>
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    pub title: Option<String>,
+    // Logical pixels.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fullscreen: Option<bool>,
+    pub resizable: Option<bool>,
+    pub decorations: Option<bool>
+}
+
+impl WindowSettings {
+    fn from_config(config: &WindowConfig) -> Self
+    {
+        let size = config.size.unwrap_or(LogicalSize::new(1280, 720));
+
+        Self {
+            title: Some(config.title.clone()),
+            width: Some(size.width),
+            height: Some(size.height),
+            fullscreen: Some(config.fullscreen),
+            resizable: Some(config.resizable),
+            decorations: Some(config.decorations)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub vsync: Option<bool>,
+    pub anti_aliasing: Option<AntiAliasing>,
+    // From 0.5 to 2 times the surface's size.
+    #[serde(serialize_with = "shortest_f32")]
+    pub render_scale: Option<f32>,
+    pub upscaling: Option<Upscaling>
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraSettings {
+    // Units moved per update while a movement key is held.
+    #[serde(serialize_with = "shortest_f32")]
+    pub speed: Option<f32>
+}
+
+impl Settings {
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error>
+    {
+        toml::from_str(contents)
+    }
+
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error>
+    {
+        toml::to_string(self)
+    }
+
+    // None when there's nothing at `path`, or when it doesn't parse, which is logged.
+    pub fn load(path: &Path) -> Option<Self>
+    {
+        let contents = read(path)?;
+
+        Self::from_toml(&contents)
+            .map_err(|e| log::warn!("Ignoring the settings in {}: {e}", path.display()))
+            .ok()
+    }
+
+    // Where load reads them from. Failing to is logged.
+    pub fn save(&self, path: &Path)
+    {
+        match self.to_toml() {
+            Ok(contents) => write(path, &contents),
+            Err(e) => log::warn!("Couldn't save the settings to {}: {e}", path.display())
+        }
+    }
+
+    // What `options` start the renderer with, every value set.
+    pub fn from_options(options: &StateOptions) -> Self
+    {
+        Self {
+            window: WindowSettings::from_config(&options.window),
+            render: RenderSettings {
+                vsync: Some(options.surface.vsync),
+                anti_aliasing: Some(options.anti_aliasing),
+                render_scale: Some(options.render_scale),
+                upscaling: Some(options.upscaling)
+            },
+            camera: CameraSettings {
+                speed: Some(options.camera_speed)
+            },
+            bindings: options.bindings.clone()
+        }
+    }
+
+    // These settings with the values `other` has in place of theirs. Bindings are
+    // merged by action.
+    pub fn merged(&self, other: &Settings) -> Self
+    {
+        fn pick<T: Clone>(value: &Option<T>, other: &Option<T>) -> Option<T>
+        {
+            other.clone().or_else(|| value.clone())
+        }
+
+        let mut bindings = self.bindings.clone();
+        bindings.extend(other.bindings.clone());
+
+        Self {
+            window: WindowSettings {
+                title: pick(&self.window.title, &other.window.title),
+                width: pick(&self.window.width, &other.window.width),
+                height: pick(&self.window.height, &other.window.height),
+                fullscreen: pick(&self.window.fullscreen, &other.window.fullscreen),
+                resizable: pick(&self.window.resizable, &other.window.resizable),
+                decorations: pick(&self.window.decorations, &other.window.decorations)
+            },
+            render: RenderSettings {
+                vsync: pick(&self.render.vsync, &other.render.vsync),
+                anti_aliasing: pick(&self.render.anti_aliasing, &other.render.anti_aliasing),
+                render_scale: pick(&self.render.render_scale, &other.render.render_scale),
+                upscaling: pick(&self.render.upscaling, &other.render.upscaling)
+            },
+            camera: CameraSettings {
+                speed: pick(&self.camera.speed, &other.camera.speed)
+            },
+            bindings
+        }
+    }
+
+    // The values these settings have that differ from those in `old`, the others left
+    // out. Bindings are compared by action.
+    pub fn changes_from(&self, old: &Settings) -> Self
+    {
+        fn change<T: Clone + PartialEq>(new: &Option<T>, old: &Option<T>) -> Option<T>
+        {
+            changed(new, old).cloned()
+        }
+
+        Self {
+            window: WindowSettings {
+                title: change(&self.window.title, &old.window.title),
+                width: change(&self.window.width, &old.window.width),
+                height: change(&self.window.height, &old.window.height),
+                fullscreen: change(&self.window.fullscreen, &old.window.fullscreen),
+                resizable: change(&self.window.resizable, &old.window.resizable),
+                decorations: change(&self.window.decorations, &old.window.decorations)
+            },
+            render: RenderSettings {
+                vsync: change(&self.render.vsync, &old.render.vsync),
+                anti_aliasing: change(&self.render.anti_aliasing, &old.render.anti_aliasing),
+                render_scale: change(&self.render.render_scale, &old.render.render_scale),
+                upscaling: change(&self.render.upscaling, &old.render.upscaling)
+            },
+            camera: CameraSettings {
+                speed: change(&self.camera.speed, &old.camera.speed)
+            },
+            bindings: self.bindings.iter()
+                .filter(|(action, keys)| old.bindings.get(*action) != Some(keys))
+                .map(|(action, keys)| (action.clone(), keys.clone()))
+                .collect()
+        }
+    }
+
+    // Before the window and State exist. The values in the settings win over the
+    // options, including those from the environment, the others are left alone.
+    pub fn apply_to_options(&self, options: &mut StateOptions)
+    {
+        fn set<T: Clone>(option: &mut T, value: &Option<T>)
+        {
+            if let Some(value) = value {
+                option.clone_from(value);
+            }
+        }
+
+        set(&mut options.window.title, &self.window.title);
+        if self.window.width.is_some() || self.window.height.is_some() {
+            let size = options.window.size.unwrap_or(LogicalSize::new(1280, 720));
+            options.window.size = Some(LogicalSize::new(self.window.width.unwrap_or(size.width),
+                self.window.height.unwrap_or(size.height)));
+        }
+        set(&mut options.window.fullscreen, &self.window.fullscreen);
+        set(&mut options.window.resizable, &self.window.resizable);
+        set(&mut options.window.decorations, &self.window.decorations);
+        set(&mut options.surface.vsync, &self.render.vsync);
+        set(&mut options.anti_aliasing, &self.render.anti_aliasing);
+        set(&mut options.render_scale, &self.render.render_scale);
+        set(&mut options.upscaling, &self.render.upscaling);
+        set(&mut options.camera_speed, &self.camera.speed);
+        options.bindings.extend(self.bindings.clone());
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool
+{
+    *value == T::default()
+}
+
+// toml widens f32 to f64, which would write 0.4 as 0.4000000059604645.
+fn shortest_f32<S: Serializer>(value: &Option<f32>, serializer: S) -> Result<S::Ok, S::Error>
+{
+    value.map(|value| value.to_string().parse().unwrap_or(f64::from(value))).serialize(serializer)
+}
+
+// The value in `new` when it differs from the one in `old`.
+pub fn changed<'s, T: PartialEq>(new: &'s Option<T>, old: &Option<T>) -> Option<&'s T>
+{
+    new.as_ref().filter(|new| old.as_ref() != Some(*new))
+}
+
+// The file on native, the localStorage entry named by the path on the web.
+fn read(path: &Path) -> Option<String>
+{
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            web_sys::window()?
+                .local_storage().ok()??
+                .get_item(&path.to_string_lossy()).ok()?
+        } else {
+            std::fs::read_to_string(path).ok()
+        }
+    }
+}
+
+// Like read, failing to write is logged.
+fn write(path: &Path, contents: &str)
+{
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            let written = web_sys::window()
+                .and_then(|window| window.local_storage().ok().flatten())
+                .is_some_and(|storage| storage.set_item(&path.to_string_lossy(), contents).is_ok());
+            if !written {
+                log::warn!("Couldn't save the settings to localStorage as {}", path.display());
+            }
+        } else {
+            if let Err(e) = std::fs::write(path, contents) {
+                log::warn!("Couldn't save the settings to {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+// Rereads the settings every second and hands them back when they changed, so edits
+// apply while running. Settings that don't parse are logged and skipped.
+pub struct SettingsWatcher {
+    path: PathBuf,
+    contents: Option<String>,
+    next_poll: Instant
+}
+
+impl SettingsWatcher {
+    pub fn new(path: &Path) -> Self
+    {
+        Self {
+            path: path.to_path_buf(),
+            contents: read(path),
+            next_poll: Instant::now() + POLL_INTERVAL
+        }
+    }
+
+    pub fn poll(&mut self) -> Option<Settings>
+    {
+        let now = Instant::now();
+        if now < self.next_poll {
+            return None;
+        }
+        self.next_poll = now + POLL_INTERVAL;
+
+        let contents = read(&self.path);
+        if contents == self.contents {
+            return None;
+        }
+        self.contents = contents;
+
+        // Deleting the file goes back to what the renderer started with.
+        Settings::from_toml(self.contents.as_deref().unwrap_or_default())
+            .map_err(|e| log::warn!("Ignoring the changed settings in {}: {e}", self.path.display()))
+            .ok()
+    }
+}
@@ -0,0 +1,97 @@
+#include "common.wgsl"
+#include "material.wgsl"
+
+#define MODE_CYLINDRICAL 1u
+
+struct BillboardInput {
+    // xyz is the center, or the bottom center for cylindrical billboards
+    @location(0) position: vec4<f32>,
+    // width, height
+    @location(1) size: vec4<f32>,
+    @location(2) color: vec4<f32>,
+    // texture index, mode
+    @location(3) params: vec4<u32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) texture_index: u32
+};
+
+struct BillboardUniform {
+    // camera near plane, far plane, the distance to the scene behind over which
+    // billboards fade out, 0 to draw them hard
+    params: vec4<f32>
+};
+
+@group(2) @binding(0)
+var<uniform> billboard: BillboardUniform;
+// The depth the opaque geometry left, bound as floats.
+@group(2) @binding(1)
+var t_depth: texture_2d<f32>;
+
+// Two triangles, counter-clockwise seen from the camera, from (0, 0) at the bottom
+// left to (1, 1) at the top right.
+fn quad_corner(vertex_index: u32) -> vec2<f32>
+{
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0)
+    );
+    return corners[vertex_index];
+}
+
+// The quad is spanned by the camera's right and up directions. Cylindrical billboards
+// keep the world's up instead and only take the horizontal part of the camera's right.
+@vertex
+fn vs_billboard(@builtin(vertex_index) vertex_index: u32, instance: BillboardInput) -> VertexOutput
+{
+    let corner = quad_corner(vertex_index);
+    var right = camera.right.xyz;
+    var up = camera.up.xyz;
+    var offset = corner - 0.5;
+    if instance.params.y == MODE_CYLINDRICAL {
+        right = normalize(vec3<f32>(right.x, 0.0, right.z));
+        up = vec3<f32>(0.0, 1.0, 0.0);
+        offset.y = corner.y;
+    }
+
+    let size = instance.size.xy;
+    let world_position = instance.position.xyz + right * offset.x * size.x + up * offset.y * size.y;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.tex_coords = vec2<f32>(corner.x, 1.0 - corner.y);
+    out.color = instance.color;
+    out.texture_index = instance.params.x;
+    return out;
+}
+
+// Distance from the camera along its view direction.
+fn linear_depth(depth: f32) -> f32
+{
+    let near = billboard.params.x;
+    let far = billboard.params.y;
+#ifdef REVERSE_Z
+    return near / max(depth, 1e-7);
+#else
+    return near * far / (far - depth * (far - near));
+#endif
+}
+
+// Soft particles: the closer the scene behind, the more transparent, so billboards
+// crossing geometry don't show a hard line where they're cut off.
+@fragment
+fn fs_billboard(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    var color = material_color(in.tex_coords, in.texture_index, in.color, vec3<f32>(0.0));
+    let soft_distance = billboard.params.z;
+    if soft_distance > 0.0 {
+        let scene_depth = textureLoad(t_depth, vec2<i32>(in.clip_position.xy), 0).r;
+        let gap = linear_depth(scene_depth) - linear_depth(in.clip_position.z);
+        color.a *= clamp(gap / soft_distance, 0.0, 1.0);
+    }
+    return color;
+}
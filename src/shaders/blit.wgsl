@@ -0,0 +1,37 @@
+#include "fullscreen.wgsl"
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@fragment
+fn fs_blit(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    return textureSampleLevel(t_source, s_source, in.uv, 0.0);
+}
+
+// How much fs_sharpen sharpens, from 0 to 1.
+#define SHARPNESS 0.5
+
+// Contrast adaptive sharpening of the bilinear upscale: the neighbours a source texel
+// away are subtracted, less where the contrast is already high so edges don't ring.
+@fragment
+fn fs_sharpen(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let center = textureSampleLevel(t_source, s_source, in.uv, 0.0);
+    let north = textureSampleLevel(t_source, s_source, in.uv - vec2<f32>(0.0, texel.y), 0.0).rgb;
+    let south = textureSampleLevel(t_source, s_source, in.uv + vec2<f32>(0.0, texel.y), 0.0).rgb;
+    let west = textureSampleLevel(t_source, s_source, in.uv - vec2<f32>(texel.x, 0.0), 0.0).rgb;
+    let east = textureSampleLevel(t_source, s_source, in.uv + vec2<f32>(texel.x, 0.0), 0.0).rgb;
+
+    let lowest = min(center.rgb, min(min(north, south), min(west, east)));
+    let highest = max(center.rgb, max(max(north, south), max(west, east)));
+    let amplitude = sqrt(clamp(min(lowest, 1.0 - highest) / max(highest, vec3<f32>(1e-5)), vec3<f32>(0.0),
+        vec3<f32>(1.0)));
+    let weight = -amplitude * mix(0.125, 0.2, SHARPNESS);
+
+    let sharpened = (center.rgb + (north + south + west + east) * weight) / (1.0 + 4.0 * weight);
+    return vec4<f32>(clamp(sharpened, vec3<f32>(0.0), vec3<f32>(1.0)), center.a);
+}
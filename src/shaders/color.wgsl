@@ -0,0 +1,21 @@
+// Color space helpers with no bindings of their own, so anything can include them.
+
+// Rec. 709 weights, for linear color.
+fn luminance(color: vec3<f32>) -> f32
+{
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32>
+{
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32>
+{
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
@@ -0,0 +1,53 @@
+#include "fullscreen.wgsl"
+#include "color.wgsl"
+
+// Linear, where contrast pivots.
+#define MIDDLE_GREY 0.18
+
+struct ColorGradingUniform {
+    // exposure multiplier, contrast, saturation, gamma
+    params: vec4<f32>,
+    // white balance multiplier, LUT strength
+    balance: vec4<f32>,
+    domain_min: vec4<f32>,
+    domain_max: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@group(1) @binding(0)
+var<uniform> grading: ColorGradingUniform;
+@group(1) @binding(1)
+var t_lut: texture_3d<f32>;
+@group(1) @binding(2)
+var s_lut: sampler;
+
+// .cube LUTs are made for sRGB encoded colors, so the lookup happens in that encoding.
+// Texel centers are what the table holds, the coordinates are pulled in to hit them.
+fn apply_lut(color: vec3<f32>) -> vec3<f32>
+{
+    let size = f32(textureDimensions(t_lut).x);
+    let range = max(grading.domain_max.rgb - grading.domain_min.rgb, vec3<f32>(1e-4));
+    let encoded = clamp((linear_to_srgb(color) - grading.domain_min.rgb) / range, vec3<f32>(0.0),
+        vec3<f32>(1.0));
+    let coordinates = encoded * (size - 1.0) / size + 0.5 / size;
+    return srgb_to_linear(textureSampleLevel(t_lut, s_lut, coordinates, 0.0).rgb);
+}
+
+// In order: exposure, white balance, contrast around middle grey, saturation around
+// the luminance, gamma, and the LUT. Everything before the LUT works on linear color.
+@fragment
+fn fs_color_grading(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let source = textureSampleLevel(t_source, s_source, in.uv, 0.0);
+    var color = source.rgb * grading.params.x * grading.balance.rgb;
+    color = max((color - MIDDLE_GREY) * grading.params.y + MIDDLE_GREY, vec3<f32>(0.0));
+    color = max(mix(vec3<f32>(luminance(color)), color, grading.params.z), vec3<f32>(0.0));
+    color = pow(color, vec3<f32>(1.0 / grading.params.w));
+    color = mix(color, apply_lut(color), grading.balance.a);
+
+    return vec4<f32>(color, source.a);
+}
@@ -0,0 +1,85 @@
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>
+};
+
+struct CameraUniform {
+    // Jittered when TAA is on, the other two never are.
+    view_proj: mat4x4<f32>,
+    unjittered_view_proj: mat4x4<f32>,
+    prev_view_proj: mat4x4<f32>,
+    // xy this frame's jitter in NDC, zw the last frame's
+    jitter: vec4<f32>,
+    // The camera's right and up directions in world space
+    right: vec4<f32>,
+    up: vec4<f32>,
+    // The same for positions relative to the origin
+    relative_view_proj: mat4x4<f32>,
+    relative_unjittered_view_proj: mat4x4<f32>,
+    relative_prev_view_proj: mat4x4<f32>,
+    // The origin is high + low, more precise than one f32
+    origin_high: vec4<f32>,
+    origin_low: vec4<f32>,
+    prev_origin_high: vec4<f32>,
+    prev_origin_low: vec4<f32>
+};
+
+// Per-instance vertex buffer input, only the skinned mesh still uses it. Instanced
+// draws read their instances from instancing.wgsl instead.
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    // phase, amplitude, seed
+    @location(9) animation: vec4<f32>,
+    @location(10) color: vec4<f32>,
+    // x is the texture index
+    @location(11) material: vec4<u32>,
+    // rgb is the material's emission
+    @location(12) emissive: vec4<f32>
+};
+
+struct VertexAnimationUniform {
+    wind: vec4<f32>,
+    time: f32
+};
+
+// Shaders whose pipelines bind the camera first define it as 0 before including this.
+#define CAMERA_GROUP 1
+
+@group(CAMERA_GROUP) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0)
+var<uniform> vertex_animation: VertexAnimationUniform;
+
+// Where the fragment was last frame, in UV units (y down) from where it is now.
+// Takes unjittered clip positions, so the jitter doesn't show up as motion.
+fn motion_vector(current_position: vec4<f32>, previous_position: vec4<f32>) -> vec2<f32>
+{
+    let current = current_position.xy / current_position.w;
+    let previous = previous_position.xy / previous_position.w;
+    return (previous - current) * vec2<f32>(0.5, -0.5);
+}
+
+fn instance_model_matrix(instance: InstanceInput) -> mat4x4<f32>
+{
+    return mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3
+    );
+}
+
+// Sways vertices along the wind, weighted by their height so the base stays in place.
+fn wind_offset(local_position: vec3<f32>, animation: vec4<f32>) -> vec3<f32>
+{
+    let wind = vertex_animation.wind;
+    let speed = 1.0 + animation.z;
+    let sway = sin(vertex_animation.time * speed + animation.x) * animation.y * wind.w;
+    let weight = clamp(local_position.y + 0.5, 0.0, 1.0);
+
+    return wind.xyz * sway * weight;
+}
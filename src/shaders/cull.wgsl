@@ -0,0 +1,79 @@
+#include "instancing.wgsl"
+#include "culling.wgsl"
+
+#define WORKGROUP_SIZE 64
+
+struct CullUniform {
+    // Left, right, bottom, top, near and far. xyz is the normal pointing inside, w the
+    // plane's distance along it.
+    planes: array<vec4<f32>, 6>,
+    // xyz is the camera's position, w the cosine of the view cone's half angle
+    cone_apex: vec4<f32>,
+    // xyz is the view direction, w the sine of the view cone's half angle
+    cone_axis: vec4<f32>,
+    // The instance mesh's bounds in object space
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    // xyz is the wind direction, w its strength, as in VertexAnimationUniform
+    wind: vec4<f32>,
+    // x is how many instances there are
+    params: vec4<u32>
+};
+
+// Which draw an instance belongs to, and where that draw's visible instances start.
+struct CullInstance {
+    draw: u32,
+    output_start: u32
+};
+
+// Laid out like wgpu's DrawIndexedIndirectArgs. The instance count starts at 0 and
+// goes up by one for every visible instance.
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32
+};
+
+@group(0) @binding(0)
+var<uniform> cull: CullUniform;
+@group(0) @binding(1)
+var<storage, read> input_instances: array<InstanceData>;
+@group(0) @binding(2)
+var<storage, read> cull_instances: array<CullInstance>;
+@group(0) @binding(3)
+var<storage, read_write> visible_instances: array<InstanceData>;
+@group(0) @binding(4)
+var<storage, read_write> draw_args: array<DrawArgs>;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cs_cull(@builtin(global_invocation_id) id: vec3<u32>)
+{
+    let index = id.x;
+    if index >= cull.params.x {
+        return;
+    }
+    let instance = input_instances[index];
+
+    // The box around the transformed mesh bounds, grown by how far the wind can sway it.
+    let local_center = (cull.bounds_min.xyz + cull.bounds_max.xyz) * 0.5;
+    let local_extent = (cull.bounds_max.xyz - cull.bounds_min.xyz) * 0.5;
+    let center = (instance.model * vec4<f32>(local_center, 1.0)).xyz;
+    let sway = abs(instance.animation.y * cull.wind.w);
+    let extent = abs(instance.model[0].xyz) * local_extent.x
+        + abs(instance.model[1].xyz) * local_extent.y
+        + abs(instance.model[2].xyz) * local_extent.z
+        + vec3<f32>(sway);
+
+    // The view cone holds the whole frustum, so it's a cheap first test. What's left
+    // goes through the six planes.
+    if sphere_outside_cone(center, length(extent), cull.cone_apex, cull.cone_axis)
+        || box_outside_planes(center, extent, cull.planes) {
+        return;
+    }
+
+    let cull_instance = cull_instances[index];
+    let slot = atomicAdd(&draw_args[cull_instance.draw].instance_count, 1u);
+    visible_instances[cull_instance.output_start + slot] = instance;
+}
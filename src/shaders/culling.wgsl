@@ -0,0 +1,27 @@
+// Visibility tests with no bindings of their own, so anything can include them.
+
+// `apex` is the cone's tip with the cosine of its half angle in w, `axis` its
+// direction with the sine in w. True only when the sphere is entirely outside.
+fn sphere_outside_cone(center: vec3<f32>, radius: f32, apex: vec4<f32>, axis: vec4<f32>) -> bool
+{
+    let offset = center - apex.xyz;
+    let along = dot(offset, axis.xyz);
+    let across = length(offset - axis.xyz * along);
+    return across * apex.w - along * axis.w > radius;
+}
+
+// The planes' xyz are normals pointing inside, w their distance along it. True only
+// when the box, `extent` being half its size, is entirely behind one of them.
+fn box_outside_planes(center: vec3<f32>, extent: vec3<f32>, planes: array<vec4<f32>, 6>) -> bool
+{
+    // Only a variable can be indexed dynamically.
+    var bounds = planes;
+    for (var i = 0u; i < 6u; i++) {
+        let plane = bounds[i];
+        let reach = dot(extent, abs(plane.xyz));
+        if dot(plane.xyz, center) + plane.w < -reach {
+            return true;
+        }
+    }
+    return false;
+}
@@ -0,0 +1,27 @@
+#define CAMERA_GROUP 0
+#include "common.wgsl"
+
+struct LineVertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>
+};
+
+@vertex
+fn vs_lines(input: LineVertexInput) -> VertexOutput
+{
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(input.position, 1.0);
+    out.color = input.color;
+    return out;
+}
+
+@fragment
+fn fs_lines(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    return vec4<f32>(in.color, 1.0);
+}
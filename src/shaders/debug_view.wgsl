@@ -0,0 +1,60 @@
+#include "instancing.wgsl"
+
+// Distance from the camera that maps to white in the depth view.
+#define DEPTH_VIEW_RANGE 20.0
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) view_depth: f32
+};
+
+@vertex
+fn vs_main(
+    @builtin(instance_index) instance_index: u32,
+    input: VertexInput
+) -> VertexOutput
+{
+    var out: VertexOutput;
+    let world_position = instance_data_world_position(input, instance_data(instance_index));
+    out.clip_position = instance_clip_position(world_position);
+    out.tex_coords = input.tex_coords;
+    out.world_position = world_position.xyz;
+    out.view_depth = out.clip_position.w;
+    return out;
+}
+
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    return vec4<f32>(0.0, 1.0, 0.4, 1.0);
+}
+
+// The vertex format has no normals, so the face normal is rebuilt from screen space derivatives.
+@fragment
+fn fs_normals(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+    return vec4<f32>(normal * 0.5 + 0.5, 1.0);
+}
+
+@fragment
+fn fs_uvs(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    return vec4<f32>(fract(in.tex_coords), 0.0, 1.0);
+}
+
+@fragment
+fn fs_depth(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let depth = clamp(in.view_depth / DEPTH_VIEW_RANGE, 0.0, 1.0);
+    return vec4<f32>(vec3<f32>(depth), 1.0);
+}
+
+// Blended additively, so each overlapping fragment pushes the pixel towards white.
+@fragment
+fn fs_overdraw(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    return vec4<f32>(0.15, 0.06, 0.02, 1.0);
+}
@@ -0,0 +1,104 @@
+#include "common.wgsl"
+#include "material.wgsl"
+
+// Cosines between the surface normal and the projection direction, the decal fades
+// out between them so it doesn't smear along surfaces it only grazes.
+#define ANGLE_FADE_START 0.5
+#define ANGLE_FADE_END 0.2
+
+// Groups 0 to 2 are laid out as for the materials' pipelines, the vertex animation
+// group goes unused.
+struct DecalUniform {
+    inverse_view_proj: mat4x4<f32>,
+    // the main viewport in pixels: x, y, width, height
+    viewport: vec4<f32>
+};
+
+@group(3) @binding(0)
+var<uniform> decal: DecalUniform;
+@group(3) @binding(1)
+var t_depth: texture_depth_2d;
+
+struct DecalInput {
+    @location(0) model_matrix_0: vec4<f32>,
+    @location(1) model_matrix_1: vec4<f32>,
+    @location(2) model_matrix_2: vec4<f32>,
+    @location(3) model_matrix_3: vec4<f32>,
+    @location(4) inverse_model_0: vec4<f32>,
+    @location(5) inverse_model_1: vec4<f32>,
+    @location(6) inverse_model_2: vec4<f32>,
+    @location(7) inverse_model_3: vec4<f32>,
+    @location(8) color: vec4<f32>,
+    // rgb is the material's emission
+    @location(9) emissive: vec4<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) emissive: vec3<f32>,
+    // The box's +z axis in world space, what the decal faces.
+    @location(2) facing: vec3<f32>,
+    @location(3) @interpolate(flat) inverse_model_0: vec4<f32>,
+    @location(4) @interpolate(flat) inverse_model_1: vec4<f32>,
+    @location(5) @interpolate(flat) inverse_model_2: vec4<f32>,
+    @location(6) @interpolate(flat) inverse_model_3: vec4<f32>
+};
+
+// Two triangles per face of the unit box, counter-clockwise seen from outside. The
+// corner index holds x, y and z in its lowest three bits.
+fn box_corner(vertex_index: u32) -> vec3<f32>
+{
+    var corners = array<u32, 36>(
+        0u, 2u, 1u, 1u, 2u, 3u,
+        4u, 5u, 6u, 5u, 7u, 6u,
+        0u, 4u, 2u, 2u, 4u, 6u,
+        1u, 3u, 5u, 3u, 7u, 5u,
+        0u, 1u, 4u, 1u, 5u, 4u,
+        2u, 6u, 3u, 3u, 6u, 7u
+    );
+    let corner = corners[vertex_index];
+    return vec3<f32>(f32(corner & 1u), f32((corner >> 1u) & 1u), f32((corner >> 2u) & 1u)) - 0.5;
+}
+
+@vertex
+fn vs_decal(@builtin(vertex_index) vertex_index: u32, instance: DecalInput) -> VertexOutput
+{
+    let model = mat4x4<f32>(instance.model_matrix_0, instance.model_matrix_1,
+        instance.model_matrix_2, instance.model_matrix_3);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * model * vec4<f32>(box_corner(vertex_index), 1.0);
+    out.color = instance.color;
+    out.emissive = instance.emissive.rgb;
+    out.facing = normalize((model * vec4<f32>(0.0, 0.0, 1.0, 0.0)).xyz);
+    out.inverse_model_0 = instance.inverse_model_0;
+    out.inverse_model_1 = instance.inverse_model_1;
+    out.inverse_model_2 = instance.inverse_model_2;
+    out.inverse_model_3 = instance.inverse_model_3;
+    return out;
+}
+
+// Only the box's back faces are drawn, so every pixel it covers is shaded once, even
+// with the camera inside it. The surface seen through the pixel is found from the
+// depth buffer and brought into the box, where its x and y are the texture
+// coordinates. Outside the box the decal is fully transparent.
+@fragment
+fn fs_decal(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(t_depth, pixel, 0);
+    let uv = (in.clip_position.xy - decal.viewport.xy) / decal.viewport.zw;
+    let position = decal.inverse_view_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world_position = position.xyz / position.w;
+    let normal = normalize(cross(dpdy(world_position), dpdx(world_position)));
+
+    let inverse_model = mat4x4<f32>(in.inverse_model_0, in.inverse_model_1, in.inverse_model_2,
+        in.inverse_model_3);
+    let local = (inverse_model * vec4<f32>(world_position, 1.0)).xyz;
+    let inside = all(abs(local) <= vec3<f32>(0.5));
+    let fade = smoothstep(ANGLE_FADE_END, ANGLE_FADE_START, dot(normal, in.facing));
+
+    let color = material_color(vec2<f32>(local.x + 0.5, 0.5 - local.y), 0u, in.color, in.emissive);
+    return vec4<f32>(color.rgb, color.a * fade * select(0.0, 1.0, inside));
+}
@@ -0,0 +1,84 @@
+#include "fullscreen.wgsl"
+
+#define GOLDEN_ANGLE 2.39996323
+// How much further out every sample lands than the one before, in pixels at a
+// radius of 1. Smaller is smoother and slower.
+#define RADIUS_STEP 1.0
+
+struct DepthOfFieldUniform {
+    // focus distance, aperture, largest blur radius in pixels
+    params: vec4<f32>,
+    // camera near and far plane
+    planes: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@group(1) @binding(0)
+var<uniform> depth_of_field: DepthOfFieldUniform;
+@group(1) @binding(1)
+var t_depth: texture_depth_2d;
+
+// Distance from the camera along its view direction.
+fn linear_depth(uv: vec2<f32>) -> f32
+{
+    let size = textureDimensions(t_depth);
+    let pixel = min(vec2<u32>(uv * vec2<f32>(size)), size - 1u);
+    let depth = textureLoad(t_depth, pixel, 0);
+    let near = depth_of_field.planes.x;
+    let far = depth_of_field.planes.y;
+#ifdef REVERSE_Z
+    return near / max(depth, 1e-7);
+#else
+    return near * far / (far - depth * (far - near));
+#endif
+}
+
+// Radius of the circle of confusion in pixels: 0 at the focus distance, growing with
+// the aperture towards the largest radius in front of it and behind it.
+fn blur_radius(depth: f32) -> f32
+{
+    let focus_distance = depth_of_field.params.x;
+    let aperture = depth_of_field.params.y;
+    let max_radius = depth_of_field.params.z;
+    return min(aperture * abs(1.0 - focus_distance / depth) * max_radius, max_radius);
+}
+
+// A gather over a spiral of samples out to the largest blur radius. Each sample counts
+// where its own circle of confusion reaches the pixel, so out of focus things spread
+// over sharp ones. Behind the pixel they're held to about its own blur, or the
+// background would bleed over what's in focus in front of it.
+@fragment
+fn fs_depth_of_field(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let center = textureSampleLevel(t_source, s_source, in.uv, 0.0);
+    let center_depth = linear_depth(in.uv);
+    let center_radius = blur_radius(center_depth);
+    let max_radius = depth_of_field.params.z;
+
+    var color = center.rgb;
+    var total = 1.0;
+    var angle = 0.0;
+    var radius = RADIUS_STEP;
+    while radius < max_radius {
+        let uv = in.uv + vec2<f32>(cos(angle), sin(angle)) * texel * radius;
+        let sample_color = textureSampleLevel(t_source, s_source, uv, 0.0).rgb;
+        let sample_depth = linear_depth(uv);
+        var sample_radius = blur_radius(sample_depth);
+        if sample_depth > center_depth {
+            sample_radius = clamp(sample_radius, 0.0, center_radius * 2.0);
+        }
+
+        let reach = smoothstep(radius - 0.5, radius + 0.5, sample_radius);
+        color += mix(color / total, sample_color, reach);
+        total += 1.0;
+        angle += GOLDEN_ANGLE;
+        radius += RADIUS_STEP / radius;
+    }
+
+    return vec4<f32>(color / total, center.a);
+}
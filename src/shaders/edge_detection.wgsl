@@ -0,0 +1,45 @@
+#define WORKGROUP_SIZE 8
+
+struct EdgeDetectionUniform {
+    // rgb is the edges' color, linear
+    color: vec4<f32>,
+    // x is the strength, y the threshold
+    params: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var t_output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2)
+var<uniform> edge_detection: EdgeDetectionUniform;
+
+fn luma_at(pixel: vec2<i32>, size: vec2<i32>) -> f32
+{
+    let color = textureLoad(t_source, clamp(pixel, vec2<i32>(0), size - 1), 0).rgb;
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn cs_edge_detection(@builtin(global_invocation_id) id: vec3<u32>)
+{
+    let size = vec2<i32>(textureDimensions(t_source));
+    let pixel = vec2<i32>(id.xy);
+    if any(pixel >= size) {
+        return;
+    }
+
+    // The 3x3 Sobel kernels on the luma around the pixel.
+    var luma: array<f32, 9>;
+    for (var i = 0; i < 9; i += 1) {
+        luma[i] = luma_at(pixel + vec2<i32>(i % 3 - 1, i / 3 - 1), size);
+    }
+    let gradient_x = (luma[2] + 2.0 * luma[5] + luma[8]) - (luma[0] + 2.0 * luma[3] + luma[6]);
+    let gradient_y = (luma[6] + 2.0 * luma[7] + luma[8]) - (luma[0] + 2.0 * luma[1] + luma[2]);
+    let threshold = edge_detection.params.y;
+    let edge = smoothstep(threshold, threshold * 2.0 + 0.0001, length(vec2<f32>(gradient_x, gradient_y)));
+
+    let color = textureLoad(t_source, pixel, 0);
+    let strength = edge_detection.params.x * edge;
+    textureStore(t_output, pixel, vec4<f32>(mix(color.rgb, edge_detection.color.rgb, strength), color.a));
+}
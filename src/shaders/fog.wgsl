@@ -0,0 +1,168 @@
+#include "fullscreen.wgsl"
+
+#define PI 3.14159265
+// Scales the sky's luminance, in thousands of candela per square meter, before it's
+// tone mapped.
+#define SKY_EXPOSURE 0.1
+// Angular radius of the sun disk in radians.
+#define SUN_RADIUS 0.0093
+
+struct FogUniform {
+    inverse_view_proj: mat4x4<f32>,
+    // camera position, height fog base height
+    eye: vec4<f32>,
+    // fog color, 1 in alpha with the sky model on
+    color: vec4<f32>,
+    // density, start, end, height falloff
+    params: vec4<f32>,
+    // direction towards the sun, turbidity
+    sun: vec4<f32>,
+    // the main viewport in pixels: x, y, width, height
+    viewport: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@group(1) @binding(0)
+var<uniform> fog: FogUniform;
+@group(1) @binding(1)
+var t_depth: texture_depth_2d;
+
+fn in_viewport(pixel: vec2<i32>) -> bool
+{
+    let low = vec2<i32>(fog.viewport.xy);
+    let high = low + vec2<i32>(fog.viewport.zw);
+    return all(pixel >= low) && all(pixel < high);
+}
+
+fn world_position(pixel: vec2<i32>, depth: f32) -> vec3<f32>
+{
+    let uv = (vec2<f32>(pixel) + 0.5 - fog.viewport.xy) / fog.viewport.zw;
+    let position = fog.inverse_view_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// Linear from the start to the end distance. Past the start there's exponential fog by
+// the density on top of it, which with a height falloff thins out going up from the
+// base height, integrated along the view ray.
+fn fog_amount(distance: f32, direction: vec3<f32>) -> f32
+{
+    let density = fog.params.x;
+    let start = fog.params.y;
+    let end = fog.params.z;
+    let falloff = fog.params.w;
+
+    let linear = clamp((distance - start) / max(end - start, 1e-4), 0.0, 1.0);
+    let travelled = max(distance - start, 0.0);
+    var optical_depth = density * travelled;
+    if falloff > 0.0 {
+        let origin = fog.eye.xyz + direction * min(start, distance);
+        let rise = falloff * direction.y * travelled;
+        let base = density * exp(-falloff * (origin.y - fog.eye.w));
+        optical_depth = base * travelled;
+        if abs(rise) > 1e-4 {
+            optical_depth = base * travelled * (1.0 - exp(-rise)) / rise;
+        }
+    }
+
+    return max(linear, 1.0 - exp(-optical_depth));
+}
+
+// Coefficients of the Perez sky luminance distribution.
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32
+};
+
+fn perez(coefficients: Perez, cos_theta: f32, gamma: f32) -> f32
+{
+    let p = coefficients;
+    let cos_gamma = cos(gamma);
+    return (1.0 + p.a * exp(p.b / cos_theta)) * (1.0 + p.c * exp(p.d * gamma) + p.e * cos_gamma * cos_gamma);
+}
+
+// Towards the view direction, relative to the zenith's value.
+fn relative_to_zenith(coefficients: Perez, cos_theta: f32, gamma: f32, theta_sun: f32) -> f32
+{
+    return perez(coefficients, cos_theta, gamma) / perez(coefficients, 1.0, theta_sun);
+}
+
+// Preetham, Shirley and Smits' analytic daylight model, the sky's color towards
+// `direction`. Below the horizon it keeps the horizon's color.
+fn preetham(direction: vec3<f32>) -> vec3<f32>
+{
+    let sun = normalize(fog.sun.xyz);
+    let t = fog.sun.w;
+    let theta_sun = acos(clamp(sun.y, 0.0, 1.0));
+    let cos_theta = max(direction.y, 0.01);
+    let gamma = acos(clamp(dot(direction, sun), -1.0, 1.0));
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+    let thetas = vec3<f32>(theta_sun * theta_sun * theta_sun, theta_sun * theta_sun, theta_sun);
+    let zenith_luminance = (4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192;
+    let zenith_x = t * t * dot(vec3<f32>(0.00166, -0.00375, 0.00209), thetas)
+        + t * (dot(vec3<f32>(-0.02903, 0.06377, -0.03202), thetas) + 0.00394)
+        + dot(vec3<f32>(0.11693, -0.21196, 0.06052), thetas) + 0.25886;
+    let zenith_y = t * t * dot(vec3<f32>(0.00275, -0.00610, 0.00317), thetas)
+        + t * (dot(vec3<f32>(-0.04214, 0.08970, -0.04153), thetas) + 0.00516)
+        + dot(vec3<f32>(0.15346, -0.26756, 0.06670), thetas) + 0.26688;
+
+    let luminance = zenith_luminance * relative_to_zenith(Perez(0.1787 * t - 1.4630,
+        -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703),
+        cos_theta, gamma, theta_sun);
+    let x = zenith_x * relative_to_zenith(Perez(-0.0193 * t - 0.2592, -0.0665 * t + 0.0008,
+        -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452), cos_theta, gamma, theta_sun);
+    let y = zenith_y * relative_to_zenith(Perez(-0.0167 * t - 0.2608, -0.0950 * t + 0.0092,
+        -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529), cos_theta, gamma, theta_sun);
+
+    // Yxy to XYZ to linear sRGB.
+    let xyz = vec3<f32>(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+    var rgb = max(mat3x3<f32>(
+        vec3<f32>(3.2406, -0.9689, 0.0557),
+        vec3<f32>(-1.5372, 1.8758, -0.2040),
+        vec3<f32>(-0.4986, 0.0415, 1.0570)
+    ) * xyz, vec3<f32>(0.0));
+    if gamma < SUN_RADIUS && direction.y > 0.0 {
+        rgb += vec3<f32>(50.0);
+    }
+
+    return 1.0 - exp(-rgb * SKY_EXPOSURE);
+}
+
+// Blends what the main pass drew towards the fog color by distance and height, and
+// with the sky model on paints the sky where nothing was drawn and uses its color
+// towards the pixel as the fog color, so distant things fade into it.
+@fragment
+fn fs_fog(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let color = textureSampleLevel(t_source, s_source, in.uv, 0.0);
+    let pixel = vec2<i32>(in.clip_position.xy);
+    if !in_viewport(pixel) {
+        return color;
+    }
+
+    let depth = textureLoad(t_depth, pixel, 0);
+    // Reverse Z's far plane is at infinity, so the sky's direction is taken halfway there.
+    let to_point = world_position(pixel, select(depth, 0.5, FAR_DEPTH == 0.0 && is_background(depth))) - fog.eye.xyz;
+    let distance = length(to_point);
+    let direction = to_point / max(distance, 1e-6);
+    let sky = fog.color.a > 0.5;
+    if is_background(depth) {
+        if sky {
+            return vec4<f32>(preetham(direction), color.a);
+        }
+        return color;
+    }
+
+    var fog_color = fog.color.rgb;
+    if sky {
+        fog_color = preetham(direction);
+    }
+    return vec4<f32>(mix(color.rgb, fog_color, fog_amount(distance, direction)), color.a);
+}
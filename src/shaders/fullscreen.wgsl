@@ -0,0 +1,33 @@
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // 0, 0 in the top left corner
+    @location(0) uv: vec2<f32>
+};
+
+// One triangle over the whole target, no vertex buffer needed.
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> FullscreenOutput
+{
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var out: FullscreenOutput;
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// The depth buffer's value where nothing was drawn.
+#ifdef REVERSE_Z
+const FAR_DEPTH: f32 = 0.0;
+#else
+const FAR_DEPTH: f32 = 1.0;
+#endif
+
+fn is_background(depth: f32) -> bool
+{
+#ifdef REVERSE_Z
+    return depth <= FAR_DEPTH;
+#else
+    return depth >= FAR_DEPTH;
+#endif
+}
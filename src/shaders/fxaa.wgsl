@@ -0,0 +1,64 @@
+#include "fullscreen.wgsl"
+
+// Contrast below the larger of the two is left alone.
+#define EDGE_THRESHOLD 0.125
+#define EDGE_THRESHOLD_MIN 0.0312
+// Longest distance in pixels the edge direction is followed.
+#define SPAN_MAX 8.0
+#define REDUCE_MUL 0.125
+#define REDUCE_MIN 0.0078125
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+// Of the gamma encoded color, contrast is judged as it's seen.
+fn luma(color: vec3<f32>) -> f32
+{
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn sample_source(uv: vec2<f32>) -> vec3<f32>
+{
+    return textureSampleLevel(t_source, s_source, uv, 0.0).rgb;
+}
+
+// Finds the direction of the edge through the pixel from its four diagonal neighbors
+// and blurs along it, keeping the wider blur unless it overshoots the local range.
+@fragment
+fn fs_fxaa(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let color = sample_source(in.uv);
+    let luma_m = luma(color);
+    let luma_nw = luma(sample_source(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_source(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_source(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_source(in.uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    if luma_max - luma_min < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD) {
+        return vec4<f32>(color, 1.0);
+    }
+
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+    let near = 0.5 * (sample_source(in.uv + direction * (1.0 / 3.0 - 0.5))
+        + sample_source(in.uv + direction * (2.0 / 3.0 - 0.5)));
+    let far = near * 0.5 + 0.25 * (sample_source(in.uv - direction * 0.5)
+        + sample_source(in.uv + direction * 0.5));
+    let luma_far = luma(far);
+
+    if luma_far < luma_min || luma_far > luma_max {
+        return vec4<f32>(near, 1.0);
+    }
+    return vec4<f32>(far, 1.0);
+}
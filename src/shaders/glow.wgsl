@@ -0,0 +1,51 @@
+#include "fullscreen.wgsl"
+
+#define GOLDEN_ANGLE 2.39996323
+#define SAMPLE_COUNT 48
+
+struct GlowUniform {
+    // threshold, soft knee, intensity, radius in pixels
+    params: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@group(1) @binding(0)
+var<uniform> glow: GlowUniform;
+
+// What's left of `color` past the threshold, eased in over the knee below it.
+fn bright_part(color: vec3<f32>) -> vec3<f32>
+{
+    let threshold = glow.params.x;
+    let knee = glow.params.y;
+    let brightness = max(color.r, max(color.g, color.b));
+    let soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    let contribution = max(soft * soft / (4.0 * knee + 1e-4), brightness - threshold);
+    return color * max(contribution, 0.0) / max(brightness, 1e-4);
+}
+
+// A spiral of samples over the disk out to the radius, spaced evenly by area. Their
+// bright parts are weighted by a Gaussian of their distance and added to the frame.
+@fragment
+fn fs_glow(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let center = textureSampleLevel(t_source, s_source, in.uv, 0.0);
+    let radius = glow.params.w;
+
+    var light = vec3<f32>(0.0);
+    var total = 0.0;
+    for (var i = 0; i < SAMPLE_COUNT; i++) {
+        let t = (f32(i) + 0.5) / f32(SAMPLE_COUNT);
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let uv = in.uv + vec2<f32>(cos(angle), sin(angle)) * texel * sqrt(t) * radius;
+        let weight = exp(-4.0 * t);
+        light += bright_part(textureSampleLevel(t_source, s_source, uv, 0.0).rgb) * weight;
+        total += weight;
+    }
+
+    return vec4<f32>(center.rgb + light / total * glow.params.z, center.a);
+}
@@ -0,0 +1,59 @@
+#include "common.wgsl"
+
+// Overridden with `uniform` and a sized array where the vertex stage can't read
+// storage buffers, see InstanceStorage.
+#define INSTANCE_ADDRESS_SPACE storage, read
+#define INSTANCE_ARRAY array<InstanceData>
+
+struct InstanceData {
+    model: mat4x4<f32>,
+    // phase, amplitude, seed
+    animation: vec4<f32>,
+    color: vec4<f32>,
+    // x is the texture index
+    material: vec4<u32>,
+    // rgb is the material's emission, w the LOD fade
+    emissive: vec4<f32>
+};
+
+@group(3) @binding(0)
+var<INSTANCE_ADDRESS_SPACE> instances: INSTANCE_ARRAY;
+
+fn instance_data(instance_index: u32) -> InstanceData
+{
+    return instances[instance_index];
+}
+
+fn instance_data_world_position(input: VertexInput, instance: InstanceData) -> vec4<f32>
+{
+    let world_position = instance.model * vec4<f32>(input.position, 1.0);
+    return vec4<f32>(world_position.xyz + wind_offset(input.position, instance.animation), 1.0);
+}
+
+// With CAMERA_RELATIVE the instances are uploaded relative to the camera's origin.
+fn instance_clip_position(position: vec4<f32>) -> vec4<f32>
+{
+#ifdef CAMERA_RELATIVE
+    return camera.relative_view_proj * position;
+#else
+    return camera.view_proj * position;
+#endif
+}
+
+fn instance_unjittered_clip_position(position: vec4<f32>) -> vec4<f32>
+{
+#ifdef CAMERA_RELATIVE
+    return camera.relative_unjittered_view_proj * position;
+#else
+    return camera.unjittered_view_proj * position;
+#endif
+}
+
+fn instance_previous_clip_position(position: vec4<f32>) -> vec4<f32>
+{
+#ifdef CAMERA_RELATIVE
+    return camera.relative_prev_view_proj * position;
+#else
+    return camera.prev_view_proj * position;
+#endif
+}
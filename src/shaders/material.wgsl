@@ -0,0 +1,49 @@
+// The material textures at group 0. Which of them exist depends on the material's
+// features, each adds its define and binding, see MaterialFeatures.
+
+// Where the normal map is lit from, in tangent space.
+#define NORMAL_MAP_LIGHT vec3<f32>(0.3, 0.5, 1.0)
+
+// One diffuse texture per layer, picked by the instance's texture index.
+@group(0) @binding(0)
+var t_diffuse: texture_2d_array<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+#ifdef HAS_NORMAL_MAP
+@group(0) @binding(2)
+var t_normal: texture_2d_array<f32>;
+#endif
+
+#ifdef HAS_EMISSIVE
+@group(0) @binding(3)
+var t_emissive: texture_2d_array<f32>;
+#endif
+
+// `emissive` is the material's emissive factor and intensity, from the instance.
+fn material_color(tex_coords: vec2<f32>, texture_index: u32, color: vec4<f32>, emissive: vec3<f32>) -> vec4<f32>
+{
+#ifdef BACKEND_GL
+    // GL clamps the layer by itself, and naga can't query the layer count there.
+    let layer = texture_index;
+#else
+    let layer = min(texture_index, textureNumLayers(t_diffuse) - 1u);
+#endif
+    var out = textureSample(t_diffuse, s_diffuse, tex_coords, layer) * color;
+
+#ifdef HAS_NORMAL_MAP
+    // The meshes have no normals or tangents, so the map is lit as if the surface
+    // faced the light head on. Enough to bring out its detail.
+    let normal = normalize(textureSample(t_normal, s_diffuse, tex_coords, 0).xyz * 2.0 - 1.0);
+    out = vec4<f32>(out.rgb * max(dot(normal, normalize(NORMAL_MAP_LIGHT)), 0.0), out.a);
+#endif
+
+    // Added after lighting, and can go past 1 for the glow to pick it up.
+    var emission = emissive;
+#ifdef HAS_EMISSIVE
+    emission *= textureSample(t_emissive, s_diffuse, tex_coords, 0).rgb;
+#endif
+    out = vec4<f32>(out.rgb + emission, out.a);
+
+    return out;
+}
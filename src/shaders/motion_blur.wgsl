@@ -0,0 +1,63 @@
+#include "fullscreen.wgsl"
+
+// Longer motion is cut down to this, so fast objects don't smear across the screen.
+#define MAX_BLUR_PIXELS 32.0
+
+struct MotionBlurUniform {
+    // from this frame's clip space to the last one's, without the jitter
+    reprojection: mat4x4<f32>,
+    // shutter scale, sample count
+    params: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@group(1) @binding(0)
+var<uniform> motion_blur: MotionBlurUniform;
+@group(1) @binding(1)
+var t_motion: texture_2d<f32>;
+@group(1) @binding(2)
+var t_depth: texture_depth_2d;
+
+// In UV, towards where the pixel was last frame. Where nothing was drawn there's no
+// motion vector, so the camera's own motion is worked out at the far plane instead and
+// the sky blurs as the camera turns.
+fn velocity(pixel: vec2<i32>, uv: vec2<f32>) -> vec2<f32>
+{
+    if !is_background(textureLoad(t_depth, pixel, 0)) {
+        return textureLoad(t_motion, pixel, 0).xy;
+    }
+
+    let previous = motion_blur.reprojection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, FAR_DEPTH, 1.0);
+    return previous.xy / previous.w * vec2<f32>(0.5, -0.5) + 0.5 - uv;
+}
+
+// Averages samples along the pixel's motion over the time the shutter was open,
+// centered on the pixel.
+@fragment
+fn fs_motion_blur(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let size = vec2<f32>(textureDimensions(t_source));
+    let center = textureSampleLevel(t_source, s_source, in.uv, 0.0);
+
+    var motion = velocity(vec2<i32>(in.clip_position.xy), in.uv) * motion_blur.params.x;
+    let length_pixels = length(motion * size);
+    if length_pixels < 0.5 {
+        return center;
+    }
+    if length_pixels > MAX_BLUR_PIXELS {
+        motion *= MAX_BLUR_PIXELS / length_pixels;
+    }
+
+    let sample_count = u32(motion_blur.params.y);
+    var color = vec3<f32>(0.0);
+    for (var i = 0u; i < sample_count; i++) {
+        let t = (f32(i) + 0.5) / f32(sample_count) - 0.5;
+        color += textureSampleLevel(t_source, s_source, in.uv + motion * t, 0.0).rgb;
+    }
+
+    return vec4<f32>(color / f32(sample_count), center.a);
+}
@@ -0,0 +1,43 @@
+#include "fullscreen.wgsl"
+
+// The pipelines set it to MSAA_SAMPLE_COUNT.
+#define SAMPLE_COUNT 4
+
+@group(0) @binding(0)
+var t_depth: texture_depth_multisampled_2d;
+
+// A viewport resolved over transparent black, bound like a post effect's source.
+@group(1) @binding(0)
+var t_source: texture_2d<f32>;
+
+// The nearest of a pixel's samples, so what the depth readers see covers the edges
+// the resolved color has.
+@fragment
+fn fs_resolve_depth(in: FullscreenOutput) -> @builtin(frag_depth) f32
+{
+    let pixel = vec2<i32>(in.clip_position.xy);
+    var depth = textureLoad(t_depth, pixel, 0);
+    for (var i = 1; i < SAMPLE_COUNT; i += 1) {
+#ifdef REVERSE_Z
+        depth = max(depth, textureLoad(t_depth, pixel, i));
+#else
+        depth = min(depth, textureLoad(t_depth, pixel, i));
+#endif
+    }
+    return depth;
+}
+
+// Over the frame, covering it as much as the viewport's samples did: the resolved
+// color already is the drawn samples' sum over the sample count, the alpha is made
+// from how many of them left depth, so premultiplied blending keeps the rest.
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let pixel = vec2<i32>(in.clip_position.xy);
+    var covered = 0.0;
+    for (var i = 0; i < SAMPLE_COUNT; i += 1) {
+        covered += select(1.0, 0.0, is_background(textureLoad(t_depth, pixel, i)));
+    }
+    let color = textureLoad(t_source, pixel, 0);
+    return vec4<f32>(color.rgb, covered / f32(SAMPLE_COUNT));
+}
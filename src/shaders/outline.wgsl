@@ -0,0 +1,47 @@
+#include "instancing.wgsl"
+
+// Overridden by Outline with its options.
+#define OUTLINE_COLOR vec4<f32>(1.0, 0.6, 0.1, 1.0)
+#define OUTLINE_SCALE 1.05
+
+// Unjittered, the outline is drawn after TAA resolved the frame.
+fn outline_clip_position(instance_index: u32, position: vec3<f32>) -> vec4<f32>
+{
+    let instance = instance_data(instance_index);
+    let input = VertexInput(position, vec2<f32>(0.0));
+    return instance_unjittered_clip_position(instance_data_world_position(input, instance));
+}
+
+// Marks the instance's pixels in the stencil buffer.
+@vertex
+fn vs_mask(
+    @builtin(instance_index) instance_index: u32,
+    input: VertexInput
+) -> @builtin(position) vec4<f32>
+{
+    return outline_clip_position(instance_index, input.position);
+}
+
+// The instance again, a bit larger around its origin. Only what sticks out past the
+// marked pixels passes the stencil test.
+@vertex
+fn vs_outline(
+    @builtin(instance_index) instance_index: u32,
+    input: VertexInput
+) -> @builtin(position) vec4<f32>
+{
+    return outline_clip_position(instance_index, input.position * OUTLINE_SCALE);
+}
+
+// Nothing is written, the mask pipeline has its color writes off.
+@fragment
+fn fs_mask() -> @location(0) vec4<f32>
+{
+    return vec4<f32>(0.0);
+}
+
+@fragment
+fn fs_outline() -> @location(0) vec4<f32>
+{
+    return OUTLINE_COLOR;
+}
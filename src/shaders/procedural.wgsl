@@ -0,0 +1,91 @@
+#define WORKGROUP_SIZE 8
+
+const PATTERN_NOISE: u32 = 0u;
+const PATTERN_CHECKERBOARD: u32 = 1u;
+const PATTERN_GRADIENT: u32 = 2u;
+
+struct ProceduralUniform {
+    // The pattern goes from the first to the second, linear
+    colors: array<vec4<f32>, 2>,
+    // x is the pattern, y the cells across, z the noise octaves, w the noise seed
+    params: vec4<u32>,
+    // x is the gradient's angle in radians
+    angle: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_output: texture_storage_2d_array<rgba8unorm, write>;
+@group(0) @binding(1)
+var<uniform> procedural: ProceduralUniform;
+
+// A well spread integer hash, from 0 to 1.
+fn hash(cell: vec2<u32>, seed: u32) -> f32
+{
+    var h = cell.x * 0x8da6b343u ^ cell.y * 0xd8163841u ^ seed * 0xcb1ab31fu;
+    h = (h ^ (h >> 16u)) * 0x7feb352du;
+    h = (h ^ (h >> 15u)) * 0x846ca68bu;
+    h = h ^ (h >> 16u);
+    return f32(h) / 4294967295.0;
+}
+
+// Smoothly interpolated random values on a lattice `period` cells across, wrapping
+// around so the texture tiles.
+fn value_noise(uv: vec2<f32>, period: u32, seed: u32) -> f32
+{
+    let p = uv * f32(period);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let i0 = vec2<u32>(floor(p)) % period;
+    let i1 = (i0 + 1u) % period;
+
+    let a = hash(i0, seed);
+    let b = hash(vec2<u32>(i1.x, i0.y), seed);
+    let c = hash(vec2<u32>(i0.x, i1.y), seed);
+    let d = hash(i1, seed);
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn fractal_noise(uv: vec2<f32>, cells: u32, octaves: u32, seed: u32) -> f32
+{
+    var value = 0.0;
+    var amplitude = 0.5;
+    var total = 0.0;
+    var period = cells;
+    for (var octave = 0u; octave < octaves; octave += 1u) {
+        value += value_noise(uv, period, seed + octave) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        period *= 2u;
+    }
+    return value / total;
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn cs_procedural(@builtin(global_invocation_id) id: vec3<u32>)
+{
+    let size = textureDimensions(t_output);
+    if any(id.xy >= size) || id.z >= textureNumLayers(t_output) {
+        return;
+    }
+
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+    let cells = procedural.params.y;
+    var t = 0.0;
+    switch procedural.params.x {
+        case PATTERN_NOISE: {
+            t = fractal_noise(uv, cells, procedural.params.z, procedural.params.w);
+        }
+        case PATTERN_CHECKERBOARD: {
+            let cell = vec2<u32>(uv * f32(cells));
+            t = f32((cell.x + cell.y) % 2u);
+        }
+        default: {
+            // From one corner to the opposite one, whichever way it points.
+            let direction = vec2<f32>(cos(procedural.angle.x), sin(procedural.angle.x));
+            let reach = 0.5 * (abs(direction.x) + abs(direction.y));
+            t = clamp(dot(uv - 0.5, direction) / reach * 0.5 + 0.5, 0.0, 1.0);
+        }
+    }
+
+    textureStore(t_output, id.xy, id.z, mix(procedural.colors[0], procedural.colors[1], t));
+}
@@ -0,0 +1,90 @@
+#include "common.wgsl"
+#include "material.wgsl"
+
+struct SkinnedVertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) joints: vec4<u32>,
+    @location(3) weights: vec4<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) texture_index: u32,
+    // Unjittered, for motion vectors
+    @location(3) current_position: vec4<f32>,
+    @location(4) previous_position: vec4<f32>,
+    @location(5) emissive: vec3<f32>
+};
+
+struct MorphWeights {
+    weights: array<vec4<f32>, 2>,
+    target_count: u32,
+    vertex_count: u32
+};
+
+// One matrix per joint, rewritten every frame from the current animation pose.
+@group(3) @binding(0)
+var<storage, read> joint_matrices: array<mat4x4<f32>>;
+
+// Position offsets of every morph target, target-major.
+@group(3) @binding(1)
+var<storage, read> morph_targets: array<vec4<f32>>;
+
+@group(3) @binding(2)
+var<uniform> morph: MorphWeights;
+
+fn morph_offset(vertex_index: u32) -> vec3<f32>
+{
+    var offset = vec3<f32>(0.0);
+    for (var morph_target = 0u; morph_target < morph.target_count; morph_target++) {
+        let weight = morph.weights[morph_target / 4u][morph_target % 4u];
+        offset += morph_targets[morph_target * morph.vertex_count + vertex_index].xyz * weight;
+    }
+    return offset;
+}
+
+fn skin_matrix(joints: vec4<u32>, weights: vec4<f32>) -> mat4x4<f32>
+{
+    return joint_matrices[joints.x] * weights.x
+        + joint_matrices[joints.y] * weights.y
+        + joint_matrices[joints.z] * weights.z
+        + joint_matrices[joints.w] * weights.w;
+}
+
+// glTF applies morph targets before skinning.
+@vertex
+fn vs_skinned(
+    @builtin(vertex_index) vertex_index: u32,
+    input: SkinnedVertexInput,
+    instance: InstanceInput
+) -> VertexOutput
+{
+    var out: VertexOutput;
+    let morphed_position = input.position + morph_offset(vertex_index);
+    let skinned_position = skin_matrix(input.joints, input.weights) * vec4<f32>(morphed_position, 1.0);
+    let world_position = instance_model_matrix(instance) * skinned_position;
+    out.clip_position = camera.view_proj * world_position;
+    out.current_position = camera.unjittered_view_proj * world_position;
+    out.previous_position = camera.prev_view_proj * world_position;
+    out.tex_coords = input.tex_coords;
+    out.color = instance.color;
+    out.texture_index = instance.material.x;
+    out.emissive = instance.emissive.rgb;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    return material_color(in.tex_coords, in.texture_index, in.color, in.emissive);
+}
+
+// Only camera motion, last frame's pose isn't kept.
+@fragment
+fn fs_motion_vectors(in: VertexOutput) -> @location(0) vec2<f32>
+{
+    return motion_vector(in.current_position, in.previous_position);
+}
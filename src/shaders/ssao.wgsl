@@ -0,0 +1,166 @@
+#include "fullscreen.wgsl"
+
+#define MAX_SAMPLE_COUNT 64
+
+struct SsaoUniform {
+    proj: mat4x4<f32>,
+    inverse_proj: mat4x4<f32>,
+    // the main viewport in pixels: x, y, width, height
+    viewport: vec4<f32>,
+    // radius, bias, intensity, sample count
+    params: vec4<f32>,
+    // Over the hemisphere around +z, more of them close to the center.
+    kernel: array<vec4<f32>, MAX_SAMPLE_COUNT>
+};
+
+// The frame so far, same as every post effect binds. Only the composite reads it.
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@group(1) @binding(0)
+var<uniform> ssao: SsaoUniform;
+@group(1) @binding(1)
+var t_depth: texture_depth_2d;
+@group(1) @binding(2)
+var t_occlusion: texture_2d<f32>;
+
+fn clamp_to_viewport(pixel: vec2<i32>) -> vec2<i32>
+{
+    let low = vec2<i32>(ssao.viewport.xy);
+    let high = low + vec2<i32>(ssao.viewport.zw) - 1;
+    return clamp(pixel, low, high);
+}
+
+fn in_viewport(pixel: vec2<i32>) -> bool
+{
+    return all(clamp_to_viewport(pixel) == pixel);
+}
+
+// Where the surface seen through `pixel` is, in view space.
+fn view_position(pixel: vec2<i32>) -> vec3<f32>
+{
+    let depth = textureLoad(t_depth, pixel, 0);
+    let uv = (vec2<f32>(pixel) + 0.5 - ssao.viewport.xy) / ssao.viewport.zw;
+    let position = ssao.inverse_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// From the positions next to it. On each axis the neighbor closer in depth is used, so
+// the normal doesn't bend across silhouettes.
+fn view_normal(pixel: vec2<i32>, center: vec3<f32>) -> vec3<f32>
+{
+    let left = view_position(clamp_to_viewport(pixel - vec2<i32>(1, 0)));
+    let right = view_position(clamp_to_viewport(pixel + vec2<i32>(1, 0)));
+    let up = view_position(clamp_to_viewport(pixel - vec2<i32>(0, 1)));
+    let down = view_position(clamp_to_viewport(pixel + vec2<i32>(0, 1)));
+
+    var across = right - center;
+    if abs(center.z - left.z) < abs(right.z - center.z) {
+        across = center - left;
+    }
+    var downwards = down - center;
+    if abs(center.z - up.z) < abs(down.z - center.z) {
+        downwards = center - up;
+    }
+
+    return normalize(cross(downwards, across));
+}
+
+// A 4x4 ordered dither in [0, 1), the blur is the same size so it averages out.
+fn rotation(pixel: vec2<i32>) -> f32
+{
+    var pattern = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0
+    );
+    return pattern[(pixel.y & 3) * 4 + (pixel.x & 3)] / 16.0;
+}
+
+// How much of the hemisphere over the surface is hidden by the depth buffer, as the
+// share of kernel samples that end up behind it. The kernel is turned around the
+// normal by a different angle in every pixel of a 4x4 tile, which trades banding for
+// noise the blur removes. Outside the main viewport and where nothing was drawn
+// nothing is hidden.
+@fragment
+fn fs_occlusion(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let pixel = vec2<i32>(in.clip_position.xy);
+    if !in_viewport(pixel) || is_background(textureLoad(t_depth, pixel, 0)) {
+        return vec4<f32>(1.0);
+    }
+
+    let radius = ssao.params.x;
+    let bias = ssao.params.y;
+    let sample_count = u32(ssao.params.w);
+    let position = view_position(pixel);
+    let normal = view_normal(pixel, position);
+
+    let angle = rotation(pixel) * 6.2831853;
+    let random = vec3<f32>(cos(angle), sin(angle), 0.0);
+    var tangent = random - normal * dot(random, normal);
+    if dot(tangent, tangent) < 1e-6 {
+        tangent = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    tangent = normalize(tangent);
+    let bitangent = cross(normal, tangent);
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < sample_count; i++) {
+        let offset = ssao.kernel[i].xyz;
+        let sample_position = position + (tangent * offset.x + bitangent * offset.y + normal * offset.z) * radius;
+        let clip = ssao.proj * vec4<f32>(sample_position, 1.0);
+        let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+        let sample_pixel = vec2<i32>(floor(ssao.viewport.xy + uv * ssao.viewport.zw));
+        if !in_viewport(sample_pixel) {
+            continue;
+        }
+
+        // Whatever is much further in front than the radius doesn't count, or things in
+        // the foreground would leave a dark halo on everything behind them.
+        let surface = view_position(sample_pixel).z;
+        let range = smoothstep(0.0, 1.0, radius / abs(position.z - surface));
+        occlusion += select(0.0, range, surface >= sample_position.z + bias);
+    }
+
+    let visibility = 1.0 - ssao.params.z * occlusion / max(f32(sample_count), 1.0);
+    return vec4<f32>(vec3<f32>(clamp(visibility, 0.0, 1.0)), 1.0);
+}
+
+// A 4x4 box over the occlusion, leaving out what's further than the radius away in
+// depth so it doesn't bleed over edges.
+@fragment
+fn fs_blur(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let pixel = vec2<i32>(in.clip_position.xy);
+    if !in_viewport(pixel) {
+        return vec4<f32>(1.0);
+    }
+    let depth = view_position(pixel).z;
+
+    var total = 0.0;
+    var weight = 0.0;
+    for (var y = -2; y < 2; y++) {
+        for (var x = -2; x < 2; x++) {
+            let neighbor = clamp_to_viewport(pixel + vec2<i32>(x, y));
+            let near = select(0.0, 1.0, abs(view_position(neighbor).z - depth) <= ssao.params.x);
+            total += textureLoad(t_occlusion, neighbor, 0).r * near;
+            weight += near;
+        }
+    }
+
+    return vec4<f32>(vec3<f32>(total / max(weight, 1.0)), 1.0);
+}
+
+// Darkens the frame by the occlusion. There's no ambient term kept apart from the
+// rest of the lighting, so it scales the lit color as a whole.
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let color = textureSampleLevel(t_source, s_source, in.uv, 0.0);
+    let visibility = textureLoad(t_occlusion, vec2<i32>(in.clip_position.xy), 0).r;
+    return vec4<f32>(color.rgb * visibility, color.a);
+}
@@ -0,0 +1,46 @@
+#include "fullscreen.wgsl"
+
+struct TaaUniform {
+    // history weight, 1 when the history holds a resolved frame, 0 after a reset
+    params: vec4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> taa: TaaUniform;
+@group(0) @binding(1)
+var t_color: texture_2d<f32>;
+@group(0) @binding(2)
+var t_history: texture_2d<f32>;
+@group(0) @binding(3)
+var t_motion: texture_2d<f32>;
+@group(0) @binding(4)
+var s_history: sampler;
+
+// Blends this frame's jittered color with the history reprojected along the motion
+// vectors. The history is clamped to the colors around the pixel first, so what
+// was disoccluded or changed doesn't leave a ghost behind.
+@fragment
+fn fs_resolve(in: FullscreenOutput) -> @location(0) vec4<f32>
+{
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let last_pixel = vec2<i32>(textureDimensions(t_color)) - 1;
+    let current = textureLoad(t_color, pixel, 0).rgb;
+
+    var low = current;
+    var high = current;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbor = textureLoad(t_color, clamp(pixel + vec2<i32>(x, y), vec2<i32>(0),
+                last_pixel), 0).rgb;
+            low = min(low, neighbor);
+            high = max(high, neighbor);
+        }
+    }
+
+    let history_uv = in.uv + textureLoad(t_motion, pixel, 0).xy;
+    let history = clamp(textureSampleLevel(t_history, s_history, history_uv, 0.0).rgb, low, high);
+    let offscreen = any(history_uv < vec2<f32>(0.0)) || any(history_uv > vec2<f32>(1.0));
+    let weight = select(taa.params.x * taa.params.y, 0.0, offscreen);
+
+    return vec4<f32>(mix(current, history, weight), 1.0);
+}
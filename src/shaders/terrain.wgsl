@@ -0,0 +1,58 @@
+#define CAMERA_GROUP 0
+#include "common.wgsl"
+
+#define ROCK_SLOPE 0.75
+#define SNOW_HEIGHT -1.5
+
+struct TerrainVertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // Unjittered, for motion vectors
+    @location(2) current_position: vec4<f32>,
+    @location(3) previous_position: vec4<f32>
+};
+
+@vertex
+fn vs_terrain(input: TerrainVertexInput) -> VertexOutput
+{
+    var out: VertexOutput;
+    let world_position = vec4<f32>(input.position, 1.0);
+    out.clip_position = camera.view_proj * world_position;
+    out.current_position = camera.unjittered_view_proj * world_position;
+    out.previous_position = camera.prev_view_proj * world_position;
+    out.world_position = input.position;
+    out.normal = input.normal;
+    return out;
+}
+
+// The terrain doesn't move, only the camera does.
+@fragment
+fn fs_motion_vectors(in: VertexOutput) -> @location(0) vec2<f32>
+{
+    return motion_vector(in.current_position, in.previous_position);
+}
+
+// Grass on flat ground, rock where it gets steep and snow above SNOW_HEIGHT, blended
+// over a short band so the borders don't show the triangles.
+@fragment
+fn fs_terrain(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let normal = normalize(in.normal);
+    let grass = vec3<f32>(0.28, 0.45, 0.16);
+    let rock = vec3<f32>(0.42, 0.38, 0.34);
+    let snow = vec3<f32>(0.92, 0.93, 0.96);
+
+    let steepness = 1.0 - smoothstep(ROCK_SLOPE - 0.1, ROCK_SLOPE + 0.1, normal.y);
+    let snowiness = smoothstep(SNOW_HEIGHT - 0.5, SNOW_HEIGHT + 0.5, in.world_position.y);
+    let albedo = mix(mix(grass, rock, steepness), snow, snowiness * (1.0 - steepness));
+
+    let sun = normalize(vec3<f32>(0.4, 1.0, 0.3));
+    let lighting = 0.3 + 0.7 * max(dot(normal, sun), 0.0);
+    return vec4<f32>(albedo * lighting, 1.0);
+}
@@ -0,0 +1,92 @@
+#include "common.wgsl"
+#include "material.wgsl"
+
+// Below this alpha nothing is drawn, alpha to coverage takes care of the rest.
+#define ALPHA_CUTOFF 0.1
+// How much darker the base of a plant is than its tip.
+#define BASE_SHADE 0.5
+// How far apart gusts of wind are, in world units.
+#define GUST_LENGTH 8.0
+
+struct PlantInput {
+    // xyz is where it stands, w its rotation around y in radians
+    @location(0) position: vec4<f32>,
+    // width, height, phase, sway
+    @location(1) shape: vec4<f32>,
+    @location(2) color: vec4<f32>,
+    // texture index, 1 when it's cut out of the texture
+    @location(3) material: vec4<u32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) material: vec2<u32>
+};
+
+// Three vertical quads 60 degrees apart, crossing at the plant's center. xy is the
+// corner from (0, 0) at the bottom left to (1, 1) at the top right, z the quad.
+fn plant_corner(vertex_index: u32) -> vec3<f32>
+{
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0)
+    );
+    return vec3<f32>(corners[vertex_index % 6u], f32(vertex_index / 6u));
+}
+
+// Bends the plant along the wind, more towards its tip. Gusts roll across the ground
+// in the wind's direction, and every plant flutters a little on its own on top.
+fn wind_bend(position: vec3<f32>, height: f32, phase: f32, sway: f32) -> vec3<f32>
+{
+    let wind = vertex_animation.wind;
+    let time = vertex_animation.time;
+    let along = dot(position, wind.xyz) / GUST_LENGTH;
+    let gust = sin(time * 1.3 - along) * 0.5 + 0.5;
+    let flutter = sin(time * 4.0 + phase) * 0.25;
+    let weight = height * height;
+
+    return wind.xyz * (gust + flutter) * sway * wind.w * weight;
+}
+
+@vertex
+fn vs_vegetation(@builtin(vertex_index) vertex_index: u32, plant: PlantInput) -> VertexOutput
+{
+    let corner = plant_corner(vertex_index);
+    let angle = plant.position.w + corner.z * 1.0471976;
+    let across = vec3<f32>(cos(angle), 0.0, sin(angle));
+    let size = plant.shape.xy;
+
+    var world_position = plant.position.xyz + across * (corner.x - 0.5) * size.x;
+    world_position.y += corner.y * size.y;
+    world_position += wind_bend(plant.position.xyz, corner.y, plant.shape.z, plant.shape.w);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.tex_coords = vec2<f32>(corner.x, 1.0 - corner.y);
+    out.color = vec4<f32>(plant.color.rgb * mix(BASE_SHADE, 1.0, corner.y), plant.color.a);
+    out.material = plant.material.xy;
+    return out;
+}
+
+// A blade narrowing from the full width at the base to a point at the tip.
+fn blade_alpha(tex_coords: vec2<f32>) -> f32
+{
+    let half_width = 0.5 * tex_coords.y;
+    let distance = abs(tex_coords.x - 0.5);
+    return clamp((half_width - distance) / max(fwidth(tex_coords.x), 1e-4) + 0.5, 0.0, 1.0);
+}
+
+@fragment
+fn fs_vegetation(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    // Both are worked out, sampling has to happen in uniform control flow.
+    let textured = material_color(in.tex_coords, in.material.x, in.color, vec3<f32>(0.0));
+    let blade = vec4<f32>(in.color.rgb, in.color.a * blade_alpha(in.tex_coords));
+    let color = select(blade, textured, in.material.y == 1u);
+    if color.a < ALPHA_CUTOFF {
+        discard;
+    }
+    return color;
+}
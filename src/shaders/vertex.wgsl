@@ -1,52 +1,93 @@
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>
-};
+#include "instancing.wgsl"
+#include "material.wgsl"
+
+// Alpha the transparent pass multiplies the diffuse texture by.
+#define TRANSPARENT_OPACITY 0.5
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>
-};
-
-struct CameraUniform {
-    view_proj: mat4x4<f32>
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) texture_index: u32,
+    // Unjittered, for motion vectors
+    @location(3) current_position: vec4<f32>,
+    @location(4) previous_position: vec4<f32>,
+    @location(5) emissive: vec3<f32>,
+    @location(6) @interpolate(flat) lod_fade: f32
 };
 
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
-};
-
-@group(1) @binding(0)
-var<uniform> camera: CameraUniform;
-
 @vertex
 fn vs_main(
-    input: VertexInput,
-    instance: InstanceInput
+    @builtin(instance_index) instance_index: u32,
+    input: VertexInput
 ) -> VertexOutput
 {
-    let model_matrix = mat4x4<f32>(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3
-    );
+    let instance = instance_data(instance_index);
+
+    let world_position = instance_data_world_position(input, instance);
+
     var out: VertexOutput;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(input.position, 1.0);
+    out.clip_position = instance_clip_position(world_position);
+    out.current_position = instance_unjittered_clip_position(world_position);
+    out.previous_position = instance_previous_clip_position(world_position);
     out.tex_coords = input.tex_coords;
+    out.color = instance.color;
+    out.texture_index = instance.material.x;
+    out.emissive = instance.emissive.rgb;
+    out.lod_fade = instance.emissive.w;
     return out;
 }
 
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0) @binding(1)
-var s_diffuse: sampler;
+// 4x4 Bayer matrix threshold of the pixel, from 0 to 15/16.
+fn dither_threshold(pixel: vec2<f32>) -> f32
+{
+    var bayer = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0
+    );
+    let cell = vec2<u32>(pixel) % 4u;
+    return bayer[cell.y * 4u + cell.x] / 16.0;
+}
+
+// Screen-door cross-fade between LOD levels. A positive fade drops that share of the
+// pixels, a negative one keeps only those, so the two levels never cover the same pixel.
+fn lod_faded_out(pixel: vec2<f32>, fade: f32) -> bool
+{
+    let threshold = dither_threshold(pixel);
+    return (fade > 0.0 && threshold < fade) || (fade < 0.0 && threshold >= -fade);
+}
+
+fn sample_diffuse(in: VertexOutput) -> vec4<f32>
+{
+    return material_color(in.tex_coords, in.texture_index, in.color, in.emissive);
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
 {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    // Sampled first, sampling has to happen in uniform control flow.
+    let color = sample_diffuse(in);
+    if lod_faded_out(in.clip_position.xy, in.lod_fade) {
+        discard;
+    }
+    return color;
+}
+
+// Only camera motion, instances don't keep last frame's transforms.
+@fragment
+fn fs_motion_vectors(in: VertexOutput) -> @location(0) vec2<f32>
+{
+    if lod_faded_out(in.clip_position.xy, in.lod_fade) {
+        discard;
+    }
+    return motion_vector(in.current_position, in.previous_position);
+}
+
+@fragment
+fn fs_transparent(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let color = sample_diffuse(in);
+    return vec4<f32>(color.rgb, color.a * TRANSPARENT_OPACITY);
 }
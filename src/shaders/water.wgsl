@@ -0,0 +1,91 @@
+#define CAMERA_GROUP 0
+#include "common.wgsl"
+
+#define WAVE_SCALE 0.05
+#define FRESNEL_F0 0.02
+#define SPECULAR_POWER 96.0
+
+struct WaterUniform {
+    // xyz is the camera position, w the time in seconds
+    eye: vec4<f32>,
+    // height, extent, wave strength, wave speed
+    params: vec4<f32>,
+    // rgb is the water color, a the murkiness
+    color: vec4<f32>
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) clip: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> water: WaterUniform;
+@group(1) @binding(1)
+var t_reflection: texture_2d<f32>;
+@group(1) @binding(2)
+var t_refraction: texture_2d<f32>;
+@group(1) @binding(3)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(4)
+var s_screen: sampler;
+@group(1) @binding(5)
+var s_normal: sampler;
+
+// Two triangles covering the water square, no vertex buffer needed.
+@vertex
+fn vs_water(@builtin(vertex_index) vertex_index: u32) -> VertexOutput
+{
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(1.0, 1.0)
+    );
+    let corner = corners[vertex_index] * water.params.y * 0.5;
+    let world_position = vec3<f32>(corner.x, water.params.x, corner.y);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.clip = out.clip_position;
+    return out;
+}
+
+fn sample_normal(uv: vec2<f32>) -> vec3<f32>
+{
+    return textureSample(t_normal, s_normal, uv).rgb * 2.0 - 1.0;
+}
+
+@fragment
+fn fs_water(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let time = water.eye.w;
+    let scroll = time * water.params.w;
+    let uv = in.world_position.xz * WAVE_SCALE;
+    let tangent_normal = normalize(
+        sample_normal(uv + vec2<f32>(scroll, scroll * 0.4))
+        + sample_normal(uv * 1.7 - vec2<f32>(scroll * 0.6, -scroll * 0.8))
+    );
+    let normal = normalize(vec3<f32>(tangent_normal.x, tangent_normal.z, tangent_normal.y));
+    let distortion = tangent_normal.xy * water.params.z;
+
+    // The reflection camera sees the scene upside down, so its lookup is flipped vertically.
+    let screen = in.clip.xy / in.clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+    let refraction_uv = clamp(screen + distortion, vec2<f32>(0.001), vec2<f32>(0.999));
+    let reflection_uv = clamp(vec2<f32>(screen.x, 1.0 - screen.y) + distortion, vec2<f32>(0.001),
+        vec2<f32>(0.999));
+    let refraction = textureSample(t_refraction, s_screen, refraction_uv).rgb;
+    let reflection = textureSample(t_reflection, s_screen, reflection_uv).rgb;
+
+    let view = normalize(water.eye.xyz - in.world_position);
+    let fresnel = FRESNEL_F0 + (1.0 - FRESNEL_F0) * pow(1.0 - max(dot(view, normal), 0.0), 5.0);
+    let under_water = mix(refraction, water.color.rgb, water.color.a);
+
+    let sun = normalize(vec3<f32>(0.4, 1.0, 0.3));
+    let specular = pow(max(dot(reflect(-sun, normal), view), 0.0), SPECULAR_POWER);
+    return vec4<f32>(mix(under_water, reflection, fresnel) + vec3<f32>(specular), 1.0);
+}
@@ -0,0 +1,214 @@
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3, VectorSpace};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix4<f32>
+    {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    pub fn blend(&self, other: &Transform, amount: f32) -> Transform
+    {
+        Transform {
+            translation: self.translation.lerp(other.translation, amount),
+            rotation: self.rotation.slerp(other.rotation, amount).normalize(),
+            scale: self.scale.lerp(other.scale, amount)
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self
+    {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub rest: Transform,
+    pub inverse_bind: Matrix4<f32>
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>
+}
+
+impl Skeleton {
+    pub fn rest_pose(&self) -> Vec<Transform>
+    {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    // What the vertex shader multiplies skinned positions by: each joint's model space
+    // transform for `pose`, relative to where it was when the mesh was bound.
+    pub fn joint_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>>
+    {
+        let mut globals: Vec<Option<Matrix4<f32>>> = vec![None; self.joints.len()];
+
+        // Joints aren't guaranteed to be listed parents first, so each one walks up
+        // until it reaches an already resolved ancestor.
+        for index in 0..self.joints.len() {
+            let mut chain = vec![index];
+            while let Some(parent) = self.joints[*chain.last().unwrap()].parent {
+                if globals[parent].is_some() || chain.contains(&parent) {
+                    break;
+                }
+                chain.push(parent);
+            }
+
+            for &joint in chain.iter().rev() {
+                let parent = self.joints[joint].parent
+                    .and_then(|parent| globals[parent])
+                    .unwrap_or_else(Matrix4::identity);
+                globals[joint] = Some(parent * pose[joint].matrix());
+            }
+        }
+
+        globals.into_iter()
+            .zip(&self.joints)
+            .map(|(global, joint)| global.unwrap_or_else(Matrix4::identity) * joint.inverse_bind)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear
+}
+
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>)
+}
+
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub joint: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes
+}
+
+impl Channel {
+    fn apply(&self, time: f32, transform: &mut Transform)
+    {
+        let Some((from, to, amount)) = keyframe_span(&self.times, self.interpolation, time) else {
+            return;
+        };
+
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation = values[from].lerp(values[to], amount);
+            },
+            Keyframes::Rotation(values) => {
+                transform.rotation = values[from].slerp(values[to], amount).normalize();
+            },
+            Keyframes::Scale(values) => {
+                transform.scale = values[from].lerp(values[to], amount);
+            }
+        }
+    }
+
+}
+
+// Animates the blend shape weights of the mesh, one value per morph target and keyframe.
+#[derive(Debug, Clone)]
+pub struct MorphChannel {
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub weights: Vec<Vec<f32>>
+}
+
+impl MorphChannel {
+    fn apply(&self, time: f32, weights: &mut [f32])
+    {
+        let Some((from, to, amount)) = keyframe_span(&self.times, self.interpolation, time) else {
+            return;
+        };
+
+        for (target, weight) in weights.iter_mut().enumerate() {
+            let (Some(from), Some(to)) = (self.weights[from].get(target), self.weights[to].get(target)) else {
+                continue;
+            };
+            *weight = from + (to - from) * amount;
+        }
+    }
+}
+
+// The two keyframes around `time` and how far between them it is.
+fn keyframe_span(times: &[f32], interpolation: Interpolation, time: f32) -> Option<(usize, usize, f32)>
+{
+    let last = times.len().checked_sub(1)?;
+    let next = times.partition_point(|&keyframe_time| keyframe_time <= time);
+
+    if next == 0 {
+        return Some((0, 0, 0.0));
+    }
+    if next > last {
+        return Some((last, last, 0.0));
+    }
+
+    let previous = next - 1;
+    let amount = match interpolation {
+        Interpolation::Step => 0.0,
+        Interpolation::Linear => {
+            let span = times[next] - times[previous];
+            if span > 0.0 { (time - times[previous]) / span } else { 0.0 }
+        }
+    };
+
+    Some((previous, next, amount))
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+    pub morph_channels: Vec<MorphChannel>
+}
+
+impl AnimationClip {
+    // Joints without a channel keep their rest transform.
+    pub fn sample(&self, skeleton: &Skeleton, time: f32) -> Vec<Transform>
+    {
+        let mut pose = skeleton.rest_pose();
+        for channel in &self.channels {
+            if let Some(transform) = pose.get_mut(channel.joint) {
+                channel.apply(time, transform);
+            }
+        }
+
+        pose
+    }
+
+    // Targets without a channel keep their weight from `defaults`.
+    pub fn sample_morph_weights(&self, defaults: &[f32], time: f32) -> Vec<f32>
+    {
+        let mut weights = defaults.to_vec();
+        for channel in &self.morph_channels {
+            channel.apply(time, &mut weights);
+        }
+
+        weights
+    }
+}
@@ -0,0 +1,429 @@
+use std::{collections::HashMap, io::Cursor, path::Path};
+
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, Point3, Quaternion, SquareMatrix, Vector3};
+use gltf::animation::{util::ReadOutputs, Interpolation as GltfInterpolation};
+use wgpu::{vertex_attr_array, VertexAttribute};
+
+use super::{bounds::Bounds, mesh_import::ImportSettings, renderer_backend::{channel_packing::{ChannelPacker, MapSource, MaterialMaps}, vertex_layout::VertexLayout}, skeleton::{AnimationClip, Channel, Interpolation, Joint, Keyframes, MorphChannel, Skeleton, Transform}};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub joints: [u32; 4],
+    pub weights: [f32; 4]
+}
+
+impl VertexLayout for SkinnedVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x2,
+        2 => Uint32x4,
+        3 => Float32x4
+    ];
+}
+
+pub struct SkinnedModel {
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<u32>,
+    // Position offsets per morph target, each with one entry per vertex.
+    pub morph_targets: Vec<Vec<[f32; 3]>>,
+    pub default_weights: Vec<f32>,
+    pub skeleton: Skeleton,
+    pub clips: Vec<AnimationClip>,
+    // Bind pose bounds, grown to cover every morph target at full weight.
+    pub bounds: Bounds,
+    // In the file's order. Only maps in files of their own are read, next to the model.
+    pub materials: Vec<MaterialMaps>
+}
+
+impl SkinnedModel {
+    // By the file's extension, glTF unless it's .obj.
+    pub fn load(path: &Path, settings: &ImportSettings) -> Result<Self>
+    {
+        if Self::is_obj(path) {
+            Self::load_obj(path, settings)
+        } else {
+            Self::load_gltf(path, settings)
+        }
+    }
+
+    // Like load, from a file's contents, e.g. one fetched on the web. The extension of
+    // `name` picks the format, and a .gltf has to embed its buffers.
+    pub fn from_bytes(name: &str, bytes: &[u8], settings: &ImportSettings) -> Result<Self>
+    {
+        if Self::is_obj(Path::new(name)) {
+            let (models, _) = tobj::load_obj_buf(&mut Cursor::new(bytes), &tobj::GPU_LOAD_OPTIONS,
+                |_| Err(tobj::LoadError::OpenFileFailed))?;
+            Self::from_obj_models(models, settings)
+        } else {
+            let (document, buffers, _) = gltf::import_slice(bytes)?;
+            Self::from_gltf(&document, &buffers, None, settings)
+        }
+    }
+
+    fn is_obj(path: &Path) -> bool
+    {
+        path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("obj"))
+    }
+
+    // Every model in the file merged into one, without a skeleton or animations. Of the
+    // materials only the maps are read, with the PBR extension's map_Pr and map_Pm.
+    pub fn load_obj(path: &Path, settings: &ImportSettings) -> Result<Self>
+    {
+        let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+        let mut model = Self::from_obj_models(models, settings)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        model.materials = materials.unwrap_or_default()
+            .iter()
+            .map(|material| Self::obj_material_maps(material, base))
+            .collect();
+        model.pack_material_maps(base, settings);
+
+        Ok(model)
+    }
+
+    fn obj_material_maps(material: &tobj::Material, base: &Path) -> MaterialMaps
+    {
+        // The file name is last, after any options like -bm.
+        let map = |key: &str| material.unknown_param.get(key)
+            .and_then(|value| value.split_whitespace().last())
+            .map(|file| MapSource::grayscale(base.join(file)));
+
+        MaterialMaps {
+            occlusion: map("map_ao"),
+            roughness: map("map_Pr"),
+            metallic: map("map_Pm"),
+            packed: None
+        }
+    }
+
+    // Leaves the maps as they were when they can't be packed, the model loads either way.
+    fn pack_material_maps(&mut self, base: &Path, settings: &ImportSettings)
+    {
+        if !settings.pack_channels {
+            return;
+        }
+
+        let mut packer = ChannelPacker::new(&base.join("packed"));
+        for maps in &mut self.materials {
+            if let Err(e) = packer.pack(maps) {
+                log::warn!("Couldn't pack material maps: {e:#}");
+            }
+        }
+    }
+
+    fn from_obj_models(models: Vec<tobj::Model>, settings: &ImportSettings) -> Result<Self>
+    {
+        ensure!(!models.is_empty(), "no mesh");
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for model in models {
+            let mesh = model.mesh;
+            let base_vertex = vertices.len() as u32;
+            for (index, position) in mesh.positions.chunks_exact(3).enumerate() {
+                // OBJ texture coordinates start at the bottom, wgpu's at the top.
+                let tex_coords = mesh.texcoords.get(2 * index..2 * index + 2)
+                    .map_or([0.0; 2], |uv| [uv[0], 1.0 - uv[1]]);
+                vertices.push(SkinnedVertex {
+                    position: [position[0], position[1], position[2]],
+                    tex_coords,
+                    joints: [0; 4],
+                    weights: [1.0, 0.0, 0.0, 0.0]
+                });
+            }
+            indices.extend(mesh.indices.iter().map(|index| base_vertex + index));
+        }
+
+        let mut morph_targets = Vec::new();
+        settings.optimize(&mut vertices, &mut indices, &mut morph_targets);
+        let bounds = Self::compute_bounds(&vertices, &morph_targets);
+
+        Ok(Self {
+            vertices,
+            indices,
+            morph_targets,
+            default_weights: Vec::new(),
+            skeleton: Skeleton::default(),
+            clips: Vec::new(),
+            bounds,
+            materials: Vec::new()
+        })
+    }
+
+    // Loads the first mesh in the file with a skin or morph targets, or the first mesh
+    // at all in a static model, with every primitive merged into one vertex and index
+    // list, plus the animations targeting its joints and morph weights. A mesh without a
+    // skin gets an empty skeleton.
+    pub fn load_gltf(path: &Path, settings: &ImportSettings) -> Result<Self>
+    {
+        let (document, buffers, _) = gltf::import(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut model = Self::from_gltf(&document, &buffers, Some(base), settings)?;
+        model.pack_material_maps(base, settings);
+
+        Ok(model)
+    }
+
+    // Image URIs are relative to `base`, without one the maps aren't read.
+    fn from_gltf(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        base: Option<&Path>,
+        settings: &ImportSettings
+    ) -> Result<Self>
+    {
+        let meshes = || document.nodes().filter_map(|node| node.mesh().map(|mesh| (node, mesh)));
+        let (node, mesh) = meshes()
+            .find(|(node, mesh)| node.skin().is_some()
+                || mesh.primitives().any(|primitive| primitive.morph_targets().len() > 0))
+            .or_else(|| meshes().next())
+            .ok_or_else(|| anyhow!("no mesh"))?;
+        let get_buffer = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|data| &data.0[..]);
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut morph_targets: Vec<Vec<[f32; 3]>> = Vec::new();
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(get_buffer);
+            let positions = reader.read_positions()
+                .ok_or_else(|| anyhow!("primitive without positions"))?;
+            let mut tex_coords = reader.read_tex_coords(0).map(|coords| coords.into_f32());
+            let mut joints = reader.read_joints(0).map(|joints| joints.into_u16());
+            let mut weights = reader.read_weights(0).map(|weights| weights.into_f32());
+
+            let base_vertex = vertices.len() as u32;
+            for position in positions {
+                vertices.push(SkinnedVertex {
+                    position,
+                    tex_coords: tex_coords.as_mut().and_then(Iterator::next).unwrap_or([0.0; 2]),
+                    joints: joints.as_mut().and_then(Iterator::next).unwrap_or([0; 4])
+                        .map(u32::from),
+                    weights: weights.as_mut().and_then(Iterator::next).unwrap_or([1.0, 0.0, 0.0, 0.0])
+                });
+            }
+
+            match reader.read_indices() {
+                Some(primitive_indices) => indices.extend(primitive_indices.into_u32()
+                    .map(|index| base_vertex + index)),
+                None => indices.extend(base_vertex..vertices.len() as u32)
+            }
+
+            // Primitives without a target (or its positions) don't move with it.
+            for (target, (positions, _, _)) in reader.read_morph_targets().enumerate() {
+                if target == morph_targets.len() {
+                    morph_targets.push(vec![[0.0; 3]; base_vertex as usize]);
+                }
+                morph_targets[target].extend(positions.into_iter().flatten());
+            }
+            for target in &mut morph_targets {
+                target.resize(vertices.len(), [0.0; 3]);
+            }
+        }
+
+        settings.optimize(&mut vertices, &mut indices, &mut morph_targets);
+        let bounds = Self::compute_bounds(&vertices, &morph_targets);
+
+        let default_weights = match mesh.weights() {
+            Some(weights) => weights.to_vec(),
+            None => vec![0.0; morph_targets.len()]
+        };
+
+        let skeleton = node.skin()
+            .map(|skin| Self::load_skeleton(document, &skin, buffers))
+            .unwrap_or_default();
+        let joint_of_node = node.skin().into_iter()
+            .flat_map(|skin| skin.joints().collect::<Vec<_>>())
+            .enumerate()
+            .map(|(joint, node)| (node.index(), joint))
+            .collect::<HashMap<_, _>>();
+        let clips = document.animations()
+            .map(|animation| Self::load_clip(&animation, &joint_of_node, node.index(),
+                morph_targets.len(), buffers))
+            .collect();
+
+        Ok(Self {
+            vertices,
+            indices,
+            morph_targets,
+            default_weights,
+            skeleton,
+            clips,
+            bounds,
+            materials: document.materials()
+                .map(|material| Self::gltf_material_maps(&material, base))
+                .collect()
+        })
+    }
+
+    // glTF keeps roughness in the green and metallic in the blue channel of one texture.
+    fn gltf_material_maps(material: &gltf::Material, base: Option<&Path>) -> MaterialMaps
+    {
+        let image_path = |texture: gltf::Texture| match (texture.source().source(), base) {
+            (gltf::image::Source::Uri { uri, .. }, Some(base)) if !uri.starts_with("data:") =>
+                Some(base.join(uri)),
+            _ => None
+        };
+        let metallic_roughness = material.pbr_metallic_roughness().metallic_roughness_texture()
+            .and_then(|info| image_path(info.texture()));
+
+        MaterialMaps {
+            occlusion: material.occlusion_texture()
+                .and_then(|info| image_path(info.texture()))
+                .map(|path| MapSource::channel(path, 0)),
+            roughness: metallic_roughness.clone().map(|path| MapSource::channel(path, 1)),
+            metallic: metallic_roughness.map(|path| MapSource::channel(path, 2)),
+            packed: None
+        }
+    }
+
+    fn compute_bounds(vertices: &[SkinnedVertex], morph_targets: &[Vec<[f32; 3]>]) -> Bounds
+    {
+        let points = vertices.iter()
+            .enumerate()
+            .flat_map(|(index, vertex)| {
+                let [x, y, z] = vertex.position;
+                std::iter::once([0.0; 3])
+                    .chain(morph_targets.iter().map(move |target| target[index]))
+                    .map(move |[dx, dy, dz]| Point3::new(x + dx, y + dy, z + dz))
+            })
+            .collect::<Vec<_>>();
+
+        Bounds::from_points(&points)
+    }
+
+    pub fn clip_index(&self, name: &str) -> Option<usize>
+    {
+        self.clips.iter().position(|clip| clip.name == name)
+    }
+
+    // Non-joint nodes between two joints are skipped, their transforms are assumed
+    // to be the identity, which holds for what common exporters write.
+    fn load_skeleton(
+        document: &gltf::Document,
+        skin: &gltf::Skin,
+        buffers: &[gltf::buffer::Data]
+    ) -> Skeleton
+    {
+        let parent_of_node = document.nodes()
+            .flat_map(|parent| parent.children().map(move |child| (child.index(), parent.index())))
+            .collect::<HashMap<_, _>>();
+        let joint_nodes = skin.joints().map(|node| node.index()).collect::<Vec<_>>();
+        let inverse_binds = skin.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]))
+            .read_inverse_bind_matrices()
+            .map(|matrices| matrices.map(Matrix4::from).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let joints = skin.joints()
+            .enumerate()
+            .map(|(index, node)| {
+                let mut ancestor = parent_of_node.get(&node.index());
+                let parent = loop {
+                    match ancestor {
+                        Some(node) => match joint_nodes.iter().position(|joint| joint == node) {
+                            Some(joint) => break Some(joint),
+                            None => ancestor = parent_of_node.get(node)
+                        },
+                        None => break None
+                    }
+                };
+                let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+
+                Joint {
+                    name: String::from(node.name().unwrap_or_default()),
+                    parent,
+                    rest: Transform {
+                        translation: translation.into(),
+                        rotation: Quaternion::new(w, x, y, z),
+                        scale: scale.into()
+                    },
+                    inverse_bind: inverse_binds.get(index).copied().unwrap_or_else(Matrix4::identity)
+                }
+            })
+            .collect();
+
+        Skeleton {
+            joints
+        }
+    }
+
+    // Cubic spline keyframes store an in-tangent, the value and an out-tangent; only
+    // the value is kept and interpolated linearly.
+    fn load_clip(
+        animation: &gltf::Animation,
+        joint_of_node: &HashMap<usize, usize>,
+        mesh_node: usize,
+        morph_target_count: usize,
+        buffers: &[gltf::buffer::Data]
+    ) -> AnimationClip
+    {
+        let mut channels = Vec::new();
+        let mut morph_channels = Vec::new();
+
+        for channel in animation.channels() {
+            let target_node = channel.target().node().index();
+            let joint = joint_of_node.get(&target_node).copied();
+            if joint.is_none() && target_node != mesh_node {
+                continue;
+            }
+            let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+            let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+                continue;
+            };
+
+            let (interpolation, stride, offset) = match channel.sampler().interpolation() {
+                GltfInterpolation::Step => (Interpolation::Step, 1, 0),
+                GltfInterpolation::Linear => (Interpolation::Linear, 1, 0),
+                GltfInterpolation::CubicSpline => (Interpolation::Linear, 3, 1)
+            };
+            let keyframes = match outputs {
+                ReadOutputs::Translations(values) => Keyframes::Translation(values
+                    .skip(offset).step_by(stride).map(Vector3::from).collect()),
+                ReadOutputs::Rotations(values) => Keyframes::Rotation(values.into_f32()
+                    .skip(offset).step_by(stride).map(|[x, y, z, w]| Quaternion::new(w, x, y, z))
+                    .collect()),
+                ReadOutputs::Scales(values) => Keyframes::Scale(values
+                    .skip(offset).step_by(stride).map(Vector3::from).collect()),
+                ReadOutputs::MorphTargetWeights(values) if target_node == mesh_node
+                    && morph_target_count > 0 => {
+                    let values = values.into_f32().collect::<Vec<_>>();
+                    morph_channels.push(MorphChannel {
+                        interpolation,
+                        times: times.collect(),
+                        weights: values.chunks(morph_target_count)
+                            .skip(offset).step_by(stride).map(<[f32]>::to_vec).collect()
+                    });
+                    continue;
+                },
+                ReadOutputs::MorphTargetWeights(_) => continue
+            };
+            let Some(joint) = joint else {
+                continue;
+            };
+
+            channels.push(Channel {
+                joint,
+                interpolation,
+                times: times.collect(),
+                keyframes
+            });
+        }
+
+        let duration = channels.iter()
+            .map(|channel| &channel.times)
+            .chain(morph_channels.iter().map(|channel| &channel.times))
+            .filter_map(|times| times.last())
+            .fold(0.0_f32, |duration, &time| duration.max(time));
+
+        AnimationClip {
+            name: String::from(animation.name().unwrap_or_default()),
+            duration,
+            channels,
+            morph_channels
+        }
+    }
+}
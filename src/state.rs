@@ -1,20 +1,76 @@
-use std::iter::once;
+use std::{cell::Cell, collections::{BTreeMap, HashMap, HashSet}, io, ops::Range, path::Path, rc::Rc, time::Duration};
 use bytemuck::cast_slice;
 
-use cgmath::{prelude::*, Deg, Quaternion, Vector3};
-use wgpu::{util::{BufferInitDescriptor, DeviceExt}, Adapter, Backends, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages, Color, CommandEncoderDescriptor, Device, DeviceDescriptor, Features, IndexFormat, Instance as WgpuInstance, InstanceDescriptor, Limits, LoadOp, Operations, PowerPreference, Queue, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, RequestAdapterOptions, ShaderStages, StoreOp, Surface, SurfaceConfiguration, SurfaceError, TextureUsages, TextureViewDescriptor};
-use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
+use cgmath::{prelude::*, Deg, Point3, Quaternion, Vector2, Vector3, Vector4};
+use image::{DynamicImage, RgbaImage};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, Adapter, Backend, Backends, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, Color, CommandEncoder, CommandEncoderDescriptor, CompareFunction, CompositeAlphaMode, ComputePassDescriptor, ComputePipeline, Device, DeviceDescriptor, DownlevelFlags, Extent3d, Face, FrontFace, Instance as WgpuInstance, InstanceDescriptor, Limits, LoadOp, Maintain, Operations, PolygonMode, PowerPreference, PresentMode, PrimitiveTopology, Queue, RenderPass, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, RequestAdapterOptions, Sampler, ShaderStages, Surface, StoreOp, SurfaceConfiguration, SurfaceError, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor};
+use winit::{dpi::{LogicalSize, PhysicalPosition, PhysicalSize}, event::{DeviceEvent, ElementState, KeyEvent, TouchPhase, WindowEvent}, keyboard::{KeyCode, ModifiersState, PhysicalKey}, window::{Fullscreen, Window}};
+use web_time::Instant;
 
-use crate::state::{camera::CameraUniform, renderer_backend::texture::Texture};
+use crate::{custom_event::CustomEvent, error::RendererError, settings::{changed, RenderSettings, Settings, SettingsWatcher}, state::{camera::CameraUniform, renderer_backend::texture::{Texture, TextureKind}}};
 
-use self::{camera::{Camera, CameraController}, renderer_backend::{pipeline_builder::PipelineBuilder, vertex::Vertex}, instance::Instance};
+use self::{camera::{halton, Camera, CameraController, CameraTransition}, camera_bookmarks::CameraBookmarks, crash_report::CrashReporter, frame_profiler::FrameProfiler, gamepad::Gamepads, gizmo::Gizmo, input_map::ActionEvent, input_trace::InputTracer, scheduler::Scheduler, options::{StateOptions, SurfaceOptions}, touch::{Gesture, TouchGestures}, renderer_backend::{asset_decode, assets::{Assets, MaterialHandle, Mesh, MeshHandle, ProceduralTextureHandle, RenderTargetHandle, TextureHandle}, billboard::{BillboardBuffer, BillboardRaw, DEFAULT_SOFT_DISTANCE}, blend_mode::BlendMode, color_grading::{ColorGrading, CubeLut}, compute_pipeline_builder::ComputePipelineBuilder, debug_labels::DebugLabels, debug_lines::{DebugLines, LineVertex}, decal::{DecalBuffer, DecalRaw}, edge_detection::EdgeDetection, outline::Outline, render_scale::{clamp_render_scale, scaled_config}, draw_queue::{DrawQueue, InstancedDraw}, error_scope::ErrorScope, gpu_allocator::{GpuAllocator, DEFAULT_BLOCK_SIZE}, gpu_culling::{CullDraw, GpuCulling}, gpu_profiler::GpuProfiler, gpu_readback::GpuReadback, instance_buffer::{InstanceBatch, InstanceBuffer, InstanceStorage}, material::{Material, MaterialFeatures}, motion_blur::MotionBlur, msaa::{self, Msaa}, pipeline_builder::{PipelineBuilder, REVERSE_Z_DEFINE}, pipeline_cache::PipelineCache, procedural_texture::{ProceduralTexture, TextureGenerator}, render_target::RenderTarget, shader_registry::{ShaderHandle, ShaderRegistry}, residency::{ResidencyManager, ResidentAsset, ResidentTexture}, sampler_cache::{SamplerCache, SamplerSpec, DEFAULT_ANISOTROPY}, skinned_mesh::SkinnedMesh, depth_of_field::DepthOfField, fog::Fog, glow::Glow, post_effect::PostProcess, ssao::{Ssao, OCCLUSION_FORMAT}, submit_batch::SubmitBatch, taa::{Taa, MOTION_VECTOR_FORMAT}, terrain_mesh::TerrainMesh, vegetation_mesh::VegetationMesh, texture_streaming::{StreamRequest, TextureStreamer, DEFAULT_UPLOAD_BUDGET_BYTES}, transient::{TransientTexture, TransientTextureDesc, TransientTexturePool}, vertex::Vertex, vertex_layout::VertexLayout, water::Water}, instance::Instance, mesh_lod::MeshLods, picking::{PickMesh, Ray, RayHit}, animator::Animator, skinned_model::{SkinnedModel, SkinnedVertex}, terrain::{Heightmap, TerrainVertex}, vegetation::PlantRaw, vertex_animation::{AnimationParams, VertexAnimationUniform}, viewport::Viewport, scene_camera::SceneCamera, recorder::Recorder};
+
+pub use self::{bounds::{Aabb, BoundingSphere, Bounds}, recorder::{RecordingOptions, RecordingOutput}, scene_camera::CameraKind, camera_bookmarks::CameraBookmark, camera_rig::{CameraKeyframe, CameraRig, RigMotion}, follow_camera::FollowCamera, frame_profiler::ScopeStats, gizmo::{GizmoMode, InstanceTransform, TransformEdit}, input_map::{Action, Binding, InputMap}, input_trace::InputRecord, instance::InstanceRaw, mesh_import::ImportSettings, placement::PlacementOptions, renderer_backend::{anti_aliasing::AntiAliasing, assets::AssetStats, billboard::{Billboard, BillboardMode}, color_grading::ColorGradingOptions, debug_view::DebugView, decal::Decal, depth_of_field::DepthOfFieldOptions, draw_queue::DrawQueueStats, edge_detection::EdgeDetectionOptions, gpu_capabilities::GpuCapabilities, outline::OutlineOptions, render_scale::Upscaling, fog::{FogOptions, SkyOptions}, glow::GlowOptions, gpu_allocator::GpuAllocatorStats, gpu_profiler::GpuTiming, motion_blur::MotionBlurOptions, pipeline_cache::PipelineCacheStats, post_effect::PostEffect, procedural_texture::ProceduralPattern, render_pass::RenderPassConfig, residency::ResidencyStats, ssao::SsaoOptions, submit_batch::SubmitStats, texture_streaming::StreamingStats, transient::TransientPoolStats, water::WaterOptions}, scheduler::{SystemTiming, Tick}, terrain::TerrainOptions, vegetation::VegetationOptions, viewport::ViewportRect};
 
 #[path ="renderer_backend/mod.rs"]
-mod renderer_backend;
+pub mod renderer_backend;
 #[path ="camera.rs"]
 mod camera;
+#[path ="camera_bookmarks.rs"]
+mod camera_bookmarks;
+#[path ="camera_rig.rs"]
+mod camera_rig;
+#[path ="follow_camera.rs"]
+mod follow_camera;
 #[path ="instance.rs"]
 mod instance;
+#[path ="picking.rs"]
+mod picking;
+#[path ="crash_report.rs"]
+mod crash_report;
+#[path ="options.rs"]
+pub mod options;
+#[path ="input_trace.rs"]
+mod input_trace;
+#[path ="input_map.rs"]
+mod input_map;
+#[path ="gamepad.rs"]
+mod gamepad;
+#[path ="touch.rs"]
+mod touch;
+#[path ="gizmo.rs"]
+mod gizmo;
+#[path ="scheduler.rs"]
+mod scheduler;
+#[path ="frame_profiler.rs"]
+mod frame_profiler;
+#[path ="placement.rs"]
+mod placement;
+#[path ="vertex_animation.rs"]
+mod vertex_animation;
+#[path ="skeleton.rs"]
+mod skeleton;
+#[path ="animator.rs"]
+mod animator;
+#[path ="skinned_model.rs"]
+mod skinned_model;
+#[path ="terrain.rs"]
+mod terrain;
+#[path ="vegetation.rs"]
+mod vegetation;
+#[path ="mesh_lod.rs"]
+mod mesh_lod;
+#[path ="mesh_import.rs"]
+mod mesh_import;
+#[path ="bounds.rs"]
+mod bounds;
+#[path ="viewport.rs"]
+mod viewport;
+#[path ="scene_camera.rs"]
+mod scene_camera;
+#[path ="recorder.rs"]
+mod recorder;
 
 const VERTICES: &[Vertex] = &[
     Vertex {
@@ -45,77 +101,344 @@ const INDICES: &[u16] = &[
     2, 3, 4
 ];
 
+const INSTANCE_PIPELINE_LABEL: &str = "Textured Instances";
+const TRANSPARENT_PIPELINE_LABEL: &str = "Transparent Instances";
+const BILLBOARD_PIPELINE_LABEL: &str = "Billboards";
+const INSTANCES_LABEL: &str = "Instances";
+// What a headless frame texture can be, select_format picks the sRGB one by default.
+const HEADLESS_FORMATS: [TextureFormat; 3] = [TextureFormat::Rgba8UnormSrgb, TextureFormat::Rgba8Unorm,
+    TextureFormat::Rgba16Float];
+const CULL_PIPELINE_LABEL: &str = "Instance Culling";
+const CULL_PASS_LABEL: &str = "Culling Pass";
+const GPU_ALLOCATOR_LABEL: &str = "Shared Geometry";
+const SKINNED_PIPELINE_LABEL: &str = "Skinned Mesh";
+const MATERIAL_PIPELINE_LABEL: &str = "Material";
+const TERRAIN_PIPELINE_LABEL: &str = "Terrain";
+const VEGETATION_PIPELINE_LABEL: &str = "Vegetation";
+const WATER_PIPELINE_LABEL: &str = "Water";
+const WATER_REFLECTION_PASS_LABEL: &str = "Water Reflection Pass";
+const WATER_REFRACTION_PASS_LABEL: &str = "Water Refraction Pass";
+// Lowers the reflection clip plane a little so the shoreline doesn't show a gap.
+const WATER_CLIP_OFFSET: f32 = 0.05;
+// How far dragging a finger across the screen turns the view, in radians per logical
+// pixel.
+const TOUCH_LOOK_SPEED: f32 = 0.005;
+// How far a gamepad's right stick orbits the follow camera per update, in radians.
+const FOLLOW_ORBIT_SPEED: f32 = 0.04;
+// How long the camera takes to move onto something it frames.
+const CAMERA_FRAMING_DURATION: Duration = Duration::from_millis(400);
+const DEBUG_LINES_PIPELINE_LABEL: &str = "Debug Lines";
+const TAA_LABEL: &str = "TAA";
+const TAA_RESOLVE_PIPELINE_LABEL: &str = "TAA Resolve";
+const BLIT_PIPELINE_LABEL: &str = "Blit";
+const UPSCALE_PIPELINE_LABEL: &str = "Upscale";
+const MOTION_VECTORS_PIPELINE_LABEL: &str = "Motion Vectors";
+const MSAA_LABEL: &str = "MSAA";
+const DEPTH_RESOLVE_PIPELINE_LABEL: &str = "MSAA Depth Resolve";
+const DEPTH_RESOLVE_PASS_LABEL: &str = "Depth Resolve Pass";
+const VIEWPORT_COMPOSITE_PIPELINE_LABEL: &str = "MSAA Viewport Composite";
+const MOTION_VECTOR_PASS_LABEL: &str = "Motion Vector Pass";
+const TAA_RESOLVE_PASS_LABEL: &str = "TAA Resolve Pass";
+const POST_PROCESS_LABEL: &str = "Post Process";
+const SSAO_LABEL: &str = "SSAO";
+const SSAO_OCCLUSION_PIPELINE_LABEL: &str = "SSAO Occlusion";
+const SSAO_BLUR_PIPELINE_LABEL: &str = "SSAO Blur";
+const FOG_LABEL: &str = "Fog";
+const DEPTH_OF_FIELD_LABEL: &str = "Depth of Field";
+const MOTION_BLUR_LABEL: &str = "Motion Blur";
+const GLOW_LABEL: &str = "Glow";
+const COLOR_GRADING_LABEL: &str = "Color Grading";
+const EDGE_DETECTION_LABEL: &str = "Edge Detection";
+const TEXTURE_GENERATOR_LABEL: &str = "Texture Generator";
+const OUTLINE_LABEL: &str = "Outline";
+const DECAL_PIPELINE_LABEL: &str = "Decal";
+const DECAL_PASS_LABEL: &str = "Decal Pass";
+const OVERLAY_PASS_LABEL: &str = "Overlay Pass";
+const CAMERA_LABEL: &str = "Camera";
+const MAIN_CAMERA_LABEL: &str = "Main Camera";
+const VERTEX_ANIMATION_LABEL: &str = "Vertex Animation";
+
+const MAX_LOD_LEVELS: usize = 4;
+const DEFAULT_LOD_ERROR_PIXELS: f32 = 1.0;
+// Share of the distance before a coarser level takes over during which both are drawn
+// and dithered into each other.
+const DEFAULT_LOD_FADE_BAND: f32 = 0.15;
+// Length of the Halton (2, 3) sequence the camera jitter cycles through.
+const JITTER_SAMPLES: u32 = 8;
+
+const RESIDENCY_BUDGET_BYTES: u64 = 256 * 1024 * 1024;
+
 const NUM_INSTANCES_PER_ROW: u32 = 10;
-const INSTANCE_DISPLACEMENT: Vector3<f32> = Vector3::new(
-    NUM_INSTANCES_PER_ROW as f32 * 0.5, 0.0, NUM_INSTANCES_PER_ROW as f32 * 0.5);
+// How often the stress mode logs its frame timings.
+const STRESS_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+// Where frames end up: the window's surface, or when running headless a texture
+// configured the same way, which read_frame reads back.
+enum FrameOutput<'a> {
+    Surface(Surface<'a>),
+    Texture(wgpu::Texture)
+}
+
+// What open_file and open_bytes make of a file, by its extension.
+enum FileKind {
+    Model,
+    Image
+}
 
 pub struct State<'a> {
-    surface: Surface<'a>,
+    instance: WgpuInstance,
+    options: StateOptions,
+    frame_output: FrameOutput<'a>,
     device: Device,
     queue: Queue,
+    capabilities: GpuCapabilities,
     config: SurfaceConfiguration,
+    // The surface's configuration at render_scale, for everything drawn before the
+    // upscale pass brings it to the surface's size.
+    render_config: SurfaceConfiguration,
+    render_scale: f32,
+    upscaling: Upscaling,
     pub size: PhysicalSize<u32>,
-    pub window: &'a Window,
-    render_pipeline: RenderPipeline,
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
+    // None when running headless.
+    pub window: Option<&'a Window>,
+    // Physical pixels per logical pixel, 1 headless.
+    scale_factor: f64,
+    shader_registry: ShaderRegistry,
+    pipeline_cache: PipelineCache,
+    render_pipeline: Rc<RenderPipeline>,
+    transparent_pipeline: Rc<RenderPipeline>,
+    debug_view: DebugView,
+    debug_pipeline: Option<Rc<RenderPipeline>>,
+    gpu_allocator: GpuAllocator,
+    assets: Assets,
+    instance_mesh: MeshHandle,
     num_indices: u32,
-    diffuse_texture: Texture,
-    diffuse_bind_group: BindGroup,
+    mesh_lods: MeshLods,
+    lod_error_threshold: f32,
+    lod_fade_band: f32,
+    texture_bind_group_layout: BindGroupLayout,
+    samplers: SamplerCache,
+    // Of the diffuse array and every loaded texture's own bind group.
+    texture_sampler: Rc<Sampler>,
+    diffuse_texture: ResidentTexture,
+    asset_residency: ResidencyManager<ResidentAsset>,
+    texture_streamer: TextureStreamer,
     camera: Camera,
     camera_controller: CameraController,
+    // Takes over the camera until it arrives, any movement input cancels it.
+    camera_transition: Option<CameraTransition>,
+    // Drives the camera instead of the controller while enabled.
+    camera_rig: Option<CameraRig>,
+    camera_rig_enabled: bool,
+    // Takes over from the controller while there is one, the rig still wins.
+    follow_camera: Option<FollowCamera>,
+    input_map: InputMap,
+    // None when headless, turned off or without gamepad support.
+    gamepads: Option<Gamepads>,
+    touch_gestures: TouchGestures,
+    // What the renderer started with, which the settings file's values are laid over.
+    startup_settings: Settings,
+    // What's applied from the settings file, to tell what changed when it does.
+    settings: Settings,
+    settings_watcher: Option<SettingsWatcher>,
+    camera_bookmarks: CameraBookmarks,
     camera_uniform: CameraUniform,
+    camera_jitter: bool,
+    jitter_index: u32,
+    camera_relative: bool,
     camera_buffer: Buffer,
+    camera_bind_group_layout: BindGroupLayout,
     camera_bind_group: BindGroup,
+    main_viewport: ViewportRect,
+    viewports: Vec<Viewport>,
+    // The active one's camera, uniform and binding are State's own, see set_active_camera.
+    scene_cameras: Vec<SceneCamera>,
+    active_camera: usize,
+    vertex_animation_uniform: VertexAnimationUniform,
+    vertex_animation_buffer: Buffer,
+    vertex_animation_bind_group_layout: BindGroupLayout,
+    vertex_animation_bind_group: BindGroup,
+    instance_bind_group_layout: BindGroupLayout,
     instances: Vec<Instance>,
-    instance_buffer: Buffer,
-    depth_texture: Texture
+    instance_buffer: InstanceBuffer,
+    // LOD level and instance batch of every instance draw this frame.
+    instance_lod_draws: Vec<(Option<MaterialHandle>, usize, InstanceBatch)>,
+    // Culls instance_lod_draws for the main camera, None draws all of them.
+    gpu_culling: Option<GpuCulling>,
+    cull_pipeline: Option<ComputePipeline>,
+    // Of the last pass drawn, which is the main one.
+    draw_queue_stats: Cell<DrawQueueStats>,
+    // When the stress mode logs its timings next, None outside of it.
+    stress_report_at: Option<Instant>,
+    // Saves the next frame rendered to a PNG, see request_screenshot.
+    screenshot_requested: bool,
+    recorder: Option<Recorder>,
+    material_layouts: HashMap<MaterialFeatures, BindGroupLayout>,
+    // None where the permutation failed to build, retried on the next shader reload.
+    material_pipelines: HashMap<MaterialFeatures, Option<Rc<RenderPipeline>>>,
+    skinned_material: Option<MaterialHandle>,
+    transparent_instances: Vec<Instance>,
+    transparent_instance_buffer: InstanceBuffer,
+    transparent_instance_batches: Vec<InstanceBatch>,
+    billboards: Vec<Billboard>,
+    billboard_buffer: BillboardBuffer,
+    billboard_pipeline: Rc<RenderPipeline>,
+    soft_billboard_distance: f32,
+    skinned_model: Option<SkinnedModel>,
+    skinned_mesh: Option<SkinnedMesh>,
+    skinned_pipeline: Option<Rc<RenderPipeline>>,
+    terrain: Option<Heightmap>,
+    terrain_mesh: Option<TerrainMesh>,
+    terrain_pipeline: Option<Rc<RenderPipeline>>,
+    // Kept while there's no terrain, the plants grow once there is one.
+    vegetation: Option<VegetationOptions>,
+    vegetation_mesh: Option<VegetationMesh>,
+    vegetation_pipeline: Option<Rc<RenderPipeline>>,
+    water: Option<Water>,
+    water_pipeline: Option<Rc<RenderPipeline>>,
+    decals: Vec<Decal>,
+    decal_buffer: DecalBuffer,
+    // Material and instance range of every decal draw this frame.
+    decal_draws: Vec<(MaterialHandle, Range<u32>)>,
+    // By the features of the decal's material, None where it failed to build.
+    decal_pipelines: HashMap<MaterialFeatures, Option<Rc<RenderPipeline>>>,
+    show_bounds: bool,
+    debug_lines: DebugLines,
+    debug_lines_pipeline: Option<Rc<RenderPipeline>>,
+    anti_aliasing: AntiAliasing,
+    // Of every scene pipeline and the passes drawing with them, from anti_aliasing.
+    sample_count: u32,
+    msaa: Option<Msaa>,
+    depth_resolve_pipeline: Option<Rc<RenderPipeline>>,
+    viewport_composite_pipeline: Option<Rc<RenderPipeline>>,
+    taa: Option<Taa>,
+    taa_resolve_pipeline: Option<Rc<RenderPipeline>>,
+    blit_pipeline: Option<Rc<RenderPipeline>>,
+    // Only while the render scale isn't 1.
+    upscale_pipeline: Option<Rc<RenderPipeline>>,
+    // By the shader of the scene pipeline they stand in for.
+    motion_vector_pipelines: HashMap<ShaderHandle, Rc<RenderPipeline>>,
+    post_process: PostProcess,
+    // In the order they run in.
+    post_effects: Vec<PostEffect>,
+    post_effect_pipelines: HashMap<PostEffect, Rc<RenderPipeline>>,
+    // Along with the SSAO post effect.
+    ssao: Option<Ssao>,
+    ssao_occlusion_pipeline: Option<Rc<RenderPipeline>>,
+    ssao_blur_pipeline: Option<Rc<RenderPipeline>>,
+    fog: Option<Fog>,
+    depth_of_field: Option<DepthOfField>,
+    motion_blur: Option<MotionBlur>,
+    glow: Option<Glow>,
+    color_grading: Option<ColorGrading>,
+    edge_detection: Option<EdgeDetection>,
+    texture_generator: Option<TextureGenerator>,
+    outline: Option<Outline>,
+    // Indices into instances, drawn with the outline when it's on. The selected
+    // instance always is.
+    outlined_instances: Vec<usize>,
+    animator: Animator,
+    custom_events: Vec<CustomEvent>,
+    depth_texture: Texture,
+    transient_textures: TransientTexturePool,
+    gpu_profiler: Option<GpuProfiler>,
+    submit_batch: SubmitBatch,
+    pick_mesh: PickMesh,
+    cursor_position: PhysicalPosition<f64>,
+    modifiers: ModifiersState,
+    selected_instance: Option<usize>,
+    // Drawn on the selected instance.
+    gizmo: Gizmo,
+    // Every finished gizmo drag since take_transform_edits.
+    transform_edits: Vec<TransformEdit>,
+    placement_options: PlacementOptions,
+    render_pass_config: RenderPassConfig,
+    crash_reporter: CrashReporter,
+    input_tracer: InputTracer,
+    scheduler: Scheduler<State<'a>>,
+    frame_profiler: FrameProfiler,
+    is_shut_down: bool,
+    // Minimized or resized to nothing, the surface keeps its last size meanwhile.
+    minimized: bool,
+    // Entirely hidden, by other windows or on another workspace.
+    occluded: bool,
+    // Paused by the application, e.g. from the page on the web.
+    paused: bool
 }
 
 impl<'a> State<'a> {
-    pub async fn new(window: &'a Window) -> Self
+    pub async fn new(window: &'a Window, options: StateOptions) -> Result<Self, RendererError>
     {
-        let size = window.inner_size();
-        let instance = WgpuInstance::new(Self::get_instance_descriptor());
-        let surface = instance.create_surface(window).unwrap();
-        let adapter = instance.request_adapter(&Self::get_adapter_descriptor(&surface))
-            .await
-            .unwrap();
-        let (device, queue) = adapter.request_device(&Self::get_device_descriptor(), None)
-            .await
-            .unwrap();
-        let config = Self::get_surface_configuration(&surface, &adapter, &size);
+        let instance = Self::create_instance(options.backends).await;
+        let surface = instance.create_surface(window)?;
 
-        surface.configure(&device, &config);
+        Self::create(instance, Some(window), Some(surface), window.inner_size(), options).await
+    }
 
-        let diffuse_bytes = include_bytes!("../res/crycat.jpg");
-        let diffuse_texture = Texture::from_bytes(&device, &queue, diffuse_bytes, "Cry Cat")
-            .unwrap();
-        let texture_bind_group_layout = Texture::get_texture_bind_group_layout(&device);
-        let diffuse_bind_group = device.create_bind_group(
-            &BindGroupDescriptor {
-                label: Some("Diffuse Bind Group"),
-                layout: &texture_bind_group_layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::TextureView(&diffuse_texture.view)
-                    },
-                    BindGroupEntry {
-                        binding: 1,
-                        resource: BindingResource::Sampler(&diffuse_texture.sampler)
-                    }
-                ]
-            }
+    // Renders into a texture of `size` instead of a window, e.g. for tests or rendering
+    // offline. Input still works, events just have to be passed in by hand.
+    pub async fn new_headless(size: PhysicalSize<u32>, options: StateOptions) -> Result<State<'static>, RendererError>
+    {
+        let instance = State::create_instance(options.backends).await;
+
+        State::create(instance, None, None, size, options).await
+    }
+
+    // Renders into a canvas handed to a web worker, which has no window. Its size and
+    // input come from the page, see web_worker.rs.
+    #[cfg(target_arch = "wasm32")]
+    pub async fn new_offscreen(
+        canvas: web_sys::OffscreenCanvas,
+        size: PhysicalSize<u32>,
+        options: StateOptions
+    ) -> Result<State<'static>, RendererError>
+    {
+        let instance = State::create_instance(options.backends).await;
+        let surface = instance.create_surface(wgpu::SurfaceTarget::OffscreenCanvas(canvas))?;
+
+        State::create(instance, None, Some(surface), size, options).await
+    }
+
+    #[tracing::instrument(name = "init", skip_all)]
+    async fn create(
+        instance: WgpuInstance,
+        window: Option<&'a Window>,
+        surface: Option<Surface<'a>>,
+        size: PhysicalSize<u32>,
+        options: StateOptions
+    ) -> Result<Self, RendererError>
+    {
+        let adapter = Self::select_adapter(&instance, surface.as_ref(), &options).await?;
+        let (device, queue) = adapter.request_device(&Self::get_device_descriptor(&adapter), None)
+            .await?;
+        let gpu_profiler = GpuProfiler::new(&device, &queue);
+        let config = Self::get_configuration(surface.as_ref(), &adapter, &size, &options.surface);
+        let capabilities = GpuCapabilities::new(&adapter, &device, surface.as_ref(), &config);
+        capabilities.log();
+
+        let crash_reporter = CrashReporter::default();
+        crash_reporter.install(&device);
+
+        let frame_output = Self::create_frame_output(&device, &config, surface);
+
+        // The default texture and the skinned model decode side by side.
+        let skinned_model_path = options.skinned_model.clone();
+        let import_settings = &options.import_settings;
+        let (diffuse_image, skinned_model) = asset_decode::join(
+            || asset_decode::decode_image(include_bytes!("../res/crycat.jpg")),
+            || skinned_model_path.as_deref()
+                .map(|path| SkinnedModel::load(path, import_settings))
         );
+        let diffuse_image = diffuse_image?;
+        let texture_bind_group_layout = Texture::get_texture_array_bind_group_layout(&device);
+        let mut samplers = SamplerCache::new(Self::max_anisotropy(&adapter));
+        let texture_sampler = samplers.get(&device, SamplerSpec::default());
+        let mut diffuse_texture = ResidentTexture::new("Cry Cat", diffuse_image, TextureKind::Color);
+        diffuse_texture.make_resident(&device, &queue, &texture_bind_group_layout,
+            &texture_sampler)?;
 
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {
-                let shader_name = include_str!("./shaders/vertex.wgsl");
-            } else {
-                let shader_name = "vertex.wgsl";
-            }
-        }
+        let mut asset_residency = ResidencyManager::new(RESIDENCY_BUDGET_BYTES);
+        asset_residency.make_resident(ResidentAsset::DiffuseArray, diffuse_texture.size_in_bytes());
 
         let camera = Camera {
             eye: (0.0, 1.0, 2.0).into(),
@@ -124,270 +447,5997 @@ impl<'a> State<'a> {
             aspect: config.width as f32 / config.height as f32,
             fovy: 45.0,
             znear: 0.1,
-            zfar: 100.0
+            zfar: 100.0,
+            reverse_z: options.reverse_z
         };
 
-        let camera_controller = CameraController::new(0.2);
+        let camera_controller = CameraController::new(options.camera_speed);
+        let mut input_map = InputMap::new(options.input_bindings.as_deref());
+        input_map.bind_all(&options.bindings, "the settings");
+        let gamepads = window.filter(|_| options.gamepad).and_then(|_| Gamepads::new());
+        let camera_bookmarks = CameraBookmarks::new(options.camera_bookmarks.as_deref());
 
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
 
-        let camera_buffer = device.create_buffer_init(
-            &BufferInitDescriptor {
-                label: Some("Camera Buffer"),
-                contents: bytemuck::cast_slice(&[camera_uniform]),
-                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
-            }
-        );
-
-        let camera_bind_group_layout = device.create_bind_group_layout(
-            &BindGroupLayoutDescriptor {
-                label: Some("Camera Bind Group Layout"),
-                entries: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::VERTEX,
-                        ty: BindingType::Buffer {
-                            ty: BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None
-                        },
-                        count: None
-                    }
-                ]
-            }
-        );
+        let camera_bind_group_layout = Self::get_camera_bind_group_layout(&device);
+        let (camera_buffer, camera_bind_group) = Self::create_camera_binding(&device,
+            &camera_bind_group_layout, &camera_uniform);
+        let scene_cameras = vec![SceneCamera::new(&device, MAIN_CAMERA_LABEL, CameraKind::Free,
+            camera.clone(), &camera_bind_group_layout)];
 
-        let camera_bind_group = device.create_bind_group(
-            &BindGroupDescriptor {
-                label: Some("Camera Bind Group"),
-                layout: &camera_bind_group_layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: camera_buffer.as_entire_binding()
-                    }
-                ]
-            }
-        );
+        let vertex_animation_uniform = VertexAnimationUniform::new();
+        let vertex_animation_bind_group_layout = Self::get_vertex_animation_bind_group_layout(&device);
+        let (vertex_animation_buffer, vertex_animation_bind_group) =
+            Self::create_vertex_animation_binding(&device, &vertex_animation_bind_group_layout,
+                &vertex_animation_uniform);
+        let instance_bind_group_layout = InstanceBuffer::get_bind_group_layout(&device,
+            INSTANCES_LABEL, InstanceStorage::for_device(&device));
 
-        let render_pipeline = PipelineBuilder::builder()
-            .set_shader_module(shader_name, "vs_main", "fs_main")
-            .set_pixel_format(config.format)
-            .build(&device, &[&texture_bind_group_layout, &camera_bind_group_layout]);
+        let mut shader_registry = ShaderRegistry::new(options.shader_dir.clone());
+        if adapter.get_info().backend == Backend::Gl {
+            shader_registry.set_define("BACKEND_GL", "1");
+        }
+        if options.reverse_z {
+            shader_registry.set_define(REVERSE_Z_DEFINE, "1");
+        }
+        if options.camera_relative {
+            shader_registry.set_define("CAMERA_RELATIVE", "1");
+        }
+        let render_pass_config = RenderPassConfig::for_depth(options.reverse_z);
+        let camera_relative = options.camera_relative;
+        let upscaling = options.upscaling;
+        let mut pipeline_cache = PipelineCache::default();
+        // Single sampled to start with, set_anti_aliasing rebuilds them for MSAA.
+        let render_pipeline = Self::create_render_pipeline(&mut pipeline_cache, &device,
+            &shader_registry, &config, 1, &[&texture_bind_group_layout, &camera_bind_group_layout,
+                &vertex_animation_bind_group_layout, &instance_bind_group_layout])?;
+        crash_reporter.register_pipeline(&DebugLabels::new(INSTANCE_PIPELINE_LABEL).pipeline(),
+            ShaderHandle::Vertex.filename());
+        let transparent_pipeline = Self::create_transparent_pipeline(&mut pipeline_cache, &device,
+            &shader_registry, &config, 1, &[&texture_bind_group_layout, &camera_bind_group_layout,
+                &vertex_animation_bind_group_layout, &instance_bind_group_layout])?;
+        crash_reporter.register_pipeline(&DebugLabels::new(TRANSPARENT_PIPELINE_LABEL).pipeline(),
+            ShaderHandle::Vertex.filename());
+        let billboard_buffer = BillboardBuffer::new(&device, BILLBOARD_PIPELINE_LABEL);
+        let billboard_pipeline = Self::create_billboard_pipeline(&mut pipeline_cache, &device,
+            &shader_registry, &config, 1, &[&texture_bind_group_layout, &camera_bind_group_layout,
+                billboard_buffer.bind_group_layout()])?;
+        crash_reporter.register_pipeline(&DebugLabels::new(BILLBOARD_PIPELINE_LABEL).pipeline(),
+            ShaderHandle::Billboard.filename());
 
-        let (vertex_buffer, index_buffer, num_indices) = Self::create_buffers(&device);
+        let mesh_lods = MeshLods::generate(&VERTICES.iter().map(|vertex| vertex.position)
+            .collect::<Vec<_>>(), INDICES, MAX_LOD_LEVELS);
+        let mut gpu_allocator = GpuAllocator::new(GPU_ALLOCATOR_LABEL, DEFAULT_BLOCK_SIZE);
+        let mut assets = Assets::default();
+        let instance_mesh = assets.meshes.insert(None, Mesh::new(&device, &queue,
+            &mut gpu_allocator, VERTICES, &mesh_lods.indices));
+        let num_indices = mesh_lods.level(0).indices.len() as u32;
 
-        let instances = (0..NUM_INSTANCES_PER_ROW).flat_map(|z| {
-            (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-                let position = Vector3 { x: x as f32, y: 0.0, z: z as f32 } - INSTANCE_DISPLACEMENT;
+        let instances = Self::create_instance_grid(options.stress_instances
+            .unwrap_or(NUM_INSTANCES_PER_ROW * NUM_INSTANCES_PER_ROW));
+        let stress_report_at = options.stress_instances
+            .map(|_| Instant::now() + STRESS_REPORT_INTERVAL);
+        let (instance_buffer, transparent_instance_buffer) = Self::create_instance_buffers(&device,
+            &instance_bind_group_layout);
 
-                let rotation = if position.is_zero() {
-                    Quaternion::from_axis_angle(Vector3::unit_z(), Deg(0.0))
-                } else {
-                    Quaternion::from_axis_angle(position.normalize(), Deg(45.0))
-                };
+        let depth_texture = Texture::create_depth_texture(&device, &config, Texture::DEPTH_FORMAT, 1,
+            "Depth Texture");
+        let post_process = PostProcess::new(&device, POST_PROCESS_LABEL, &mut samplers);
+        let decal_buffer = DecalBuffer::new(&device, DECAL_PIPELINE_LABEL);
 
-                Instance {
-                    position,
-                    rotation
-                }
-            })
-        }).collect::<Vec<_>>();
-        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-        let instance_buffer = device.create_buffer_init(
-            &BufferInitDescriptor {
-                label: Some("Instance Buffer"),
-                contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX
-            }
+        let pick_mesh = PickMesh::new(
+            VERTICES.iter().map(|vertex| vertex.position.into()).collect(),
+            INDICES
         );
 
-        let depth_texture = Texture::create_depth_texture(&device, &config, "Depth Texture");
+        let input_tracer = InputTracer::new(options.trace_input);
+        let settings = Settings::from_options(&options);
+        let settings_watcher = options.settings.as_deref().map(SettingsWatcher::new);
 
-        Self {
-            surface,
+        let mut state = Self {
+            instance,
+            options,
+            frame_output,
             device,
             queue,
+            capabilities,
+            render_config: config.clone(),
+            render_scale: 1.0,
+            upscaling,
             config,
             size,
             window,
+            scale_factor: window.map_or(1.0, |window| window.scale_factor()),
+            shader_registry,
+            pipeline_cache,
             render_pipeline,
-            vertex_buffer,
-            index_buffer,
+            transparent_pipeline,
+            debug_view: DebugView::default(),
+            debug_pipeline: None,
+            gpu_allocator,
+            assets,
+            instance_mesh,
             num_indices,
+            mesh_lods,
+            lod_error_threshold: DEFAULT_LOD_ERROR_PIXELS,
+            lod_fade_band: DEFAULT_LOD_FADE_BAND,
+            texture_bind_group_layout,
+            samplers,
+            texture_sampler,
             diffuse_texture,
-            diffuse_bind_group,
+            asset_residency,
+            texture_streamer: TextureStreamer::new(DEFAULT_UPLOAD_BUDGET_BYTES),
             camera,
             camera_controller,
+            camera_transition: None,
+            camera_rig: None,
+            camera_rig_enabled: false,
+            follow_camera: None,
+            input_map,
+            gamepads,
+            touch_gestures: TouchGestures::default(),
+            startup_settings: settings.clone(),
+            settings,
+            settings_watcher,
+            camera_bookmarks,
             camera_uniform,
+            camera_jitter: false,
+            jitter_index: 0,
+            camera_relative,
             camera_buffer,
+            camera_bind_group_layout,
             camera_bind_group,
+            main_viewport: ViewportRect::FULL,
+            viewports: Vec::new(),
+            scene_cameras,
+            active_camera: 0,
+            vertex_animation_uniform,
+            vertex_animation_buffer,
+            vertex_animation_bind_group_layout,
+            vertex_animation_bind_group,
+            instance_bind_group_layout,
             instances,
             instance_buffer,
-            depth_texture
+            instance_lod_draws: Vec::new(),
+            gpu_culling: None,
+            cull_pipeline: None,
+            draw_queue_stats: Cell::new(DrawQueueStats::default()),
+            stress_report_at,
+            screenshot_requested: false,
+            recorder: None,
+            material_layouts: HashMap::new(),
+            material_pipelines: HashMap::new(),
+            skinned_material: None,
+            transparent_instances: Vec::new(),
+            transparent_instance_buffer,
+            transparent_instance_batches: Vec::new(),
+            billboards: Vec::new(),
+            billboard_buffer,
+            billboard_pipeline,
+            soft_billboard_distance: DEFAULT_SOFT_DISTANCE,
+            skinned_model: None,
+            skinned_mesh: None,
+            skinned_pipeline: None,
+            terrain: None,
+            terrain_mesh: None,
+            terrain_pipeline: None,
+            vegetation: None,
+            vegetation_mesh: None,
+            vegetation_pipeline: None,
+            water: None,
+            water_pipeline: None,
+            decals: Vec::new(),
+            decal_buffer,
+            decal_draws: Vec::new(),
+            decal_pipelines: HashMap::new(),
+            show_bounds: false,
+            debug_lines: DebugLines::new(DEBUG_LINES_PIPELINE_LABEL),
+            debug_lines_pipeline: None,
+            anti_aliasing: AntiAliasing::None,
+            sample_count: 1,
+            msaa: None,
+            depth_resolve_pipeline: None,
+            viewport_composite_pipeline: None,
+            taa: None,
+            taa_resolve_pipeline: None,
+            blit_pipeline: None,
+            upscale_pipeline: None,
+            motion_vector_pipelines: HashMap::new(),
+            post_process,
+            post_effects: Vec::new(),
+            post_effect_pipelines: HashMap::new(),
+            ssao: None,
+            ssao_occlusion_pipeline: None,
+            ssao_blur_pipeline: None,
+            fog: None,
+            depth_of_field: None,
+            motion_blur: None,
+            glow: None,
+            color_grading: None,
+            edge_detection: None,
+            texture_generator: None,
+            outline: None,
+            outlined_instances: Vec::new(),
+            animator: Animator::new(),
+            custom_events: Vec::new(),
+            depth_texture,
+            transient_textures: TransientTexturePool::default(),
+            gpu_profiler,
+            submit_batch: SubmitBatch::default(),
+            pick_mesh,
+            cursor_position: PhysicalPosition::new(0.0, 0.0),
+            modifiers: ModifiersState::default(),
+            selected_instance: None,
+            gizmo: Gizmo::default(),
+            transform_edits: Vec::new(),
+            placement_options: PlacementOptions::default(),
+            render_pass_config,
+            crash_reporter,
+            input_tracer,
+            scheduler: Self::default_scheduler(),
+            frame_profiler: FrameProfiler::default(),
+            is_shut_down: false,
+            minimized: false,
+            occluded: false,
+            paused: false
+        };
+
+        if let (Some(path), Some(model)) = (skinned_model_path, skinned_model) {
+            let model = model.map_err(|source| RendererError::Model { path: path.clone(), source });
+            if let Err(e) = model.and_then(|model| state.set_skinned_model(&path, model)) {
+                log::error!("{e}");
+            }
+        }
+        if state.options.terrain {
+            if let Err(e) = state.generate_terrain(&TerrainOptions::default()) {
+                log::error!("{e}");
+            }
+        }
+        if state.options.vegetation {
+            if let Err(e) = state.set_vegetation(Some(VegetationOptions::default())) {
+                log::error!("{e}");
+            }
+        }
+        if state.options.water {
+            if let Err(e) = state.enable_water(&WaterOptions::default()) {
+                log::error!("{e}");
+            }
+        }
+        if let Err(e) = state.set_anti_aliasing(state.options.anti_aliasing) {
+            log::error!("Couldn't set up {}: {e}", state.options.anti_aliasing.label());
+        }
+        if let Err(e) = state.set_gpu_culling(state.options.gpu_culling) {
+            log::error!("Couldn't set up {CULL_PIPELINE_LABEL}: {e}");
+        }
+        if let Err(e) = state.set_ssao(state.options.ssao) {
+            log::error!("Couldn't set up {SSAO_LABEL}: {e}");
+        }
+        if let Err(e) = state.set_fog(state.options.fog) {
+            log::error!("Couldn't set up {FOG_LABEL}: {e}");
+        }
+        if let Err(e) = state.set_depth_of_field(state.options.depth_of_field) {
+            log::error!("Couldn't set up {DEPTH_OF_FIELD_LABEL}: {e}");
+        }
+        if let Err(e) = state.set_motion_blur(state.options.motion_blur) {
+            log::error!("Couldn't set up {MOTION_BLUR_LABEL}: {e}");
+        }
+        if let Err(e) = state.set_glow(state.options.glow) {
+            log::error!("Couldn't set up {GLOW_LABEL}: {e}");
+        }
+        if let Err(e) = state.set_color_grading(state.options.color_grading) {
+            log::error!("Couldn't set up {COLOR_GRADING_LABEL}: {e}");
+        }
+        if let Err(e) = state.set_edge_detection(state.options.edge_detection) {
+            log::error!("Couldn't set up {EDGE_DETECTION_LABEL}: {e}");
         }
+        if let Err(e) = state.set_outline(state.options.selection_outline) {
+            log::error!("Couldn't set up {OUTLINE_LABEL}: {e}");
+        }
+        if let Err(e) = state.set_render_scale(state.options.render_scale) {
+            log::error!("Couldn't set up the render scale: {e}");
+        }
+        if let Some(path) = state.options.color_grading_lut.clone() {
+            if let Err(e) = state.load_color_grading_lut(&path) {
+                log::error!("{e}");
+            }
+        }
+
+        Ok(state)
     }
 
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>)
+    // Waits for in-flight GPU work, saves the settings changed while running and releases
+    // GPU resources before the window goes away. Camera bookmarks are saved as they're
+    // set. Rendering and updates are no-ops afterwards.
+    pub fn shutdown(&mut self)
     {
-        if new_size.width < 1 && new_size.height < 1 { return };
+        if self.is_shut_down {
+            return;
+        }
+        self.is_shut_down = true;
 
-        self.size = new_size;
-        self.config.width = new_size.width;
-        self.config.height = new_size.height;
-        self.depth_texture = Texture::create_depth_texture(&self.device, &self.config,
-            "Depth Texture");
-        self.surface.configure(&self.device, &self.config);
+        log::info!("Shutting down renderer");
+        self.stop_recording();
+        self.persist_settings();
+        self.device.poll(Maintain::Wait);
+
+        self.scheduler = Scheduler::default();
+        self.transient_textures.clear();
+        self.diffuse_texture.evict();
+        self.assets.evict_textures();
+        self.taa = None;
+        self.ssao = None;
+        self.depth_texture.texture.destroy();
+
+        self.device.poll(Maintain::Wait);
     }
 
-    pub fn render(&mut self) -> Result<(), SurfaceError>
+    pub fn is_shut_down(&self) -> bool
     {
-        let drawable = self.surface.get_current_texture()?;
-        let image_view = drawable.texture.create_view(&Self::get_image_descriptor());
-        let mut command_encoder = self.device
-            .create_command_encoder(&Self::get_command_encoder_descriptor());
+        self.is_shut_down
+    }
 
-        let color_attachment = RenderPassColorAttachment {
-            view: &image_view,
-            resolve_target: None,
-            ops: Operations {
-                load: LoadOp::Clear(
-                    Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0
-                    }
-                ),
-                store: StoreOp::Store
+    // The settings as they are now, e.g. after anti-aliasing was cycled with its key or
+    // the window resized.
+    pub fn current_settings(&self) -> Settings
+    {
+        let mut settings = self.settings.clone();
+        if let Some(window) = self.window {
+            let fullscreen = window.fullscreen().is_some();
+            settings.window.fullscreen = Some(fullscreen);
+            if !fullscreen {
+                let size: LogicalSize<u32> = window.inner_size().to_logical(window.scale_factor());
+                settings.window.width = Some(size.width);
+                settings.window.height = Some(size.height);
             }
+        }
+        settings.render = RenderSettings {
+            vsync: Some(self.options.surface.vsync),
+            anti_aliasing: Some(self.anti_aliasing),
+            render_scale: Some(self.render_scale),
+            upscaling: Some(self.upscaling)
         };
+        settings.camera.speed = Some(self.camera_controller.speed());
 
-        {
-            let mut render_pass = command_encoder.begin_render_pass(
-                &RenderPassDescriptor {
-                    label: Some("Render Pass"),
-                    color_attachments: &[Some(color_attachment)],
-                    depth_stencil_attachment: Some(
-                        RenderPassDepthStencilAttachment {
-                            view: &self.depth_texture.view,
-                            depth_ops: Some(
-                                Operations {
-                                    load: LoadOp::Clear(1.0),
-                                    store: StoreOp::Store
-                                }
-                            ),
-                            stencil_ops: None
-                        }
-                    ),
-                    occlusion_query_set: None,
-                    timestamp_writes: None
-                }
-            );
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
+        settings
+    }
+
+    // Lays what changed while running over the settings file so the next run starts
+    // with it. Values only the options or the environment set stay out of the file.
+    fn persist_settings(&self)
+    {
+        let Some(path) = self.options.settings.as_deref() else {
+            return;
+        };
+        let changes = self.current_settings().changes_from(&self.settings);
+        if changes == Settings::default() {
+            return;
         }
-        
-        self.queue.submit(once(command_encoder.finish()));
 
-        drawable.present();
+        log::info!("Saving the changed settings to {}", path.display());
+        Settings::load(path).unwrap_or_default().merged(&changes).save(path);
+    }
 
-        Ok(())
+    // Nothing of the window can be seen, see is_paused.
+    pub fn set_occluded(&mut self, occluded: bool)
+    {
+        if self.occluded && !occluded && !self.minimized {
+            self.scheduler.reset_clock();
+        }
+        self.occluded = occluded;
     }
 
-    pub fn input(&mut self, event: &WindowEvent) -> bool
+    // Stops updating and rendering like an occluded window does, until unpaused.
+    pub fn set_paused(&mut self, paused: bool)
     {
-        self.camera_controller.process_events(event)
+        let was_paused = self.is_paused();
+        self.paused = paused;
+        if was_paused && !self.is_paused() {
+            self.scheduler.reset_clock();
+        }
     }
 
-    pub fn update(&mut self)
+    // While minimized, occluded or paused update and render do nothing and redraws
+    // shouldn't be requested. Time doesn't pass for the scheduler's systems either.
+    pub fn is_paused(&self) -> bool
     {
-        self.camera_controller.update_camera(&mut self.camera);
-        self.camera_uniform.update_view_proj(&self.camera);
-        self.queue.write_buffer(&self.camera_buffer, 0, cast_slice(&[self.camera_uniform]));
+        self.minimized || self.occluded || self.paused
     }
 
-    // new function
-    fn get_instance_descriptor() -> InstanceDescriptor
+    pub fn is_device_lost(&self) -> bool
     {
-        InstanceDescriptor {
-            backends: Backends::all(),
-            ..Default::default()
+        self.crash_reporter.is_device_lost()
+    }
+
+    // What the current adapter and device support, logged when the device is created.
+    pub fn capabilities(&self) -> &GpuCapabilities
+    {
+        &self.capabilities
+    }
+
+    // Reads buffers and textures of the current device back to the CPU.
+    pub fn readback(&self) -> GpuReadback<'_>
+    {
+        GpuReadback::new(&self.device, &self.queue)
+    }
+
+    // Rebuilds the device, surface and every GPU resource from the CPU-side state
+    // (camera, instances, decoded images), e.g. after a driver reset or adapter removal.
+    #[tracing::instrument(skip_all)]
+    pub async fn recover_device(&mut self) -> Result<(), RendererError>
+    {
+        log::warn!("Recreating the GPU device and resources");
+
+        let surface = match self.window {
+            Some(window) => Some(self.instance.create_surface(window)?),
+            None => None
+        };
+        let adapter = Self::select_adapter(&self.instance, surface.as_ref(), &self.options).await?;
+        let (device, queue) = adapter.request_device(&Self::get_device_descriptor(&adapter), None)
+            .await?;
+        self.crash_reporter.install(&device);
+        self.gpu_profiler = GpuProfiler::new(&device, &queue);
+
+        self.config = Self::get_configuration(surface.as_ref(), &adapter, &self.size,
+            &self.options.surface);
+        self.capabilities = GpuCapabilities::new(&adapter, &device, surface.as_ref(), &self.config);
+        self.capabilities.log();
+        self.frame_output = Self::create_frame_output(&device, &self.config, surface);
+        self.render_config = scaled_config(&self.config, self.render_scale);
+
+        self.texture_bind_group_layout = Texture::get_texture_array_bind_group_layout(&device);
+        self.samplers = SamplerCache::new(Self::max_anisotropy(&adapter));
+        self.texture_sampler = self.samplers.get(&device, SamplerSpec::default());
+        self.diffuse_texture.evict();
+        self.diffuse_texture.make_resident(&device, &queue, &self.texture_bind_group_layout,
+            &self.texture_sampler)?;
+
+        self.camera_bind_group_layout = Self::get_camera_bind_group_layout(&device);
+        (self.camera_buffer, self.camera_bind_group) = Self::create_camera_binding(&device,
+            &self.camera_bind_group_layout, &self.camera_uniform);
+        self.vertex_animation_bind_group_layout = Self::get_vertex_animation_bind_group_layout(&device);
+        (self.vertex_animation_buffer, self.vertex_animation_bind_group) =
+            Self::create_vertex_animation_binding(&device, &self.vertex_animation_bind_group_layout,
+                &self.vertex_animation_uniform);
+        self.instance_bind_group_layout = InstanceBuffer::get_bind_group_layout(&device,
+            INSTANCES_LABEL, InstanceStorage::for_device(&device));
+        self.pipeline_cache.clear();
+        self.debug_pipeline = None;
+        self.material_layouts.clear();
+        for features in self.material_pipelines.keys() {
+            self.unregister_pipeline(&format!("{MATERIAL_PIPELINE_LABEL} {}", features.name()));
+        }
+        for features in self.decal_pipelines.keys() {
+            self.unregister_pipeline(&format!("{DECAL_PIPELINE_LABEL} {}", features.name()));
+        }
+        self.material_pipelines.clear();
+        self.decal_buffer = DecalBuffer::new(&device, DECAL_PIPELINE_LABEL);
+        self.decal_pipelines.clear();
+        let bind_group_layouts = [&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+            &self.vertex_animation_bind_group_layout, &self.instance_bind_group_layout];
+        self.render_pipeline = Self::create_render_pipeline(&mut self.pipeline_cache, &device,
+            &self.shader_registry, &self.config, self.sample_count, &bind_group_layouts)?;
+        self.transparent_pipeline = Self::create_transparent_pipeline(&mut self.pipeline_cache,
+            &device, &self.shader_registry, &self.config, self.sample_count, &bind_group_layouts)?;
+        self.billboard_buffer = BillboardBuffer::new(&device, BILLBOARD_PIPELINE_LABEL);
+        self.billboard_pipeline = Self::create_billboard_pipeline(&mut self.pipeline_cache,
+            &device, &self.shader_registry, &self.config, self.sample_count,
+            &[&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+                self.billboard_buffer.bind_group_layout()])?;
+
+        self.gpu_allocator.clear();
+        self.assets.recover(&device, &queue, &mut self.gpu_allocator,
+            &self.texture_bind_group_layout, &self.texture_sampler)?;
+        for render_target in self.assets.render_targets.iter_mut() {
+            render_target.recreate(&device, self.config.format, &self.camera_bind_group_layout);
+        }
+        self.texture_generator = None;
+        if !self.assets.procedural_textures.is_empty() {
+            let texture_generator = TextureGenerator::new(&device, &self.shader_registry,
+                TEXTURE_GENERATOR_LABEL)?;
+            for procedural_texture in self.assets.procedural_textures.iter_mut() {
+                procedural_texture.recreate(&device, &queue, &texture_generator)?;
+            }
+            self.texture_generator = Some(texture_generator);
+        }
+        for viewport in &mut self.viewports {
+            viewport.recreate(&device, &self.camera_bind_group_layout);
+        }
+        for scene_camera in &mut self.scene_cameras {
+            scene_camera.recreate(&device, &self.camera_bind_group_layout);
+        }
+        (self.instance_buffer, self.transparent_instance_buffer) = Self::create_instance_buffers(
+            &device, &self.instance_bind_group_layout);
+        self.skinned_mesh = None;
+        self.skinned_pipeline = None;
+        if let Some(model) = &self.skinned_model {
+            let skinned_mesh = Self::create_skinned_mesh(&device, &queue, &mut self.gpu_allocator,
+                model);
+            self.skinned_pipeline = Some(Self::create_skinned_pipeline(&mut self.pipeline_cache,
+                &device, &self.shader_registry, &self.config, self.sample_count,
+                &[&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+                    &self.vertex_animation_bind_group_layout, skinned_mesh.joint_bind_group_layout()])?);
+            self.skinned_mesh = Some(skinned_mesh);
+        }
+        self.terrain_mesh = None;
+        self.terrain_pipeline = None;
+        self.vegetation_mesh = None;
+        self.vegetation_pipeline = None;
+        if let Some(heightmap) = &self.terrain {
+            self.terrain_pipeline = Some(Self::create_terrain_pipeline(&mut self.pipeline_cache,
+                &device, &self.shader_registry, &self.config, self.sample_count,
+                &[&self.camera_bind_group_layout])?);
+            self.terrain_mesh = Some(TerrainMesh::new(&device, &queue, &mut self.gpu_allocator,
+                TERRAIN_PIPELINE_LABEL, heightmap));
+        }
+        self.water_pipeline = None;
+        if let Some(water_options) = self.water.take().map(|water| *water.options()) {
+            let water = Water::new(&device, &queue, WATER_PIPELINE_LABEL, &water_options,
+                &self.camera_bind_group_layout, &mut self.samplers);
+            self.water_pipeline = Some(Self::create_water_pipeline(&mut self.pipeline_cache,
+                &device, &self.shader_registry, &self.config, self.sample_count,
+                &[&self.camera_bind_group_layout, water.bind_group_layout()])?);
+            self.water = Some(water);
+        }
+        self.debug_lines.release();
+        self.debug_lines_pipeline = None;
+        if self.show_bounds || self.selected_instance.is_some() {
+            self.debug_lines_pipeline = Some(Self::create_debug_lines_pipeline(
+                &mut self.pipeline_cache, &device, &self.shader_registry, &self.config,
+                self.sample_count, &[&self.camera_bind_group_layout])?);
+        } else {
+            self.unregister_pipeline(DEBUG_LINES_PIPELINE_LABEL);
+        }
+        self.depth_texture = Texture::create_depth_texture(&device, &self.render_config,
+            Texture::DEPTH_FORMAT, 1, "Depth Texture");
+        self.transient_textures.clear();
+        self.upscale_pipeline = None;
+        if self.render_scale != 1.0 {
+            self.upscale_pipeline = Some(Self::create_upscale_pipeline(&mut self.pipeline_cache,
+                &device, &self.shader_registry, &self.config, self.upscaling,
+                &[self.post_process.bind_group_layout()])?);
+        }
+
+        self.device = device;
+        self.queue = queue;
+        self.rebuild_vegetation()?;
+        let gpu_culling = self.gpu_culling.take().is_some();
+        self.cull_pipeline = None;
+        self.set_gpu_culling(gpu_culling)?;
+
+        self.post_process = PostProcess::new(&self.device, POST_PROCESS_LABEL, &mut self.samplers);
+        if let Some(options) = self.ssao.as_ref().map(Ssao::options) {
+            self.ssao = Some(Ssao::new(&self.device, SSAO_LABEL, options));
+            self.create_ssao_pipelines()?;
         }
+        if let Some(options) = self.fog.as_ref().map(Fog::options) {
+            self.fog = Some(Fog::new(&self.device, FOG_LABEL, options));
+        }
+        if let Some(options) = self.depth_of_field.as_ref().map(DepthOfField::options) {
+            self.depth_of_field = Some(DepthOfField::new(&self.device, DEPTH_OF_FIELD_LABEL, options));
+        }
+        if let Some(options) = self.motion_blur.as_ref().map(MotionBlur::options) {
+            self.motion_blur = Some(MotionBlur::new(&self.device, MOTION_BLUR_LABEL, options));
+        }
+        if let Some(options) = self.glow.as_ref().map(Glow::options) {
+            self.glow = Some(Glow::new(&self.device, GLOW_LABEL, options));
+        }
+        if let Some(color_grading) = self.color_grading.take() {
+            let mut recreated = ColorGrading::new(&self.device, &self.queue, COLOR_GRADING_LABEL,
+                color_grading.options(), &mut self.samplers);
+            recreated.set_lut(&self.device, &self.queue, color_grading.lut().cloned());
+            self.color_grading = Some(recreated);
+        }
+        self.post_effect_pipelines.clear();
+        for effect in self.post_effects.clone() {
+            let pipeline = self.build_post_effect_pipeline(effect)?;
+            self.post_effect_pipelines.insert(effect, pipeline);
+        }
+        if self.taa.is_some() {
+            self.taa = Some(Taa::new(&self.device, TAA_LABEL, &self.render_config, &mut self.samplers));
+            self.create_taa_pipelines()?;
+        }
+        if let Some(options) = self.edge_detection.take().as_ref().map(EdgeDetection::options) {
+            self.set_edge_detection(Some(options))?;
+        }
+        if let Some(options) = self.outline.take().as_ref().map(Outline::options) {
+            self.set_outline(Some(options))?;
+        }
+        self.create_motion_vector_pipelines()?;
+        if self.msaa.is_some() {
+            self.msaa = Some(Msaa::new(&self.device, MSAA_LABEL));
+            self.create_msaa_pipelines()?;
+        }
+
+        self.set_debug_view(self.debug_view)
     }
 
-    fn get_adapter_descriptor<'b>(surface: &'b Surface<'a>) -> RequestAdapterOptions<'b, 'a>
+    #[tracing::instrument(skip_all, fields(width = new_size.width, height = new_size.height))]
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>)
     {
-        RequestAdapterOptions {
-            power_preference: PowerPreference::HighPerformance,
-            compatible_surface: Some(surface),
-            force_fallback_adapter: false
+        let was_paused = self.is_paused();
+        self.minimized = new_size.width < 1 || new_size.height < 1;
+        if self.minimized {
+            return;
+        }
+        if was_paused && !self.is_paused() {
+            self.scheduler.reset_clock();
+        }
+
+        self.size = new_size;
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        self.resize_render_targets();
+        if let Some(outline) = &mut self.outline {
+            outline.resize(&self.device, &self.config);
+        }
+        match &mut self.frame_output {
+            FrameOutput::Surface(surface) => surface.configure(&self.device, &self.config),
+            FrameOutput::Texture(texture) => *texture = Self::create_headless_texture(&self.device,
+                &self.config)
         }
     }
 
-    fn get_device_descriptor() -> DeviceDescriptor<'a>
+    // Borderless on the monitor the window is on. The resize follows as a window event,
+    // headless there's nothing to do.
+    pub fn set_fullscreen(&self, fullscreen: bool)
     {
-        DeviceDescriptor {
-            required_features: Features::empty(),
-            required_limits: if cfg!(target_arch = "wasm32") {
-                Limits::downlevel_webgl2_defaults()
-            } else {
-                Limits::default()
-            },
-            label: Some("Device")
+        if let Some(window) = self.window {
+            window.set_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)));
         }
     }
 
-    fn get_surface_configuration(
-        surface: &Surface,
-        adapter: &Adapter,
-        size: &PhysicalSize<u32>
-    ) -> SurfaceConfiguration
+    pub fn set_vsync(&mut self, vsync: bool)
     {
-        let surface_capabilities = surface.get_capabilities(adapter);
-        let surface_format = surface_capabilities.formats.iter()
-            .copied()
-            .filter(|f| f.is_srgb())
-            .next()
-            .unwrap_or(surface_capabilities.formats[0]);
+        self.options.surface.vsync = vsync;
+        self.config.present_mode = self.options.surface.present_mode();
+        if let FrameOutput::Surface(surface) = &self.frame_output {
+            surface.configure(&self.device, &self.config);
+        }
+    }
 
-        SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_capabilities.present_modes[0],
-            alpha_mode: surface_capabilities.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2
+    pub fn is_fullscreen(&self) -> bool
+    {
+        self.window.is_some_and(|window| window.fullscreen().is_some())
+    }
+
+    // None lets the window shrink as far as the platform allows.
+    pub fn set_min_window_size(&self, size: Option<LogicalSize<u32>>)
+    {
+        if let Some(window) = self.window {
+            window.set_min_inner_size(size);
         }
     }
 
-    fn create_buffers(device: &Device) -> (Buffer, Buffer, u32)
+    // E.g. after the window moved to a monitor with a different DPI. The surface follows
+    // the window's new size right away, as not every platform sends a resize after.
+    pub fn set_scale_factor(&mut self, scale_factor: f64)
     {
-        let vertex_buffer = device.create_buffer_init(
-            &BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: BufferUsages::VERTEX
-            }
-        );
-        let index_buffer = device.create_buffer_init(
-            &BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: BufferUsages::INDEX
+        self.scale_factor = scale_factor;
+        if let Some(size) = self.window.map(Window::inner_size).filter(|size| *size != self.size) {
+            self.resize(size);
+        }
+    }
+
+    pub fn scale_factor(&self) -> f64
+    {
+        self.scale_factor
+    }
+
+    // What the last render drew, only when running headless.
+    pub async fn read_frame(&self) -> Result<RgbaImage, RendererError>
+    {
+        match &self.frame_output {
+            FrameOutput::Texture(texture) => self.readback().read_image(texture).await,
+            FrameOutput::Surface(_) => Err(RendererError::NotHeadless)
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn render(&mut self) -> Result<(), SurfaceError>
+    {
+        if self.is_shut_down || self.is_paused() {
+            return Ok(());
+        }
+
+        self.crash_reporter.begin_frame();
+        self.transient_textures.begin_frame();
+        self.submit_batch.begin_frame();
+        if let Some(gpu_profiler) = &mut self.gpu_profiler {
+            gpu_profiler.begin_frame();
+        }
+
+        let (drawable, image_view) = {
+            let _timer = self.frame_profiler.scope("acquire");
+            match &self.frame_output {
+                FrameOutput::Surface(surface) => {
+                    let drawable = surface.get_current_texture()?;
+                    let image_view = drawable.texture.create_view(&Self::get_image_descriptor());
+                    (Some(drawable), image_view)
+                },
+                FrameOutput::Texture(texture) => (None, texture.create_view(&Self::get_image_descriptor()))
             }
-        );
-        let num_indices = INDICES.len() as u32;
+        };
+        let encode_timer = self.frame_profiler.scope("encode");
+        let error_scope = ErrorScope::push(&self.device, "Frame");
+        self.upload_instances_by_lod();
+        self.upload_skinned_emissive();
+        self.touch_drawn_assets();
+        self.prepare_materials();
+        self.prepare_decals();
+        self.upload_transparent_instances();
+        self.upload_outlined_instances();
+        self.upload_billboards();
+        self.upload_debug_lines();
+        let mut command_encoder = self.device
+            .create_command_encoder(&Self::get_command_encoder_descriptor());
+
+        self.encode_cull_pass(&mut command_encoder);
+        self.encode_render_target_passes(&mut command_encoder);
+        let water_targets = self.encode_water_passes(&mut command_encoder);
+        let water_bind_group = water_targets.as_ref()
+            .zip(self.water.as_ref())
+            .map(|((reflection, refraction), water)| water.create_bind_group(&self.device,
+                &reflection.view, &refraction.view));
+
+        // With TAA, edge detection, post effects or another render scale the main pass
+        // renders offscreen, and the last of them writes the surface. TAA and motion blur both need motion vectors.
+        let taa_ready = self.taa.is_some() && self.taa_resolve_pipeline.is_some()
+            && self.blit_pipeline.is_some();
+        let edge_detection_ready = self.edge_detection.is_some() && self.blit_pipeline.is_some();
+        let offscreen = taa_ready || edge_detection_ready || self.upscale_pipeline.is_some()
+            || self.post_effects.iter().any(|effect| self.post_effect_pipelines.contains_key(effect));
+        let motion_ready = (taa_ready || self.motion_blur.is_some())
+            && !self.motion_vector_pipelines.is_empty();
+        let scene_desc = self.post_process.target_desc(&self.render_config);
+        let scene_target = offscreen.then(|| self.transient_textures.acquire(&self.device,
+            &scene_desc, "Scene Color Texture"));
+        let motion_target = motion_ready.then(|| self.transient_textures.acquire(&self.device,
+            &Taa::motion_target_desc(&self.render_config), "Motion Vector Texture"));
+        // With MSAA the scene passes draw into these and resolve into the targets above.
+        let msaa_targets = self.msaa.is_some().then(|| {
+            let (width, height) = (self.render_config.width, self.render_config.height);
+            (
+                self.transient_textures.acquire(&self.device,
+                    &Msaa::target_desc(width, height, self.render_config.format),
+                    "MSAA Color Texture"),
+                self.transient_textures.acquire(&self.device,
+                    &Msaa::target_desc(width, height, Texture::DEPTH_FORMAT),
+                    "MSAA Depth Texture")
+            )
+        });
+        let msaa_color = msaa_targets.as_ref().map(|(color, _)| &color.view);
+        let msaa_depth = msaa_targets.as_ref().map(|(_, depth)| &depth.view);
+        let decals_ready = !self.decal_draws.is_empty() && self.debug_pipeline.is_none();
+        // Billboards read the depth while the overlay pass has it attached again, so
+        // without MSAA's resolved one they read a copy. Where there's neither they're
+        // drawn hard.
+        let soft_billboards = self.billboard_buffer.num_billboards() > 0
+            && self.debug_pipeline.is_none() && self.soft_billboard_distance > 0.0
+            && (msaa_depth.is_some() || self.capabilities.can_copy_depth());
+        let depth_copy = (soft_billboards && msaa_depth.is_none()).then(|| {
+            let desc = TransientTextureDesc {
+                width: self.render_config.width,
+                height: self.render_config.height,
+                format: Texture::DEPTH_FORMAT,
+                usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+                sample_count: 1
+            };
+            self.transient_textures.acquire(&self.device, &desc, "Billboard Depth Texture")
+        });
+        let billboard_bind_group = soft_billboards.then(|| {
+            self.billboard_buffer.write_uniforms(&self.queue, &self.camera,
+                self.soft_billboard_distance);
+            let depth = depth_copy.as_ref().map_or(&self.depth_texture.view, |copy| &copy.view);
+            self.billboard_buffer.create_bind_group(&self.device, depth)
+        });
+        let color_view = scene_target.as_ref().map_or(&image_view, |scene| &scene.view);
+        let color_ops = match &scene_target {
+            Some(_) => self.render_pass_config.offscreen_color_operations(),
+            None => self.render_pass_config.color_operations()
+        };
+        let color_attachment = msaa::color_attachment(color_view, msaa_color, color_ops);
+
+        {
+            let mut render_pass = command_encoder.begin_render_pass(
+                &RenderPassDescriptor {
+                    label: Some("Main Pass"),
+                    color_attachments: &[Some(color_attachment)],
+                    depth_stencil_attachment: Some(
+                        RenderPassDepthStencilAttachment {
+                            view: msaa_depth.unwrap_or(&self.depth_texture.view),
+                            depth_ops: Some(self.render_pass_config.depth_operations()),
+                            stencil_ops: None
+                        }
+                    ),
+                    occlusion_query_set: None,
+                    timestamp_writes: self.gpu_profiler.as_mut()
+                        .and_then(|gpu_profiler| gpu_profiler.timestamp_writes("Main Pass"))
+                }
+            );
+            self.crash_reporter.record(format!("begin_render_pass Main Pass {:?}",
+                self.render_pass_config));
+
+            self.main_viewport.apply(&mut render_pass, self.render_config.width,
+                self.render_config.height);
+            self.draw_opaque(&mut render_pass, &self.camera_bind_group, self.camera.eye, None);
+            if !decals_ready && !soft_billboards {
+                self.draw_water_and_overlays(&mut render_pass, water_bind_group.as_ref(), None);
+            }
+        }
+        // Decals and billboards read the depth the opaque geometry left, so decals get a
+        // pass of their own without it attached, and what blends over them one after.
+        if decals_ready || soft_billboards {
+            if let Some(msaa_depth) = msaa_depth {
+                self.encode_depth_resolve_pass(&mut command_encoder, msaa_depth);
+            }
+            if decals_ready {
+                self.encode_decal_pass(&mut command_encoder, color_view, msaa_color);
+            }
+            if let Some(copy) = &depth_copy {
+                command_encoder.copy_texture_to_texture(self.depth_texture.texture.as_image_copy(),
+                    copy.texture.as_image_copy(), self.depth_texture.texture.size());
+            }
+
+            let mut render_pass = command_encoder.begin_render_pass(
+                &RenderPassDescriptor {
+                    label: Some(OVERLAY_PASS_LABEL),
+                    color_attachments: &[Some(msaa::color_attachment(color_view, msaa_color,
+                        Operations { load: LoadOp::Load, store: StoreOp::Store }))],
+                    depth_stencil_attachment: Some(
+                        RenderPassDepthStencilAttachment {
+                            view: msaa_depth.unwrap_or(&self.depth_texture.view),
+                            depth_ops: Some(Operations { load: LoadOp::Load, store: StoreOp::Store }),
+                            stencil_ops: None
+                        }
+                    ),
+                    occlusion_query_set: None,
+                    timestamp_writes: self.gpu_profiler.as_mut()
+                        .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(OVERLAY_PASS_LABEL))
+                }
+            );
+            self.crash_reporter.record(format!("begin_render_pass {OVERLAY_PASS_LABEL}"));
+
+            self.main_viewport.apply(&mut render_pass, self.render_config.width,
+                self.render_config.height);
+            self.draw_water_and_overlays(&mut render_pass, water_bind_group.as_ref(),
+                billboard_bind_group.as_ref());
+        }
+        if let Some(msaa_depth) = msaa_depth {
+            self.encode_depth_resolve_pass(&mut command_encoder, msaa_depth);
+        }
+        if let Some(scene) = &scene_target {
+            if let Some(motion) = &motion_target {
+                self.encode_motion_vector_pass(&mut command_encoder, &motion.view, msaa_depth);
+            }
+            self.encode_post_passes(&mut command_encoder, &scene.view,
+                motion_target.as_ref().map(|motion| &motion.view), &image_view);
+        }
+        self.encode_outline_pass(&mut command_encoder, &image_view);
+        self.encode_viewport_passes(&mut command_encoder, &image_view);
+        if let Some((reflection, refraction)) = water_targets {
+            self.transient_textures.release(reflection);
+            self.transient_textures.release(refraction);
+        }
+        if let Some((color, depth)) = msaa_targets {
+            self.transient_textures.release(color);
+            self.transient_textures.release(depth);
+        }
+        for target in scene_target.into_iter().chain(motion_target).chain(depth_copy) {
+            self.transient_textures.release(target);
+        }
+        
+        if let Some(gpu_profiler) = &mut self.gpu_profiler {
+            gpu_profiler.resolve(&mut command_encoder);
+        }
+        drop(encode_timer);
+
+        {
+            let _timer = self.frame_profiler.scope("submit");
+            self.submit_batch.push(command_encoder.finish());
+            self.submit_batch.flush(&self.queue);
+        }
+        self.crash_reporter.record("submit");
+        // Handled like an uncaptured error, the report has what the frame recorded.
+        if let Err(e) = error_scope.pop_now(&self.device) {
+            log::error!("{e}");
+            self.crash_reporter.write_report(&e.to_string());
+        }
+
+        if let Some(gpu_profiler) = &mut self.gpu_profiler {
+            gpu_profiler.end_frame(&self.device);
+        }
+
+        if std::mem::take(&mut self.screenshot_requested) {
+            match (&drawable, &self.frame_output) {
+                (Some(drawable), _) => self.save_screenshot(&drawable.texture),
+                (None, FrameOutput::Texture(texture)) => self.save_screenshot(texture),
+                (None, FrameOutput::Surface(_)) => {}
+            }
+        }
+        if self.recorder.is_some() {
+            let texture = match (&drawable, &self.frame_output) {
+                (Some(drawable), _) => Some(&drawable.texture),
+                (None, FrameOutput::Texture(texture)) => Some(texture),
+                (None, FrameOutput::Surface(_)) => None
+            };
+            let frame = texture.map(|texture| pollster::block_on(self.readback().read_image(texture)));
+            if let Some(frame) = frame {
+                self.record_frame(frame);
+            }
+        }
+
+        {
+            let _timer = self.frame_profiler.scope("present");
+            if let Some(drawable) = drawable {
+                drawable.present();
+            }
+        }
+        self.crash_reporter.record("present");
+        self.submit_batch.end_frame();
+        self.report_stress();
+
+        Ok(())
+    }
+
+    // Writes the next frame to screenshots/<timestamp>.png in the working directory.
+    // Surfaces that can't be copied from, and the web, can't take them.
+    pub fn request_screenshot(&mut self)
+    {
+        self.screenshot_requested = true;
+    }
+
+    fn save_screenshot(&self, texture: &wgpu::Texture)
+    {
+        if !texture.usage().contains(TextureUsages::COPY_SRC) {
+            log::warn!("Screenshots aren't supported, the surface can't be copied from");
+            return;
+        }
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                log::warn!("Screenshots aren't supported on the web");
+            } else {
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|duration| duration.as_millis())
+                    .unwrap_or_default();
+                let path = std::env::current_dir()
+                    .unwrap_or_default()
+                    .join("screenshots")
+                    .join(format!("{timestamp}.png"));
+
+                let saved = pollster::block_on(self.readback().read_image(texture))
+                    .map_err(|e| e.to_string())
+                    .and_then(|image| {
+                        std::fs::create_dir_all(path.parent().unwrap_or(&path)).map_err(|e| e.to_string())?;
+                        image.save(&path).map_err(|e| e.to_string())
+                    });
+                match saved {
+                    Ok(()) => log::info!("Screenshot saved to {}", path.display()),
+                    Err(e) => log::error!("Couldn't save a screenshot: {e}")
+                }
+            }
+        }
+    }
+
+    // Records every frame from the next one on, until it has as many as `options` asks
+    // for or stop_recording is called. Each frame is read back before the next one
+    // starts, which slows rendering down a lot. Surfaces that can't be copied from, and
+    // the web, can't record.
+    pub fn start_recording(&mut self, options: RecordingOptions) -> Result<(), RendererError>
+    {
+        if cfg!(target_arch = "wasm32") {
+            return Err(RendererError::Recording(io::Error::new(io::ErrorKind::Unsupported,
+                "frames can't be written to files on the web")));
+        }
+        if matches!(self.frame_output, FrameOutput::Surface(_))
+            && !self.config.usage.contains(TextureUsages::COPY_SRC) {
+            return Err(RendererError::Recording(io::Error::new(io::ErrorKind::Unsupported,
+                "the surface can't be copied from")));
+        }
+
+        self.stop_recording();
+        let recorder = Recorder::start(options, (self.config.width, self.config.height))
+            .map_err(RendererError::Recording)?;
+        log::info!("Recording to {}", recorder.options().output.path().display());
+        self.recorder = Some(recorder);
+
+        Ok(())
+    }
+
+    // Finishes writing what was recorded so far, waiting for ffmpeg to encode it.
+    pub fn stop_recording(&mut self)
+    {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+
+        let frames = recorder.frames_written();
+        let path = recorder.options().output.path().to_path_buf();
+        match recorder.finish() {
+            Ok(()) => log::info!("Recorded {frames} frames to {}", path.display()),
+            Err(e) => log::error!("Couldn't finish recording to {}: {e}", path.display())
+        }
+    }
+
+    pub fn is_recording(&self) -> bool
+    {
+        self.recorder.is_some()
+    }
+
+    // Starts recording with StateOptions::recording, or stops.
+    pub fn toggle_recording(&mut self) -> Result<(), RendererError>
+    {
+        if self.recorder.is_some() {
+            self.stop_recording();
+            return Ok(());
+        }
+
+        self.start_recording(self.options.recording.clone())
+    }
+
+    fn record_frame(&mut self, frame: Result<RgbaImage, RendererError>)
+    {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+
+        let written = frame.map_err(|e| e.to_string())
+            .and_then(|frame| recorder.write_frame(&frame).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            log::error!("Stopped recording: {e}");
+            self.stop_recording();
+        } else if recorder.is_done() {
+            self.stop_recording();
+        }
+    }
+
+    // Logs the average of every frame scope since the last report, and how many draws
+    // the opaque instances took.
+    fn report_stress(&mut self)
+    {
+        let Some(report_at) = self.stress_report_at else {
+            return;
+        };
+        let now = Instant::now();
+        if now < report_at {
+            return;
+        }
+
+        let scopes = self.frame_profiler.stats().iter()
+            .map(|stats| format!("{} {:.2}ms", stats.name, stats.average.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let draws = self.draw_queue_stats.get();
+        log::info!("Stress: {} instances in {} draws ({} submitted), {scopes}", self.instances.len(),
+            draws.recorded, draws.submitted);
+
+        self.frame_profiler.reset();
+        self.stress_report_at = Some(now + STRESS_REPORT_INTERVAL);
+    }
+
+    fn draw_water_and_overlays<'p>(
+        &'p self,
+        render_pass: &mut RenderPass<'p>,
+        water_bind_group: Option<&'p BindGroup>,
+        billboard_bind_group: Option<&'p BindGroup>
+    )
+    {
+        if let (Some(water), Some(water_pipeline), Some(water_bind_group)) =
+            (&self.water, &self.water_pipeline, water_bind_group) {
+            self.crash_reporter.record(format!("draw {WATER_PIPELINE_LABEL} vertices=0..6"));
+            if self.options.debug_markers {
+                render_pass.push_debug_group(WATER_PIPELINE_LABEL);
+                render_pass.insert_debug_marker(water.label());
+            }
+            render_pass.set_pipeline(water_pipeline);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, water_bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+            if self.options.debug_markers {
+                render_pass.pop_debug_group();
+            }
+        }
+
+        self.draw_overlays(render_pass, &self.camera_bind_group, billboard_bind_group);
+    }
+
+    // The decals over the main viewport, blended onto `color_view` where they cover
+    // the depth the main pass left behind.
+    // Counts the instances the main camera sees into the indirect draw arguments, and
+    // copies them where those draws read them.
+    fn encode_cull_pass(&self, command_encoder: &mut CommandEncoder)
+    {
+        let (Some(gpu_culling), Some(cull_pipeline)) = (&self.gpu_culling, &self.cull_pipeline) else {
+            return;
+        };
+        if gpu_culling.num_instances() == 0 {
+            return;
+        }
+
+        let mut compute_pass = command_encoder.begin_compute_pass(
+            &ComputePassDescriptor {
+                label: Some(CULL_PASS_LABEL),
+                timestamp_writes: None
+            }
+        );
+        self.crash_reporter.record(format!("dispatch {CULL_PIPELINE_LABEL} instances={}",
+            gpu_culling.num_instances()));
+        if self.options.debug_markers {
+            compute_pass.push_debug_group(CULL_PIPELINE_LABEL);
+            compute_pass.insert_debug_marker(&format!("{} instances in {} draws of {}",
+                gpu_culling.num_instances(), gpu_culling.draws().len(), gpu_culling.label()));
+        }
+        compute_pass.set_pipeline(cull_pipeline);
+        gpu_culling.dispatch(&mut compute_pass);
+        if self.options.debug_markers {
+            compute_pass.pop_debug_group();
+        }
+    }
+
+    // Where an instance_lod_draws entry reads its instances from. Seen through the main
+    // camera and culled on the GPU, that's the compacted instances and an indirect draw.
+    fn instance_draw_source(
+        &self,
+        index: usize,
+        batch: &InstanceBatch,
+        camera_bind_group: &BindGroup
+    ) -> (&BindGroup, InstanceBatch, Option<(&Buffer, u64)>)
+    {
+        let culled = self.gpu_culling.as_ref()
+            .filter(|_| self.cull_pipeline.is_some()
+                && std::ptr::eq(camera_bind_group, &self.camera_bind_group))
+            .and_then(|gpu_culling| gpu_culling.draws().get(index).map(|draw| (gpu_culling, draw)));
+
+        match culled {
+            Some((gpu_culling, draw)) => (gpu_culling.visible_bind_group(), draw.batch.clone(),
+                Some((gpu_culling.args_buffer(), draw.args_offset))),
+            None => (self.instance_buffer.bind_group(), batch.clone(), None)
+        }
+    }
+
+    fn encode_decal_pass(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        color_view: &TextureView,
+        msaa_color: Option<&TextureView>
+    )
+    {
+        let viewport = self.main_viewport.to_pixels(self.render_config.width,
+            self.render_config.height);
+        self.decal_buffer.write_uniforms(&self.queue, self.camera_uniform.inverse_view_proj(),
+            viewport);
+        let decal_bind_group = self.decal_buffer.create_bind_group(&self.device,
+            &self.depth_texture.view);
+
+        let mut render_pass = command_encoder.begin_render_pass(
+            &RenderPassDescriptor {
+                label: Some(DECAL_PASS_LABEL),
+                color_attachments: &[Some(msaa::color_attachment(color_view, msaa_color,
+                    Operations { load: LoadOp::Load, store: StoreOp::Store }))],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: self.gpu_profiler.as_mut()
+                    .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(DECAL_PASS_LABEL))
+            }
+        );
+        self.crash_reporter.record(format!("begin_render_pass {DECAL_PASS_LABEL}"));
+
+        if !self.decal_buffer.bind(&mut render_pass) {
+            return;
+        }
+        if self.options.debug_markers {
+            render_pass.push_debug_group(DECAL_PIPELINE_LABEL);
+        }
+        self.main_viewport.apply(&mut render_pass, self.render_config.width, self.render_config.height);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.vertex_animation_bind_group, &[]);
+        render_pass.set_bind_group(3, &decal_bind_group, &[]);
+        for (handle, decals) in &self.decal_draws {
+            let Some((pipeline, material)) = self.assets.materials.get(handle)
+                .and_then(|material| Some((
+                    self.decal_pipelines.get(&material.features())?.as_deref()?,
+                    material.bind_group()?
+                ))) else {
+                continue;
+            };
+            self.crash_reporter.record(format!("draw {DECAL_PIPELINE_LABEL} vertices=0..36 \
+                instances={decals:?}"));
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, material, &[]);
+            render_pass.draw(0..36, decals.clone());
+        }
+        if self.options.debug_markers {
+            render_pass.pop_debug_group();
+        }
+    }
+
+    // Transparent instances, billboards and the debug lines, after everything opaque is
+    // drawn. Billboards fade into the depth `billboard_bind_group` reads, without one
+    // they're drawn hard.
+    fn draw_overlays<'p>(
+        &'p self,
+        render_pass: &mut RenderPass<'p>,
+        camera_bind_group: &'p BindGroup,
+        billboard_bind_group: Option<&'p BindGroup>
+    )
+    {
+        // Tested against the opaque depth without writing any.
+        if let Some(diffuse_bind_group) = self.diffuse_texture.bind_group()
+            .filter(|_| !self.transparent_instances.is_empty()) {
+            self.crash_reporter.record(format!(
+                "draw_indexed {TRANSPARENT_PIPELINE_LABEL} indices=0..{} instances={}",
+                self.num_indices, self.transparent_instances.len()));
+            if self.options.debug_markers {
+                render_pass.push_debug_group(TRANSPARENT_PIPELINE_LABEL);
+            }
+            render_pass.set_pipeline(self.debug_pipeline.as_deref()
+                .unwrap_or(&self.transparent_pipeline));
+            render_pass.set_bind_group(0, diffuse_bind_group, &[]);
+            render_pass.set_bind_group(1, camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.vertex_animation_bind_group, &[]);
+            if let Some(mesh) = self.assets.meshes.get(&self.instance_mesh) {
+                mesh.bind(render_pass, &self.gpu_allocator);
+            }
+            for batch in &self.transparent_instance_batches {
+                render_pass.set_bind_group(3, self.transparent_instance_buffer.bind_group(),
+                    &[batch.offset]);
+                render_pass.draw_indexed(0..self.num_indices, 0, batch.instances.clone());
+            }
+            if self.options.debug_markers {
+                render_pass.pop_debug_group();
+            }
+        }
+
+        // The debug views replace the shading, they have nothing to draw billboards with.
+        if let Some(diffuse_bind_group) = self.diffuse_texture.bind_group()
+            .filter(|_| self.billboard_buffer.num_billboards() > 0 && self.debug_pipeline.is_none()) {
+            self.crash_reporter.record(format!("draw {BILLBOARD_PIPELINE_LABEL} vertices=0..6 \
+                instances=0..{}", self.billboard_buffer.num_billboards()));
+            if self.options.debug_markers {
+                render_pass.push_debug_group(BILLBOARD_PIPELINE_LABEL);
+                render_pass.insert_debug_marker(self.billboard_buffer.label());
+            }
+            render_pass.set_pipeline(&self.billboard_pipeline);
+            render_pass.set_bind_group(0, diffuse_bind_group, &[]);
+            render_pass.set_bind_group(1, camera_bind_group, &[]);
+            render_pass.set_bind_group(2, billboard_bind_group
+                .unwrap_or(self.billboard_buffer.hard_bind_group()), &[]);
+            self.billboard_buffer.draw(render_pass);
+            if self.options.debug_markers {
+                render_pass.pop_debug_group();
+            }
+        }
+
+        if let Some(debug_lines_pipeline) = self.debug_lines_pipeline.as_ref()
+            .filter(|_| self.show_bounds || self.gizmo_transform().is_some()) {
+            self.crash_reporter.record(format!("draw {DEBUG_LINES_PIPELINE_LABEL} vertices=0..{}",
+                self.debug_lines.num_vertices()));
+            if self.options.debug_markers {
+                render_pass.push_debug_group(DEBUG_LINES_PIPELINE_LABEL);
+                render_pass.insert_debug_marker(self.debug_lines.label());
+            }
+            render_pass.set_pipeline(debug_lines_pipeline);
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            self.debug_lines.draw(render_pass);
+            if self.options.debug_markers {
+                render_pass.pop_debug_group();
+            }
+        }
+    }
+
+    // Terrain, instances and the skinned mesh: everything that writes depth, drawn as
+    // seen through `camera_bind_group`. Shared by the main pass, the water passes and
+    // the render target passes, which pass their target's id to leave out the draws
+    // that sample it.
+    fn draw_opaque<'p>(
+        &'p self,
+        render_pass: &mut RenderPass<'p>,
+        camera_bind_group: &'p BindGroup,
+        eye: Point3<f32>,
+        render_target: Option<u64>
+    )
+    {
+        let samples_target = |material: Option<&MaterialHandle>| render_target.is_some()
+            && material.and_then(|handle| self.assets.materials.get(handle))
+                .and_then(Material::render_target)
+                .map(RenderTargetHandle::id) == render_target;
+
+        // The terrain binds the camera at group 0, the instances below rebind every group.
+        if let (Some(terrain_mesh), Some(terrain_pipeline)) =
+            (&self.terrain_mesh, &self.terrain_pipeline) {
+            self.crash_reporter.record(format!("draw_indexed {TERRAIN_PIPELINE_LABEL} chunks={}",
+                terrain_mesh.num_chunks()));
+            if self.options.debug_markers {
+                render_pass.push_debug_group(TERRAIN_PIPELINE_LABEL);
+                render_pass.insert_debug_marker(&format!("{} chunks of {}",
+                    terrain_mesh.num_chunks(), terrain_mesh.label()));
+            }
+            render_pass.set_pipeline(terrain_pipeline);
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            terrain_mesh.draw(render_pass, &self.gpu_allocator, eye);
+            if self.options.debug_markers {
+                render_pass.pop_debug_group();
+            }
+        }
+
+        let Some(diffuse_bind_group) = self.diffuse_texture.bind_group() else {
+            return;
+        };
+
+        if let (Some(vegetation_mesh), Some(vegetation_pipeline)) =
+            (&self.vegetation_mesh, &self.vegetation_pipeline) {
+            self.crash_reporter.record(format!("draw {VEGETATION_PIPELINE_LABEL} plants={}",
+                vegetation_mesh.num_plants()));
+            if self.options.debug_markers {
+                render_pass.push_debug_group(VEGETATION_PIPELINE_LABEL);
+                render_pass.insert_debug_marker(&format!("{} plants of {}",
+                    vegetation_mesh.num_plants(), vegetation_mesh.label()));
+            }
+            render_pass.set_pipeline(vegetation_pipeline);
+            render_pass.set_bind_group(0, diffuse_bind_group, &[]);
+            render_pass.set_bind_group(1, camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.vertex_animation_bind_group, &[]);
+            vegetation_mesh.draw(render_pass, &self.gpu_allocator, eye);
+            if self.options.debug_markers {
+                render_pass.pop_debug_group();
+            }
+        }
+
+        if self.options.debug_markers {
+            render_pass.push_debug_group(INSTANCE_PIPELINE_LABEL);
+            render_pass.insert_debug_marker(&format!("{} instances of {}",
+                self.instances.len(), self.diffuse_texture.label()));
+        }
+        let instance_pipeline = self.debug_pipeline.as_deref().unwrap_or(&self.render_pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.vertex_animation_bind_group, &[]);
+        let mut draw_queue = DrawQueue::default();
+        if let Some(mesh) = self.assets.meshes.get(&self.instance_mesh) {
+            for (index, (material, level, batch)) in self.instance_lod_draws.iter().enumerate() {
+                if samples_target(material.as_ref()) {
+                    continue;
+                }
+                // The debug views replace the shading, materials included.
+                let material = material.as_ref().filter(|_| self.debug_pipeline.is_none());
+                let (pipeline, material) = self.material_binding(material, instance_pipeline,
+                    diffuse_bind_group);
+                let (instances, batch, indirect) = self.instance_draw_source(index, batch,
+                    camera_bind_group);
+                draw_queue.push(InstancedDraw {
+                    pipeline,
+                    material,
+                    mesh,
+                    indices: self.mesh_lods.level(*level).indices.clone(),
+                    instances,
+                    batch,
+                    indirect
+                });
+            }
+        }
+        draw_queue.sort_and_merge();
+        for draw in draw_queue.draws() {
+            self.crash_reporter.record(format!("draw_indexed {INSTANCE_PIPELINE_LABEL} \
+                indices={:?} instances={:?} offset={}", draw.indices, draw.batch.instances,
+                draw.batch.offset));
+        }
+        self.draw_queue_stats.set(draw_queue.record(render_pass, &self.gpu_allocator));
+        if self.options.debug_markers {
+            render_pass.pop_debug_group();
+        }
+
+        if let (Some(skinned_mesh), Some(skinned_pipeline), false) = (&self.skinned_mesh,
+            &self.skinned_pipeline, samples_target(self.skinned_material.as_ref())) {
+            self.crash_reporter.record(format!(
+                "draw_indexed {SKINNED_PIPELINE_LABEL} {} indices=0..{} instances=0..1",
+                skinned_mesh.label(), skinned_mesh.num_indices()));
+            if self.options.debug_markers {
+                render_pass.push_debug_group(SKINNED_PIPELINE_LABEL);
+            }
+            let (pipeline, material) = self.material_binding(self.skinned_material.as_ref(),
+                skinned_pipeline, diffuse_bind_group);
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, material, &[]);
+            skinned_mesh.draw(render_pass, &self.gpu_allocator);
+            if self.options.debug_markers {
+                render_pass.pop_debug_group();
+            }
+        }
+    }
+
+    // Renders the reflection and refraction targets the water samples in the main
+    // pass, returning them so they go back to the pool once the frame is encoded.
+    fn encode_water_passes(
+        &mut self,
+        command_encoder: &mut CommandEncoder
+    ) -> Option<(Rc<TransientTexture>, Rc<TransientTexture>)>
+    {
+        let water = self.water.as_ref()?;
+        let color_desc = water.color_target_desc(&self.render_config);
+        let depth_desc = water.depth_target_desc(&self.render_config);
+
+        let reflection = self.transient_textures.acquire(&self.device, &color_desc,
+            "Water Reflection Texture");
+        let refraction = self.transient_textures.acquire(&self.device, &color_desc,
+            "Water Refraction Texture");
+        // Both passes share one depth target, they run one after the other. With MSAA
+        // they share the multisampled color they resolve from too.
+        let depth_desc = match self.msaa {
+            Some(_) => Msaa::target_desc(depth_desc.width, depth_desc.height, depth_desc.format),
+            None => depth_desc
+        };
+        let depth = self.transient_textures.acquire(&self.device, &depth_desc,
+            "Water Depth Texture");
+        let msaa_color = self.msaa.is_some().then(|| self.transient_textures.acquire(&self.device,
+            &Msaa::target_desc(color_desc.width, color_desc.height, color_desc.format),
+            "Water MSAA Color Texture"));
+
+        for (label, target) in [(WATER_REFLECTION_PASS_LABEL, &reflection),
+            (WATER_REFRACTION_PASS_LABEL, &refraction)] {
+            let mut render_pass = command_encoder.begin_render_pass(
+                &RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[Some(msaa::color_attachment(&target.view,
+                        msaa_color.as_ref().map(|msaa_color| &msaa_color.view),
+                        self.render_pass_config.offscreen_color_operations()))],
+                    depth_stencil_attachment: Some(
+                        RenderPassDepthStencilAttachment {
+                            view: &depth.view,
+                            depth_ops: Some(self.render_pass_config.offscreen_depth_operations()),
+                            stencil_ops: None
+                        }
+                    ),
+                    occlusion_query_set: None,
+                    timestamp_writes: self.gpu_profiler.as_mut()
+                        .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(label))
+                }
+            );
+            self.crash_reporter.record(format!("begin_render_pass {label}"));
+
+            let (camera_bind_group, eye) = match (label, &self.water) {
+                (WATER_REFLECTION_PASS_LABEL, Some(water)) => (water.reflection_camera_bind_group(),
+                    self.camera.reflected(water.options().height).eye),
+                _ => (&self.camera_bind_group, self.camera.eye)
+            };
+            self.draw_opaque(&mut render_pass, camera_bind_group, eye, None);
+        }
+        self.transient_textures.release(depth);
+        if let Some(msaa_color) = msaa_color {
+            self.transient_textures.release(msaa_color);
+        }
+
+        Some((reflection, refraction))
+    }
+
+    // Everything draw_opaque draws, writing where it was on screen last frame instead of
+    // its color. The position doesn't depend on the material, so every instance draw
+    // binds the diffuse texture.
+    fn draw_motion_vectors<'p>(&'p self, render_pass: &mut RenderPass<'p>)
+    {
+        if let (Some(terrain_mesh), Some(pipeline)) = (&self.terrain_mesh,
+            self.motion_vector_pipelines.get(&ShaderHandle::Terrain)) {
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            terrain_mesh.draw(render_pass, &self.gpu_allocator, self.camera.eye);
+        }
+
+        let Some(diffuse_bind_group) = self.diffuse_texture.bind_group() else {
+            return;
+        };
+
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.vertex_animation_bind_group, &[]);
+        if let (Some(mesh), Some(pipeline)) = (self.assets.meshes.get(&self.instance_mesh),
+            self.motion_vector_pipelines.get(&ShaderHandle::Vertex)) {
+            let mut draw_queue = DrawQueue::default();
+            for (index, (_, level, batch)) in self.instance_lod_draws.iter().enumerate() {
+                let (instances, batch, indirect) = self.instance_draw_source(index, batch,
+                    &self.camera_bind_group);
+                draw_queue.push(InstancedDraw {
+                    pipeline,
+                    material: diffuse_bind_group,
+                    mesh,
+                    indices: self.mesh_lods.level(*level).indices.clone(),
+                    instances,
+                    batch,
+                    indirect
+                });
+            }
+            draw_queue.sort_and_merge();
+            draw_queue.record(render_pass, &self.gpu_allocator);
+        }
+
+        if let (Some(skinned_mesh), Some(pipeline)) = (&self.skinned_mesh,
+            self.motion_vector_pipelines.get(&ShaderHandle::Skinned)) {
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, diffuse_bind_group, &[]);
+            skinned_mesh.draw(render_pass, &self.gpu_allocator);
+        }
+    }
+
+    // Where everything draw_opaque drew moved from since the last frame, tested against
+    // the main pass's depth, the multisampled one with MSAA.
+    fn encode_motion_vector_pass(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        motion: &TextureView,
+        msaa_depth: Option<&TextureView>
+    )
+    {
+        let (width, height) = (self.render_config.width, self.render_config.height);
+        let msaa_motion = msaa_depth.map(|_| self.transient_textures.acquire(&self.device,
+            &Msaa::target_desc(width, height, MOTION_VECTOR_FORMAT), "MSAA Motion Vector Texture"));
+
+        let mut render_pass = command_encoder.begin_render_pass(
+            &RenderPassDescriptor {
+                label: Some(MOTION_VECTOR_PASS_LABEL),
+                color_attachments: &[Some(msaa::color_attachment(motion,
+                    msaa_motion.as_ref().map(|msaa_motion| &msaa_motion.view),
+                    Operations {
+                        load: LoadOp::Clear(Color::TRANSPARENT),
+                        store: StoreOp::Store
+                    }))],
+                depth_stencil_attachment: Some(
+                    RenderPassDepthStencilAttachment {
+                        view: msaa_depth.unwrap_or(&self.depth_texture.view),
+                        depth_ops: Some(Operations {
+                            load: LoadOp::Load,
+                            store: StoreOp::Store
+                        }),
+                        stencil_ops: None
+                    }
+                ),
+                occlusion_query_set: None,
+                timestamp_writes: self.gpu_profiler.as_mut()
+                    .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(MOTION_VECTOR_PASS_LABEL))
+            }
+        );
+        self.crash_reporter.record(format!("begin_render_pass {MOTION_VECTOR_PASS_LABEL}"));
+
+        self.main_viewport.apply(&mut render_pass, self.render_config.width, self.render_config.height);
+        self.draw_motion_vectors(&mut render_pass);
+        drop(render_pass);
+        if let Some(msaa_motion) = msaa_motion {
+            self.transient_textures.release(msaa_motion);
+        }
+    }
+
+    // Fills the depth texture from the multisampled depth the scene passes drew with, for
+    // everything after them that reads it.
+    fn encode_depth_resolve_pass(&mut self, command_encoder: &mut CommandEncoder, msaa_depth: &TextureView)
+    {
+        let (Some(msaa), Some(depth_resolve_pipeline)) = (&self.msaa, &self.depth_resolve_pipeline) else {
+            return;
+        };
+        let bind_group = msaa.create_bind_group(&self.device, msaa_depth);
+
+        let mut render_pass = command_encoder.begin_render_pass(
+            &RenderPassDescriptor {
+                label: Some(DEPTH_RESOLVE_PASS_LABEL),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(
+                    RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: Some(Operations {
+                            load: LoadOp::Load,
+                            store: StoreOp::Store
+                        }),
+                        stencil_ops: None
+                    }
+                ),
+                occlusion_query_set: None,
+                timestamp_writes: self.gpu_profiler.as_mut()
+                    .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(DEPTH_RESOLVE_PASS_LABEL))
+            }
+        );
+        self.crash_reporter.record(format!("begin_render_pass {DEPTH_RESOLVE_PASS_LABEL}"));
+
+        if self.options.debug_markers {
+            render_pass.insert_debug_marker(msaa.label());
+        }
+        render_pass.set_pipeline(depth_resolve_pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    // Everything between the main pass and the surface: with motion vectors the TAA
+    // resolve, then the post effects in order, the last of them writing the surface.
+    // Without any post effects the resolved frame is blitted there instead. Every
+    // effect but the last gets its own target from the pool, and with SSAO on its
+    // occlusion and blur passes go first.
+    fn encode_post_passes(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        scene: &TextureView,
+        motion: Option<&TextureView>,
+        image_view: &TextureView
+    )
+    {
+        let ssao_ready = self.ssao.is_some() && self.ssao_occlusion_pipeline.is_some();
+        let effects = self.post_effects.iter()
+            .filter(|effect| match effect {
+                PostEffect::Ssao => ssao_ready,
+                PostEffect::Fog => self.fog.is_some(),
+                PostEffect::DepthOfField => self.depth_of_field.is_some(),
+                PostEffect::MotionBlur => self.motion_blur.is_some() && motion.is_some(),
+                PostEffect::Glow => self.glow.is_some(),
+                PostEffect::ColorGrading => self.color_grading.is_some(),
+                PostEffect::Fxaa => true
+            })
+            .filter_map(|effect| self.post_effect_pipelines.get(effect)
+                .map(|pipeline| (*effect, pipeline.clone())))
+            .collect::<Vec<_>>();
+        // Upscaling, the last effect writes another target for the upscale pass.
+        let upscale = self.upscale_pipeline.clone();
+        let target_count = if upscale.is_some() { effects.len() } else { effects.len().saturating_sub(1) };
+        let target_desc = self.post_process.target_desc(&self.render_config);
+        let targets = (0..target_count)
+            .map(|_| self.transient_textures.acquire(&self.device, &target_desc,
+                "Post Effect Texture"))
+            .collect::<Vec<_>>();
+        let occlusion_desc = Ssao::occlusion_target_desc(&self.render_config);
+        let occlusion_targets = if effects.iter().any(|(effect, _)| *effect == PostEffect::Ssao) {
+            ["SSAO Occlusion Texture", "SSAO Blur Texture"]
+                .map(|label| Some(self.transient_textures.acquire(&self.device, &occlusion_desc, label)))
+        } else {
+            [None, None]
+        };
+        let edge_target = self.edge_detection.as_ref()
+            .filter(|_| self.blit_pipeline.is_some())
+            .map(|edge_detection| {
+                edge_detection.write_uniforms(&self.queue);
+                let target = self.transient_textures.acquire(&self.device,
+                    &EdgeDetection::output_target_desc(&self.render_config), "Edge Detection Texture");
+                self.crash_reporter.record(format!("dispatch {EDGE_DETECTION_LABEL} size={}x{}",
+                    self.render_config.width, self.render_config.height));
+                edge_detection.encode(&self.device, command_encoder, scene, &target.view,
+                    (self.render_config.width, self.render_config.height));
+                target
+            });
+
+        let mut resolved = false;
+        {
+            let mut source = edge_target.as_ref().map_or(scene, |target| &target.view);
+            let mut passes = Vec::new();
+            let mut effect_bind_groups = HashMap::new();
+            if let (Some(ssao), Some(occlusion_pipeline), [Some(occlusion), Some(blurred)]) =
+                (&self.ssao, &self.ssao_occlusion_pipeline, &occlusion_targets) {
+                let viewport = self.main_viewport.to_pixels(self.render_config.width,
+                    self.render_config.height);
+                ssao.write_uniforms(&self.queue, &self.camera, viewport);
+                passes.push((format!("{SSAO_OCCLUSION_PIPELINE_LABEL} Pass"), &occlusion.view,
+                    occlusion_pipeline.clone(), vec![self.post_process.create_bind_group(&self.device, source),
+                        ssao.create_bind_group(&self.device, &self.depth_texture.view, &blurred.view)]));
+                let mut result = &occlusion.view;
+                if let Some(blur_pipeline) = self.ssao_blur_pipeline.as_ref().filter(|_| ssao.options().blur) {
+                    passes.push((format!("{SSAO_BLUR_PIPELINE_LABEL} Pass"), &blurred.view,
+                        blur_pipeline.clone(), vec![self.post_process.create_bind_group(&self.device, source),
+                            ssao.create_bind_group(&self.device, &self.depth_texture.view, &occlusion.view)]));
+                    result = &blurred.view;
+                }
+                effect_bind_groups.insert(PostEffect::Ssao,
+                    ssao.create_bind_group(&self.device, &self.depth_texture.view, result));
+            }
+            if let Some(fog) = &self.fog {
+                let viewport = self.main_viewport.to_pixels(self.render_config.width,
+                    self.render_config.height);
+                fog.write_uniforms(&self.queue, &self.camera, viewport);
+                effect_bind_groups.insert(PostEffect::Fog, fog.create_bind_group(&self.device,
+                    &self.depth_texture.view));
+            }
+            if let Some(depth_of_field) = &self.depth_of_field {
+                depth_of_field.write_uniforms(&self.queue, &self.camera);
+                effect_bind_groups.insert(PostEffect::DepthOfField,
+                    depth_of_field.create_bind_group(&self.device, &self.depth_texture.view));
+            }
+            if let (Some(motion_blur), Some(motion)) = (&self.motion_blur, motion) {
+                motion_blur.write_uniforms(&self.queue, self.camera_uniform.reprojection());
+                effect_bind_groups.insert(PostEffect::MotionBlur,
+                    motion_blur.create_bind_group(&self.device, motion, &self.depth_texture.view));
+            }
+            if let Some(glow) = &self.glow {
+                glow.write_uniforms(&self.queue);
+                effect_bind_groups.insert(PostEffect::Glow, glow.create_bind_group(&self.device));
+            }
+            if let Some(color_grading) = &self.color_grading {
+                color_grading.write_uniforms(&self.queue);
+                effect_bind_groups.insert(PostEffect::ColorGrading, color_grading.create_bind_group(&self.device));
+            }
+            if let (Some(taa), Some(pipeline), Some(motion)) =
+                (&self.taa, &self.taa_resolve_pipeline, motion) {
+                resolved = true;
+                taa.write_uniforms(&self.queue);
+                passes.push((String::from(TAA_RESOLVE_PASS_LABEL), taa.resolve_view(), pipeline.clone(),
+                    vec![taa.create_bind_group(&self.device, source, motion)]));
+                source = taa.resolve_view();
+            }
+            let blit = match upscale {
+                Some(pipeline) => Some((UPSCALE_PIPELINE_LABEL, pipeline, None)),
+                None => self.blit_pipeline.clone()
+                    .filter(|_| effects.is_empty())
+                    .map(|pipeline| (BLIT_PIPELINE_LABEL, pipeline, None))
+            };
+            let effects = effects.into_iter()
+                .map(|(effect, pipeline)| (effect.label(), pipeline, Some(effect)));
+            for (index, (label, pipeline, effect)) in effects.chain(blit).enumerate() {
+                let target = targets.get(index).map_or(image_view, |target| &target.view);
+                let mut bind_groups = vec![self.post_process.create_bind_group(&self.device, source)];
+                bind_groups.extend(effect.and_then(|effect| effect_bind_groups.remove(&effect)));
+                passes.push((format!("{label} Pass"), target, pipeline, bind_groups));
+                source = target;
+            }
+
+            for (label, target, pipeline, bind_groups) in &passes {
+                let mut render_pass = command_encoder.begin_render_pass(
+                    &RenderPassDescriptor {
+                        label: Some(label),
+                        color_attachments: &[Some(RenderPassColorAttachment {
+                            view: target,
+                            resolve_target: None,
+                            ops: self.render_pass_config.offscreen_color_operations()
+                        })],
+                        depth_stencil_attachment: None,
+                        occlusion_query_set: None,
+                        timestamp_writes: self.gpu_profiler.as_mut()
+                            .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(label))
+                    }
+                );
+                self.crash_reporter.record(format!("begin_render_pass {label}"));
+                self.crash_reporter.record(format!("draw {label} vertices=0..3"));
+
+                render_pass.set_pipeline(pipeline);
+                for (index, bind_group) in bind_groups.iter().enumerate() {
+                    render_pass.set_bind_group(index as u32, bind_group, &[]);
+                }
+                render_pass.draw(0..3, 0..1);
+            }
+        }
+
+        for target in occlusion_targets.into_iter().flatten().chain(edge_target) {
+            self.transient_textures.release(target);
+        }
+        for target in targets {
+            self.transient_textures.release(target);
+        }
+        if let Some(taa) = self.taa.as_mut().filter(|_| resolved) {
+            taa.end_frame();
+        }
+    }
+
+    // Over the finished frame, after the post passes, so none of them blur or shade it.
+    fn encode_outline_pass(&mut self, command_encoder: &mut CommandEncoder, image_view: &TextureView)
+    {
+        let (Some(outline), Some(diffuse_bind_group), Some(mesh)) = (&self.outline,
+            self.diffuse_texture.bind_group(), self.assets.meshes.get(&self.instance_mesh)) else {
+            return;
+        };
+        if outline.is_empty() {
+            return;
+        }
+
+        let mut render_pass = command_encoder.begin_render_pass(
+            &RenderPassDescriptor {
+                label: Some(OUTLINE_LABEL),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: image_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: StoreOp::Store
+                    }
+                })],
+                depth_stencil_attachment: Some(outline.depth_stencil_attachment()),
+                occlusion_query_set: None,
+                timestamp_writes: self.gpu_profiler.as_mut()
+                    .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(OUTLINE_LABEL))
+            }
+        );
+        self.crash_reporter.record(format!("begin_render_pass {OUTLINE_LABEL} instances={:?}",
+            self.outlined_instances));
+
+        self.main_viewport.apply(&mut render_pass, self.config.width, self.config.height);
+        render_pass.set_bind_group(0, diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.vertex_animation_bind_group, &[]);
+        mesh.bind(&mut render_pass, &self.gpu_allocator);
+        outline.draw(&mut render_pass, 0..self.num_indices);
+    }
+
+    // Every added viewport over the main pass, in the order they were added. They clear
+    // the depth, but not the color outside of what they draw. At another render scale the
+    // depth texture doesn't match the surface, so they share one that does.
+    fn encode_viewport_passes(&mut self, command_encoder: &mut CommandEncoder, image_view: &TextureView)
+    {
+        if self.msaa.is_some() {
+            self.encode_msaa_viewport_passes(command_encoder, image_view);
+            return;
+        }
+
+        let surface_depth = (!self.viewports.is_empty() && self.upscale_pipeline.is_some()).then(|| {
+            let desc = TransientTextureDesc {
+                width: self.config.width,
+                height: self.config.height,
+                format: Texture::DEPTH_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                sample_count: 1
+            };
+            self.transient_textures.acquire(&self.device, &desc, "Viewport Depth Texture")
+        });
+        let depth_view = surface_depth.as_ref().map_or(&self.depth_texture.view, |depth| &depth.view);
+        for viewport in &self.viewports {
+            let label = viewport.label();
+            let mut render_pass = command_encoder.begin_render_pass(
+                &RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: image_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Load,
+                            store: StoreOp::Store
+                        }
+                    })],
+                    depth_stencil_attachment: Some(
+                        RenderPassDepthStencilAttachment {
+                            view: depth_view,
+                            depth_ops: Some(self.render_pass_config.offscreen_depth_operations()),
+                            stencil_ops: None
+                        }
+                    ),
+                    occlusion_query_set: None,
+                    timestamp_writes: self.gpu_profiler.as_mut()
+                        .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(label))
+                }
+            );
+            self.crash_reporter.record(format!("begin_render_pass {label} {:?}", viewport.rect()));
+
+            viewport.rect().apply(&mut render_pass, self.config.width, self.config.height);
+            self.draw_opaque(&mut render_pass, viewport.camera_bind_group(), viewport.camera().eye,
+                None);
+            self.draw_overlays(&mut render_pass, viewport.camera_bind_group(), None);
+        }
+        if let Some(depth) = surface_depth {
+            self.transient_textures.release(depth);
+        }
+    }
+
+    // With MSAA a viewport's resolve would replace the whole frame, so every viewport is
+    // drawn over transparent black and resolved into a target of its own, which the
+    // composite blends into its rect by how much of each pixel it covered.
+    fn encode_msaa_viewport_passes(&mut self, command_encoder: &mut CommandEncoder, image_view: &TextureView)
+    {
+        let (Some(msaa), Some(composite_pipeline)) = (&self.msaa, &self.viewport_composite_pipeline) else {
+            return;
+        };
+        if self.viewports.is_empty() {
+            return;
+        }
+
+        let (width, height) = (self.config.width, self.config.height);
+        let color = self.transient_textures.acquire(&self.device,
+            &Msaa::target_desc(width, height, self.config.format), "Viewport MSAA Color Texture");
+        let depth = self.transient_textures.acquire(&self.device,
+            &Msaa::target_desc(width, height, Texture::DEPTH_FORMAT), "Viewport MSAA Depth Texture");
+        let resolved = self.transient_textures.acquire(&self.device,
+            &self.post_process.target_desc(&self.config), "Viewport Texture");
+        let depth_bind_group = msaa.create_bind_group(&self.device, &depth.view);
+        let resolved_bind_group = self.post_process.create_bind_group(&self.device, &resolved.view);
+        for viewport in &self.viewports {
+            let label = viewport.label();
+            {
+                let mut render_pass = command_encoder.begin_render_pass(
+                    &RenderPassDescriptor {
+                        label: Some(label),
+                        color_attachments: &[Some(msaa::color_attachment(&resolved.view,
+                            Some(&color.view), Operations {
+                                load: LoadOp::Clear(Color::TRANSPARENT),
+                                store: StoreOp::Store
+                            }))],
+                        depth_stencil_attachment: Some(
+                            RenderPassDepthStencilAttachment {
+                                view: &depth.view,
+                                depth_ops: Some(self.render_pass_config.offscreen_depth_operations()),
+                                stencil_ops: None
+                            }
+                        ),
+                        occlusion_query_set: None,
+                        timestamp_writes: self.gpu_profiler.as_mut()
+                            .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(label))
+                    }
+                );
+                self.crash_reporter.record(format!("begin_render_pass {label} {:?}", viewport.rect()));
+
+                viewport.rect().apply(&mut render_pass, width, height);
+                self.draw_opaque(&mut render_pass, viewport.camera_bind_group(),
+                    viewport.camera().eye, None);
+                self.draw_overlays(&mut render_pass, viewport.camera_bind_group(), None);
+            }
+
+            let mut render_pass = command_encoder.begin_render_pass(
+                &RenderPassDescriptor {
+                    label: Some(VIEWPORT_COMPOSITE_PIPELINE_LABEL),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: image_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Load,
+                            store: StoreOp::Store
+                        }
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: None
+                }
+            );
+            self.crash_reporter.record(format!("draw {VIEWPORT_COMPOSITE_PIPELINE_LABEL} {label}"));
+
+            viewport.rect().apply(&mut render_pass, width, height);
+            render_pass.set_pipeline(composite_pipeline);
+            render_pass.set_bind_group(0, &depth_bind_group, &[]);
+            render_pass.set_bind_group(1, &resolved_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        for target in [color, depth, resolved] {
+            self.transient_textures.release(target);
+        }
+    }
+
+    // The opaque scene from every render target's camera, before anything samples them.
+    // With MSAA they're drawn into multisampled targets from the pool instead of their
+    // own, resolving into their color.
+    fn encode_render_target_passes(&mut self, command_encoder: &mut CommandEncoder)
+    {
+        for (id, render_target) in self.assets.render_targets.iter() {
+            let label = render_target.label();
+            let (width, height) = render_target.size();
+            let msaa_targets = self.msaa.is_some().then(|| (
+                self.transient_textures.acquire(&self.device,
+                    &Msaa::target_desc(width, height, self.config.format),
+                    "Render Target MSAA Color Texture"),
+                self.transient_textures.acquire(&self.device,
+                    &Msaa::target_desc(width, height, Texture::DEPTH_FORMAT),
+                    "Render Target MSAA Depth Texture")
+            ));
+            let resolve_view = msaa_targets.as_ref().map(|_| render_target.resolve_view());
+            let mut render_pass = command_encoder.begin_render_pass(
+                &RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[Some(msaa::color_attachment(
+                        resolve_view.as_ref().unwrap_or(render_target.view()), msaa_targets.as_ref().map(|(color, _)| &color.view),
+                        self.render_pass_config.offscreen_color_operations()))],
+                    depth_stencil_attachment: Some(
+                        RenderPassDepthStencilAttachment {
+                            view: msaa_targets.as_ref()
+                                .map_or(render_target.depth_view(), |(_, depth)| &depth.view),
+                            depth_ops: Some(self.render_pass_config.offscreen_depth_operations()),
+                            stencil_ops: None
+                        }
+                    ),
+                    occlusion_query_set: None,
+                    timestamp_writes: self.gpu_profiler.as_mut()
+                        .and_then(|gpu_profiler| gpu_profiler.timestamp_writes(label))
+                }
+            );
+            self.crash_reporter.record(format!("begin_render_pass {label}"));
+
+            self.draw_opaque(&mut render_pass, render_target.camera_bind_group(),
+                render_target.camera().eye, Some(id));
+            drop(render_pass);
+            if let Some((color, depth)) = msaa_targets {
+                self.transient_textures.release(color);
+                self.transient_textures.release(depth);
+            }
+        }
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool
+    {
+        let handler = self.dispatch_input(event);
+        if !matches!(event, WindowEvent::RedrawRequested) {
+            self.input_tracer.record(event, handler);
+        }
+
+        handler.is_some()
+    }
+
+    pub fn trace_device_event(&mut self, event: &DeviceEvent)
+    {
+        self.input_tracer.record(event, None);
+    }
+
+    pub fn set_input_tracing(&mut self, enabled: bool)
+    {
+        self.input_tracer.set_enabled(enabled);
+    }
+
+    pub fn input_trace(&self) -> impl Iterator<Item = &InputRecord>
+    {
+        self.input_tracer.records()
+    }
+
+    fn dispatch_input(&mut self, event: &WindowEvent) -> Option<&'static str>
+    {
+        if let Some(action_event) = self.input_map.translate(event) {
+            return self.dispatch_action(action_event);
+        }
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => Some(self.move_cursor(*position)),
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                None
+            },
+            WindowEvent::DroppedFile(path) => {
+                if let Err(e) = self.open_file(path) {
+                    log::error!("{e}");
+                }
+                Some("dropped_file")
+            },
+            WindowEvent::Touch(touch) => {
+                self.touch(touch.id, touch.phase, touch.location).then_some("touch")
+            },
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(code),
+                    repeat: false,
+                    ..
+                },
+                ..
+            } if CameraBookmarks::slot_for_key(*code).is_some() => self.camera_bookmark_key(*code),
+            _ => None
+        }
+    }
+
+    // Ctrl and a digit saves the camera into the digit's slot, the digit alone restores it.
+    fn camera_bookmark_key(&mut self, code: KeyCode) -> Option<&'static str>
+    {
+        let slot = CameraBookmarks::slot_for_key(code)?;
+        if self.modifiers.control_key() {
+            self.save_camera_bookmark(slot, &format!("Bookmark {}", slot + 1));
+        } else {
+            self.restore_camera_bookmark(slot);
+        }
+
+        Some("camera_bookmarks")
+    }
+
+    // Input that doesn't come as winit events, e.g. what the page forwards to a web
+    // worker. Returns whether something handled the binding.
+    pub fn input_binding(&mut self, binding: Binding, pressed: bool, repeat: bool) -> bool
+    {
+        if let Some(action) = self.input_map.action(binding) {
+            return self.dispatch_action(ActionEvent { action, pressed, repeat }).is_some();
+        }
+
+        // Like in dispatch_input, the digits only reach the bookmarks when they aren't bound.
+        match binding {
+            Binding::Key(code) if pressed && !repeat => self.camera_bookmark_key(code).is_some(),
+            _ => false
+        }
+    }
+
+    // A finger on the screen, from winit or from the page of a web worker. Returns
+    // whether it made a gesture, which moves the camera.
+    pub fn touch(&mut self, id: u64, phase: TouchPhase, location: PhysicalPosition<f64>) -> bool
+    {
+        let Some(gesture) = self.touch_gestures.process(id, phase, location) else {
+            return false;
+        };
+        self.apply_gesture(gesture);
+
+        true
+    }
+
+    pub fn set_cursor_position(&mut self, position: PhysicalPosition<f64>)
+    {
+        self.move_cursor(position);
+    }
+
+    pub fn set_modifiers(&mut self, modifiers: ModifiersState)
+    {
+        self.modifiers = modifiers;
+    }
+
+    fn move_cursor(&mut self, position: PhysicalPosition<f64>) -> &'static str
+    {
+        self.cursor_position = position;
+        if self.update_gizmo() {
+            return "gizmo";
+        }
+        if self.render_pass_config.cursor_tint {
+            self.set_clear_color(Color {
+                r: position.x / self.size.width.max(1) as f64,
+                g: position.y / self.size.height.max(1) as f64,
+                b: 0.3,
+                a: 1.0
+            });
+        }
+
+        "cursor"
+    }
+
+    fn dispatch_action(&mut self, event: ActionEvent) -> Option<&'static str>
+    {
+        if self.camera_controller.process_action(event.action, event.pressed) {
+            self.camera_transition = None;
+            return Some("camera_controller");
+        }
+        if event.action == Action::Select && !event.pressed {
+            return self.end_gizmo_drag().then_some("gizmo");
+        }
+        // Only the depth of field focus keeps changing while its key is held.
+        if !event.pressed || (event.repeat && !event.action.is_continuous()) {
+            return None;
+        }
+
+        match event.action {
+            Action::Select if self.modifiers.shift_key() => {
+                self.place_transparent_instance(self.cursor_position);
+                Some("placement")
+            },
+            Action::Select if self.begin_gizmo_drag() => Some("gizmo"),
+            Action::Select => {
+                if let Err(e) = self.select_instance(self.pick(self.cursor_position)) {
+                    log::error!("Couldn't show the gizmo: {e}");
+                }
+                log::info!("Selected instance: {:?}", self.selected_instance);
+                Some("picking")
+            },
+            Action::ReloadShaders => {
+                self.reload_shaders();
+                Some("shader_reload")
+            },
+            Action::ToggleFullscreen => {
+                self.set_fullscreen(!self.is_fullscreen());
+                Some("fullscreen")
+            },
+            Action::Screenshot => {
+                self.request_screenshot();
+                Some("screenshot")
+            },
+            Action::ToggleRecording => {
+                if let Err(e) = self.toggle_recording() {
+                    log::error!("{e}");
+                }
+                Some("recording")
+            },
+            Action::ToggleWireframe => {
+                let view = if self.debug_view == DebugView::Wireframe {
+                    DebugView::Shaded
+                } else {
+                    DebugView::Wireframe
+                };
+                if let Err(e) = self.set_debug_view(view) {
+                    log::error!("Couldn't switch debug view: {e}");
+                }
+                Some("debug_view")
+            },
+            Action::CycleDebugView => {
+                if let Err(e) = self.cycle_debug_view() {
+                    log::error!("Couldn't switch debug view: {e}");
+                }
+                Some("debug_view")
+            },
+            Action::ToggleBounds => {
+                if let Err(e) = self.set_show_bounds(!self.show_bounds) {
+                    log::error!("Couldn't show bounds: {e}");
+                }
+                Some("bounds")
+            },
+            Action::CycleGizmoMode => {
+                self.set_gizmo_mode(self.gizmo.mode().next());
+                Some("gizmo")
+            },
+            Action::FrameSelected => self.frame_selected().then_some("camera_framing"),
+            Action::ToggleCameraRig => {
+                let enabled = !self.camera_rig_enabled;
+                self.set_camera_rig_enabled(enabled).then_some("camera_rig")
+            },
+            Action::CycleCamera => self.cycle_camera().then_some("active_camera"),
+            Action::CycleAntiAliasing => {
+                if let Err(e) = self.cycle_anti_aliasing() {
+                    log::error!("Couldn't switch anti-aliasing: {e}");
+                }
+                Some("anti_aliasing")
+            },
+            Action::ToggleSsao => {
+                if let Err(e) = self.toggle_ssao() {
+                    log::error!("Couldn't toggle SSAO: {e}");
+                }
+                Some("ssao")
+            },
+            Action::CycleFog => {
+                if let Err(e) = self.cycle_fog() {
+                    log::error!("Couldn't switch fog: {e}");
+                }
+                Some("fog")
+            },
+            Action::ToggleMotionBlur => {
+                if let Err(e) = self.toggle_motion_blur() {
+                    log::error!("Couldn't toggle motion blur: {e}");
+                }
+                Some("motion_blur")
+            },
+            Action::ToggleColorGrading => {
+                if let Err(e) = self.toggle_color_grading() {
+                    log::error!("Couldn't toggle color grading: {e}");
+                }
+                Some("color_grading")
+            },
+            Action::ToggleGlow => {
+                if let Err(e) = self.toggle_glow() {
+                    log::error!("Couldn't toggle glow: {e}");
+                }
+                Some("glow")
+            },
+            Action::ToggleDepthOfField => {
+                if let Err(e) = self.toggle_depth_of_field() {
+                    log::error!("Couldn't toggle depth of field: {e}");
+                }
+                Some("depth_of_field")
+            },
+            Action::ToggleEdgeDetection => {
+                if let Err(e) = self.toggle_edge_detection() {
+                    log::error!("Couldn't toggle edge detection: {e}");
+                }
+                Some("edge_detection")
+            },
+            Action::FocusNearer | Action::FocusFarther => {
+                self.scale_focus_distance(if event.action == Action::FocusNearer { 0.8 } else { 1.25 });
+                Some("depth_of_field")
+            },
+            Action::MoveForward | Action::MoveBackward | Action::MoveLeft | Action::MoveRight => None
+        }
+    }
+
+    // Buttons trigger their actions like keys do, the left stick moves the camera, the
+    // right one turns it and the triggers change how fast it moves.
+    fn poll_gamepads(&mut self)
+    {
+        let Some(gamepads) = &mut self.gamepads else {
+            return;
+        };
+        let actions = gamepads.poll(&self.input_map);
+        let axes = gamepads.axes();
+        self.camera_controller.set_analog_input(axes.left_stick, axes.right_stick, axes.speed_scale());
+        if let Some(follow_camera) = &mut self.follow_camera {
+            follow_camera.orbit_by(axes.right_stick * FOLLOW_ORBIT_SPEED);
+        }
+
+        for action in actions {
+            self.dispatch_action(action);
+        }
+    }
+
+    // One finger drags the view around, as if grabbing the scene. Two pinch to zoom and
+    // move together to pan.
+    fn apply_gesture(&mut self, gesture: Gesture)
+    {
+        self.camera_transition = None;
+        let height = self.size.height.max(1) as f32;
+        let look_radians = |delta: Vector2<f32>| delta * TOUCH_LOOK_SPEED / self.scale_factor as f32;
+        // The follow camera orbits and zooms instead, it can't pan off its target.
+        if let Some(follow_camera) = &mut self.follow_camera {
+            match gesture {
+                Gesture::Drag(delta) => follow_camera.orbit_by(look_radians(delta)),
+                Gesture::Pinch { zoom, .. } => follow_camera.zoom_by(zoom)
+            }
+            return;
+        }
+        match gesture {
+            Gesture::Drag(delta) => {
+                let radians = look_radians(delta);
+                self.camera_controller.look_by(Vector2::new(-radians.x, radians.y));
+            },
+            Gesture::Pinch { zoom, pan } => {
+                self.camera_controller.zoom_by(zoom);
+                self.camera_controller.pan_by(Vector2::new(-pan.x, pan.y) / height);
+            }
+        }
+    }
+
+    fn poll_settings(&mut self)
+    {
+        if let Some(settings) = self.settings_watcher.as_mut().and_then(SettingsWatcher::poll) {
+            log::info!("Settings changed, applying them");
+            self.apply_settings(settings);
+        }
+    }
+
+    // Applies the values `settings` has over those the renderer started with, where
+    // they differ from the settings applied last.
+    pub fn apply_settings(&mut self, settings: Settings)
+    {
+        let settings = self.startup_settings.merged(&settings);
+        let old = std::mem::replace(&mut self.settings, settings.clone());
+
+        if let Some(window) = self.window {
+            if let Some(title) = changed(&settings.window.title, &old.window.title) {
+                window.set_title(title);
+            }
+            let size = (settings.window.width, settings.window.height);
+            if size != (old.window.width, old.window.height) {
+                if let (Some(width), Some(height)) = size {
+                    let _ = window.request_inner_size(LogicalSize::new(width, height));
+                }
+            }
+            if let Some(resizable) = changed(&settings.window.resizable, &old.window.resizable) {
+                window.set_resizable(*resizable);
+            }
+            if let Some(decorations) = changed(&settings.window.decorations, &old.window.decorations) {
+                window.set_decorations(*decorations);
+            }
+        }
+        if let Some(fullscreen) = changed(&settings.window.fullscreen, &old.window.fullscreen) {
+            self.set_fullscreen(*fullscreen);
+        }
+
+        if let Some(vsync) = changed(&settings.render.vsync, &old.render.vsync) {
+            self.set_vsync(*vsync);
+        }
+        if let Some(anti_aliasing) = changed(&settings.render.anti_aliasing, &old.render.anti_aliasing) {
+            if let Err(e) = self.set_anti_aliasing(*anti_aliasing) {
+                log::error!("Couldn't switch anti-aliasing: {e}");
+            }
+        }
+        if let Some(upscaling) = changed(&settings.render.upscaling, &old.render.upscaling) {
+            if let Err(e) = self.set_upscaling(*upscaling) {
+                log::error!("Couldn't switch upscaling: {e}");
+            }
+        }
+        if let Some(render_scale) = changed(&settings.render.render_scale, &old.render.render_scale) {
+            if let Err(e) = self.set_render_scale(*render_scale) {
+                log::error!("Couldn't change the render scale: {e}");
+            }
+        }
+
+        if let Some(speed) = changed(&settings.camera.speed, &old.camera.speed) {
+            self.camera_controller.set_speed(*speed);
+        }
+
+        if settings.bindings != old.bindings {
+            self.input_map = InputMap::new(self.options.input_bindings.as_deref());
+            self.input_map.bind_all(&settings.bindings, "the settings");
+        }
+    }
+
+    pub fn input_map(&self) -> &InputMap
+    {
+        &self.input_map
+    }
+
+    // E.g. to let a settings screen rebind keys while running.
+    pub fn bind_action(&mut self, action: Action, bindings: &[Binding])
+    {
+        self.input_map.bind(action, bindings);
+    }
+
+    pub fn write_crash_report(&self, reason: &str)
+    {
+        self.crash_reporter.write_report(reason);
+    }
+
+    pub fn set_clear_color(&mut self, color: Color)
+    {
+        self.render_pass_config.color_load_op = LoadOp::Clear(color);
+    }
+
+    pub fn render_pass_config(&self) -> &RenderPassConfig
+    {
+        &self.render_pass_config
+    }
+
+    // Ctrl+1..9 saves the current view into a slot, 1..9 jumps back to it.
+    pub fn save_camera_bookmark(&mut self, slot: usize, name: &str) -> bool
+    {
+        let saved = self.camera_bookmarks.set(slot, CameraBookmark::from_camera(name,
+            &self.camera));
+        if saved {
+            log::info!("Saved camera bookmark {} \"{name}\"", slot + 1);
+        }
+
+        saved
+    }
+
+    // Follows the instance from behind until it's set back to None, or the instance is
+    // gone.
+    pub fn set_follow_camera(&mut self, follow_camera: Option<FollowCamera>)
+    {
+        self.follow_camera = follow_camera;
+        self.camera_transition = None;
+    }
+
+    // E.g. to change its distance or shoulder offset.
+    pub fn follow_camera_mut(&mut self) -> Option<&mut FollowCamera>
+    {
+        self.follow_camera.as_mut()
+    }
+
+    // The other instances and the skinned model are in the way, and so is the terrain.
+    fn update_follow_camera(&mut self, delta: f32)
+    {
+        let Some(follow_camera) = &mut self.follow_camera else {
+            return;
+        };
+        let Some(target) = self.instances.get(follow_camera.target) else {
+            log::warn!("The followed instance {} is gone, back to the free camera", follow_camera.target);
+            self.follow_camera = None;
+            return;
+        };
+
+        let mesh_bounds = self.pick_mesh.bounds();
+        let obstacles = self.instances.iter()
+            .enumerate()
+            .filter(|(index, _)| *index != follow_camera.target)
+            .map(|(_, instance)| instance.world_bounds(mesh_bounds).aabb)
+            .chain(self.skinned_model.as_ref().map(|model| model.bounds.aabb))
+            .collect::<Vec<_>>();
+        let terrain = self.terrain.as_ref();
+        follow_camera.update(&mut self.camera, Point3::from_vec(target.position), &obstacles,
+            |ray, max_distance| terrain?.intersect_ray(ray, max_distance).map(|hit| hit.distance), delta);
+    }
+
+    // Enables the rig right away, None goes back to the interactive controls.
+    pub fn set_camera_rig(&mut self, rig: Option<CameraRig>)
+    {
+        self.camera_rig_enabled = rig.is_some();
+        self.camera_rig = rig;
+        self.camera_transition = None;
+    }
+
+    pub fn camera_rig(&self) -> Option<&CameraRig>
+    {
+        self.camera_rig.as_ref()
+    }
+
+    // E.g. to move what it follows.
+    pub fn camera_rig_mut(&mut self) -> Option<&mut CameraRig>
+    {
+        self.camera_rig.as_mut()
+    }
+
+    // Switches between the rig and the interactive controls, keeping the rig where it
+    // was. False when there's no rig to switch to.
+    pub fn set_camera_rig_enabled(&mut self, enabled: bool) -> bool
+    {
+        if self.camera_rig.is_none() {
+            return false;
+        }
+        self.camera_rig_enabled = enabled;
+        self.camera_transition = None;
+
+        true
+    }
+
+    pub fn is_camera_rig_enabled(&self) -> bool
+    {
+        self.camera_rig_enabled
+    }
+
+    // Moves the camera smoothly until `aabb` fills the view, see Camera::frame_bounds.
+    pub fn frame_camera(&mut self, aabb: &Aabb)
+    {
+        let mut framed = self.camera.clone();
+        framed.frame_bounds(aabb);
+        self.camera_transition = Some(CameraTransition::new(&self.camera, &framed,
+            CAMERA_FRAMING_DURATION));
+    }
+
+    // Frames the selected instance, or the skinned model when none is selected. False
+    // when there's neither.
+    pub fn frame_selected(&mut self) -> bool
+    {
+        let mesh_bounds = self.pick_mesh.bounds();
+        let bounds = self.selected_instance
+            .and_then(|index| self.instances.get(index))
+            .map(|instance| instance.world_bounds(mesh_bounds))
+            .or_else(|| self.skinned_model_bounds());
+        let Some(bounds) = bounds else {
+            return false;
+        };
+        self.frame_camera(&bounds.aabb);
+
+        true
+    }
+
+    pub fn restore_camera_bookmark(&mut self, slot: usize) -> bool
+    {
+        let Some(bookmark) = self.camera_bookmarks.get(slot) else {
+            return false;
+        };
+        bookmark.apply(&mut self.camera);
+        self.camera_transition = None;
+
+        true
+    }
+
+    pub fn restore_camera_view(&mut self, name: &str) -> bool
+    {
+        let Some(bookmark) = self.camera_bookmarks.find(name) else {
+            return false;
+        };
+        bookmark.apply(&mut self.camera);
+        self.camera_transition = None;
+
+        true
+    }
+
+    // Cuts the main camera to look from `eye` at `target`.
+    pub fn set_camera(&mut self, eye: Point3<f32>, target: Point3<f32>)
+    {
+        self.camera.eye = eye;
+        self.camera.target = target;
+        self.camera_transition = None;
+    }
+
+    pub fn camera_bookmark(&self, slot: usize) -> Option<&CameraBookmark>
+    {
+        self.camera_bookmarks.get(slot)
+    }
+
+    pub fn debug_view(&self) -> DebugView
+    {
+        self.debug_view
+    }
+
+    // Debug pipelines are built the first time their view is selected and come from
+    // the pipeline cache afterwards, so cycling through the views compiles each once.
+    pub fn set_debug_view(&mut self, view: DebugView) -> Result<(), RendererError>
+    {
+        if !view.is_supported(self.capabilities.features) {
+            log::warn!("{} isn't supported by this device", view.label());
+            return Ok(());
+        }
+
+        if self.debug_view != DebugView::Shaded {
+            self.unregister_pipeline(self.debug_view.label());
+        }
+        self.debug_pipeline = if view == DebugView::Shaded {
+            None
+        } else {
+            let pipeline = Self::create_debug_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count,
+                view, &[&self.texture_bind_group_layout,
+                    &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout,
+                    &self.instance_bind_group_layout])?;
+            self.crash_reporter.register_pipeline(&DebugLabels::new(view.label()).pipeline(),
+                ShaderHandle::DebugView.filename());
+            Some(pipeline)
+        };
+
+        log::info!("Debug view: {}", view.label());
+        self.debug_view = view;
+
+        Ok(())
+    }
+
+    // Replaces the shaders' sources, e.g. with ones fetched on the web, and reloads them.
+    pub fn set_shader_sources(&mut self, sources: Vec<(ShaderHandle, String)>) -> bool
+    {
+        for (shader, source) in sources {
+            self.shader_registry.set_source(shader, source);
+        }
+        self.reload_shaders()
+    }
+
+    // Rebuilds the pipelines from the shader sources. When a shader fails to compile
+    // the error is logged and the last good pipeline keeps rendering.
+    pub fn reload_shaders(&mut self) -> bool
+    {
+        self.pipeline_cache.clear();
+        let bind_group_layouts = [&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+            &self.vertex_animation_bind_group_layout, &self.instance_bind_group_layout];
+        let mut reloaded = true;
+
+        match Self::create_render_pipeline(&mut self.pipeline_cache, &self.device,
+            &self.shader_registry, &self.config, self.sample_count, &bind_group_layouts) {
+            Ok(pipeline) => self.render_pipeline = pipeline,
+            Err(e) => {
+                log::error!("Keeping the last good {INSTANCE_PIPELINE_LABEL} pipeline: {e}");
+                reloaded = false;
+            }
+        }
+
+        match Self::create_transparent_pipeline(&mut self.pipeline_cache, &self.device,
+            &self.shader_registry, &self.config, self.sample_count, &bind_group_layouts) {
+            Ok(pipeline) => self.transparent_pipeline = pipeline,
+            Err(e) => {
+                log::error!("Keeping the last good {TRANSPARENT_PIPELINE_LABEL} pipeline: {e}");
+                reloaded = false;
+            }
+        }
+
+        match Self::create_billboard_pipeline(&mut self.pipeline_cache, &self.device,
+            &self.shader_registry, &self.config, self.sample_count,
+            &[&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+                self.billboard_buffer.bind_group_layout()]) {
+            Ok(pipeline) => self.billboard_pipeline = pipeline,
+            Err(e) => {
+                log::error!("Keeping the last good {BILLBOARD_PIPELINE_LABEL} pipeline: {e}");
+                reloaded = false;
+            }
+        }
+
+        if let Some(skinned_mesh) = &self.skinned_mesh {
+            match Self::create_skinned_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count,
+                &[&self.texture_bind_group_layout,
+                    &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout,
+                    skinned_mesh.joint_bind_group_layout()]) {
+                Ok(pipeline) => self.skinned_pipeline = Some(pipeline),
+                Err(e) => {
+                    log::error!("Keeping the last good {SKINNED_PIPELINE_LABEL} pipeline: {e}");
+                    reloaded = false;
+                }
+            }
+        }
+
+        let material_features = self.material_pipelines.keys().copied().collect::<Vec<_>>();
+        for features in material_features {
+            let Some(layout) = self.material_layouts.get(&features) else {
+                continue;
+            };
+            let last_layout = if features.skinning {
+                match &self.skinned_mesh {
+                    Some(skinned_mesh) => skinned_mesh.joint_bind_group_layout(),
+                    None => continue
+                }
+            } else {
+                &self.instance_bind_group_layout
+            };
+            match Self::create_material_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count, features, &[layout,
+                    &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout,
+                    last_layout]) {
+                Ok(pipeline) => {
+                    self.material_pipelines.insert(features, Some(pipeline));
+                },
+                Err(e) => {
+                    log::error!("Keeping the last good {MATERIAL_PIPELINE_LABEL} {} pipeline: {e}",
+                        features.name());
+                    reloaded = false;
+                }
+            }
+        }
+
+        let decal_features = self.decal_pipelines.keys().copied().collect::<Vec<_>>();
+        for features in decal_features {
+            let Some(layout) = self.material_layouts.get(&features) else {
+                continue;
+            };
+            match Self::create_decal_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count, features, &[layout,
+                    &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout,
+                    self.decal_buffer.bind_group_layout()]) {
+                Ok(pipeline) => {
+                    self.decal_pipelines.insert(features, Some(pipeline));
+                },
+                Err(e) => {
+                    log::error!("Keeping the last good {DECAL_PIPELINE_LABEL} {} pipeline: {e}",
+                        features.name());
+                    reloaded = false;
+                }
+            }
+        }
+
+        if self.terrain_mesh.is_some() {
+            match Self::create_terrain_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count,
+                &[&self.camera_bind_group_layout]) {
+                Ok(pipeline) => self.terrain_pipeline = Some(pipeline),
+                Err(e) => {
+                    log::error!("Keeping the last good {TERRAIN_PIPELINE_LABEL} pipeline: {e}");
+                    reloaded = false;
+                }
+            }
+        }
+
+        if let Some(gpu_culling) = &self.gpu_culling {
+            match Self::create_cull_pipeline(&self.device, &self.shader_registry,
+                &[gpu_culling.bind_group_layout()]) {
+                Ok(pipeline) => self.cull_pipeline = Some(pipeline),
+                Err(e) => {
+                    log::error!("Keeping the last good {CULL_PIPELINE_LABEL} pipeline: {e}");
+                    reloaded = false;
+                }
+            }
+        }
+
+        if self.vegetation_pipeline.is_some() {
+            match Self::create_vegetation_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count,
+                &[&self.texture_bind_group_layout,
+                    &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout]) {
+                Ok(pipeline) => self.vegetation_pipeline = Some(pipeline),
+                Err(e) => {
+                    log::error!("Keeping the last good {VEGETATION_PIPELINE_LABEL} pipeline: {e}");
+                    reloaded = false;
+                }
+            }
+        }
+
+        if let Some(water) = &self.water {
+            match Self::create_water_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count,
+                &[&self.camera_bind_group_layout, water.bind_group_layout()]) {
+                Ok(pipeline) => self.water_pipeline = Some(pipeline),
+                Err(e) => {
+                    log::error!("Keeping the last good {WATER_PIPELINE_LABEL} pipeline: {e}");
+                    reloaded = false;
+                }
+            }
+        }
+
+        if self.debug_lines_pipeline.is_some() {
+            match Self::create_debug_lines_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count,
+                &[&self.camera_bind_group_layout]) {
+                Ok(pipeline) => self.debug_lines_pipeline = Some(pipeline),
+                Err(e) => {
+                    log::error!("Keeping the last good {DEBUG_LINES_PIPELINE_LABEL} pipeline: {e}");
+                    reloaded = false;
+                }
+            }
+        }
+
+        if self.debug_view != DebugView::Shaded {
+            match Self::create_debug_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count,
+                self.debug_view, &bind_group_layouts) {
+                Ok(pipeline) => self.debug_pipeline = Some(pipeline),
+                Err(e) => {
+                    log::error!("Keeping the last good {} pipeline: {e}", self.debug_view.label());
+                    reloaded = false;
+                }
+            }
+        }
+
+        for effect in self.post_effects.clone() {
+            match self.build_post_effect_pipeline(effect) {
+                Ok(pipeline) => {
+                    self.post_effect_pipelines.insert(effect, pipeline);
+                },
+                Err(e) => {
+                    log::error!("Keeping the last good {} pipeline: {e}", effect.label());
+                    reloaded = false;
+                }
+            }
+        }
+
+        if self.taa.is_some() {
+            if let Err(e) = self.create_taa_pipelines() {
+                log::error!("Keeping the last good {TAA_LABEL} pipelines: {e}");
+                reloaded = false;
+            }
+        }
+        if self.upscale_pipeline.is_some() {
+            match Self::create_upscale_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.upscaling,
+                &[self.post_process.bind_group_layout()]) {
+                Ok(pipeline) => self.upscale_pipeline = Some(pipeline),
+                Err(e) => {
+                    log::error!("Keeping the last good {UPSCALE_PIPELINE_LABEL} pipeline: {e}");
+                    reloaded = false;
+                }
+            }
+        }
+        if let Some(edge_detection) = &mut self.edge_detection {
+            if let Err(e) = edge_detection.rebuild(&self.device, &self.shader_registry) {
+                log::error!("Keeping the last good {EDGE_DETECTION_LABEL} pipeline: {e}");
+                reloaded = false;
+            }
+        }
+        if let Some(texture_generator) = &mut self.texture_generator {
+            if let Err(e) = texture_generator.rebuild(&self.device, &self.shader_registry) {
+                log::error!("Keeping the last good {TEXTURE_GENERATOR_LABEL} pipeline: {e}");
+                reloaded = false;
+            }
+        }
+        if let Some(outline) = &mut self.outline {
+            if let Err(e) = outline.rebuild(&mut self.pipeline_cache, &self.device, &self.shader_registry,
+                &self.config, &[&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+                    &self.vertex_animation_bind_group_layout, &self.instance_bind_group_layout]) {
+                log::error!("Keeping the last good {OUTLINE_LABEL} pipelines: {e}");
+                reloaded = false;
+            }
+        }
+        if self.motion_blur.is_some() {
+            if let Err(e) = self.create_motion_vector_pipelines() {
+                log::error!("Keeping the last good {MOTION_VECTORS_PIPELINE_LABEL} pipelines: {e}");
+                reloaded = false;
+            }
+        }
+        if self.ssao.is_some() {
+            if let Err(e) = self.create_ssao_pipelines() {
+                log::error!("Keeping the last good {SSAO_LABEL} pipelines: {e}");
+                reloaded = false;
+            }
+        }
+        if self.msaa.is_some() {
+            if let Err(e) = self.create_msaa_pipelines() {
+                log::error!("Keeping the last good {MSAA_LABEL} pipelines: {e}");
+                reloaded = false;
+            }
+        }
+
+        if reloaded {
+            log::info!("Reloaded shaders");
+        }
+
+        reloaded
+    }
+
+    pub fn cycle_debug_view(&mut self) -> Result<(), RendererError>
+    {
+        self.set_debug_view(self.debug_view.next(self.capabilities.features))
+    }
+
+    pub fn set_render_pass_config(&mut self, config: RenderPassConfig)
+    {
+        self.render_pass_config = config;
+    }
+
+    pub fn pick(&self, cursor: PhysicalPosition<f64>) -> Option<usize>
+    {
+        let ray = self.camera.screen_to_ray(cursor, self.size);
+
+        self.raycast(&ray, None).map(|(index, _)| index)
+    }
+
+    // How many logical pixels of error a simplified instance mesh may show before a
+    // more detailed level is used. 0 always draws full detail.
+    pub fn set_lod_error_threshold(&mut self, pixels: f32)
+    {
+        self.lod_error_threshold = pixels.max(0.0);
+    }
+
+    // Share of the distance, before a coarser level takes over, over which instances
+    // cross-fade between the two levels. 0 switches them outright.
+    pub fn set_lod_fade_band(&mut self, fraction: f32)
+    {
+        self.lod_fade_band = fraction.clamp(0.0, 1.0);
+    }
+
+    // Frustum culls the instances against the main camera in a compute pass, and draws
+    // what's left with indirect draws. Without compute shaders and indirect draws (e.g.
+    // WebGL2) every instance is drawn, as when it's off.
+    pub fn set_gpu_culling(&mut self, enabled: bool) -> Result<(), RendererError>
+    {
+        if !enabled {
+            self.gpu_culling = None;
+            self.cull_pipeline = None;
+            self.unregister_pipeline(CULL_PIPELINE_LABEL);
+            return Ok(());
+        }
+        if !GpuCulling::is_supported(&self.capabilities) {
+            log::warn!("{CULL_PIPELINE_LABEL} needs compute shaders and indirect draws, drawing every instance");
+            return Ok(());
+        }
+        if self.gpu_culling.is_some() {
+            return Ok(());
+        }
+
+        let gpu_culling = GpuCulling::new(&self.device, CULL_PIPELINE_LABEL,
+            &self.instance_bind_group_layout);
+        self.cull_pipeline = Some(Self::create_cull_pipeline(&self.device, &self.shader_registry,
+            &[gpu_culling.bind_group_layout()])?);
+        self.crash_reporter.register_pipeline(&DebugLabels::new(CULL_PIPELINE_LABEL).pipeline(),
+            ShaderHandle::Cull.filename());
+        self.gpu_culling = Some(gpu_culling);
+
+        Ok(())
+    }
+
+    pub fn gpu_culling(&self) -> bool
+    {
+        self.gpu_culling.is_some()
+    }
+
+    // World space bounds of an instance, for culling and picking outside the renderer.
+    pub fn instance_bounds(&self, index: usize) -> Option<Bounds>
+    {
+        self.instances.get(index)
+            .map(|instance| instance.world_bounds(self.pick_mesh.bounds()))
+    }
+
+    // The skinned model is drawn at the origin, so its bounds are already in world space.
+    pub fn skinned_model_bounds(&self) -> Option<Bounds>
+    {
+        self.skinned_model.as_ref().map(|model| model.bounds)
+    }
+
+    pub fn show_bounds(&self) -> bool
+    {
+        self.show_bounds
+    }
+
+    // Draws the boxes and spheres of every instance, the skinned model and the terrain
+    // chunks as lines on top of the scene. B toggles it.
+    pub fn set_show_bounds(&mut self, show: bool) -> Result<(), RendererError>
+    {
+        if show {
+            self.create_debug_lines_pipeline_once()?;
+        }
+        self.show_bounds = show;
+
+        Ok(())
+    }
+
+    fn create_debug_lines_pipeline_once(&mut self) -> Result<(), RendererError>
+    {
+        if self.debug_lines_pipeline.is_none() {
+            self.debug_lines_pipeline = Some(Self::create_debug_lines_pipeline(
+                &mut self.pipeline_cache, &self.device, &self.shader_registry, &self.config,
+                self.sample_count, &[&self.camera_bind_group_layout])?);
+            self.crash_reporter.register_pipeline(
+                &DebugLabels::new(DEBUG_LINES_PIPELINE_LABEL).pipeline(),
+                ShaderHandle::DebugLines.filename());
+        }
+
+        Ok(())
+    }
+
+    pub fn selected_instance(&self) -> Option<usize>
+    {
+        self.selected_instance
+    }
+
+    // Shows the gizmo on the instance, None hides it. Ends any drag in progress.
+    pub fn select_instance(&mut self, index: Option<usize>) -> Result<(), RendererError>
+    {
+        self.end_gizmo_drag();
+        if index.is_some() {
+            self.create_debug_lines_pipeline_once()?;
+        }
+        self.selected_instance = index.filter(|index| *index < self.instances.len());
+
+        Ok(())
+    }
+
+    pub fn gizmo_mode(&self) -> GizmoMode
+    {
+        self.gizmo.mode()
+    }
+
+    pub fn set_gizmo_mode(&mut self, mode: GizmoMode)
+    {
+        self.end_gizmo_drag();
+        self.gizmo.set_mode(mode);
+        log::info!("Gizmo: {mode:?}");
+    }
+
+    // Every finished gizmo drag since the last call, oldest first.
+    pub fn take_transform_edits(&mut self) -> Vec<TransformEdit>
+    {
+        std::mem::take(&mut self.transform_edits)
+    }
+
+    fn gizmo_transform(&self) -> Option<InstanceTransform>
+    {
+        self.instances.get(self.selected_instance?).map(InstanceTransform::of)
+    }
+
+    fn begin_gizmo_drag(&mut self) -> bool
+    {
+        let (Some(index), Some(transform)) = (self.selected_instance, self.gizmo_transform()) else {
+            return false;
+        };
+        let ray = self.camera.screen_to_ray(self.cursor_position, self.size);
+
+        self.gizmo.begin_drag(&ray, index, &transform, self.camera.eye)
+    }
+
+    // Follows the cursor with the dragged instance, or highlights the handle under it.
+    fn update_gizmo(&mut self) -> bool
+    {
+        let Some(transform) = self.gizmo_transform() else {
+            return false;
+        };
+        let ray = self.camera.screen_to_ray(self.cursor_position, self.size);
+        if !self.gizmo.is_dragging() {
+            return self.gizmo.hover(&ray, &transform, self.camera.eye);
+        }
+
+        if let Some((index, transform)) = self.gizmo.drag(&ray) {
+            if let Some(instance) = self.instances.get_mut(index) {
+                transform.apply(instance);
+            }
+        }
+
+        true
+    }
+
+    fn end_gizmo_drag(&mut self) -> bool
+    {
+        if !self.gizmo.is_dragging() {
+            return false;
+        }
+        if let Some(edit) = self.gizmo.end_drag(self.gizmo_transform()) {
+            log::info!("Instance {} moved to {:?}", edit.instance, edit.after);
+            self.transform_edits.push(edit);
+        }
+
+        true
+    }
+
+    pub fn render_scale(&self) -> f32
+    {
+        self.render_scale
+    }
+
+    // Renders everything before the outline and the viewports at `scale` times the
+    // surface's size, from MIN_RENDER_SCALE to MAX_RENDER_SCALE, and upscales it to the
+    // surface with upscaling. Below 1 it trades sharpness for speed, above 1 it's
+    // supersampled.
+    pub fn set_render_scale(&mut self, scale: f32) -> Result<(), RendererError>
+    {
+        let scale = clamp_render_scale(scale);
+        if scale == self.render_scale {
+            return Ok(());
+        }
+
+        self.upscale_pipeline = None;
+        self.unregister_pipeline(UPSCALE_PIPELINE_LABEL);
+        if scale != 1.0 {
+            self.upscale_pipeline = Some(Self::create_upscale_pipeline(&mut self.pipeline_cache,
+                &self.device, &self.shader_registry, &self.config, self.upscaling,
+                &[self.post_process.bind_group_layout()])?);
+            self.crash_reporter.register_pipeline(&DebugLabels::new(UPSCALE_PIPELINE_LABEL).pipeline(),
+                ShaderHandle::Blit.filename());
+        }
+        self.render_scale = scale;
+        self.resize_render_targets();
+        log::info!("Render scale: {:.0}%", scale * 100.0);
+
+        Ok(())
+    }
+
+    pub fn upscaling(&self) -> Upscaling
+    {
+        self.upscaling
+    }
+
+    pub fn set_upscaling(&mut self, upscaling: Upscaling) -> Result<(), RendererError>
+    {
+        if self.upscale_pipeline.is_some() {
+            self.upscale_pipeline = Some(Self::create_upscale_pipeline(&mut self.pipeline_cache,
+                &self.device, &self.shader_registry, &self.config, upscaling,
+                &[self.post_process.bind_group_layout()])?);
+        }
+        log::info!("Upscaling: {}", upscaling.label());
+        self.upscaling = upscaling;
+
+        Ok(())
+    }
+
+    // Everything sized by render_config, after the surface or the render scale changed.
+    fn resize_render_targets(&mut self)
+    {
+        self.render_config = scaled_config(&self.config, self.render_scale);
+        self.depth_texture = Texture::create_depth_texture(&self.device, &self.render_config,
+            Texture::DEPTH_FORMAT, 1, "Depth Texture");
+        if let Some(taa) = &mut self.taa {
+            taa.resize(&self.device, &self.render_config);
+        }
+        self.transient_textures.clear();
+    }
+
+    pub fn anti_aliasing(&self) -> AntiAliasing
+    {
+        self.anti_aliasing
+    }
+
+    // TAA's history and pipelines are created when it's turned on and dropped when it's
+    // turned off again, the camera jitter goes with it. FXAA is a post effect, MSAA
+    // rebuilds the scene pipelines with its sample count. T cycles through the modes,
+    // the GPU timings show what each costs.
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) -> Result<(), RendererError>
+    {
+        if !anti_aliasing.is_supported(&self.capabilities) {
+            log::warn!("{} isn't supported here", anti_aliasing.label());
+            return Ok(());
+        }
+
+        if anti_aliasing.sample_count() != self.sample_count
+            && !self.set_sample_count(anti_aliasing.sample_count()) {
+            return Ok(());
+        }
+        if let Some(effect) = self.anti_aliasing.post_effect() {
+            self.set_post_effect(effect, false)?;
+        }
+        if let Some(effect) = anti_aliasing.post_effect() {
+            self.set_post_effect(effect, true)?;
+        }
+        if anti_aliasing.is_temporal() {
+            if self.taa.is_none() {
+                self.taa = Some(Taa::new(&self.device, TAA_LABEL, &self.render_config, &mut self.samplers));
+            }
+            if let Err(e) = self.create_taa_pipelines() {
+                self.taa = None;
+                return Err(e);
+            }
+        } else {
+            self.taa = None;
+            self.taa_resolve_pipeline = None;
+            self.unregister_pipeline(TAA_RESOLVE_PIPELINE_LABEL);
+            if self.edge_detection.is_none() {
+                self.blit_pipeline = None;
+                self.unregister_pipeline(BLIT_PIPELINE_LABEL);
+            }
+            self.create_motion_vector_pipelines()?;
+        }
+
+        self.set_camera_jitter(anti_aliasing.is_temporal());
+        log::info!("Anti-aliasing: {}", anti_aliasing.label());
+        self.anti_aliasing = anti_aliasing;
+
+        Ok(())
+    }
+
+    pub fn cycle_anti_aliasing(&mut self) -> Result<(), RendererError>
+    {
+        self.set_anti_aliasing(self.anti_aliasing.next(&self.capabilities))
+    }
+
+    // Rebuilds every pipeline the scene is drawn with for the passes' new sample count.
+    // False when one of them didn't build, which is logged, and the last sample count
+    // is kept.
+    fn set_sample_count(&mut self, sample_count: u32) -> bool
+    {
+        let last_sample_count = self.sample_count;
+        self.sample_count = sample_count;
+        self.msaa = (sample_count > 1).then(|| Msaa::new(&self.device, MSAA_LABEL));
+        self.depth_resolve_pipeline = None;
+        self.viewport_composite_pipeline = None;
+        for label in [DEPTH_RESOLVE_PIPELINE_LABEL, VIEWPORT_COMPOSITE_PIPELINE_LABEL] {
+            self.unregister_pipeline(label);
+        }
+        if self.reload_shaders() {
+            return true;
+        }
+
+        log::error!("Couldn't rebuild the pipelines for {sample_count} samples, staying at \
+            {last_sample_count}");
+        self.sample_count = last_sample_count;
+        self.msaa = (last_sample_count > 1).then(|| Msaa::new(&self.device, MSAA_LABEL));
+        self.reload_shaders();
+
+        false
+    }
+
+    // MSAA's depth resolve and viewport composite, the viewports' resolved frame bound
+    // after the multisampled depth.
+    fn create_msaa_pipelines(&mut self) -> Result<(), RendererError>
+    {
+        let Some(msaa) = &self.msaa else {
+            return Ok(());
+        };
+
+        let mut builder = PipelineBuilder::builder();
+        builder.set_label(DEPTH_RESOLVE_PIPELINE_LABEL);
+        Msaa::configure_depth_resolve(&mut builder);
+        let depth_resolve_pipeline = self.pipeline_cache.get_or_build(&mut builder, &self.device,
+            &self.shader_registry, &[msaa.bind_group_layout()])?;
+
+        let mut builder = PipelineBuilder::builder();
+        builder.set_label(VIEWPORT_COMPOSITE_PIPELINE_LABEL);
+        Msaa::configure_composite(&mut builder);
+        builder
+            .set_pixel_format(self.config.format)
+            .set_blend_mode(BlendMode::Premultiplied);
+        let viewport_composite_pipeline = self.pipeline_cache.get_or_build(&mut builder,
+            &self.device, &self.shader_registry,
+            &[msaa.bind_group_layout(), self.post_process.bind_group_layout()])?;
+
+        for label in [DEPTH_RESOLVE_PIPELINE_LABEL, VIEWPORT_COMPOSITE_PIPELINE_LABEL] {
+            self.crash_reporter.register_pipeline(&DebugLabels::new(label).pipeline(),
+                ShaderHandle::Msaa.filename());
+        }
+        self.depth_resolve_pipeline = Some(depth_resolve_pipeline);
+        self.viewport_composite_pipeline = Some(viewport_composite_pipeline);
+
+        Ok(())
+    }
+
+    pub fn post_effects(&self) -> &[PostEffect]
+    {
+        &self.post_effects
+    }
+
+    // An effect's pipeline is built when it's first enabled and comes from the pipeline
+    // cache afterwards.
+    pub fn set_post_effect(&mut self, effect: PostEffect, enabled: bool) -> Result<(), RendererError>
+    {
+        if !enabled {
+            self.post_effects.retain(|enabled_effect| *enabled_effect != effect);
+            self.post_effect_pipelines.remove(&effect);
+            self.unregister_pipeline(effect.label());
+            match effect {
+                PostEffect::Ssao => {
+                    self.ssao = None;
+                    self.ssao_occlusion_pipeline = None;
+                    self.ssao_blur_pipeline = None;
+                    for label in [SSAO_OCCLUSION_PIPELINE_LABEL, SSAO_BLUR_PIPELINE_LABEL] {
+                        self.unregister_pipeline(label);
+                    }
+                },
+                PostEffect::Fog => self.fog = None,
+                PostEffect::DepthOfField => self.depth_of_field = None,
+                PostEffect::MotionBlur => {
+                    self.motion_blur = None;
+                    return self.create_motion_vector_pipelines();
+                },
+                PostEffect::Glow => self.glow = None,
+                PostEffect::ColorGrading => self.color_grading = None,
+                PostEffect::Fxaa => ()
+            }
+            return Ok(());
+        }
+        if self.post_effects.contains(&effect) {
+            return Ok(());
+        }
+        if !effect.is_supported(&self.capabilities) {
+            log::warn!("{} isn't supported here", effect.label());
+            return self.set_post_effect(effect, false);
+        }
+
+        match effect {
+            PostEffect::Ssao => {
+                if self.ssao.is_none() {
+                    self.ssao = Some(Ssao::new(&self.device, SSAO_LABEL, SsaoOptions::default()));
+                }
+                if let Err(e) = self.create_ssao_pipelines() {
+                    self.ssao = None;
+                    return Err(e);
+                }
+            },
+            PostEffect::Fog if self.fog.is_none() => {
+                self.fog = Some(Fog::new(&self.device, FOG_LABEL, FogOptions::default()));
+            },
+            PostEffect::DepthOfField if self.depth_of_field.is_none() => {
+                self.depth_of_field = Some(DepthOfField::new(&self.device, DEPTH_OF_FIELD_LABEL,
+                    DepthOfFieldOptions::default()));
+            },
+            PostEffect::MotionBlur => {
+                if self.motion_blur.is_none() {
+                    self.motion_blur = Some(MotionBlur::new(&self.device, MOTION_BLUR_LABEL,
+                        MotionBlurOptions::default()));
+                }
+                if let Err(e) = self.create_motion_vector_pipelines() {
+                    self.motion_blur = None;
+                    return Err(e);
+                }
+            },
+            PostEffect::Glow if self.glow.is_none() => {
+                self.glow = Some(Glow::new(&self.device, GLOW_LABEL, GlowOptions::default()));
+            },
+            PostEffect::ColorGrading if self.color_grading.is_none() => {
+                self.color_grading = Some(ColorGrading::new(&self.device, &self.queue, COLOR_GRADING_LABEL,
+                    ColorGradingOptions::default(), &mut self.samplers));
+            },
+            _ => ()
+        }
+        let pipeline = self.build_post_effect_pipeline(effect)?;
+        self.crash_reporter.register_pipeline(&DebugLabels::new(effect.label()).pipeline(),
+            effect.shader().filename());
+        self.post_effect_pipelines.insert(effect, pipeline);
+        self.post_effects.push(effect);
+        self.post_effects.sort();
+
+        Ok(())
+    }
+
+    pub fn ssao_options(&self) -> Option<SsaoOptions>
+    {
+        self.ssao.as_ref().map(Ssao::options)
+    }
+
+    // None turns SSAO off. Changing the options of SSAO that's already on doesn't touch
+    // the pipelines, the sample count is a uniform.
+    pub fn set_ssao(&mut self, options: Option<SsaoOptions>) -> Result<(), RendererError>
+    {
+        let Some(options) = options else {
+            return self.set_post_effect(PostEffect::Ssao, false);
+        };
+
+        match &mut self.ssao {
+            Some(ssao) => ssao.set_options(options),
+            None => self.ssao = Some(Ssao::new(&self.device, SSAO_LABEL, options))
+        }
+        self.set_post_effect(PostEffect::Ssao, true)
+    }
+
+    pub fn toggle_ssao(&mut self) -> Result<(), RendererError>
+    {
+        let enabled = self.ssao.is_some();
+        self.set_ssao((!enabled).then(SsaoOptions::default))
+    }
+
+    pub fn fog_options(&self) -> Option<FogOptions>
+    {
+        self.fog.as_ref().map(Fog::options)
+    }
+
+    // None turns fog off, and the sky with it. The options are uniforms, so they can
+    // change every frame, e.g. to move the sun.
+    pub fn set_fog(&mut self, options: Option<FogOptions>) -> Result<(), RendererError>
+    {
+        let Some(options) = options else {
+            return self.set_post_effect(PostEffect::Fog, false);
+        };
+
+        match &mut self.fog {
+            Some(fog) => fog.set_options(options),
+            None => self.fog = Some(Fog::new(&self.device, FOG_LABEL, options))
+        }
+        self.set_post_effect(PostEffect::Fog, true)
+    }
+
+    // Cycles from no fog to fog against the clear color to fog under the sky model.
+    pub fn cycle_fog(&mut self) -> Result<(), RendererError>
+    {
+        match self.fog_options() {
+            None => self.set_fog(Some(FogOptions::default())),
+            Some(options) if options.sky.is_none() => self.set_fog(Some(FogOptions {
+                sky: Some(SkyOptions::default()),
+                ..options
+            })),
+            Some(_) => self.set_fog(None)
+        }
+    }
+
+    pub fn depth_of_field_options(&self) -> Option<DepthOfFieldOptions>
+    {
+        self.depth_of_field.as_ref().map(DepthOfField::options)
+    }
+
+    // None turns depth of field off. The options are uniforms, so changing them every
+    // frame, e.g. to pull focus, is fine.
+    pub fn set_depth_of_field(&mut self, options: Option<DepthOfFieldOptions>) -> Result<(), RendererError>
+    {
+        let Some(options) = options else {
+            return self.set_post_effect(PostEffect::DepthOfField, false);
+        };
+
+        match &mut self.depth_of_field {
+            Some(depth_of_field) => depth_of_field.set_options(options),
+            None => self.depth_of_field = Some(DepthOfField::new(&self.device, DEPTH_OF_FIELD_LABEL,
+                options))
+        }
+        self.set_post_effect(PostEffect::DepthOfField, true)
+    }
+
+    pub fn toggle_depth_of_field(&mut self) -> Result<(), RendererError>
+    {
+        let enabled = self.depth_of_field.is_some();
+        self.set_depth_of_field((!enabled).then(DepthOfFieldOptions::default))
+    }
+
+    pub fn motion_blur_options(&self) -> Option<MotionBlurOptions>
+    {
+        self.motion_blur.as_ref().map(MotionBlur::options)
+    }
+
+    // None turns motion blur off. It needs motion vectors, so with TAA off turning it
+    // on adds the motion vector pass.
+    pub fn set_motion_blur(&mut self, options: Option<MotionBlurOptions>) -> Result<(), RendererError>
+    {
+        let Some(options) = options else {
+            return self.set_post_effect(PostEffect::MotionBlur, false);
+        };
+
+        match &mut self.motion_blur {
+            Some(motion_blur) => motion_blur.set_options(options),
+            None => self.motion_blur = Some(MotionBlur::new(&self.device, MOTION_BLUR_LABEL, options))
+        }
+        self.set_post_effect(PostEffect::MotionBlur, true)
+    }
+
+    pub fn toggle_motion_blur(&mut self) -> Result<(), RendererError>
+    {
+        let enabled = self.motion_blur.is_some();
+        self.set_motion_blur((!enabled).then(MotionBlurOptions::default))
+    }
+
+    pub fn glow_options(&self) -> Option<GlowOptions>
+    {
+        self.glow.as_ref().map(Glow::options)
+    }
+
+    // None turns the glow off. Emissive materials feed it, see
+    // Material::set_emissive_intensity.
+    pub fn set_glow(&mut self, options: Option<GlowOptions>) -> Result<(), RendererError>
+    {
+        let Some(options) = options else {
+            return self.set_post_effect(PostEffect::Glow, false);
+        };
+
+        match &mut self.glow {
+            Some(glow) => glow.set_options(options),
+            None => self.glow = Some(Glow::new(&self.device, GLOW_LABEL, options))
+        }
+        self.set_post_effect(PostEffect::Glow, true)
+    }
+
+    pub fn toggle_glow(&mut self) -> Result<(), RendererError>
+    {
+        let enabled = self.glow.is_some();
+        self.set_glow((!enabled).then(GlowOptions::default))
+    }
+
+    pub fn color_grading_options(&self) -> Option<ColorGradingOptions>
+    {
+        self.color_grading.as_ref().map(ColorGrading::options)
+    }
+
+    // None turns color grading off, which drops its LUT too. The options are uniforms,
+    // so they can change every frame, e.g. to adapt the exposure.
+    pub fn set_color_grading(&mut self, options: Option<ColorGradingOptions>) -> Result<(), RendererError>
+    {
+        let Some(options) = options else {
+            return self.set_post_effect(PostEffect::ColorGrading, false);
+        };
+
+        match &mut self.color_grading {
+            Some(color_grading) => color_grading.set_options(options),
+            None => self.color_grading = Some(ColorGrading::new(&self.device, &self.queue,
+                COLOR_GRADING_LABEL, options, &mut self.samplers))
+        }
+        self.set_post_effect(PostEffect::ColorGrading, true)
+    }
+
+    pub fn toggle_color_grading(&mut self) -> Result<(), RendererError>
+    {
+        let enabled = self.color_grading.is_some();
+        self.set_color_grading((!enabled).then(ColorGradingOptions::default))
+    }
+
+    // Turns color grading on with the default options if it's off. None goes back to
+    // the identity LUT.
+    pub fn set_color_grading_lut(&mut self, lut: Option<CubeLut>) -> Result<(), RendererError>
+    {
+        if self.color_grading.is_none() {
+            self.set_color_grading(Some(ColorGradingOptions::default()))?;
+        }
+        if let Some(color_grading) = &mut self.color_grading {
+            color_grading.set_lut(&self.device, &self.queue, lut);
+        }
+
+        Ok(())
+    }
+
+    pub fn edge_detection_options(&self) -> Option<EdgeDetectionOptions>
+    {
+        self.edge_detection.as_ref().map(EdgeDetection::options)
+    }
+
+    // Outlines what's in the frame by the changes in its brightness, in a compute pass
+    // before the post effects. None turns it off, as does a device without compute
+    // shaders (e.g. WebGL2).
+    pub fn set_edge_detection(&mut self, options: Option<EdgeDetectionOptions>) -> Result<(), RendererError>
+    {
+        let Some(options) = options else {
+            self.edge_detection = None;
+            self.unregister_pipeline(EDGE_DETECTION_LABEL);
+            if self.taa.is_none() {
+                self.blit_pipeline = None;
+                self.unregister_pipeline(BLIT_PIPELINE_LABEL);
+            }
+            return Ok(());
+        };
+        if !EdgeDetection::is_supported(&self.capabilities) {
+            log::warn!("{EDGE_DETECTION_LABEL} needs compute shaders, leaving the frame as it is");
+            return Ok(());
+        }
+
+        if let Some(edge_detection) = &mut self.edge_detection {
+            edge_detection.set_options(options);
+            return Ok(());
+        }
+        // Copies the result to the surface when no post effect comes after it.
+        if self.blit_pipeline.is_none() {
+            self.blit_pipeline = Some(Self::create_blit_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, &[self.post_process.bind_group_layout()])?);
+            self.crash_reporter.register_pipeline(&DebugLabels::new(BLIT_PIPELINE_LABEL).pipeline(),
+                ShaderHandle::Blit.filename());
+        }
+        self.edge_detection = Some(EdgeDetection::new(&self.device, &self.shader_registry,
+            EDGE_DETECTION_LABEL, options)?);
+        self.crash_reporter.register_pipeline(&DebugLabels::new(EDGE_DETECTION_LABEL).pipeline(),
+            ShaderHandle::EdgeDetection.filename());
+
+        Ok(())
+    }
+
+    pub fn toggle_edge_detection(&mut self) -> Result<(), RendererError>
+    {
+        let enabled = self.edge_detection.is_some();
+        self.set_edge_detection((!enabled).then(EdgeDetectionOptions::default))
+    }
+
+    pub fn outline_options(&self) -> Option<OutlineOptions>
+    {
+        self.outline.as_ref().map(Outline::options)
+    }
+
+    // Outlines the selected instance and the ones set with set_outlined_instances, drawn
+    // with the stencil buffer over the finished frame. None turns it off, see
+    // StateOptions::selection_outline for the one it starts with.
+    pub fn set_outline(&mut self, options: Option<OutlineOptions>) -> Result<(), RendererError>
+    {
+        let Some(options) = options else {
+            self.outline = None;
+            self.unregister_pipeline(OUTLINE_LABEL);
+            return Ok(());
+        };
+        if !self.capabilities.can_render_to(Texture::DEPTH_STENCIL_FORMAT) {
+            log::warn!("{OUTLINE_LABEL} needs a depth-stencil target, leaving instances unoutlined");
+            return Ok(());
+        }
+
+        let bind_group_layouts = [&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+            &self.vertex_animation_bind_group_layout, &self.instance_bind_group_layout];
+        if let Some(outline) = &mut self.outline {
+            return outline.set_options(&mut self.pipeline_cache, &self.device, &self.shader_registry,
+                &self.config, options, &bind_group_layouts);
+        }
+        self.outline = Some(Outline::new(&mut self.pipeline_cache, &self.device, &self.shader_registry,
+            &self.config, OUTLINE_LABEL, options, &bind_group_layouts)?);
+        self.crash_reporter.register_pipeline(&DebugLabels::new(OUTLINE_LABEL).pipeline(),
+            ShaderHandle::Outline.filename());
+
+        Ok(())
+    }
+
+    pub fn outlined_instances(&self) -> &[usize]
+    {
+        &self.outlined_instances
+    }
+
+    // Indices into the instances, the ones out of range are skipped.
+    pub fn set_outlined_instances(&mut self, indices: &[usize])
+    {
+        self.outlined_instances = indices.to_vec();
+    }
+
+    // Reads a .cube file, see set_color_grading_lut.
+    pub fn load_color_grading_lut(&mut self, path: &Path) -> Result<(), RendererError>
+    {
+        let source = std::fs::read_to_string(path)
+            .map_err(|source| RendererError::LutRead { path: path.to_path_buf(), source })?;
+        let lut = CubeLut::parse(&path.display().to_string(), &source)?;
+        log::info!("Loaded LUT {}", lut.title.as_deref().unwrap_or(&path.display().to_string()));
+
+        self.set_color_grading_lut(Some(lut))
+    }
+
+    // Multiplies the focus distance of depth of field that's on, e.g. by the brackets.
+    pub fn scale_focus_distance(&mut self, factor: f32)
+    {
+        if let Some(depth_of_field) = &mut self.depth_of_field {
+            let mut options = depth_of_field.options();
+            options.focus_distance *= factor;
+            depth_of_field.set_options(options);
+            log::info!("Focus distance: {:.2}", depth_of_field.options().focus_distance);
+        }
+    }
+
+    // Higher is smoother but slower to catch up with changes, 0 turns the history off.
+    pub fn set_taa_history_weight(&mut self, weight: f32)
+    {
+        if let Some(taa) = &mut self.taa {
+            taa.set_history_weight(weight);
+        }
+    }
+
+    // Drops what TAA has accumulated, e.g. after the camera cuts to somewhere else.
+    pub fn reset_taa_history(&mut self)
+    {
+        if let Some(taa) = &mut self.taa {
+            taa.reset_history();
+        }
+    }
+
+    // The resolve and blit, and the motion vector pipelines. Nothing is replaced unless
+    // all of them build.
+    fn create_taa_pipelines(&mut self) -> Result<(), RendererError>
+    {
+        let Some(taa) = &self.taa else {
+            return Ok(());
+        };
+
+        let taa_resolve_pipeline = Self::create_taa_resolve_pipeline(&mut self.pipeline_cache,
+            &self.device, &self.shader_registry, &self.config, &[taa.bind_group_layout()])?;
+        let blit_pipeline = Self::create_blit_pipeline(&mut self.pipeline_cache, &self.device,
+            &self.shader_registry, &self.config, &[self.post_process.bind_group_layout()])?;
+        self.create_motion_vector_pipelines()?;
+
+        for (label, shader) in [(TAA_RESOLVE_PIPELINE_LABEL, ShaderHandle::Taa),
+            (BLIT_PIPELINE_LABEL, ShaderHandle::Blit)] {
+            self.crash_reporter.register_pipeline(&DebugLabels::new(label).pipeline(),
+                shader.filename());
+        }
+        self.taa_resolve_pipeline = Some(taa_resolve_pipeline);
+        self.blit_pipeline = Some(blit_pipeline);
+
+        Ok(())
+    }
+
+    // A motion vector pipeline for every scene pipeline draw_opaque uses, while TAA or
+    // motion blur is on and none otherwise. The cache makes calling it again after the
+    // terrain or skinned model changes cheap.
+    fn create_motion_vector_pipelines(&mut self) -> Result<(), RendererError>
+    {
+        if self.taa.is_none() && self.motion_blur.is_none() {
+            for shader in self.motion_vector_pipelines.keys() {
+                self.unregister_pipeline(&Self::motion_vector_label(*shader));
+            }
+            self.motion_vector_pipelines.clear();
+            return Ok(());
+        }
+
+        let mut motion_vector_pipelines = HashMap::new();
+        motion_vector_pipelines.insert(ShaderHandle::Vertex, Self::create_motion_vector_pipeline(
+            &mut self.pipeline_cache, &self.device, &self.shader_registry, self.sample_count,
+            ShaderHandle::Vertex,
+            &[&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+                &self.vertex_animation_bind_group_layout, &self.instance_bind_group_layout])?);
+        if self.terrain_mesh.is_some() {
+            motion_vector_pipelines.insert(ShaderHandle::Terrain, Self::create_motion_vector_pipeline(
+                &mut self.pipeline_cache, &self.device, &self.shader_registry, self.sample_count,
+                ShaderHandle::Terrain, &[&self.camera_bind_group_layout])?);
+        }
+        if let Some(skinned_mesh) = &self.skinned_mesh {
+            motion_vector_pipelines.insert(ShaderHandle::Skinned, Self::create_motion_vector_pipeline(
+                &mut self.pipeline_cache, &self.device, &self.shader_registry, self.sample_count,
+                ShaderHandle::Skinned,
+                &[&self.texture_bind_group_layout, &self.camera_bind_group_layout,
+                    &self.vertex_animation_bind_group_layout, skinned_mesh.joint_bind_group_layout()])?);
+        }
+
+        for shader in self.motion_vector_pipelines.keys() {
+            if !motion_vector_pipelines.contains_key(shader) {
+                self.unregister_pipeline(&Self::motion_vector_label(*shader));
+            }
+        }
+        for shader in motion_vector_pipelines.keys() {
+            self.crash_reporter.register_pipeline(&DebugLabels::new(&Self::motion_vector_label(*shader))
+                .pipeline(), shader.filename());
+        }
+        self.motion_vector_pipelines = motion_vector_pipelines;
+
+        Ok(())
+    }
+
+    fn motion_vector_label(shader: ShaderHandle) -> String
+    {
+        format!("{MOTION_VECTORS_PIPELINE_LABEL} {shader:?}")
+    }
+
+    // Drops a pipeline that was set to None or removed from the crash reports.
+    fn unregister_pipeline(&self, label: &str)
+    {
+        self.crash_reporter.unregister_pipeline(&DebugLabels::new(label).pipeline());
+    }
+
+    // Every post effect binds the frame so far, those that need more their own group
+    // on top of it.
+    fn build_post_effect_pipeline(&mut self, effect: PostEffect) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut bind_group_layouts = vec![self.post_process.bind_group_layout()];
+        match effect {
+            PostEffect::Ssao => bind_group_layouts.extend(self.ssao.as_ref().map(Ssao::bind_group_layout)),
+            PostEffect::Fog => bind_group_layouts.extend(self.fog.as_ref().map(Fog::bind_group_layout)),
+            PostEffect::DepthOfField => bind_group_layouts.extend(self.depth_of_field.as_ref()
+                .map(DepthOfField::bind_group_layout)),
+            PostEffect::MotionBlur => bind_group_layouts.extend(self.motion_blur.as_ref()
+                .map(MotionBlur::bind_group_layout)),
+            PostEffect::Glow => bind_group_layouts.extend(self.glow.as_ref().map(Glow::bind_group_layout)),
+            PostEffect::ColorGrading => bind_group_layouts.extend(self.color_grading.as_ref()
+                .map(ColorGrading::bind_group_layout)),
+            PostEffect::Fxaa => ()
+        }
+
+        Self::create_post_effect_pipeline(&mut self.pipeline_cache, &self.device, &self.shader_registry,
+            &self.config, effect, &bind_group_layouts)
+    }
+
+    // The occlusion and blur passes the SSAO post effect reads from, both into
+    // OCCLUSION_FORMAT targets.
+    fn create_ssao_pipelines(&mut self) -> Result<(), RendererError>
+    {
+        let Some(ssao) = &self.ssao else {
+            return Ok(());
+        };
+
+        let bind_group_layouts = [self.post_process.bind_group_layout(), ssao.bind_group_layout()];
+        let mut pipelines = Vec::new();
+        for (label, fragment_entry) in [(SSAO_OCCLUSION_PIPELINE_LABEL, "fs_occlusion"),
+            (SSAO_BLUR_PIPELINE_LABEL, "fs_blur")] {
+            let mut builder = PipelineBuilder::builder();
+            builder
+                .set_label(label)
+                .set_shader_module(ShaderHandle::Ssao, "vs_fullscreen", fragment_entry)
+                .set_fullscreen()
+                .set_pixel_format(OCCLUSION_FORMAT);
+            pipelines.push(self.pipeline_cache.get_or_build(&mut builder, &self.device,
+                &self.shader_registry, &bind_group_layouts)?);
+        }
+
+        for label in [SSAO_OCCLUSION_PIPELINE_LABEL, SSAO_BLUR_PIPELINE_LABEL] {
+            self.crash_reporter.register_pipeline(&DebugLabels::new(label).pipeline(),
+                ShaderHandle::Ssao.filename());
+        }
+        self.ssao_blur_pipeline = pipelines.pop();
+        self.ssao_occlusion_pipeline = pipelines.pop();
+
+        Ok(())
+    }
+
+    pub fn placement_options(&self) -> &PlacementOptions
+    {
+        &self.placement_options
+    }
+
+    pub fn set_placement_options(&mut self, options: PlacementOptions)
+    {
+        self.placement_options = options;
+    }
+
+    // Spawns a new instance under the cursor, returns its index.
+    pub fn place_instance(&mut self, cursor: PhysicalPosition<f64>) -> Option<usize>
+    {
+        let instance = self.placement_at(cursor, None)?;
+        self.instances.push(instance);
+
+        Some(self.instances.len() - 1)
+    }
+
+    // Like place_instance, but the new instance is drawn see-through in the transparent pass.
+    pub fn place_transparent_instance(&mut self, cursor: PhysicalPosition<f64>) -> Option<usize>
+    {
+        let instance = self.placement_at(cursor, None)?;
+        self.transparent_instances.push(instance);
+
+        Some(self.transparent_instances.len() - 1)
+    }
+
+    pub fn move_instance(&mut self, index: usize, cursor: PhysicalPosition<f64>) -> bool
+    {
+        if index >= self.instances.len() {
+            return false;
+        }
+        let Some(instance) = self.placement_at(cursor, Some(index)) else {
+            return false;
+        };
+
+        self.instances[index] = instance;
+
+        true
+    }
+
+    // Falls back to the terrain, then the ground plane, when no instance is under the cursor. The
+    // instance being moved is skipped so it doesn't end up stacked on itself.
+    fn placement_at(&self, cursor: PhysicalPosition<f64>, skip: Option<usize>) -> Option<Instance>
+    {
+        let ray = self.camera.screen_to_ray(cursor, self.size);
+        let hit = self.raycast(&ray, skip)
+            .map(|(_, hit)| hit)
+            .or_else(|| self.terrain.as_ref()
+                .and_then(|terrain| terrain.intersect_ray(&ray, self.camera.zfar)))
+            .or_else(|| {
+                let ground = Point3::new(0.0, self.placement_options.ground_height, 0.0);
+
+                ray.intersect_plane(ground, Vector3::unit_y()).map(|distance| RayHit {
+                    distance,
+                    point: ray.at(distance),
+                    normal: Vector3::unit_y()
+                })
+            })?;
+
+        Some(self.placement_options.place(&hit))
+    }
+
+    // Instances are grouped by the LOD level their distance to the camera allows and
+    // uploaded in that order every frame, so each level is drawn from a contiguous
+    // range of the instance buffer (one batch per uniform block without storage buffers).
+    // How many pixels tall something one unit high is at a distance of one unit.
+    fn pixels_per_unit(&self) -> f32
+    {
+        self.config.height as f32 / (2.0 * (self.camera.fovy.to_radians() * 0.5).tan())
+    }
+
+    fn upload_instances_by_lod(&mut self)
+    {
+        let pixels_per_unit = self.pixels_per_unit();
+        let lod_error_threshold = self.lod_error_threshold * self.scale_factor as f32;
+        let eye = self.camera.eye.to_vec();
+        let origin = self.render_origin();
+        // Grouped by material first, so each material is bound once.
+        let mut groups = BTreeMap::new();
+        for instance in &self.instances {
+            let (level, blend) = self.mesh_lods.select_blend(instance.position.distance(eye),
+                pixels_per_unit, lod_error_threshold, self.lod_fade_band);
+            let material = instance.material.as_ref();
+            let raw = match material.and_then(|handle| self.assets.materials.get(handle)) {
+                Some(material) => instance.to_raw_relative(origin)
+                    .tinted(material.color())
+                    .with_emissive(material.emissive(instance.emissive_intensity)),
+                None => instance.to_raw_relative(origin)
+            };
+            // Near a switch the instance is drawn in both levels, each dithering away
+            // the pixels the other one covers.
+            let draws = match blend {
+                Some((next, fade)) => vec![(level, raw.with_lod_fade(fade)), (next, raw.with_lod_fade(-fade))],
+                None => vec![(level, raw)]
+            };
+            for (level, raw) in draws {
+                groups.entry((material.map(MaterialHandle::id), level))
+                    .or_insert_with(|| (material.cloned(), Vec::new()))
+                    .1.push(raw);
+            }
+        }
+
+        let (keys, groups): (Vec<_>, Vec<_>) = groups.into_iter()
+            .map(|((_, level), (material, group))| ((material, level), group))
+            .unzip();
+        let batches = self.instance_buffer.write(&self.device, &self.queue,
+            &self.instance_bind_group_layout, &groups);
+        self.instance_lod_draws = keys.into_iter()
+            .zip(batches)
+            .flat_map(|((material, level), batches)| batches.into_iter()
+                .filter(|batch| !batch.instances.is_empty())
+                .map(move |batch| (material.clone(), level, batch)))
+            .collect();
+
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            let draws = self.instance_lod_draws.iter()
+                .map(|(_, level, batch)| CullDraw {
+                    instances: batch.instances.clone(),
+                    indices: self.mesh_lods.level(*level).indices.clone()
+                })
+                .collect::<Vec<_>>();
+            gpu_culling.write(&self.device, &self.queue, &self.instance_bind_group_layout,
+                self.instance_buffer.buffer(), &draws);
+            gpu_culling.write_view(&self.queue, &self.camera.relative_to(origin), &self.pick_mesh.bounds().aabb,
+                self.vertex_animation_uniform.wind());
+        }
+    }
+
+    fn upload_skinned_emissive(&mut self)
+    {
+        let Some(skinned_mesh) = &self.skinned_mesh else {
+            return;
+        };
+
+        let emissive = self.skinned_material.as_ref()
+            .and_then(|handle| self.assets.materials.get(handle))
+            .map_or([0.0; 3], |material| material.emissive(None));
+        skinned_mesh.write_emissive(&self.queue, &self.gpu_allocator, emissive);
+    }
+
+    // Blending isn't order independent, so the transparent instances are uploaded
+    // furthest from the camera first every frame.
+    // Every material about to be drawn gets its bind group layout, its shader
+    // permutation and its bind group. Until all of them exist it is drawn from the
+    // material texture array instead.
+    fn prepare_materials(&mut self)
+    {
+        let materials = self.instance_lod_draws.iter()
+            .filter_map(|(material, _, _)| material.clone())
+            .chain(self.skinned_material.clone())
+            .collect::<Vec<_>>();
+
+        for handle in materials {
+            let Some(material) = self.assets.materials.get_mut(&handle) else {
+                continue;
+            };
+            let features = material.features();
+            let layout = self.material_layouts.entry(features)
+                .or_insert_with(|| features.get_bind_group_layout(&self.device, MATERIAL_PIPELINE_LABEL));
+            material.prepare(&self.device, layout, &self.assets.textures, &self.assets.render_targets,
+                &self.assets.procedural_textures, &mut self.samplers);
+
+            if self.material_pipelines.contains_key(&features) {
+                continue;
+            }
+            let last_layout = if features.skinning {
+                match &self.skinned_mesh {
+                    Some(skinned_mesh) => skinned_mesh.joint_bind_group_layout(),
+                    None => continue
+                }
+            } else {
+                &self.instance_bind_group_layout
+            };
+            let label = format!("{MATERIAL_PIPELINE_LABEL} {}", features.name());
+            let pipeline = Self::create_material_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count, features, &[layout,
+                    &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout,
+                    last_layout]);
+            let shader = if features.skinning { ShaderHandle::Skinned } else { ShaderHandle::Vertex };
+            self.crash_reporter.register_pipeline(&DebugLabels::new(&label).pipeline(),
+                shader.filename());
+            if let Err(e) = &pipeline {
+                log::error!("Couldn't build the {label} pipeline: {e}");
+            }
+            self.material_pipelines.insert(features, pipeline.ok());
+        }
+    }
+
+    // Like prepare_materials for the decals' materials, then uploads the decals grouped
+    // by material so every material is bound once.
+    fn prepare_decals(&mut self)
+    {
+        let mut groups: BTreeMap<u64, (MaterialHandle, Vec<DecalRaw>)> = BTreeMap::new();
+        for decal in &self.decals {
+            let Some(material) = self.assets.materials.get(&decal.material) else {
+                continue;
+            };
+            groups.entry(decal.material.id())
+                .or_insert_with(|| (decal.material.clone(), Vec::new()))
+                .1.push(decal.to_raw(material.color(), material.emissive(None)));
+        }
+
+        self.decal_draws.clear();
+        let mut decals = Vec::new();
+        for (handle, group) in groups.into_values() {
+            let start = decals.len() as u32;
+            decals.extend(group);
+            self.decal_draws.push((handle, start..decals.len() as u32));
+        }
+        self.decal_buffer.write(&self.device, &self.queue, &decals);
+
+        for (handle, _) in &self.decal_draws {
+            let Some(material) = self.assets.materials.get_mut(handle) else {
+                continue;
+            };
+            let features = material.features();
+            let layout = self.material_layouts.entry(features)
+                .or_insert_with(|| features.get_bind_group_layout(&self.device, MATERIAL_PIPELINE_LABEL));
+            material.prepare(&self.device, layout, &self.assets.textures, &self.assets.render_targets,
+                &self.assets.procedural_textures, &mut self.samplers);
+
+            if self.decal_pipelines.contains_key(&features) {
+                continue;
+            }
+            let label = format!("{DECAL_PIPELINE_LABEL} {}", features.name());
+            let pipeline = Self::create_decal_pipeline(&mut self.pipeline_cache, &self.device,
+                &self.shader_registry, &self.config, self.sample_count, features, &[layout,
+                    &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout,
+                    self.decal_buffer.bind_group_layout()]);
+            self.crash_reporter.register_pipeline(&DebugLabels::new(&label).pipeline(),
+                ShaderHandle::Decal.filename());
+            if let Err(e) = &pipeline {
+                log::error!("Couldn't build the {label} pipeline: {e}");
+            }
+            self.decal_pipelines.insert(features, pipeline.ok());
+        }
+    }
+
+    // The pipeline and group 0 for a material, or `pipeline` with the material texture
+    // array where there is no material or it can't be drawn yet.
+    fn material_binding<'p>(
+        &'p self,
+        material: Option<&MaterialHandle>,
+        pipeline: &'p RenderPipeline,
+        diffuse_bind_group: &'p BindGroup
+    ) -> (&'p RenderPipeline, &'p BindGroup)
+    {
+        material
+            .and_then(|handle| self.assets.materials.get(handle))
+            .and_then(|material| Some((
+                self.material_pipelines.get(&material.features())?.as_deref()?,
+                material.bind_group()?
+            )))
+            .unwrap_or((pipeline, diffuse_bind_group))
+    }
+
+    fn upload_transparent_instances(&mut self)
+    {
+        let eye = self.camera.eye.to_vec();
+        let mut sorted = self.transparent_instances.iter().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| b.position.distance2(eye).total_cmp(&a.position.distance2(eye)));
+
+        let origin = self.render_origin();
+        let instance_data = sorted.iter().map(|instance| instance.to_raw_relative(origin)).collect::<Vec<_>>();
+        self.transparent_instance_batches = self.transparent_instance_buffer.write(&self.device,
+            &self.queue, &self.instance_bind_group_layout, &[instance_data])
+            .concat();
+    }
+
+    fn upload_outlined_instances(&mut self)
+    {
+        if self.outline.is_none() {
+            return;
+        }
+
+        let origin = self.render_origin();
+        let selected = self.selected_instance.filter(|index| !self.outlined_instances.contains(index));
+        let instance_data = self.outlined_instances.iter()
+            .chain(&selected)
+            .filter_map(|index| self.instances.get(*index))
+            .map(|instance| instance.to_raw_relative(origin))
+            .collect();
+        if let Some(outline) = &mut self.outline {
+            outline.write_instances(&self.device, &self.queue, &self.instance_bind_group_layout,
+                instance_data);
+        }
+    }
+
+    fn upload_billboards(&mut self)
+    {
+        let eye = self.camera.eye.to_vec();
+        let mut sorted = self.billboards.iter().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| b.position.distance2(eye).total_cmp(&a.position.distance2(eye)));
+
+        let billboard_data = sorted.iter().map(|billboard| billboard.to_raw()).collect::<Vec<_>>();
+        self.billboard_buffer.write(&self.device, &self.queue, &billboard_data);
+    }
+
+    fn upload_debug_lines(&mut self)
+    {
+        let gizmo_transform = self.gizmo_transform();
+        if !self.show_bounds && gizmo_transform.is_none() {
+            return;
+        }
+
+        self.debug_lines.clear();
+        if self.show_bounds {
+            self.push_bounds_lines();
+        }
+        if let Some(transform) = gizmo_transform {
+            self.gizmo.push_lines(&mut self.debug_lines, &transform, self.camera.eye);
+        }
+
+        self.debug_lines.upload(&self.device, &self.queue);
+    }
+
+    fn push_bounds_lines(&mut self)
+    {
+        const BOX_COLOR: [f32; 3] = [1.0, 0.85, 0.2];
+        const SPHERE_COLOR: [f32; 3] = [0.2, 0.8, 1.0];
+        const SELECTED_COLOR: [f32; 3] = [1.0, 0.25, 0.2];
+        const TERRAIN_COLOR: [f32; 3] = [0.6, 0.6, 0.6];
+        const FRUSTUM_COLOR: [f32; 3] = [0.8, 0.4, 1.0];
+
+        let mesh_bounds = self.pick_mesh.bounds();
+        for (index, instance) in self.instances.iter().chain(&self.transparent_instances).enumerate() {
+            let bounds = instance.world_bounds(mesh_bounds);
+            let box_color = if Some(index) == self.selected_instance { SELECTED_COLOR } else { BOX_COLOR };
+            self.debug_lines.push_aabb(&bounds.aabb, box_color);
+            self.debug_lines.push_sphere(&bounds.sphere, SPHERE_COLOR);
+        }
+        if let Some(model) = &self.skinned_model {
+            self.debug_lines.push_aabb(&model.bounds.aabb, BOX_COLOR);
+            self.debug_lines.push_sphere(&model.bounds.sphere, SPHERE_COLOR);
+        }
+        if let Some(terrain_mesh) = &self.terrain_mesh {
+            for bounds in terrain_mesh.chunk_bounds() {
+                self.debug_lines.push_aabb(&bounds.aabb, TERRAIN_COLOR);
+            }
+        }
+        // What the other cameras see, e.g. the main camera's frustum from a light debug one.
+        for (index, scene_camera) in self.scene_cameras.iter().enumerate() {
+            if index != self.active_camera {
+                self.debug_lines.push_box(&scene_camera.camera().frustum_corners(), FRUSTUM_COLOR);
+            }
+        }
+    }
+
+    fn raycast(&self, ray: &Ray, skip: Option<usize>) -> Option<(usize, RayHit)>
+    {
+        self.instances.iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index) != skip)
+            .filter_map(|(index, instance)| {
+                let model = instance.model_matrix();
+                let model_inverse = model.invert()?;
+
+                self.pick_mesh.intersect(ray, &model, &model_inverse)
+                    .map(|hit| (index, hit))
+            })
+            .min_by(|a, b| a.1.distance.total_cmp(&b.1.distance))
+    }
+
+    pub fn update(&mut self)
+    {
+        if self.is_shut_down || self.is_paused() {
+            return;
+        }
+
+        self.frame_profiler.begin_frame();
+        let _timer = self.frame_profiler.scope("update");
+        self.poll_gamepads();
+        self.poll_settings();
+
+        let mut scheduler = std::mem::take(&mut self.scheduler);
+        scheduler.run(self);
+        self.scheduler = scheduler;
+    }
+
+    pub fn add_system(
+        &mut self,
+        name: &'static str,
+        priority: i32,
+        budget: Option<Duration>,
+        tick: impl FnMut(&mut State<'a>, &Tick) + 'static
+    )
+    {
+        self.scheduler.add_system(name, priority, budget, tick);
+    }
+
+    pub fn remove_system(&mut self, name: &str)
+    {
+        self.scheduler.remove_system(name);
+    }
+
+    pub fn system_timings(&self) -> Vec<SystemTiming>
+    {
+        self.scheduler.timings().cloned().collect()
+    }
+
+    pub fn frame_stats(&self) -> Vec<ScopeStats>
+    {
+        self.frame_profiler.stats()
+    }
+
+    pub fn reset_frame_stats(&mut self)
+    {
+        self.frame_profiler.reset();
+    }
+
+    // `count` instances on a square grid around the origin, tilted away from it.
+    fn create_instance_grid(count: u32) -> Vec<Instance>
+    {
+        let per_row = (count as f32).sqrt().ceil().max(1.0) as u32;
+        let displacement = Vector3::new(per_row as f32 * 0.5, 0.0, per_row as f32 * 0.5);
+
+        (0..count).map(|index| {
+            let (x, z) = (index % per_row, index / per_row);
+            let position = Vector3 { x: x as f32, y: 0.0, z: z as f32 } - displacement;
+
+            let rotation = if position.is_zero() {
+                Quaternion::from_axis_angle(Vector3::unit_z(), Deg(0.0))
+            } else {
+                Quaternion::from_axis_angle(position.normalize(), Deg(45.0))
+            };
+
+            Instance {
+                position,
+                rotation,
+                scale: Vector3::new(1.0, 1.0, 1.0),
+                animation: AnimationParams::from_index(index, 0.1),
+                color: instance::palette_color(index),
+                texture_index: 0,
+                material: None,
+                emissive_intensity: None
+            }
+        }).collect()
+    }
+
+    fn default_scheduler() -> Scheduler<State<'a>>
+    {
+        let mut scheduler = Scheduler::default();
+        scheduler.add_system("camera", 0, None, |state: &mut State, tick| state.update_camera(tick.delta));
+        scheduler.add_system("vertex_animation", 10, None,
+            |state: &mut State, tick| state.update_vertex_animation(tick.delta));
+        scheduler.add_system("skeletal_animation", 10, None,
+            |state: &mut State, tick| state.update_skeletal_animation(tick.delta));
+        scheduler.add_system("water", 10, None,
+            |state: &mut State, tick| state.update_water(tick.delta));
+        scheduler.add_system("residency", 100, Some(Duration::from_millis(2)),
+            |state: &mut State, _| state.update_residency());
+        scheduler.add_system("texture_streaming", 110, Some(Duration::from_millis(2)),
+            |state: &mut State, _| state.update_texture_streaming());
+        scheduler.add_system("assets", 100, None, |state: &mut State, _| state.unload_unused_assets());
+
+        scheduler
+    }
+
+    fn update_camera(&mut self, delta: Duration)
+    {
+        let sun_direction = self.fog_options()
+            .and_then(|options| options.sky)
+            .unwrap_or_default()
+            .sun_direction;
+        match &mut self.scene_cameras[self.active_camera].kind {
+            CameraKind::Free => self.update_free_camera(delta),
+            kind => kind.update(&mut self.camera, sun_direction, delta.as_secs_f32())
+        }
+        if let Some(transition) = &mut self.camera_transition {
+            if transition.step(&mut self.camera, delta.as_secs_f32()) {
+                self.camera_transition = None;
+            }
+        }
+        self.camera.aspect = self.main_viewport.aspect(self.config.width, self.config.height);
+        let jitter = self.next_camera_jitter();
+        let origin = self.render_origin();
+        self.camera_uniform.set_jitter(jitter);
+        self.camera_uniform.set_origin(origin);
+        self.camera_uniform.update_view_proj(&self.camera);
+        let _timer = self.frame_profiler.scope("buffer_writes");
+        self.queue.write_buffer(&self.camera_buffer, 0, cast_slice(&[self.camera_uniform]));
+        for viewport in &mut self.viewports {
+            viewport.write_camera(&self.queue, self.config.width, self.config.height, origin);
+        }
+        // The instances they draw moved with the origin.
+        if self.camera_relative {
+            for render_target in self.assets.render_targets.iter_mut() {
+                render_target.write_camera(&self.queue, origin);
+            }
+        }
+    }
+
+    fn update_free_camera(&mut self, delta: Duration)
+    {
+        match &mut self.camera_rig {
+            Some(rig) if self.camera_rig_enabled => {
+                rig.update(&mut self.camera, delta.as_secs_f32());
+                // A path that's over hands the camera back where it ended.
+                if rig.is_finished() {
+                    self.camera_rig_enabled = false;
+                }
+            },
+            _ if self.follow_camera.is_some() => self.update_follow_camera(delta.as_secs_f32()),
+            _ => self.camera_controller.update_camera(&mut self.camera)
+        }
+        let ground_height = self.terrain_height_at(self.camera.eye.x, self.camera.eye.z);
+        self.camera_controller.follow_ground(&mut self.camera, ground_height, delta.as_secs_f32());
+    }
+
+    // What the instances are uploaded relative to, the eye when drawing camera relative.
+    pub fn render_origin(&self) -> Point3<f64>
+    {
+        if self.camera_relative {
+            self.camera.eye.cast().unwrap()
+        } else {
+            Point3::origin()
+        }
+    }
+
+    // A sub-pixel offset of the main viewport, different every frame, in NDC.
+    fn next_camera_jitter(&mut self) -> Vector2<f32>
+    {
+        if !self.camera_jitter {
+            return Vector2::zero();
+        }
+
+        self.jitter_index = self.jitter_index % JITTER_SAMPLES + 1;
+        let (_, _, width, height) = self.main_viewport.to_pixels(self.render_config.width,
+            self.render_config.height);
+        let offset = Vector2::new(halton(self.jitter_index, 2), halton(self.jitter_index, 3))
+            - Vector2::new(0.5, 0.5);
+
+        Vector2::new(2.0 * offset.x / width as f32, 2.0 * offset.y / height as f32)
+    }
+
+    fn update_vertex_animation(&mut self, delta: Duration)
+    {
+        self.vertex_animation_uniform.advance(delta);
+        let _timer = self.frame_profiler.scope("buffer_writes");
+        self.queue.write_buffer(&self.vertex_animation_buffer, 0,
+            cast_slice(&[self.vertex_animation_uniform]));
+    }
+
+    // Runs after the camera system, the reflection camera follows the one just updated.
+    fn update_water(&mut self, delta: Duration)
+    {
+        let origin = self.render_origin();
+        let Some(water) = &mut self.water else {
+            return;
+        };
+
+        water.advance(delta.as_secs_f32());
+        let height = water.options().height;
+        let mut reflection_camera = CameraUniform::new();
+        reflection_camera.set_origin(origin);
+        reflection_camera.update_clipped_view_proj(&self.camera.reflected(height),
+            Vector4::new(0.0, 1.0, 0.0, -height + WATER_CLIP_OFFSET));
+        let _timer = self.frame_profiler.scope("buffer_writes");
+        water.write_uniforms(&self.queue, self.camera.eye, &reflection_camera);
+    }
+
+    fn update_skeletal_animation(&mut self, delta: Duration)
+    {
+        let (Some(model), Some(skinned_mesh)) = (&self.skinned_model, &self.skinned_mesh) else {
+            return;
+        };
+
+        for clip in self.animator.advance(delta, &model.clips) {
+            self.custom_events.push(CustomEvent::AnimationFinished(model.clips[clip].name.clone()));
+        }
+        let joint_matrices = self.animator.joint_matrices(&model.skeleton, &model.clips);
+        let morph_weights = self.animator.morph_weights(&model.default_weights, &model.clips);
+        let _timer = self.frame_profiler.scope("buffer_writes");
+        skinned_mesh.write_joint_matrices(&self.queue, &joint_matrices);
+        if !morph_weights.is_empty() {
+            skinned_mesh.write_morph_weights(&self.queue, &self.gpu_allocator, &morph_weights);
+        }
+    }
+
+    // Replaces the current skinned model and starts looping its first animation. The
+    // model is drawn once at the origin, next to the instances.
+    pub fn load_skinned_model(&mut self, path: &Path) -> Result<(), RendererError>
+    {
+        let model = SkinnedModel::load(path, &self.options.import_settings)
+            .map_err(|source| RendererError::Model { path: path.to_path_buf(), source })?;
+        self.set_skinned_model(path, model)
+    }
+
+    // Like load_skinned_model with a file's contents, see SkinnedModel::from_bytes.
+    pub fn load_skinned_model_from_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<(), RendererError>
+    {
+        let path = Path::new(name);
+        let model = SkinnedModel::from_bytes(name, bytes, &self.options.import_settings)
+            .map_err(|source| RendererError::Model { path: path.to_path_buf(), source })?;
+        self.set_skinned_model(path, model)
+    }
+
+    // What a file dropped on the window does: a model replaces the skinned model and
+    // the camera frames it, an image becomes the texture of the selected instance, or
+    // of every instance when none is selected.
+    pub fn open_file(&mut self, path: &Path) -> Result<(), RendererError>
+    {
+        match Self::file_kind(path) {
+            Some(FileKind::Model) => {
+                self.load_skinned_model(path)?;
+                self.frame_skinned_model();
+            },
+            Some(FileKind::Image) => {
+                let texture_index = self.load_material_texture(path)?;
+                self.show_material_texture(texture_index);
+            },
+            None => return Err(RendererError::UnsupportedFile { path: path.to_path_buf() })
+        }
+        log::info!("Opened {}", path.display());
+
+        Ok(())
+    }
+
+    // Like open_file with a file's contents, e.g. fetched on the web, `name` being the
+    // file's name or URL.
+    pub fn open_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<(), RendererError>
+    {
+        let path = Path::new(name);
+        match Self::file_kind(path) {
+            Some(FileKind::Model) => {
+                self.load_skinned_model_from_bytes(name, bytes)?;
+                self.frame_skinned_model();
+            },
+            Some(FileKind::Image) => {
+                let image = asset_decode::decode_image(bytes)?;
+                let texture_index = self.add_material_textures(vec![image])?[0];
+                self.show_material_texture(texture_index);
+            },
+            None => return Err(RendererError::UnsupportedFile { path: path.to_path_buf() })
+        }
+        log::info!("Opened {name}");
+
+        Ok(())
+    }
+
+    fn file_kind(path: &Path) -> Option<FileKind>
+    {
+        let extension = path.extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("gltf" | "glb" | "obj") => Some(FileKind::Model),
+            Some("png" | "jpg" | "jpeg") => Some(FileKind::Image),
+            _ => None
+        }
+    }
+
+    fn frame_skinned_model(&mut self)
+    {
+        if let Some(bounds) = self.skinned_model_bounds() {
+            self.frame_camera(&bounds.aabb);
+        }
+    }
+
+    // On the selected instance, or every instance when none is selected.
+    fn show_material_texture(&mut self, texture_index: u32)
+    {
+        match self.selected_instance {
+            Some(index) => {
+                self.set_instance_texture(index, texture_index);
+            },
+            None => {
+                for instance in &mut self.instances {
+                    instance.texture_index = texture_index;
+                }
+            }
+        }
+    }
+
+    fn set_skinned_model(&mut self, path: &Path, model: SkinnedModel) -> Result<(), RendererError>
+    {
+        let skinned_mesh = Self::create_skinned_mesh(&self.device, &self.queue,
+            &mut self.gpu_allocator, &model);
+        let skinned_pipeline = Self::create_skinned_pipeline(&mut self.pipeline_cache,
+            &self.device, &self.shader_registry, &self.config, self.sample_count,
+            &[&self.texture_bind_group_layout,
+                &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout,
+                skinned_mesh.joint_bind_group_layout()])?;
+        self.crash_reporter.register_pipeline(&DebugLabels::new(SKINNED_PIPELINE_LABEL).pipeline(),
+            ShaderHandle::Skinned.filename());
+
+        log::info!("Loaded {} with {} vertices, {} joints, {} morph targets and {} animations",
+            path.display(), model.vertices.len(), model.skeleton.joints.len(),
+            model.morph_targets.len(), model.clips.len());
+        if model.clips.is_empty() {
+            self.animator.stop();
+        } else {
+            self.animator.play(0, true, Duration::ZERO);
+        }
+
+        self.skinned_model = Some(model);
+        // The skinning permutations were built against the old mesh's joint layout.
+        for features in self.material_pipelines.keys().filter(|features| features.skinning) {
+            self.unregister_pipeline(&format!("{MATERIAL_PIPELINE_LABEL} {}", features.name()));
+        }
+        self.material_pipelines.retain(|features, _| !features.skinning);
+        if let Some(old_mesh) = self.skinned_mesh.replace(skinned_mesh) {
+            old_mesh.free(&mut self.gpu_allocator);
+        }
+        self.skinned_pipeline = Some(skinned_pipeline);
+
+        self.create_motion_vector_pipelines()
+    }
+
+    // Replaces the terrain with a noise heightmap. The heights are kept on the CPU so
+    // the terrain can be rebuilt after a device loss.
+    pub fn generate_terrain(&mut self, options: &TerrainOptions) -> Result<(), RendererError>
+    {
+        self.set_terrain(Heightmap::from_noise(options))
+    }
+
+    // Uses the brightness of an image (e.g. a grayscale PNG) as the heights.
+    pub fn load_terrain_heightmap(
+        &mut self,
+        path: &Path,
+        options: &TerrainOptions
+    ) -> Result<(), RendererError>
+    {
+        let image = image::open(path)?;
+        self.set_terrain(Heightmap::from_image(&image, options))
+    }
+
+    // None where there is no terrain.
+    pub fn terrain_height_at(&self, x: f32, z: f32) -> Option<f32>
+    {
+        self.terrain.as_ref().and_then(|terrain| terrain.height_at(x, z))
+    }
+
+    // With a terrain, the camera never goes below `eye_height` above it. Gravity (in
+    // units per second squared) also pulls it back down onto the ground.
+    pub fn set_camera_gravity(&mut self, gravity: Option<f32>, eye_height: f32)
+    {
+        self.camera_controller.set_gravity(gravity, eye_height);
+    }
+
+    pub fn clear_terrain(&mut self)
+    {
+        self.terrain = None;
+        if let Some(terrain_mesh) = self.terrain_mesh.take() {
+            terrain_mesh.free(&mut self.gpu_allocator);
+        }
+        self.terrain_pipeline = None;
+        if let Some(vegetation_mesh) = self.vegetation_mesh.take() {
+            vegetation_mesh.free(&mut self.gpu_allocator);
+        }
+        self.vegetation_pipeline = None;
+        for label in [TERRAIN_PIPELINE_LABEL, VEGETATION_PIPELINE_LABEL] {
+            self.unregister_pipeline(label);
+        }
+    }
+
+    // Grows plants over the terrain, and again every time it changes. Without a
+    // terrain they grow once there is one. None removes them.
+    pub fn set_vegetation(&mut self, options: Option<VegetationOptions>) -> Result<(), RendererError>
+    {
+        self.vegetation = options;
+
+        self.rebuild_vegetation()
+    }
+
+    pub fn vegetation_options(&self) -> Option<VegetationOptions>
+    {
+        self.vegetation
+    }
+
+    fn rebuild_vegetation(&mut self) -> Result<(), RendererError>
+    {
+        if let Some(vegetation_mesh) = self.vegetation_mesh.take() {
+            vegetation_mesh.free(&mut self.gpu_allocator);
+        }
+        let (Some(options), Some(heightmap)) = (&self.vegetation, &self.terrain) else {
+            self.vegetation_pipeline = None;
+            self.unregister_pipeline(VEGETATION_PIPELINE_LABEL);
+            return Ok(());
+        };
+
+        if self.vegetation_pipeline.is_none() {
+            self.vegetation_pipeline = Some(Self::create_vegetation_pipeline(&mut self.pipeline_cache,
+                &self.device, &self.shader_registry, &self.config, self.sample_count,
+                &[&self.texture_bind_group_layout,
+                    &self.camera_bind_group_layout, &self.vertex_animation_bind_group_layout])?);
+            self.crash_reporter.register_pipeline(
+                &DebugLabels::new(VEGETATION_PIPELINE_LABEL).pipeline(),
+                ShaderHandle::Vegetation.filename());
+        }
+        self.vegetation_mesh = VegetationMesh::new(&self.device, &self.queue,
+            &mut self.gpu_allocator, VEGETATION_PIPELINE_LABEL, heightmap, options);
+        if let Some(vegetation_mesh) = &self.vegetation_mesh {
+            log::info!("Grew {} plants over the terrain", vegetation_mesh.num_plants());
+        }
+
+        Ok(())
+    }
+
+    // Each frame with water renders the scene two extra times, into the reflection and
+    // refraction targets, at the options' resolution scale.
+    pub fn enable_water(&mut self, options: &WaterOptions) -> Result<(), RendererError>
+    {
+        let water = Water::new(&self.device, &self.queue, WATER_PIPELINE_LABEL, options,
+            &self.camera_bind_group_layout, &mut self.samplers);
+        let water_pipeline = Self::create_water_pipeline(&mut self.pipeline_cache, &self.device,
+            &self.shader_registry, &self.config, self.sample_count,
+            &[&self.camera_bind_group_layout, water.bind_group_layout()])?;
+        self.crash_reporter.register_pipeline(&DebugLabels::new(WATER_PIPELINE_LABEL).pipeline(),
+            ShaderHandle::Water.filename());
+
+        self.water = Some(water);
+        self.water_pipeline = Some(water_pipeline);
+
+        Ok(())
+    }
+
+    pub fn disable_water(&mut self)
+    {
+        self.water = None;
+        self.water_pipeline = None;
+        self.unregister_pipeline(WATER_PIPELINE_LABEL);
+    }
+
+    fn set_terrain(&mut self, heightmap: Heightmap) -> Result<(), RendererError>
+    {
+        let terrain_pipeline = Self::create_terrain_pipeline(&mut self.pipeline_cache, &self.device,
+            &self.shader_registry, &self.config, self.sample_count,
+            &[&self.camera_bind_group_layout])?;
+        self.crash_reporter.register_pipeline(&DebugLabels::new(TERRAIN_PIPELINE_LABEL).pipeline(),
+            ShaderHandle::Terrain.filename());
+        let terrain_mesh = TerrainMesh::new(&self.device, &self.queue, &mut self.gpu_allocator,
+            TERRAIN_PIPELINE_LABEL, &heightmap);
+
+        log::info!("Generated a terrain of {} chunks", terrain_mesh.num_chunks());
+        self.terrain = Some(heightmap);
+        if let Some(old_mesh) = self.terrain_mesh.replace(terrain_mesh) {
+            old_mesh.free(&mut self.gpu_allocator);
+        }
+        self.terrain_pipeline = Some(terrain_pipeline);
+        self.rebuild_vegetation()?;
+
+        self.create_motion_vector_pipelines()
+    }
+
+    // Cross-fades from whatever is playing over `fade`. One-shot clips send
+    // CustomEvent::AnimationFinished once they reach their end.
+    pub fn play_animation(&mut self, name: &str, looping: bool, fade: Duration) -> bool
+    {
+        let Some(clip) = self.skinned_model.as_ref().and_then(|model| model.clip_index(name)) else {
+            return false;
+        };
+        self.animator.play(clip, looping, fade);
+
+        true
+    }
+
+    pub fn set_animation_speed(&mut self, speed: f32)
+    {
+        self.animator.set_speed(speed);
+    }
+
+    pub fn is_animation_finished(&self) -> bool
+    {
+        self.animator.is_finished()
+    }
+
+    // Events raised while updating, forwarded to the event loop by the caller.
+    pub(crate) fn take_custom_events(&mut self) -> Vec<CustomEvent>
+    {
+        std::mem::take(&mut self.custom_events)
+    }
+
+    // A strength of 0 (the default) keeps every instance still.
+    pub fn set_wind(&mut self, direction: Vector3<f32>, strength: f32)
+    {
+        self.vertex_animation_uniform.set_wind(direction, strength);
+    }
+
+    // Adds a layer to the material texture array and returns its index, for
+    // set_instance_texture. Layers are scaled to the size of the first one.
+    pub fn add_material_texture(&mut self, image: DynamicImage) -> Result<u32, RendererError>
+    {
+        self.add_material_textures(vec![image]).map(|indices| indices[0])
+    }
+
+    // The array is uploaded again once for all of the new layers.
+    pub fn add_material_textures(&mut self, images: Vec<DynamicImage>) -> Result<Vec<u32>, RendererError>
+    {
+        let texture_indices = images.into_iter()
+            .map(|image| self.diffuse_texture.push_layer(image))
+            .collect();
+        self.diffuse_texture.make_resident(&self.device, &self.queue,
+            &self.texture_bind_group_layout, &self.texture_sampler)?;
+        self.asset_residency.make_resident(ResidentAsset::DiffuseArray,
+            self.diffuse_texture.size_in_bytes());
+
+        Ok(texture_indices)
+    }
+
+    pub fn load_material_texture(&mut self, path: &Path) -> Result<u32, RendererError>
+    {
+        self.load_material_textures(&[path]).map(|indices| indices[0])
+    }
+
+    // Decodes the files in parallel, natively, before adding them in order.
+    pub fn load_material_textures<P: AsRef<Path> + Sync>(
+        &mut self,
+        paths: &[P]
+    ) -> Result<Vec<u32>, RendererError>
+    {
+        let images = asset_decode::open_images(paths).into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        self.add_material_textures(images)
+    }
+
+    // Loading a path that is already loaded as the same kind shares the texture. It is
+    // unloaded a frame after the last handle to it (or to a material using it) is
+    // dropped. Normal maps are TextureKind::Data.
+    pub fn load_texture(&mut self, path: &Path, kind: TextureKind) -> Result<TextureHandle, RendererError>
+    {
+        self.assets.load_texture(&self.device, &self.queue, &self.texture_bind_group_layout,
+            &self.texture_sampler, path, kind)
+    }
+
+    pub fn load_textures<P: AsRef<Path> + Sync>(
+        &mut self,
+        paths: &[P],
+        kind: TextureKind
+    ) -> Result<Vec<TextureHandle>, RendererError>
+    {
+        self.assets.load_textures(&self.device, &self.queue, &self.texture_bind_group_layout,
+            &self.texture_sampler, paths, kind)
+    }
+
+    // Like load_texture with a file's contents, shared by `name` the way load_texture
+    // shares by path.
+    pub fn load_texture_from_bytes(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        kind: TextureKind
+    ) -> Result<TextureHandle, RendererError>
+    {
+        self.assets.load_texture_from_bytes(&self.device, &self.queue, &self.texture_bind_group_layout,
+            &self.texture_sampler, name, bytes, kind)
+    }
+
+    // Starts out looking through the main camera, see set_render_target_camera. The
+    // scene is rendered into it every frame until the last handle to it (and to every
+    // material showing it) is dropped.
+    pub fn create_render_target(&mut self, label: &str, width: u32, height: u32) -> RenderTargetHandle
+    {
+        let render_target = RenderTarget::new(&self.device, label, (width, height), self.config.format,
+            self.camera.clone(), &self.camera_bind_group_layout);
+
+        self.assets.render_targets.insert(None, render_target)
+    }
+
+    // Moves the main camera by a different sub-pixel offset every frame, for temporal
+    // anti-aliasing to resolve.
+    pub fn set_camera_jitter(&mut self, enabled: bool)
+    {
+        self.camera_jitter = enabled;
+        self.jitter_index = 0;
+    }
+
+    // This frame's, in NDC.
+    pub fn camera_jitter(&self) -> Vector2<f32>
+    {
+        self.camera_uniform.jitter()
+    }
+
+    // Where the main camera is drawn, the whole window by default.
+    pub fn set_main_viewport(&mut self, rect: ViewportRect)
+    {
+        self.main_viewport = rect;
+    }
+
+    // Starts out looking through the main camera, see set_viewport_camera. Returns the
+    // viewport's index.
+    pub fn add_viewport(&mut self, rect: ViewportRect) -> usize
+    {
+        let label = format!("Viewport {}", self.viewports.len());
+        self.viewports.push(Viewport::new(&self.device, &label, rect, self.camera.clone(),
+            &self.camera_bind_group_layout));
+
+        self.viewports.len() - 1
+    }
+
+    pub fn set_viewport_rect(&mut self, index: usize, rect: ViewportRect) -> bool
+    {
+        let Some(viewport) = self.viewports.get_mut(index) else {
+            return false;
+        };
+        viewport.set_rect(rect);
+
+        true
+    }
+
+    pub fn set_viewport_camera(&mut self, index: usize, eye: Point3<f32>, target: Point3<f32>) -> bool
+    {
+        let Some(viewport) = self.viewports.get_mut(index) else {
+            return false;
+        };
+        let camera = viewport.camera_mut();
+        camera.eye = eye;
+        camera.target = target;
+
+        true
+    }
+
+    // The viewports after it move down one index.
+    pub fn remove_viewport(&mut self, index: usize) -> bool
+    {
+        if index >= self.viewports.len() {
+            return false;
+        }
+        self.viewports.remove(index);
+
+        true
+    }
+
+    pub fn num_viewports(&self) -> usize
+    {
+        self.viewports.len()
+    }
+
+    // Another camera the main pass can look through, starting as a copy of the active
+    // one looking from `eye` at `target`. Returns its index, the startup camera is 0.
+    pub fn add_camera(&mut self, label: &str, kind: CameraKind, eye: Point3<f32>, target: Point3<f32>) -> usize
+    {
+        let camera = Camera {
+            eye,
+            target,
+            ..self.camera.clone()
+        };
+        self.scene_cameras.push(SceneCamera::new(&self.device, label, kind, camera,
+            &self.camera_bind_group_layout));
+
+        self.scene_cameras.len() - 1
+    }
+
+    // The active camera can't be removed. The cameras after it move down one index.
+    pub fn remove_camera(&mut self, index: usize) -> bool
+    {
+        if index >= self.scene_cameras.len() || index == self.active_camera {
+            return false;
+        }
+        self.scene_cameras.remove(index);
+        if index < self.active_camera {
+            self.active_camera -= 1;
+        }
+
+        true
+    }
+
+    pub fn num_cameras(&self) -> usize
+    {
+        self.scene_cameras.len()
+    }
+
+    pub fn camera_label(&self, index: usize) -> Option<&str>
+    {
+        self.scene_cameras.get(index).map(SceneCamera::label)
+    }
+
+    pub fn camera_kind_mut(&mut self, index: usize) -> Option<&mut CameraKind>
+    {
+        self.scene_cameras.get_mut(index).map(|scene_camera| &mut scene_camera.kind)
+    }
+
+    pub fn active_camera(&self) -> usize
+    {
+        self.active_camera
+    }
+
+    // Looks through camera `index` in the main pass from the next update on. Everything
+    // that works with the camera, the controller, picking and the post effects included,
+    // works with the active one.
+    pub fn set_active_camera(&mut self, index: usize) -> bool
+    {
+        if index >= self.scene_cameras.len() {
+            return false;
+        }
+        if index == self.active_camera {
+            return true;
+        }
+
+        // The active camera's state goes back into its slot and the new one's comes out.
+        for slot in [self.active_camera, index] {
+            self.scene_cameras[slot].swap(&mut self.camera, &mut self.camera_uniform,
+                &mut self.camera_buffer, &mut self.camera_bind_group);
+        }
+        self.active_camera = index;
+        self.camera_transition = None;
+        log::info!("Looking through {}", self.scene_cameras[index].label());
+
+        true
+    }
+
+    pub fn cycle_camera(&mut self) -> bool
+    {
+        let next = (self.active_camera + 1) % self.scene_cameras.len();
+
+        self.set_active_camera(next) && self.scene_cameras.len() > 1
+    }
+
+    // Generated once on the GPU, and again only when its pattern changes. Materials
+    // made with Material::from_procedural show it. It goes a frame after the last
+    // handle to it (and to every material showing it) is dropped.
+    pub fn create_procedural_texture(
+        &mut self,
+        label: &str,
+        pattern: ProceduralPattern,
+        width: u32,
+        height: u32
+    ) -> Result<ProceduralTextureHandle, RendererError>
+    {
+        if !TextureGenerator::is_supported(&self.capabilities) {
+            return Err(RendererError::NoComputeShaders(String::from(label)));
+        }
+        let texture_generator = match self.texture_generator.take() {
+            Some(texture_generator) => texture_generator,
+            None => TextureGenerator::new(&self.device, &self.shader_registry, TEXTURE_GENERATOR_LABEL)?
+        };
+        let procedural_texture = ProceduralTexture::new(&self.device, &self.queue, &texture_generator,
+            label, pattern, (width, height));
+        self.texture_generator = Some(texture_generator);
+
+        Ok(self.assets.procedural_textures.insert(None, procedural_texture?))
+    }
+
+    pub fn set_procedural_pattern(&mut self, procedural_texture: &ProceduralTextureHandle, pattern: ProceduralPattern) -> bool
+    {
+        let (Some(procedural_texture), Some(texture_generator)) = (
+            self.assets.procedural_textures.get_mut(procedural_texture), &self.texture_generator) else {
+            return false;
+        };
+        procedural_texture.set_pattern(&self.device, &self.queue, texture_generator, pattern);
+
+        true
+    }
+
+    pub fn set_render_target_camera(
+        &mut self,
+        render_target: &RenderTargetHandle,
+        eye: Point3<f32>,
+        target: Point3<f32>
+    ) -> bool
+    {
+        let origin = self.render_origin();
+        let Some(render_target) = self.assets.render_targets.get_mut(render_target) else {
+            return false;
+        };
+        let camera = render_target.camera_mut();
+        camera.eye = eye;
+        camera.target = target;
+        render_target.write_camera(&self.queue, origin);
+
+        true
+    }
+
+    pub fn create_material(&mut self, material: Material) -> MaterialHandle
+    {
+        self.assets.materials.insert(None, material)
+    }
+
+    // Skinning materials only go on the skinned model, see set_skinned_model_material.
+    pub fn set_instance_material(&mut self, index: usize, material: Option<MaterialHandle>) -> bool
+    {
+        if !self.is_material_skinning(material.as_ref(), false) {
+            return false;
+        }
+        let Some(instance) = self.instances.get_mut(index) else {
+            return false;
+        };
+        instance.material = material;
+
+        true
+    }
+
+    pub fn set_skinned_model_material(&mut self, material: Option<MaterialHandle>) -> bool
+    {
+        if !self.is_material_skinning(material.as_ref(), true) {
+            return false;
+        }
+        self.skinned_material = material;
+
+        true
+    }
+
+    pub fn instance_mesh(&self) -> MeshHandle
+    {
+        self.instance_mesh.clone()
+    }
+
+    pub fn asset_stats(&self) -> AssetStats
+    {
+        self.assets.stats()
+    }
+
+    pub fn num_material_textures(&self) -> u32
+    {
+        self.diffuse_texture.num_layers()
+    }
+
+    pub fn set_instance_texture(&mut self, index: usize, texture_index: u32) -> bool
+    {
+        let Some(instance) = self.instances.get_mut(index) else {
+            return false;
+        };
+        instance.texture_index = texture_index;
+
+        true
+    }
+
+    // Overrides the emissive intensity of the instance's material, None goes back to
+    // the material's.
+    pub fn set_instance_emissive_intensity(&mut self, index: usize, intensity: Option<f32>) -> bool
+    {
+        let Some(instance) = self.instances.get_mut(index) else {
+            return false;
+        };
+        instance.emissive_intensity = intensity;
+
+        true
+    }
+
+    // Drawn with the material texture array, returning its index.
+    pub fn add_billboard(&mut self, billboard: Billboard) -> usize
+    {
+        self.billboards.push(billboard);
+
+        self.billboards.len() - 1
+    }
+
+    pub fn billboard_mut(&mut self, index: usize) -> Option<&mut Billboard>
+    {
+        self.billboards.get_mut(index)
+    }
+
+    // The billboards after it move down by one.
+    pub fn remove_billboard(&mut self, index: usize) -> bool
+    {
+        if index >= self.billboards.len() {
+            return false;
+        }
+        self.billboards.remove(index);
+
+        true
+    }
+
+    pub fn clear_billboards(&mut self)
+    {
+        self.billboards.clear();
+    }
+
+    pub fn num_billboards(&self) -> usize
+    {
+        self.billboards.len()
+    }
+
+    // How far in front of the scene behind them, in world units, billboards start to
+    // fade out. 0 draws them hard, cut off where they cross the geometry.
+    pub fn set_soft_billboard_distance(&mut self, distance: f32)
+    {
+        self.soft_billboard_distance = distance.max(0.0);
+    }
+
+    pub fn soft_billboard_distance(&self) -> f32
+    {
+        self.soft_billboard_distance
+    }
+
+    // Projected onto whatever opaque geometry is inside its box, returning its index.
+    // Skinning materials can't be used.
+    pub fn add_decal(&mut self, decal: Decal) -> Option<usize>
+    {
+        if !self.is_material_skinning(Some(&decal.material), false) {
+            return None;
+        }
+        self.decals.push(decal);
+
+        Some(self.decals.len() - 1)
+    }
+
+    // The decals after it move down by one.
+    pub fn remove_decal(&mut self, index: usize) -> bool
+    {
+        if index >= self.decals.len() {
+            return false;
+        }
+        self.decals.remove(index);
+
+        true
+    }
+
+    pub fn clear_decals(&mut self)
+    {
+        self.decals.clear();
+    }
+
+    pub fn num_decals(&self) -> usize
+    {
+        self.decals.len()
+    }
+
+    // For the textures and meshes together, see update_residency.
+    pub fn set_texture_budget(&mut self, budget_bytes: u64)
+    {
+        self.asset_residency.set_budget(budget_bytes);
+    }
+
+    pub fn residency_stats(&self) -> ResidencyStats
+    {
+        self.asset_residency.stats()
+    }
+
+    // Bytes of mips uploaded per frame at most, though at least one mip always goes.
+    pub fn set_texture_upload_budget(&mut self, budget_bytes: u64)
+    {
+        self.texture_streamer.set_upload_budget(budget_bytes);
+    }
+
+    pub fn texture_streaming_stats(&self) -> StreamingStats
+    {
+        self.texture_streamer.stats()
+    }
+
+    pub fn draw_queue_stats(&self) -> DrawQueueStats
+    {
+        self.draw_queue_stats.get()
+    }
+
+    pub fn gpu_allocator_stats(&self) -> GpuAllocatorStats
+    {
+        self.gpu_allocator.stats()
+    }
+
+    pub fn gpu_timings(&self) -> &[GpuTiming]
+    {
+        self.gpu_profiler.as_ref()
+            .map_or(&[], |gpu_profiler| gpu_profiler.timings())
+    }
+
+    pub fn pipeline_cache_stats(&self) -> PipelineCacheStats
+    {
+        self.pipeline_cache.stats()
+    }
+
+    pub fn submit_stats(&self) -> SubmitStats
+    {
+        self.submit_batch.stats()
+    }
+
+    pub fn transient_pool_stats(&self) -> TransientPoolStats
+    {
+        self.transient_textures.stats()
+    }
+
+    fn is_material_skinning(&self, material: Option<&MaterialHandle>, skinning: bool) -> bool
+    {
+        material.is_none_or(|handle| self.assets.materials.get(handle)
+            .is_some_and(|material| material.features().skinning == skinning))
+    }
+
+    fn unload_unused_assets(&mut self)
+    {
+        let unloaded = self.assets.collect_unused(&mut self.gpu_allocator);
+        if unloaded > 0 {
+            log::debug!("Unloaded {unloaded} unused assets: {:?}", self.assets.stats());
+        }
+    }
+
+    // Tracks every loaded texture and mesh, forgets the unloaded ones and evicts what
+    // the frames drew least recently until the budget is met. touch_drawn_assets
+    // uploads them again once a draw needs them.
+    fn update_residency(&mut self)
+    {
+        let textures = self.assets.textures.iter()
+            .map(|(id, texture)| (ResidentAsset::Texture(id), texture.is_resident(), texture.size_in_bytes()));
+        let meshes = self.assets.meshes.iter()
+            .map(|(id, mesh)| (ResidentAsset::Mesh(id), mesh.is_resident(), mesh.size_in_bytes()));
+        let assets = textures.chain(meshes).collect::<Vec<_>>();
+        let loaded = assets.iter().map(|(key, _, _)| *key).collect::<HashSet<_>>();
+        self.asset_residency.retain(|key| *key == ResidentAsset::DiffuseArray || loaded.contains(key));
+        for (key, resident, size_bytes) in assets {
+            if resident && !self.asset_residency.is_resident(&key) {
+                self.asset_residency.make_resident(key, size_bytes);
+            }
+        }
+
+        let evicted = self.asset_residency.evict_over_budget();
+        for key in &evicted {
+            match *key {
+                ResidentAsset::DiffuseArray => self.diffuse_texture.evict(),
+                ResidentAsset::Texture(id) => if let Some(texture) = self.assets.textures.get_mut_by_id(id) {
+                    texture.evict();
+                },
+                ResidentAsset::Mesh(id) => if let Some(mesh) = self.assets.meshes.get_mut_by_id(id) {
+                    mesh.evict(&mut self.gpu_allocator);
+                }
+            }
+            log::debug!("Evicted {key:?}: {:?}", self.asset_residency.stats());
+        }
+        if evicted.iter().any(|key| matches!(key, ResidentAsset::Texture(_))) {
+            for material in self.assets.materials.iter_mut() {
+                material.release_bind_group();
+            }
+        }
+    }
+
+    // Marks the textures and meshes this frame draws as used, uploading the evicted
+    // ones again. Everything but the terrain and the decals draws with the diffuse
+    // array, materials only replace it once their bind group exists.
+    fn touch_drawn_assets(&mut self)
+    {
+        self.asset_residency.begin_frame();
+
+        let uses_diffuse = !self.instances.is_empty() || !self.transparent_instances.is_empty()
+            || !self.billboards.is_empty() || self.vegetation_mesh.is_some() || self.skinned_mesh.is_some();
+        if uses_diffuse && !self.diffuse_texture.is_resident() {
+            if let Err(e) = self.diffuse_texture.make_resident(&self.device, &self.queue,
+                &self.texture_bind_group_layout, &self.texture_sampler) {
+                log::error!("Couldn't reload texture {}: {e}", self.diffuse_texture.label());
+            }
+        }
+        if uses_diffuse && self.diffuse_texture.is_resident()
+            && !self.asset_residency.touch(&ResidentAsset::DiffuseArray) {
+            self.asset_residency.make_resident(ResidentAsset::DiffuseArray,
+                self.diffuse_texture.size_in_bytes());
+        }
+
+        // Transparent instances are drawn with the diffuse array, whatever their material.
+        let materials = self.instances.iter()
+            .filter_map(|instance| instance.material.as_ref())
+            .chain(self.decals.iter().map(|decal| &decal.material))
+            .chain(self.skinned_mesh.as_ref().and(self.skinned_material.as_ref()));
+        let mut textures = BTreeMap::new();
+        for material in materials.filter_map(|handle| self.assets.materials.get(handle)) {
+            textures.extend(material.textures().map(|handle| (handle.id(), handle.clone())));
+        }
+        let mut reloaded = false;
+        for (id, handle) in textures {
+            let Some(texture) = self.assets.textures.get_mut(&handle) else {
+                continue;
+            };
+            if !texture.is_resident() {
+                match texture.make_resident(&self.device, &self.queue, &self.texture_bind_group_layout,
+                    &self.texture_sampler) {
+                    Ok(()) => reloaded = true,
+                    Err(e) => log::error!("Couldn't reload texture {}: {e}", texture.label())
+                }
+            }
+            let key = ResidentAsset::Texture(id);
+            if texture.is_resident() && !self.asset_residency.touch(&key) {
+                self.asset_residency.make_resident(key, texture.size_in_bytes());
+            }
+        }
+        if reloaded {
+            for material in self.assets.materials.iter_mut() {
+                material.release_bind_group();
+            }
+        }
+
+        if self.instances.is_empty() && self.transparent_instances.is_empty() {
+            return;
+        }
+        let key = ResidentAsset::Mesh(self.instance_mesh.id());
+        if let Some(mesh) = self.assets.meshes.get_mut(&self.instance_mesh) {
+            mesh.make_resident(&self.device, &self.queue, &mut self.gpu_allocator);
+            if !self.asset_residency.touch(&key) {
+                self.asset_residency.make_resident(key, mesh.size_in_bytes());
+            }
+        }
+    }
+
+    // Streams every resident texture towards the mip its nearest user needs, within the
+    // texture budget. Instances without a material and the skinned model without one
+    // use the diffuse array.
+    fn update_texture_streaming(&mut self)
+    {
+        let eye = self.camera.eye.to_vec();
+        let skinned = self.skinned_mesh.as_ref()
+            .map(|_| (Vector3::zero(), self.skinned_material.as_ref()));
+        let users = self.instances.iter()
+            .chain(&self.transparent_instances)
+            .map(|instance| (instance.position, instance.material.as_ref()))
+            .chain(self.decals.iter().map(|decal| (decal.position, Some(&decal.material))))
+            .chain(self.billboards.iter().map(|billboard| (billboard.position, None)))
+            .chain(skinned);
+
+        let mut distances: HashMap<&str, f32> = HashMap::new();
+        for (position, material) in users {
+            let distance = position.distance(eye);
+            let mut labels = match material.and_then(|handle| self.assets.materials.get(handle)) {
+                Some(material) => material.textures()
+                    .filter_map(|handle| self.assets.textures.get(handle))
+                    .map(ResidentTexture::label)
+                    .collect::<Vec<_>>(),
+                None => vec![self.diffuse_texture.label()]
+            };
+            for label in labels.drain(..) {
+                distances.entry(label)
+                    .and_modify(|closest| *closest = closest.min(distance))
+                    .or_insert(distance);
+            }
+        }
+        let distances = distances.into_iter()
+            .map(|(label, distance)| (String::from(label), distance))
+            .collect::<HashMap<_, _>>();
+
+        let pixels_per_unit = self.pixels_per_unit();
+        let vram_budget = self.asset_residency.stats().budget_bytes;
+        let mut requests = std::iter::once(&mut self.diffuse_texture)
+            .chain(self.assets.textures.iter_mut())
+            .filter(|texture| texture.is_resident())
+            .map(|texture| {
+                let distance = distances.get(texture.label()).copied();
+                StreamRequest::new(texture, distance, pixels_per_unit)
+            })
+            .collect::<Vec<_>>();
+        let changed = match self.texture_streamer.update(&self.device, &self.queue,
+            &self.texture_bind_group_layout, &self.texture_sampler, &mut requests, vram_budget) {
+            Ok(changed) => changed,
+            Err(e) => {
+                log::error!("Texture streaming failed: {e}");
+                true
+            }
+        };
+        drop(requests);
+
+        if changed {
+            for material in self.assets.materials.iter_mut() {
+                material.release_bind_group();
+            }
+            self.asset_residency.resize(&ResidentAsset::DiffuseArray,
+                self.diffuse_texture.size_in_bytes());
+            for (id, texture) in self.assets.textures.iter() {
+                self.asset_residency.resize(&ResidentAsset::Texture(id), texture.size_in_bytes());
+            }
+        }
+    }
+
+    // new function
+    fn get_instance_descriptor(backends: Backends) -> InstanceDescriptor
+    {
+        InstanceDescriptor {
+            backends,
+            ..Default::default()
+        }
+    }
+
+    // An instance with WebGPU can't make WebGL2 adapters, so on the web WebGPU is only
+    // picked when the browser has an adapter for it, not just navigator.gpu.
+    async fn create_instance(backends: Backends) -> WgpuInstance
+    {
+        if cfg!(target_arch = "wasm32") && backends.contains(Backends::BROWSER_WEBGPU) {
+            let instance = WgpuInstance::new(Self::get_instance_descriptor(Backends::BROWSER_WEBGPU));
+            if instance.request_adapter(&RequestAdapterOptions::default()).await.is_some() {
+                log::info!("Rendering with WebGPU");
+                return instance;
+            }
+            log::warn!("WebGPU isn't available, rendering with WebGL2");
+        }
+
+        WgpuInstance::new(Self::get_instance_descriptor(backends - Backends::BROWSER_WEBGPU))
+    }
+
+    // Headless, any adapter will do.
+    async fn select_adapter(
+        instance: &WgpuInstance,
+        surface: Option<&Surface<'a>>,
+        options: &StateOptions
+    ) -> Result<Adapter, RendererError>
+    {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let adapters = instance.enumerate_adapters(options.backends);
+            for (index, adapter) in adapters.iter().enumerate() {
+                let info = adapter.get_info();
+                log::info!("Adapter {index}: {} ({:?}, {:?})", info.name, info.backend,
+                    info.device_type);
+            }
+
+            let requested = adapters.into_iter()
+                .enumerate()
+                .find(|(index, adapter)| options.matches_adapter(*index, &adapter.get_info().name));
+
+            match requested {
+                Some((_, adapter)) if surface.is_none_or(|surface| adapter.is_surface_supported(surface)) => {
+                    return Ok(adapter);
+                },
+                Some((index, _)) => log::warn!("Adapter {index} can't present to the window"),
+                None if options.requests_specific_adapter() => {
+                    log::warn!("No adapter matches {:?}/{:?}", options.adapter_name,
+                        options.adapter_index);
+                },
+                None => {}
+            }
+        }
+
+        if !options.fallback_adapter {
+            let descriptor = Self::get_adapter_descriptor(surface, options.power_preference, false);
+            if let Some(adapter) = instance.request_adapter(&descriptor).await {
+                return Ok(adapter);
+            }
+            log::warn!("No GPU adapter found, trying a software one");
+        }
+
+        instance.request_adapter(&Self::get_adapter_descriptor(surface, options.power_preference, true))
+            .await
+            .ok_or(RendererError::NoAdapter)
+    }
+
+    fn get_adapter_descriptor<'b>(
+        surface: Option<&'b Surface<'a>>,
+        power_preference: PowerPreference,
+        force_fallback_adapter: bool
+    ) -> RequestAdapterOptions<'b, 'a>
+    {
+        RequestAdapterOptions {
+            power_preference,
+            compatible_surface: surface,
+            force_fallback_adapter
+        }
+    }
+
+    fn max_anisotropy(adapter: &Adapter) -> u16
+    {
+        if adapter.get_downlevel_capabilities().flags.contains(DownlevelFlags::ANISOTROPIC_FILTERING) {
+            DEFAULT_ANISOTROPY
+        } else {
+            1
+        }
+    }
+
+    fn get_device_descriptor(adapter: &Adapter) -> DeviceDescriptor<'a>
+    {
+        DeviceDescriptor {
+            required_features: adapter.features() & GpuCapabilities::optional_features(),
+            required_limits: if cfg!(target_arch = "wasm32") && adapter.get_info().backend == Backend::Gl {
+                Limits::downlevel_webgl2_defaults()
+            } else {
+                Limits::default()
+            },
+            label: Some("Device")
+        }
+    }
+
+    fn get_configuration(
+        surface: Option<&Surface>,
+        adapter: &Adapter,
+        size: &PhysicalSize<u32>,
+        surface_options: &SurfaceOptions
+    ) -> SurfaceConfiguration
+    {
+        match surface {
+            Some(surface) => Self::get_surface_configuration(surface, adapter, size, surface_options),
+            None => Self::get_headless_configuration(size, surface_options)
+        }
+    }
+
+    // Like a surface's, so nothing else has to care whether it's running headless. The
+    // texture can be copied from, for read_frame.
+    fn get_headless_configuration(size: &PhysicalSize<u32>, surface_options: &SurfaceOptions) -> SurfaceConfiguration
+    {
+        let format = surface_options.select_format(&HEADLESS_FORMATS);
+
+        SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: PresentMode::Fifo,
+            alpha_mode: CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2
+        }
+    }
+
+    fn create_frame_output(device: &Device, config: &SurfaceConfiguration, surface: Option<Surface<'a>>) -> FrameOutput<'a>
+    {
+        match surface {
+            Some(surface) => {
+                surface.configure(device, config);
+                FrameOutput::Surface(surface)
+            },
+            None => FrameOutput::Texture(Self::create_headless_texture(device, config))
+        }
+    }
+
+    fn create_headless_texture(device: &Device, config: &SurfaceConfiguration) -> wgpu::Texture
+    {
+        device.create_texture(
+            &TextureDescriptor {
+                label: Some("Headless Frame Texture"),
+                size: Extent3d {
+                    width: config.width.max(1),
+                    height: config.height.max(1),
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: config.format,
+                usage: config.usage,
+                view_formats: &config.view_formats
+            }
+        )
+    }
+
+    fn get_surface_configuration(
+        surface: &Surface,
+        adapter: &Adapter,
+        size: &PhysicalSize<u32>,
+        surface_options: &SurfaceOptions
+    ) -> SurfaceConfiguration
+    {
+        let surface_capabilities = surface.get_capabilities(adapter);
+        let surface_format = surface_options.select_format(&surface_capabilities.formats);
+        let view_formats = if adapter.get_downlevel_capabilities().flags
+            .contains(DownlevelFlags::SURFACE_VIEW_FORMATS) {
+            SurfaceOptions::view_formats(surface_format)
+        } else {
+            vec![]
+        };
+        log::info!("Surface format: {surface_format:?}, view formats: {view_formats:?}");
+
+        SurfaceConfiguration {
+            // Copied from for screenshots where the surface allows it.
+            usage: TextureUsages::RENDER_ATTACHMENT | (surface_capabilities.usages & TextureUsages::COPY_SRC),
+            format: surface_format,
+            // A canvas on the web has no size until the page is laid out, it's resized then.
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: surface_options.present_mode(),
+            alpha_mode: surface_capabilities.alpha_modes[0],
+            view_formats,
+            desired_maximum_frame_latency: 2
+        }
+    }
+
+    pub fn get_camera_bind_group_layout(device: &Device) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&DebugLabels::new(CAMERA_LABEL).bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    fn create_camera_binding(
+        device: &Device,
+        layout: &BindGroupLayout,
+        camera_uniform: &CameraUniform
+    ) -> (Buffer, BindGroup)
+    {
+        let labels = DebugLabels::new(CAMERA_LABEL);
+        let camera_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[*camera_uniform]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        let camera_bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.bind_group()),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: camera_buffer.as_entire_binding()
+                    }
+                ]
+            }
+        );
+
+        (camera_buffer, camera_bind_group)
+    }
+
+    pub fn get_vertex_animation_bind_group_layout(device: &Device) -> BindGroupLayout
+    {
+        device.create_bind_group_layout(
+            &BindGroupLayoutDescriptor {
+                label: Some(&DebugLabels::new(VERTEX_ANIMATION_LABEL).bind_group_layout()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None
+                        },
+                        count: None
+                    }
+                ]
+            }
+        )
+    }
+
+    fn create_vertex_animation_binding(
+        device: &Device,
+        layout: &BindGroupLayout,
+        vertex_animation_uniform: &VertexAnimationUniform
+    ) -> (Buffer, BindGroup)
+    {
+        let labels = DebugLabels::new(VERTEX_ANIMATION_LABEL);
+        let vertex_animation_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.buffer()),
+                contents: bytemuck::cast_slice(&[*vertex_animation_uniform]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+
+        let vertex_animation_bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.bind_group()),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: vertex_animation_buffer.as_entire_binding()
+                    }
+                ]
+            }
+        );
+
+        (vertex_animation_buffer, vertex_animation_bind_group)
+    }
+
+    fn create_render_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(INSTANCE_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Vertex, "vs_main", "fs_main")
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count);
+        InstanceStorage::for_device(device).configure(&mut builder);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_transparent_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(TRANSPARENT_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Vertex, "vs_main", "fs_transparent")
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count)
+            .set_blend_mode(BlendMode::AlphaBlending)
+            .set_depth_state(false, CompareFunction::Less);
+        InstanceStorage::for_device(device).configure(&mut builder);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    // Blended like the transparent instances, tested against the opaque depth without
+    // writing any.
+    fn create_billboard_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(BILLBOARD_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Billboard, "vs_billboard", "fs_billboard")
+            .set_vertex_layouts(&[BillboardRaw::vertex_buffer_layout()])
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count)
+            .set_blend_mode(BlendMode::AlphaBlending)
+            .set_depth_state(false, CompareFunction::Less);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_skinned_mesh(
+        device: &Device,
+        queue: &Queue,
+        gpu_allocator: &mut GpuAllocator,
+        model: &SkinnedModel
+    ) -> SkinnedMesh
+    {
+        let instance = Instance {
+            position: Vector3::zero(),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            animation: AnimationParams::default(),
+            color: instance::WHITE,
+            texture_index: 0,
+            material: None,
+            emissive_intensity: None
+        };
+
+        SkinnedMesh::new(device, queue, gpu_allocator, SKINNED_PIPELINE_LABEL, model, &instance)
+    }
+
+    fn create_skinned_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(SKINNED_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Skinned, "vs_skinned", "fs_main")
+            .set_vertex_layouts(&[
+                SkinnedVertex::vertex_buffer_layout(),
+                InstanceRaw::vertex_buffer_layout()
+            ])
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    // Skinning picks the skinned vertex stage, the other features are preprocessor
+    // defines, so every feature combination is its own cached pipeline.
+    fn create_material_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        features: MaterialFeatures,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(&format!("{MATERIAL_PIPELINE_LABEL} {}", features.name()))
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count);
+        if features.skinning {
+            builder
+                .set_shader_module(ShaderHandle::Skinned, "vs_skinned", "fs_main")
+                .set_vertex_layouts(&[
+                    SkinnedVertex::vertex_buffer_layout(),
+                    InstanceRaw::vertex_buffer_layout()
+                ]);
+        } else {
+            builder.set_shader_module(ShaderHandle::Vertex, "vs_main", "fs_main");
+            InstanceStorage::for_device(device).configure(&mut builder);
+        }
+        features.configure(&mut builder);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    // Decals share the material permutations' bind group layouts and defines. They
+    // draw the back faces of their box, so the camera can stand inside one.
+    fn create_decal_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        features: MaterialFeatures,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(&format!("{DECAL_PIPELINE_LABEL} {}", features.name()))
+            .set_shader_module(ShaderHandle::Decal, "vs_decal", "fs_decal")
+            .set_vertex_layouts(&[DecalRaw::vertex_buffer_layout()])
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count)
+            .set_primitive(PrimitiveTopology::TriangleList, Some(Face::Front), FrontFace::Ccw,
+                PolygonMode::Fill)
+            .set_blend_mode(BlendMode::AlphaBlending)
+            .set_depth_disabled();
+        features.configure(&mut builder);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    // Both sides of the plants' quads are drawn, cut out by their alpha.
+    fn create_cull_pipeline(
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<ComputePipeline, RendererError>
+    {
+        ComputePipelineBuilder::builder()
+            .set_label(CULL_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Cull, "cs_cull")
+            .build(device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_vegetation_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(VEGETATION_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Vegetation, "vs_vegetation", "fs_vegetation")
+            .set_vertex_layouts(&[PlantRaw::vertex_buffer_layout()])
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count)
+            .set_primitive(PrimitiveTopology::TriangleList, None, FrontFace::Ccw, PolygonMode::Fill)
+            .set_alpha_to_coverage(true);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_terrain_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(TERRAIN_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Terrain, "vs_terrain", "fs_terrain")
+            .set_vertex_layouts(&[TerrainVertex::vertex_buffer_layout()])
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    // Double sided so it can be seen from below, its vertices come from the vertex index.
+    fn create_water_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(WATER_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Water, "vs_water", "fs_water")
+            .set_vertex_layouts(&[])
+            .set_primitive(PrimitiveTopology::TriangleList, None, FrontFace::Ccw, PolygonMode::Fill)
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    // Depth tested against the scene without writing, so hidden edges stay hidden.
+    fn create_debug_lines_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(DEBUG_LINES_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::DebugLines, "vs_lines", "fs_lines")
+            .set_vertex_layouts(&[LineVertex::vertex_buffer_layout()])
+            .set_primitive(PrimitiveTopology::LineList, None, FrontFace::Ccw, PolygonMode::Fill)
+            .set_depth_state(false, CompareFunction::LessEqual)
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_debug_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        sample_count: u32,
+        view: DebugView,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        view.configure(&mut builder);
+        builder
+            .set_pixel_format(config.format)
+            .set_sample_count(sample_count);
+        InstanceStorage::for_device(device).configure(&mut builder);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    // The scene pipeline's vertex stage, writing motion vectors only where it matches
+    // the depth the main pass left.
+    fn create_motion_vector_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        sample_count: u32,
+        shader: ShaderHandle,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder.set_label(&format!("{MOTION_VECTORS_PIPELINE_LABEL} {shader:?}"));
+        match shader {
+            ShaderHandle::Terrain => {
+                builder
+                    .set_shader_module(ShaderHandle::Terrain, "vs_terrain", "fs_motion_vectors")
+                    .set_vertex_layouts(&[TerrainVertex::vertex_buffer_layout()]);
+            },
+            ShaderHandle::Skinned => {
+                builder
+                    .set_shader_module(ShaderHandle::Skinned, "vs_skinned", "fs_motion_vectors")
+                    .set_vertex_layouts(&[
+                        SkinnedVertex::vertex_buffer_layout(),
+                        InstanceRaw::vertex_buffer_layout()
+                    ]);
+            },
+            _ => {
+                builder.set_shader_module(ShaderHandle::Vertex, "vs_main", "fs_motion_vectors");
+                InstanceStorage::for_device(device).configure(&mut builder);
+            }
+        }
+        builder
+            .set_pixel_format(MOTION_VECTOR_FORMAT)
+            .set_sample_count(sample_count)
+            .set_depth_state(false, CompareFunction::LessEqual);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_taa_resolve_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(TAA_RESOLVE_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Taa, "vs_fullscreen", "fs_resolve")
+            .set_fullscreen()
+            .set_pixel_format(config.format);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_upscale_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        upscaling: Upscaling,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(UPSCALE_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Blit, "vs_fullscreen", upscaling.fragment_entry())
+            .set_fullscreen()
+            .set_pixel_format(config.format);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_blit_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        builder
+            .set_label(BLIT_PIPELINE_LABEL)
+            .set_shader_module(ShaderHandle::Blit, "vs_fullscreen", "fs_blit")
+            .set_fullscreen()
+            .set_pixel_format(config.format);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_post_effect_pipeline(
+        pipeline_cache: &mut PipelineCache,
+        device: &Device,
+        shader_registry: &ShaderRegistry,
+        config: &SurfaceConfiguration,
+        effect: PostEffect,
+        bind_group_layouts: &[&BindGroupLayout]
+    ) -> Result<Rc<RenderPipeline>, RendererError>
+    {
+        let mut builder = PipelineBuilder::builder();
+        effect.configure(&mut builder);
+        builder.set_pixel_format(config.format);
+
+        pipeline_cache.get_or_build(&mut builder, device, shader_registry, bind_group_layouts)
+    }
+
+    fn create_instance_buffers(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout
+    ) -> (InstanceBuffer, InstanceBuffer)
+    {
+        let storage = InstanceStorage::for_device(device);
 
-        (vertex_buffer, index_buffer, num_indices)
+        (
+            InstanceBuffer::new(device, INSTANCE_PIPELINE_LABEL, bind_group_layout, storage),
+            InstanceBuffer::new(device, TRANSPARENT_PIPELINE_LABEL, bind_group_layout, storage)
+        )
     }
 
     // render function
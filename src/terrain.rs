@@ -0,0 +1,319 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Point3, Vector3};
+use image::{imageops::FilterType, DynamicImage};
+use wgpu::{vertex_attr_array, VertexAttribute};
+
+use super::{bounds::Bounds, picking::{Ray, RayHit}, renderer_backend::vertex_layout::VertexLayout};
+
+// Quads along each side of a chunk. Every chunk has the same grid, so the index
+// buffers for each LOD level are shared by all of them.
+pub const CHUNK_CELLS: u32 = 32;
+pub const LOD_LEVELS: u32 = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TerrainOptions {
+    // Chunks along each side, the terrain is centered on the origin.
+    pub chunks: u32,
+    pub cell_size: f32,
+    // Heights go from base_height to base_height + height_scale.
+    pub height_scale: f32,
+    pub base_height: f32,
+    pub seed: u32,
+    // Chunks closer than this use full detail, each doubling of the distance halves it.
+    pub lod_distance: f32
+}
+
+impl Default for TerrainOptions {
+    fn default() -> Self
+    {
+        Self {
+            chunks: 8,
+            cell_size: 1.0,
+            height_scale: 6.0,
+            base_height: -6.0,
+            seed: 0,
+            lod_distance: 48.0
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct TerrainVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3]
+}
+
+impl VertexLayout for TerrainVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3
+    ];
+}
+
+// Heights in 0..1 on a grid of `size` x `size` samples, scaled and placed by the options.
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    size: u32,
+    heights: Vec<f32>,
+    options: TerrainOptions
+}
+
+impl Heightmap {
+    pub fn from_noise(options: &TerrainOptions) -> Self
+    {
+        let size = Self::size_for(options);
+        let heights = (0..size * size)
+            .map(|index| {
+                let (x, z) = ((index % size) as f32, (index / size) as f32);
+                fractal_noise(x / 48.0, z / 48.0, options.seed)
+            })
+            .collect();
+
+        Self {
+            size,
+            heights,
+            options: *options
+        }
+    }
+
+    // Uses the image's brightness, resampled to the grid the options ask for.
+    pub fn from_image(image: &DynamicImage, options: &TerrainOptions) -> Self
+    {
+        let size = Self::size_for(options);
+        let luma = image::imageops::resize(&image.to_luma16(), size, size, FilterType::Triangle);
+        let heights = luma.pixels()
+            .map(|pixel| pixel[0] as f32 / u16::MAX as f32)
+            .collect();
+
+        Self {
+            size,
+            heights,
+            options: *options
+        }
+    }
+
+    pub fn options(&self) -> &TerrainOptions
+    {
+        &self.options
+    }
+
+    pub fn chunks(&self) -> u32
+    {
+        self.options.chunks
+    }
+
+    // World space height of the surface, bilinearly interpolated between samples the
+    // same way the triangles are drawn at full detail. None outside the terrain.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32>
+    {
+        let (grid_x, grid_z) = self.to_grid(x, z)?;
+        let (cell_x, cell_z) = (grid_x.floor() as u32, grid_z.floor() as u32);
+        let (fx, fz) = (grid_x.fract(), grid_z.fract());
+
+        let top = self.sample(cell_x, cell_z)
+            + (self.sample(cell_x + 1, cell_z) - self.sample(cell_x, cell_z)) * fx;
+        let bottom = self.sample(cell_x, cell_z + 1)
+            + (self.sample(cell_x + 1, cell_z + 1) - self.sample(cell_x, cell_z + 1)) * fx;
+
+        Some(top + (bottom - top) * fz)
+    }
+
+    pub fn normal_at(&self, x: f32, z: f32) -> Option<Vector3<f32>>
+    {
+        let (grid_x, grid_z) = self.to_grid(x, z)?;
+
+        Some(self.normal(grid_x.round() as u32, grid_z.round() as u32))
+    }
+
+    // Marches along the ray half a cell at a time until it goes below the surface,
+    // then bisects the last step to find the crossing.
+    pub fn intersect_ray(&self, ray: &Ray, max_distance: f32) -> Option<RayHit>
+    {
+        let step = self.options.cell_size * 0.5;
+        let is_below = |distance: f32| {
+            let point = ray.at(distance);
+            self.height_at(point.x, point.z).is_some_and(|height| point.y <= height)
+        };
+
+        let mut previous = 0.0;
+        let mut distance = step;
+        while distance <= max_distance {
+            if is_below(distance) {
+                let (mut above, mut below) = (previous, distance);
+                for _ in 0..8 {
+                    let middle = (above + below) * 0.5;
+                    if is_below(middle) { below = middle } else { above = middle }
+                }
+                let point = ray.at(below);
+
+                return Some(RayHit {
+                    distance: below,
+                    point,
+                    normal: self.normal_at(point.x, point.z)?
+                });
+            }
+            previous = distance;
+            distance += step;
+        }
+
+        None
+    }
+
+    fn to_grid(&self, x: f32, z: f32) -> Option<(f32, f32)>
+    {
+        let half_extent = (self.size - 1) as f32 * self.options.cell_size * 0.5;
+        let grid_x = (x + half_extent) / self.options.cell_size;
+        let grid_z = (z + half_extent) / self.options.cell_size;
+        let last = (self.size - 1) as f32;
+
+        ((0.0..=last).contains(&grid_x) && (0.0..=last).contains(&grid_z)).then_some((grid_x, grid_z))
+    }
+
+    fn size_for(options: &TerrainOptions) -> u32
+    {
+        options.chunks.max(1) * CHUNK_CELLS + 1
+    }
+
+    fn sample(&self, x: u32, z: u32) -> f32
+    {
+        let (x, z) = (x.min(self.size - 1), z.min(self.size - 1));
+        self.options.base_height + self.heights[(z * self.size + x) as usize] * self.options.height_scale
+    }
+
+    fn position(&self, x: u32, z: u32) -> Point3<f32>
+    {
+        let half_extent = (self.size - 1) as f32 * self.options.cell_size * 0.5;
+
+        Point3::new(
+            x as f32 * self.options.cell_size - half_extent,
+            self.sample(x, z),
+            z as f32 * self.options.cell_size - half_extent
+        )
+    }
+
+    // Central differences, clamped at the borders.
+    fn normal(&self, x: u32, z: u32) -> Vector3<f32>
+    {
+        let left = self.sample(x.saturating_sub(1), z);
+        let right = self.sample(x + 1, z);
+        let back = self.sample(x, z.saturating_sub(1));
+        let front = self.sample(x, z + 1);
+
+        Vector3::new(left - right, 2.0 * self.options.cell_size, back - front).normalize()
+    }
+
+    // All chunks back to back, row by row, CHUNK_CELLS + 1 squared vertices each.
+    // Neighbouring chunks duplicate their shared edge so each can be drawn on its own.
+    pub fn chunk_vertices(&self) -> Vec<TerrainVertex>
+    {
+        let chunks = self.chunks();
+        let mut vertices = Vec::with_capacity(((CHUNK_CELLS + 1).pow(2) * chunks * chunks) as usize);
+
+        for chunk_z in 0..chunks {
+            for chunk_x in 0..chunks {
+                for z in 0..=CHUNK_CELLS {
+                    for x in 0..=CHUNK_CELLS {
+                        let (x, z) = (chunk_x * CHUNK_CELLS + x, chunk_z * CHUNK_CELLS + z);
+                        vertices.push(TerrainVertex {
+                            position: self.position(x, z).into(),
+                            normal: self.normal(x, z).into()
+                        });
+                    }
+                }
+            }
+        }
+
+        vertices
+    }
+
+    pub fn chunk_center(&self, chunk: u32) -> Point3<f32>
+    {
+        let (chunk_x, chunk_z) = (chunk % self.chunks(), chunk / self.chunks());
+        let center = |chunk: u32| chunk * CHUNK_CELLS + CHUNK_CELLS / 2;
+
+        self.position(center(chunk_x), center(chunk_z))
+    }
+
+    pub fn chunk_bounds(&self, chunk: u32) -> Bounds
+    {
+        let (chunk_x, chunk_z) = (chunk % self.chunks(), chunk / self.chunks());
+        let points = (0..=CHUNK_CELLS)
+            .flat_map(|z| (0..=CHUNK_CELLS).map(move |x| (x, z)))
+            .map(|(x, z)| self.position(chunk_x * CHUNK_CELLS + x, chunk_z * CHUNK_CELLS + z))
+            .collect::<Vec<_>>();
+
+        Bounds::from_points(&points)
+    }
+}
+
+// Indices of one chunk for every LOD level, concatenated. Level n only uses every
+// 2^n-th vertex. Returns the indices and where each level starts and ends.
+pub fn lod_indices() -> (Vec<u16>, Vec<std::ops::Range<u32>>)
+{
+    let row = CHUNK_CELLS + 1;
+    let mut indices = Vec::new();
+    let mut ranges = Vec::new();
+
+    for level in 0..LOD_LEVELS {
+        let step = 1 << level;
+        let start = indices.len() as u32;
+
+        for z in (0..CHUNK_CELLS).step_by(step) {
+            for x in (0..CHUNK_CELLS).step_by(step) {
+                let top_left = (z * row + x) as u16;
+                let top_right = (z * row + x + step as u32) as u16;
+                let bottom_left = ((z + step as u32) * row + x) as u16;
+                let bottom_right = ((z + step as u32) * row + x + step as u32) as u16;
+
+                indices.extend([top_left, bottom_left, top_right, top_right, bottom_left, bottom_right]);
+            }
+        }
+
+        ranges.push(start..indices.len() as u32);
+    }
+
+    (indices, ranges)
+}
+
+// Pseudo random in [0, 1), the same for the same grid point and seed.
+pub fn hash(x: i32, z: i32, seed: u32) -> f32
+{
+    let hash = (x as u32).wrapping_mul(0x8DA6_B343)
+        ^ (z as u32).wrapping_mul(0xD816_3841)
+        ^ seed.wrapping_mul(0xCB1A_B31F);
+    let hash = hash.rotate_left(13).wrapping_mul(0x85EB_CA6B);
+
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
+
+fn value_noise(x: f32, z: f32, seed: u32) -> f32
+{
+    let (cell_x, cell_z) = (x.floor(), z.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (fx, fz) = (smooth(x - cell_x), smooth(z - cell_z));
+    let (ix, iz) = (cell_x as i32, cell_z as i32);
+
+    let top = hash(ix, iz, seed) + (hash(ix + 1, iz, seed) - hash(ix, iz, seed)) * fx;
+    let bottom = hash(ix, iz + 1, seed) + (hash(ix + 1, iz + 1, seed) - hash(ix, iz + 1, seed)) * fx;
+
+    top + (bottom - top) * fz
+}
+
+// Five octaves of value noise, normalized back to 0..1.
+fn fractal_noise(x: f32, z: f32, seed: u32) -> f32
+{
+    let mut value = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.0;
+    let mut total = 0.0;
+
+    for octave in 0..5 {
+        value += value_noise(x * frequency, z * frequency, seed.wrapping_add(octave)) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    value / total
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use cgmath::{InnerSpace, Vector2};
+use winit::{dpi::PhysicalPosition, event::TouchPhase};
+
+// What the fingers on the screen did since the last touch event, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    // One finger dragged by this much.
+    Drag(Vector2<f32>),
+    // Two fingers moved apart by `zoom` times their previous distance, and the point
+    // between them moved by `pan`.
+    Pinch {
+        zoom: f32,
+        pan: Vector2<f32>
+    }
+}
+
+// Follows the fingers touching the screen and turns their movement into gestures. A
+// third finger or more is tracked but ignored, the first two pinch.
+#[derive(Debug, Default)]
+pub struct TouchGestures {
+    touches: BTreeMap<u64, Vector2<f32>>
+}
+
+impl TouchGestures {
+    // A winit Touch's id, phase and location, as a render worker's page forwards them
+    // without one.
+    pub fn process(
+        &mut self,
+        id: u64,
+        phase: TouchPhase,
+        location: PhysicalPosition<f64>
+    ) -> Option<Gesture>
+    {
+        let position = Vector2::new(location.x as f32, location.y as f32);
+        match phase {
+            TouchPhase::Started => {
+                self.touches.insert(id, position);
+                None
+            },
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&id);
+                None
+            },
+            TouchPhase::Moved => {
+                let before = self.pinch();
+                let previous = self.touches.insert(id, position)?;
+
+                match (before, self.pinch()) {
+                    (Some((center, distance)), Some((new_center, new_distance))) if distance > 0.0 => {
+                        Some(Gesture::Pinch {
+                            zoom: new_distance / distance,
+                            pan: new_center - center
+                        })
+                    },
+                    (None, None) => Some(Gesture::Drag(position - previous)),
+                    _ => None
+                }
+            }
+        }
+    }
+
+    // The point between the first two fingers and how far apart they are.
+    fn pinch(&self) -> Option<(Vector2<f32>, f32)>
+    {
+        let mut touches = self.touches.values();
+        let (first, second) = (touches.next()?, touches.next()?);
+
+        Some(((first + second) / 2.0, (first - second).magnitude()))
+    }
+}
@@ -0,0 +1,119 @@
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{vertex_attr_array, VertexAttribute, VertexStepMode};
+
+use super::{camera::halton, renderer_backend::vertex_layout::VertexLayout, terrain::{self, Heightmap}, vertex_animation::AnimationParams};
+
+#[derive(Debug, Clone, Copy)]
+pub struct VegetationOptions {
+    // Plants per square world unit.
+    pub density: f32,
+    pub seed: u32,
+    // Every plant is somewhere between the two heights, in world units.
+    pub min_height: f32,
+    pub max_height: f32,
+    // Relative to the plant's height.
+    pub width: f32,
+    // In degrees from horizontal, nothing grows on steeper ground.
+    pub max_slope: f32,
+    // How far the tips bend at full wind strength, in world units.
+    pub sway: f32,
+    // Chunks of plants further than this from the camera aren't drawn.
+    pub draw_distance: f32,
+    pub color: [f32; 4],
+    // Layer of the material texture array whose alpha cuts the plants out, e.g. leaves.
+    // None draws plain grass blades.
+    pub texture_index: Option<u32>
+}
+
+impl Default for VegetationOptions {
+    fn default() -> Self
+    {
+        Self {
+            density: 2.0,
+            seed: 0,
+            min_height: 0.3,
+            max_height: 0.6,
+            width: 0.8,
+            max_slope: 35.0,
+            sway: 0.15,
+            draw_distance: 48.0,
+            color: [0.35, 0.6, 0.2, 1.0],
+            texture_index: None
+        }
+    }
+}
+
+// Matches PlantInput in vegetation.wgsl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct PlantRaw {
+    // xyz is where it stands, w its rotation around y in radians
+    position: [f32; 4],
+    // width, height, phase, sway
+    shape: [f32; 4],
+    color: [f32; 4],
+    // texture index, 1 when it's cut out of the texture
+    material: [u32; 4]
+}
+
+// The only vertex buffer, the plant's quads come from the vertex index.
+impl VertexLayout for PlantRaw {
+    const ATTRIBUTES: &'static [VertexAttribute] = &vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+        2 => Float32x4,
+        3 => Uint32x4
+    ];
+    const STEP_MODE: VertexStepMode = VertexStepMode::Instance;
+}
+
+// Plants over every chunk of the terrain, in chunk order, with the range each chunk's
+// plants take up. Within a chunk they follow a Halton sequence, shifted by a different
+// random offset in every chunk so the pattern doesn't repeat.
+pub fn scatter(heightmap: &Heightmap, options: &VegetationOptions) -> (Vec<PlantRaw>, Vec<Range<u32>>)
+{
+    let min_normal_y = options.max_slope.clamp(0.0, 90.0).to_radians().cos();
+    let (min_height, max_height) = (options.min_height.max(0.0), options.max_height.max(0.0));
+    let material = match options.texture_index {
+        Some(texture_index) => [texture_index, 1, 0, 0],
+        None => [0; 4]
+    };
+
+    let mut plants = Vec::new();
+    let mut ranges = Vec::new();
+    for chunk in 0..heightmap.chunks().pow(2) {
+        let aabb = heightmap.chunk_bounds(chunk).aabb;
+        let (width, depth) = (aabb.max.x - aabb.min.x, aabb.max.z - aabb.min.z);
+        let count = (width * depth * options.density.max(0.0)).round() as u32;
+        let offset_x = terrain::hash(chunk as i32, 0, options.seed);
+        let offset_z = terrain::hash(chunk as i32, 1, options.seed);
+
+        let start = plants.len() as u32;
+        for index in 1..=count {
+            let x = aabb.min.x + (halton(index, 2) + offset_x).fract() * width;
+            let z = aabb.min.z + (halton(index, 3) + offset_z).fract() * depth;
+            let (Some(y), Some(normal)) = (heightmap.height_at(x, z), heightmap.normal_at(x, z)) else {
+                continue;
+            };
+            if normal.y < min_normal_y {
+                continue;
+            }
+
+            let plant = chunk.wrapping_mul(count).wrapping_add(index);
+            let animation = AnimationParams::from_index(plant ^ options.seed, options.sway);
+            let height = min_height + (max_height - min_height) * animation.seed;
+            let yaw = halton(index, 5) * std::f32::consts::TAU;
+            plants.push(PlantRaw {
+                position: [x, y, z, yaw],
+                shape: [height * options.width, height, animation.phase, animation.amplitude],
+                color: options.color,
+                material
+            });
+        }
+        ranges.push(start..plants.len() as u32);
+    }
+
+    (plants, ranges)
+}
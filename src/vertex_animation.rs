@@ -0,0 +1,75 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Vector3};
+use web_time::Duration;
+
+// Per-instance inputs for the vertex animation in vertex.wgsl. The shader offsets
+// vertices along the wind direction by `sin(time * speed(seed) + phase) * amplitude`,
+// so instances sway independently without any per-instance CPU updates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnimationParams {
+    pub phase: f32,
+    pub amplitude: f32,
+    pub seed: f32
+}
+
+impl AnimationParams {
+    // Deterministic pseudo random parameters, so the same index always animates the same way.
+    pub fn from_index(index: u32, amplitude: f32) -> Self
+    {
+        let hash = index.wrapping_mul(0x9E37_79B9).rotate_left(16).wrapping_mul(0x85EB_CA6B);
+        let seed = (hash >> 8) as f32 / (1 << 24) as f32;
+
+        Self {
+            phase: seed * std::f32::consts::TAU,
+            amplitude,
+            seed
+        }
+    }
+
+    pub fn to_raw(self) -> [f32; 4]
+    {
+        [self.phase, self.amplitude, self.seed, 0.0]
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct VertexAnimationUniform {
+    // xyz is the normalized wind direction, w its strength. A strength of 0 disables the animation.
+    wind: [f32; 4],
+    time: f32,
+    _padding: [f32; 3]
+}
+
+impl VertexAnimationUniform {
+    pub fn new() -> Self
+    {
+        Self {
+            wind: [1.0, 0.0, 0.0, 0.0],
+            time: 0.0,
+            _padding: [0.0; 3]
+        }
+    }
+
+    pub fn advance(&mut self, delta: Duration)
+    {
+        // Wrapped to keep sin() precise on long sessions.
+        self.time = (self.time + delta.as_secs_f32()) % 3600.0;
+    }
+
+    pub fn set_wind(&mut self, direction: Vector3<f32>, strength: f32)
+    {
+        let direction = if direction.magnitude2() > f32::EPSILON {
+            direction.normalize()
+        } else {
+            Vector3::unit_x()
+        };
+
+        self.wind = [direction.x, direction.y, direction.z, strength];
+    }
+
+    pub fn wind(&self) -> [f32; 4]
+    {
+        self.wind
+    }
+}
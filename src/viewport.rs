@@ -0,0 +1,173 @@
+use cgmath::Point3;
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, Buffer, BufferUsages, Device, Queue, RenderPass};
+
+use super::{camera::{Camera, CameraUniform}, renderer_backend::debug_labels::DebugLabels};
+
+// A region of the window in fractions of its size, from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+impl ViewportRect {
+    pub const FULL: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0
+    };
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self
+    {
+        Self { x, y, width, height }
+    }
+
+    // Column `index` of `count` side by side, for split screen.
+    pub fn column(index: u32, count: u32) -> Self
+    {
+        let width = 1.0 / count.max(1) as f32;
+
+        Self::new(index as f32 * width, 0.0, width, 1.0)
+    }
+
+    // In pixels, clamped to the window and at least one pixel in size.
+    pub fn to_pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32)
+    {
+        let (window_width, window_height) = (width.max(1) as f32, height.max(1) as f32);
+        let x = (self.x.clamp(0.0, 1.0) * window_width) as u32;
+        let y = (self.y.clamp(0.0, 1.0) * window_height) as u32;
+        let right = ((self.x + self.width).clamp(0.0, 1.0) * window_width) as u32;
+        let bottom = ((self.y + self.height).clamp(0.0, 1.0) * window_height) as u32;
+        let (x, y) = (x.min(width.max(1) - 1), y.min(height.max(1) - 1));
+
+        (x, y, right.saturating_sub(x).max(1), bottom.saturating_sub(y).max(1))
+    }
+
+    pub fn aspect(&self, width: u32, height: u32) -> f32
+    {
+        let (_, _, width, height) = self.to_pixels(width, height);
+
+        width as f32 / height as f32
+    }
+
+    // Viewport and scissor both, so nothing is drawn outside of it.
+    pub fn apply(&self, render_pass: &mut RenderPass, width: u32, height: u32)
+    {
+        let (x, y, width, height) = self.to_pixels(width, height);
+        render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+        render_pass.set_scissor_rect(x, y, width, height);
+    }
+}
+
+// One more view of the scene, drawn over the main one in its own rect through its own
+// camera, e.g. the second player's half of the window or a top-down minimap inset.
+pub struct Viewport {
+    labels: DebugLabels,
+    rect: ViewportRect,
+    camera: Camera,
+    camera_buffer: Buffer,
+    camera_bind_group: BindGroup
+}
+
+impl Viewport {
+    pub fn new(
+        device: &Device,
+        label: &str,
+        rect: ViewportRect,
+        camera: Camera,
+        camera_bind_group_layout: &BindGroupLayout
+    ) -> Self
+    {
+        let labels = DebugLabels::new(label);
+        let (camera_buffer, camera_bind_group) = Self::create_camera_binding(device, &labels,
+            camera_bind_group_layout);
+
+        Self {
+            labels,
+            rect,
+            camera,
+            camera_buffer,
+            camera_bind_group
+        }
+    }
+
+    // After a device loss.
+    pub fn recreate(&mut self, device: &Device, camera_bind_group_layout: &BindGroupLayout)
+    {
+        (self.camera_buffer, self.camera_bind_group) = Self::create_camera_binding(device,
+            &self.labels, camera_bind_group_layout);
+    }
+
+    pub fn label(&self) -> &str
+    {
+        self.labels.name()
+    }
+
+    pub fn rect(&self) -> ViewportRect
+    {
+        self.rect
+    }
+
+    pub fn set_rect(&mut self, rect: ViewportRect)
+    {
+        self.rect = rect;
+    }
+
+    pub fn camera(&self) -> &Camera
+    {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera
+    {
+        &mut self.camera
+    }
+
+    pub fn camera_bind_group(&self) -> &BindGroup
+    {
+        &self.camera_bind_group
+    }
+
+    // The aspect follows the rect's size in the window.
+    // `origin` is what the instances are uploaded relative to.
+    pub fn write_camera(&mut self, queue: &Queue, width: u32, height: u32, origin: Point3<f64>)
+    {
+        self.camera.aspect = self.rect.aspect(width, height);
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.set_origin(origin);
+        camera_uniform.update_view_proj(&self.camera);
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+    }
+
+    fn create_camera_binding(
+        device: &Device,
+        labels: &DebugLabels,
+        layout: &BindGroupLayout
+    ) -> (Buffer, BindGroup)
+    {
+        let camera_buffer = device.create_buffer_init(
+            &BufferInitDescriptor {
+                label: Some(&labels.with_suffix("Camera Buffer")),
+                contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+            }
+        );
+        let camera_bind_group = device.create_bind_group(
+            &BindGroupDescriptor {
+                label: Some(&labels.with_suffix("Camera Bind Group")),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: camera_buffer.as_entire_binding()
+                    }
+                ]
+            }
+        );
+
+        (camera_buffer, camera_bind_group)
+    }
+}
@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+
+use cgmath::Point3;
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+use wgpu::Color;
+use winit::{dpi::{PhysicalPosition, PhysicalSize}, event::TouchPhase, event_loop::EventLoopProxy, keyboard::ModifiersState};
+
+use crate::{custom_event::CustomEvent, renderer_backend::{asset_fetch, shader_registry::ShaderHandle}, web_worker, Binding, State};
+
+thread_local! {
+    // Set by run_with_options before the event loop starts, the functions below fail
+    // until then. With a render worker there's none, they go to the worker's queue.
+    static EVENT_LOOP_PROXY: RefCell<Option<EventLoopProxy<CustomEvent>>> = const { RefCell::new(None) };
+}
+
+// What the page asks of the renderer. They're sent to the event loop as CustomEvent::Web
+// and applied between frames, or posted to the render worker first when there is one.
+// The canvas' size and input only come this way from a render worker's page, see
+// web_worker.rs, winit has them otherwise.
+#[derive(Debug, Clone)]
+pub enum WebCommand {
+    SetClearColor(Color),
+    // A model or an image by the name's extension, see State::open_bytes.
+    Open {
+        name: String,
+        bytes: Vec<u8>
+    },
+    SetShaderSources(Vec<(ShaderHandle, String)>),
+    SetCamera {
+        eye: Point3<f32>,
+        target: Point3<f32>
+    },
+    SetPaused(bool),
+    Resize {
+        size: PhysicalSize<u32>,
+        scale_factor: f64
+    },
+    CursorMoved(PhysicalPosition<f64>),
+    SetModifiers(ModifiersState),
+    Input {
+        binding: Binding,
+        pressed: bool,
+        repeat: bool
+    },
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        location: PhysicalPosition<f64>
+    }
+}
+
+impl WebCommand {
+    pub fn apply(self, state: &mut State)
+    {
+        match self {
+            WebCommand::SetClearColor(color) => state.set_clear_color(color),
+            WebCommand::Open { name, bytes } => {
+                if let Err(e) = state.open_bytes(&name, &bytes) {
+                    log::error!("Couldn't open {name}: {e}");
+                }
+            },
+            WebCommand::SetShaderSources(sources) => {
+                state.set_shader_sources(sources);
+            },
+            WebCommand::SetCamera { eye, target } => state.set_camera(eye, target),
+            WebCommand::SetPaused(paused) => state.set_paused(paused),
+            WebCommand::Resize { size, scale_factor } => {
+                state.set_scale_factor(scale_factor);
+                state.resize(size);
+            },
+            WebCommand::CursorMoved(position) => state.set_cursor_position(position),
+            WebCommand::SetModifiers(modifiers) => state.set_modifiers(modifiers),
+            WebCommand::Input { binding, pressed, repeat } => {
+                state.input_binding(binding, pressed, repeat);
+            },
+            WebCommand::Touch { id, phase, location } => {
+                state.touch(id, phase, location);
+            }
+        }
+    }
+}
+
+pub fn set_event_loop_proxy(proxy: EventLoopProxy<CustomEvent>)
+{
+    EVENT_LOOP_PROXY.set(Some(proxy));
+}
+
+fn send(command: WebCommand) -> Result<(), JsValue>
+{
+    if web_worker::in_worker() {
+        web_worker::send_event(CustomEvent::Web(command));
+        return Ok(());
+    }
+    if web_worker::has_worker() {
+        return web_worker::post_command(&command);
+    }
+
+    EVENT_LOOP_PROXY.with_borrow(|proxy| {
+        proxy.as_ref()
+            .ok_or_else(|| JsValue::from_str("the renderer isn't running yet"))?
+            .send_event(CustomEvent::Web(command))
+            .map_err(|_| JsValue::from_str("the renderer has stopped"))
+    })
+}
+
+// Calls the page's callback, if it gave one, with what was loaded so far and the total,
+// undefined when it isn't known.
+fn progress_callback(on_progress: Option<Function>) -> impl FnMut(u64, Option<u64>)
+{
+    move |loaded, total| {
+        if let Some(on_progress) = &on_progress {
+            let total = total.map_or(JsValue::UNDEFINED, |total| JsValue::from_f64(total as f64));
+            if let Err(e) = on_progress.call2(&JsValue::NULL, &JsValue::from_f64(loaded as f64), &total) {
+                log::warn!("The progress callback failed: {e:?}");
+            }
+        }
+    }
+}
+
+// Relative to the page, without the query or fragment.
+fn file_name(url: &str) -> String
+{
+    String::from(url.split(['?', '#']).next().unwrap_or(url))
+}
+
+async fn open_url(url: &str, on_progress: Option<Function>) -> Result<(), JsValue>
+{
+    let bytes = asset_fetch::fetch_bytes(url, progress_callback(on_progress)).await
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    send(WebCommand::Open { name: file_name(url), bytes })
+}
+
+fn point(values: &[f32], name: &str) -> Result<Point3<f32>, JsValue>
+{
+    match values {
+        [x, y, z] => Ok(Point3::new(*x, *y, *z)),
+        _ => Err(JsValue::from_str(&format!("{name} needs 3 coordinates, got {}", values.len())))
+    }
+}
+
+// Linear, from 0 to 1.
+#[wasm_bindgen]
+pub fn set_clear_color(r: f64, g: f64, b: f64) -> Result<(), JsValue>
+{
+    send(WebCommand::SetClearColor(Color { r, g, b, a: 1.0 }))
+}
+
+// Replaces the skinned model with a glTF binary, a .gltf with its buffers embedded or
+// an OBJ, and frames it once loaded. on_progress(loaded, total) is called with the bytes
+// fetched so far. Resolves once it's fetched, loading errors are logged.
+#[wasm_bindgen]
+pub async fn load_model_from_url(url: String, on_progress: Option<Function>) -> Result<(), JsValue>
+{
+    open_url(&url, on_progress).await
+}
+
+// A PNG or JPEG for the selected instance, or every instance when none is selected.
+// Otherwise like load_model_from_url.
+#[wasm_bindgen]
+pub async fn load_texture_from_url(url: String, on_progress: Option<Function>) -> Result<(), JsValue>
+{
+    open_url(&url, on_progress).await
+}
+
+// Replaces the embedded shaders with the ones under base_url, e.g. "shaders", by their
+// file names, and reloads them. Shaders that aren't there stay embedded.
+// on_progress(loaded, total) is called with the number of shaders tried so far.
+#[wasm_bindgen]
+pub async fn load_shaders_from_url(base_url: String, on_progress: Option<Function>) -> Result<(), JsValue>
+{
+    let sources = asset_fetch::fetch_shaders(&base_url, progress_callback(on_progress)).await
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    send(WebCommand::SetShaderSources(sources))
+}
+
+// Both as [x, y, z] arrays.
+#[wasm_bindgen]
+pub fn set_camera(eye: &[f32], target: &[f32]) -> Result<(), JsValue>
+{
+    send(WebCommand::SetCamera { eye: point(eye, "eye")?, target: point(target, "target")? })
+}
+
+#[wasm_bindgen]
+pub fn pause() -> Result<(), JsValue>
+{
+    send(WebCommand::SetPaused(true))
+}
+
+#[wasm_bindgen]
+pub fn resume() -> Result<(), JsValue>
+{
+    send(WebCommand::SetPaused(false))
+}
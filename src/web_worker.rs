@@ -0,0 +1,473 @@
+use std::{cell::RefCell, collections::VecDeque};
+
+use cgmath::Point3;
+use js_sys::{Array, Float32Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use web_sys::{DedicatedWorkerGlobalScope, Event, EventTarget, HtmlCanvasElement, KeyboardEvent, MouseEvent, OffscreenCanvas, PointerEvent, ResizeObserver, Worker, WorkerOptions, WorkerType};
+use wgpu::{Color, SurfaceError};
+use winit::{dpi::{PhysicalPosition, PhysicalSize}, event::TouchPhase, keyboard::ModifiersState};
+
+use crate::{custom_event::CustomEvent, error::RendererError, renderer_backend::shader_registry::ShaderHandle, web_api::WebCommand, Binding, State, StateOptions};
+
+// With WindowConfig::worker the page's thread only forwards its canvas, size and input
+// to a web worker, which owns the device and renders on animation frames. The worker's
+// script, worker.js next to index.html, passes the first message to start_worker and
+// the rest to handle_worker_message. The functions in web_api.rs can be called on
+// either side, the page posts what they ask for to the worker.
+
+thread_local! {
+    // In the worker, once start_worker made it.
+    static STATE: RefCell<Option<State<'static>>> = const { RefCell::new(None) };
+    // What arrived since the last frame, handled before it like the event loop would.
+    static EVENTS: RefCell<VecDeque<CustomEvent>> = const { RefCell::new(VecDeque::new()) };
+    // On the page, once spawn started it.
+    static WORKER: RefCell<Option<Worker>> = const { RefCell::new(None) };
+}
+
+pub fn in_worker() -> bool
+{
+    js_sys::global().is_instance_of::<DedicatedWorkerGlobalScope>()
+}
+
+pub fn send_event(event: CustomEvent)
+{
+    EVENTS.with_borrow_mut(|events| events.push_back(event));
+}
+
+pub fn has_worker() -> bool
+{
+    WORKER.with_borrow(Option::is_some)
+}
+
+// From the page to the worker, as the message `commands` turns back into it.
+pub fn post_command(command: &WebCommand) -> Result<(), JsValue>
+{
+    WORKER.with_borrow(|worker| {
+        worker.as_ref()
+            .ok_or_else(|| JsValue::from_str("there's no render worker"))?
+            .post_message(&command_message(command))
+    })
+}
+
+// On the page's thread, instead of the event loop. The canvas can't be drawn on here
+// afterwards.
+pub fn spawn(script: &str, canvas: &HtmlCanvasElement) -> Result<(), RendererError>
+{
+    let error = |value: JsValue| RendererError::Worker(value.as_string().unwrap_or_else(|| format!("{value:?}")));
+
+    let mut options = WorkerOptions::new();
+    options.type_(WorkerType::Module);
+    let worker = Worker::new_with_options(script, &options).map_err(error)?;
+
+    let offscreen = canvas.transfer_control_to_offscreen().map_err(error)?;
+    let init = resize_message(canvas, "init");
+    set(&init, "canvas", offscreen.clone());
+    worker.post_message_with_transfer(&init, &Array::of1(&offscreen)).map_err(error)?;
+
+    forward_input(&worker, canvas);
+    WORKER.set(Some(worker));
+    log::info!("Rendering in the web worker {script}");
+
+    Ok(())
+}
+
+fn forward_input(worker: &Worker, canvas: &HtmlCanvasElement)
+{
+    let window = web_sys::window().expect("The page has a window.");
+    // The gestures are the renderer's, not the browser's scrolling and zooming.
+    if let Err(e) = canvas.style().set_property("touch-action", "none") {
+        log::warn!("Couldn't turn the browser's touch gestures off: {e:?}");
+    }
+
+    observe_size(worker, canvas);
+    // Keys go to the page, as the canvas only gets them once focused.
+    listen(&window, "keydown", worker, |event: &KeyboardEvent| key_message(event, true));
+    listen(&window, "keyup", worker, |event: &KeyboardEvent| key_message(event, false));
+    // Fingers move the camera with gestures, mice and pens press buttons and move the
+    // cursor, like winit has them.
+    listen(canvas, "pointerdown", worker, |event: &PointerEvent| if is_touch(event) {
+        touch_message(event, TouchPhase::Started)
+    } else {
+        button_message(event, true)
+    });
+    listen(canvas, "pointerup", worker, |event: &PointerEvent| if is_touch(event) {
+        touch_message(event, TouchPhase::Ended)
+    } else {
+        button_message(event, false)
+    });
+    listen(canvas, "pointercancel", worker, |event: &PointerEvent| {
+        touch_message(event, TouchPhase::Cancelled)
+    });
+    listen(canvas, "pointermove", worker, |event: &PointerEvent| if is_touch(event) {
+        touch_message(event, TouchPhase::Moved)
+    } else {
+        command_message(&WebCommand::CursorMoved(offset(event)))
+    });
+}
+
+// The canvas' own size, which the page's layout can change without the window's.
+fn observe_size(worker: &Worker, canvas: &HtmlCanvasElement)
+{
+    let (worker, resized) = (worker.clone(), canvas.clone());
+    let callback = Closure::<dyn Fn()>::new(move || {
+        if let Err(e) = worker.post_message(&resize_message(&resized, "resize")) {
+            log::warn!("Couldn't forward the canvas' size to the render worker: {e:?}");
+        }
+    });
+    match ResizeObserver::new(callback.as_ref().unchecked_ref()) {
+        Ok(observer) => observer.observe(canvas),
+        Err(e) => log::warn!("Couldn't observe the canvas' size: {e:?}")
+    }
+    // Observes for as long as the page is open.
+    callback.forget();
+}
+
+fn listen<E: JsCast>(
+    target: &EventTarget,
+    kind: &str,
+    worker: &Worker,
+    to_message: impl Fn(&E) -> Object + 'static
+)
+{
+    let worker = worker.clone();
+    let listener = Closure::<dyn Fn(Event)>::new(move |event: Event| {
+        if let Err(e) = worker.post_message(&to_message(event.unchecked_ref())) {
+            log::warn!("Couldn't forward {} to the render worker: {e:?}", event.type_());
+        }
+    });
+    if let Err(e) = target.add_event_listener_with_callback(kind, listener.as_ref().unchecked_ref()) {
+        log::warn!("Couldn't listen to {kind}: {e:?}");
+    }
+    // Listens for as long as the page is open.
+    listener.forget();
+}
+
+fn device_pixel_ratio() -> f64
+{
+    web_sys::window().map_or(1.0, |window| window.device_pixel_ratio())
+}
+
+// Within the canvas, in physical pixels.
+fn offset(event: &MouseEvent) -> PhysicalPosition<f64>
+{
+    let scale_factor = device_pixel_ratio();
+    let physical = |css: i32| css as f64 * scale_factor;
+
+    PhysicalPosition::new(physical(event.offset_x()), physical(event.offset_y()))
+}
+
+fn is_touch(event: &PointerEvent) -> bool
+{
+    event.pointer_type() == "touch"
+}
+
+fn message(kind: &str) -> Object
+{
+    let message = Object::new();
+    set(&message, "type", kind);
+
+    message
+}
+
+fn set(message: &Object, field: &str, value: impl Into<JsValue>)
+{
+    Reflect::set(message, &JsValue::from_str(field), &value.into()).expect("Messages are plain objects.");
+}
+
+// The canvas' size in physical pixels, which the page's layout decides.
+fn resize_message(canvas: &HtmlCanvasElement, kind: &str) -> Object
+{
+    let scale_factor = device_pixel_ratio();
+    let physical = |css: i32| ((css as f64 * scale_factor).round() as u32).max(1);
+
+    let message = message(kind);
+    set(&message, "width", physical(canvas.client_width()));
+    set(&message, "height", physical(canvas.client_height()));
+    set(&message, "scaleFactor", scale_factor);
+
+    message
+}
+
+// Bindings by their names in a bindings file, which for keys are the events' codes.
+fn input_message(binding: &str, pressed: bool, repeat: bool, modifiers: ModifiersState) -> Object
+{
+    let message = message("input");
+    set(&message, "binding", binding);
+    set(&message, "pressed", pressed);
+    set(&message, "repeat", repeat);
+    set(&message, "modifiers", modifiers.bits());
+
+    message
+}
+
+fn point_message(point: &Point3<f32>) -> Float32Array
+{
+    Float32Array::from(&[point.x, point.y, point.z][..])
+}
+
+fn command_message(command: &WebCommand) -> Object
+{
+    match command {
+        WebCommand::SetClearColor(color) => {
+            let message = message("clearColor");
+            set(&message, "r", color.r);
+            set(&message, "g", color.g);
+            set(&message, "b", color.b);
+            message
+        },
+        WebCommand::Open { name, bytes } => {
+            let message = message("open");
+            set(&message, "name", name);
+            set(&message, "bytes", Uint8Array::from(bytes.as_slice()));
+            message
+        },
+        WebCommand::SetShaderSources(sources) => {
+            let message = message("shaderSources");
+            let sources = sources.iter()
+                .map(|(shader, source)| Array::of2(&shader.filename().into(), &source.into()))
+                .collect::<Array>();
+            set(&message, "sources", sources);
+            message
+        },
+        WebCommand::SetCamera { eye, target } => {
+            let message = message("camera");
+            set(&message, "eye", point_message(eye));
+            set(&message, "target", point_message(target));
+            message
+        },
+        WebCommand::SetPaused(paused) => {
+            let message = message("paused");
+            set(&message, "paused", *paused);
+            message
+        },
+        WebCommand::Resize { size, scale_factor } => {
+            let message = message("resize");
+            set(&message, "width", size.width);
+            set(&message, "height", size.height);
+            set(&message, "scaleFactor", *scale_factor);
+            message
+        },
+        WebCommand::CursorMoved(position) => {
+            let message = message("cursor");
+            set(&message, "x", position.x);
+            set(&message, "y", position.y);
+            message
+        },
+        WebCommand::SetModifiers(modifiers) => {
+            let message = message("modifiers");
+            set(&message, "modifiers", modifiers.bits());
+            message
+        },
+        WebCommand::Input { binding, pressed, repeat } => {
+            let message = message("input");
+            set(&message, "binding", binding.name());
+            set(&message, "pressed", *pressed);
+            set(&message, "repeat", *repeat);
+            message
+        },
+        WebCommand::Touch { id, phase, location } => {
+            let message = message("touch");
+            set(&message, "id", *id as f64);
+            set(&message, "phase", format!("{phase:?}"));
+            set(&message, "x", location.x);
+            set(&message, "y", location.y);
+            message
+        }
+    }
+}
+
+fn modifiers(shift: bool, control: bool, alt: bool, meta: bool) -> ModifiersState
+{
+    let mut modifiers = ModifiersState::empty();
+    modifiers.set(ModifiersState::SHIFT, shift);
+    modifiers.set(ModifiersState::CONTROL, control);
+    modifiers.set(ModifiersState::ALT, alt);
+    modifiers.set(ModifiersState::SUPER, meta);
+
+    modifiers
+}
+
+fn key_message(event: &KeyboardEvent, pressed: bool) -> Object
+{
+    let modifiers = modifiers(event.shift_key(), event.ctrl_key(), event.alt_key(), event.meta_key());
+
+    input_message(&event.code(), pressed, event.repeat(), modifiers)
+}
+
+// Every finger is a pointer of its own while it touches the canvas.
+fn touch_message(event: &PointerEvent, phase: TouchPhase) -> Object
+{
+    let id = event.pointer_id() as u64;
+
+    command_message(&WebCommand::Touch { id, phase, location: offset(event) })
+}
+
+fn button_message(event: &MouseEvent, pressed: bool) -> Object
+{
+    let modifiers = modifiers(event.shift_key(), event.ctrl_key(), event.alt_key(), event.meta_key());
+    let binding = match event.button() {
+        0 => "MouseLeft",
+        1 => "MouseMiddle",
+        2 => "MouseRight",
+        _ => ""
+    };
+
+    input_message(binding, pressed, false, modifiers)
+}
+
+// The page's messages as the commands the renderer takes from the page anyway. An input
+// message carries the modifiers along when it comes from a key or button event.
+fn commands(message: &JsValue) -> Result<Vec<WebCommand>, JsValue>
+{
+    let field = |name: &str| Reflect::get(message, &JsValue::from_str(name));
+    let number = |name: &str| field(name).map(|value| value.as_f64().unwrap_or(0.0));
+    let flag = |name: &str| field(name).map(|value| value.is_truthy());
+    let text = |name: &str| field(name).map(|value| value.as_string().unwrap_or_default());
+    let point = |name: &str| field(name).and_then(|value| {
+        match Float32Array::new(&value).to_vec()[..] {
+            [x, y, z] => Ok(Point3::new(x, y, z)),
+            _ => Err(JsValue::from_str(&format!("{name} needs 3 coordinates")))
+        }
+    });
+
+    let kind = text("type")?;
+    match kind.as_str() {
+        "clearColor" => Ok(vec![WebCommand::SetClearColor(
+            Color { r: number("r")?, g: number("g")?, b: number("b")?, a: 1.0 }
+        )]),
+        "open" => Ok(vec![WebCommand::Open {
+            name: text("name")?,
+            bytes: Uint8Array::new(&field("bytes")?).to_vec()
+        }]),
+        "shaderSources" => {
+            let sources = Array::from(&field("sources")?).iter()
+                .filter_map(|entry| {
+                    let entry = Array::from(&entry);
+                    let filename = entry.get(0).as_string().unwrap_or_default();
+                    let shader = ShaderHandle::from_filename(&filename);
+                    if shader.is_none() {
+                        log::warn!("There's no shader named {filename:?}");
+                    }
+                    Some((shader?, entry.get(1).as_string().unwrap_or_default()))
+                })
+                .collect();
+            Ok(vec![WebCommand::SetShaderSources(sources)])
+        },
+        "camera" => Ok(vec![WebCommand::SetCamera { eye: point("eye")?, target: point("target")? }]),
+        "paused" => Ok(vec![WebCommand::SetPaused(flag("paused")?)]),
+        "resize" => Ok(vec![WebCommand::Resize {
+            size: PhysicalSize::new(number("width")? as u32, number("height")? as u32),
+            scale_factor: number("scaleFactor")?
+        }]),
+        "cursor" => Ok(vec![WebCommand::CursorMoved(PhysicalPosition::new(number("x")?, number("y")?))]),
+        "modifiers" => Ok(vec![WebCommand::SetModifiers(
+            ModifiersState::from_bits_truncate(number("modifiers")? as u32)
+        )]),
+        "input" => {
+            let mut commands = Vec::new();
+            if !field("modifiers")?.is_undefined() {
+                let modifiers = ModifiersState::from_bits_truncate(number("modifiers")? as u32);
+                commands.push(WebCommand::SetModifiers(modifiers));
+            }
+            if let Some(binding) = Binding::from_name(&text("binding")?) {
+                commands.push(WebCommand::Input { binding, pressed: flag("pressed")?, repeat: flag("repeat")? });
+            }
+            Ok(commands)
+        },
+        "touch" => {
+            let phase = match text("phase")?.as_str() {
+                "Started" => TouchPhase::Started,
+                "Moved" => TouchPhase::Moved,
+                "Ended" => TouchPhase::Ended,
+                _ => TouchPhase::Cancelled
+            };
+            let location = PhysicalPosition::new(number("x")?, number("y")?);
+            Ok(vec![WebCommand::Touch { id: number("id")? as u64, phase, location }])
+        },
+        _ => Err(JsValue::from_str(&format!("unknown message type {kind:?}")))
+    }
+}
+
+// In the worker, with what the page sent first. Resolves once the renderer runs.
+#[wasm_bindgen]
+pub async fn start_worker(canvas: OffscreenCanvas, width: u32, height: u32, scale_factor: f64) -> Result<(), JsValue>
+{
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+    let mut options = StateOptions::from_env();
+    let _log_guard = options.log.init();
+    options.apply_settings();
+
+    let size = PhysicalSize::new(width.max(1), height.max(1));
+    let mut state = State::new_offscreen(canvas, size, options).await
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    state.set_scale_factor(scale_factor);
+    STATE.set(Some(state));
+    request_frame();
+
+    Ok(())
+}
+
+// Every message from the page after the first.
+#[wasm_bindgen]
+pub fn handle_worker_message(message: JsValue) -> Result<(), JsValue>
+{
+    for command in commands(&message)? {
+        send_event(CustomEvent::Web(command));
+    }
+
+    Ok(())
+}
+
+fn request_frame()
+{
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let callback = Closure::once_into_js(|| {
+        if frame() {
+            request_frame();
+        }
+    });
+    if let Err(e) = scope.request_animation_frame(callback.unchecked_ref()) {
+        log::error!("Couldn't request an animation frame: {e:?}");
+    }
+}
+
+// Like a redraw in run_with_options, after the events that came in since the last one.
+// False once the renderer can't go on.
+fn frame() -> bool
+{
+    STATE.with_borrow_mut(|state| {
+        let Some(state) = state else {
+            return false;
+        };
+        for event in EVENTS.take() {
+            match event {
+                CustomEvent::Web(command) => command.apply(state),
+                CustomEvent::AnimationFinished(clip) => log::info!("Animation {clip} finished"),
+                CustomEvent::Timer => {}
+            }
+        }
+        if state.is_device_lost() {
+            log::error!("GPU device lost, recovery isn't supported on the web");
+            return false;
+        }
+        if state.is_paused() {
+            return true;
+        }
+
+        state.update();
+        let custom_events = state.take_custom_events();
+        EVENTS.with_borrow_mut(|events| events.extend(custom_events));
+        match state.render() {
+            Ok(_) => {},
+            Err(SurfaceError::Lost | SurfaceError::Outdated) => state.resize(state.size),
+            Err(SurfaceError::OutOfMemory) => {
+                state.write_crash_report("surface out of memory");
+                log::error!("The surface ran out of memory, recovery isn't supported on the web");
+                return false;
+            },
+            Err(e) => log::error!("{e:?}")
+        }
+
+        true
+    })
+}
@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use winit::{dpi::LogicalSize, window::{Fullscreen, Icon, WindowBuilder}};
+
+// How run_with_options opens the window. On the web the window is a canvas sized by
+// the page's CSS, which only takes the title and fullscreen.
+#[derive(Debug, Clone)]
+pub struct WindowConfig {
+    pub title: String,
+    // Inner size in logical pixels, None leaves it to the platform.
+    pub size: Option<LogicalSize<u32>>,
+    // How small the window can be resized, see State::set_min_window_size.
+    pub min_size: Option<LogicalSize<u32>>,
+    pub resizable: bool,
+    // Borderless on the current monitor, F11 toggles it.
+    pub fullscreen: bool,
+    pub decorations: bool,
+    // A PNG or JPEG, a missing or broken one leaves the platform's icon.
+    pub icon: Option<PathBuf>,
+    // On the web, the id of the page's canvas to render into. Without one, or when the
+    // page has no such canvas, a new one filling <body> is appended to it.
+    pub canvas_id: Option<String>,
+    // On the web, a module script rendering in a web worker on an OffscreenCanvas, see
+    // web_worker.rs, which leaves the page's thread to its UI. Needs the page's canvas.
+    pub worker: Option<String>
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self
+    {
+        Self {
+            title: String::from("learn_wgpu"),
+            size: Some(LogicalSize::new(1280, 720)),
+            min_size: Some(LogicalSize::new(320, 240)),
+            resizable: true,
+            fullscreen: false,
+            decorations: true,
+            icon: None,
+            canvas_id: cfg!(target_arch = "wasm32").then(|| String::from("learn_wgpu")),
+            worker: None
+        }
+    }
+}
+
+impl WindowConfig {
+    // LEARN_WGPU_FULLSCREEN=1 starts in fullscreen. On the web, a page opened with
+    // ?worker renders in worker.js next to it.
+    pub fn from_env() -> Self
+    {
+        Self {
+            fullscreen: std::env::var("LEARN_WGPU_FULLSCREEN").is_ok_and(|value| value == "1"),
+            worker: Self::worker_from_page(),
+            ..Self::default()
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn worker_from_page() -> Option<String>
+    {
+        let search = web_sys::window()?.location().search().ok()?;
+        let params = web_sys::UrlSearchParams::new_with_str(&search).ok()?;
+
+        params.has("worker").then(|| String::from("./worker.js"))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn worker_from_page() -> Option<String>
+    {
+        None
+    }
+
+    pub fn builder(&self) -> WindowBuilder
+    {
+        let mut builder = WindowBuilder::new()
+            .with_title(&self.title)
+            .with_resizable(self.resizable)
+            .with_decorations(self.decorations)
+            .with_fullscreen(self.fullscreen.then_some(Fullscreen::Borderless(None)))
+            .with_window_icon(self.load_icon());
+        // A canvas given a size in pixels wouldn't follow the page's layout anymore.
+        if cfg!(target_arch = "wasm32") {
+            return builder;
+        }
+        if let Some(size) = self.size {
+            builder = builder.with_inner_size(size);
+        }
+        if let Some(min_size) = self.min_size {
+            builder = builder.with_min_inner_size(min_size);
+        }
+
+        builder
+    }
+
+    fn load_icon(&self) -> Option<Icon>
+    {
+        let path = self.icon.as_ref()?;
+        let icon = image::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|image| {
+                let image = image.to_rgba8();
+                let (width, height) = image.dimensions();
+                Icon::from_rgba(image.into_raw(), width, height).map_err(|e| e.to_string())
+            });
+
+        match icon {
+            Ok(icon) => Some(icon),
+            Err(e) => {
+                log::warn!("Couldn't load the window icon {}: {e}", path.display());
+                None
+            }
+        }
+    }
+}
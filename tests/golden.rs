@@ -0,0 +1,174 @@
+// Renders known scenes headless and compares them against the reference images in
+// tests/golden. A missing reference is written instead, as is every reference with
+// LEARN_WGPU_UPDATE_GOLDEN=1, so check the new images before committing them. A software
+// adapter is used without a GPU, or always with LEARN_WGPU_FALLBACK_ADAPTER=1 as on CI,
+// which installs Mesa for one. Without any adapter, not even a software one, the tests
+// are skipped, except on CI where they fail.
+
+use std::{env, path::PathBuf};
+
+use image::{Rgba, RgbaImage};
+use learn_wgpu::{AntiAliasing, ColorGradingOptions, DebugView, RendererError, State, StateOptions};
+use winit::dpi::PhysicalSize;
+
+const SIZE: PhysicalSize<u32> = PhysicalSize::new(256, 256);
+// How far apart in YIQ, from 0 to 1, two pixels can be before they count as different.
+// Small enough to catch a darker or shifted color, big enough for rounding between
+// drivers.
+const PIXEL_THRESHOLD: f32 = 0.05;
+// How many pixels can differ, edges rasterize slightly differently between drivers.
+const MAX_DIFFERING_PIXELS: f32 = 0.005;
+
+// Nothing that depends on files outside the repo, timing or the build profile.
+fn golden_options() -> StateOptions
+{
+    StateOptions {
+        debug_markers: false,
+        camera_bookmarks: None,
+        shader_dir: None,
+        input_bindings: None,
+        settings: None,
+        anti_aliasing: AntiAliasing::None,
+        fallback_adapter: env::var("LEARN_WGPU_FALLBACK_ADAPTER").is_ok_and(|value| value == "1"),
+        ..StateOptions::default()
+    }
+}
+
+// Renders one frame after `setup` and reads it back, None when there's no adapter.
+fn render(options: StateOptions, setup: impl FnOnce(&mut State)) -> Option<RgbaImage>
+{
+    let mut state = match pollster::block_on(State::new_headless(SIZE, options)) {
+        Ok(state) => state,
+        Err(RendererError::NoAdapter) if env::var_os("CI").is_none() => {
+            eprintln!("No adapter, skipping the golden image test");
+            return None;
+        },
+        Err(e) => panic!("Couldn't create a headless renderer: {e}")
+    };
+    setup(&mut state);
+    state.render().expect("Couldn't render");
+
+    Some(pollster::block_on(state.read_frame()).expect("Couldn't read the frame back"))
+}
+
+// The perceived difference between two pixels, from 0 to 1, after pixelmatch's YIQ
+// delta, which weighs brightness over hue much like the eye does.
+fn color_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32
+{
+    let yiq = |pixel: &Rgba<u8>| {
+        let [r, g, b] = [0, 1, 2].map(|i| pixel[i] as f32 / 255.0);
+        [
+            0.298_895 * r + 0.586_622 * g + 0.114_482 * b,
+            0.595_978 * r - 0.274_176 * g - 0.321_802 * b,
+            0.211_470 * r - 0.522_617 * g + 0.311_147 * b
+        ]
+    };
+    let ([y1, i1, q1], [y2, i2, q2]) = (yiq(a), yiq(b));
+    // The weights add up to 1, so black against white comes out as 1 at most.
+    (0.5053 * (y1 - y2).powi(2) + 0.299 * (i1 - i2).powi(2) + 0.1957 * (q1 - q2).powi(2)).sqrt()
+}
+
+fn assert_golden(name: &str, actual: &RgbaImage)
+{
+    let reference_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+    assert!(reference_path.exists() || env::var_os("CI").is_none(),
+        "There's no reference image {}, render and commit it first", reference_path.display());
+    if env::var("LEARN_WGPU_UPDATE_GOLDEN").is_ok_and(|value| value == "1") || !reference_path.exists() {
+        actual.save(&reference_path).expect("Couldn't write the reference image");
+        eprintln!("Wrote the reference image {}", reference_path.display());
+        return;
+    }
+
+    let reference = image::open(&reference_path)
+        .expect("Couldn't read the reference image")
+        .to_rgba8();
+    assert_eq!(reference.dimensions(), actual.dimensions(), "{name} changed size");
+
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    let mut differing = 0;
+    for ((expected, pixel), diff_pixel) in reference.pixels().zip(actual.pixels()).zip(diff.pixels_mut()) {
+        *diff_pixel = if color_delta(expected, pixel) > PIXEL_THRESHOLD {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let gray = (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 12;
+            Rgba([gray as u8, gray as u8, gray as u8, 255])
+        };
+    }
+
+    let fraction = differing as f32 / (actual.width() * actual.height()) as f32;
+    if fraction > MAX_DIFFERING_PIXELS {
+        let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&out_dir).expect("Couldn't create the output directory");
+        let actual_path = out_dir.join(format!("{name}-actual.png"));
+        let diff_path = out_dir.join(format!("{name}-diff.png"));
+        actual.save(&actual_path).expect("Couldn't write the actual image");
+        diff.save(&diff_path).expect("Couldn't write the diff image");
+
+        panic!("{name} differs from its reference in {:.2}% of its pixels, see {} and {}",
+            fraction * 100.0, actual_path.display(), diff_path.display());
+    }
+}
+
+#[test]
+fn default_scene()
+{
+    if let Some(frame) = render(golden_options(), |_| {}) {
+        assert_golden("default_scene", &frame);
+    }
+}
+
+// Culling only drops what can't be seen, so it mustn't change the picture.
+#[test]
+fn without_gpu_culling()
+{
+    let options = StateOptions {
+        gpu_culling: false,
+        ..golden_options()
+    };
+    if let Some(frame) = render(options, |_| {}) {
+        assert_golden("default_scene", &frame);
+    }
+}
+
+// Goes through the post effect chain.
+#[test]
+fn color_grading()
+{
+    let options = StateOptions {
+        color_grading: Some(ColorGradingOptions {
+            contrast: 1.3,
+            saturation: 0.4,
+            temperature: 0.5,
+            ..ColorGradingOptions::default()
+        }),
+        ..golden_options()
+    };
+    if let Some(frame) = render(options, |_| {}) {
+        assert_golden("color_grading", &frame);
+    }
+}
+
+#[test]
+fn depth_view()
+{
+    let frame = render(golden_options(), |state| {
+        state.set_debug_view(DebugView::Depth).expect("Couldn't switch to the depth view");
+    });
+    if let Some(frame) = frame {
+        assert_golden("depth_view", &frame);
+    }
+}
+
+#[test]
+fn normals_view()
+{
+    let frame = render(golden_options(), |state| {
+        state.set_debug_view(DebugView::Normals).expect("Couldn't switch to the normals view");
+    });
+    if let Some(frame) = frame {
+        assert_golden("normals_view", &frame);
+    }
+}
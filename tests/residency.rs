@@ -0,0 +1,61 @@
+// The LRU policy of ResidencyManager, without a GPU.
+
+use learn_wgpu::renderer_backend::residency::{ResidencyManager, ResidentAsset};
+
+const MIB: u64 = 1024 * 1024;
+
+#[test]
+fn evicts_least_recently_used_over_budget()
+{
+    let mut residency = ResidencyManager::new(3 * MIB);
+    residency.begin_frame();
+    residency.make_resident(ResidentAsset::Texture(0), MIB);
+    residency.make_resident(ResidentAsset::Texture(1), MIB);
+    residency.begin_frame();
+    residency.make_resident(ResidentAsset::Mesh(2), MIB);
+    residency.touch(&ResidentAsset::Texture(0));
+    assert!(residency.evict_over_budget().is_empty());
+
+    // Texture 1 was last drawn two frames ago, the others one.
+    residency.begin_frame();
+    residency.make_resident(ResidentAsset::DiffuseArray, MIB);
+
+    assert_eq!(residency.evict_over_budget(), vec![ResidentAsset::Texture(1)]);
+    assert!(!residency.is_resident(&ResidentAsset::Texture(1)));
+    assert!(!residency.touch(&ResidentAsset::Texture(1)));
+    let stats = residency.stats();
+    assert_eq!(stats.resident_bytes, 3 * MIB);
+    assert_eq!(stats.resident_count, 3);
+    assert_eq!(stats.evictions, 1);
+}
+
+#[test]
+fn keeps_what_the_frame_uses()
+{
+    let mut residency = ResidencyManager::new(MIB);
+    residency.begin_frame();
+    residency.make_resident(ResidentAsset::Texture(0), MIB);
+    residency.make_resident(ResidentAsset::Texture(1), MIB);
+
+    // Both were used this frame, the budget stays exceeded until one isn't.
+    assert!(residency.evict_over_budget().is_empty());
+
+    residency.begin_frame();
+    residency.touch(&ResidentAsset::Texture(1));
+    assert_eq!(residency.evict_over_budget(), vec![ResidentAsset::Texture(0)]);
+    assert_eq!(residency.stats().resident_bytes, MIB);
+}
+
+#[test]
+fn stops_tracking_unloaded_assets()
+{
+    let mut residency = ResidencyManager::new(MIB);
+    residency.make_resident(ResidentAsset::Texture(0), MIB);
+    residency.make_resident(ResidentAsset::Mesh(1), MIB);
+    residency.resize(&ResidentAsset::Mesh(1), MIB / 2);
+
+    residency.retain(|key| *key != ResidentAsset::Texture(0));
+
+    assert!(!residency.is_resident(&ResidentAsset::Texture(0)));
+    assert_eq!(residency.stats().resident_bytes, MIB / 2);
+}
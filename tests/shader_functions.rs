@@ -0,0 +1,186 @@
+// The WGSL helper libraries run on the GPU against known inputs through ShaderTest.
+// Without any adapter that can run compute shaders the tests are skipped.
+
+use cgmath::{perspective, Deg, Matrix4, Point3, Vector3};
+use learn_wgpu::{renderer_backend::{gpu_culling::frustum_planes, shader_test::ShaderTest}, RendererError};
+
+const EPSILON: f32 = 1e-4;
+
+#[rustfmt::skip]
+const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0
+);
+
+fn shader_test() -> Option<ShaderTest>
+{
+    match pollster::block_on(ShaderTest::new()) {
+        Ok(shader_test) => Some(shader_test),
+        Err(RendererError::NoAdapter) => {
+            eprintln!("No adapter that can run compute shaders, skipping the shader test");
+            None
+        },
+        Err(e) => panic!("Couldn't create the shader test device: {e}")
+    }
+}
+
+fn run<I: bytemuck::Pod, O: bytemuck::Pod>(shader_test: &ShaderTest, source: &str, inputs: &[I]) -> Vec<O>
+{
+    pollster::block_on(shader_test.run(source, inputs)).expect("Couldn't run the shader test")
+}
+
+fn assert_close(actual: f32, expected: f32, what: &str)
+{
+    assert!((actual - expected).abs() < EPSILON, "{what}: expected {expected}, got {actual}");
+}
+
+#[test]
+fn srgb_conversions()
+{
+    let Some(shader_test) = shader_test() else {
+        return;
+    };
+    let source = r#"
+#include "color.wgsl"
+
+alias Input = vec4<f32>;
+alias Output = vec4<f32>;
+
+fn test(input: Input) -> Output
+{
+    let encoded = linear_to_srgb(input.rgb);
+    return vec4<f32>(encoded, srgb_to_linear(encoded).r);
+}
+"#;
+    let values = [0.0f32, 0.001, 0.0031308, 0.05, 0.18, 0.5, 0.9, 1.0];
+    let inputs = values.map(|value| [value, 0.5, 1.0, 0.0]);
+    let outputs: Vec<[f32; 4]> = run(&shader_test, source, &inputs);
+
+    for (value, output) in values.iter().zip(&outputs) {
+        assert_close(output[3], *value, "sRGB round trip");
+        let expected = if *value <= 0.0031308 {
+            value * 12.92
+        } else {
+            1.055 * value.powf(1.0 / 2.4) - 0.055
+        };
+        assert_close(output[0], expected, "linear_to_srgb");
+    }
+    assert_close(outputs[0][1], 0.735_357, "linear_to_srgb(0.5)");
+    assert_close(outputs[0][2], 1.0, "linear_to_srgb(1.0)");
+}
+
+#[test]
+fn luminance()
+{
+    let Some(shader_test) = shader_test() else {
+        return;
+    };
+    let source = r#"
+#include "color.wgsl"
+
+alias Input = vec4<f32>;
+alias Output = f32;
+
+fn test(input: Input) -> Output
+{
+    return luminance(input.rgb);
+}
+"#;
+    let inputs = [[1.0f32, 1.0, 1.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 0.0], [0.5, 0.5, 0.5, 0.0]];
+    let outputs: Vec<f32> = run(&shader_test, source, &inputs);
+
+    assert_close(outputs[0], 1.0, "white");
+    assert_close(outputs[1], 0.7152, "green");
+    assert_close(outputs[2], 0.0, "black");
+    assert_close(outputs[3], 0.5, "grey");
+}
+
+#[test]
+fn sphere_outside_cone()
+{
+    let Some(shader_test) = shader_test() else {
+        return;
+    };
+    // A cone from the origin down +z, 30 degrees to each side.
+    let (sin, cos) = 30f32.to_radians().sin_cos();
+    let source = format!(r#"
+#include "culling.wgsl"
+
+alias Input = vec4<f32>;
+alias Output = u32;
+
+fn test(input: Input) -> Output
+{{
+    let apex = vec4<f32>(0.0, 0.0, 0.0, {cos:?});
+    let axis = vec4<f32>(0.0, 0.0, 1.0, {sin:?});
+    return u32(sphere_outside_cone(input.xyz, input.w, apex, axis));
+}}
+"#);
+    // xyz is the center, w the radius.
+    let spheres = [
+        ([0.0f32, 0.0, 5.0, 0.1], false),
+        ([2.0, 0.0, 5.0, 0.1], false),
+        ([0.0, 5.0, 1.0, 0.1], true),
+        ([0.0, 5.0, 1.0, 5.0], false),
+        ([0.0, 0.0, -5.0, 1.0], true),
+        ([0.0, 0.0, -0.5, 1.0], false),
+        ([4.0, 0.0, 5.0, 0.5], true)
+    ];
+    let inputs = spheres.map(|(sphere, _)| sphere);
+    let outputs: Vec<u32> = run(&shader_test, &source, &inputs);
+
+    for ((sphere, outside), output) in spheres.iter().zip(outputs) {
+        assert_eq!(output != 0, *outside, "sphere {sphere:?}");
+    }
+}
+
+#[test]
+fn box_outside_planes()
+{
+    let Some(shader_test) = shader_test() else {
+        return;
+    };
+    // The planes the culling pass gets for a camera at the origin looking down -z.
+    let view = Matrix4::look_at_rh(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0),
+        Vector3::unit_y());
+    let planes = frustum_planes(OPENGL_TO_WGPU_MATRIX * perspective(Deg(90.0), 1.0, 0.1, 100.0) * view);
+    let planes = planes.iter()
+        .map(|[x, y, z, w]| format!("vec4<f32>({x:?}, {y:?}, {z:?}, {w:?})"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let source = format!(r#"
+#include "culling.wgsl"
+
+struct Input {{
+    center: vec4<f32>,
+    extent: vec4<f32>
+}};
+alias Output = u32;
+
+fn test(input: Input) -> Output
+{{
+    let planes = array<vec4<f32>, 6>({planes});
+    return u32(box_outside_planes(input.center.xyz, input.extent.xyz, planes));
+}}
+"#);
+    let boxes = [
+        ([0.0f32, 0.0, -10.0, 0.0], [1.0f32, 1.0, 1.0, 0.0], false),
+        ([0.0, 0.0, 10.0, 0.0], [1.0, 1.0, 1.0, 0.0], true),
+        ([50.0, 0.0, -10.0, 0.0], [1.0, 1.0, 1.0, 0.0], true),
+        // Straddles the left plane, x = z.
+        ([-10.5, 0.0, -10.0, 0.0], [1.0, 1.0, 1.0, 0.0], false),
+        ([0.0, 0.0, -200.0, 0.0], [1.0, 1.0, 1.0, 0.0], true),
+        ([0.0, 0.0, -200.0, 0.0], [1.0, 1.0, 150.0, 0.0], false),
+        // Closer than the near plane, and straddling it.
+        ([0.0, 0.0, -0.01, 0.0], [0.05, 0.05, 0.05, 0.0], true),
+        ([0.0, 0.0, -0.1, 0.0], [0.05, 0.05, 0.05, 0.0], false)
+    ];
+    let inputs = boxes.map(|(center, extent, _)| [center, extent]);
+    let outputs: Vec<u32> = run(&shader_test, &source, &inputs);
+
+    for ((center, extent, outside), output) in boxes.iter().zip(outputs) {
+        assert_eq!(output != 0, *outside, "box at {center:?} with extent {extent:?}");
+    }
+}
@@ -0,0 +1,16 @@
+// The render worker for index.html?worker, the page forwards its canvas and input here,
+// and what its calls to the exported functions like set_camera or load_model_from_url
+// ask for. They can be called in here as well, through self.learn_wgpu.
+import init, * as learn_wgpu from "./pkg/learn_wgpu.js";
+
+const ready = init();
+self.learn_wgpu = learn_wgpu;
+
+self.onmessage = async ({ data }) => {
+    await ready;
+    if (data.type === "init") {
+        await learn_wgpu.start_worker(data.canvas, data.width, data.height, data.scaleFactor);
+    } else {
+        learn_wgpu.handle_worker_message(data);
+    }
+};